@@ -83,8 +83,8 @@ async function fetchAlbumSummaries(query) {
       : '/library/albums/search';
     const response = await apiRequest(endpoint);
 
-    if (response.success && Array.isArray(response.data)) {
-      return response.data;
+    if (response.success && response.data && Array.isArray(response.data.albums)) {
+      return response.data.albums;
     }
 
     return [];
@@ -804,31 +804,47 @@ ipcMain.handle('previous-track', async () => {
   }
 });
 
+// Poll the status of a library scan job until it is no longer running
+async function waitForScanJob(job) {
+  for (;;) {
+    const status = await apiRequest('/library/scan/status');
+    if (status.data && (status.data.job !== job || !status.data.running)) {
+      return status.data;
+    }
+    await new Promise((resolve) => setTimeout(resolve, 500));
+  }
+}
+
 ipcMain.handle('scan-library', async (event, directories) => {
   try {
     console.log('Main process: scan-library called with directories:', directories);
     
-    // Call Rust backend API to scan library
+    // Call Rust backend API to start the scan, then wait for the job to finish
     const response = await apiRequest('/library/scan', {
       method: 'POST',
       body: { directories },
     });
     
     if (response.success) {
+      const job = await waitForScanJob(response.data.job);
+      if (job.error) {
+        return { success: false, error: job.error };
+      }
+
       const tracksResult = await fetchTracksWithArtwork();
       if (!tracksResult.success) {
         return {
           success: false,
           error: tracksResult.error,
           tracks: [],
-          count: response.data || 0,
+          count: job.tracks || 0,
         };
       }
 
       return {
         success: true,
         tracks: tracksResult.tracks,
-        count: response.data || 0,
+        count: job.tracks || 0,
       };
     }
 
@@ -0,0 +1,284 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Result};
+use axum::body::{to_bytes, Body};
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{header, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use subtle::ConstantTimeEq;
+use tracing::info;
+
+use super::{ApiError, CONTROL_BODY_LIMIT, SHARE_PUBLIC_ROUTES};
+use crate::config::ApiConfig;
+use crate::events;
+
+/// Default `api.guest_enqueues_per_minute`
+pub const DEFAULT_GUEST_ENQUEUES_PER_MINUTE: u32 = 10;
+/// Default `api.guest_max_volume`
+pub const DEFAULT_GUEST_MAX_VOLUME: f32 = 0.8;
+/// Source of the events caused by guest requests
+pub const GUEST_SOURCE: &str = "guest";
+
+/// What guests may do on a route
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GuestRule {
+    /// Use the route
+    Allow,
+    /// Queue tracks, within the enqueue rate limit
+    Enqueue,
+    /// Set the volume, up to `api.guest_max_volume`
+    Volume,
+}
+
+/// Changes guests may make, by method and route path as registered with the router.
+/// Every other change is refused.
+const GUEST_CHANGES: &[(&str, &str, GuestRule)] = &[
+    ("POST", "/api/queue", GuestRule::Enqueue),
+    ("POST", "/api/audio/next", GuestRule::Allow),
+    ("POST", "/api/audio/volume", GuestRule::Volume),
+];
+
+/// Reads guests may not make, as they reveal the owner's setup rather than the music.
+/// Every other read is allowed.
+const GUEST_PRIVATE_READS: &[&str] = &[
+    "/api/health/doctor",
+    "/api/webhooks",
+    "/api/events/log",
+    "/api/jobs",
+    "/api/jobs/:id",
+    "/api/library/albums/manual/export",
+    "/api/library/albums/:id/manual",
+];
+
+/// The rule for guest requests of `method` to the route `path`; `None` when guests
+/// may not use it
+pub fn guest_rule(method: &Method, path: &str) -> Option<GuestRule> {
+    if method == Method::GET || method == Method::HEAD {
+        return (!GUEST_PRIVATE_READS.contains(&path)).then_some(GuestRule::Allow);
+    }
+    GUEST_CHANGES
+        .iter()
+        .find(|(rule_method, rule_path, _)| *rule_method == method.as_str() && *rule_path == path)
+        .map(|(_, _, rule)| *rule)
+}
+
+/// Allows at most `limit` actions in any `window`
+pub struct RateLimiter {
+    limit: u32,
+    window: Duration,
+    recent: Mutex<VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    pub fn new(limit: u32, window: Duration) -> Self {
+        Self {
+            limit,
+            window,
+            recent: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Count an action at `now`, or return how long to wait until one is allowed
+    pub fn acquire_at(&self, now: Instant) -> Result<(), Duration> {
+        let mut recent = self.recent.lock().unwrap();
+        while recent
+            .front()
+            .is_some_and(|oldest| now.duration_since(*oldest) >= self.window)
+        {
+            recent.pop_front();
+        }
+        if recent.len() < self.limit as usize {
+            recent.push_back(now);
+            return Ok(());
+        }
+        let oldest = recent.front().copied().unwrap_or(now);
+        Err(self.window.saturating_sub(now.duration_since(oldest)))
+    }
+
+    pub fn acquire(&self) -> Result<(), Duration> {
+        self.acquire_at(Instant::now())
+    }
+}
+
+/// Who made a request, by the token it carries
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Caller {
+    Owner,
+    Guest,
+    Unknown,
+}
+
+/// The limited profile of requests made with `api.guest_token`, for guests who may
+/// browse the library, queue tracks, skip and turn the volume up to a bound, but not
+/// change the library, playlists or settings. Other requests must carry the owner's
+/// `api.auth_token`.
+pub struct GuestPolicy {
+    token: String,
+    owner_token: Option<String>,
+    max_volume: f32,
+    enqueues: RateLimiter,
+}
+
+impl GuestPolicy {
+    /// A policy without an owner token, under which only guests and share links are
+    /// let in; see [`GuestPolicy::with_owner_token`]
+    pub fn new(token: impl Into<String>, enqueues_per_minute: u32, max_volume: f32) -> Self {
+        Self {
+            token: token.into(),
+            owner_token: None,
+            max_volume: max_volume.clamp(0.0, 1.0),
+            enqueues: RateLimiter::new(enqueues_per_minute, Duration::from_secs(60)),
+        }
+    }
+
+    /// Let requests carrying `token` through unrestricted
+    pub fn with_owner_token(mut self, token: impl Into<String>) -> Self {
+        self.owner_token = Some(token.into());
+        self
+    }
+
+    /// The policy for `api.guest_token`, if one is set. Fails when `api.auth_token` is
+    /// not set with it, as nothing would tell the owner's requests from strangers'.
+    pub fn from_config(config: &ApiConfig) -> Result<Option<Self>> {
+        let Some(token) = non_empty(config.guest_token.as_deref()) else {
+            return Ok(None);
+        };
+        let owner_token = non_empty(config.auth_token.as_deref())
+            .ok_or_else(|| anyhow!("api.guest_token is set, but api.auth_token is not"))?;
+        if owner_token == token {
+            bail!("api.guest_token and api.auth_token must differ");
+        }
+        Ok(Some(
+            Self::new(
+                token,
+                config.guest_enqueues_per_minute,
+                config.guest_max_volume,
+            )
+            .with_owner_token(owner_token),
+        ))
+    }
+
+    /// Whose token `request` carries as `Authorization: Bearer`
+    fn caller(&self, request: &Request) -> Caller {
+        let token = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(str::trim);
+        let owner_token = self.owner_token.as_deref();
+        match token {
+            Some(token) if owner_token.is_some_and(|owner| tokens_match(owner, token)) => {
+                Caller::Owner
+            }
+            Some(token) if tokens_match(&self.token, token) => Caller::Guest,
+            _ => Caller::Unknown,
+        }
+    }
+
+    /// Check the volume a guest asks for, passing the request on with its body
+    async fn check_volume(&self, request: Request) -> Result<Request, Response> {
+        let (parts, body) = request.into_parts();
+        let bytes = to_bytes(body, CONTROL_BODY_LIMIT)
+            .await
+            .map_err(|_| StatusCode::PAYLOAD_TOO_LARGE.into_response())?;
+        let volume = serde_json::from_slice::<serde_json::Value>(&bytes)
+            .ok()
+            .and_then(|body| body.get("volume")?.as_f64());
+        if volume.is_some_and(|volume| volume > f64::from(self.max_volume)) {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                format!("Guests may set the volume up to {}", self.max_volume),
+            )
+            .into_response());
+        }
+        Ok(Request::from_parts(parts, Body::from(bytes)))
+    }
+}
+
+/// Compare tokens in time independent of where they differ
+fn tokens_match(expected: &str, given: &str) -> bool {
+    expected.as_bytes().ct_eq(given.as_bytes()).into()
+}
+
+/// Restrict requests made with the guest token to the routes [`guest_rule`] allows,
+/// answering others with 403, and attribute the events they cause to guests.
+/// Requests with the owner token, and requests opening share links, are passed on
+/// unchanged; any other is answered with 401. Without a guest token every request
+/// is passed on.
+pub(super) async fn enforce_guest_policy(
+    State(policy): State<Option<Arc<GuestPolicy>>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(policy) = policy else {
+        return next.run(request).await;
+    };
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    if SHARE_PUBLIC_ROUTES.contains(&path.as_str()) {
+        return next.run(request).await;
+    }
+    match policy.caller(&request) {
+        Caller::Owner => return next.run(request).await,
+        Caller::Guest => {}
+        Caller::Unknown => {
+            let mut response = ApiError::new(
+                StatusCode::UNAUTHORIZED,
+                "Send the owner or guest token as Authorization: Bearer",
+            )
+            .into_response();
+            response
+                .headers_mut()
+                .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+            return response;
+        }
+    }
+    let method = request.method().clone();
+
+    let request = match guest_rule(&method, &path) {
+        None => {
+            info!("Refused guest request {} {}", method, path);
+            return ApiError::new(
+                StatusCode::FORBIDDEN,
+                format!("Guests may not use {} {}", method, path),
+            )
+            .into_response();
+        }
+        Some(GuestRule::Allow) => request,
+        Some(GuestRule::Enqueue) => match policy.enqueues.acquire() {
+            Ok(()) => request,
+            Err(wait) => {
+                let mut response = ApiError::new(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    format!(
+                        "Guests may queue {} tracks per minute",
+                        policy.enqueues.limit
+                    ),
+                )
+                .into_response();
+                let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+                response
+                    .headers_mut()
+                    .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+                return response;
+            }
+        },
+        Some(GuestRule::Volume) => match policy.check_volume(request).await {
+            Ok(request) => request,
+            Err(response) => return response,
+        },
+    };
+
+    events::attributed(GUEST_SOURCE, next.run(request)).await
+}
+
+fn non_empty(token: Option<&str>) -> Option<&str> {
+    token.map(str::trim).filter(|token| !token.is_empty())
+}
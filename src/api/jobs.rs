@@ -0,0 +1,546 @@
+//! Long-running operations tracked as jobs.
+//!
+//! Scans, verification, waveform precomputation, maintenance runs and metadata
+//! find-and-replace are started through [`JobManager::start`] (or [`JobManager::run`]
+//! when the request waits for the outcome). Each [`Job`] records its parameters,
+//! progress, state and result, is listed at `GET /api/jobs` and announced with
+//! `job_progress` events. Only one job of a kind runs at a time, and finished jobs
+//! are kept for the history window, optionally across restarts.
+
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::{oneshot, Notify};
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::events::{EventBus, EventPayload};
+
+/// Minutes finished jobs are kept by default
+pub const DEFAULT_JOB_HISTORY_MINUTES: u64 = 60;
+
+/// Tracks between progress reports of jobs working track by track
+pub const JOB_PROGRESS_INTERVAL: usize = 25;
+
+/// What a job does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    /// Library scan, including the first scan of the setup
+    Scan,
+    /// Integrity check of every file
+    Verify,
+    /// Waveform precomputation
+    Waveforms,
+    /// Maintenance run
+    Maintenance,
+    /// Library-wide find-and-replace of a tag value
+    MetadataReplace,
+}
+
+impl JobKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Scan => "scan",
+            Self::Verify => "verify",
+            Self::Waveforms => "waveforms",
+            Self::Maintenance => "maintenance",
+            Self::MetadataReplace => "metadata_replace",
+        }
+    }
+
+    /// Whether jobs of this kind stop early when cancelled. The others cannot be
+    /// interrupted once started.
+    pub fn is_cancellable(self) -> bool {
+        matches!(
+            self,
+            Self::Scan | Self::Verify | Self::Waveforms | Self::MetadataReplace
+        )
+    }
+
+    /// How many jobs of this kind may run at once
+    pub fn concurrency_limit(self) -> usize {
+        1
+    }
+}
+
+/// Where a job is in its life
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Queued => "queued",
+            Self::Running => "running",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+            Self::Cancelled => "cancelled",
+        }
+    }
+
+    /// Whether the job is over
+    pub fn is_finished(self) -> bool {
+        matches!(self, Self::Completed | Self::Failed | Self::Cancelled)
+    }
+}
+
+/// How far a job got
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct JobProgress {
+    #[schema(example = 120)]
+    pub current: usize,
+    /// Units of work in all, when known
+    #[schema(example = 1200)]
+    pub total: Option<usize>,
+    #[schema(example = "/music/Artist/Album")]
+    pub message: Option<String>,
+}
+
+/// A long-running operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Job {
+    #[schema(example = 7)]
+    pub id: u64,
+    pub kind: JobKind,
+    /// What the job was asked to do
+    #[schema(value_type = Object)]
+    pub params: Value,
+    pub state: JobState,
+    pub progress: JobProgress,
+    /// Outcome of a finished job, by kind
+    #[schema(value_type = Option<Object>)]
+    pub result: Option<Value>,
+    /// Why the job failed
+    pub error: Option<String>,
+    /// Whether the job can be cancelled
+    pub cancellable: bool,
+    /// Whether cancelling it was requested
+    pub cancel_requested: bool,
+    #[serde(with = "crate::utils::serde_rfc3339")]
+    pub created_at: DateTime<Utc>,
+    #[serde(default, with = "crate::utils::serde_rfc3339::option")]
+    pub started_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "crate::utils::serde_rfc3339::option")]
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// State of the library scans started through the API, from the latest scan job
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ScanJobStatus {
+    /// Job id of the latest scan; 0 before the first one
+    #[schema(example = 3)]
+    pub job: u64,
+    /// Whether that scan is still running
+    pub running: bool,
+    /// Tracks in the library after it, once it completed
+    #[schema(example = 1200)]
+    pub tracks: Option<usize>,
+    /// Why it failed
+    pub error: Option<String>,
+}
+
+impl From<&Job> for ScanJobStatus {
+    fn from(job: &Job) -> Self {
+        Self {
+            job: job.id,
+            running: !job.state.is_finished(),
+            tracks: job
+                .result
+                .as_ref()
+                .and_then(|result| result["tracks"].as_u64())
+                .map(|tracks| tracks as usize),
+            error: job.error.clone(),
+        }
+    }
+}
+
+/// Why a job could not be started or cancelled
+#[derive(Debug, thiserror::Error)]
+pub enum JobError {
+    #[error("A {} job is already running", .0.as_str())]
+    Busy(JobKind),
+    #[error("Job {0} not found")]
+    NotFound(u64),
+    #[error("Job {0} has already finished")]
+    Finished(u64),
+    #[error("{} jobs cannot be cancelled", .0.as_str())]
+    NotCancellable(JobKind),
+}
+
+#[derive(Default)]
+struct Cancellation {
+    requested: AtomicBool,
+    notify: Notify,
+}
+
+/// A running job's view of itself, to report progress and notice cancellation
+#[derive(Clone)]
+pub struct JobHandle {
+    id: u64,
+    manager: Arc<JobManager>,
+    cancellation: Arc<Cancellation>,
+}
+
+impl JobHandle {
+    #[allow(dead_code)]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Record how far the job got and announce it
+    pub fn progress(&self, current: usize, total: Option<usize>, message: Option<String>) {
+        self.manager.update(self.id, |job| {
+            job.progress = JobProgress {
+                current,
+                total,
+                message,
+            };
+        });
+    }
+
+    /// Whether cancelling the job was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.requested.load(Ordering::SeqCst)
+    }
+
+    /// Wait until cancelling the job is requested
+    pub async fn cancelled(&self) {
+        loop {
+            // Registered before checking, so a request in between still wakes us
+            let notified = self.cancellation.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+struct JobEntry {
+    job: Job,
+    cancellation: Arc<Cancellation>,
+}
+
+/// Starts jobs and keeps them, with their history
+pub struct JobManager {
+    jobs: Mutex<BTreeMap<u64, JobEntry>>,
+    /// How long finished jobs are kept
+    history: Duration,
+    /// Where finished jobs are saved, if they outlive the process
+    history_file: Option<PathBuf>,
+    event_bus: Option<Arc<EventBus>>,
+}
+
+impl Default for JobManager {
+    fn default() -> Self {
+        Self {
+            jobs: Mutex::new(BTreeMap::new()),
+            history: Duration::from_secs(DEFAULT_JOB_HISTORY_MINUTES * 60),
+            history_file: None,
+            event_bus: None,
+        }
+    }
+}
+
+impl JobManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep finished jobs for `history`
+    pub fn with_history(mut self, history: Duration) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// Save finished jobs to `path`, loading those a previous run saved there
+    pub fn with_history_file(mut self, path: PathBuf) -> Self {
+        let saved: Vec<Job> = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring invalid job history {:?}: {}", path, e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        {
+            let mut jobs = self.jobs.lock().unwrap();
+            for job in saved.into_iter().filter(|job| job.state.is_finished()) {
+                jobs.insert(
+                    job.id,
+                    JobEntry {
+                        job,
+                        cancellation: Arc::default(),
+                    },
+                );
+            }
+        }
+        self.history_file = Some(path);
+        self.prune();
+        self
+    }
+
+    /// Announce job changes with `job_progress` events on `event_bus`
+    pub fn with_event_bus(mut self, event_bus: Arc<EventBus>) -> Self {
+        self.event_bus = Some(event_bus);
+        self
+    }
+
+    /// Every job kept, newest first
+    pub fn list(&self) -> Vec<Job> {
+        self.prune();
+        let jobs = self.jobs.lock().unwrap();
+        jobs.values().rev().map(|entry| entry.job.clone()).collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.get(&id).map(|entry| entry.job.clone())
+    }
+
+    /// The most recent job of `kind`
+    pub fn latest(&self, kind: JobKind) -> Option<Job> {
+        let jobs = self.jobs.lock().unwrap();
+        jobs.values()
+            .rev()
+            .find(|entry| entry.job.kind == kind)
+            .map(|entry| entry.job.clone())
+    }
+
+    /// The job of `kind` that has not finished yet, if any
+    pub fn active(&self, kind: JobKind) -> Option<Job> {
+        self.latest(kind).filter(|job| !job.state.is_finished())
+    }
+
+    /// Start `work` in the background as a job of `kind`. Returns the queued job, or
+    /// [`JobError::Busy`] without doing anything when as many jobs of that kind as
+    /// may run at once are active.
+    ///
+    /// The value `work` resolves to becomes the job's result. A job whose
+    /// cancellation was requested ends cancelled, whatever `work` resolves to.
+    pub fn start<T, F, Fut>(
+        self: &Arc<Self>,
+        kind: JobKind,
+        params: Value,
+        work: F,
+    ) -> Result<Job, JobError>
+    where
+        T: Serialize + Send + 'static,
+        F: FnOnce(JobHandle) -> Fut,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        self.spawn(kind, params, work, None)
+    }
+
+    /// Run `work` as a job of `kind` like [`JobManager::start`], waiting for its
+    /// outcome. The job keeps running if the caller stops waiting.
+    pub async fn run<T, F, Fut>(
+        self: &Arc<Self>,
+        kind: JobKind,
+        params: Value,
+        work: F,
+    ) -> Result<Result<T>, JobError>
+    where
+        T: Serialize + Send + 'static,
+        F: FnOnce(JobHandle) -> Fut,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        let (sender, receiver) = oneshot::channel();
+        self.spawn(kind, params, work, Some(sender))?;
+        Ok(receiver
+            .await
+            .unwrap_or_else(|_| Err(anyhow::anyhow!("the job was dropped"))))
+    }
+
+    /// Ask job `id` to stop. Returns the job, which keeps running until it notices.
+    pub fn cancel(&self, id: u64) -> Result<Job, JobError> {
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let entry = jobs.get_mut(&id).ok_or(JobError::NotFound(id))?;
+            if entry.job.state.is_finished() {
+                return Err(JobError::Finished(id));
+            }
+            if !entry.job.cancellable {
+                return Err(JobError::NotCancellable(entry.job.kind));
+            }
+            entry.job.cancel_requested = true;
+            entry.cancellation.requested.store(true, Ordering::SeqCst);
+            entry.cancellation.notify.notify_waiters();
+            entry.job.clone()
+        };
+        self.announce(&job);
+        Ok(job)
+    }
+
+    fn spawn<T, F, Fut>(
+        self: &Arc<Self>,
+        kind: JobKind,
+        params: Value,
+        work: F,
+        outcome: Option<oneshot::Sender<Result<T>>>,
+    ) -> Result<Job, JobError>
+    where
+        T: Serialize + Send + 'static,
+        F: FnOnce(JobHandle) -> Fut,
+        Fut: Future<Output = Result<T>> + Send + 'static,
+    {
+        let cancellation = Arc::new(Cancellation::default());
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let active = jobs
+                .values()
+                .filter(|entry| entry.job.kind == kind && !entry.job.state.is_finished())
+                .count();
+            if active >= kind.concurrency_limit() {
+                return Err(JobError::Busy(kind));
+            }
+            let id = jobs.keys().next_back().map_or(1, |last| last + 1);
+            let job = Job {
+                id,
+                kind,
+                params,
+                state: JobState::Queued,
+                progress: JobProgress::default(),
+                result: None,
+                error: None,
+                cancellable: kind.is_cancellable(),
+                cancel_requested: false,
+                created_at: Utc::now(),
+                started_at: None,
+                finished_at: None,
+            };
+            jobs.insert(
+                id,
+                JobEntry {
+                    job: job.clone(),
+                    cancellation: cancellation.clone(),
+                },
+            );
+            job
+        };
+        self.announce(&job);
+
+        let id = job.id;
+        let future = work(JobHandle {
+            id,
+            manager: Arc::clone(self),
+            cancellation: cancellation.clone(),
+        });
+        let manager = Arc::clone(self);
+        tokio::spawn(async move {
+            manager.update(id, |job| {
+                job.state = JobState::Running;
+                job.started_at = Some(Utc::now());
+            });
+            let result = future.await;
+            let cancelled = cancellation.requested.load(Ordering::SeqCst);
+            manager.update(id, |job| {
+                job.finished_at = Some(Utc::now());
+                match &result {
+                    Ok(value) => {
+                        job.result = serde_json::to_value(value).ok();
+                        job.state = JobState::Completed;
+                    }
+                    Err(error) => {
+                        job.error = Some(format!("{:#}", error));
+                        job.state = JobState::Failed;
+                    }
+                }
+                if cancelled {
+                    job.state = JobState::Cancelled;
+                }
+            });
+            manager.save_history();
+            if let Some(outcome) = outcome {
+                let _ = outcome.send(result);
+            }
+        });
+
+        Ok(job)
+    }
+
+    /// Change job `id` and announce it
+    fn update(&self, id: u64, change: impl FnOnce(&mut Job)) {
+        let job = {
+            let mut jobs = self.jobs.lock().unwrap();
+            let Some(entry) = jobs.get_mut(&id) else {
+                return;
+            };
+            change(&mut entry.job);
+            entry.job.clone()
+        };
+        self.announce(&job);
+    }
+
+    fn announce(&self, job: &Job) {
+        if let Some(event_bus) = self.event_bus.as_ref() {
+            event_bus.emit(EventPayload::job_progress(
+                job.id,
+                job.kind.as_str(),
+                job.state.as_str(),
+                job.progress.current,
+                job.progress.total,
+                job.progress.message.clone(),
+            ));
+        }
+    }
+
+    /// Forget finished jobs older than the history window
+    fn prune(&self) {
+        let Ok(history) = chrono::Duration::from_std(self.history) else {
+            return;
+        };
+        let cutoff = Utc::now() - history;
+        let mut jobs = self.jobs.lock().unwrap();
+        // The newest job of each kind is kept, so its outcome stays known and ids
+        // keep increasing
+        let mut newest: BTreeMap<&'static str, u64> = BTreeMap::new();
+        for (id, entry) in jobs.iter() {
+            newest.insert(entry.job.kind.as_str(), *id);
+        }
+        let newest: Vec<u64> = newest.into_values().collect();
+        jobs.retain(|id, entry| {
+            newest.contains(id) || entry.job.finished_at.is_none_or(|at| at >= cutoff)
+        });
+    }
+
+    fn save_history(&self) {
+        let Some(path) = self.history_file.as_ref() else {
+            return;
+        };
+        self.prune();
+        let finished: Vec<Job> = {
+            let jobs = self.jobs.lock().unwrap();
+            jobs.values()
+                .filter(|entry| entry.job.state.is_finished())
+                .map(|entry| entry.job.clone())
+                .collect()
+        };
+        if let Err(e) = write_history(path, &finished) {
+            warn!("Failed to save the job history to {:?}: {}", path, e);
+        }
+    }
+}
+
+fn write_history(path: &Path, jobs: &[Job]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(jobs)?)?;
+    Ok(())
+}
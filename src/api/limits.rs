@@ -0,0 +1,104 @@
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+
+use super::ApiError;
+use crate::playlist::normalize_folder;
+
+/// Largest request body accepted by playback, queue and other control endpoints
+pub const CONTROL_BODY_LIMIT: usize = 16 * 1024;
+/// Largest request body accepted by endpoints editing library metadata, playlists
+/// or the setup
+pub const EDIT_BODY_LIMIT: usize = 256 * 1024;
+/// Default `api.max_import_mb`
+pub const DEFAULT_IMPORT_LIMIT_MB: u64 = 8;
+/// Most directories a single scan or setup request may list
+pub const MAX_DIRECTORIES: usize = 64;
+/// Longest playlist name accepted, in characters
+pub const MAX_PLAYLIST_NAME_CHARS: usize = 200;
+/// Longest playlist folder path accepted, in characters
+pub const MAX_FOLDER_PATH_CHARS: usize = 500;
+/// Most tracks a single bulk track operation may act on
+pub const MAX_BULK_TRACKS: usize = 1000;
+
+/// Turn the plain-text 413 axum answers to bodies over the limit into the usual
+/// JSON error.
+pub(super) async fn json_payload_too_large(response: Response) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE || is_json {
+        return response;
+    }
+    ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large").into_response()
+}
+
+/// Reject more than [`MAX_DIRECTORIES`] directories.
+pub(super) fn check_directory_count(count: usize) -> Result<(), ApiError> {
+    if count > MAX_DIRECTORIES {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("At most {} directories can be listed", MAX_DIRECTORIES),
+        ));
+    }
+    Ok(())
+}
+
+/// Reject empty batches and ones over [`MAX_BULK_TRACKS`].
+pub(super) fn check_bulk_track_count(count: usize) -> Result<(), ApiError> {
+    if count == 0 {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "No tracks were listed",
+        ));
+    }
+    if count > MAX_BULK_TRACKS {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("At most {} tracks can be listed", MAX_BULK_TRACKS),
+        ));
+    }
+    Ok(())
+}
+
+/// Trim a playlist name, rejecting empty names and ones over
+/// [`MAX_PLAYLIST_NAME_CHARS`].
+pub(super) fn playlist_name(name: &str) -> Result<&str, ApiError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Playlist name cannot be empty",
+        ));
+    }
+    if name.chars().count() > MAX_PLAYLIST_NAME_CHARS {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Playlist names are limited to {} characters",
+                MAX_PLAYLIST_NAME_CHARS
+            ),
+        ));
+    }
+    Ok(name)
+}
+
+/// Normalize a playlist folder path, `None` being the root. Paths over
+/// [`MAX_FOLDER_PATH_CHARS`] are rejected.
+pub(super) fn playlist_folder(folder: &str) -> Result<Option<String>, ApiError> {
+    let folder = normalize_folder(folder);
+    if folder
+        .as_ref()
+        .is_some_and(|folder| folder.chars().count() > MAX_FOLDER_PATH_CHARS)
+    {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Playlist folders are limited to {} characters",
+                MAX_FOLDER_PATH_CHARS
+            ),
+        ));
+    }
+    Ok(folder)
+}
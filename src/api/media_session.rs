@@ -0,0 +1,536 @@
+//! The operating system's media session: the now-playing overlay of Windows (System
+//! Media Transport Controls) and macOS (Now Playing), with their play, pause, skip and
+//! seek controls and the keyboard's media keys.
+//!
+//! [`MediaSession::follow`] keeps a [`MediaSessionSink`] showing what the player is
+//! doing and carries out the [`MediaCommand`]s pressed in it through the same
+//! functions as the API, so they emit the usual events and bump the playback revision.
+//! The OS session itself needs a build with the `media-controls` feature; without it,
+//! or on platforms without one, there is nothing to register with.
+
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::debug;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+
+use super::{
+    active_track_path, emit_current_playback_state, pause_audio, play_from_queue, resume_audio,
+    stop_audio, ApiError, AppState, RevisionQuery,
+};
+use crate::audio::AudioState;
+use crate::events::EventPayload;
+use crate::playlist::PlaybackQueue;
+
+/// How far the seek buttons that do not say move playback
+#[allow(dead_code)]
+pub const SEEK_STEP: Duration = Duration::from_secs(10);
+/// How far into a track Previous restarts it rather than going back a track
+pub const RESTART_THRESHOLD: Duration = Duration::from_secs(3);
+
+/// The track shown in the media session
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaInfo {
+    /// The title tag, or the file name for untagged tracks
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<Duration>,
+    /// `file://` URL of the album's cached artwork
+    pub cover_url: Option<String>,
+}
+
+impl MediaInfo {
+    /// What the session shows for the file at `track_path`
+    fn new(state: &AppState, track_path: &str) -> Self {
+        let track = state.library.get_track_by_path(Path::new(track_path));
+        let metadata = track.as_ref().map(|track| &track.metadata);
+        Self {
+            title: metadata
+                .and_then(|metadata| metadata.title.clone())
+                .or_else(|| {
+                    Path::new(track_path)
+                        .file_stem()
+                        .map(|stem| stem.to_string_lossy().to_string())
+                })
+                .unwrap_or_default(),
+            artist: metadata.and_then(|metadata| metadata.artist.clone()),
+            album: metadata.and_then(|metadata| metadata.album.clone()),
+            duration: metadata
+                .and_then(|metadata| metadata.duration)
+                .map(Duration::from_secs),
+            cover_url: track
+                .as_ref()
+                .and_then(|track| track.album_id())
+                .and_then(|album_id| state.album_service.cached_artwork_path(&album_id))
+                .map(|path| file_url(&path)),
+        }
+    }
+}
+
+/// Playback as shown in the media session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaStatus {
+    Playing { position: Duration },
+    Paused { position: Duration },
+    Stopped,
+}
+
+/// A control pressed in the media session
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MediaCommand {
+    Play,
+    Pause,
+    /// Play/pause, as sent by most media keys
+    Toggle,
+    Next,
+    /// Back a track, or to the start of the current one once past
+    /// [`RESTART_THRESHOLD`]
+    Previous,
+    Stop,
+    SeekForward(Duration),
+    SeekBackward(Duration),
+    SetPosition(Duration),
+}
+
+/// Where the media session state goes: the operating system's session, or a stand-in
+pub trait MediaSessionSink: Send + 'static {
+    /// Show `info`, or no track at all
+    fn set_metadata(&mut self, info: Option<&MediaInfo>);
+    fn set_status(&mut self, status: MediaStatus);
+}
+
+/// Follows playback into the operating system's media session
+pub struct MediaSession;
+
+impl MediaSession {
+    /// Register with the operating system's media session when `enabled` (from
+    /// `integrations.media_session`). Nothing is started when the build lacks the
+    /// `media-controls` feature or the system has no session to register with.
+    pub fn start(state: &AppState, enabled: bool) -> Option<JoinHandle<()>> {
+        if !enabled {
+            return None;
+        }
+
+        #[cfg(all(feature = "media-controls", any(windows, target_os = "macos")))]
+        {
+            let (commands, received) = mpsc::unbounded_channel();
+            let sink = os::OsMediaSession::open(commands)?;
+            Some(Self::follow(state.clone(), sink, received))
+        }
+        #[cfg(not(all(feature = "media-controls", any(windows, target_os = "macos"))))]
+        {
+            let _ = state;
+            debug!("No media session to register with: it needs Windows or macOS and the media-controls feature");
+            None
+        }
+    }
+
+    /// Keep `sink` showing the current track and playback status, updated on every
+    /// `playback_state` event, and carry out the `commands` pressed in it. Stops
+    /// once the commands' sender is dropped.
+    #[allow(dead_code)]
+    pub fn follow(
+        state: AppState,
+        mut sink: impl MediaSessionSink,
+        mut commands: mpsc::UnboundedReceiver<MediaCommand>,
+    ) -> JoinHandle<()> {
+        let mut receiver = state.event_bus.subscribe();
+        tokio::spawn(async move {
+            let mut shown = None;
+            show(&state, &mut sink, &mut shown);
+            loop {
+                tokio::select! {
+                    event = receiver.recv() => match event {
+                        Ok(message) => {
+                            if !matches!(message.payload, EventPayload::PlaybackState { .. }) {
+                                continue;
+                            }
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            debug!("Media session skipped {} events", skipped);
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    command = commands.recv() => {
+                        let Some(command) = command else {
+                            break;
+                        };
+                        if let Err(error) = control(&state, command).await {
+                            debug!("Media session {:?} refused: {}", command, error.message);
+                        }
+                    }
+                }
+                show(&state, &mut sink, &mut shown);
+            }
+        })
+    }
+}
+
+/// Bring `sink` in line with the player, sending the track only when it changed
+/// from `shown`
+fn show(state: &AppState, sink: &mut impl MediaSessionSink, shown: &mut Option<MediaInfo>) {
+    let position = state.audio_player.get_position();
+    let status = match state.audio_player.get_state() {
+        AudioState::Playing | AudioState::Loading => MediaStatus::Playing { position },
+        AudioState::Paused | AudioState::DeviceLost => MediaStatus::Paused { position },
+        AudioState::Stopped => MediaStatus::Stopped,
+    };
+    let info = match status {
+        MediaStatus::Stopped => None,
+        _ => state
+            .audio_player
+            .get_current_track()
+            .map(|path| MediaInfo::new(state, &path)),
+    };
+    if info != *shown {
+        sink.set_metadata(info.as_ref());
+        *shown = info;
+    }
+    sink.set_status(status);
+}
+
+/// Carry out `command` as the matching API request would
+pub async fn control(state: &AppState, command: MediaCommand) -> Result<(), ApiError> {
+    let state_now = state.audio_player.get_state();
+    let revision = || Query(RevisionQuery::default());
+    match command {
+        MediaCommand::Play | MediaCommand::Toggle if state_now == AudioState::Paused => {
+            let _ = resume_audio(State(state.clone()), revision()).await?;
+        }
+        MediaCommand::Play | MediaCommand::Toggle if state_now == AudioState::Stopped => {
+            play_from_queue(
+                state,
+                &RevisionQuery::default(),
+                |queue| queue.current_track().or_else(|| queue.next_track()),
+                "The queue is empty",
+            )?;
+        }
+        MediaCommand::Pause | MediaCommand::Toggle if state_now == AudioState::Playing => {
+            let _ = pause_audio(State(state.clone()), revision()).await?;
+        }
+        MediaCommand::Play | MediaCommand::Pause | MediaCommand::Toggle => {}
+        MediaCommand::Next => {
+            play_from_queue(
+                state,
+                &RevisionQuery::default(),
+                PlaybackQueue::next_track,
+                "The queue has no next track",
+            )?;
+        }
+        MediaCommand::Previous => {
+            let restart = active_track_path(state).is_some()
+                && state.audio_player.get_position() > RESTART_THRESHOLD;
+            if restart {
+                seek(state, Duration::ZERO)?;
+            } else {
+                play_from_queue(
+                    state,
+                    &RevisionQuery::default(),
+                    PlaybackQueue::previous_track,
+                    "The queue has no previous track",
+                )?;
+            }
+        }
+        MediaCommand::Stop => {
+            let _ = stop_audio(State(state.clone()), revision()).await?;
+        }
+        MediaCommand::SeekForward(by) => seek(state, state.audio_player.get_position() + by)?,
+        MediaCommand::SeekBackward(by) => {
+            seek(state, state.audio_player.get_position().saturating_sub(by))?
+        }
+        MediaCommand::SetPosition(position) => seek(state, position)?,
+    }
+    Ok(())
+}
+
+/// Move playback of the current track to `position`
+fn seek(state: &AppState, position: Duration) -> Result<(), ApiError> {
+    if active_track_path(state).is_none() {
+        return Err(ApiError::new(StatusCode::CONFLICT, "Nothing is playing"));
+    }
+    state.audio_player.seek(position).map_err(|error| {
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to seek: {}", error),
+        )
+    })?;
+    emit_current_playback_state(state);
+    Ok(())
+}
+
+/// `file://` URL of `path`, as the media sessions load artwork from
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut url = String::from("file://");
+    if !path.starts_with('/') {
+        // A drive letter
+        url.push('/');
+    }
+    for ch in path.chars() {
+        match ch {
+            ' ' => url.push_str("%20"),
+            '#' => url.push_str("%23"),
+            '%' => url.push_str("%25"),
+            '?' => url.push_str("%3F"),
+            ch => url.push(ch),
+        }
+    }
+    url
+}
+
+/// Run the backend, `backend` being the whole of it. On macOS with the
+/// `media-controls` feature, media commands are delivered on the main dispatch queue,
+/// so the backend runs on a thread of its own while the main thread serves the queue,
+/// and the process exits with the backend.
+pub fn run_backend(
+    backend: impl FnOnce() -> anyhow::Result<()> + Send + 'static,
+) -> anyhow::Result<()> {
+    #[cfg(all(feature = "media-controls", target_os = "macos"))]
+    {
+        std::thread::Builder::new()
+            .name("hexendrum-main".into())
+            // As much as the main thread it stands in for
+            .stack_size(8 * 1024 * 1024)
+            .spawn(move || {
+                let code = match backend() {
+                    Ok(()) => 0,
+                    Err(error) => {
+                        tracing::error!("Error: {:?}", error);
+                        1
+                    }
+                };
+                std::process::exit(code);
+            })?;
+        // SAFETY: called once, from the main thread, which it then never returns to
+        unsafe { dispatch::ffi::dispatch_main() };
+        unreachable!("dispatch_main never returns");
+    }
+    #[cfg(not(all(feature = "media-controls", target_os = "macos")))]
+    backend()
+}
+
+#[cfg(all(feature = "media-controls", any(windows, target_os = "macos")))]
+mod os {
+    use souvlaki::{
+        MediaControlEvent, MediaControls, MediaMetadata, MediaPlayback, MediaPosition,
+        PlatformConfig, SeekDirection,
+    };
+    use std::sync::mpsc as std_mpsc;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+    use tracing::{debug, warn};
+
+    use super::{MediaCommand, MediaInfo, MediaSessionSink, MediaStatus, SEEK_STEP};
+
+    /// How often the session thread serves the platform's messages between updates
+    const PUMP_INTERVAL: Duration = Duration::from_millis(50);
+
+    enum Update {
+        Metadata(Option<MediaInfo>),
+        Status(MediaStatus),
+    }
+
+    /// The operating system's media session, owned by a thread of its own that
+    /// serves the window messages the session needs on Windows
+    pub struct OsMediaSession {
+        updates: std_mpsc::Sender<Update>,
+    }
+
+    impl OsMediaSession {
+        /// Register with the session, sending the controls pressed in it to
+        /// `commands`; `None` when the system has no session or refuses it
+        pub fn open(commands: mpsc::UnboundedSender<MediaCommand>) -> Option<Self> {
+            let (updates, received) = std_mpsc::channel();
+            let (ready, registered) = std_mpsc::sync_channel(1);
+            let spawned = std::thread::Builder::new()
+                .name("hexendrum-media-session".into())
+                .spawn(move || {
+                    let mut controls = match register(commands) {
+                        Ok(controls) => controls,
+                        Err(error) => {
+                            let _ = ready.send(Err(error));
+                            return;
+                        }
+                    };
+                    let _ = ready.send(Ok(()));
+                    serve(&mut controls, received);
+                });
+            if let Err(error) = spawned {
+                warn!("Cannot start the media session: {}", error);
+                return None;
+            }
+            match registered.recv() {
+                Ok(Ok(())) => Some(Self { updates }),
+                Ok(Err(error)) => {
+                    warn!("Cannot register with the media session: {}", error);
+                    None
+                }
+                Err(_) => {
+                    warn!("The media session thread stopped while registering");
+                    None
+                }
+            }
+        }
+    }
+
+    impl MediaSessionSink for OsMediaSession {
+        fn set_metadata(&mut self, info: Option<&MediaInfo>) {
+            let _ = self.updates.send(Update::Metadata(info.cloned()));
+        }
+
+        fn set_status(&mut self, status: MediaStatus) {
+            let _ = self.updates.send(Update::Status(status));
+        }
+    }
+
+    fn register(commands: mpsc::UnboundedSender<MediaCommand>) -> Result<MediaControls, String> {
+        let config = PlatformConfig {
+            display_name: "Hexendrum",
+            dbus_name: "hexendrum",
+            hwnd: platform::window(),
+        };
+        if cfg!(windows) && config.hwnd.is_none() {
+            return Err("no window to attach to".into());
+        }
+        let mut controls = MediaControls::new(config).map_err(|error| format!("{:?}", error))?;
+        controls
+            .attach(move |event| {
+                if let Some(command) = command(event) {
+                    let _ = commands.send(command);
+                }
+            })
+            .map_err(|error| format!("{:?}", error))?;
+        Ok(controls)
+    }
+
+    /// Apply updates to the session until the sending [`OsMediaSession`] is dropped
+    fn serve(controls: &mut MediaControls, updates: std_mpsc::Receiver<Update>) {
+        loop {
+            let result = match updates.recv_timeout(PUMP_INTERVAL) {
+                Ok(Update::Metadata(info)) => {
+                    let info = info.as_ref();
+                    controls.set_metadata(MediaMetadata {
+                        title: info.map(|info| info.title.as_str()),
+                        album: info.and_then(|info| info.album.as_deref()),
+                        artist: info.and_then(|info| info.artist.as_deref()),
+                        cover_url: info.and_then(|info| info.cover_url.as_deref()),
+                        duration: info.and_then(|info| info.duration),
+                    })
+                }
+                Ok(Update::Status(status)) => controls.set_playback(match status {
+                    MediaStatus::Playing { position } => MediaPlayback::Playing {
+                        progress: Some(MediaPosition(position)),
+                    },
+                    MediaStatus::Paused { position } => MediaPlayback::Paused {
+                        progress: Some(MediaPosition(position)),
+                    },
+                    MediaStatus::Stopped => MediaPlayback::Stopped,
+                }),
+                Err(std_mpsc::RecvTimeoutError::Timeout) => Ok(()),
+                Err(std_mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            if let Err(error) = result {
+                debug!("Media session update failed: {:?}", error);
+            }
+            platform::pump();
+        }
+    }
+
+    /// The command a control pressed in the session stands for, if any
+    fn command(event: MediaControlEvent) -> Option<MediaCommand> {
+        let seek = |direction, by| match direction {
+            SeekDirection::Forward => MediaCommand::SeekForward(by),
+            SeekDirection::Backward => MediaCommand::SeekBackward(by),
+        };
+        Some(match event {
+            MediaControlEvent::Play => MediaCommand::Play,
+            MediaControlEvent::Pause => MediaCommand::Pause,
+            MediaControlEvent::Toggle => MediaCommand::Toggle,
+            MediaControlEvent::Next => MediaCommand::Next,
+            MediaControlEvent::Previous => MediaCommand::Previous,
+            MediaControlEvent::Stop => MediaCommand::Stop,
+            MediaControlEvent::Seek(direction) => seek(direction, SEEK_STEP),
+            MediaControlEvent::SeekBy(direction, by) => seek(direction, by),
+            MediaControlEvent::SetPosition(MediaPosition(position)) => {
+                MediaCommand::SetPosition(position)
+            }
+            MediaControlEvent::SetVolume(_)
+            | MediaControlEvent::OpenUri(_)
+            | MediaControlEvent::Raise
+            | MediaControlEvent::Quit => return None,
+        })
+    }
+
+    #[cfg(windows)]
+    mod platform {
+        use std::ffi::c_void;
+        use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+        use windows_sys::Win32::UI::WindowsAndMessaging::{
+            CreateWindowExW, DefWindowProcW, DispatchMessageW, PeekMessageW, RegisterClassW,
+            TranslateMessage, MSG, PM_REMOVE, WNDCLASSW, WS_OVERLAPPED,
+        };
+
+        /// A hidden window for the session to belong to, the backend having none
+        pub fn window() -> Option<*mut c_void> {
+            let class: Vec<u16> = "HexendrumMediaSession\0".encode_utf16().collect();
+            // SAFETY: the class name outlives both calls, and the window procedure is
+            // the default one
+            unsafe {
+                let instance = GetModuleHandleW(std::ptr::null());
+                let window_class = WNDCLASSW {
+                    lpfnWndProc: Some(DefWindowProcW),
+                    hInstance: instance,
+                    lpszClassName: class.as_ptr(),
+                    ..std::mem::zeroed()
+                };
+                RegisterClassW(&window_class);
+                let window = CreateWindowExW(
+                    0,
+                    class.as_ptr(),
+                    class.as_ptr(),
+                    WS_OVERLAPPED,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    0,
+                    instance,
+                    std::ptr::null(),
+                );
+                (window != 0).then_some(window as *mut c_void)
+            }
+        }
+
+        /// Dispatch the messages waiting for the hidden window
+        pub fn pump() {
+            // SAFETY: `message` is only read after PeekMessageW filled it in
+            unsafe {
+                let mut message: MSG = std::mem::zeroed();
+                while PeekMessageW(&mut message, 0, 0, 0, PM_REMOVE) != 0 {
+                    TranslateMessage(&message);
+                    DispatchMessageW(&message);
+                }
+            }
+        }
+    }
+
+    #[cfg(not(windows))]
+    mod platform {
+        use std::ffi::c_void;
+
+        pub fn window() -> Option<*mut c_void> {
+            None
+        }
+
+        /// macOS delivers the session's commands on the main dispatch queue, served by
+        /// [`run_backend`](super::super::run_backend)
+        pub fn pump() {}
+    }
+}
@@ -2,44 +2,167 @@ use anyhow::Result;
 use axum::{
     body::Body,
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        Path, Query, State,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Path, Query, State,
     },
-    http::{header, StatusCode},
-    response::{Json, Response},
-    routing::{get, post},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Json, Response},
+    routing::{delete, get, patch, post, put},
     Router,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 use std::path::{Path as FsPath, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs;
+use tokio::net::TcpListener;
+use tokio::sync::Notify;
 use tower_http::cors::{Any, CorsLayer};
-use tracing::{error, info};
-use utoipa::{OpenApi, ToSchema};
+use tracing::{error, info, warn};
+use utoipa::{IntoParams, OpenApi, ToSchema};
 use utoipa_swagger_ui::SwaggerUi;
 
-use crate::audio::AudioPlayer;
-use crate::events::{EventBus, EventMessage, EventPayload};
+mod guest;
+mod jobs;
+mod limits;
+mod media_session;
+mod play_context;
+mod resume;
+mod revision;
+mod share;
+mod sleep_timer;
+mod timeouts;
+#[cfg(unix)]
+mod unix_socket;
+mod up_next;
+use guest::enforce_guest_policy;
+#[allow(unused_imports)]
+pub use guest::{guest_rule, GuestRule, RateLimiter, GUEST_SOURCE};
+pub use guest::{GuestPolicy, DEFAULT_GUEST_ENQUEUES_PER_MINUTE, DEFAULT_GUEST_MAX_VOLUME};
+pub use jobs::{Job, JobKind, JobManager, JobState, ScanJobStatus, DEFAULT_JOB_HISTORY_MINUTES};
+#[allow(unused_imports)]
+pub use jobs::{JobError, JobHandle, JobProgress};
+use limits::{
+    check_bulk_track_count, check_directory_count, json_payload_too_large, playlist_folder,
+    playlist_name,
+};
+pub use limits::{CONTROL_BODY_LIMIT, DEFAULT_IMPORT_LIMIT_MB, EDIT_BODY_LIMIT};
+#[allow(unused_imports)]
+pub use limits::{MAX_BULK_TRACKS, MAX_DIRECTORIES, MAX_PLAYLIST_NAME_CHARS};
+#[allow(unused_imports)]
+pub use media_session::{
+    control as media_control, MediaCommand, MediaInfo, MediaSessionSink, MediaStatus,
+    RESTART_THRESHOLD, SEEK_STEP,
+};
+pub use media_session::{run_backend, MediaSession};
+#[allow(unused_imports)]
+pub use play_context::QUEUE_WINDOW_TRACKS;
+use play_context::{album_position, context_position, context_track_ids, queue_window};
+pub use play_context::{PlayContext, PlayContextRequest, PlayContextType, QueueWindow};
+pub use resume::ResumePositions;
+pub use revision::PlaybackRevision;
+pub use share::{ShareClaims, ShareError, ShareScope, ShareSigner, SHARE_PUBLIC_ROUTES};
+pub use share::{DEFAULT_SHARE_HOURS, MAX_SHARE_HOURS};
+pub use sleep_timer::SleepTimer;
+#[allow(unused_imports)]
+pub use sleep_timer::{SleepTimerRequest, SleepTimerStatus, MAX_SLEEP_MINUTES, SLEEP_FADE};
+use timeouts::enforce_route_budget;
+pub use timeouts::RouteBudgets;
+pub use timeouts::{
+    DEFAULT_LONG_TIMEOUT_SECS, DEFAULT_ROUTE_TIMEOUT_SECS, DEFAULT_STATUS_TIMEOUT_SECS,
+};
+#[cfg(unix)]
+pub use unix_socket::serve_unix_socket;
+pub use up_next::UpNextWatcher;
+
+use crate::audio::{
+    read_chunks, transcode_stream, AudioDeviceInfo, AudioPlayer, AudioState, LoopError,
+    PlaybackLoop, Precache, PrecacheStats, PreviewStatus, ReplayGain, SourceFormat, TechnicalInfo,
+    Transcode, TranscodeCache, MAX_SPEED, MIN_SPEED,
+};
+use crate::config::{Config, Paths};
+use crate::diagnostics::{self, CheckResult, CheckStatus, DoctorReport};
+use crate::events::{
+    parse_since, EventBus, EventFilter, EventLog, EventMessage, EventPayload, UpNextTrack,
+    WebhookDispatcher, WebhookStatus,
+};
 use crate::library::{
-    album_identifier, AlbumExportFormat, AlbumMetadata, AlbumOverrideRecord, AlbumService,
-    AlbumSummary, Library, ManualAlbumUpdate, Track,
+    album_artwork_url, find_duplicate_groups, find_incomplete_albums, group_works,
+    recommend_keeper, similar_tracks, track_album_identifier, AlbumDisambiguation,
+    AlbumEditFileResult, AlbumEditReport, AlbumExportFormat, AlbumMetadata, AlbumOverrideRecord,
+    AlbumSearch, AlbumService, AlbumSort, AlbumSummary, ArtistCredit, Chapter, DecadeCount,
+    DeleteMode, DuplicateCandidate, DuplicateGroup, DuplicatePreferences, GenreRetagFile,
+    GenreSummary, GuessedFields, IncompleteAlbum, IntegrityRecord, IntegrityStatus, Library,
+    LibraryError, ManualAlbumUpdate, MetadataReplace, MetadataReplaceFile, MetadataSource,
+    MusicBrainzIds, NameCount, RawGenre, ReadOnlyError, ReplaceField, ReplaceMatch,
+    ScanInProgressError, ScanReport, Section, SidecarMetadata, StatsStore, SuggestionGroup,
+    SuggestionType, TagStatsImport, Track, TrackMatch, TrackSort, TrackTagUpdate, Trash,
+    VerificationJob, WaveformCache, WaveformPrecompute, Work, SCAN_RETRY_AFTER, WAVEFORM_BUCKETS,
+};
+use crate::maintenance::{
+    Maintenance, MaintenanceReport, MaintenanceRequest, MaintenanceTask, TaskReport,
+};
+use crate::playlist::{
+    CsvImportMatch, CsvImportReport, CsvTrackRow, ImportAction, ImportConflictPolicy,
+    OrphanedEntry, PlayOrder, PlaybackQueue, PlaylistEditError, PlaylistEntry, PlaylistError,
+    PlaylistFolder, PlaylistManager, PlaylistSummary, RepeatMode, QUEUE_HISTORY_LIMIT,
 };
-use crate::playlist::PlaylistManager;
-use chrono::{DateTime, Utc};
+use crate::utils::serde_rfc3339;
+use chrono::{DateTime, SubsecRound, Utc};
+
+/// Port the API server listens on
+pub const DEFAULT_PORT: u16 = 3030;
 
 /// API state shared across all handlers
 #[derive(Clone)]
 pub struct AppState {
     pub library: Arc<Library>,
     pub playlist_manager: Arc<PlaylistManager>,
+    pub playback_queue: Arc<PlaybackQueue>,
     pub audio_player: Arc<AudioPlayer>,
     pub album_service: Arc<AlbumService>,
+    pub stats_store: Arc<StatsStore>,
+    pub verification_job: Arc<VerificationJob>,
+    pub webhooks: Arc<WebhookDispatcher>,
+    pub trash: Arc<Trash>,
+    pub delete_mode: DeleteMode,
+    /// Which copy of a duplicated track is recommended as the keeper
+    pub duplicate_preferences: DuplicatePreferences,
     pub event_bus: Arc<EventBus>,
+    /// Where configuration and caches are stored
+    pub paths: Paths,
+    /// Signalled when the backend is asked to shut down
+    pub shutdown: Arc<Notify>,
+    /// Log of past events, when `events.log_file` is configured
+    pub event_log: Option<Arc<EventLog>>,
+    /// Revision of the playback state and volume, for conditional writes
+    pub revision: Arc<PlaybackRevision>,
+    /// Transcoded streams kept on disk, when `api.transcode_cache_mb` is set
+    pub transcode_cache: Option<Arc<TranscodeCache>>,
+    /// Local copies of the next tracks, when `audio.precache_mb` is set
+    pub precache: Option<Arc<Precache>>,
+    /// Largest request body accepted by import endpoints, from `api.max_import_mb`
+    pub max_import_bytes: usize,
+    /// Where long tracks were left off, from `audio.resume_min_minutes`
+    pub resume_positions: Arc<ResumePositions>,
+    /// Scans, verifications and other long-running operations, with their history
+    pub jobs: Arc<JobManager>,
+    /// How long requests may take, from `api.timeouts`
+    pub route_budgets: RouteBudgets,
+    /// What requests made with `api.guest_token` may do, when one is set
+    pub guest_policy: Option<Arc<GuestPolicy>>,
+    /// Waveforms of tracks, computed when first requested
+    pub waveforms: Arc<WaveformCache>,
+    /// Signs and checks share links
+    pub shares: Arc<ShareSigner>,
+    /// Stops playback after a while, when set with `POST /api/audio/sleep-timer`
+    pub sleep_timer: Arc<SleepTimer>,
 }
 
 /// Track response format for API
-#[derive(Debug, Serialize, ToSchema)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct TrackResponse {
     /// Unique track identifier
     #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
@@ -68,6 +191,30 @@ pub struct TrackResponse {
     /// Full file path
     #[schema(example = "/path/to/track.mp3")]
     pub path: String,
+    /// When the file was last modified
+    #[serde(with = "serde_rfc3339")]
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub last_modified: DateTime<Utc>,
+    /// Whether a `.hexendrum.json` sidecar overrides some of the file's tags
+    pub metadata_source: MetadataSource,
+    /// Fields guessed from the file name because the file has no such tags
+    pub guessed: GuessedFields,
+    /// Codec, sample rate, channels, bit depth and average bitrate, when the file could
+    /// be probed
+    pub technical: Option<TechnicalInfo>,
+    /// Seconds into the track where playback will resume, for long tracks that were
+    /// left off partway
+    #[schema(example = 1520)]
+    pub resume_position: Option<u64>,
+    /// Stars, 1–5, set through the API or imported from the file's tags
+    #[schema(example = 4)]
+    pub rating: Option<u8>,
+    /// Whether the track is marked as a favorite
+    pub favorite: bool,
+    /// MusicBrainz identifiers the file is tagged with
+    pub musicbrainz: MusicBrainzIds,
+    /// ReplayGain values the file is tagged with, applied with `audio.replaygain_mode`
+    pub replaygain: ReplayGain,
 }
 
 impl From<&Track> for TrackResponse {
@@ -75,8 +222,8 @@ impl From<&Track> for TrackResponse {
         let album_id = track
             .metadata
             .album
-            .as_ref()
-            .map(|album| album_identifier(track.metadata.artist.as_deref(), album));
+            .as_deref()
+            .map(|album| track_album_identifier(&track.metadata, album));
 
         Self {
             id: track.id.clone(),
@@ -88,6 +235,29 @@ impl From<&Track> for TrackResponse {
             duration: track.metadata.duration,
             file_size: track.metadata.file_size,
             path: track.metadata.file_path.to_string_lossy().to_string(),
+            last_modified: track.metadata.last_modified,
+            metadata_source: track.metadata.metadata_source,
+            guessed: track.metadata.guessed,
+            technical: track.metadata.technical.clone(),
+            resume_position: None,
+            rating: None,
+            favorite: false,
+            musicbrainz: track.metadata.musicbrainz.clone(),
+            replaygain: track.metadata.replaygain,
+        }
+    }
+}
+
+impl TrackResponse {
+    /// The response for `track`, with its saved resume position, rating and favorite
+    /// mark.
+    fn with_stats(track: &Track, stats: &StatsStore) -> Self {
+        let stats = stats.get(&track.metadata.file_path).unwrap_or_default();
+        Self {
+            resume_position: stats.resume_position,
+            rating: stats.rating,
+            favorite: stats.favorite,
+            ..Self::from(track)
         }
     }
 }
@@ -101,26 +271,157 @@ pub struct AlbumResponse {
     /// Album title
     #[schema(example = "A Night at the Opera")]
     pub title: String,
+    /// Edition when albums sharing a title and artist were told apart by release
+    /// year or track total
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "2005, 16 tracks")]
+    pub edition: Option<String>,
     /// Primary artist (if available)
     #[schema(example = "Queen")]
     pub primary_artist: Option<String>,
     /// All contributing artists discovered in the library
     #[schema(example = r#"["Queen"]"#)]
     pub artists: Vec<String>,
+    /// Artists credited on the album's tracks with their number of tracks, most
+    /// credited first
+    pub artist_credits: Vec<ArtistCredit>,
     /// Number of tracks in the album
     #[schema(example = 12)]
     pub track_count: usize,
     /// Artwork endpoint URL if cached
-    #[schema(example = "/api/library/albums/1f3870be274f6c49b3e31a0c6728957f/artwork")]
+    #[schema(
+        example = "/api/library/albums/1f3870be274f6c49b3e31a0c6728957f/artwork?v=9f86d081884c7d65"
+    )]
     pub artwork_url: Option<String>,
     /// Stored metadata resolved for this album (if available)
     pub metadata: Option<AlbumMetadata>,
     /// Indicates whether this album data was manually overridden
     pub is_manual: bool,
+    /// MusicBrainz release the album's tracks are tagged with, which identifies the
+    /// album, or else the one Last.fm matched it with
+    #[schema(example = "1dc4c347-a1db-32aa-b14f-bc9cc507b843")]
+    pub musicbrainz_release_id: Option<String>,
+}
+
+impl From<AlbumSummary> for AlbumResponse {
+    fn from(album: AlbumSummary) -> Self {
+        let AlbumSummary {
+            id,
+            title,
+            edition,
+            primary_artist,
+            artists,
+            artist_credits,
+            track_count,
+            artwork_path,
+            metadata,
+            is_manual,
+            musicbrainz_release_id,
+        } = album;
+
+        let artwork_url = artwork_path.map(|path| album_artwork_url(&id, &path));
+
+        Self {
+            id,
+            title,
+            edition,
+            primary_artist,
+            artists,
+            artist_credits,
+            track_count,
+            artwork_url,
+            metadata,
+            is_manual,
+            musicbrainz_release_id,
+        }
+    }
+}
+
+/// A page of album search results
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AlbumPageResponse {
+    /// Number of albums matching the query
+    #[schema(example = 5000)]
+    pub total: usize,
+    /// Index of the first album of this page
+    #[schema(example = 0)]
+    pub offset: usize,
+    pub albums: Vec<AlbumResponse>,
+    /// Letter sections of all matching albums, for title and artist sorts
+    pub sections: Vec<Section>,
+}
+
+/// Artists of the library in collation order
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ArtistListResponse {
+    #[schema(example = json!(["ABBA", "Édith Piaf", "Ólafur Arnalds"]))]
+    pub artists: Vec<String>,
+    /// Letter sections of `artists`, with `#` for names not starting with a letter
+    pub sections: Vec<Section>,
 }
 
 /// API response wrapper
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+#[aliases(
+    ApiResponseString = ApiResponse<String>,
+    ApiResponseUsize = ApiResponse<usize>,
+    ApiResponseCleanupReport = ApiResponse<CleanupReport>,
+    ApiResponseDoctorReport = ApiResponse<DoctorReport>,
+    ApiResponseMaintenanceReport = ApiResponse<MaintenanceReport>,
+    ApiResponseSetupStatus = ApiResponse<SetupStatusResponse>,
+    ApiResponseTrack = ApiResponse<TrackResponse>,
+    ApiResponseTracks = ApiResponse<Vec<TrackResponse>>,
+    ApiResponseAlbum = ApiResponse<AlbumResponse>,
+    ApiResponseSuggestions = ApiResponse<Vec<SuggestionGroup>>,
+    ApiResponseChapters = ApiResponse<Vec<Chapter>>,
+    ApiResponseWaveform = ApiResponse<WaveformResponse>,
+    ApiResponseWaveformPrecompute = ApiResponse<WaveformPrecompute>,
+    ApiResponseShareLink = ApiResponse<ShareLink>,
+    ApiResponseSharedView = ApiResponse<SharedView>,
+    ApiResponseScanReport = ApiResponse<ScanReportResponse>,
+    ApiResponseScanJob = ApiResponse<ScanJobStatus>,
+    ApiResponseJob = ApiResponse<Job>,
+    ApiResponseJobs = ApiResponse<Vec<Job>>,
+    ApiResponseDeletedTrack = ApiResponse<DeletedTrackResponse>,
+    ApiResponseBulkTracks = ApiResponse<BulkTrackResponse>,
+    ApiResponseCorruptTracks = ApiResponse<Vec<CorruptTrackResponse>>,
+    ApiResponseAlbums = ApiResponse<AlbumPageResponse>,
+    ApiResponseArtists = ApiResponse<ArtistListResponse>,
+    ApiResponseSections = ApiResponse<Vec<Section>>,
+    ApiResponseAlbumOverride = ApiResponse<AlbumOverrideResponse>,
+    ApiResponseAlbumArtwork = ApiResponse<AlbumArtworkResponse>,
+    ApiResponseAlbumEdit = ApiResponse<AlbumEditResponse>,
+    ApiResponseWorks = ApiResponse<Vec<WorkResponse>>,
+    ApiResponseGenres = ApiResponse<Vec<GenreSummary>>,
+    ApiResponseRawGenres = ApiResponse<Vec<RawGenre>>,
+    ApiResponseGenreRetag = ApiResponse<Vec<GenreRetagFile>>,
+    ApiResponseMetadataReplace = ApiResponse<Vec<MetadataReplaceFile>>,
+    ApiResponseTagStatsImport = ApiResponse<TagStatsImport>,
+    ApiResponseIncompleteAlbums = ApiResponse<Vec<IncompleteAlbumResponse>>,
+    ApiResponseDuplicateGroups = ApiResponse<Vec<DuplicateGroup>>,
+    ApiResponseDuplicateReport = ApiResponse<DuplicateReportResponse>,
+    ApiResponseDuplicateResolution = ApiResponse<DuplicateResolutionResponse>,
+    ApiResponseStats = ApiResponse<LibraryStats>,
+    ApiResponsePlaylist = ApiResponse<PlaylistResponse>,
+    ApiResponsePlaylists = ApiResponse<Vec<PlaylistResponse>>,
+    ApiResponsePlaylistTree = ApiResponse<PlaylistFolderResponse>,
+    ApiResponsePlaylistTrack = ApiResponse<PlaylistTrackResponse>,
+    ApiResponsePlaylistTracks = ApiResponse<PlaylistTracksResponse>,
+    ApiResponsePlaylistStats = ApiResponse<PlaylistStatsResponse>,
+    ApiResponseCsvImport = ApiResponse<CsvImportResponse>,
+    ApiResponseAudioStatus = ApiResponse<AudioStatusResponse>,
+    ApiResponseAudioLoop = ApiResponse<AudioLoop>,
+    ApiResponseSleepTimer = ApiResponse<SleepTimerStatus>,
+    ApiResponseAudioDevice = ApiResponse<AudioDeviceInfo>,
+    ApiResponseAudioDevices = ApiResponse<AudioDeviceList>,
+    ApiResponsePrecacheStats = ApiResponse<PrecacheStats>,
+    ApiResponsePreview = ApiResponse<PreviewStatus>,
+    ApiResponseQueue = ApiResponse<QueueResponse>,
+    ApiResponseQueueWindow = ApiResponse<QueueWindow>,
+    ApiResponseQueueHistory = ApiResponse<Vec<QueueHistoryItem>>,
+    ApiResponseWebhooks = ApiResponse<Vec<WebhookStatusResponse>>,
+    ApiResponseEventLog = ApiResponse<EventLogResponse>
+)]
 pub struct ApiResponse<T> {
     /// Whether the request was successful
     pub success: bool,
@@ -128,6 +429,10 @@ pub struct ApiResponse<T> {
     pub data: Option<T>,
     /// Error message (present if success is false)
     pub error: Option<String>,
+    /// Set while the library cache is still loading after startup, when library data
+    /// may be incomplete
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub loading: bool,
 }
 
 impl<T> ApiResponse<T> {
@@ -136,16 +441,180 @@ impl<T> ApiResponse<T> {
             success: true,
             data: Some(data),
             error: None,
+            loading: false,
         }
     }
 
+    fn with_loading(mut self, loading: bool) -> Self {
+        self.loading = loading;
+        self
+    }
+
     #[allow(dead_code)]
     fn error(message: String) -> ApiResponse<()> {
         ApiResponse {
             success: false,
             data: None,
             error: Some(message),
+            loading: false,
+        }
+    }
+}
+
+/// Body of error responses
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ApiErrorResponse {
+    /// Always false
+    #[schema(example = false)]
+    pub success: bool,
+    /// Always null
+    #[schema(value_type = Option<Object>)]
+    pub data: Option<()>,
+    /// Error message
+    #[schema(example = "Not Found")]
+    pub error: String,
+}
+
+/// Error returned by handlers, sent to the client as an [`ApiErrorResponse`].
+#[derive(Debug)]
+pub struct ApiError {
+    status: StatusCode,
+    message: String,
+    /// Seconds sent in a `Retry-After` header
+    retry_after: Option<u64>,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self {
+            status,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    /// Tell the client to try again after `seconds`
+    fn with_retry_after(mut self, seconds: u64) -> Self {
+        self.retry_after = Some(seconds);
+        self
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self::new(
+            status,
+            status.canonical_reason().unwrap_or("Request failed"),
+        )
+    }
+}
+
+impl From<ReadOnlyError> for ApiError {
+    fn from(error: ReadOnlyError) -> Self {
+        Self::new(StatusCode::FORBIDDEN, error.to_string())
+    }
+}
+
+impl From<ScanInProgressError> for ApiError {
+    fn from(error: ScanInProgressError) -> Self {
+        Self::new(StatusCode::CONFLICT, error.to_string())
+            .with_retry_after(error.retry_after.as_secs())
+    }
+}
+
+impl From<JobError> for ApiError {
+    fn from(error: JobError) -> Self {
+        let status = match error {
+            JobError::NotFound(_) => StatusCode::NOT_FOUND,
+            JobError::Busy(_) | JobError::Finished(_) | JobError::NotCancellable(_) => {
+                StatusCode::CONFLICT
+            }
+        };
+        Self::new(status, error.to_string())
+    }
+}
+
+impl From<LibraryError> for ApiError {
+    fn from(error: LibraryError) -> Self {
+        match error {
+            LibraryError::TrackNotFound(_) => Self::new(StatusCode::NOT_FOUND, error.to_string()),
+            LibraryError::ScanInProgress => ScanInProgressError {
+                retry_after: SCAN_RETRY_AFTER,
+            }
+            .into(),
+            LibraryError::ReadOnly(error) => error.into(),
+            LibraryError::ScanCancelled => Self::new(StatusCode::CONFLICT, error.to_string()),
+            LibraryError::CacheCorrupt { .. }
+            | LibraryError::DirectoryUnreadable { .. }
+            | LibraryError::Metadata { .. }
+            | LibraryError::Unreadable { .. }
+            | LibraryError::Schema(_)
+            | LibraryError::Io(_)
+            | LibraryError::Serde(_) => {
+                error!("Library operation failed: {}", error);
+                Self::new(StatusCode::INTERNAL_SERVER_ERROR, error.to_string())
+            }
+        }
+    }
+}
+
+impl From<PlaylistError> for ApiError {
+    fn from(error: PlaylistError) -> Self {
+        let status = match error {
+            PlaylistError::PlaylistNotFound(_) => StatusCode::NOT_FOUND,
+            PlaylistError::Conflict(_) => StatusCode::CONFLICT,
+            PlaylistError::RootFolder
+            | PlaylistError::InvalidRepeatMode(_)
+            | PlaylistError::MissingTitleColumn
+            | PlaylistError::Csv(_) => StatusCode::BAD_REQUEST,
+            PlaylistError::Schema(_) | PlaylistError::Io(_) | PlaylistError::Serde(_) => {
+                error!("Playlist operation failed: {}", error);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+        Self::new(status, error.to_string())
+    }
+}
+
+impl From<PlaylistEditError> for ApiError {
+    fn from(error: PlaylistEditError) -> Self {
+        match error {
+            PlaylistEditError::NotFound => StatusCode::NOT_FOUND.into(),
+            PlaylistEditError::Conflict { .. } => {
+                Self::new(StatusCode::CONFLICT, error.to_string())
+            }
+            PlaylistEditError::Save(e) => {
+                error!("Failed to save playlist: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into()
+            }
+        }
+    }
+}
+
+impl From<ShareError> for ApiError {
+    fn from(error: ShareError) -> Self {
+        let status = match error {
+            ShareError::Invalid => StatusCode::NOT_FOUND,
+            ShareError::Expired { .. } => StatusCode::GONE,
+        };
+        Self::new(status, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ApiErrorResponse {
+            success: false,
+            data: None,
+            error: self.message,
+        };
+        let mut response = (self.status, Json(body)).into_response();
+        if let Some(seconds) = self.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, header::HeaderValue::from(seconds));
         }
+        response
     }
 }
 
@@ -155,22 +624,330 @@ pub struct ScanRequest {
     /// List of directory paths to scan for music files
     #[schema(example = r#"["/home/user/Music", "/home/user/Documents/Music"]"#)]
     pub directories: Vec<String>,
+    /// Read every file again and rebuild the library from what is found, rather than
+    /// keeping the tracks of unchanged files
+    #[serde(default)]
+    pub full_rescan: bool,
 }
 
 /// Search query parameters
-#[derive(Debug, Deserialize, ToSchema)]
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct SearchQuery {
-    /// Search query string
-    #[schema(example = "rock")]
+    /// Search query string, optionally with `format:` and `samplerate:` filters
+    #[param(example = "rock format:flac samplerate:>48000")]
     pub q: String,
 }
 
-/// Album search query parameters
+/// Suggestion query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SuggestQuery {
+    /// Text typed so far
+    #[param(example = "radio")]
+    pub q: String,
+    /// Comma-separated suggestion types (defaults to artist,album,title)
+    #[param(example = "artist,album")]
+    pub types: Option<String>,
+    /// Maximum suggestions per type (defaults to 8, at most 50)
+    #[param(example = 8)]
+    pub limit: Option<usize>,
+}
+
+/// Track streaming parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StreamQuery {
+    /// Transcode to this format instead of sending the file as stored
+    #[param(example = "opus")]
+    pub transcode: Option<String>,
+    /// Bitrate of the transcoded stream in kbit/s, 16 to 320 (defaults to 128)
+    #[param(example = 128)]
+    pub bitrate: Option<u32>,
+    /// Start the transcoded stream this many seconds into the track
+    #[param(example = 90.5)]
+    pub start: Option<f64>,
+}
+
+/// A configured music directory and whether the backend can read it
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SetupDirectoryStatus {
+    #[schema(example = "/home/user/Music")]
+    pub path: String,
+    pub readable: bool,
+}
+
+/// What a fresh install still needs before it is usable
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SetupStatusResponse {
+    /// Whether the setup flow should be shown: there is no config file or no music
+    /// directory is configured
+    pub needs_setup: bool,
+    /// Whether a config file exists; without one the defaults are in effect
+    pub config_exists: bool,
+    /// Configured music directories
+    pub music_directories: Vec<SetupDirectoryStatus>,
+    /// Whether at least one music directory is configured and all of them can be read
+    pub music_directories_ready: bool,
+    /// Whether a library scan has completed, in this run or an earlier one
+    pub first_scan_completed: bool,
+    /// Whether a scan is running
+    pub scanning: bool,
+    /// Whether an audio output device is available
+    pub audio_device_available: bool,
+    /// Name of the output device, when known
+    #[schema(example = "default")]
+    pub audio_device: Option<String>,
+}
+
+/// Starter configuration written by `POST /api/setup/initialize`
 #[derive(Debug, Deserialize, ToSchema)]
+pub struct SetupRequest {
+    /// Music directories to scan
+    #[schema(example = json!(["/home/user/Music"]))]
+    pub music_directories: Vec<String>,
+    /// Initial volume (0.0 to 1.0)
+    #[schema(example = 0.7)]
+    pub default_volume: Option<f32>,
+    /// Scan the music directories on startup
+    pub auto_scan: Option<bool>,
+    /// Never write tags, sidecars or files in the music directories; applies after a
+    /// restart
+    pub read_only: Option<bool>,
+    /// Replace an existing config file
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Track listing query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TrackListQuery {
+    /// Result ordering, by the configured `library.sort_locale`; unsorted when unset
+    pub sort: Option<TrackSort>,
+}
+
+/// Album search query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct AlbumSearchQuery {
     /// Optional search query string
-    #[schema(example = "opera")]
+    #[param(example = "opera")]
     pub q: Option<String>,
+    /// Result ordering (defaults to title)
+    pub sort: Option<AlbumSort>,
+    /// Index of the first album to return (defaults to 0)
+    #[param(example = 0)]
+    pub offset: Option<usize>,
+    /// Maximum number of albums to return (defaults to all of them)
+    #[param(example = 50)]
+    pub limit: Option<usize>,
+}
+
+/// Classical works query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WorksQuery {
+    /// Only list works by this composer
+    #[param(example = "Ludwig van Beethoven")]
+    pub composer: Option<String>,
+}
+
+/// A movement of a work, backed by a library track
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WorkMovementResponse {
+    /// Library track identifier
+    pub track_id: String,
+    /// Full track title
+    #[schema(example = "Symphony No. 5 in C minor, Op. 67: II. Andante con moto")]
+    pub title: Option<String>,
+    /// Movement name
+    #[schema(example = "Andante con moto")]
+    pub movement: Option<String>,
+    /// Movement number within the work
+    #[schema(example = 2)]
+    pub movement_number: Option<u32>,
+    /// Performing artist
+    #[schema(example = "Berliner Philharmoniker")]
+    pub artist: Option<String>,
+    /// Album the recording belongs to
+    pub album: Option<String>,
+    /// Duration in seconds
+    pub duration: Option<u64>,
+}
+
+/// An album with track numbers missing from the library
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct IncompleteAlbumResponse {
+    /// Stable album identifier derived from artist and album tags
+    #[schema(example = "1f3870be274f6c49b3e31a0c6728957f")]
+    pub id: String,
+    /// Album title
+    #[schema(example = "A Night at the Opera")]
+    pub title: String,
+    /// Primary artist for the album
+    #[schema(example = "Queen")]
+    pub primary_artist: Option<String>,
+    /// Number of the album's tracks in the library
+    #[schema(example = 10)]
+    pub track_count: usize,
+    /// Track total from the tags, when present
+    #[schema(example = 12)]
+    pub track_total: Option<u32>,
+    /// Track numbers with no track in the library
+    #[schema(example = json!([4, 11]))]
+    pub missing_track_numbers: Vec<u32>,
+}
+
+impl From<IncompleteAlbum> for IncompleteAlbumResponse {
+    fn from(album: IncompleteAlbum) -> Self {
+        Self {
+            id: album.album_id,
+            title: album.title,
+            primary_artist: album.primary_artist,
+            track_count: album.track_count,
+            track_total: album.track_total,
+            missing_track_numbers: album.missing_track_numbers,
+        }
+    }
+}
+
+/// Copies of a duplicated track compared side by side
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DuplicateReportResponse {
+    pub group: DuplicateGroup,
+    /// Identifier of the copy recommended to keep
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub recommended_keeper: Option<String>,
+    pub tracks: Vec<DuplicateCandidate>,
+}
+
+/// Which copy of a duplicate group to keep
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ResolveDuplicatesRequest {
+    /// Track to keep; the recommended keeper when omitted
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub keeper_id: Option<String>,
+}
+
+/// Outcome of resolving a duplicate group
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DuplicateResolutionResponse {
+    /// The copy that was kept
+    pub keeper_id: String,
+    /// The copies that were deleted
+    pub removed: Vec<DeletedTrackResponse>,
+    /// Playlists whose entries were moved to the keeper
+    #[schema(example = 2)]
+    pub playlists_updated: usize,
+}
+
+/// A sidecar file skipped during the last scan
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SidecarErrorResponse {
+    /// Path of the sidecar file
+    #[schema(example = "/music/track.mp3.hexendrum.json")]
+    pub path: String,
+    /// Why the sidecar was skipped
+    #[schema(example = "unknown field `titel`, expected one of `title`, `artist`, ...")]
+    pub error: String,
+}
+
+/// Outcome of the last library scan
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ScanReportResponse {
+    /// Tracks in the library after the scan
+    #[schema(example = 1200)]
+    pub tracks: usize,
+    /// Sidecars that were ignored; their tracks use the file's own tags
+    pub sidecar_errors: Vec<SidecarErrorResponse>,
+}
+
+impl From<ScanReport> for ScanReportResponse {
+    fn from(report: ScanReport) -> Self {
+        Self {
+            tracks: report.tracks,
+            sidecar_errors: report
+                .sidecar_errors
+                .into_iter()
+                .map(|error| SidecarErrorResponse {
+                    path: error.path.to_string_lossy().to_string(),
+                    error: error.error,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A composition with its movements gathered across albums
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WorkResponse {
+    /// Composer name
+    #[schema(example = "Ludwig van Beethoven")]
+    pub composer: String,
+    /// Work title
+    #[schema(example = "Symphony No. 5 in C minor, Op. 67")]
+    pub title: String,
+    /// Movements ordered by movement number
+    pub movements: Vec<WorkMovementResponse>,
+}
+
+impl From<Work> for WorkResponse {
+    fn from(work: Work) -> Self {
+        Self {
+            composer: work.composer,
+            title: work.title,
+            movements: work
+                .movements
+                .into_iter()
+                .map(|movement| WorkMovementResponse {
+                    track_id: movement.track_id,
+                    title: movement.title,
+                    movement: movement.movement,
+                    movement_number: movement.movement_number,
+                    artist: movement.artist,
+                    album: movement.album,
+                    duration: movement.duration,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Event stream query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventsQuery {
+    /// Comma separated event types to receive; all events when omitted
+    #[param(example = "playback_state,library_scan")]
+    pub types: Option<String>,
+}
+
+const DEFAULT_EVENT_LOG_LIMIT: usize = 100;
+const MAX_EVENT_LOG_LIMIT: usize = 1000;
+
+/// Event log query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct EventLogQuery {
+    /// Only entries from this time on: an RFC 3339 timestamp, Unix seconds or an age
+    /// such as `15m`, `2h` or `7d`
+    #[param(example = "2h")]
+    pub since: Option<String>,
+    /// Maximum number of entries, the most recent ones (defaults to 100, at most 1000)
+    #[param(example = 100)]
+    pub limit: Option<usize>,
+}
+
+/// Recent entries of the event log
+#[derive(Debug, Serialize, ToSchema)]
+pub struct EventLogResponse {
+    /// Logged events, oldest first
+    #[schema(value_type = Vec<EventMessage>)]
+    pub entries: Vec<serde_json::Value>,
+    /// Events the log writer dropped since startup because it fell behind
+    #[schema(example = 0)]
+    pub dropped: u64,
 }
 
 /// Manual album metadata update payload
@@ -191,6 +968,21 @@ pub struct ManualAlbumUpdateRequest {
     /// Force Hexendrum to refresh cached artwork and metadata from remote providers
     #[serde(default)]
     pub refresh_artwork: bool,
+    /// Always merge (`merge`) or split (`split`) the editions of this album, or follow
+    /// `library.album_disambiguation` (`auto`)
+    pub disambiguation: Option<AlbumDisambiguation>,
+}
+
+/// Artwork stored for an album
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AlbumArtworkResponse {
+    #[schema(example = "1f3870be274f6c49b3e31a0c6728957f")]
+    pub album_id: String,
+    /// Artwork URL, which changes whenever the image does
+    #[schema(
+        example = "/api/library/albums/1f3870be274f6c49b3e31a0c6728957f/artwork?v=9f86d081884c7d65"
+    )]
+    pub artwork_url: String,
 }
 
 /// Manual override record response
@@ -212,7 +1004,15 @@ pub struct AlbumOverrideResponse {
     pub artwork_path: Option<String>,
     /// Artwork URL served by the backend (if cached)
     pub artwork_url: Option<String>,
+    /// Whether the album's editions are always merged, always split or follow the
+    /// configuration
+    pub disambiguation: AlbumDisambiguation,
+    /// MusicBrainz release Last.fm matched the album with, when its tracks are not
+    /// tagged with one
+    #[schema(example = "1dc4c347-a1db-32aa-b14f-bc9cc507b843")]
+    pub musicbrainz_release_id: Option<String>,
     /// Last time this override was updated
+    #[serde(with = "serde_rfc3339")]
     pub updated_at: DateTime<Utc>,
 }
 
@@ -221,7 +1021,7 @@ impl From<AlbumOverrideRecord> for AlbumOverrideResponse {
         let artwork_url = record
             .artwork_path
             .as_ref()
-            .map(|_| format!("/api/library/albums/{}/artwork", record.album_id));
+            .map(|path| album_artwork_url(&record.album_id, FsPath::new(path)));
 
         Self {
             album_id: record.album_id,
@@ -232,47 +1032,420 @@ impl From<AlbumOverrideRecord> for AlbumOverrideResponse {
             metadata: record.metadata,
             artwork_path: record.artwork_path,
             artwork_url,
+            disambiguation: record.disambiguation,
+            musicbrainz_release_id: record.musicbrainz_release_id,
             updated_at: record.updated_at,
         }
     }
 }
 
-/// Query parameters used when exporting manual album overrides
+/// Bulk metadata edit applied to every track of an album
 #[derive(Debug, Deserialize, ToSchema)]
+pub struct AlbumEditRequest {
+    /// Track artist written to every track
+    #[schema(example = "The Beatles")]
+    pub artist: Option<String>,
+    /// Album artist written to every track
+    #[schema(example = "The Beatles")]
+    pub album_artist: Option<String>,
+    /// Album title written to every track
+    #[schema(example = "Abbey Road")]
+    pub title: Option<String>,
+    /// Genre written to every track
+    #[schema(example = "Rock")]
+    pub genre: Option<String>,
+    /// Release year written to every track
+    #[schema(example = 1969)]
+    pub year: Option<i32>,
+}
+
+/// Result of a bulk album edit for a single file
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AlbumEditFileResponse {
+    /// Track identifier
+    pub track_id: String,
+    /// File path of the track
+    pub path: String,
+    /// Whether the tags were written successfully
+    pub success: bool,
+    /// Error message when the file could not be updated
+    pub error: Option<String>,
+}
+
+/// Report returned by the bulk album edit endpoint
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AlbumEditResponse {
+    /// Album identifier before the edit
+    #[schema(example = "1f3870be274f6c49b3e31a0c6728957f")]
+    pub previous_album_id: String,
+    /// Album identifier after the edit (changes when artist or title changed)
+    #[schema(example = "5d41402abc4b2a76b9719d911017c592")]
+    pub album_id: String,
+    /// Number of files updated successfully
+    pub updated: usize,
+    /// Number of files that failed to update
+    pub failed: usize,
+    /// Per-file results
+    pub files: Vec<AlbumEditFileResponse>,
+    /// Manual override fields the new album already set differently, which were kept
+    /// over those of the edited album
+    #[schema(example = json!(["title"]))]
+    pub override_conflicts: Vec<String>,
+}
+
+impl From<AlbumEditFileResult> for AlbumEditFileResponse {
+    fn from(file: AlbumEditFileResult) -> Self {
+        Self {
+            track_id: file.track_id,
+            path: file.path.to_string_lossy().to_string(),
+            success: file.error.is_none(),
+            error: file.error,
+        }
+    }
+}
+
+impl From<AlbumEditReport> for AlbumEditResponse {
+    fn from(report: AlbumEditReport) -> Self {
+        let updated = report.succeeded();
+        let failed = report.failed();
+
+        Self {
+            previous_album_id: report.previous_album_id,
+            album_id: report.album_id,
+            updated,
+            failed,
+            files: report.files.into_iter().map(Into::into).collect(),
+            override_conflicts: report
+                .override_conflicts
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+}
+
+/// Query parameters used when exporting manual album overrides
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct AlbumExportQuery {
-    /// Desired export format (`json` or `yaml`)
-    #[schema(example = "yaml")]
+    /// Desired export format (`json` or `yaml`, defaults to `json`)
+    #[param(value_type = Option<AlbumExportFormat>, example = "yaml")]
     pub format: Option<String>,
 }
 
 /// OpenAPI documentation structure
 #[derive(OpenApi)]
 #[openapi(
+    paths(
+        health_check,
+        doctor,
+        shutdown,
+        run_maintenance,
+        get_setup_status,
+        initialize_setup,
+        get_all_tracks,
+        get_track_sections,
+        scan_library,
+        cancel_library_scan,
+        search_tracks,
+        suggest_library,
+        verify_library,
+        cancel_library_verification,
+        get_corrupt_tracks,
+        delete_track,
+        bulk_track_action,
+        set_track_rating,
+        set_track_favorite,
+        restore_track,
+        get_track_chapters,
+        get_tracks_by_mbid,
+        get_album_by_mbid,
+        get_track_waveform,
+        precompute_waveforms,
+        create_share,
+        revoke_shares,
+        get_shared,
+        get_shared_artwork,
+        stream_shared_track,
+        stream_track,
+        update_track_sidecar,
+        get_scan_report,
+        get_scan_status,
+        list_jobs,
+        get_job,
+        cancel_job,
+        search_albums,
+        get_artists,
+        get_album_artwork,
+        upload_album_artwork,
+        get_artist_image,
+        get_works,
+        get_genres,
+        get_raw_genres,
+        retag_genres,
+        replace_metadata,
+        import_tag_stats,
+        get_incomplete_albums,
+        get_duplicate_groups,
+        get_duplicate_report,
+        resolve_duplicates,
+        get_album_manual_override,
+        set_album_manual_override,
+        edit_album,
+        export_album_overrides,
+        get_library_stats,
+        events_ws_handler,
+        get_event_log,
+        get_playlists,
+        get_playlist_tree,
+        rename_playlist_folder,
+        get_playlist_tracks,
+        get_playlist_stats,
+        update_playlist,
+        update_playlist_entry,
+        export_playlist_m3u,
+        play_playlist,
+        cleanup_playlist,
+        cleanup_all_playlists,
+        import_playlist_csv,
+        play_audio,
+        play_context,
+        pause_audio,
+        resume_audio,
+        stop_audio,
+        enqueue_gapless,
+        play_next,
+        get_audio_status,
+        get_audio_device,
+        get_audio_devices,
+        switch_audio_device,
+        set_audio_device,
+        get_precache_stats,
+        set_audio_volume,
+        set_audio_speed,
+        play_preview,
+        stop_preview,
+        set_preview_volume,
+        set_repeat_mode,
+        set_shuffle,
+        start_radio,
+        stop_radio,
+        seek_chapter,
+        set_audio_loop,
+        clear_audio_loop,
+        set_sleep_timer,
+        get_sleep_timer,
+        cancel_sleep_timer,
+        get_queue,
+        enqueue_track,
+        clear_queue,
+        get_queue_history,
+        get_webhooks
+    ),
     components(schemas(
         TrackResponse,
         AlbumResponse,
+        ArtistCredit,
+        ApiErrorResponse,
         ApiResponseString,
+        ApiResponseUsize,
+        ApiResponseCleanupReport,
+        ApiResponseDoctorReport,
+        DoctorReport,
+        ApiResponseMaintenanceReport,
+        ApiResponseSetupStatus,
+        MaintenanceRequest,
+        MaintenanceTask,
+        MaintenanceReport,
+        SetupDirectoryStatus,
+        SetupStatusResponse,
+        SetupRequest,
+        TaskReport,
+        CheckResult,
+        CheckStatus,
+        CleanupReport,
+        CleanupEntryResponse,
+        ApiResponseTrack,
         ApiResponseTracks,
+        ApiResponseAlbum,
+        MusicBrainzIds,
+        ApiResponseSuggestions,
+        ApiResponseScanReport,
+        ScanReportResponse,
+        ApiResponseScanJob,
+        ScanJobStatus,
+        ApiResponseJob,
+        ApiResponseJobs,
+        Job,
+        JobKind,
+        JobState,
+        JobProgress,
+        SidecarErrorResponse,
+        SidecarMetadata,
+        MetadataSource,
+        GuessedFields,
+        TechnicalInfo,
+        ReplayGain,
+        ApiResponseDeletedTrack,
+        ApiResponseBulkTracks,
+        BulkTrackAction,
+        BulkTrackRequest,
+        BulkTrackParams,
+        TrackRatingRequest,
+        TrackFavoriteRequest,
+        BulkTrackResult,
+        BulkTrackResponse,
+        ApiResponseCorruptTracks,
+        AlbumPageResponse,
+        AlbumSort,
+        ApiResponseAlbums,
+        ArtistListResponse,
+        ApiResponseArtists,
+        Section,
+        TrackSort,
+        ApiResponseSections,
+        ApiResponseAlbumOverride,
+        AlbumArtworkResponse,
+        ApiResponseAlbumArtwork,
+        ApiResponseAlbumEdit,
+        ApiResponseWorks,
+        ApiResponseGenres,
+        ApiResponseRawGenres,
+        ApiResponseGenreRetag,
+        ApiResponseMetadataReplace,
+        ApiResponseTagStatsImport,
+        TagStatsImport,
+        ApiResponseIncompleteAlbums,
+        IncompleteAlbumResponse,
+        ApiResponseDuplicateGroups,
+        ApiResponseDuplicateReport,
+        ApiResponseDuplicateResolution,
+        DuplicateGroup,
+        DuplicateCandidate,
+        DuplicateReportResponse,
+        ResolveDuplicatesRequest,
+        DuplicateResolutionResponse,
         ApiResponseStats,
+        ApiResponsePlaylist,
         ApiResponsePlaylists,
-        ApiResponseUsize,
+        ApiResponsePlaylistTree,
+        ApiResponsePlaylistTrack,
+        ApiResponsePlaylistTracks,
+        PlaylistTracksResponse,
+        PlaylistTrackResponse,
+        ApiResponsePlaylistStats,
+        PlaylistStatsResponse,
+        NameCount,
+        DecadeCount,
+        ApiResponseCsvImport,
+        ImportConflictPolicy,
+        ImportAction,
+        ApiResponseAudioStatus,
+        ApiResponseAudioDevice,
+        PrecacheStats,
+        ApiResponsePrecacheStats,
+        ApiResponsePreview,
+        ApiResponseWebhooks,
+        EventLogResponse,
+        ApiResponseEventLog,
         ScanRequest,
-        SearchQuery,
-        AlbumSearchQuery,
+        WorkResponse,
+        GenreSummary,
+        RawGenre,
+        GenreRetagFile,
+        MetadataReplaceRequest,
+        MetadataReplaceFile,
+        ReplaceField,
+        ReplaceMatch,
+        WorkMovementResponse,
         ManualAlbumUpdateRequest,
         AlbumOverrideResponse,
-        AlbumExportQuery,
+        AlbumOverrideRecord,
+        AlbumDisambiguation,
+        SuggestionType,
+        SuggestionGroup,
+        AlbumExportFormat,
+        AlbumEditRequest,
+        AlbumEditFileResponse,
+        AlbumEditResponse,
         AlbumMetadata,
         LibraryStats,
+        IntegrityStatus,
+        CorruptTrackResponse,
+        DeletedTrackResponse,
+        WebhookStatusResponse,
         PlaylistResponse,
+        PlaylistFolderResponse,
+        RenamePlaylistFolderRequest,
+        PlayOrder,
+        UpdatePlaylistRequest,
+        UpdatePlaylistEntryRequest,
+        CsvImportRowResponse,
+        CsvImportResponse,
+        PlayBehavior,
         PlayRequest,
+        AudioState,
         AudioStatusResponse,
-        VolumeRequest
+        SourceFormat,
+        AudioDeviceInfo,
+        ApiResponseAudioDevices,
+        AudioDeviceList,
+        SwitchDeviceRequest,
+        VolumeRequest,
+        SpeedRequest,
+        PreviewStatus,
+        PreviewRequest,
+        GaplessRequest,
+        RepeatMode,
+        RepeatModeRequest,
+        ShuffleRequest,
+        RadioRequest,
+        SeekChapterRequest,
+        AudioLoop,
+        ApiResponseAudioLoop,
+        SleepTimerRequest,
+        SleepTimerStatus,
+        ApiResponseSleepTimer,
+        Chapter,
+        ApiResponseChapters,
+        WaveformFormat,
+        WaveformResponse,
+        ApiResponseWaveform,
+        WaveformPrecompute,
+        ApiResponseWaveformPrecompute,
+        ShareScope,
+        CreateShareRequest,
+        ShareLink,
+        ApiResponseShareLink,
+        SharedTrack,
+        SharedView,
+        ApiResponseSharedView,
+        QueueHistoryItem,
+        QueueResponse,
+        ApiResponseQueue,
+        EnqueueRequest,
+        PlayContextType,
+        PlayContext,
+        PlayContextRequest,
+        QueueWindow,
+        ApiResponseQueueWindow,
+        ApiResponseQueueHistory,
+        EventMessage,
+        EventPayload,
+        UpNextTrack
     )),
     tags(
         (name = "Health", description = "Health check endpoints"),
         (name = "Library", description = "Music library management endpoints"),
-        (name = "Playlists", description = "Playlist management endpoints")
+        (name = "Playlists", description = "Playlist management endpoints"),
+        (name = "Audio", description = "Playback control endpoints"),
+        (name = "Queue", description = "Playback queue and play history"),
+        (name = "Maintenance", description = "Library and cache housekeeping"),
+        (name = "Jobs", description = "Long-running operations and their history"),
+        (name = "Setup", description = "First-run configuration"),
+        (name = "Events", description = "Backend event stream"),
+        (name = "Webhooks", description = "Webhook delivery status"),
+        (name = "Sharing", description = "Links showing a track, album or what is playing to anyone")
     ),
     info(
         title = "Hexendrum API",
@@ -282,25 +1455,137 @@ pub struct AlbumExportQuery {
 
 ### Health
 - `GET /api/health` - Health check
+- `GET /api/health/doctor` - Environment checks with remediation hints
+- `POST /api/system/shutdown` - Shut the backend down
 
 ### Library
-- `GET /api/library/tracks` - Get all tracks from library
-- `POST /api/library/scan` - Scan directories for music files
-- `GET /api/library/search?q={query}` - Search tracks
+- `GET /api/library/tracks?sort={title|artist|album}` - Get all tracks from library
+- `GET /api/library/tracks/sections?sort={title|artist|album}` - Letter sections of the sorted tracks
+- `POST /api/library/scan` - Start scanning directories for music files in the background
+- `POST /api/library/scan/cancel` - Cancel the running scan, keeping the library as it was
+- `GET /api/library/scan/status` - Whether the latest scan is running and how it ended
+- `GET /api/library/scan/report` - Sidecar files skipped by the last scan
+- `GET /api/library/search?q={query}` - Search tracks, with `format:` and `samplerate:` filters
+- `GET /api/library/suggest?q={query}&types=artist,album,title&limit=8` - Search-as-you-type suggestions
 - `GET /api/library/stats` - Get library statistics
+- `POST /api/library/verify` - Start verifying file integrity in the background
+- `POST /api/library/verify/cancel` - Cancel a running verification
+- `GET /api/library/tracks/corrupt` - List files that failed verification
+- `DELETE /api/library/tracks/{id}` - Delete a track (trash or unlink, per `delete_mode`)
+- `POST /api/library/tracks/{id}/restore` - Restore a track from the trash
+- `POST /api/library/tracks/bulk` - Add tracks to a playlist, queue them, set their genre, rating or favorite mark, or delete them in one call
+- `PUT /api/library/tracks/{id}/rating` - Rate a track
+- `PUT /api/library/tracks/{id}/favorite` - Mark or unmark a track as a favorite
+- `GET /api/library/tracks/{id}/chapters` - Get the chapter markers of a track
+- `GET /api/library/tracks/by-mbid/{id}` - Get the tracks of a MusicBrainz recording
+- `GET /api/library/albums/by-mbid/{id}` - Get the album of a MusicBrainz release
+- `GET /api/library/tracks/{id}/waveform?points=400` - Get the waveform of a track for seek-bar previews
+- `POST /api/library/waveforms` - Compute the missing waveforms of the whole library
+- `GET /api/library/tracks/{id}/stream?transcode=opus&bitrate=128&start={seconds}` - Stream a track, optionally transcoded
+- `PUT /api/library/tracks/{id}/sidecar` - Write metadata overriding the file's tags
+- `POST /api/library/albums/{id}/edit` - Bulk edit tags of every track in an album
+- `GET /api/library/albums/incomplete` - List albums with missing track numbers
+- `GET /api/library/duplicates` - List groups of tracks that look like copies of one recording
+- `GET /api/library/duplicates/{group_id}/report` - Compare the copies of a duplicate group and recommend one to keep
+- `POST /api/library/duplicates/{group_id}/resolve` - Delete all copies but the keeper, moving playlist entries to it
+- `PUT /api/library/albums/{id}/artwork` - Upload artwork for an album
+- `GET /api/library/artists` - List artists in collation order, with letter sections
+- `GET /api/library/artists/{name}/image` - Get an image of an artist
+- `GET /api/library/works?composer={name}` - Browse classical works grouped by composer
+- `GET /api/library/genres` - List genres, with spellings such as hip hop and Hip-Hop/Rap merged
+- `GET /api/library/genres/raw` - List genre tag values as found in the files, with the genre each is listed under
+- `POST /api/library/import/tag-stats` - Import ratings and play counts from POPM, FMPS and RATING tags
+- `POST /api/library/genres/retag?dry_run={bool}` - Rewrite genre tags to their canonical names
+- `POST /api/library/metadata/replace` - Find and replace a tag value across the library
+
+Until the library cache has loaded after startup, health, track, search, suggestion and stats responses carry `\"loading\": true` and may be incomplete. Clients sending `Prefer: handling=strict` get 503 from the library endpoints instead.
+
+Request bodies are limited to 16 KiB for playback, queue and other control endpoints, 256 KiB for metadata, playlist and setup edits, and `api.max_import_mb` (default 8 MiB) for imports; larger ones get 413 with the usual JSON error.
+
+Requests deleting, restoring or editing tracks, and non-dry-run cleanups and maintenance, wait for a running library scan to finish. With `library.scan_conflict = reject` they get 409 with a `Retry-After` header instead. Changes made while a scan runs are kept when it completes.
 
 ### Playlists
 - `GET /api/playlists` - Get all playlists
-- `POST /api/playlists/{id}/cleanup` - Cleanup specific playlist
-- `POST /api/playlists/cleanup` - Cleanup all playlists
+- `GET /api/playlists/tree` - Get the playlists nested under their virtual folders
+- `POST /api/playlists/folders/rename` - Rename a folder, moving every playlist in or below it
+- `GET /api/playlists/{id}/tracks?offset={n}&limit={n}` - Get a page of a playlist's entries
+- `GET /api/playlists/{id}/stats` - Get a playlist's duration and its breakdown by artist, genre and decade
+- `PATCH /api/playlists/{id}` - Rename a playlist, move it to a folder or change its play order and default repeat mode
+- `PATCH /api/playlists/{id}/tracks/{track_id}` - Set the note of an entry or pin it to the top
+- `GET /api/playlists/{id}/m3u` - Export a playlist as extended M3U, with entry notes as comments
+- `POST /api/playlists/{id}/play` - Replace the queue with a playlist in its play order and play it
+- `POST /api/playlists/{id}/cleanup` - Cleanup specific playlist (`?dry_run=true` only lists the entries)
+- `POST /api/playlists/cleanup` - Cleanup all playlists (`?dry_run=true` only lists the entries)
+- `POST /api/playlists/import/csv?name={name}&dry_run={bool}&on_conflict={policy}` - Import a playlist from an exported CSV; a playlist of the same name is renamed around, merged into, replaced or refused
 
 ### Audio Playback
-- `POST /api/audio/play` - Play audio file
+- `POST /api/audio/play` - Play audio file (or queue it, see `behavior`)
+- `POST /api/audio/play-context` - Play a track of an album, playlist or artist, queueing the tracks around it
 - `POST /api/audio/pause` - Pause playback
 - `POST /api/audio/resume` - Resume playback
 - `POST /api/audio/stop` - Stop playback
+- `POST /api/audio/enqueue` - Queue a file to follow the current track without a gap
+- `POST /api/audio/next` - Skip to the next track of the queue
 - `GET /api/audio/status` - Get playback status
+- `GET /api/audio/device` - Get the parameters the output device was opened with
+- `GET /api/audio/devices` - List the output devices
+- `POST /api/audio/devices/switch` - Move playback to another output device
+- `POST /api/audio/device` - Move playback to another output device, like `/api/audio/devices/switch`
+- `GET /api/audio/precache` - Count tracks opened from their precached copy
 - `POST /api/audio/volume` - Set volume
+- `POST /api/audio/speed` - Set the playback speed
+- `POST /api/audio/preview/play` - Preview a file quietly, mixed over main playback
+- `POST /api/audio/preview/stop` - Stop the preview
+- `POST /api/audio/preview/volume` - Set the preview volume
+- `POST /api/audio/repeat` - Set queue repeat mode
+- `POST /api/audio/shuffle` - Enable or disable shuffle
+- `POST /api/audio/radio` - Keep the queue topped up with tracks similar to a seed track
+- `DELETE /api/audio/radio` - Stop radio mode
+- `POST /api/audio/seek-chapter` - Seek to a chapter of the current track
+- `POST /api/audio/loop` - Play a region of the current track over and over
+- `DELETE /api/audio/loop` - Stop looping
+- `POST /api/audio/sleep-timer` - Stop playback after a number of minutes, optionally fading out
+- `GET /api/audio/sleep-timer` - Time left on the sleep timer
+- `DELETE /api/audio/sleep-timer` - Cancel the sleep timer
+
+### Queue
+- `GET /api/queue` - Get the playback queue and recently played tracks
+- `POST /api/queue` - Queue a track at the end, or next
+- `DELETE /api/queue?history={bool}` - Clear the queue, optionally with its history
+- `GET /api/queue/history?limit={n}` - Recently played tracks, newest first
+
+### Maintenance
+- `POST /api/maintenance` - Run housekeeping tasks (rescan, cache save, playlist cleanup, artwork and sidecar cleanup, stats compaction)
+
+### Jobs
+- `GET /api/jobs?kind={kind}&state={state}` - List running and recently finished jobs (scans, verifications, waveform precomputation, maintenance), newest first
+- `GET /api/jobs/{id}` - Get the state, progress and result of a job
+- `POST /api/jobs/{id}/cancel` - Cancel a running verification or waveform precomputation
+
+Only one job of a kind runs at a time; starting another answers 409. Jobs report progress through `job_progress` events and stay listed for `api.job_history_minutes` after finishing, across restarts with `api.persist_jobs`.
+
+### Setup
+- `GET /api/setup/status` - What a fresh install still needs (config, music directories, first scan, audio device)
+- `POST /api/setup/initialize` - Write a starter config and run the first scan
+
+### Events
+- `GET /api/events/ws?types={list}` - WebSocket event stream, optionally limited to comma separated event types
+- `GET /api/events/log?since={time}&limit={n}` - Recent entries of the event log file
+
+`library_updated` events carry the library change `sequence` and, when known, the number of tracks `added`, `removed` and `updated` since the previous one, with their `track_ids` for up to 100 tracks.
+
+### Webhooks
+- `GET /api/webhooks` - List configured webhooks and their delivery counters
+
+### Guests
+With `api.guest_token` set, requests sending it as `Authorization: Bearer {token}` may read the library, queue, playlists and playback status, queue tracks (`api.guest_enqueues_per_minute`, 429 beyond), skip to the next track and set the volume up to `api.guest_max_volume`. Other requests are refused with 403, and the events guests cause carry `\"source\": \"guest\"`. The owner's requests then send `api.auth_token` instead, and requests carrying neither token are refused with 401, except those opening share links.
+
+### Sharing
+- `POST /api/share` - Create an expiring link sharing a track, an album or what is playing
+- `DELETE /api/share` - Revoke all share links
+- `GET /api/share/{token}` - Open a share link; needs no credentials, like the two below
+- `GET /api/share/{token}/artwork` - Artwork of a share link
+- `GET /api/share/{token}/tracks/{track_id}/stream` - Stream a shared track, when `api.share_allow_stream` is set
 
 See Swagger UI at `/swagger-ui` for interactive API documentation.",
         version = "1.0.0",
@@ -319,33 +1604,163 @@ struct ApiDoc;
 pub fn create_router(state: AppState) -> Router {
     let openapi = ApiDoc::openapi();
 
+    // Small JSON bodies at most
+    let control = Router::new()
+        .route("/api/system/shutdown", post(shutdown))
+        .route("/api/maintenance", post(run_maintenance))
+        .route("/api/library/genres/retag", post(retag_genres))
+        .route("/api/library/import/tag-stats", post(import_tag_stats))
+        .route("/api/library/waveforms", post(precompute_waveforms))
+        .route("/api/share", post(create_share).delete(revoke_shares))
+        .route("/api/library/scan", post(scan_library))
+        .route("/api/library/verify", post(verify_library))
+        .route("/api/audio/devices/switch", post(switch_audio_device))
+        .route("/api/audio/device", post(set_audio_device))
+        .route("/api/jobs/:id/cancel", post(cancel_job))
+        .route(
+            "/api/library/verify/cancel",
+            post(cancel_library_verification),
+        )
+        .route("/api/library/scan/cancel", post(cancel_library_scan))
+        .route("/api/library/tracks/:id", delete(delete_track))
+        .route("/api/library/tracks/:id/restore", post(restore_track))
+        .route("/api/library/tracks/:id/rating", put(set_track_rating))
+        .route("/api/library/tracks/:id/favorite", put(set_track_favorite))
+        .route("/api/playlists/:id/play", post(play_playlist))
+        .route("/api/playlists/:id/cleanup", post(cleanup_playlist))
+        .route("/api/playlists/cleanup", post(cleanup_all_playlists))
+        .route("/api/audio/play", post(play_audio))
+        .route("/api/audio/play-context", post(play_context))
+        .route("/api/audio/pause", post(pause_audio))
+        .route("/api/audio/resume", post(resume_audio))
+        .route("/api/audio/stop", post(stop_audio))
+        .route("/api/audio/enqueue", post(enqueue_gapless))
+        .route("/api/audio/next", post(play_next))
+        .route("/api/audio/volume", post(set_audio_volume))
+        .route("/api/audio/speed", post(set_audio_speed))
+        .route("/api/audio/preview/play", post(play_preview))
+        .route("/api/audio/preview/stop", post(stop_preview))
+        .route("/api/audio/preview/volume", post(set_preview_volume))
+        .route("/api/audio/repeat", post(set_repeat_mode))
+        .route("/api/audio/shuffle", post(set_shuffle))
+        .route("/api/audio/radio", post(start_radio).delete(stop_radio))
+        .route("/api/audio/seek-chapter", post(seek_chapter))
+        .route(
+            "/api/audio/loop",
+            post(set_audio_loop).delete(clear_audio_loop),
+        )
+        .route(
+            "/api/audio/sleep-timer",
+            post(set_sleep_timer)
+                .get(get_sleep_timer)
+                .delete(cancel_sleep_timer),
+        )
+        .route(
+            "/api/queue",
+            get(get_queue).post(enqueue_track).delete(clear_queue),
+        )
+        .layer(DefaultBodyLimit::max(CONTROL_BODY_LIMIT));
+
+    let edits = Router::new()
+        .route("/api/setup/initialize", post(initialize_setup))
+        .route("/api/library/tracks/bulk", post(bulk_track_action))
+        .route("/api/library/metadata/replace", post(replace_metadata))
+        .route("/api/library/tracks/:id/sidecar", put(update_track_sidecar))
+        .route(
+            "/api/library/duplicates/:group_id/resolve",
+            post(resolve_duplicates),
+        )
+        .route(
+            "/api/library/albums/:id/manual",
+            get(get_album_manual_override).put(set_album_manual_override),
+        )
+        .route("/api/library/albums/:id/edit", post(edit_album))
+        .route("/api/playlists/:id", patch(update_playlist))
+        .route(
+            "/api/playlists/folders/rename",
+            post(rename_playlist_folder),
+        )
+        .route(
+            "/api/playlists/:id/tracks/:track_id",
+            patch(update_playlist_entry),
+        )
+        .layer(DefaultBodyLimit::max(EDIT_BODY_LIMIT));
+
+    let imports = Router::new()
+        .route("/api/playlists/import/csv", post(import_playlist_csv))
+        .route(
+            "/api/library/albums/:id/artwork",
+            get(get_album_artwork).put(upload_album_artwork),
+        )
+        .layer(DefaultBodyLimit::max(state.max_import_bytes));
+
     Router::new()
         .merge(SwaggerUi::new("/swagger-ui").url("/api-doc/openapi.json", openapi.clone()))
         .route("/api/health", get(health_check))
+        .route("/api/health/doctor", get(doctor))
+        .route("/api/setup/status", get(get_setup_status))
         .route("/api/library/tracks", get(get_all_tracks))
-        .route("/api/library/scan", post(scan_library))
+        .route("/api/library/tracks/sections", get(get_track_sections))
+        .route("/api/library/scan/report", get(get_scan_report))
+        .route("/api/library/scan/status", get(get_scan_status))
+        .route("/api/jobs", get(list_jobs))
+        .route("/api/jobs/:id", get(get_job))
         .route("/api/library/search", get(search_tracks))
+        .route("/api/library/suggest", get(suggest_library))
+        .route("/api/library/tracks/corrupt", get(get_corrupt_tracks))
+        .route("/api/library/tracks/:id/chapters", get(get_track_chapters))
+        .route("/api/library/tracks/by-mbid/:id", get(get_tracks_by_mbid))
+        .route("/api/library/albums/by-mbid/:id", get(get_album_by_mbid))
+        .route("/api/library/tracks/:id/waveform", get(get_track_waveform))
+        .route("/api/library/tracks/:id/stream", get(stream_track))
         .route("/api/library/albums/search", get(search_albums))
-        .route("/api/library/albums/:id/artwork", get(get_album_artwork))
+        .route("/api/library/artists", get(get_artists))
+        .route("/api/library/artists/:name/image", get(get_artist_image))
+        .route("/api/library/works", get(get_works))
+        .route("/api/library/genres", get(get_genres))
+        .route("/api/library/genres/raw", get(get_raw_genres))
+        .route("/api/library/albums/incomplete", get(get_incomplete_albums))
+        .route("/api/library/duplicates", get(get_duplicate_groups))
         .route(
-            "/api/library/albums/:id/manual",
-            get(get_album_manual_override).put(set_album_manual_override),
+            "/api/library/duplicates/:group_id/report",
+            get(get_duplicate_report),
         )
+        .route("/api/webhooks", get(get_webhooks))
         .route(
             "/api/library/albums/manual/export",
             get(export_album_overrides),
         )
         .route("/api/events/ws", get(events_ws_handler))
+        .route("/api/events/log", get(get_event_log))
         .route("/api/library/stats", get(get_library_stats))
         .route("/api/playlists", get(get_playlists))
-        .route("/api/playlists/:id/cleanup", post(cleanup_playlist))
-        .route("/api/playlists/cleanup", post(cleanup_all_playlists))
-        .route("/api/audio/play", post(play_audio))
-        .route("/api/audio/pause", post(pause_audio))
-        .route("/api/audio/resume", post(resume_audio))
-        .route("/api/audio/stop", post(stop_audio))
+        .route("/api/playlists/tree", get(get_playlist_tree))
+        .route("/api/playlists/:id/tracks", get(get_playlist_tracks))
+        .route("/api/playlists/:id/stats", get(get_playlist_stats))
+        .route("/api/playlists/:id/m3u", get(export_playlist_m3u))
         .route("/api/audio/status", get(get_audio_status))
-        .route("/api/audio/volume", post(set_audio_volume))
+        .route("/api/audio/device", get(get_audio_device))
+        .route("/api/audio/devices", get(get_audio_devices))
+        .route("/api/audio/precache", get(get_precache_stats))
+        .route("/api/queue/history", get(get_queue_history))
+        .route("/api/share/:token", get(get_shared))
+        .route("/api/share/:token/artwork", get(get_shared_artwork))
+        .route(
+            "/api/share/:token/tracks/:track_id/stream",
+            get(stream_shared_track),
+        )
+        .merge(control)
+        .merge(edits)
+        .merge(imports)
+        .layer(middleware::from_fn_with_state(
+            state.route_budgets,
+            enforce_route_budget,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.guest_policy.clone(),
+            enforce_guest_policy,
+        ))
+        .layer(middleware::map_response(json_payload_too_large))
         .layer(
             CorsLayer::new()
                 .allow_origin(Any)
@@ -358,666 +1773,6221 @@ pub fn create_router(state: AppState) -> Router {
 /// Health check endpoint
 ///
 /// Returns the health status of the API server
-async fn health_check() -> Json<ApiResponse<&'static str>> {
-    Json(ApiResponse::success("OK"))
+#[utoipa::path(
+    get,
+    path = "/api/health",
+    tag = "Health",
+    responses(
+        (status = 200, description = "Server is healthy", body = ApiResponseString),
+    )
+)]
+async fn health_check(State(state): State<AppState>) -> Json<ApiResponse<&'static str>> {
+    Json(ApiResponse::success("OK").with_loading(!state.library.is_ready()))
 }
 
-/// Get all tracks from library
-///
-/// Returns a list of all tracks currently in the music library.
-/// Tracks are loaded from cache if available, otherwise the library may be empty.
-async fn get_all_tracks(
-    State(state): State<AppState>,
-) -> Result<Json<ApiResponse<Vec<TrackResponse>>>, StatusCode> {
-    let tracks = state.library.get_tracks();
-    let track_responses: Vec<TrackResponse> = tracks.iter().map(TrackResponse::from).collect();
-    Ok(Json(ApiResponse::success(track_responses)))
+/// Whether library data is incomplete because the cache is still loading. Clients
+/// sending `Prefer: handling=strict` get 503 instead of partial data.
+fn library_loading(state: &AppState, headers: &HeaderMap) -> Result<bool, ApiError> {
+    if state.library.is_ready() {
+        return Ok(false);
+    }
+
+    let strict = headers
+        .get_all("prefer")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|preference| preference.trim().eq_ignore_ascii_case("handling=strict"));
+    if strict {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The library is still loading",
+        ));
+    }
+    Ok(true)
 }
 
-/// Scan library directories
+/// Shut the backend down
 ///
-/// Scans the specified directories for music files and adds them to the library.
-/// Supported formats: MP3, FLAC, OGG, WAV, M4A, AAC
+/// Used by a new instance started with `--takeover` to replace this one. The
+/// response is sent before the backend exits.
+#[utoipa::path(
+    post,
+    path = "/api/system/shutdown",
+    tag = "Health",
+    responses(
+        (status = 200, description = "Shutdown requested", body = ApiResponseString),
+    )
+)]
+async fn shutdown(State(state): State<AppState>) -> Json<ApiResponse<String>> {
+    info!("Shutdown requested through the API");
+    state.shutdown.notify_one();
+    Json(ApiResponse::success("Shutting down".to_string()))
+}
+
+/// Run the environment checks
 ///
-/// After scanning, the library is automatically cached for faster loading on next startup.
-async fn scan_library(
+/// Runs the `hexendrum doctor` checks from within the backend: configuration, music
+/// directories, cache and playlist directories, curl and Last.fm. The audio device
+/// and API port checks are left out, as the running backend holds both.
+#[utoipa::path(
+    get,
+    path = "/api/health/doctor",
+    tag = "Health",
+    responses(
+        (status = 200, description = "Results of the environment checks", body = ApiResponseDoctorReport),
+    )
+)]
+async fn doctor(
     State(state): State<AppState>,
-    Json(request): Json<ScanRequest>,
-) -> Result<Json<ApiResponse<usize>>, StatusCode> {
-    let directories: Vec<PathBuf> = request
-        .directories
-        .iter()
-        .map(|s| PathBuf::from(s))
-        .collect();
+) -> Result<Json<ApiResponse<DoctorReport>>, ApiError> {
+    let paths = state.paths.clone();
+    let report = tokio::task::spawn_blocking(move || diagnostics::run_all(&paths, true))
+        .await
+        .map_err(|e| {
+            error!("Doctor checks failed to run: {}", e);
+            ApiError::from(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+
+    Ok(Json(ApiResponse::success(report)))
+}
 
+/// Run maintenance tasks
+///
+/// Runs the selected housekeeping tasks in sequence: an incremental rescan, saving the
+/// library cache, playlist cleanup, eviction of unused cached artwork, removal of
+/// orphaned sidecars and compaction of the statistics store. Every task runs when
+/// `tasks` is empty; with `dry_run` the cleanup tasks only list what they would remove.
+/// Progress is reported through `maintenance` events and the run is listed as a
+/// `maintenance` job. Playback is not interrupted.
+#[utoipa::path(
+    post,
+    path = "/api/maintenance",
+    tag = "Maintenance",
+    request_body = MaintenanceRequest,
+    responses(
+        (status = 200, description = "Outcome of each task", body = ApiResponseMaintenanceReport),
+        (status = 409, description = "A maintenance run is already in progress, or a library scan is and `library.scan_conflict` is `reject`", body = ApiErrorResponse),
+        (status = 500, description = "The tasks could not be run", body = ApiErrorResponse),
+    )
+)]
+async fn run_maintenance(
+    State(state): State<AppState>,
+    Json(request): Json<MaintenanceRequest>,
+) -> Result<Json<ApiResponse<MaintenanceReport>>, ApiError> {
+    if !request.dry_run {
+        state.library.guard_mutation().await?;
+    }
+    if state.jobs.active(JobKind::Maintenance).is_some() {
+        return Err(JobError::Busy(JobKind::Maintenance).into());
+    }
+    let since = state.library.change_sequence();
+    let total = request.selected_tasks().len();
+    let params = serde_json::to_value(&request).unwrap_or_default();
     state
         .event_bus
-        .emit(EventPayload::library_scan("started", None, None));
+        .emit(EventPayload::maintenance("started", None, 0, total));
 
-    match state.library.scan_directories(&directories) {
-        Ok(_) => {
-            let count = state.library.track_count();
-            info!("Library scan completed: {} tracks", count);
-            state
-                .event_bus
-                .emit(EventPayload::library_scan("completed", None, None));
-            state.event_bus.emit(EventPayload::library_updated(count));
-            Ok(Json(ApiResponse::success(count)))
-        }
-        Err(e) => {
-            error!("Failed to scan library: {}", e);
+    let task_state = state.clone();
+    let report = state
+        .jobs
+        .run(JobKind::Maintenance, params, move |handle| async move {
+            let report = tokio::task::spawn_blocking(move || {
+                let state = task_state;
+                let music_directories = match &request.directories {
+                    Some(directories) => directories.iter().map(PathBuf::from).collect(),
+                    None => Config::load(&state.paths)
+                        .unwrap_or_default()
+                        .library
+                        .music_directory_paths(),
+                };
+                let maintenance = Maintenance {
+                    library: &state.library,
+                    playlist_manager: &state.playlist_manager,
+                    album_service: &state.album_service,
+                    stats_store: &state.stats_store,
+                    trash: &state.trash,
+                    music_directories,
+                };
+                maintenance.run(&request, |task, completed| {
+                    state.event_bus.emit(EventPayload::maintenance(
+                        "running",
+                        Some(task.as_str().to_string()),
+                        completed,
+                        total,
+                    ));
+                    handle.progress(completed, Some(total), Some(task.as_str().to_string()));
+                })
+            })
+            .await?;
+            Ok(report)
+        })
+        .await?
+        .map_err(|e| {
+            error!("Maintenance failed to run: {}", e);
             state
                 .event_bus
-                .emit(EventPayload::library_scan("failed", None, None));
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+                .emit(EventPayload::maintenance("failed", None, 0, total));
+            ApiError::from(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+
+    info!(
+        "Maintenance completed: {} task(s) in {} ms",
+        report.tasks.len(),
+        report.duration_ms
+    );
+    state
+        .event_bus
+        .emit(EventPayload::maintenance("completed", None, total, total));
+    let rescanned = report
+        .tasks
+        .iter()
+        .any(|task| task.task == MaintenanceTask::Rescan && task.affected > 0);
+    if rescanned {
+        emit_library_updated(&state, since);
     }
+
+    Ok(Json(ApiResponse::success(report)))
 }
 
-/// Search tracks
+/// Report what a fresh install still needs
 ///
-/// Searches the library for tracks matching the query string.
-/// Searches in track title, artist, and album fields.
-async fn search_tracks(
+/// Tells "never configured" apart from "empty library": whether a config file exists,
+/// whether music directories are configured and readable, whether a library scan has
+/// completed and whether an audio output device is available.
+#[utoipa::path(
+    get,
+    path = "/api/setup/status",
+    tag = "Setup",
+    responses(
+        (status = 200, description = "Setup progress", body = ApiResponseSetupStatus),
+    )
+)]
+async fn get_setup_status(
     State(state): State<AppState>,
-    Query(query): Query<SearchQuery>,
-) -> Result<Json<ApiResponse<Vec<TrackResponse>>>, StatusCode> {
-    let tracks = state.library.search_tracks(&query.q);
-    let track_responses: Vec<TrackResponse> = tracks.iter().map(TrackResponse::from).collect();
-    Ok(Json(ApiResponse::success(track_responses)))
+) -> Result<Json<ApiResponse<SetupStatusResponse>>, ApiError> {
+    Ok(Json(ApiResponse::success(setup_status(&state))))
 }
 
-/// Search albums
+/// Write a starter configuration and run the first scan
 ///
-/// Aggregates albums from the library and returns matching entries with cached artwork information.
-async fn search_albums(
+/// Saves a config file with the given music directories and options, then scans the
+/// directories in the background as a `scan` job, reporting progress through
+/// `library_scan` events with `processed` and `total` counting directories. Refuses to
+/// replace an existing config file unless `overwrite` is set.
+#[utoipa::path(
+    post,
+    path = "/api/setup/initialize",
+    tag = "Setup",
+    request_body = SetupRequest,
+    responses(
+        (status = 200, description = "Config written and first scan started", body = ApiResponseSetupStatus),
+        (status = 400, description = "Invalid or too many directories, or invalid options", body = ApiErrorResponse),
+        (status = 409, description = "A config file already exists, or a scan is running", body = ApiErrorResponse),
+        (status = 413, description = "Request body too large", body = ApiErrorResponse),
+        (status = 500, description = "The config file could not be written", body = ApiErrorResponse),
+    )
+)]
+async fn initialize_setup(
     State(state): State<AppState>,
-    Query(query): Query<AlbumSearchQuery>,
-) -> Result<Json<ApiResponse<Vec<AlbumResponse>>>, StatusCode> {
-    let albums = state
-        .album_service
-        .search_albums(state.library.as_ref(), query.q.as_deref())
-        .await;
-
-    let album_responses: Vec<AlbumResponse> = albums
-        .into_iter()
-        .map(|album: AlbumSummary| {
-            let AlbumSummary {
-                id,
-                title,
-                primary_artist,
-                artists,
-                track_count,
-                artwork_path,
-                metadata,
-                is_manual,
-            } = album;
-
-            let artwork_url = artwork_path.map(|_| format!("/api/library/albums/{}/artwork", id));
-
-            AlbumResponse {
-                id,
-                title,
-                primary_artist,
-                artists,
-                track_count,
-                artwork_url,
-                metadata,
-                is_manual,
-            }
-        })
+    Json(request): Json<SetupRequest>,
+) -> Result<Json<ApiResponse<SetupStatusResponse>>, ApiError> {
+    let since = state.library.change_sequence();
+    if state.paths.config_file().exists() && !request.overwrite {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "A config file already exists; set overwrite to replace it",
+        ));
+    }
+    if request.music_directories.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "At least one music directory is required",
+        ));
+    }
+    check_directory_count(request.music_directories.len())?;
+    let directories: Vec<PathBuf> = request
+        .music_directories
+        .iter()
+        .map(PathBuf::from)
         .collect();
-
-    Ok(Json(ApiResponse::success(album_responses)))
-}
-
-/// Subscribe to backend events (playback, library updates) using WebSocket.
-async fn events_ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
-    ws.on_upgrade(move |socket| handle_events_socket(socket, state))
-}
-
-async fn handle_events_socket(mut socket: WebSocket, state: AppState) {
-    if let Err(err) = send_initial_events(&mut socket, &state).await {
-        tracing::warn!("Failed to send initial event snapshot: {}", err);
+    for directory in &directories {
+        if let Err(e) = std::fs::read_dir(directory) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("{} cannot be read: {}", directory.display(), e),
+            ));
+        }
     }
 
-    let mut receiver = state.event_bus.subscribe();
-
-    loop {
-        tokio::select! {
-            event = receiver.recv() => {
-                match event {
-                    Ok(message) => {
-                        match serde_json::to_string(&message) {
-                            Ok(payload) => {
-                                if socket.send(Message::Text(payload)).await.is_err() {
-                                    break;
-                                }
-                            }
-                            Err(err) => tracing::warn!("Failed to serialise event message: {}", err),
-                        }
-                    }
-                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
-                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
-                        tracing::warn!("Event stream lagged, skipped {} events", skipped);
-                    }
-                }
-            }
-            socket_msg = socket.recv() => {
-                match socket_msg {
-                    Some(Ok(Message::Ping(payload))) => {
-                        if socket.send(Message::Pong(payload)).await.is_err() {
-                            break;
-                        }
-                    }
-                    Some(Ok(Message::Close(_))) | None => break,
-                    Some(Ok(_)) => {
-                        // Ignore other incoming messages from the client
-                    }
-                    Some(Err(err)) => {
-                        tracing::warn!("WebSocket error: {}", err);
-                        break;
-                    }
-                }
-            }
+    let mut config = Config::default();
+    config.library.music_directories = directories.iter().cloned().map(Into::into).collect();
+    if let Some(volume) = request.default_volume {
+        if !(0.0..=1.0).contains(&volume) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "default_volume must be between 0.0 and 1.0",
+            ));
         }
+        config.audio.default_volume = volume;
+    }
+    if let Some(auto_scan) = request.auto_scan {
+        config.library.auto_scan = auto_scan;
+    }
+    if let Some(read_only) = request.read_only {
+        config.library.read_only = read_only;
+    }
+    if state.jobs.active(JobKind::Scan).is_some() || state.library.is_scanning() {
+        return Err(ScanInProgressError {
+            retry_after: SCAN_RETRY_AFTER,
+        }
+        .into());
     }
-}
-
-async fn send_initial_events(socket: &mut WebSocket, state: &AppState) -> Result<(), String> {
-    let current_state = state.audio_player.get_state();
-    let track_path = state.audio_player.get_current_track();
-    let (track_id, track_duration) = track_path
-        .as_deref()
-        .map(|path| lookup_track_metadata(state.library.as_ref(), FsPath::new(path)))
-        .unwrap_or((None, None));
 
-    let playback_payload = EventPayload::playback_state(
-        format!("{:?}", current_state).to_lowercase(),
-        track_path.clone(),
-        track_id,
-        Some(state.audio_player.get_volume()),
-        track_duration,
+    config.save(&state.paths).map_err(|e| {
+        error!("Failed to write the starter config: {}", e);
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to write the config file: {}", e),
+        )
+    })?;
+    info!(
+        "Wrote starter config with {} music director(ies)",
+        directories.len()
     );
 
-    send_event(socket, playback_payload).await?;
-
-    let library_count = state.library.track_count();
-    send_event(socket, EventPayload::library_updated(library_count)).await?;
+    let params = serde_json::json!({ "directories": request.music_directories });
+    let scan_state = state.clone();
+    let started = state
+        .jobs
+        .start(JobKind::Scan, params, move |handle| async move {
+            tokio::task::spawn_blocking(move || {
+                first_scan(&scan_state, &handle, &directories, since)
+            })
+            .await?
+        });
+    if started.is_err() {
+        return Err(ScanInProgressError {
+            retry_after: SCAN_RETRY_AFTER,
+        }
+        .into());
+    }
 
-    Ok(())
+    Ok(Json(ApiResponse::success(setup_status(&state))))
 }
 
-async fn send_event(socket: &mut WebSocket, payload: EventPayload) -> Result<(), String> {
-    let message = EventMessage::new(payload);
-    let serialized = serde_json::to_string(&message).map_err(|err| err.to_string())?;
-    socket
-        .send(Message::Text(serialized))
-        .await
-        .map_err(|err| err.to_string())
+/// Scan the directories of a fresh install, directory by directory so progress can be
+/// reported in between
+fn first_scan(
+    state: &AppState,
+    handle: &JobHandle,
+    directories: &[PathBuf],
+    since: u64,
+) -> Result<serde_json::Value> {
+    let total = directories.len();
+    state
+        .event_bus
+        .emit(EventPayload::library_scan("started", Some(0), Some(total)));
+    for (index, directory) in directories.iter().enumerate() {
+        if handle.is_cancelled() {
+            info!(
+                "First scan cancelled after {} of {} directories",
+                index, total
+            );
+            state.event_bus.emit(EventPayload::library_scan(
+                "cancelled",
+                Some(index),
+                Some(total),
+            ));
+            emit_library_updated(state, since);
+            return Err(LibraryError::ScanCancelled.into());
+        }
+        if let Err(e) = state.library.refresh(std::slice::from_ref(directory)) {
+            error!("First scan of {:?} failed: {}", directory, e);
+            state.event_bus.emit(EventPayload::library_scan(
+                "failed",
+                Some(index),
+                Some(total),
+            ));
+            return Err(e.into());
+        }
+        state.event_bus.emit(EventPayload::library_scan(
+            "running",
+            Some(index + 1),
+            Some(total),
+        ));
+        handle.progress(
+            index + 1,
+            Some(total),
+            Some(directory.display().to_string()),
+        );
+    }
+
+    // Saved even when empty, marking the first scan as completed
+    if let Err(e) = state.library.save_to_cache() {
+        warn!(
+            "Failed to save library to cache after the first scan: {}",
+            e
+        );
+    }
+    let count = state.library.track_count();
+    info!("First scan completed: {} tracks", count);
+    state.event_bus.emit(EventPayload::library_scan(
+        "completed",
+        Some(total),
+        Some(total),
+    ));
+    emit_library_updated(state, since);
+    Ok(serde_json::json!({ "tracks": count }))
 }
 
-/// Retrieve cached artwork for a specific album
-async fn get_album_artwork(
-    State(state): State<AppState>,
-    Path(album_id): Path<String>,
-) -> Result<Response, StatusCode> {
-    if let Some(path) = state.album_service.cached_artwork_path(&album_id) {
-        match fs::read(&path).await {
-            Ok(bytes) => Response::builder()
-                .status(StatusCode::OK)
-                .header(header::CONTENT_TYPE, "image/jpeg")
-                .body(Body::from(bytes))
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR),
-            Err(error) => {
-                error!("Failed to read artwork for album {}: {}", album_id, error);
-                Err(StatusCode::INTERNAL_SERVER_ERROR)
-            }
-        }
-    } else {
-        Err(StatusCode::NOT_FOUND)
+fn setup_status(state: &AppState) -> SetupStatusResponse {
+    let config_exists = state.paths.config_file().exists();
+    let config = Config::load(&state.paths).unwrap_or_default();
+    let music_directories: Vec<SetupDirectoryStatus> = config
+        .library
+        .music_directory_paths()
+        .into_iter()
+        .map(|path| SetupDirectoryStatus {
+            readable: std::fs::read_dir(&path).is_ok(),
+            path: path.to_string_lossy().to_string(),
+        })
+        .collect();
+    let music_directories_ready = !music_directories.is_empty()
+        && music_directories.iter().all(|directory| directory.readable);
+    let audio_device_available = state.audio_player.get_state() != AudioState::DeviceLost;
+
+    SetupStatusResponse {
+        needs_setup: !config_exists || music_directories.is_empty(),
+        config_exists,
+        music_directories,
+        music_directories_ready,
+        first_scan_completed: state.library.last_scan_report().is_some()
+            || state.paths.library_cache_file().exists(),
+        scanning: state.library.is_scanning(),
+        audio_device_available,
+        audio_device: audio_device_available
+            .then(|| state.audio_player.device_info())
+            .flatten()
+            .and_then(|info| info.device_name),
     }
 }
 
-/// Retrieve the manual override (if any) for an album
-async fn get_album_manual_override(
+/// Get all tracks from library
+///
+/// Returns a list of all tracks currently in the music library, sorted by
+/// title, artist or album when asked to. Tracks are loaded from cache if available,
+/// otherwise the library may be empty.
+#[utoipa::path(
+    get,
+    path = "/api/library/tracks",
+    tag = "Library",
+    params(TrackListQuery),
+    responses(
+        (status = 200, description = "All library tracks", body = ApiResponseTracks),
+        (status = 503, description = "The library is still loading and the client asked for `Prefer: handling=strict`", body = ApiErrorResponse),
+    )
+)]
+async fn get_all_tracks(
     State(state): State<AppState>,
-    Path(album_id): Path<String>,
-) -> Result<Json<ApiResponse<AlbumOverrideResponse>>, StatusCode> {
-    match state.album_service.get_override(&album_id) {
-        Some(record) => Ok(Json(ApiResponse::success(record.into()))),
-        None => Err(StatusCode::NOT_FOUND),
+    Query(query): Query<TrackListQuery>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<TrackResponse>>>, ApiError> {
+    let loading = library_loading(&state, &headers)?;
+    let mut tracks = state.library.get_tracks();
+    if let Some(sort) = query.sort {
+        state.library.collator().sort_tracks(&mut tracks, sort);
     }
+    let track_responses: Vec<TrackResponse> = tracks
+        .iter()
+        .map(|track| TrackResponse::with_stats(track, &state.stats_store))
+        .collect();
+    Ok(Json(
+        ApiResponse::success(track_responses).with_loading(loading),
+    ))
 }
 
-/// Create or update the manual override for an album and refresh metadata/artwork
-async fn set_album_manual_override(
+/// Get the letter sections of the track listing
+///
+/// Sections of `GET /api/library/tracks` with the same sort, for A-Z jump bars.
+/// Tracks missing the sorted tag are listed under `#`.
+#[utoipa::path(
+    get,
+    path = "/api/library/tracks/sections",
+    tag = "Library",
+    params(TrackListQuery),
+    responses(
+        (status = 200, description = "Letter sections of the sorted tracks", body = ApiResponseSections),
+        (status = 503, description = "The library is still loading and the client asked for `Prefer: handling=strict`", body = ApiErrorResponse),
+    )
+)]
+async fn get_track_sections(
     State(state): State<AppState>,
-    Path(album_id): Path<String>,
-    Json(payload): Json<ManualAlbumUpdateRequest>,
-) -> Result<Json<ApiResponse<AlbumOverrideResponse>>, StatusCode> {
-    let update = ManualAlbumUpdate {
-        title: payload.title,
-        primary_artist: payload.primary_artist,
-        search_album: payload.search_album,
-        search_artist: payload.search_artist,
-        refresh_artwork: payload.refresh_artwork,
-    };
+    Query(query): Query<TrackListQuery>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<Section>>>, ApiError> {
+    let loading = library_loading(&state, &headers)?;
+    let sort = query.sort.unwrap_or_default();
+    let collator = state.library.collator();
+    let mut tracks = state.library.get_tracks();
+    collator.sort_tracks(&mut tracks, sort);
+    let sections = collator.track_sections(&tracks, sort);
+    Ok(Json(ApiResponse::success(sections).with_loading(loading)))
+}
 
-    match state
-        .album_service
-        .set_manual_override(&album_id, update)
-        .await
-    {
-        Ok(record) => Ok(Json(ApiResponse::success(record.into()))),
-        Err(error) => {
-            error!(
-                "Failed to set manual override for album {}: {}",
-                album_id, error
-            );
-            Err(StatusCode::BAD_REQUEST)
-        }
-    }
+/// Result of deleting a track
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DeletedTrackResponse {
+    /// Identifier of the deleted track
+    pub track_id: String,
+    /// Where the file was moved, when it was moved to the trash
+    #[schema(example = "/home/user/.local/share/Trash/files/song.flac")]
+    pub trash_path: Option<String>,
+    /// Whether the deletion can be undone with the restore endpoint
+    pub restorable: bool,
 }
 
-/// Export all manual album overrides in JSON or YAML formats
-async fn export_album_overrides(
+/// Delete a track and its file
+///
+/// Depending on `library.delete_mode` the file is moved to the trash, unlinked, or
+/// the request is refused with 403. Files in read-only library directories are never
+/// deleted.
+#[utoipa::path(
+    delete,
+    path = "/api/library/tracks/{id}",
+    tag = "Library",
+    params(("id" = String, Path, description = "Track identifier", example = "550e8400-e29b-41d4-a716-446655440000")),
+    responses(
+        (status = 200, description = "Track deleted", body = ApiResponseDeletedTrack),
+        (status = 403, description = "Deleting files is disabled or the file is read-only", body = ApiErrorResponse),
+        (status = 404, description = "Unknown track", body = ApiErrorResponse),
+        (status = 409, description = "A library scan is in progress and `library.scan_conflict` is `reject`", body = ApiErrorResponse),
+        (status = 500, description = "The file could not be deleted", body = ApiErrorResponse),
+    )
+)]
+async fn delete_track(
     State(state): State<AppState>,
-    Query(query): Query<AlbumExportQuery>,
-) -> Result<Response, StatusCode> {
-    let format_string = query
-        .format
-        .as_deref()
-        .unwrap_or("json")
-        .trim()
-        .to_lowercase();
+    Path(track_id): Path<String>,
+) -> Result<Json<ApiResponse<DeletedTrackResponse>>, ApiError> {
+    state.library.guard_mutation().await?;
+    let since = state.library.change_sequence();
+    let track = state
+        .library
+        .get_track(&track_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    state.library.read_only().check(&track.metadata.file_path)?;
 
-    let (format, content_type) = match format_string.as_str() {
-        "yaml" | "yml" => (AlbumExportFormat::Yaml, "application/x-yaml"),
-        "json" => (AlbumExportFormat::Json, "application/json"),
-        other => {
-            error!("Unsupported export format requested: {}", other);
-            return Err(StatusCode::BAD_REQUEST);
+    let deleted = discard_track(&state, &track).await?;
+    emit_library_updated(&state, since);
+
+    Ok(Json(ApiResponse::success(deleted)))
+}
+
+/// Move a track's file to the trash or unlink it, per `library.delete_mode`, and drop
+/// the track from the library
+async fn discard_track(state: &AppState, track: &Track) -> Result<DeletedTrackResponse, ApiError> {
+    let path = &track.metadata.file_path;
+    let trash_path = match state.delete_mode {
+        DeleteMode::Forbid => {
+            return Err(ApiError::new(
+                StatusCode::FORBIDDEN,
+                "Deleting files is disabled by library.delete_mode",
+            ))
+        }
+        DeleteMode::Trash => {
+            let entry = state
+                .trash
+                .move_to_trash(&track.id, path)
+                .map_err(|error| {
+                    error!("Failed to move {:?} to the trash: {}", path, error);
+                    StatusCode::INTERNAL_SERVER_ERROR
+                })?;
+            Some(entry.trash_path.to_string_lossy().to_string())
+        }
+        DeleteMode::Permanent => {
+            fs::remove_file(path).await.map_err(|error| {
+                error!("Failed to delete {:?}: {}", path, error);
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+            None
         }
     };
 
-    let payload = state
-        .album_service
-        .export_overrides(format)
-        .map_err(|error| {
-            error!("Failed to export album overrides: {}", error);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
+    state.library.remove_track(&track.id);
+    info!("Deleted track {} ({:?})", track.id, path);
 
-    Response::builder()
-        .status(StatusCode::OK)
-        .header(header::CONTENT_TYPE, content_type)
-        .body(Body::from(payload))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    Ok(DeletedTrackResponse {
+        track_id: track.id.clone(),
+        restorable: trash_path.is_some(),
+        trash_path,
+    })
 }
 
-/// Get library statistics
-#[derive(Debug, Serialize, ToSchema)]
-pub struct LibraryStats {
-    /// Total number of tracks in library
-    #[schema(example = 150)]
-    pub total_tracks: usize,
-    /// Total number of unique artists
-    #[schema(example = 25)]
-    pub total_artists: usize,
-    /// Total number of unique albums
-    #[schema(example = 45)]
-    pub total_albums: usize,
-    /// Approximate cache size
-    #[schema(example = 150)]
-    pub cache_size: usize,
+/// Track rating request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TrackRatingRequest {
+    /// Stars, 1–5; null or omitted clears the rating
+    #[schema(example = 4)]
+    #[serde(default)]
+    pub rating: Option<u8>,
 }
 
-/// Get library statistics
+/// Track favorite request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct TrackFavoriteRequest {
+    pub favorite: bool,
+}
+
+/// `rating` if it is a number of stars, 1–5
+fn check_rating(rating: Option<u8>) -> Result<Option<u8>, ApiError> {
+    match rating {
+        Some(stars) if !(1..=5).contains(&stars) => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Ratings are 1 to 5 stars",
+        )),
+        _ => Ok(rating),
+    }
+}
+
+/// Save the stats store after `changed` tracks had their rating or favorite mark set
+fn save_track_stats(state: &AppState, changed: bool) -> Result<(), ApiError> {
+    if changed {
+        state.stats_store.save().map_err(|error| {
+            error!("Failed to save track stats: {}", error);
+            ApiError::from(StatusCode::INTERNAL_SERVER_ERROR)
+        })?;
+    }
+    Ok(())
+}
+
+/// Rate a track
 ///
-/// Returns statistics about the music library including total tracks, artists, albums, and cache size.
-async fn get_library_stats(
+/// Sets the track's rating in stars, or clears it. Ratings are kept by file path with
+/// the other track stats, and take precedence over ones imported from tags.
+#[utoipa::path(
+    put,
+    path = "/api/library/tracks/{id}/rating",
+    tag = "Library",
+    params(("id" = String, Path, description = "Track identifier", example = "550e8400-e29b-41d4-a716-446655440000")),
+    request_body = TrackRatingRequest,
+    responses(
+        (status = 200, description = "Track with its new rating", body = ApiResponseTrack),
+        (status = 400, description = "Rating out of range", body = ApiErrorResponse),
+        (status = 404, description = "Track not found", body = ApiErrorResponse),
+        (status = 500, description = "Stats could not be saved", body = ApiErrorResponse),
+    )
+)]
+async fn set_track_rating(
     State(state): State<AppState>,
-) -> Result<Json<ApiResponse<LibraryStats>>, StatusCode> {
-    let total_tracks = state.library.track_count();
-    let artists = state.library.get_artists();
-    let albums = state.library.get_albums();
+    Path(track_id): Path<String>,
+    Json(request): Json<TrackRatingRequest>,
+) -> Result<Json<ApiResponse<TrackResponse>>, ApiError> {
+    let rating = check_rating(request.rating)?;
+    let track = state
+        .library
+        .get_track(&track_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
 
-    let stats = LibraryStats {
-        total_tracks,
-        total_artists: artists.len(),
-        total_albums: albums.len(),
-        cache_size: total_tracks, // Could be enhanced to check actual cache file size
-    };
+    let changed = state
+        .stats_store
+        .set_rating(&track.metadata.file_path, rating);
+    save_track_stats(&state, changed)?;
 
-    Ok(Json(ApiResponse::success(stats)))
+    Ok(Json(ApiResponse::success(TrackResponse::with_stats(
+        &track,
+        &state.stats_store,
+    ))))
 }
 
-/// Get all playlists
-#[derive(Debug, Serialize, ToSchema)]
-pub struct PlaylistResponse {
-    /// Playlist unique identifier
-    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
-    pub id: String,
-    /// Playlist name
-    #[schema(example = "My Favorites")]
-    pub name: String,
-    /// Optional playlist description
-    #[schema(example = "Best tracks collection")]
-    pub description: Option<String>,
-    /// Number of tracks in playlist
-    #[schema(example = 25)]
-    pub track_count: usize,
-    /// Creation timestamp (RFC3339)
-    #[schema(example = "2024-01-15T10:30:00Z")]
-    pub created_at: String,
-    /// Last modification timestamp (RFC3339)
-    #[schema(example = "2024-01-20T14:45:00Z")]
-    pub modified_at: String,
+/// Mark a track as a favorite
+///
+/// Marks or unmarks the track as a favorite. Like ratings, the mark is kept by file
+/// path with the other track stats.
+#[utoipa::path(
+    put,
+    path = "/api/library/tracks/{id}/favorite",
+    tag = "Library",
+    params(("id" = String, Path, description = "Track identifier", example = "550e8400-e29b-41d4-a716-446655440000")),
+    request_body = TrackFavoriteRequest,
+    responses(
+        (status = 200, description = "Track with its new favorite mark", body = ApiResponseTrack),
+        (status = 404, description = "Track not found", body = ApiErrorResponse),
+        (status = 500, description = "Stats could not be saved", body = ApiErrorResponse),
+    )
+)]
+async fn set_track_favorite(
+    State(state): State<AppState>,
+    Path(track_id): Path<String>,
+    Json(request): Json<TrackFavoriteRequest>,
+) -> Result<Json<ApiResponse<TrackResponse>>, ApiError> {
+    let track = state
+        .library
+        .get_track(&track_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let changed = state
+        .stats_store
+        .set_favorite(&track.metadata.file_path, request.favorite);
+    save_track_stats(&state, changed)?;
+
+    Ok(Json(ApiResponse::success(TrackResponse::with_stats(
+        &track,
+        &state.stats_store,
+    ))))
 }
 
-// Helper types for OpenAPI schema generation
-#[allow(dead_code)]
-#[derive(ToSchema)]
-pub struct ApiResponseString {
-    pub success: bool,
-    pub data: Option<String>,
-    pub error: Option<String>,
+/// Action of a bulk track operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkTrackAction {
+    /// Append the tracks to `params.playlist_id`, in the order given
+    AddToPlaylist,
+    /// Queue the tracks at the end, or right after the current track with
+    /// `params.next`
+    Enqueue,
+    /// Write `params.genre` to the tracks' files
+    SetGenre,
+    /// Rate the tracks `params.rating` stars, or clear their ratings without it
+    SetRating,
+    /// Mark the tracks as favorites, or unmark them with `params.favorite` false
+    Favorite,
+    /// Delete the tracks and their files, per `library.delete_mode`
+    Remove,
 }
 
-#[allow(dead_code)]
-#[derive(ToSchema)]
-pub struct ApiResponseTracks {
-    pub success: bool,
-    #[schema(value_type = Vec<TrackResponse>)]
-    pub data: Option<Vec<TrackResponse>>,
-    pub error: Option<String>,
+/// Parameters of a bulk track operation; each action reads its own
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct BulkTrackParams {
+    /// Playlist to add the tracks to, for `add_to_playlist`
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub playlist_id: Option<String>,
+    /// `modified_at` of the playlist as last seen, for `add_to_playlist`; the tracks
+    /// are not added if the playlist has changed since
+    #[serde(default, with = "serde_rfc3339::option")]
+    #[schema(value_type = Option<String>, example = "2024-01-20T14:45:00Z")]
+    pub modified_at: Option<DateTime<Utc>>,
+    /// Queue the tracks right after the current one, for `enqueue`
+    #[serde(default)]
+    pub next: bool,
+    /// Genre to write, for `set_genre`
+    #[schema(example = "Hip-Hop")]
+    pub genre: Option<String>,
+    /// Stars, 1–5, for `set_rating`
+    #[schema(example = 4)]
+    pub rating: Option<u8>,
+    /// Whether to mark or unmark the tracks, for `favorite`; marks them when omitted
+    pub favorite: Option<bool>,
 }
 
-#[allow(dead_code)]
-#[derive(ToSchema)]
-pub struct ApiResponseStats {
-    pub success: bool,
-    pub data: Option<LibraryStats>,
-    pub error: Option<String>,
+/// Bulk track operation request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BulkTrackRequest {
+    /// Tracks to act on, at most 1000
+    #[schema(example = json!(["550e8400-e29b-41d4-a716-446655440000"]))]
+    pub track_ids: Vec<String>,
+    pub action: BulkTrackAction,
+    #[serde(default)]
+    pub params: BulkTrackParams,
 }
 
-#[allow(dead_code)]
-#[derive(ToSchema)]
-pub struct ApiResponsePlaylists {
+/// Outcome of a bulk operation on one track
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BulkTrackResult {
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub track_id: String,
     pub success: bool,
-    #[schema(value_type = Vec<PlaylistResponse>)]
-    pub data: Option<Vec<PlaylistResponse>>,
+    /// Why the action failed for this track
     pub error: Option<String>,
 }
 
-#[allow(dead_code)]
-#[derive(ToSchema)]
-pub struct ApiResponseUsize {
-    pub success: bool,
-    pub data: Option<usize>,
-    pub error: Option<String>,
+impl BulkTrackResult {
+    fn new(track_id: &str, result: Result<(), String>) -> Self {
+        Self {
+            track_id: track_id.to_string(),
+            success: result.is_ok(),
+            error: result.err(),
+        }
+    }
 }
 
-/// Get all playlists
+/// Outcome of a bulk track operation
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BulkTrackResponse {
+    pub action: BulkTrackAction,
+    #[schema(example = 3)]
+    pub succeeded: usize,
+    #[schema(example = 0)]
+    pub failed: usize,
+    /// One entry per requested track, in request order
+    pub results: Vec<BulkTrackResult>,
+}
+
+/// Act on several tracks at once
 ///
-/// Returns a list of all playlists in the system.
-async fn get_playlists(
+/// Every track id is checked before anything happens; unknown ones fail the whole
+/// request with 404. Adding to a playlist saves the playlist once, enqueueing emits
+/// a single `queue_updated` event and ratings and favorite marks are saved together,
+/// so these apply to all tracks or none. Genre writes and removals are attempted
+/// track by track, and failures are reported per track.
+#[utoipa::path(
+    post,
+    path = "/api/library/tracks/bulk",
+    tag = "Library",
+    request_body = BulkTrackRequest,
+    responses(
+        (status = 200, description = "Per-track outcome of the action", body = ApiResponseBulkTracks),
+        (status = 400, description = "No tracks, too many tracks, missing parameters or a rating out of range", body = ApiErrorResponse),
+        (status = 403, description = "Deleting files is disabled by `library.delete_mode`", body = ApiErrorResponse),
+        (status = 404, description = "Unknown tracks or playlist", body = ApiErrorResponse),
+        (status = 409, description = "A library scan is in progress and `library.scan_conflict` is `reject`, or the playlist changed after `params.modified_at`", body = ApiErrorResponse),
+        (status = 500, description = "The playlist or the track stats could not be saved", body = ApiErrorResponse),
+    )
+)]
+async fn bulk_track_action(
     State(state): State<AppState>,
-) -> Result<Json<ApiResponse<Vec<PlaylistResponse>>>, StatusCode> {
-    let playlists = state.playlist_manager.get_playlists();
-    let responses: Vec<PlaylistResponse> = playlists
+    Json(request): Json<BulkTrackRequest>,
+) -> Result<Json<ApiResponse<BulkTrackResponse>>, ApiError> {
+    state.library.guard_mutation().await?;
+    let since = state.library.change_sequence();
+    check_bulk_track_count(request.track_ids.len())?;
+    let unknown: Vec<&str> = request
+        .track_ids
         .iter()
-        .map(|p| PlaylistResponse {
-            id: p.id.clone(),
-            name: p.name.clone(),
-            description: p.description.clone(),
-            track_count: p.track_count(),
-            created_at: p.created_at.to_rfc3339(),
-            modified_at: p.modified_at.to_rfc3339(),
-        })
+        .filter(|track_id| state.library.get_track(track_id).is_none())
+        .map(String::as_str)
         .collect();
+    if !unknown.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("Unknown tracks: {}", unknown.join(", ")),
+        ));
+    }
 
-    Ok(Json(ApiResponse::success(responses)))
+    let track_ids = request.track_ids;
+    let params = request.params;
+    let results: Vec<BulkTrackResult> = match request.action {
+        BulkTrackAction::AddToPlaylist => {
+            let playlist_id = params.playlist_id.ok_or_else(|| {
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "add_to_playlist needs params.playlist_id",
+                )
+            })?;
+            state
+                .playlist_manager
+                .edit_playlist(&playlist_id, params.modified_at, |playlist| {
+                    for track in track_ids
+                        .iter()
+                        .filter_map(|track_id| state.library.get_track(track_id))
+                    {
+                        playlist.add_track(&track);
+                    }
+                    Ok::<_, ApiError>(())
+                })?;
+            track_ids
+                .iter()
+                .map(|track_id| BulkTrackResult::new(track_id, Ok(())))
+                .collect()
+        }
+        BulkTrackAction::Enqueue => {
+            // Inserting after the current track one by one reverses the order, so
+            // insert from the last track on
+            let mut last = None;
+            if params.next {
+                for track_id in track_ids.iter().rev() {
+                    let position = state.playback_queue.insert_next(track_id.clone());
+                    last.get_or_insert((track_id.clone(), position));
+                }
+            } else {
+                for track_id in &track_ids {
+                    last = Some((
+                        track_id.clone(),
+                        state.playback_queue.push_back(track_id.clone()),
+                    ));
+                }
+            }
+            if let Some((track_id, position)) = last {
+                state.event_bus.emit(EventPayload::queue_updated(
+                    Some(track_id),
+                    Some(position),
+                    state.playback_queue.len(),
+                ));
+            }
+            track_ids
+                .iter()
+                .map(|track_id| BulkTrackResult::new(track_id, Ok(())))
+                .collect()
+        }
+        BulkTrackAction::SetGenre => {
+            let genre = params.genre.as_deref().unwrap_or_default().trim();
+            if genre.is_empty() {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "set_genre needs a non-empty params.genre",
+                ));
+            }
+            let update = TrackTagUpdate {
+                genre: Some(genre.to_string()),
+                ..Default::default()
+            };
+            let library = state.library.clone();
+            let written = tokio::task::spawn_blocking(move || {
+                library.update_tracks_tags(&track_ids, &update)
+            })
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            if written.iter().any(|(_, result)| result.is_ok()) {
+                emit_library_updated(&state, since);
+            }
+            written
+                .into_iter()
+                .map(|(track_id, result)| {
+                    BulkTrackResult::new(&track_id, result.map(|_| ()).map_err(|e| e.to_string()))
+                })
+                .collect()
+        }
+        BulkTrackAction::SetRating | BulkTrackAction::Favorite => {
+            let rating = check_rating(params.rating)?;
+            let favorite = params.favorite.unwrap_or(true);
+            let mut changed = false;
+            for track in track_ids
+                .iter()
+                .filter_map(|track_id| state.library.get_track(track_id))
+            {
+                let path = &track.metadata.file_path;
+                changed |= if request.action == BulkTrackAction::SetRating {
+                    state.stats_store.set_rating(path, rating)
+                } else {
+                    state.stats_store.set_favorite(path, favorite)
+                };
+            }
+            save_track_stats(&state, changed)?;
+            track_ids
+                .iter()
+                .map(|track_id| BulkTrackResult::new(track_id, Ok(())))
+                .collect()
+        }
+        BulkTrackAction::Remove => {
+            if state.delete_mode == DeleteMode::Forbid {
+                return Err(ApiError::new(
+                    StatusCode::FORBIDDEN,
+                    "Deleting files is disabled by library.delete_mode",
+                ));
+            }
+            let mut results = Vec::with_capacity(track_ids.len());
+            for track_id in &track_ids {
+                // Listed twice, or removed by a concurrent request
+                let Some(track) = state.library.get_track(track_id) else {
+                    results.push(BulkTrackResult::new(
+                        track_id,
+                        Err("Track not found".to_string()),
+                    ));
+                    continue;
+                };
+                let result = match state.library.read_only().check(&track.metadata.file_path) {
+                    Ok(()) => discard_track(&state, &track).await.map(|_| ()),
+                    Err(e) => Err(e.into()),
+                };
+                results.push(BulkTrackResult::new(
+                    track_id,
+                    result.map_err(|e| e.message),
+                ));
+            }
+            if results.iter().any(|result| result.success) {
+                emit_library_updated(&state, since);
+            }
+            results
+        }
+    };
+
+    let succeeded = results.iter().filter(|result| result.success).count();
+    info!(
+        "Bulk {:?}: {} of {} track(s) succeeded",
+        request.action,
+        succeeded,
+        results.len()
+    );
+    Ok(Json(ApiResponse::success(BulkTrackResponse {
+        action: request.action,
+        succeeded,
+        failed: results.len() - succeeded,
+        results,
+    })))
 }
 
-/// Cleanup a specific playlist
+/// Restore a track that was moved to the trash
 ///
-/// Removes tracks from the specified playlist that no longer exist in the library.
-/// Returns the number of tracks removed.
-async fn cleanup_playlist(
+/// Moves the file back to its original location and re-adds the track with its
+/// previous identifier. Returns 404 when no deletion within the retention window is
+/// recorded for the track.
+#[utoipa::path(
+    post,
+    path = "/api/library/tracks/{id}/restore",
+    tag = "Library",
+    params(("id" = String, Path, description = "Track identifier", example = "550e8400-e29b-41d4-a716-446655440000")),
+    responses(
+        (status = 200, description = "Track restored", body = ApiResponseTrack),
+        (status = 403, description = "The original location is read-only", body = ApiErrorResponse),
+        (status = 404, description = "No restorable deletion recorded", body = ApiErrorResponse),
+        (status = 409, description = "The original location is taken, or a library scan is in progress and `library.scan_conflict` is `reject`", body = ApiErrorResponse),
+        (status = 500, description = "The restored file could not be read", body = ApiErrorResponse),
+    )
+)]
+async fn restore_track(
     State(state): State<AppState>,
-    Path(id): Path<String>,
-) -> Result<Json<ApiResponse<usize>>, StatusCode> {
-    match state.playlist_manager.cleanup_playlist(&id, &state.library) {
-        Ok(removed) => Ok(Json(ApiResponse::success(removed))),
-        Err(e) => {
-            error!("Failed to cleanup playlist {}: {}", id, e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+    Path(track_id): Path<String>,
+) -> Result<Json<ApiResponse<TrackResponse>>, ApiError> {
+    state.library.guard_mutation().await?;
+    let since = state.library.change_sequence();
+    if !state.trash.is_restorable(&track_id) {
+        return Err(StatusCode::NOT_FOUND.into());
     }
+    if let Some(original_path) = state.trash.original_path(&track_id) {
+        state.library.read_only().check(&original_path)?;
+    }
+
+    let entry = state.trash.restore(&track_id).map_err(|error| {
+        error!("Failed to restore track {}: {}", track_id, error);
+        StatusCode::CONFLICT
+    })?;
+
+    let track = Track::new(entry.original_path.clone()).map_err(|error| {
+        error!("Failed to read restored file: {}", error);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let response = TrackResponse::from(&track);
+    state.library.add_track(track);
+    emit_library_updated(&state, since);
+
+    Ok(Json(ApiResponse::success(response)))
 }
 
-/// Cleanup all playlists
+/// Get the chapter markers of a track
 ///
-/// Removes missing tracks from all playlists in the system.
-/// Returns the total number of tracks removed across all playlists.
-async fn cleanup_all_playlists(
+/// Chapters come from Vorbis comments (FLAC, Ogg), ID3v2 `CHAP` frames or MP4 Nero
+/// chapters. Tracks without chapters return an empty list.
+#[utoipa::path(
+    get,
+    path = "/api/library/tracks/{id}/chapters",
+    tag = "Library",
+    params(("id" = String, Path, description = "Track identifier", example = "550e8400-e29b-41d4-a716-446655440000")),
+    responses(
+        (status = 200, description = "Chapters ordered by start time", body = ApiResponseChapters),
+        (status = 404, description = "Track not found", body = ApiErrorResponse),
+    )
+)]
+async fn get_track_chapters(
     State(state): State<AppState>,
-) -> Result<Json<ApiResponse<usize>>, StatusCode> {
-    match state
-        .playlist_manager
-        .cleanup_missing_tracks(&state.library)
-    {
-        Ok(removed) => Ok(Json(ApiResponse::success(removed))),
-        Err(e) => {
-            error!("Failed to cleanup playlists: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
+    Path(track_id): Path<String>,
+) -> Result<Json<ApiResponse<Vec<Chapter>>>, ApiError> {
+    let track = state
+        .library
+        .get_track(&track_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(ApiResponse::success(track.metadata.chapters)))
 }
 
-fn lookup_track_metadata(library: &Library, track_path: &FsPath) -> (Option<String>, Option<u64>) {
-    if let Some(track) = library.get_track_by_path(track_path) {
-        (Some(track.id), track.metadata.duration)
-    } else {
-        (None, None)
+/// Get the tracks of a MusicBrainz recording
+///
+/// Looks tracks up by the recording MBID they are tagged with (MUSICBRAINZ_TRACKID),
+/// in any case. Every file of the recording is returned, ordered by path.
+#[utoipa::path(
+    get,
+    path = "/api/library/tracks/by-mbid/{id}",
+    tag = "Library",
+    params(("id" = String, Path, description = "MusicBrainz recording identifier", example = "b1a9c0e9-d987-4042-ae91-78d6a3267d69")),
+    responses(
+        (status = 200, description = "Tracks of the recording", body = ApiResponseTracks),
+        (status = 404, description = "No track is tagged with the recording", body = ApiErrorResponse),
+    )
+)]
+async fn get_tracks_by_mbid(
+    State(state): State<AppState>,
+    Path(mbid): Path<String>,
+) -> Result<Json<ApiResponse<Vec<TrackResponse>>>, ApiError> {
+    let tracks = state.library.get_tracks_by_musicbrainz_id(&mbid);
+    if tracks.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "No track is tagged with this MusicBrainz recording",
+        ));
     }
+    let stats = state.stats_store.as_ref();
+    Ok(Json(ApiResponse::success(
+        tracks
+            .iter()
+            .map(|track| TrackResponse::with_stats(track, stats))
+            .collect(),
+    )))
 }
 
-fn emit_playback_event(
-    state: &AppState,
-    playback_state: &str,
-    track_path: Option<String>,
-    track_id: Option<String>,
-    track_duration: Option<u64>,
-) {
-    state.event_bus.emit(EventPayload::playback_state(
-        playback_state.to_string(),
-        track_path,
-        track_id,
-        Some(state.audio_player.get_volume()),
-        track_duration,
-    ));
+/// Get the album of a MusicBrainz release
+///
+/// Looks the album up by the release MBID its tracks are tagged with
+/// (MUSICBRAINZ_ALBUMID), in any case.
+#[utoipa::path(
+    get,
+    path = "/api/library/albums/by-mbid/{id}",
+    tag = "Library",
+    params(("id" = String, Path, description = "MusicBrainz release identifier", example = "1dc4c347-a1db-32aa-b14f-bc9cc507b843")),
+    responses(
+        (status = 200, description = "The album", body = ApiResponseAlbum),
+        (status = 404, description = "No album is tagged with the release", body = ApiErrorResponse),
+    )
+)]
+async fn get_album_by_mbid(
+    State(state): State<AppState>,
+    Path(mbid): Path<String>,
+) -> Result<Json<ApiResponse<AlbumResponse>>, ApiError> {
+    let album = state
+        .album_service
+        .album_by_musicbrainz_id(state.library.as_ref(), &mbid)
+        .await
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                "No album is tagged with this MusicBrainz release",
+            )
+        })?;
+    Ok(Json(ApiResponse::success(AlbumResponse::from(album))))
 }
 
-/// Play audio request
-#[derive(Debug, Deserialize, ToSchema)]
-pub struct PlayRequest {
-    /// File path to audio file
-    #[schema(example = "/path/to/track.mp3")]
-    pub file_path: String,
+/// Encoding of a waveform response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WaveformFormat {
+    /// An [`ApiResponse`] with the peaks as a JSON array
+    #[default]
+    Json,
+    /// The peaks as raw signed bytes, min and max of each point in turn
+    Binary,
 }
 
-/// Audio status response
+/// Waveform parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct WaveformQuery {
+    /// Points to return, 1 to 800 (defaults to 800)
+    #[param(example = 400)]
+    pub points: Option<usize>,
+    /// `json` (default) or `binary`
+    #[param(inline)]
+    pub format: Option<WaveformFormat>,
+}
+
+/// Waveform of a track for drawing a seek bar
 #[derive(Debug, Serialize, ToSchema)]
-pub struct AudioStatusResponse {
-    /// Current playback state
-    #[schema(example = "Playing")]
-    pub state: String,
-    /// Current track path
-    #[schema(example = "/path/to/track.mp3")]
-    pub current_track: Option<String>,
-    /// Current volume (0.0 to 1.0)
-    #[schema(example = 0.7)]
-    pub volume: f32,
+pub struct WaveformResponse {
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub track_id: String,
+    /// Points returned; fewer than asked for when the track is very short
+    #[schema(example = 400)]
+    pub points: usize,
+    /// Seconds the points span
+    #[schema(example = 245)]
+    pub duration: Option<u64>,
+    /// Lowest and highest sample of each point in turn, from -127 to 127
+    #[schema(example = json!([-64, 72, -80, 91]))]
+    pub data: Vec<i8>,
 }
 
-/// Play audio file
-async fn play_audio(
+/// Get the waveform of a track
+///
+/// Returns the lowest and highest sample of `points` evenly spaced stretches of the
+/// track, across all channels, for drawing a waveform in the seek bar. The first
+/// request decodes the track, which takes a moment; the result is cached until the
+/// file changes. With `format=binary` the same values are sent as raw signed bytes.
+#[utoipa::path(
+    get,
+    path = "/api/library/tracks/{id}/waveform",
+    tag = "Library",
+    params(
+        ("id" = String, Path, description = "Track identifier", example = "550e8400-e29b-41d4-a716-446655440000"),
+        WaveformQuery,
+    ),
+    responses(
+        (status = 200, description = "Peaks of the track", body = ApiResponseWaveform),
+        (status = 400, description = "`points` is 0 or above 800", body = ApiErrorResponse),
+        (status = 404, description = "Track not found", body = ApiErrorResponse),
+        (status = 422, description = "The track cannot be decoded", body = ApiErrorResponse),
+    )
+)]
+async fn get_track_waveform(
     State(state): State<AppState>,
-    Json(request): Json<PlayRequest>,
-) -> Result<Json<ApiResponse<String>>, StatusCode> {
-    let file_path = FsPath::new(&request.file_path);
-
-    match state.audio_player.play(file_path) {
-        Ok(_) => {
-            info!("Started playing: {}", request.file_path);
-            let (track_id, track_duration) =
-                lookup_track_metadata(state.library.as_ref(), file_path);
-            emit_playback_event(
-                &state,
-                "playing",
-                Some(request.file_path.clone()),
-                track_id,
-                track_duration,
-            );
-            Ok(Json(ApiResponse::success("Playback started".to_string())))
-        }
-        Err(e) => {
-            error!("Failed to play audio: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+    Path(track_id): Path<String>,
+    Query(query): Query<WaveformQuery>,
+) -> Result<Response, ApiError> {
+    let points = query.points.unwrap_or(WAVEFORM_BUCKETS);
+    if !(1..=WAVEFORM_BUCKETS).contains(&points) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("points must be between 1 and {}", WAVEFORM_BUCKETS),
+        ));
     }
-}
+    let track = state
+        .library
+        .get_track(&track_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
 
-/// Pause audio playback
-async fn pause_audio(
-    State(state): State<AppState>,
-) -> Result<Json<ApiResponse<String>>, StatusCode> {
-    match state.audio_player.pause() {
-        Ok(_) => {
-            info!("Audio paused");
-            let track_path = state.audio_player.get_current_track();
-            let (track_id, track_duration) = track_path
-                .as_deref()
-                .map(|path| lookup_track_metadata(state.library.as_ref(), FsPath::new(path)))
-                .unwrap_or((None, None));
-            emit_playback_event(&state, "paused", track_path, track_id, track_duration);
-            Ok(Json(ApiResponse::success("Playback paused".to_string())))
-        }
-        Err(e) => {
-            error!("Failed to pause audio: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+    let waveform = state
+        .waveforms
+        .load(track.metadata.file_path.clone())
+        .await
+        .map_err(|e| {
+            warn!("Cannot compute the waveform of {}: {}", track_id, e);
+            ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("The track cannot be decoded: {}", e),
+            )
+        })?;
+    let data: Vec<i8> = waveform
+        .downsample(points)
+        .iter()
+        .flat_map(|peak| [peak.min, peak.max])
+        .collect();
+
+    if query.format.unwrap_or_default() == WaveformFormat::Binary {
+        let bytes: Vec<u8> = data.iter().map(|value| *value as u8).collect();
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "application/octet-stream")
+            .body(Body::from(bytes))
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into());
     }
+    Ok(Json(ApiResponse::success(WaveformResponse {
+        track_id,
+        points: data.len() / 2,
+        duration: track.metadata.duration,
+        data,
+    }))
+    .into_response())
 }
 
-/// Resume audio playback
-async fn resume_audio(
+/// Compute the missing waveforms of the library
+///
+/// Decodes every track whose waveform is not cached yet, two at a time, so seek bars
+/// show their waveform right away. Waveforms are otherwise computed when first
+/// requested. Runs as a `waveforms` job, which can be cancelled through
+/// `POST /api/jobs/{id}/cancel`.
+#[utoipa::path(
+    post,
+    path = "/api/library/waveforms",
+    tag = "Library",
+    responses(
+        (status = 200, description = "How many waveforms were computed", body = ApiResponseWaveformPrecompute),
+        (status = 409, description = "Waveforms are already being computed", body = ApiErrorResponse),
+        (status = 503, description = "The library is still loading", body = ApiErrorResponse),
+    )
+)]
+async fn precompute_waveforms(
     State(state): State<AppState>,
-) -> Result<Json<ApiResponse<String>>, StatusCode> {
-    match state.audio_player.resume() {
-        Ok(_) => {
-            info!("Audio resumed");
-            let track_path = state.audio_player.get_current_track();
-            let (track_id, track_duration) = track_path
-                .as_deref()
-                .map(|path| lookup_track_metadata(state.library.as_ref(), FsPath::new(path)))
-                .unwrap_or((None, None));
-            emit_playback_event(&state, "playing", track_path, track_id, track_duration);
-            Ok(Json(ApiResponse::success("Playback resumed".to_string())))
-        }
-        Err(e) => {
-            error!("Failed to resume audio: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
+) -> Result<Json<ApiResponse<WaveformPrecompute>>, ApiError> {
+    if !state.library.is_ready() {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The library is still loading",
+        ));
     }
-}
 
-/// Stop audio playback
-async fn stop_audio(
-    State(state): State<AppState>,
-) -> Result<Json<ApiResponse<String>>, StatusCode> {
-    let track_path_before_stop = state.audio_player.get_current_track();
-    let (track_id_before_stop, track_duration_before_stop) = track_path_before_stop
+    let tracks = state.library.get_tracks();
+    let total = tracks.len();
+    let waveforms = state.waveforms.clone();
+    let report = state
+        .jobs
+        .run(
+            JobKind::Waveforms,
+            serde_json::json!({ "tracks": total }),
+            move |handle| async move {
+                let report = tokio::task::spawn_blocking(move || {
+                    waveforms.precompute_with(
+                        &tracks,
+                        |done| {
+                            if done % jobs::JOB_PROGRESS_INTERVAL == 0 || done == total {
+                                handle.progress(done, Some(total), None);
+                            }
+                        },
+                        || handle.is_cancelled(),
+                    )
+                })
+                .await?;
+                Ok(report)
+            },
+        )
+        .await
+        .map_err(|_| ApiError::new(StatusCode::CONFLICT, "Waveforms are already being computed"))?
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!(
+        "Computed {} waveform(s), {} cached already, {} failed",
+        report.computed, report.cached, report.failed
+    );
+    Ok(Json(ApiResponse::success(report)))
+}
+
+/// Request body for creating a share link
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateShareRequest {
+    pub scope: ShareScope,
+    /// Hours until the link expires, 1 to 8760 (defaults to a week)
+    #[schema(example = 48)]
+    pub expires_in_hours: Option<u32>,
+}
+
+/// A share link
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ShareLink {
+    #[schema(
+        example = "eyJzY29wZSI6eyJraW5kIjoibm93X3BsYXlpbmcifSwiZXhwaXJlc19hdCI6MTcwNTMxNDYwMH0.c2lnbmF0dXJl"
+    )]
+    pub token: String,
+    /// Path of the shared view, relative to the address the API is reachable at
+    #[schema(
+        example = "/api/share/eyJzY29wZSI6eyJraW5kIjoibm93X3BsYXlpbmcifSwiZXhwaXJlc19hdCI6MTcwNTMxNDYwMH0.c2lnbmF0dXJl"
+    )]
+    pub url: String,
+    #[serde(with = "serde_rfc3339")]
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A track as shown through a share link, without its location on disk
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SharedTrack {
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub id: String,
+    #[schema(example = "Bohemian Rhapsody")]
+    pub title: Option<String>,
+    #[schema(example = "Queen")]
+    pub artist: Option<String>,
+    #[schema(example = "A Night at the Opera")]
+    pub album: Option<String>,
+    /// Seconds
+    #[schema(example = 355)]
+    pub duration: Option<u64>,
+    /// Where the track can be streamed, when `api.share_allow_stream` is set
+    pub stream_url: Option<String>,
+}
+
+/// What a share link shows
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SharedView {
+    pub scope: ShareScope,
+    #[serde(with = "serde_rfc3339")]
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub expires_at: DateTime<Utc>,
+    /// Artwork of the album shared, or of the album of the track shared
+    pub artwork_url: Option<String>,
+    /// The shared track, the tracks of the shared album in order, or the track playing;
+    /// empty when nothing is playing
+    pub tracks: Vec<SharedTrack>,
+}
+
+/// Tracks a share link shows, in album order; 404 when they left the library
+fn shared_tracks(state: &AppState, scope: &ShareScope) -> Result<Vec<Track>, ApiError> {
+    let tracks = match scope {
+        ShareScope::Track { id } => state.library.get_track(id).into_iter().collect(),
+        ShareScope::Album { id } => {
+            let mut tracks = state.album_service.album_tracks(&state.library, id);
+            tracks.sort_by_cached_key(album_position);
+            tracks
+        }
+        ShareScope::NowPlaying => {
+            return Ok(state
+                .audio_player
+                .get_current_track()
+                .and_then(|path| state.library.get_track_by_path(FsPath::new(&path)))
+                .into_iter()
+                .collect())
+        }
+    };
+    if tracks.is_empty() {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "The shared music is no longer in the library",
+        ));
+    }
+    Ok(tracks)
+}
+
+/// Album whose artwork a share link shows
+fn shared_album_id(scope: &ShareScope, tracks: &[Track]) -> Option<String> {
+    match scope {
+        ShareScope::Album { id } => Some(id.clone()),
+        _ => tracks.first()?.album_id(),
+    }
+}
+
+/// Create a share link
+///
+/// Signs a link showing a track, an album or whatever is playing, which anyone holding
+/// it can open with `GET /api/share/{token}` until it expires. Links are not stored;
+/// `DELETE /api/share` revokes all of them.
+#[utoipa::path(
+    post,
+    path = "/api/share",
+    tag = "Sharing",
+    request_body = CreateShareRequest,
+    responses(
+        (status = 200, description = "Share link created", body = ApiResponseShareLink),
+        (status = 400, description = "`expires_in_hours` is 0 or above 8760", body = ApiErrorResponse),
+        (status = 404, description = "Unknown track or album", body = ApiErrorResponse),
+    )
+)]
+async fn create_share(
+    State(state): State<AppState>,
+    Json(request): Json<CreateShareRequest>,
+) -> Result<Json<ApiResponse<ShareLink>>, ApiError> {
+    let hours = request.expires_in_hours.unwrap_or(DEFAULT_SHARE_HOURS);
+    if !(1..=MAX_SHARE_HOURS).contains(&hours) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("expires_in_hours must be between 1 and {}", MAX_SHARE_HOURS),
+        ));
+    }
+    shared_tracks(&state, &request.scope)?;
+
+    let expires_at = (Utc::now() + chrono::Duration::hours(i64::from(hours))).trunc_subsecs(0);
+    let token = state.shares.sign(&ShareClaims {
+        scope: request.scope.clone(),
+        expires_at,
+    });
+    info!(
+        "Created a share link for {:?} until {}",
+        request.scope,
+        expires_at.to_rfc3339()
+    );
+    Ok(Json(ApiResponse::success(ShareLink {
+        url: format!("/api/share/{}", token),
+        token,
+        expires_at,
+    })))
+}
+
+/// Revoke all share links
+///
+/// Replaces the secret share links are signed with, so every link handed out so far
+/// stops working.
+#[utoipa::path(
+    delete,
+    path = "/api/share",
+    tag = "Sharing",
+    responses(
+        (status = 200, description = "Share links revoked", body = ApiResponseString),
+        (status = 500, description = "The new secret could not be stored", body = ApiErrorResponse),
+    )
+)]
+async fn revoke_shares(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    state.shares.rotate().map_err(|error| {
+        error!("Failed to rotate the share secret: {}", error);
+        ApiError::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "The share secret could not be replaced",
+        )
+    })?;
+    info!("Revoked all share links");
+    Ok(Json(ApiResponse::success(
+        "All share links were revoked".to_string(),
+    )))
+}
+
+/// Open a share link
+///
+/// Needs no credentials. Shows the metadata of the shared tracks, never their
+/// location, with links to their artwork and, when `api.share_allow_stream` is set,
+/// their audio.
+#[utoipa::path(
+    get,
+    path = "/api/share/{token}",
+    tag = "Sharing",
+    params(("token" = String, Path, description = "Share link token", example = "eyJzY29wZSI6eyJraW5kIjoibm93X3BsYXlpbmcifSwiZXhwaXJlc19hdCI6MTcwNTMxNDYwMH0.c2lnbmF0dXJl")),
+    responses(
+        (status = 200, description = "The shared music", body = ApiResponseSharedView),
+        (status = 404, description = "Unknown or revoked link, or the music left the library", body = ApiErrorResponse),
+        (status = 410, description = "The link expired", body = ApiErrorResponse),
+    )
+)]
+async fn get_shared(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<Json<ApiResponse<SharedView>>, ApiError> {
+    let claims = state.shares.verify(&token, Utc::now())?;
+    let tracks = shared_tracks(&state, &claims.scope)?;
+    let artwork_url = shared_album_id(&claims.scope, &tracks)
+        .and_then(|album_id| state.album_service.cached_artwork_path(&album_id))
+        .map(|_| format!("/api/share/{}/artwork", token));
+    let allow_stream = state.shares.allow_stream();
+
+    let tracks = tracks
+        .into_iter()
+        .map(|track| SharedTrack {
+            stream_url: allow_stream
+                .then(|| format!("/api/share/{}/tracks/{}/stream", token, track.id)),
+            id: track.id,
+            title: track.metadata.title,
+            artist: track.metadata.artist,
+            album: track.metadata.album,
+            duration: track.metadata.duration,
+        })
+        .collect();
+    Ok(Json(ApiResponse::success(SharedView {
+        scope: claims.scope,
+        expires_at: claims.expires_at,
+        artwork_url,
+        tracks,
+    })))
+}
+
+/// Get the artwork of a share link
+#[utoipa::path(
+    get,
+    path = "/api/share/{token}/artwork",
+    tag = "Sharing",
+    params(("token" = String, Path, description = "Share link token", example = "eyJzY29wZSI6eyJraW5kIjoibm93X3BsYXlpbmcifSwiZXhwaXJlc19hdCI6MTcwNTMxNDYwMH0.c2lnbmF0dXJl")),
+    responses(
+        (status = 200, description = "Artwork image (JPEG, PNG or WebP)", content_type = "image/jpeg"),
+        (status = 304, description = "Artwork unchanged since the `If-None-Match` ETag"),
+        (status = 404, description = "Unknown or revoked link, or no artwork", body = ApiErrorResponse),
+        (status = 410, description = "The link expired", body = ApiErrorResponse),
+    )
+)]
+async fn get_shared_artwork(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let claims = state.shares.verify(&token, Utc::now())?;
+    let tracks = shared_tracks(&state, &claims.scope)?;
+    let path = shared_album_id(&claims.scope, &tracks)
+        .and_then(|album_id| state.album_service.cached_artwork_path(&album_id))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    image_response(&path, &headers).await.map_err(|error| {
+        error!("Failed to read shared artwork {:?}: {}", path, error);
+        StatusCode::INTERNAL_SERVER_ERROR.into()
+    })
+}
+
+/// Stream a shared track
+///
+/// Sends a track of a share link as stored, with `Range` support, when
+/// `api.share_allow_stream` is set.
+#[utoipa::path(
+    get,
+    path = "/api/share/{token}/tracks/{track_id}/stream",
+    tag = "Sharing",
+    params(
+        ("token" = String, Path, description = "Share link token", example = "eyJzY29wZSI6eyJraW5kIjoibm93X3BsYXlpbmcifSwiZXhwaXJlc19hdCI6MTcwNTMxNDYwMH0.c2lnbmF0dXJl"),
+        ("track_id" = String, Path, description = "Track identifier", example = "550e8400-e29b-41d4-a716-446655440000"),
+    ),
+    responses(
+        (status = 200, description = "Audio file", content_type = "application/octet-stream"),
+        (status = 206, description = "Requested byte range", content_type = "application/octet-stream"),
+        (status = 403, description = "Streaming is disabled, or the link does not share this track", body = ApiErrorResponse),
+        (status = 404, description = "Unknown or revoked link", body = ApiErrorResponse),
+        (status = 410, description = "The link expired", body = ApiErrorResponse),
+    )
+)]
+async fn stream_shared_track(
+    State(state): State<AppState>,
+    Path((token, track_id)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let claims = state.shares.verify(&token, Utc::now())?;
+    if !state.shares.allow_stream() {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "Streaming shared tracks is disabled",
+        ));
+    }
+    let track = shared_tracks(&state, &claims.scope)?
+        .into_iter()
+        .find(|track| track.id == track_id)
+        .ok_or_else(|| {
+            ApiError::new(StatusCode::FORBIDDEN, "This link does not share that track")
+        })?;
+    file_response(&track.metadata.file_path, &headers, None).await
+}
+
+/// Stream a track
+///
+/// Sends the file as stored, honouring `Range` requests. With `transcode=opus` the
+/// audio is transcoded on the fly and sent with chunked transfer encoding, when the
+/// backend was built with the `transcode` feature and ffmpeg is installed.
+/// Transcoded streams cannot be seeked by byte range: they answer with
+/// `Accept-Ranges: none` and `X-Transcode: opus; bitrate=128`, and are seeked by
+/// requesting them again with `start`. When transcoding is unavailable the file is
+/// sent as stored with `X-Transcode: unavailable`, and `start` is ignored.
+#[utoipa::path(
+    get,
+    path = "/api/library/tracks/{id}/stream",
+    tag = "Library",
+    params(
+        ("id" = String, Path, description = "Track identifier", example = "550e8400-e29b-41d4-a716-446655440000"),
+        StreamQuery,
+    ),
+    responses(
+        (status = 200, description = "Audio data, transcoded or as stored", content_type = "application/octet-stream"),
+        (status = 206, description = "The requested byte range of the file", content_type = "application/octet-stream"),
+        (status = 400, description = "Invalid transcoding parameters", body = ApiErrorResponse),
+        (status = 404, description = "Unknown track or missing file", body = ApiErrorResponse),
+        (status = 416, description = "The byte range lies outside the file"),
+    )
+)]
+async fn stream_track(
+    State(state): State<AppState>,
+    Path(track_id): Path<String>,
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let transcode = Transcode::from_params(query.transcode.as_deref(), query.bitrate, query.start)
+        .map_err(|message| ApiError::new(StatusCode::BAD_REQUEST, message))?;
+    let track = state
+        .library
+        .get_track(&track_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let path = track.metadata.file_path;
+    let metadata = fs::metadata(&path)
+        .await
+        .map_err(|error| stream_error(&path, error))?;
+
+    let Some(transcode) = transcode else {
+        return file_response(&path, &headers, None).await;
+    };
+
+    let cache = match (&state.transcode_cache, metadata.modified()) {
+        (Some(cache), Ok(modified)) => {
+            TranscodeCache::key(&path, modified, &transcode).map(|key| (cache.clone(), key))
+        }
+        _ => None,
+    };
+    if let Some(cached) = cache.as_ref().and_then(|(cache, key)| cache.get(key)) {
+        let file = fs::File::open(&cached)
+            .await
+            .map_err(|error| stream_error(&cached, error))?;
+        return transcoded_response(&transcode, Body::from_stream(read_chunks(file)));
+    }
+
+    match transcode_stream(&path, &transcode, cache) {
+        Ok(Some(stream)) => return transcoded_response(&transcode, Body::from_stream(stream)),
+        Ok(None) => {}
+        Err(e) => warn!("Cannot transcode {:?}, sending it as stored: {}", path, e),
+    }
+    file_response(&path, &headers, Some("unavailable")).await
+}
+
+/// Response carrying a transcoded stream, which cannot be seeked by byte range
+fn transcoded_response(transcode: &Transcode, body: Body) -> Result<Response, ApiError> {
+    Response::builder()
+        .header(header::CONTENT_TYPE, transcode.format.content_type())
+        .header(header::ACCEPT_RANGES, "none")
+        .header("x-transcode", transcode.header_value())
+        .body(body)
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into())
+}
+
+/// Send the file at `path`, or the part of it asked for with a `Range` header
+async fn file_response(
+    path: &FsPath,
+    headers: &HeaderMap,
+    transcode: Option<&str>,
+) -> Result<Response, ApiError> {
+    use std::io::SeekFrom;
+    use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+    let mut file = fs::File::open(path)
+        .await
+        .map_err(|error| stream_error(path, error))?;
+    let size = file
+        .metadata()
+        .await
+        .map_err(|error| stream_error(path, error))?
+        .len();
+
+    let mut builder = Response::builder()
+        .header(header::CONTENT_TYPE, audio_content_type(path))
+        .header(header::ACCEPT_RANGES, "bytes");
+    if let Some(transcode) = transcode {
+        builder = builder.header("x-transcode", transcode);
+    }
+
+    let range = headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(ByteRange::Whole, |value| byte_range(value, size));
+    let (status, start, length) = match range {
+        ByteRange::Whole => (StatusCode::OK, 0, size),
+        ByteRange::Part(start, end) => {
+            builder = builder.header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", start, end, size),
+            );
+            (StatusCode::PARTIAL_CONTENT, start, end - start + 1)
+        }
+        ByteRange::Unsatisfiable => {
+            return builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+                .body(Body::empty())
+                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into());
+        }
+    };
+
+    file.seek(SeekFrom::Start(start))
+        .await
+        .map_err(|error| stream_error(path, error))?;
+    builder
+        .status(status)
+        .header(header::CONTENT_LENGTH, length)
+        .body(Body::from_stream(read_chunks(file.take(length))))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into())
+}
+
+fn stream_error(path: &FsPath, error: std::io::Error) -> ApiError {
+    if error.kind() == std::io::ErrorKind::NotFound {
+        return StatusCode::NOT_FOUND.into();
+    }
+    error!("Failed to stream {:?}: {}", path, error);
+    StatusCode::INTERNAL_SERVER_ERROR.into()
+}
+
+/// What a `Range` header asks of a file
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    Whole,
+    /// First and last byte, inclusive
+    Part(u64, u64),
+    Unsatisfiable,
+}
+
+/// Parse a single `bytes=` range against a file of `size` bytes. Malformed headers and
+/// multiple ranges are answered with the whole file.
+fn byte_range(value: &str, size: u64) -> ByteRange {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return ByteRange::Whole;
+    };
+    let Some((first, last)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+        return ByteRange::Whole;
+    };
+    let (first, last) = (first.trim(), last.trim());
+
+    let (start, end) = match (first.parse::<u64>(), last.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+        (Ok(start), Err(_)) if last.is_empty() => (start, size.saturating_sub(1)),
+        // The last `suffix` bytes
+        (Err(_), Ok(suffix)) if first.is_empty() => {
+            if suffix == 0 {
+                return ByteRange::Unsatisfiable;
+            }
+            (size.saturating_sub(suffix), size.saturating_sub(1))
+        }
+        _ => return ByteRange::Whole,
+    };
+    if start >= size {
+        ByteRange::Unsatisfiable
+    } else {
+        ByteRange::Part(start, end)
+    }
+}
+
+fn audio_content_type(path: &FsPath) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "flac" => "audio/flac",
+        "mp3" => "audio/mpeg",
+        "ogg" | "oga" => "audio/ogg",
+        "opus" => "audio/ogg; codecs=opus",
+        "wav" => "audio/wav",
+        "m4a" | "mp4" | "aac" => "audio/mp4",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Write a track's metadata sidecar
+///
+/// Merges the given fields into `<file>.hexendrum.json` next to the audio file,
+/// creating it if needed. Sidecar fields take precedence over the file's tags, which
+/// is useful for files that cannot be retagged. Returns the track with the sidecar
+/// applied.
+#[utoipa::path(
+    put,
+    path = "/api/library/tracks/{id}/sidecar",
+    tag = "Library",
+    params(("id" = String, Path, description = "Track identifier", example = "550e8400-e29b-41d4-a716-446655440000")),
+    request_body = SidecarMetadata,
+    responses(
+        (status = 200, description = "Track with the updated sidecar applied", body = ApiResponseTrack),
+        (status = 403, description = "The track is in a read-only library directory", body = ApiErrorResponse),
+        (status = 404, description = "Track not found", body = ApiErrorResponse),
+        (status = 409, description = "A library scan is in progress and `library.scan_conflict` is `reject`", body = ApiErrorResponse),
+        (status = 500, description = "Sidecar could not be written", body = ApiErrorResponse),
+    )
+)]
+async fn update_track_sidecar(
+    State(state): State<AppState>,
+    Path(track_id): Path<String>,
+    Json(update): Json<SidecarMetadata>,
+) -> Result<Json<ApiResponse<TrackResponse>>, ApiError> {
+    state.library.guard_mutation().await?;
+    let since = state.library.change_sequence();
+    if state.library.get_track(&track_id).is_none() {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    let track = state.library.update_track_sidecar(&track_id, update)?;
+    emit_library_updated(&state, since);
+
+    Ok(Json(ApiResponse::success(TrackResponse::from(&track))))
+}
+
+/// Get the report of the last library scan
+///
+/// Lists sidecar files that were ignored because they could not be read or did not
+/// match the sidecar schema. Returns 404 if no scan has completed since startup.
+#[utoipa::path(
+    get,
+    path = "/api/library/scan/report",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Report of the last scan", body = ApiResponseScanReport),
+        (status = 404, description = "No scan has completed yet", body = ApiErrorResponse),
+    )
+)]
+async fn get_scan_report(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<ScanReportResponse>>, ApiError> {
+    let report = state
+        .library
+        .last_scan_report()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(ApiResponse::success(report.into())))
+}
+
+/// Scan library directories
+///
+/// Starts scanning the specified directories for music files in the background and
+/// returns right away. Progress is reported through `library_scan` events, whose
+/// `processed` and `total` count the audio files found once they are listed, and
+/// `GET /api/library/scan/status` or `GET /api/jobs/{job}` tells when the scan with the
+/// returned `job` number is done. Supported formats: MP3, FLAC, OGG, WAV, M4A, AAC
+///
+/// After scanning, the library is automatically cached for faster loading on next startup.
+#[utoipa::path(
+    post,
+    path = "/api/library/scan",
+    tag = "Library",
+    request_body = ScanRequest,
+    responses(
+        (status = 202, description = "Scan started", body = ApiResponseScanJob),
+        (status = 400, description = "Too many directories", body = ApiErrorResponse),
+        (status = 409, description = "A scan is already running", body = ApiErrorResponse),
+        (status = 413, description = "Request body too large", body = ApiErrorResponse),
+    )
+)]
+async fn scan_library(
+    State(state): State<AppState>,
+    Json(request): Json<ScanRequest>,
+) -> Result<(StatusCode, Json<ApiResponse<ScanJobStatus>>), ApiError> {
+    check_directory_count(request.directories.len())?;
+    let directories: Vec<PathBuf> = request.directories.iter().map(PathBuf::from).collect();
+    if state.library.is_scanning() {
+        return Err(ScanInProgressError {
+            retry_after: SCAN_RETRY_AFTER,
+        }
+        .into());
+    }
+
+    match start_scan_job(&state, directories, request.full_rescan) {
+        Ok(job) => Ok((
+            StatusCode::ACCEPTED,
+            Json(ApiResponse::success(ScanJobStatus::from(&job))),
+        )),
+        Err(_) => Err(ScanInProgressError {
+            retry_after: SCAN_RETRY_AFTER,
+        }
+        .into()),
+    }
+}
+
+/// Scan `directories` in the background as a [`JobKind::Scan`] job, reporting through
+/// `library_scan` events. The scan runs on a blocking thread and stops when the job is
+/// cancelled. [`JobError::Busy`] if a scan job is already running.
+pub fn start_scan_job(
+    state: &AppState,
+    directories: Vec<PathBuf>,
+    full_rescan: bool,
+) -> Result<Job, JobError> {
+    let scan_state = state.clone();
+    let params = serde_json::json!({
+        "directories": directories,
+        "full_rescan": full_rescan,
+    });
+    state
+        .jobs
+        .start(JobKind::Scan, params, move |handle| async move {
+            let state = scan_state;
+            let since = state.library.change_sequence();
+            // A scan requested during startup runs once the cache is loaded
+            state.library.ready().await;
+            if handle.is_cancelled() {
+                state
+                    .event_bus
+                    .emit(EventPayload::library_scan("cancelled", None, None));
+                return Err(LibraryError::ScanCancelled.into());
+            }
+            state
+                .event_bus
+                .emit(EventPayload::library_scan("started", None, None));
+
+            let library = state.library.clone();
+            let event_bus = state.event_bus.clone();
+            let progress_handle = handle.clone();
+            let mut scan = tokio::task::spawn_blocking(move || {
+                library.scan_directories_reporting(&directories, full_rescan, &|progress| {
+                    event_bus.emit(EventPayload::library_scan(
+                        "running",
+                        Some(progress.processed),
+                        Some(progress.total),
+                    ));
+                    progress_handle.progress(progress.processed, Some(progress.total), None);
+                })
+            });
+            // A cancel arriving before the scan has begun is refused by the library,
+            // so it is repeated until the scan takes it or ends
+            let mut cancelling = false;
+            let mut cancelled = false;
+            let result = loop {
+                tokio::select! {
+                    result = &mut scan => break result,
+                    _ = handle.cancelled(), if !cancelling => {
+                        cancelling = true;
+                        cancelled = state.library.cancel_scan();
+                    }
+                    _ = tokio::time::sleep(Duration::from_millis(10)), if cancelling && !cancelled => {
+                        cancelled = state.library.cancel_scan();
+                    }
+                }
+            };
+            let result = result
+                .map_err(anyhow::Error::from)
+                .and_then(|result| Ok(result?));
+            match result {
+                Ok(report) => {
+                    let count = state.library.track_count();
+                    info!(
+                        "Library scan completed: {} tracks, {} invalid sidecar(s)",
+                        count,
+                        report.sidecar_errors.len()
+                    );
+                    state.event_bus.emit(EventPayload::library_scan(
+                        "completed",
+                        Some(report.files),
+                        Some(report.files),
+                    ));
+                    emit_library_updated(&state, since);
+                    Ok(serde_json::json!({ "tracks": count }))
+                }
+                Err(e) if matches!(e.downcast_ref(), Some(LibraryError::ScanCancelled)) => {
+                    state
+                        .event_bus
+                        .emit(EventPayload::library_scan("cancelled", None, None));
+                    Err(e)
+                }
+                Err(e) => {
+                    error!("Failed to scan library: {}", e);
+                    state
+                        .event_bus
+                        .emit(EventPayload::library_scan("failed", None, None));
+                    Err(e)
+                }
+            }
+        })
+}
+
+/// Cancel the running library scan
+///
+/// The scan stops before the next file it lists or reads, and its job ends cancelled
+/// with a `library_scan` event of status `cancelled`. Nothing it read is merged: the
+/// library keeps the tracks it had before the scan started. A first scan started by
+/// `POST /api/setup/initialize` stops after the directory it is reading, keeping the
+/// directories already scanned.
+#[utoipa::path(
+    post,
+    path = "/api/library/scan/cancel",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Scan cancelling", body = ApiResponseString),
+        (status = 404, description = "No scan is running", body = ApiErrorResponse),
+    )
+)]
+async fn cancel_library_scan(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let cancelled = match state.jobs.active(JobKind::Scan) {
+        Some(job) => state.jobs.cancel(job.id).is_ok(),
+        None => state.library.cancel_scan(),
+    };
+    if !cancelled {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "No library scan is running",
+        ));
+    }
+
+    info!("Library scan cancellation requested");
+    Ok(Json(ApiResponse::success(
+        "Library scan cancelling".to_string(),
+    )))
+}
+
+/// Get the status of the latest library scan
+///
+/// Tells whether the scan started by the last `POST /api/library/scan` is still
+/// running and, once it is done, how many tracks the library has or why it failed.
+#[utoipa::path(
+    get,
+    path = "/api/library/scan/status",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Status of the latest scan", body = ApiResponseScanJob),
+    )
+)]
+async fn get_scan_status(State(state): State<AppState>) -> Json<ApiResponse<ScanJobStatus>> {
+    let status = state
+        .jobs
+        .latest(JobKind::Scan)
+        .map(|job| ScanJobStatus::from(&job))
+        .unwrap_or_default();
+    Json(ApiResponse::success(status))
+}
+
+/// Job list filters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobListQuery {
+    /// Only list jobs of this kind
+    pub kind: Option<JobKind>,
+    /// Only list jobs in this state
+    pub state: Option<JobState>,
+}
+
+/// List jobs
+///
+/// Running jobs and those finished within `api.job_history_minutes`, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/jobs",
+    tag = "Jobs",
+    params(JobListQuery),
+    responses(
+        (status = 200, description = "Jobs, newest first", body = ApiResponseJobs),
+    )
+)]
+async fn list_jobs(
+    State(state): State<AppState>,
+    Query(query): Query<JobListQuery>,
+) -> Json<ApiResponse<Vec<Job>>> {
+    let jobs = state
+        .jobs
+        .list()
+        .into_iter()
+        .filter(|job| query.kind.is_none_or(|kind| job.kind == kind))
+        .filter(|job| query.state.is_none_or(|job_state| job.state == job_state))
+        .collect();
+    Json(ApiResponse::success(jobs))
+}
+
+/// Get a job
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{id}",
+    tag = "Jobs",
+    params(("id" = u64, Path, description = "Job identifier", example = 7)),
+    responses(
+        (status = 200, description = "The job", body = ApiResponseJob),
+        (status = 404, description = "Job not found", body = ApiErrorResponse),
+    )
+)]
+async fn get_job(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<ApiResponse<Job>>, ApiError> {
+    let job = state.jobs.get(id).ok_or(JobError::NotFound(id))?;
+    Ok(Json(ApiResponse::success(job)))
+}
+
+/// Cancel a job
+///
+/// Asks a running verification or waveform precomputation to stop. The job ends
+/// `cancelled` shortly after, keeping what it did so far.
+#[utoipa::path(
+    post,
+    path = "/api/jobs/{id}/cancel",
+    tag = "Jobs",
+    params(("id" = u64, Path, description = "Job identifier", example = 7)),
+    responses(
+        (status = 200, description = "Cancellation requested", body = ApiResponseJob),
+        (status = 404, description = "Job not found", body = ApiErrorResponse),
+        (status = 409, description = "The job has finished or cannot be cancelled", body = ApiErrorResponse),
+    )
+)]
+async fn cancel_job(
+    State(state): State<AppState>,
+    Path(id): Path<u64>,
+) -> Result<Json<ApiResponse<Job>>, ApiError> {
+    let job = state.jobs.cancel(id)?;
+    info!("Cancellation of job {} requested", id);
+    Ok(Json(ApiResponse::success(job)))
+}
+
+/// Verify the integrity of every file in the library
+///
+/// Starts a background job that decodes each file to detect corruption or truncation.
+/// Progress is reported through `library_verify` events. Files verified before are
+/// skipped unless they changed since. Returns 409 if a verification is already running.
+#[utoipa::path(
+    post,
+    path = "/api/library/verify",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Verification started", body = ApiResponseString),
+        (status = 409, description = "A verification is already running", body = ApiErrorResponse),
+    )
+)]
+async fn verify_library(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let tracks = state.library.get_tracks();
+    let params = serde_json::json!({ "tracks": tracks.len() });
+    let verify_state = state.clone();
+    let started = state.jobs.start(JobKind::Verify, params, move |handle| {
+        let state = verify_state;
+        let verification_job = state.verification_job.clone();
+        let (finished, outcome) = tokio::sync::oneshot::channel();
+        let mut finished = Some(finished);
+        let progress_handle = handle.clone();
+        let started = verification_job.start(tracks, state.stats_store.clone(), move |progress| {
+            state.event_bus.emit(EventPayload::library_verify(
+                progress.status.clone(),
+                progress.processed,
+                progress.total,
+                progress.skipped,
+                progress.failed,
+            ));
+            progress_handle.progress(progress.processed, Some(progress.total), None);
+            if matches!(progress.status.as_str(), "completed" | "cancelled") {
+                if let Some(finished) = finished.take() {
+                    let _ = finished.send(progress.clone());
+                }
+            }
+        });
+        async move {
+            if !started {
+                anyhow::bail!("A library verification is already running");
+            }
+            let mut outcome = outcome;
+            let mut cancelling = false;
+            loop {
+                tokio::select! {
+                    progress = &mut outcome => {
+                        return progress.map_err(|_| anyhow::anyhow!("the verification failed"));
+                    }
+                    _ = handle.cancelled(), if !cancelling => {
+                        verification_job.cancel();
+                        cancelling = true;
+                    }
+                }
+            }
+        }
+    });
+
+    if started.is_err() {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "A library verification is already running",
+        ));
+    }
+
+    info!("Library verification started");
+    Ok(Json(ApiResponse::success(
+        "Library verification started".to_string(),
+    )))
+}
+
+/// Cancel a running library verification
+#[utoipa::path(
+    post,
+    path = "/api/library/verify/cancel",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Verification cancelling", body = ApiResponseString),
+        (status = 404, description = "No verification is running", body = ApiErrorResponse),
+    )
+)]
+async fn cancel_library_verification(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let cancelled = match state.jobs.active(JobKind::Verify) {
+        Some(job) => state.jobs.cancel(job.id).is_ok(),
+        None => state.verification_job.cancel(),
+    };
+    if !cancelled {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "No library verification is running",
+        ));
+    }
+
+    info!("Library verification cancellation requested");
+    Ok(Json(ApiResponse::success(
+        "Library verification cancelling".to_string(),
+    )))
+}
+
+/// A file that failed its last integrity check
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CorruptTrackResponse {
+    /// Full file path
+    #[schema(example = "/path/to/track.mp3")]
+    pub path: String,
+    /// Library track, if the file is still part of the library
+    pub track: Option<TrackResponse>,
+    /// Integrity status: corrupt or unreadable
+    #[schema(value_type = IntegrityStatus, example = "corrupt")]
+    pub integrity: String,
+    /// Failure details
+    #[schema(example = "truncated: decoded 1024 of 441000 frames")]
+    pub error: Option<String>,
+    /// When the file was checked (RFC3339)
+    pub checked_at: String,
+}
+
+impl CorruptTrackResponse {
+    fn new(path: &FsPath, record: IntegrityRecord, track: Option<&Track>) -> Self {
+        Self {
+            path: path.to_string_lossy().to_string(),
+            track: track.map(TrackResponse::from),
+            integrity: record.status.as_str().to_string(),
+            error: record.error,
+            checked_at: serde_rfc3339::format(&record.checked_at),
+        }
+    }
+}
+
+/// List files that failed integrity verification
+#[utoipa::path(
+    get,
+    path = "/api/library/tracks/corrupt",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Files that failed verification", body = ApiResponseCorruptTracks),
+    )
+)]
+async fn get_corrupt_tracks(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<Vec<CorruptTrackResponse>>>, ApiError> {
+    let failures = state
+        .stats_store
+        .integrity_failures()
+        .into_iter()
+        .map(|(path, record)| {
+            let track = state.library.get_track_by_path(&path);
+            CorruptTrackResponse::new(&path, record, track.as_ref())
+        })
+        .collect();
+
+    Ok(Json(ApiResponse::success(failures)))
+}
+
+/// Search tracks
+///
+/// Searches the library for tracks matching the query string.
+/// Searches in track title, artist, and album fields. Terms such as `format:flac`
+/// (codec or file extension) and `samplerate:>48000` (also `<`, `<=`, `>=`, `=` and
+/// `k`/`khz` units, as in `samplerate:96k`) filter by technical details.
+#[utoipa::path(
+    get,
+    path = "/api/library/search",
+    tag = "Library",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matching tracks", body = ApiResponseTracks),
+        (status = 503, description = "The library is still loading and the client asked for `Prefer: handling=strict`", body = ApiErrorResponse),
+    )
+)]
+async fn search_tracks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<SearchQuery>,
+) -> Result<Json<ApiResponse<Vec<TrackResponse>>>, ApiError> {
+    let loading = library_loading(&state, &headers)?;
+    let tracks = state.library.search_tracks(&query.q);
+    let track_responses: Vec<TrackResponse> = tracks
+        .iter()
+        .map(|track| TrackResponse::with_stats(track, &state.stats_store))
+        .collect();
+    Ok(Json(
+        ApiResponse::success(track_responses).with_loading(loading),
+    ))
+}
+
+/// Suggest search completions
+///
+/// Returns distinct artist, album and title strings matching what has been typed so
+/// far, grouped by type in the requested order. Strings starting with the query come
+/// first, then ones containing it; case and diacritics are ignored. Served from an
+/// index, so it is cheap enough to call on every keystroke.
+#[utoipa::path(
+    get,
+    path = "/api/library/suggest",
+    tag = "Library",
+    params(SuggestQuery),
+    responses(
+        (status = 200, description = "Suggestions grouped by type", body = ApiResponseSuggestions),
+        (status = 400, description = "Unknown suggestion type"),
+        (status = 503, description = "The library is still loading and the client asked for `Prefer: handling=strict`", body = ApiErrorResponse),
+    )
+)]
+async fn suggest_library(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<SuggestQuery>,
+) -> Result<Json<ApiResponse<Vec<SuggestionGroup>>>, ApiError> {
+    let loading = library_loading(&state, &headers)?;
+    let mut types: Vec<SuggestionType> = Vec::new();
+    match query
+        .types
+        .as_deref()
+        .filter(|types| !types.trim().is_empty())
+    {
+        Some(list) => {
+            for name in list.split(',').filter(|name| !name.trim().is_empty()) {
+                let kind = name
+                    .parse::<SuggestionType>()
+                    .map_err(|message| ApiError::new(StatusCode::BAD_REQUEST, message))?;
+                if !types.contains(&kind) {
+                    types.push(kind);
+                }
+            }
+        }
+        None => types.extend(SuggestionType::ALL),
+    }
+    let limit = query.limit.unwrap_or(8).min(50);
+
+    let groups = state.library.suggest(&query.q, &types, limit);
+    Ok(Json(ApiResponse::success(groups).with_loading(loading)))
+}
+
+/// Search albums
+///
+/// Aggregates albums from the library and returns one sorted page of matching entries
+/// with cached artwork information, along with the number of matching albums.
+#[utoipa::path(
+    get,
+    path = "/api/library/albums/search",
+    tag = "Library",
+    params(AlbumSearchQuery),
+    responses(
+        (status = 200, description = "Matching albums", body = ApiResponseAlbums),
+    )
+)]
+async fn search_albums(
+    State(state): State<AppState>,
+    Query(query): Query<AlbumSearchQuery>,
+) -> Result<Json<ApiResponse<AlbumPageResponse>>, ApiError> {
+    let search = AlbumSearch {
+        query: query.q,
+        sort: query.sort.unwrap_or_default(),
+        offset: query.offset.unwrap_or(0),
+        limit: query.limit,
+        musicbrainz_release_id: None,
+    };
+    let page = state
+        .album_service
+        .search_albums_page(state.library.as_ref(), &search)
+        .await;
+
+    let album_responses: Vec<AlbumResponse> =
+        page.albums.into_iter().map(AlbumResponse::from).collect();
+
+    Ok(Json(ApiResponse::success(AlbumPageResponse {
+        total: page.total,
+        offset: search.offset,
+        albums: album_responses,
+        sections: page.sections,
+    })))
+}
+
+/// List artists
+///
+/// Lists the artists of the library sorted for the configured `library.sort_locale`,
+/// so that accented names sort with their base letter, along with their letter
+/// sections.
+#[utoipa::path(
+    get,
+    path = "/api/library/artists",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Artists in collation order", body = ApiResponseArtists),
+        (status = 503, description = "The library is still loading and the client asked for `Prefer: handling=strict`", body = ApiErrorResponse),
+    )
+)]
+async fn get_artists(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<ArtistListResponse>>, ApiError> {
+    let loading = library_loading(&state, &headers)?;
+    let artists = state.library.get_artists();
+    let sections = state
+        .library
+        .collator()
+        .sections(artists.iter().map(String::as_str));
+    Ok(Json(
+        ApiResponse::success(ArtistListResponse { artists, sections }).with_loading(loading),
+    ))
+}
+
+/// Browse classical works
+///
+/// Groups tracks with a composer tag by composer and work, using WORK/MOVEMENT tags
+/// or "Work: Movement" style titles, so that movements spread across tracks and
+/// recordings by different performers are listed together.
+#[utoipa::path(
+    get,
+    path = "/api/library/works",
+    tag = "Library",
+    params(WorksQuery),
+    responses(
+        (status = 200, description = "Works grouped by composer", body = ApiResponseWorks),
+    )
+)]
+async fn get_works(
+    State(state): State<AppState>,
+    Query(query): Query<WorksQuery>,
+) -> Json<ApiResponse<Vec<WorkResponse>>> {
+    let composer = query
+        .composer
+        .as_deref()
+        .map(str::trim)
+        .filter(|composer| !composer.is_empty());
+    let works = group_works(&state.library.get_tracks(), composer)
+        .into_iter()
+        .map(WorkResponse::from)
+        .collect();
+
+    Json(ApiResponse::success(works))
+}
+
+/// List genres
+///
+/// Genre tags are grouped ignoring case and punctuation, and known spellings such as
+/// "Hip-Hop/Rap" or "RnB" are listed under their canonical names, extended by
+/// `library.genre_aliases`. Other genres take their most common spelling. The tags
+/// on the tracks are unchanged.
+#[utoipa::path(
+    get,
+    path = "/api/library/genres",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Genres by name", body = ApiResponseGenres),
+        (status = 503, description = "The library is still loading and the client asked for `Prefer: handling=strict`", body = ApiErrorResponse),
+    )
+)]
+async fn get_genres(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<GenreSummary>>>, ApiError> {
+    let loading = library_loading(&state, &headers)?;
+    let genres = state.library.genre_index().genres().to_vec();
+    Ok(Json(ApiResponse::success(genres).with_loading(loading)))
+}
+
+/// List raw genre tags
+///
+/// Returns each genre tag value as found in the files, with its track count and the
+/// genre it is listed under.
+#[utoipa::path(
+    get,
+    path = "/api/library/genres/raw",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Genre tag values", body = ApiResponseRawGenres),
+        (status = 503, description = "The library is still loading and the client asked for `Prefer: handling=strict`", body = ApiErrorResponse),
+    )
+)]
+async fn get_raw_genres(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<Vec<RawGenre>>>, ApiError> {
+    let loading = library_loading(&state, &headers)?;
+    let genres = state.library.genre_index().raw_genres();
+    Ok(Json(ApiResponse::success(genres).with_loading(loading)))
+}
+
+/// Rewrite genre tags to their canonical names
+///
+/// Writes the canonical genre, as listed by `GET /api/library/genres`, to every file
+/// whose genre tag differs from it. Files are written independently; ones in
+/// read-only directories or that could not be written are reported with the error.
+/// With `dry_run` the files are only listed.
+#[utoipa::path(
+    post,
+    path = "/api/library/genres/retag",
+    tag = "Library",
+    params(CleanupQuery),
+    responses(
+        (status = 200, description = "Files whose genre was, or would be, rewritten", body = ApiResponseGenreRetag),
+        (status = 409, description = "A library scan is in progress and `library.scan_conflict` is `reject`", body = ApiErrorResponse),
+        (status = 503, description = "The library is still loading", body = ApiErrorResponse),
+    )
+)]
+async fn retag_genres(
+    State(state): State<AppState>,
+    Query(query): Query<CleanupQuery>,
+) -> Result<Json<ApiResponse<Vec<GenreRetagFile>>>, ApiError> {
+    if !query.dry_run {
+        state.library.guard_mutation().await?;
+    }
+    let since = state.library.change_sequence();
+    if !state.library.is_ready() {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The library is still loading",
+        ));
+    }
+
+    let library = state.library.clone();
+    let files = tokio::task::spawn_blocking(move || library.retag_genres(query.dry_run))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let retagged = files.iter().filter(|file| file.error.is_none()).count();
+    if !query.dry_run && retagged > 0 {
+        info!("Rewrote the genre of {} file(s)", retagged);
+        emit_library_updated(&state, since);
+    }
+
+    Ok(Json(ApiResponse::success(files)))
+}
+
+/// Library-wide find-and-replace of a tag value
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct MetadataReplaceRequest {
+    pub field: ReplaceField,
+    /// Value, text or regular expression to find, depending on `match`
+    #[schema(example = "Unknown Artist ")]
+    pub find: String,
+    /// What to replace it with; empty removes exact matches from the tags
+    #[serde(default)]
+    #[schema(example = "Unknown Artist")]
+    pub replace: String,
+    #[serde(rename = "match", default)]
+    pub mode: ReplaceMatch,
+    /// Only report the files that would change
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Find and replace a tag value across the library
+///
+/// Rewrites `field` in every file whose value matches `find`: the whole value with
+/// `exact`, every occurrence with `substring`, or every match of a regular expression
+/// with `regex`, whose replacement may use groups such as `$1`. Regular expressions
+/// run in linear time and are limited in length and compiled size. Files are written
+/// independently; ones in read-only directories or that could not be written are
+/// reported with the error, and a single `library_updated` event covers the others.
+/// Runs as a `metadata_replace` job, reporting its progress and cancellable through
+/// `POST /api/jobs/{id}/cancel`; with `dry_run` the files are only listed.
+#[utoipa::path(
+    post,
+    path = "/api/library/metadata/replace",
+    tag = "Library",
+    request_body = MetadataReplaceRequest,
+    responses(
+        (status = 200, description = "Files whose tag was, or would be, rewritten", body = ApiResponseMetadataReplace),
+        (status = 400, description = "Nothing to find, or the pattern is too long or invalid", body = ApiErrorResponse),
+        (status = 409, description = "A find-and-replace is already running, or a library scan is and `library.scan_conflict` is `reject`", body = ApiErrorResponse),
+        (status = 503, description = "The library is still loading", body = ApiErrorResponse),
+    )
+)]
+async fn replace_metadata(
+    State(state): State<AppState>,
+    Json(request): Json<MetadataReplaceRequest>,
+) -> Result<Json<ApiResponse<Vec<MetadataReplaceFile>>>, ApiError> {
+    let replace = MetadataReplace::new(&request.find, &request.replace, request.mode)
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+    if !request.dry_run {
+        state.library.guard_mutation().await?;
+    }
+    let since = state.library.change_sequence();
+    if !state.library.is_ready() {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The library is still loading",
+        ));
+    }
+
+    let params = serde_json::to_value(&request).unwrap_or_default();
+    let (field, dry_run) = (request.field, request.dry_run);
+    let library = state.library.clone();
+    let files = state
+        .jobs
+        .run(JobKind::MetadataReplace, params, move |handle| async move {
+            let files = tokio::task::spawn_blocking(move || {
+                library.replace_metadata(
+                    field,
+                    &replace,
+                    dry_run,
+                    |done, total| {
+                        if done % jobs::JOB_PROGRESS_INTERVAL == 0 || done == total {
+                            handle.progress(done, Some(total), None);
+                        }
+                    },
+                    || handle.is_cancelled(),
+                )
+            })
+            .await?;
+            Ok(files)
+        })
+        .await?
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let rewritten = files.iter().filter(|file| file.error.is_none()).count();
+    if !dry_run && rewritten > 0 {
+        info!("Replaced {:?} values in {} file(s)", field, rewritten);
+        emit_library_updated(&state, since);
+    }
+
+    Ok(Json(ApiResponse::success(files)))
+}
+
+/// Import ratings and play counts from file tags
+///
+/// Reads ID3 POPM frames, `FMPS_Rating` and `FMPS_Playcount` tags and `RATING` tags
+/// of every track, as written by players such as Windows Media Player, MusicBee and
+/// foobar2000, and maps their ratings to 1–5 stars. Ratings are taken by tracks that
+/// have none, and play counts only where they are higher than the local ones.
+#[utoipa::path(
+    post,
+    path = "/api/library/import/tag-stats",
+    tag = "Library",
+    responses(
+        (status = 200, description = "How many tracks gained ratings and play counts", body = ApiResponseTagStatsImport),
+        (status = 503, description = "The library is still loading", body = ApiErrorResponse),
+    )
+)]
+async fn import_tag_stats(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<TagStatsImport>>, ApiError> {
+    if !state.library.is_ready() {
+        return Err(ApiError::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "The library is still loading",
+        ));
+    }
+
+    let library = state.library.clone();
+    let stats = state.stats_store.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        let report = crate::library::import_tag_stats(&library.get_tracks(), &stats);
+        if let Err(e) = stats.save() {
+            warn!("Failed to save imported track stats: {}", e);
+        }
+        report
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!(
+        "Imported {} rating(s) and {} play count(s) from tags of {} track(s)",
+        report.ratings, report.play_counts, report.scanned
+    );
+    Ok(Json(ApiResponse::success(report)))
+}
+
+/// List incomplete albums
+///
+/// Finds albums whose track numbers have gaps or whose TRACKTOTAL tag exceeds the
+/// tracks present, listing the missing track numbers. Albums without track numbers
+/// are skipped.
+#[utoipa::path(
+    get,
+    path = "/api/library/albums/incomplete",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Albums with missing tracks", body = ApiResponseIncompleteAlbums),
+    )
+)]
+async fn get_incomplete_albums(
+    State(state): State<AppState>,
+) -> Json<ApiResponse<Vec<IncompleteAlbumResponse>>> {
+    let albums = find_incomplete_albums(&state.library.get_tracks())
+        .into_iter()
+        .map(IncompleteAlbumResponse::from)
+        .collect();
+
+    Json(ApiResponse::success(albums))
+}
+
+/// List duplicate tracks
+///
+/// Groups tracks sharing a normalized title and primary artist whose durations are
+/// within a few seconds of each other, such as an album's FLAC rip next to an older
+/// MP3 of the same song.
+#[utoipa::path(
+    get,
+    path = "/api/library/duplicates",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Groups of duplicate tracks", body = ApiResponseDuplicateGroups),
+    )
+)]
+async fn get_duplicate_groups(
+    State(state): State<AppState>,
+) -> Json<ApiResponse<Vec<DuplicateGroup>>> {
+    Json(ApiResponse::success(find_duplicate_groups(
+        &state.library.get_tracks(),
+    )))
+}
+
+/// Compare the copies of a duplicate group
+///
+/// Reports each copy's container format, duration, file size, effective bitrate,
+/// sample rate, bit depth and tag completeness, and flags the copy recommended to
+/// keep per `library.duplicates`.
+#[utoipa::path(
+    get,
+    path = "/api/library/duplicates/{group_id}/report",
+    tag = "Library",
+    params(("group_id" = String, Path, description = "Duplicate group identifier", example = "3a7bd3e2360a3d29eea436fcfb7e44c735d117c42d1c1835420b6b9942dd4f1b")),
+    responses(
+        (status = 200, description = "Comparison of the copies", body = ApiResponseDuplicateReport),
+        (status = 404, description = "Duplicate group not found", body = ApiErrorResponse),
+    )
+)]
+async fn get_duplicate_report(
+    State(state): State<AppState>,
+    Path(group_id): Path<String>,
+) -> Result<Json<ApiResponse<DuplicateReportResponse>>, ApiError> {
+    let (report, _) = duplicate_report(&state, &group_id).await?;
+    Ok(Json(ApiResponse::success(report)))
+}
+
+/// Resolve a duplicate group
+///
+/// Deletes every copy but the keeper (the recommended one unless `keeper_id` is
+/// given) the way track deletion does, so with `library.delete_mode = "trash"` they
+/// can be restored. Playlist entries of the deleted copies are moved to the keeper,
+/// adding their play counts to its entry.
+#[utoipa::path(
+    post,
+    path = "/api/library/duplicates/{group_id}/resolve",
+    tag = "Library",
+    params(("group_id" = String, Path, description = "Duplicate group identifier", example = "3a7bd3e2360a3d29eea436fcfb7e44c735d117c42d1c1835420b6b9942dd4f1b")),
+    request_body = ResolveDuplicatesRequest,
+    responses(
+        (status = 200, description = "Duplicates removed", body = ApiResponseDuplicateResolution),
+        (status = 400, description = "The keeper is not in the group", body = ApiErrorResponse),
+        (status = 403, description = "Deleting is disabled or a copy is read-only", body = ApiErrorResponse),
+        (status = 404, description = "Duplicate group not found", body = ApiErrorResponse),
+        (status = 409, description = "A library scan is in progress and `library.scan_conflict` is `reject`", body = ApiErrorResponse),
+        (status = 500, description = "A file could not be deleted", body = ApiErrorResponse),
+    )
+)]
+async fn resolve_duplicates(
+    State(state): State<AppState>,
+    Path(group_id): Path<String>,
+    Json(request): Json<ResolveDuplicatesRequest>,
+) -> Result<Json<ApiResponse<DuplicateResolutionResponse>>, ApiError> {
+    state.library.guard_mutation().await?;
+    let since = state.library.change_sequence();
+    let (report, tracks) = duplicate_report(&state, &group_id).await?;
+    let keeper_id = match request.keeper_id {
+        Some(keeper_id) if report.group.track_ids.contains(&keeper_id) => keeper_id,
+        Some(keeper_id) => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Track {} is not in duplicate group {}", keeper_id, group_id),
+            ))
+        }
+        None => report
+            .recommended_keeper
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)?,
+    };
+
+    let discarded: Vec<Track> = tracks
+        .into_iter()
+        .filter(|track| track.id != keeper_id)
+        .collect();
+    if state.delete_mode == DeleteMode::Forbid {
+        return Err(ApiError::new(
+            StatusCode::FORBIDDEN,
+            "Deleting files is disabled by library.delete_mode",
+        ));
+    }
+    for track in &discarded {
+        state.library.read_only().check(&track.metadata.file_path)?;
+    }
+
+    // Playlists are moved first so no entry is left pointing at a deleted copy
+    let discarded_ids: Vec<String> = discarded.iter().map(|track| track.id.clone()).collect();
+    let playlists_updated = state
+        .playlist_manager
+        .replace_tracks(&discarded_ids, &keeper_id);
+
+    // The discarded copies' plays and ratings carry over to the keeper
+    let keeper_path = state
+        .library
+        .get_track(&keeper_id)
+        .map(|track| track.metadata.file_path);
+    let mut removed = Vec::with_capacity(discarded.len());
+    let mut failure = None;
+    for track in &discarded {
+        if let Some(keeper_path) = &keeper_path {
+            state
+                .stats_store
+                .merge_duplicate(&track.metadata.file_path, keeper_path);
+        }
+        match discard_track(&state, track).await {
+            Ok(deleted) => removed.push(deleted),
+            Err(error) => {
+                failure = Some(error);
+                break;
+            }
+        }
+    }
+    if !removed.is_empty() {
+        if let Err(e) = state.stats_store.save() {
+            warn!("Failed to save the merged track stats: {}", e);
+        }
+        emit_library_updated(&state, since);
+    }
+    if let Some(error) = failure {
+        return Err(error);
+    }
+
+    info!(
+        "Resolved duplicate group {}: kept {}, removed {} copies",
+        group_id,
+        keeper_id,
+        removed.len()
+    );
+    Ok(Json(ApiResponse::success(DuplicateResolutionResponse {
+        keeper_id,
+        removed,
+        playlists_updated,
+    })))
+}
+
+/// Compare the tracks of a duplicate group, probing their files off the async runtime
+async fn duplicate_report(
+    state: &AppState,
+    group_id: &str,
+) -> Result<(DuplicateReportResponse, Vec<Track>), ApiError> {
+    let group = find_duplicate_groups(&state.library.get_tracks())
+        .into_iter()
+        .find(|group| group.id == group_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let tracks: Vec<Track> = group
+        .track_ids
+        .iter()
+        .filter_map(|id| state.library.get_track(id))
+        .collect();
+
+    let preferences = state.duplicate_preferences.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut candidates: Vec<DuplicateCandidate> =
+            tracks.iter().map(DuplicateCandidate::measure).collect();
+        let keeper = recommend_keeper(&candidates, &preferences);
+        if let Some(index) = keeper {
+            candidates[index].recommended = true;
+        }
+        let report = DuplicateReportResponse {
+            recommended_keeper: keeper.map(|index| candidates[index].track_id.clone()),
+            group,
+            tracks: candidates,
+        };
+        (report, tracks)
+    })
+    .await
+    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+}
+
+/// Read back the event log
+///
+/// Returns the most recent entries of the `events.log_file` log, including rotated
+/// files, so past events can be inspected without shell access.
+#[utoipa::path(
+    get,
+    path = "/api/events/log",
+    tag = "Events",
+    params(EventLogQuery),
+    responses(
+        (status = 200, description = "Logged events", body = ApiResponseEventLog),
+        (status = 400, description = "Invalid `since` value", body = ApiErrorResponse),
+        (status = 404, description = "The event log is disabled", body = ApiErrorResponse),
+        (status = 500, description = "The log could not be read", body = ApiErrorResponse),
+    )
+)]
+async fn get_event_log(
+    State(state): State<AppState>,
+    Query(query): Query<EventLogQuery>,
+) -> Result<Json<ApiResponse<EventLogResponse>>, ApiError> {
+    let event_log = state.event_log.clone().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "The event log is disabled; set events.log_file to enable it",
+        )
+    })?;
+    let since = query
+        .since
+        .as_deref()
+        .filter(|since| !since.trim().is_empty())
+        .map(|since| parse_since(since, Utc::now()))
+        .transpose()
+        .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e.to_string()))?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_EVENT_LOG_LIMIT)
+        .min(MAX_EVENT_LOG_LIMIT);
+
+    let entries = tokio::task::spawn_blocking(move || {
+        event_log
+            .read_entries(since, limit)
+            .map(|entries| (entries, event_log.dropped()))
+    })
+    .await
+    .map_err(|e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let (entries, dropped) = entries.map_err(|e| {
+        error!("Failed to read the event log: {}", e);
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    })?;
+
+    Ok(Json(ApiResponse::success(EventLogResponse {
+        entries,
+        dropped,
+    })))
+}
+
+/// Subscribe to backend events (playback, library updates) using WebSocket.
+///
+/// `types` limits the stream, including the initial snapshot, to the listed event
+/// types. Unknown type names close the connection with a policy violation frame.
+#[utoipa::path(
+    get,
+    path = "/api/events/ws",
+    tag = "Events",
+    params(EventsQuery),
+    responses(
+        (status = 101, description = "Switching to WebSocket; every message is an `EventMessage`", body = EventMessage),
+    )
+)]
+async fn events_ws_handler(
+    ws: WebSocketUpgrade,
+    Query(query): Query<EventsQuery>,
+    State(state): State<AppState>,
+) -> Response {
+    let filter = EventFilter::parse(query.types.as_deref().unwrap_or_default());
+
+    ws.on_upgrade(move |socket| async move {
+        match filter {
+            Ok(filter) => handle_events_socket(socket, state, filter).await,
+            Err(error) => reject_events_socket(socket, error.to_string()).await,
+        }
+    })
+}
+
+async fn reject_events_socket(mut socket: WebSocket, reason: String) {
+    // Close reasons are limited to 123 bytes.
+    let mut end = reason.len().min(123);
+    while !reason.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    let frame = CloseFrame {
+        code: close_code::POLICY,
+        reason: reason[..end].to_string().into(),
+    };
+    let _ = socket.send(Message::Close(Some(frame))).await;
+}
+
+async fn handle_events_socket(mut socket: WebSocket, state: AppState, filter: EventFilter) {
+    // Subscribe before sending the snapshot so no event falls in between.
+    let mut receiver = state.event_bus.subscribe();
+
+    if let Err(err) = send_initial_events(&mut socket, &state, &filter).await {
+        tracing::warn!("Failed to send initial event snapshot: {}", err);
+    }
+
+    loop {
+        tokio::select! {
+            event = receiver.recv() => {
+                match event {
+                    Ok(message) if !filter.accepts(&message.payload) => {}
+                    Ok(message) => {
+                        match serde_json::to_string(&message) {
+                            Ok(payload) => {
+                                if socket.send(Message::Text(payload)).await.is_err() {
+                                    break;
+                                }
+                            }
+                            Err(err) => tracing::warn!("Failed to serialise event message: {}", err),
+                        }
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::warn!("Event stream lagged, skipped {} events", skipped);
+                    }
+                }
+            }
+            socket_msg = socket.recv() => {
+                match socket_msg {
+                    Some(Ok(Message::Ping(payload))) => {
+                        if socket.send(Message::Pong(payload)).await.is_err() {
+                            break;
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => {
+                        // Ignore other incoming messages from the client
+                    }
+                    Some(Err(err)) => {
+                        tracing::warn!("WebSocket error: {}", err);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn send_initial_events(
+    socket: &mut WebSocket,
+    state: &AppState,
+    filter: &EventFilter,
+) -> Result<(), String> {
+    let current_state = state.audio_player.get_state();
+    let track_path = state.audio_player.get_current_track();
+    let (track_id, track_duration) = track_path
+        .as_deref()
+        .map(|path| lookup_track_metadata(state.library.as_ref(), FsPath::new(path)))
+        .unwrap_or((None, None));
+
+    let playback_payload = EventPayload::playback_state(
+        format!("{:?}", current_state).to_lowercase(),
+        track_path.clone(),
+        track_id,
+        Some(state.audio_player.get_volume()),
+        track_duration,
+    )
+    .with_queue_settings(
+        state.playback_queue.get_repeat_mode().as_str(),
+        state.playback_queue.is_shuffle_enabled(),
+    )
+    .with_revision(state.revision.current());
+
+    let library_payload = EventPayload::library_changed(
+        state.library.track_count(),
+        state.library.change_sequence(),
+        None,
+    );
+
+    for payload in [playback_payload, library_payload] {
+        if filter.accepts(&payload) {
+            send_event(socket, payload).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn send_event(socket: &mut WebSocket, payload: EventPayload) -> Result<(), String> {
+    let message = EventMessage::new(payload);
+    let serialized = serde_json::to_string(&message).map_err(|err| err.to_string())?;
+    socket
+        .send(Message::Text(serialized))
+        .await
+        .map_err(|err| err.to_string())
+}
+
+/// Retrieve cached artwork for a specific album
+#[utoipa::path(
+    get,
+    path = "/api/library/albums/{id}/artwork",
+    tag = "Library",
+    params(("id" = String, Path, description = "Album identifier", example = "1f3870be274f6c49b3e31a0c6728957f")),
+    responses(
+        (status = 200, description = "Artwork image (JPEG, PNG or WebP)", content_type = "image/jpeg"),
+        (status = 304, description = "Artwork unchanged since the `If-None-Match` ETag"),
+        (status = 404, description = "No artwork cached", body = ApiErrorResponse),
+        (status = 500, description = "Artwork could not be read", body = ApiErrorResponse),
+    )
+)]
+async fn get_album_artwork(
+    State(state): State<AppState>,
+    Path(album_id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let path = state
+        .album_service
+        .cached_artwork_path(&album_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let response = image_response(&path, &headers).await.map_err(|error| {
+        let missing = error
+            .downcast_ref::<std::io::Error>()
+            .is_some_and(|error| error.kind() == std::io::ErrorKind::NotFound);
+        if missing {
+            // Deleted from the cache by someone else; stop advertising it.
+            state.album_service.forget_artwork(&album_id);
+            return StatusCode::NOT_FOUND;
+        }
+
+        error!("Failed to read artwork for album {}: {}", album_id, error);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(response)
+}
+
+/// Upload artwork for an album
+///
+/// Stores a JPEG, PNG or WebP image as the album's artwork, replacing the cached one,
+/// and announces it with an `album_artwork_updated` event. The response carries the
+/// new ETag of `GET /api/library/albums/{id}/artwork`.
+#[utoipa::path(
+    put,
+    path = "/api/library/albums/{id}/artwork",
+    tag = "Library",
+    params(("id" = String, Path, description = "Album identifier", example = "1f3870be274f6c49b3e31a0c6728957f")),
+    request_body(content = Vec<u8>, description = "Artwork image", content_type = "image/jpeg"),
+    responses(
+        (status = 200, description = "Artwork stored", body = ApiResponseAlbumArtwork),
+        (status = 400, description = "The body is not a JPEG, PNG or WebP image", body = ApiErrorResponse),
+        (status = 404, description = "Unknown album", body = ApiErrorResponse),
+        (status = 413, description = "The image is larger than `api.max_import_mb`", body = ApiErrorResponse),
+    )
+)]
+async fn upload_album_artwork(
+    State(state): State<AppState>,
+    Path(album_id): Path<String>,
+    body: axum::body::Bytes,
+) -> Result<Response, ApiError> {
+    if !is_image(&body) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Artwork must be a JPEG, PNG or WebP image",
+        ));
+    }
+    let known = state.album_service.get_override(&album_id).is_some()
+        || !state
+            .album_service
+            .album_tracks(&state.library, &album_id)
+            .is_empty();
+    if !known {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("Unknown album {}", album_id),
+        ));
+    }
+
+    let path = state
+        .album_service
+        .store_artwork(&album_id, &body)
+        .map_err(|error| {
+            error!("Failed to store artwork for album {}: {}", album_id, error);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to store artwork: {}", error),
+            )
+        })?;
+    let artwork_url = album_artwork_url(&album_id, &path);
+    Ok((
+        [(header::ETAG, image_etag(&body))],
+        Json(ApiResponse::success(AlbumArtworkResponse {
+            album_id,
+            artwork_url,
+        })),
+    )
+        .into_response())
+}
+
+/// Retrieve an image of an artist
+///
+/// Uses an `artist.jpg` from the artist's album folders when present, otherwise the
+/// configured remote provider. Featured artists are ignored when matching the name.
+#[utoipa::path(
+    get,
+    path = "/api/library/artists/{name}/image",
+    tag = "Library",
+    params(("name" = String, Path, description = "Artist name", example = "Queen")),
+    responses(
+        (status = 200, description = "Artist image (JPEG, PNG or WebP)", content_type = "image/jpeg"),
+        (status = 304, description = "Image unchanged since the `If-None-Match` ETag"),
+        (status = 404, description = "No image found", body = ApiErrorResponse),
+        (status = 500, description = "Image could not be read", body = ApiErrorResponse),
+    )
+)]
+async fn get_artist_image(
+    State(state): State<AppState>,
+    Path(artist): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let path = state
+        .album_service
+        .resolve_artist_image(&state.library, &artist)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let response = image_response(&path, &headers).await.map_err(|error| {
+        error!("Failed to read image for artist {}: {}", artist, error);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(response)
+}
+
+/// Serve a cached image, answering conditional requests with 304 Not Modified.
+async fn image_response(path: &FsPath, headers: &HeaderMap) -> Result<Response> {
+    let bytes = fs::read(path).await?;
+    let etag = image_etag(&bytes);
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.split(',').any(|tag| tag.trim() == etag));
+
+    let builder = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, "no-cache");
+
+    let response = if not_modified {
+        builder
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())?
+    } else {
+        builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, image_content_type(&bytes))
+            .body(Body::from(bytes))?
+    };
+
+    Ok(response)
+}
+
+/// ETag of an image, derived from its content
+fn image_etag(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+
+    let digest = format!("{:x}", Sha256::digest(bytes));
+    format!("\"{}\"", &digest[..32])
+}
+
+fn is_image(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0xFF, 0xD8]) || image_content_type(bytes) != "image/jpeg"
+}
+
+fn image_content_type(bytes: &[u8]) -> &'static str {
+    if bytes.starts_with(&[0x89, b'P', b'N', b'G']) {
+        "image/png"
+    } else if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        "image/webp"
+    } else {
+        "image/jpeg"
+    }
+}
+
+/// Retrieve the manual override (if any) for an album
+#[utoipa::path(
+    get,
+    path = "/api/library/albums/{id}/manual",
+    tag = "Library",
+    params(("id" = String, Path, description = "Album identifier", example = "1f3870be274f6c49b3e31a0c6728957f")),
+    responses(
+        (status = 200, description = "Manual override", body = ApiResponseAlbumOverride),
+        (status = 404, description = "No manual override stored", body = ApiErrorResponse),
+    )
+)]
+async fn get_album_manual_override(
+    State(state): State<AppState>,
+    Path(album_id): Path<String>,
+) -> Result<Json<ApiResponse<AlbumOverrideResponse>>, ApiError> {
+    match state.album_service.get_override(&album_id) {
+        Some(record) => Ok(Json(ApiResponse::success(record.into()))),
+        None => Err(StatusCode::NOT_FOUND.into()),
+    }
+}
+
+/// Create or update the manual override for an album and refresh metadata/artwork
+#[utoipa::path(
+    put,
+    path = "/api/library/albums/{id}/manual",
+    tag = "Library",
+    params(("id" = String, Path, description = "Album identifier", example = "1f3870be274f6c49b3e31a0c6728957f")),
+    request_body = ManualAlbumUpdateRequest,
+    responses(
+        (status = 200, description = "Updated manual override", body = ApiResponseAlbumOverride),
+        (status = 400, description = "Override could not be stored", body = ApiErrorResponse),
+    )
+)]
+async fn set_album_manual_override(
+    State(state): State<AppState>,
+    Path(album_id): Path<String>,
+    Json(payload): Json<ManualAlbumUpdateRequest>,
+) -> Result<Json<ApiResponse<AlbumOverrideResponse>>, ApiError> {
+    let update = ManualAlbumUpdate {
+        title: payload.title,
+        primary_artist: payload.primary_artist,
+        search_album: payload.search_album,
+        search_artist: payload.search_artist,
+        refresh_artwork: payload.refresh_artwork,
+        disambiguation: payload.disambiguation,
+        musicbrainz_release_id: state
+            .album_service
+            .album_tracks(state.library.as_ref(), &album_id)
+            .into_iter()
+            .find_map(|track| track.metadata.musicbrainz.release_id),
+    };
+
+    match state
+        .album_service
+        .set_manual_override(&album_id, update)
+        .await
+    {
+        Ok(record) => Ok(Json(ApiResponse::success(record.into()))),
+        Err(error) => {
+            error!(
+                "Failed to set manual override for album {}: {}",
+                album_id, error
+            );
+            Err(StatusCode::BAD_REQUEST.into())
+        }
+    }
+}
+
+/// Bulk edit the tags of every track in an album
+///
+/// Writes the given fields to each file, refreshes the library and moves manual overrides
+/// and cached artwork when the album identifier changes. Files are updated independently
+/// and the response reports the outcome for each of them.
+#[utoipa::path(
+    post,
+    path = "/api/library/albums/{id}/edit",
+    tag = "Library",
+    params(("id" = String, Path, description = "Album identifier", example = "1f3870be274f6c49b3e31a0c6728957f")),
+    request_body = AlbumEditRequest,
+    responses(
+        (status = 200, description = "Per-file edit report", body = ApiResponseAlbumEdit),
+        (status = 400, description = "Edit could not be applied", body = ApiErrorResponse),
+        (status = 403, description = "Tracks of the album are in a read-only library directory", body = ApiErrorResponse),
+        (status = 404, description = "Unknown album", body = ApiErrorResponse),
+        (status = 409, description = "A library scan is in progress and `library.scan_conflict` is `reject`", body = ApiErrorResponse),
+    )
+)]
+async fn edit_album(
+    State(state): State<AppState>,
+    Path(album_id): Path<String>,
+    Json(payload): Json<AlbumEditRequest>,
+) -> Result<Json<ApiResponse<AlbumEditResponse>>, ApiError> {
+    state.library.guard_mutation().await?;
+    let since = state.library.change_sequence();
+    let update = TrackTagUpdate {
+        title: None,
+        artist: payload.artist,
+        album_artist: payload.album_artist,
+        album: payload.title,
+        genre: payload.genre,
+        year: payload.year,
+    };
+
+    // Tags are written with blocking file IO
+    let library = state.library.clone();
+    let album_service = state.album_service.clone();
+    let edited_album_id = album_id.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        album_service.edit_album(library.as_ref(), &edited_album_id, update)
+    })
+    .await
+    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
+    .map_err(|error| {
+        if let Some(error) = error.downcast_ref::<ReadOnlyError>() {
+            return ApiError::new(StatusCode::FORBIDDEN, error.to_string());
+        }
+        error!("Failed to edit album {}: {}", album_id, error);
+        StatusCode::BAD_REQUEST.into()
+    })?;
+
+    if report.files.is_empty() {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    info!(
+        "Album {} edited: {} updated, {} failed",
+        album_id,
+        report.succeeded(),
+        report.failed()
+    );
+
+    emit_library_updated(&state, since);
+
+    Ok(Json(ApiResponse::success(report.into())))
+}
+
+/// Export all manual album overrides in JSON or YAML formats
+#[utoipa::path(
+    get,
+    path = "/api/library/albums/manual/export",
+    tag = "Library",
+    params(AlbumExportQuery),
+    responses(
+        (status = 200, description = "Manual overrides as JSON or YAML", body = Vec<AlbumOverrideRecord>, content_type = ["application/json", "application/x-yaml"]),
+        (status = 400, description = "Unsupported export format", body = ApiErrorResponse),
+        (status = 500, description = "Export failed", body = ApiErrorResponse),
+    )
+)]
+async fn export_album_overrides(
+    State(state): State<AppState>,
+    Query(query): Query<AlbumExportQuery>,
+) -> Result<Response, ApiError> {
+    let format_string = query
+        .format
+        .as_deref()
+        .unwrap_or("json")
+        .trim()
+        .to_lowercase();
+
+    let (format, content_type) = match format_string.as_str() {
+        "yaml" | "yml" => (AlbumExportFormat::Yaml, "application/x-yaml"),
+        "json" => (AlbumExportFormat::Json, "application/json"),
+        other => {
+            error!("Unsupported export format requested: {}", other);
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!("Unsupported export format '{}'", other),
+            ));
+        }
+    };
+
+    let payload = state
+        .album_service
+        .export_overrides(format)
+        .map_err(|error| {
+            error!("Failed to export album overrides: {}", error);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .body(Body::from(payload))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(response)
+}
+
+/// Delivery statistics of a configured webhook
+#[derive(Debug, Serialize, ToSchema)]
+pub struct WebhookStatusResponse {
+    /// Receiver URL
+    #[schema(example = "http://homeassistant.local:8123/api/webhook/hexendrum")]
+    pub url: String,
+    /// Event types delivered to the hook; empty means all events
+    pub events: Vec<String>,
+    /// Events accepted by the receiver
+    pub delivered: u64,
+    /// Events that could not be delivered after all retries
+    pub failed: u64,
+    /// Events dropped because the receiver could not keep up
+    pub dropped: u64,
+}
+
+impl From<WebhookStatus> for WebhookStatusResponse {
+    fn from(status: WebhookStatus) -> Self {
+        Self {
+            url: status.url,
+            events: status.events,
+            delivered: status.delivered,
+            failed: status.failed,
+            dropped: status.dropped,
+        }
+    }
+}
+
+/// List configured webhooks with their delivery counters
+#[utoipa::path(
+    get,
+    path = "/api/webhooks",
+    tag = "Webhooks",
+    responses(
+        (status = 200, description = "Configured webhooks", body = ApiResponseWebhooks),
+    )
+)]
+async fn get_webhooks(
+    State(state): State<AppState>,
+) -> Json<ApiResponse<Vec<WebhookStatusResponse>>> {
+    let hooks = state
+        .webhooks
+        .status()
+        .into_iter()
+        .map(WebhookStatusResponse::from)
+        .collect();
+    Json(ApiResponse::success(hooks))
+}
+
+/// Get library statistics
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LibraryStats {
+    /// Total number of tracks in library
+    #[schema(example = 150)]
+    pub total_tracks: usize,
+    /// Total number of unique artists
+    #[schema(example = 25)]
+    pub total_artists: usize,
+    /// Total number of unique albums
+    #[schema(example = 45)]
+    pub total_albums: usize,
+    /// Approximate cache size
+    #[schema(example = 150)]
+    pub cache_size: usize,
+}
+
+/// Get library statistics
+///
+/// Returns statistics about the music library including total tracks, artists, albums, and cache size.
+#[utoipa::path(
+    get,
+    path = "/api/library/stats",
+    tag = "Library",
+    responses(
+        (status = 200, description = "Library statistics", body = ApiResponseStats),
+        (status = 503, description = "The library is still loading and the client asked for `Prefer: handling=strict`", body = ApiErrorResponse),
+    )
+)]
+async fn get_library_stats(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<ApiResponse<LibraryStats>>, ApiError> {
+    let loading = library_loading(&state, &headers)?;
+    let total_tracks = state.library.track_count();
+    let artists = state.library.get_artists();
+    let albums = state.library.get_albums();
+
+    let stats = LibraryStats {
+        total_tracks,
+        total_artists: artists.len(),
+        total_albums: albums.len(),
+        cache_size: total_tracks, // Could be enhanced to check actual cache file size
+    };
+
+    Ok(Json(ApiResponse::success(stats).with_loading(loading)))
+}
+
+/// Get all playlists
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlaylistResponse {
+    /// Playlist unique identifier
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub id: String,
+    /// Playlist name
+    #[schema(example = "My Favorites")]
+    pub name: String,
+    /// Optional playlist description
+    #[schema(example = "Best tracks collection")]
+    pub description: Option<String>,
+    /// Number of tracks in playlist
+    #[schema(example = 25)]
+    pub track_count: usize,
+    /// Creation timestamp (RFC3339)
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub created_at: String,
+    /// Last modification timestamp (RFC3339)
+    #[schema(example = "2024-01-20T14:45:00Z")]
+    pub modified_at: String,
+    /// Order the tracks are queued in when the playlist is played
+    pub play_order: PlayOrder,
+    /// Repeat mode set when the playlist is played
+    pub default_repeat: Option<RepeatMode>,
+    /// Virtual folder path; `null` at the root
+    #[schema(example = "Workout/Running")]
+    pub folder: Option<String>,
+}
+
+impl From<PlaylistSummary> for PlaylistResponse {
+    fn from(playlist: PlaylistSummary) -> Self {
+        Self {
+            id: playlist.id,
+            name: playlist.name,
+            description: playlist.description,
+            track_count: playlist.track_count,
+            created_at: serde_rfc3339::format(&playlist.created_at),
+            modified_at: serde_rfc3339::format(&playlist.modified_at),
+            play_order: playlist.play_order,
+            default_repeat: playlist.default_repeat,
+            folder: playlist.folder,
+        }
+    }
+}
+
+/// A virtual playlist folder with its subfolders and playlists
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlaylistFolderResponse {
+    /// Last level of the path; empty for the root
+    #[schema(example = "Running")]
+    pub name: String,
+    /// Full path; empty for the root
+    #[schema(example = "Workout/Running")]
+    pub path: String,
+    /// Subfolders in natural order
+    pub folders: Vec<PlaylistFolderResponse>,
+    /// Playlists directly in the folder, in natural order of their names
+    pub playlists: Vec<PlaylistResponse>,
+}
+
+impl From<PlaylistFolder> for PlaylistFolderResponse {
+    fn from(folder: PlaylistFolder) -> Self {
+        Self {
+            name: folder.name,
+            path: folder.path,
+            folders: folder.folders.into_iter().map(Self::from).collect(),
+            playlists: folder
+                .playlists
+                .into_iter()
+                .map(PlaylistResponse::from)
+                .collect(),
+        }
+    }
+}
+
+/// Get all playlists
+///
+/// Returns a list of all playlists in the system.
+#[utoipa::path(
+    get,
+    path = "/api/playlists",
+    tag = "Playlists",
+    responses(
+        (status = 200, description = "All playlists", body = ApiResponsePlaylists),
+    )
+)]
+async fn get_playlists(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<Vec<PlaylistResponse>>>, ApiError> {
+    let responses: Vec<PlaylistResponse> = state
+        .playlist_manager
+        .playlist_summaries()
+        .into_iter()
+        .map(PlaylistResponse::from)
+        .collect();
+
+    Ok(Json(ApiResponse::success(responses)))
+}
+
+/// Get the playlist folder tree
+///
+/// Returns the root folder, holding the playlists without a folder and the folders
+/// in use. Folders and playlists are sorted naturally, so "Mix 2" comes before "Mix 10".
+#[utoipa::path(
+    get,
+    path = "/api/playlists/tree",
+    tag = "Playlists",
+    responses(
+        (status = 200, description = "The root playlist folder", body = ApiResponsePlaylistTree),
+    )
+)]
+async fn get_playlist_tree(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<PlaylistFolderResponse>>, ApiError> {
+    let tree = state.playlist_manager.playlist_tree();
+    Ok(Json(ApiResponse::success(PlaylistFolderResponse::from(
+        tree,
+    ))))
+}
+
+/// Playlist folder rename request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RenamePlaylistFolderRequest {
+    /// Folder to rename
+    #[schema(example = "Workout/Running")]
+    pub from: String,
+    /// New path of the folder; an empty string moves its contents to the root
+    #[schema(example = "Sport/Running")]
+    pub to: String,
+}
+
+/// Rename a playlist folder
+///
+/// Folders are virtual, so this moves every playlist in the folder or below it.
+/// Subfolders keep their place under the renamed folder.
+#[utoipa::path(
+    post,
+    path = "/api/playlists/folders/rename",
+    tag = "Playlists",
+    request_body = RenamePlaylistFolderRequest,
+    responses(
+        (status = 200, description = "Number of playlists moved", body = ApiResponseUsize),
+        (status = 400, description = "The root folder was given or the new path is too long", body = ApiErrorResponse),
+        (status = 404, description = "No playlist is in the folder", body = ApiErrorResponse),
+    )
+)]
+async fn rename_playlist_folder(
+    State(state): State<AppState>,
+    Json(request): Json<RenamePlaylistFolderRequest>,
+) -> Result<Json<ApiResponse<usize>>, ApiError> {
+    let to = playlist_folder(&request.to)?.unwrap_or_default();
+    let moved = state.playlist_manager.rename_folder(&request.from, &to)?;
+    if moved == 0 {
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "No playlist is in that folder",
+        ));
+    }
+    Ok(Json(ApiResponse::success(moved)))
+}
+
+/// Number of playlist entries returned when no limit is given
+const DEFAULT_PLAYLIST_PAGE_SIZE: usize = 100;
+
+/// Largest page of playlist entries returned at once
+const MAX_PLAYLIST_PAGE_SIZE: usize = 1000;
+
+/// Playlist tracks query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PlaylistTracksQuery {
+    /// Index of the first entry to return (defaults to 0)
+    #[param(example = 0)]
+    pub offset: Option<usize>,
+    /// Maximum number of entries to return (defaults to 100, at most 1000)
+    #[param(example = 100)]
+    pub limit: Option<usize>,
+}
+
+/// A playlist entry with its track
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlaylistTrackResponse {
+    /// Position of the entry in the playlist
+    #[schema(example = 0)]
+    pub position: usize,
+    /// Track identifier
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub track_id: String,
+    /// When the entry was added (RFC3339)
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub added_at: String,
+    /// Times the entry was played from the playlist
+    #[schema(example = 3)]
+    pub play_count: u32,
+    /// Note about the entry
+    #[schema(example = "This reminds me of the trip")]
+    pub note: Option<String>,
+    /// Whether the entry is pinned to the top of the playlist
+    pub pinned: bool,
+    /// The track, if it is still in the library
+    pub track: Option<TrackResponse>,
+}
+
+impl PlaylistTrackResponse {
+    fn new(state: &AppState, position: usize, entry: &PlaylistEntry) -> Self {
+        Self {
+            position,
+            track_id: entry.track_id.clone(),
+            added_at: serde_rfc3339::format(&entry.added_at),
+            play_count: entry.play_count,
+            note: entry.note.clone(),
+            pinned: entry.pinned,
+            track: state
+                .library
+                .get_track(&entry.track_id)
+                .map(|track| TrackResponse::with_stats(&track, &state.stats_store)),
+        }
+    }
+}
+
+/// A page of playlist entries
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlaylistTracksResponse {
+    /// Playlist identifier
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub playlist_id: String,
+    /// Number of entries in the playlist
+    #[schema(example = 20000)]
+    pub total: usize,
+    /// Index of the first entry of this page
+    #[schema(example = 0)]
+    pub offset: usize,
+    /// Page size used
+    #[schema(example = 100)]
+    pub limit: usize,
+    pub entries: Vec<PlaylistTrackResponse>,
+}
+
+/// Get the tracks of a playlist
+///
+/// Entries are returned in playlist order with pinned entries first, a page at a time.
+/// `position` is the stored position of each entry. Entries whose track has left the
+/// library are listed with `track` set to null.
+#[utoipa::path(
+    get,
+    path = "/api/playlists/{id}/tracks",
+    tag = "Playlists",
+    params(
+        ("id" = String, Path, description = "Playlist identifier", example = "550e8400-e29b-41d4-a716-446655440000"),
+        PlaylistTracksQuery
+    ),
+    responses(
+        (status = 200, description = "A page of playlist entries", body = ApiResponsePlaylistTracks),
+        (status = 404, description = "Playlist not found", body = ApiErrorResponse),
+    )
+)]
+async fn get_playlist_tracks(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<PlaylistTracksQuery>,
+) -> Result<Json<ApiResponse<PlaylistTracksResponse>>, ApiError> {
+    let playlist = state
+        .playlist_manager
+        .get_playlist(&id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let offset = query.offset.unwrap_or(0);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PLAYLIST_PAGE_SIZE)
+        .min(MAX_PLAYLIST_PAGE_SIZE);
+    let entries = playlist
+        .listed_entries()
+        .into_iter()
+        .skip(offset)
+        .take(limit)
+        .map(|(position, entry)| PlaylistTrackResponse::new(&state, position, entry))
+        .collect();
+
+    Ok(Json(ApiResponse::success(PlaylistTracksResponse {
+        playlist_id: playlist.id.clone(),
+        total: playlist.track_count(),
+        offset,
+        limit,
+        entries,
+    })))
+}
+
+/// Composition of a playlist
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PlaylistStatsResponse {
+    /// Playlist identifier
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub playlist_id: String,
+    /// Entries whose track is in the library; the figures below are of these
+    #[schema(example = 18)]
+    pub track_count: usize,
+    /// Entries skipped as their track has left the library
+    #[schema(example = 2)]
+    pub missing_tracks: usize,
+    /// Total duration in seconds
+    #[schema(example = 4210)]
+    pub total_duration: u64,
+    /// Average track duration in seconds; `null` when no duration is known
+    #[schema(example = 233)]
+    pub average_duration: Option<u64>,
+    /// Tracks per artist, most first
+    pub artists: Vec<NameCount>,
+    /// Tracks per canonical genre, most first
+    pub genres: Vec<NameCount>,
+    /// Tracks per decade of their year tag, oldest first
+    pub decades: Vec<DecadeCount>,
+    /// When the earliest entry was added, of all entries
+    #[schema(example = "2024-01-15T10:30:00Z")]
+    pub first_added: Option<String>,
+    /// When the latest entry was added, of all entries
+    #[schema(example = "2024-03-02T21:05:00Z")]
+    pub last_added: Option<String>,
+}
+
+/// Get the composition of a playlist
+///
+/// Joins the entries against the library to report the total and average duration
+/// and how the tracks spread over artists, genres and decades. Entries whose track
+/// has left the library are skipped and counted in `missing_tracks`; tracks without
+/// an artist, genre or year are left out of that breakdown.
+#[utoipa::path(
+    get,
+    path = "/api/playlists/{id}/stats",
+    tag = "Playlists",
+    params(
+        ("id" = String, Path, description = "Playlist identifier", example = "550e8400-e29b-41d4-a716-446655440000"),
+    ),
+    responses(
+        (status = 200, description = "Playlist composition", body = ApiResponsePlaylistStats),
+        (status = 404, description = "Playlist not found", body = ApiErrorResponse),
+    )
+)]
+async fn get_playlist_stats(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<ApiResponse<PlaylistStatsResponse>>, ApiError> {
+    let playlist = state
+        .playlist_manager
+        .get_playlist(&id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let (breakdown, missing_tracks) = state
+        .library
+        .breakdown(playlist.entries.iter().map(|entry| entry.track_id.as_str()));
+    let added = playlist.entries.iter().map(|entry| entry.added_at);
+
+    Ok(Json(ApiResponse::success(PlaylistStatsResponse {
+        playlist_id: playlist.id.clone(),
+        track_count: breakdown.track_count,
+        missing_tracks,
+        total_duration: breakdown.total_duration,
+        average_duration: breakdown.average_duration,
+        artists: breakdown.artists,
+        genres: breakdown.genres,
+        decades: breakdown.decades,
+        first_added: added.clone().min().map(|at| serde_rfc3339::format(&at)),
+        last_added: added.max().map(|at| serde_rfc3339::format(&at)),
+    })))
+}
+
+/// Playlist update request; fields left out are unchanged
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdatePlaylistRequest {
+    /// New playlist name
+    #[schema(example = "Chill")]
+    pub name: Option<String>,
+    /// New description; an empty string removes it
+    #[schema(example = "Evening listening")]
+    pub description: Option<String>,
+    /// Order the tracks are queued in when the playlist is played
+    pub play_order: Option<PlayOrder>,
+    /// Repeat mode set when the playlist is played; `null` keeps the queue's mode
+    #[serde(default, deserialize_with = "deserialize_present")]
+    #[schema(value_type = Option<RepeatMode>)]
+    pub default_repeat: Option<Option<RepeatMode>>,
+    /// Virtual folder path such as "Workout/Running"; `null` or an empty string
+    /// moves the playlist to the root
+    #[serde(default, deserialize_with = "deserialize_present")]
+    #[schema(value_type = Option<String>, example = "Workout/Running")]
+    pub folder: Option<Option<String>>,
+    /// `modified_at` of the playlist as last seen; the update is refused with 409 if
+    /// the playlist has changed since
+    #[serde(default, with = "serde_rfc3339::option")]
+    #[schema(value_type = Option<String>, example = "2024-01-20T14:45:00Z")]
+    pub modified_at: Option<DateTime<Utc>>,
+}
+
+/// Deserialize a field that is present, so `null` can be told apart from a missing field.
+fn deserialize_present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+/// Update a playlist
+///
+/// Changes the name, description, folder, play order or default repeat mode. The
+/// stored entry order is never changed by the play order. Send the `modified_at` last
+/// read to be told with 409 when another client changed the playlist in between.
+#[utoipa::path(
+    patch,
+    path = "/api/playlists/{id}",
+    tag = "Playlists",
+    params(
+        ("id" = String, Path, description = "Playlist identifier", example = "550e8400-e29b-41d4-a716-446655440000"),
+    ),
+    request_body = UpdatePlaylistRequest,
+    responses(
+        (status = 200, description = "The updated playlist", body = ApiResponsePlaylist),
+        (status = 400, description = "Empty or too long playlist name, or too long folder", body = ApiErrorResponse),
+        (status = 404, description = "Playlist not found", body = ApiErrorResponse),
+        (status = 409, description = "The playlist changed after `modified_at`", body = ApiErrorResponse),
+        (status = 500, description = "The playlist could not be saved", body = ApiErrorResponse),
+    )
+)]
+async fn update_playlist(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Json(request): Json<UpdatePlaylistRequest>,
+) -> Result<Json<ApiResponse<PlaylistResponse>>, ApiError> {
+    let ((), playlist) =
+        state
+            .playlist_manager
+            .edit_playlist(&id, request.modified_at, |playlist| {
+                if let Some(name) = request.name {
+                    playlist.name = playlist_name(&name)?.to_string();
+                }
+                if let Some(description) = request.description {
+                    let description = description.trim();
+                    playlist.description =
+                        (!description.is_empty()).then(|| description.to_string());
+                }
+                if let Some(play_order) = request.play_order {
+                    playlist.play_order = play_order;
+                }
+                if let Some(default_repeat) = request.default_repeat {
+                    playlist.default_repeat = default_repeat;
+                }
+                if let Some(folder) = request.folder {
+                    playlist.folder = match folder {
+                        Some(folder) => playlist_folder(&folder)?,
+                        None => None,
+                    };
+                }
+                Ok::<_, ApiError>(())
+            })?;
+
+    Ok(Json(ApiResponse::success(PlaylistResponse::from(
+        PlaylistSummary::from(playlist.as_ref()),
+    ))))
+}
+
+/// Playlist entry update request; fields left out are unchanged
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdatePlaylistEntryRequest {
+    /// Note about the entry; `null` or an empty string removes it
+    #[serde(default, deserialize_with = "deserialize_present")]
+    #[schema(value_type = Option<String>, example = "This reminds me of the trip")]
+    pub note: Option<Option<String>>,
+    /// Pin the entry to the top of the playlist
+    pub pinned: Option<bool>,
+    /// `modified_at` of the playlist as last seen; the update is refused with 409 if
+    /// the playlist has changed since
+    #[serde(default, with = "serde_rfc3339::option")]
+    #[schema(value_type = Option<String>, example = "2024-01-20T14:45:00Z")]
+    pub modified_at: Option<DateTime<Utc>>,
+}
+
+/// Update a playlist entry
+///
+/// Sets the note of the first entry of a track or pins it to the top of the playlist.
+/// Pinned entries are listed and played first, keeping their relative order.
+#[utoipa::path(
+    patch,
+    path = "/api/playlists/{id}/tracks/{track_id}",
+    tag = "Playlists",
+    params(
+        ("id" = String, Path, description = "Playlist identifier", example = "550e8400-e29b-41d4-a716-446655440000"),
+        ("track_id" = String, Path, description = "Track identifier", example = "f47ac10b-58cc-4372-a567-0e02b2c3d479"),
+    ),
+    request_body = UpdatePlaylistEntryRequest,
+    responses(
+        (status = 200, description = "The updated entry", body = ApiResponsePlaylistTrack),
+        (status = 404, description = "Playlist not found or track not in it", body = ApiErrorResponse),
+        (status = 409, description = "The playlist changed after `modified_at`", body = ApiErrorResponse),
+        (status = 500, description = "The playlist could not be saved", body = ApiErrorResponse),
+    )
+)]
+async fn update_playlist_entry(
+    State(state): State<AppState>,
+    Path((id, track_id)): Path<(String, String)>,
+    Json(request): Json<UpdatePlaylistEntryRequest>,
+) -> Result<Json<ApiResponse<PlaylistTrackResponse>>, ApiError> {
+    let (position, playlist) =
+        state
+            .playlist_manager
+            .edit_playlist(&id, request.modified_at, |playlist| {
+                playlist
+                    .update_entry(&track_id, request.note, request.pinned)
+                    .ok_or_else(|| {
+                        ApiError::new(
+                            StatusCode::NOT_FOUND,
+                            format!("Track {} is not in the playlist", track_id),
+                        )
+                    })
+            })?;
+
+    Ok(Json(ApiResponse::success(PlaylistTrackResponse::new(
+        &state,
+        position,
+        &playlist.entries[position],
+    ))))
+}
+
+/// Export a playlist as M3U
+///
+/// Returns an extended M3U file listing the playlist's tracks with pinned entries
+/// first. Entry notes are written as comments after their `#EXTINF` line. Entries
+/// whose track has left the library are skipped.
+#[utoipa::path(
+    get,
+    path = "/api/playlists/{id}/m3u",
+    tag = "Playlists",
+    params(
+        ("id" = String, Path, description = "Playlist identifier", example = "550e8400-e29b-41d4-a716-446655440000"),
+    ),
+    responses(
+        (status = 200, description = "The playlist as extended M3U", body = String, content_type = "audio/x-mpegurl"),
+        (status = 404, description = "Playlist not found", body = ApiErrorResponse),
+    )
+)]
+async fn export_playlist_m3u(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let playlist = state
+        .playlist_manager
+        .get_playlist(&id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "audio/x-mpegurl; charset=utf-8")
+        .body(Body::from(playlist.to_m3u(&state.library)))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok(response)
+}
+
+/// Play a playlist
+///
+/// Replaces the queue with the playlist's tracks in its play order and starts the
+/// first one. The playlist's default repeat mode, if set, becomes the queue's repeat
+/// mode. Radio mode is turned off.
+#[utoipa::path(
+    post,
+    path = "/api/playlists/{id}/play",
+    tag = "Playlists",
+    params(
+        ("id" = String, Path, description = "Playlist identifier", example = "550e8400-e29b-41d4-a716-446655440000"),
+        RevisionQuery,
+    ),
+    responses(
+        (status = 200, description = "Playback started; the resulting queue", body = ApiResponseQueue),
+        (status = 400, description = "None of the playlist's tracks are in the library", body = ApiErrorResponse),
+        (status = 404, description = "Playlist not found", body = ApiErrorResponse),
+        (status = 409, description = "`if_revision` is no longer current", body = ApiErrorResponse),
+        (status = 500, description = "Playback failed", body = ApiErrorResponse),
+    )
+)]
+async fn play_playlist(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(revision): Query<RevisionQuery>,
+) -> Result<Json<ApiResponse<QueueResponse>>, ApiError> {
+    let playlist = state
+        .playlist_manager
+        .get_playlist(&id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let mut change = begin_playback_change(&state, &revision)?;
+
+    let radio_was_on = state.playback_queue.radio_seed().is_some();
+    let queued = state
+        .playback_queue
+        .load_playlist(&playlist, &state.library);
+    if radio_was_on {
+        state.event_bus.emit(EventPayload::radio_mode(None));
+    }
+    info!(
+        "Queued {} track(s) of playlist '{}' in {:?} order",
+        queued, playlist.name, playlist.play_order
+    );
+
+    let first = state
+        .playback_queue
+        .next_track()
+        .and_then(|track_id| state.library.get_track(&track_id));
+    state.event_bus.emit(EventPayload::queue_updated(
+        first.as_ref().map(|track| track.id.clone()),
+        first.as_ref().map(|_| 0),
+        queued,
+    ));
+    let Some(first) = first else {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "None of the playlist's tracks are in the library",
+        ));
+    };
+
+    let active_track = active_track_path(&state);
+    let result = state
+        .resume_positions
+        .play(&state, &first.metadata.file_path, false);
+    let revision = change.commit();
+    if let Some(previous) = active_track {
+        let (track_id, track_duration) =
+            lookup_track_metadata(state.library.as_ref(), FsPath::new(&previous));
+        emit_playback_event(
+            &state,
+            "stopped",
+            Some(previous),
+            track_id,
+            track_duration,
+            revision,
+        );
+    }
+    if let Err(e) = result {
+        error!("Failed to play playlist {}: {}", id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+    }
+
+    record_track_started(&state, &first.id, &first.metadata.file_path);
+    emit_playback_event(
+        &state,
+        "playing",
+        Some(first.metadata.file_path.to_string_lossy().to_string()),
+        Some(first.id.clone()),
+        first.metadata.duration,
+        revision,
+    );
+    drop(change);
+
+    Ok(Json(ApiResponse::success(queue_snapshot(&state))))
+}
+
+/// Query parameters for the playlist cleanup endpoints
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CleanupQuery {
+    /// Only report the entries that would be removed
+    #[serde(default)]
+    #[param(example = true)]
+    pub dry_run: bool,
+}
+
+/// A playlist entry whose track is no longer in the library
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CleanupEntryResponse {
+    /// Playlist identifier
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub playlist_id: String,
+    /// Playlist name
+    #[schema(example = "My Favorites")]
+    pub playlist_name: String,
+    /// Identifier of the missing track
+    #[schema(example = "f47ac10b-58cc-4372-a567-0e02b2c3d479")]
+    pub track_id: String,
+    /// Position of the entry in the playlist before cleanup
+    #[schema(example = 3)]
+    pub position: usize,
+    /// Last known file path, when the deletion was journaled
+    #[schema(value_type = Option<String>, example = "/music/Queen/Bohemian Rhapsody.mp3")]
+    pub path: Option<PathBuf>,
+}
+
+/// Entries removed (or, in a dry run, that would be removed) by a playlist cleanup
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CleanupReport {
+    /// Whether this was a dry run that left the playlists untouched
+    pub dry_run: bool,
+    /// Number of entries listed in `entries`
+    #[schema(example = 1)]
+    pub removed: usize,
+    pub entries: Vec<CleanupEntryResponse>,
+}
+
+fn cleanup_report(state: &AppState, dry_run: bool, orphans: Vec<OrphanedEntry>) -> CleanupReport {
+    let entries: Vec<CleanupEntryResponse> = orphans
+        .into_iter()
+        .map(|orphan| CleanupEntryResponse {
+            path: state.trash.original_path(&orphan.track_id),
+            playlist_id: orphan.playlist_id,
+            playlist_name: orphan.playlist_name,
+            track_id: orphan.track_id,
+            position: orphan.position,
+        })
+        .collect();
+
+    CleanupReport {
+        dry_run,
+        removed: entries.len(),
+        entries,
+    }
+}
+
+/// Cleanup a specific playlist
+///
+/// Removes tracks from the specified playlist that no longer exist in the library.
+/// Returns the removed entries; with `dry_run=true` the entries are only listed.
+#[utoipa::path(
+    post,
+    path = "/api/playlists/{id}/cleanup",
+    tag = "Playlists",
+    params(
+        ("id" = String, Path, description = "Playlist identifier", example = "550e8400-e29b-41d4-a716-446655440000"),
+        CleanupQuery,
+    ),
+    responses(
+        (status = 200, description = "Entries removed from the playlist", body = ApiResponseCleanupReport),
+        (status = 404, description = "Playlist not found", body = ApiErrorResponse),
+        (status = 409, description = "A library scan is in progress and `library.scan_conflict` is `reject`", body = ApiErrorResponse),
+        (status = 500, description = "Cleanup failed", body = ApiErrorResponse),
+    )
+)]
+async fn cleanup_playlist(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<CleanupQuery>,
+) -> Result<Json<ApiResponse<CleanupReport>>, ApiError> {
+    if !query.dry_run {
+        state.library.guard_mutation().await?;
+    }
+    if state.playlist_manager.get_playlist(&id).is_none() {
+        return Err(StatusCode::NOT_FOUND.into());
+    }
+
+    let orphans = if query.dry_run {
+        state
+            .playlist_manager
+            .find_orphaned_entries(&state.library, Some(&id))?
+    } else {
+        state
+            .playlist_manager
+            .cleanup_playlist(&id, &state.library)?
+    };
+
+    Ok(Json(ApiResponse::success(cleanup_report(
+        &state,
+        query.dry_run,
+        orphans,
+    ))))
+}
+
+/// Cleanup all playlists
+///
+/// Removes missing tracks from all playlists in the system.
+/// Returns the removed entries across all playlists; with `dry_run=true` the entries
+/// are only listed.
+#[utoipa::path(
+    post,
+    path = "/api/playlists/cleanup",
+    tag = "Playlists",
+    params(CleanupQuery),
+    responses(
+        (status = 200, description = "Entries removed across all playlists", body = ApiResponseCleanupReport),
+        (status = 409, description = "A library scan is in progress and `library.scan_conflict` is `reject`", body = ApiErrorResponse),
+        (status = 500, description = "Cleanup failed", body = ApiErrorResponse),
+    )
+)]
+async fn cleanup_all_playlists(
+    State(state): State<AppState>,
+    Query(query): Query<CleanupQuery>,
+) -> Result<Json<ApiResponse<CleanupReport>>, ApiError> {
+    if !query.dry_run {
+        state.library.guard_mutation().await?;
+    }
+    let orphans = if query.dry_run {
+        state
+            .playlist_manager
+            .find_orphaned_entries(&state.library, None)?
+    } else {
+        state
+            .playlist_manager
+            .cleanup_missing_tracks(&state.library)?
+    };
+
+    Ok(Json(ApiResponse::success(cleanup_report(
+        &state,
+        query.dry_run,
+        orphans,
+    ))))
+}
+
+/// Query parameters for the playlist CSV import endpoint
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CsvImportQuery {
+    /// Name of the playlist to create
+    #[param(example = "Road Trip")]
+    pub name: Option<String>,
+    /// Only report matches without creating a playlist
+    #[serde(default)]
+    #[param(example = true)]
+    pub dry_run: bool,
+    /// What to do when a playlist with the same name exists: rename (default), merge,
+    /// replace or fail
+    #[serde(default)]
+    #[param(example = "merge")]
+    pub on_conflict: ImportConflictPolicy,
+}
+
+/// A single row of an imported CSV and the library track it matched
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CsvImportRowResponse {
+    /// Line number in the CSV file
+    #[schema(example = 2)]
+    pub line: usize,
+    /// Track title from the CSV
+    #[schema(example = "Bohemian Rhapsody")]
+    pub title: String,
+    /// Artist from the CSV
+    #[schema(example = "Queen")]
+    pub artist: Option<String>,
+    /// Album from the CSV
+    pub album: Option<String>,
+    /// Duration in seconds from the CSV
+    pub duration: Option<u64>,
+    /// Matched library track identifier
+    pub track_id: Option<String>,
+    /// Match confidence between 0.0 and 1.0
+    #[schema(example = 0.92)]
+    pub confidence: Option<f32>,
+}
+
+/// Report returned by the playlist CSV import endpoint
+#[derive(Debug, Serialize, ToSchema)]
+pub struct CsvImportResponse {
+    /// Identifier of the created or updated playlist (absent for dry runs)
+    pub playlist_id: Option<String>,
+    /// Playlist name, with " (imported)" appended when the import was renamed
+    pub playlist_name: String,
+    /// How a clash with an existing playlist was handled (absent for dry runs)
+    pub action: Option<ImportAction>,
+    /// Whether this was a dry run
+    pub dry_run: bool,
+    /// Number of track rows in the CSV
+    pub total_rows: usize,
+    /// Rows matched to library tracks
+    pub matched: Vec<CsvImportRowResponse>,
+    /// Matched rows whose confidence is low enough to warrant review
+    pub low_confidence: Vec<CsvImportRowResponse>,
+    /// Rows without a matching library track
+    pub unmatched: Vec<CsvImportRowResponse>,
+}
+
+impl CsvImportRowResponse {
+    fn new(row: &CsvTrackRow, track: Option<&TrackMatch>) -> Self {
+        Self {
+            line: row.line,
+            title: row.query.title.clone(),
+            artist: row.query.artist.clone(),
+            album: row.query.album.clone(),
+            duration: row.query.duration,
+            track_id: track.map(|track| track.track_id.clone()),
+            confidence: track.map(|track| track.confidence),
+        }
+    }
+}
+
+impl From<CsvImportReport> for CsvImportResponse {
+    fn from(report: CsvImportReport) -> Self {
+        let row_response =
+            |entry: &CsvImportMatch| CsvImportRowResponse::new(&entry.row, Some(&entry.track));
+
+        Self {
+            low_confidence: report.low_confidence().map(row_response).collect(),
+            matched: report.matched.iter().map(row_response).collect(),
+            unmatched: report
+                .unmatched
+                .iter()
+                .map(|row| CsvImportRowResponse::new(row, None))
+                .collect(),
+            playlist_id: report.playlist_id,
+            playlist_name: report.playlist_name,
+            action: report.action,
+            dry_run: report.dry_run,
+            total_rows: report.total_rows,
+        }
+    }
+}
+
+/// Import a playlist from an exported CSV
+///
+/// Accepts Exportify (Spotify) and YouTube Music style CSV exports as the request body.
+/// Each row is matched against the library by normalized artist and title, with a fuzzy
+/// fallback. With `dry_run=true` only the match report is returned.
+///
+/// When a playlist with the same name exists, `on_conflict` decides whether the import
+/// is renamed, merged into it, replaces its contents or is refused.
+#[utoipa::path(
+    post,
+    path = "/api/playlists/import/csv",
+    tag = "Playlists",
+    params(CsvImportQuery),
+    request_body(content = String, description = "Exported playlist CSV", content_type = "text/csv"),
+    responses(
+        (status = 200, description = "Match report", body = ApiResponseCsvImport),
+        (status = 400, description = "The CSV could not be parsed or the name is too long", body = ApiErrorResponse),
+        (status = 409, description = "A playlist with the same name exists and `on_conflict` is `fail` (`data` holds it)", body = ApiResponsePlaylist),
+        (status = 413, description = "The CSV is larger than `api.max_import_mb`", body = ApiErrorResponse),
+    )
+)]
+async fn import_playlist_csv(
+    State(state): State<AppState>,
+    Query(query): Query<CsvImportQuery>,
+    body: String,
+) -> Result<Json<ApiResponse<CsvImportResponse>>, Response> {
+    let name = match query.name.as_deref().filter(|name| !name.trim().is_empty()) {
+        Some(name) => playlist_name(name).map_err(IntoResponse::into_response)?,
+        None => "Imported playlist",
+    };
+
+    match state.playlist_manager.import_csv(
+        &state.library,
+        name,
+        &body,
+        query.dry_run,
+        query.on_conflict,
+    ) {
+        Ok(report) => Ok(Json(ApiResponse::success(report.into()))),
+        Err(PlaylistError::Conflict(conflict)) => {
+            let body = ApiResponse {
+                success: false,
+                error: Some(conflict.to_string()),
+                data: Some(PlaylistResponse::from(conflict.existing)),
+                loading: false,
+            };
+            Err((StatusCode::CONFLICT, Json(body)).into_response())
+        }
+        Err(e) => Err(ApiError::from(e).into_response()),
+    }
+}
+
+fn lookup_track_metadata(library: &Library, track_path: &FsPath) -> (Option<String>, Option<u64>) {
+    if let Some(track) = library.get_track_by_path(track_path) {
+        (Some(track.id), track.metadata.duration)
+    } else {
+        (None, None)
+    }
+}
+
+fn emit_playback_event(
+    state: &AppState,
+    playback_state: &str,
+    track_path: Option<String>,
+    track_id: Option<String>,
+    track_duration: Option<u64>,
+    revision: u64,
+) {
+    state.event_bus.emit(
+        EventPayload::playback_state(
+            playback_state.to_string(),
+            track_path,
+            track_id,
+            Some(state.audio_player.get_volume()),
+            track_duration,
+        )
+        .with_queue_settings(
+            state.playback_queue.get_repeat_mode().as_str(),
+            state.playback_queue.is_shuffle_enabled(),
+        )
+        .with_revision(revision),
+    );
+}
+
+/// Conditional write query parameters
+#[derive(Debug, Default, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RevisionQuery {
+    /// Only apply the change if the playback revision is still this one
+    #[param(example = 42)]
+    pub if_revision: Option<u64>,
+}
+
+/// Start a playback change, refusing with 409 Conflict when `if_revision` is stale.
+fn begin_playback_change<'a>(
+    state: &'a AppState,
+    query: &RevisionQuery,
+) -> Result<revision::RevisionChange<'a>, ApiError> {
+    state.revision.begin(query.if_revision).map_err(|current| {
+        ApiError::new(
+            StatusCode::CONFLICT,
+            format!(
+                "Playback changed: revision is {}, not {}",
+                current,
+                query.if_revision.unwrap_or_default()
+            ),
+        )
+    })
+}
+
+/// What to do when a play request arrives while a track is already playing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayBehavior {
+    /// Stop the current track and play the new one immediately
+    #[default]
+    Interrupt,
+    /// Queue the track to play right after the current one
+    EnqueueNext,
+    /// Append the track to the end of the queue
+    EnqueueEnd,
+    /// Refuse with 409 Conflict and report the current track, unless it is paused
+    RejectIfPlaying,
+}
+
+/// Play audio request
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PlayRequest {
+    /// File path to audio file
+    #[schema(example = "/path/to/track.mp3")]
+    pub file_path: String,
+    /// Behavior when a track is already playing (defaults to `interrupt`)
+    #[serde(default)]
+    pub behavior: PlayBehavior,
+    /// Start from the beginning rather than where a long track was left off
+    #[serde(default)]
+    pub from_start: bool,
+}
+
+/// Audio status response
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AudioStatusResponse {
+    /// Current playback state
+    #[schema(value_type = AudioState, example = "Playing")]
+    pub state: String,
+    /// Current track path
+    #[schema(example = "/path/to/track.mp3")]
+    pub current_track: Option<String>,
+    /// File queued with `POST /api/audio/enqueue` to follow the current track
+    #[serde(default)]
+    #[schema(example = "/path/to/next.mp3")]
+    pub next_track: Option<String>,
+    /// Seconds played of the current track, as kept by the audio thread across pauses
+    /// and seeks; 0 when stopped
+    #[serde(default)]
+    #[schema(example = 83.4)]
+    pub position_seconds: f64,
+    /// Current volume (0.0 to 1.0)
+    #[schema(example = 0.7)]
+    pub volume: f32,
+    /// Playback speed factor (0.5 to 2.0), set with `POST /api/audio/speed`
+    #[serde(default = "normal_speed")]
+    #[schema(example = 1.0)]
+    pub speed: f32,
+    /// Queue repeat mode (none, one, all)
+    #[schema(value_type = RepeatMode, example = "none")]
+    pub repeat_mode: String,
+    /// Whether shuffle is enabled
+    #[schema(example = false)]
+    pub shuffle: bool,
+    /// Seed track of radio mode, when it is on
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub radio_seed: Option<String>,
+    /// Sample rate, channels and bit depth of the current track, before any resampling
+    pub source_format: Option<SourceFormat>,
+    /// A-B loop set on the current track with `POST /api/audio/loop`
+    #[serde(default, rename = "loop")]
+    pub playback_loop: Option<AudioLoop>,
+    /// The preview sink, which plays independently of the fields above
+    pub preview: PreviewStatus,
+    /// Revision of the playback state and volume; pass it as `if_revision` to make a
+    /// change conditional on nobody else having changed playback since
+    #[serde(default)]
+    #[schema(example = 42)]
+    pub revision: u64,
+}
+
+fn normal_speed() -> f32 {
+    1.0
+}
+
+/// Play audio file
+///
+/// When a track is already playing, `behavior` decides whether the new track interrupts
+/// it, is queued, or the request is rejected; a paused track is never a reason to reject.
+/// With nothing playing every behavior starts playback immediately.
+#[utoipa::path(
+    post,
+    path = "/api/audio/play",
+    tag = "Audio",
+    params(RevisionQuery),
+    request_body = PlayRequest,
+    responses(
+        (status = 200, description = "Playback started or track queued", body = ApiResponseString),
+        (status = 404, description = "Track to queue is not in the library", body = ApiErrorResponse),
+        (status = 409, description = "A track is already playing (`data` holds it), or `if_revision` is no longer current", body = ApiResponseTrack),
+        (status = 500, description = "Playback failed", body = ApiErrorResponse),
+    )
+)]
+async fn play_audio(
+    State(state): State<AppState>,
+    Query(revision): Query<RevisionQuery>,
+    Json(request): Json<PlayRequest>,
+) -> Result<Json<ApiResponse<String>>, Response> {
+    let mut change =
+        begin_playback_change(&state, &revision).map_err(IntoResponse::into_response)?;
+    let file_path = FsPath::new(&request.file_path);
+    let active_track = active_track_path(&state);
+
+    match (request.behavior, active_track.as_deref()) {
+        (PlayBehavior::RejectIfPlaying, Some(current))
+            if state.audio_player.get_state() != AudioState::Paused =>
+        {
+            let current_track = state
+                .library
+                .get_track_by_path(FsPath::new(current))
+                .map(|track| TrackResponse::from(&track));
+
+            let body = ApiResponse {
+                success: false,
+                data: current_track,
+                error: Some("A track is already playing".to_string()),
+                loading: false,
+            };
+            return Err((StatusCode::CONFLICT, Json(body)).into_response());
+        }
+        (PlayBehavior::EnqueueNext | PlayBehavior::EnqueueEnd, Some(_)) => {
+            let track = state.library.get_track_by_path(file_path).ok_or_else(|| {
+                error!(
+                    "Cannot queue track outside the library: {}",
+                    request.file_path
+                );
+                ApiError::new(StatusCode::NOT_FOUND, "Track is not in the library").into_response()
+            })?;
+
+            let position = if request.behavior == PlayBehavior::EnqueueNext {
+                state.playback_queue.insert_next(track.id.clone())
+            } else {
+                state.playback_queue.push_back(track.id.clone())
+            };
+
+            info!("Queued {} at position {}", request.file_path, position);
+            state.event_bus.emit(EventPayload::queue_updated(
+                Some(track.id),
+                Some(position),
+                state.playback_queue.len(),
+            ));
+            return Ok(Json(ApiResponse::success("Track queued".to_string())));
+        }
+        _ => {}
+    }
+
+    let result = state
+        .resume_positions
+        .play(&state, file_path, request.from_start);
+    let revision = change.commit();
+
+    if let Some(previous) = active_track {
+        let (track_id, track_duration) =
+            lookup_track_metadata(state.library.as_ref(), FsPath::new(&previous));
+        emit_playback_event(
+            &state,
+            "stopped",
+            Some(previous),
+            track_id,
+            track_duration,
+            revision,
+        );
+    }
+
+    match result {
+        Ok(_) => {
+            info!("Started playing: {}", request.file_path);
+            let (track_id, track_duration) =
+                lookup_track_metadata(state.library.as_ref(), file_path);
+            if let Some(track_id) = track_id.as_deref() {
+                record_track_started(&state, track_id, file_path);
+                top_up_radio(&state);
+            }
+            emit_playback_event(
+                &state,
+                "playing",
+                Some(request.file_path.clone()),
+                track_id,
+                track_duration,
+                revision,
+            );
+            Ok(Json(ApiResponse::success("Playback started".to_string())))
+        }
+        Err(e) => {
+            error!("Failed to play audio: {}", e);
+            Err(ApiError::from(StatusCode::INTERNAL_SERVER_ERROR).into_response())
+        }
+    }
+}
+
+/// Emit `library_updated` with the tracks changed after library change `since`.
+fn emit_library_updated(state: &AppState, since: u64) {
+    let (sequence, delta) = state.library.changes_since(since);
+    state.event_bus.emit(EventPayload::library_changed(
+        state.library.track_count(),
+        sequence,
+        delta,
+    ));
+}
+
+/// Play a track of an album, playlist or artist
+///
+/// Replaces the queue with the tracks of the context in their listed order, with the
+/// chosen track current, and plays it, so the rest of the album or playlist follows.
+/// With shuffle on, the other tracks are queued after it in random order. The repeat
+/// mode is kept. Radio mode is turned off. Returns the queue around the chosen track.
+#[utoipa::path(
+    post,
+    path = "/api/audio/play-context",
+    tag = "Audio",
+    params(RevisionQuery),
+    request_body = PlayContextRequest,
+    responses(
+        (status = 200, description = "Playback started; the queue around the track", body = ApiResponseQueueWindow),
+        (status = 400, description = "The track is not part of the context", body = ApiErrorResponse),
+        (status = 404, description = "Unknown album, playlist or artist", body = ApiErrorResponse),
+        (status = 409, description = "`if_revision` is no longer current", body = ApiErrorResponse),
+        (status = 500, description = "Playback failed", body = ApiErrorResponse),
+    )
+)]
+async fn play_context(
+    State(state): State<AppState>,
+    Query(revision): Query<RevisionQuery>,
+    Json(request): Json<PlayContextRequest>,
+) -> Result<Json<ApiResponse<QueueWindow>>, ApiError> {
+    let track_ids = context_track_ids(&state, &request.context)?;
+    let start = context_position(&track_ids, &request.track_id, &request.context)?;
+    let track = state
+        .library
+        .get_track(&request.track_id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let mut change = begin_playback_change(&state, &revision)?;
+
+    let radio_was_on = state.playback_queue.radio_seed().is_some();
+    let queued = track_ids.len();
+    let position = state.playback_queue.load_context(track_ids, start);
+    if radio_was_on {
+        state.event_bus.emit(EventPayload::radio_mode(None));
+    }
+    state.event_bus.emit(EventPayload::queue_updated(
+        Some(track.id.clone()),
+        Some(position),
+        queued,
+    ));
+
+    let active_track = active_track_path(&state);
+    let result = state
+        .resume_positions
+        .play(&state, &track.metadata.file_path, false);
+    let revision = change.commit();
+    if let Some(previous) = active_track {
+        let (track_id, track_duration) =
+            lookup_track_metadata(state.library.as_ref(), FsPath::new(&previous));
+        emit_playback_event(
+            &state,
+            "stopped",
+            Some(previous),
+            track_id,
+            track_duration,
+            revision,
+        );
+    }
+    if let Err(e) = result {
+        error!(
+            "Failed to play {} of {} {}: {}",
+            track.id,
+            request.context.context_type.as_str(),
+            request.context.id,
+            e
+        );
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+    }
+
+    info!(
+        "Playing track {} of {} queued from {} {}",
+        position + 1,
+        queued,
+        request.context.context_type.as_str(),
+        request.context.id
+    );
+    record_track_started(&state, &track.id, &track.metadata.file_path);
+    emit_playback_event(
+        &state,
+        "playing",
+        Some(track.metadata.file_path.to_string_lossy().to_string()),
+        Some(track.id.clone()),
+        track.metadata.duration,
+        revision,
+    );
+    drop(change);
+
+    let window = queue_window(&state).ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(ApiResponse::success(window)))
+}
+
+/// Path of the track currently loaded in the player, if playback is not stopped.
+fn active_track_path(state: &AppState) -> Option<String> {
+    if state.audio_player.get_state() == AudioState::Stopped {
+        return None;
+    }
+
+    state.audio_player.get_current_track()
+}
+
+/// Pause audio playback
+#[utoipa::path(
+    post,
+    path = "/api/audio/pause",
+    tag = "Audio",
+    params(RevisionQuery),
+    responses(
+        (status = 200, description = "Playback paused", body = ApiResponseString),
+        (status = 409, description = "`if_revision` is no longer current", body = ApiErrorResponse),
+        (status = 500, description = "Pausing failed", body = ApiErrorResponse),
+    )
+)]
+async fn pause_audio(
+    State(state): State<AppState>,
+    Query(revision): Query<RevisionQuery>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let mut change = begin_playback_change(&state, &revision)?;
+    match state.audio_player.pause() {
+        Ok(_) => {
+            info!("Audio paused");
+            let track_path = state.audio_player.get_current_track();
+            if let Some(path) = track_path.as_deref() {
+                state.resume_positions.save(&state, FsPath::new(path));
+            }
+            let (track_id, track_duration) = track_path
+                .as_deref()
+                .map(|path| lookup_track_metadata(state.library.as_ref(), FsPath::new(path)))
+                .unwrap_or((None, None));
+            emit_playback_event(
+                &state,
+                "paused",
+                track_path,
+                track_id,
+                track_duration,
+                change.commit(),
+            );
+            Ok(Json(ApiResponse::success("Playback paused".to_string())))
+        }
+        Err(e) => {
+            error!("Failed to pause audio: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+}
+
+/// Resume audio playback
+#[utoipa::path(
+    post,
+    path = "/api/audio/resume",
+    tag = "Audio",
+    params(RevisionQuery),
+    responses(
+        (status = 200, description = "Playback resumed", body = ApiResponseString),
+        (status = 409, description = "`if_revision` is no longer current", body = ApiErrorResponse),
+        (status = 500, description = "Resuming failed", body = ApiErrorResponse),
+    )
+)]
+async fn resume_audio(
+    State(state): State<AppState>,
+    Query(revision): Query<RevisionQuery>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let mut change = begin_playback_change(&state, &revision)?;
+    match state.audio_player.resume() {
+        Ok(_) => {
+            info!("Audio resumed");
+            let track_path = state.audio_player.get_current_track();
+            let (track_id, track_duration) = track_path
+                .as_deref()
+                .map(|path| lookup_track_metadata(state.library.as_ref(), FsPath::new(path)))
+                .unwrap_or((None, None));
+            emit_playback_event(
+                &state,
+                "playing",
+                track_path,
+                track_id,
+                track_duration,
+                change.commit(),
+            );
+            Ok(Json(ApiResponse::success("Playback resumed".to_string())))
+        }
+        Err(e) => {
+            error!("Failed to resume audio: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+}
+
+/// Stop audio playback
+#[utoipa::path(
+    post,
+    path = "/api/audio/stop",
+    tag = "Audio",
+    params(RevisionQuery),
+    responses(
+        (status = 200, description = "Playback stopped", body = ApiResponseString),
+        (status = 409, description = "`if_revision` is no longer current", body = ApiErrorResponse),
+        (status = 500, description = "Stopping failed", body = ApiErrorResponse),
+    )
+)]
+async fn stop_audio(
+    State(state): State<AppState>,
+    Query(revision): Query<RevisionQuery>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let mut change = begin_playback_change(&state, &revision)?;
+    let track_path_before_stop = state.audio_player.get_current_track();
+    let (track_id_before_stop, track_duration_before_stop) = track_path_before_stop
         .as_deref()
         .map(|path| lookup_track_metadata(state.library.as_ref(), FsPath::new(path)))
         .unwrap_or((None, None));
+    if let Some(path) = active_track_path(&state) {
+        state.resume_positions.save(&state, FsPath::new(&path));
+    }
+
+    match state.audio_player.stop() {
+        Ok(_) => {
+            info!("Audio stopped");
+            emit_playback_event(
+                &state,
+                "stopped",
+                track_path_before_stop,
+                track_id_before_stop,
+                track_duration_before_stop,
+                change.commit(),
+            );
+            Ok(Json(ApiResponse::success("Playback stopped".to_string())))
+        }
+        Err(e) => {
+            error!("Failed to stop audio: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+}
+
+/// Gapless enqueue request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct GaplessRequest {
+    /// File path to audio file
+    #[schema(example = "/path/to/next.mp3")]
+    pub file_path: String,
+}
+
+/// Queue a file to follow the current track without a gap
+///
+/// The file is decoded right away and appended to the playing output, so it starts
+/// the moment the current track ends, and then becomes the current track with a
+/// `playback_state` event. One file can be queued at a time. A file that cannot be
+/// decoded is refused with a `playback_error` event, and playback stops at the end of
+/// the current track as usual. Stopping or playing another track drops the queued
+/// file. The play queue is left alone.
+#[utoipa::path(
+    post,
+    path = "/api/audio/enqueue",
+    tag = "Audio",
+    request_body = GaplessRequest,
+    responses(
+        (status = 200, description = "File queued", body = ApiResponseString),
+        (status = 404, description = "File not found", body = ApiErrorResponse),
+        (status = 409, description = "Nothing is playing, or a file is queued already", body = ApiErrorResponse),
+        (status = 422, description = "The file cannot be decoded", body = ApiErrorResponse),
+    )
+)]
+async fn enqueue_gapless(
+    State(state): State<AppState>,
+    Json(request): Json<GaplessRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let file_path = FsPath::new(&request.file_path);
+    if !file_path.is_file() {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "File not found"));
+    }
+    if !matches!(
+        state.audio_player.get_state(),
+        AudioState::Playing | AudioState::Paused
+    ) {
+        return Err(ApiError::new(StatusCode::CONFLICT, "Nothing is playing"));
+    }
+    if let Some(next) = state.audio_player.get_next_track() {
+        return Err(ApiError::new(
+            StatusCode::CONFLICT,
+            format!("{} is queued already", next),
+        ));
+    }
+
+    match state.audio_player.enqueue_next(file_path) {
+        Ok(()) => {
+            info!("Queued {} to follow gaplessly", request.file_path);
+            Ok(Json(ApiResponse::success("Track queued".to_string())))
+        }
+        Err(e) => {
+            warn!("Failed to queue {}: {}", request.file_path, e);
+            Err(ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                e.to_string(),
+            ))
+        }
+    }
+}
+
+/// Skip to the next track
+///
+/// Plays the next track of the queue, following the repeat mode and shuffle, and
+/// returns it. Queued tracks that have left the library are skipped.
+#[utoipa::path(
+    post,
+    path = "/api/audio/next",
+    tag = "Audio",
+    params(RevisionQuery),
+    responses(
+        (status = 200, description = "The track now playing", body = ApiResponseTrack),
+        (status = 404, description = "The queue has no next track", body = ApiErrorResponse),
+        (status = 409, description = "`if_revision` is no longer current", body = ApiErrorResponse),
+        (status = 500, description = "Playback failed", body = ApiErrorResponse),
+    )
+)]
+async fn play_next(
+    State(state): State<AppState>,
+    Query(revision): Query<RevisionQuery>,
+) -> Result<Json<ApiResponse<TrackResponse>>, ApiError> {
+    let track = play_from_queue(
+        &state,
+        &revision,
+        PlaybackQueue::next_track,
+        "The queue has no next track",
+    )?;
+    Ok(Json(ApiResponse::success(TrackResponse::from(&track))))
+}
+
+/// Play the track `step` moves the queue to, stepping on past tracks that have left
+/// the library, and return it. `none_left` is the error when no track is found.
+fn play_from_queue(
+    state: &AppState,
+    revision: &RevisionQuery,
+    step: fn(&PlaybackQueue) -> Option<String>,
+    none_left: &'static str,
+) -> Result<Track, ApiError> {
+    let mut change = begin_playback_change(state, revision)?;
+    let track = std::iter::from_fn(|| step(&state.playback_queue))
+        .take(state.playback_queue.len())
+        .find_map(|track_id| state.library.get_track(&track_id))
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, none_left))?;
+    state.event_bus.emit(EventPayload::queue_updated(
+        Some(track.id.clone()),
+        state.playback_queue.current_index(),
+        state.playback_queue.len(),
+    ));
+
+    let active_track = active_track_path(state);
+    if let Some(path) = active_track.as_deref() {
+        state.resume_positions.save(state, FsPath::new(path));
+    }
+    let result = state
+        .resume_positions
+        .play(state, &track.metadata.file_path, false);
+    let revision = change.commit();
+    if let Some(previous) = active_track {
+        let (track_id, track_duration) =
+            lookup_track_metadata(state.library.as_ref(), FsPath::new(&previous));
+        emit_playback_event(
+            state,
+            "stopped",
+            Some(previous),
+            track_id,
+            track_duration,
+            revision,
+        );
+    }
+    if let Err(e) = result {
+        error!("Failed to play the queued track {}: {}", track.id, e);
+        return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
+    }
+
+    info!("Skipped to {}", track.display_name());
+    record_track_started(state, &track.id, &track.metadata.file_path);
+    top_up_radio(state);
+    emit_playback_event(
+        state,
+        "playing",
+        Some(track.metadata.file_path.to_string_lossy().to_string()),
+        Some(track.id.clone()),
+        track.metadata.duration,
+        revision,
+    );
+    Ok(track)
+}
+
+/// Get audio playback status
+#[utoipa::path(
+    get,
+    path = "/api/audio/status",
+    tag = "Audio",
+    responses(
+        (status = 200, description = "Playback status", body = ApiResponseAudioStatus),
+    )
+)]
+async fn get_audio_status(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<AudioStatusResponse>>, ApiError> {
+    // Read first, so a change racing this request makes the revision stale rather
+    // than newer than the state reported with it
+    let revision = state.revision.current();
+    let audio_state = state.audio_player.get_state();
+    let current_track = state.audio_player.get_current_track();
+    let position = state.audio_player.get_position();
+    let volume = state.audio_player.get_volume();
+    let current_track_format = current_track
+        .as_ref()
+        .and_then(|_| state.audio_player.get_source_format());
+
+    let status = AudioStatusResponse {
+        state: format!("{:?}", audio_state),
+        current_track,
+        next_track: state.audio_player.get_next_track(),
+        position_seconds: position.as_secs_f64(),
+        volume,
+        speed: state.audio_player.get_speed(),
+        repeat_mode: state.playback_queue.get_repeat_mode().to_string(),
+        shuffle: state.playback_queue.is_shuffle_enabled(),
+        radio_seed: state.playback_queue.radio_seed(),
+        source_format: current_track_format,
+        playback_loop: state.audio_player.get_loop().map(AudioLoop::from),
+        preview: state.audio_player.preview_status(),
+        revision,
+    };
+
+    Ok(Json(ApiResponse::success(status)))
+}
+
+/// Get the sample rate, buffer size and channels the output device was opened with,
+/// next to the ones requested in the configuration
+#[utoipa::path(
+    get,
+    path = "/api/audio/device",
+    tag = "Audio",
+    responses(
+        (status = 200, description = "Output device parameters", body = ApiResponseAudioDevice),
+        (status = 404, description = "The audio backend does not report device parameters"),
+    )
+)]
+async fn get_audio_device(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<AudioDeviceInfo>>, ApiError> {
+    let info = state.audio_player.device_info().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "The audio backend does not report device parameters",
+        )
+    })?;
+    Ok(Json(ApiResponse::success(info)))
+}
+
+/// Output devices
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AudioDeviceList {
+    /// Names of the devices playback can be switched to
+    #[schema(example = json!(["default", "USB Audio DAC"]))]
+    pub devices: Vec<String>,
+    /// Device playing now
+    #[schema(example = "default")]
+    pub current: Option<String>,
+}
+
+/// Output device switch request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SwitchDeviceRequest {
+    /// Device to play on, matched by name ignoring case (part of the name will do);
+    /// the default device when unset or when no device matches
+    #[schema(example = "usb")]
+    pub name: Option<String>,
+}
+
+fn audio_device_list(state: &AppState) -> AudioDeviceList {
+    AudioDeviceList {
+        devices: state.audio_player.list_output_devices(),
+        current: state
+            .audio_player
+            .device_info()
+            .and_then(|info| info.device_name),
+    }
+}
+
+/// List the output devices
+///
+/// Names accepted by `audio.output_device` and `POST /api/audio/devices/switch`.
+#[utoipa::path(
+    get,
+    path = "/api/audio/devices",
+    tag = "Audio",
+    responses(
+        (status = 200, description = "Output devices", body = ApiResponseAudioDevices),
+    )
+)]
+async fn get_audio_devices(State(state): State<AppState>) -> Json<ApiResponse<AudioDeviceList>> {
+    Json(ApiResponse::success(audio_device_list(&state)))
+}
+
+/// Move playback to another output device
+///
+/// Reopens the output on the named device and carries on with the current track
+/// where it was. Until the next restart only; set `audio.output_device` to keep the
+/// choice.
+#[utoipa::path(
+    post,
+    path = "/api/audio/devices/switch",
+    tag = "Audio",
+    request_body = SwitchDeviceRequest,
+    responses(
+        (status = 200, description = "Output devices after the switch", body = ApiResponseAudioDevices),
+        (status = 500, description = "The device could not be opened; the player keeps trying to reacquire one", body = ApiErrorResponse),
+    )
+)]
+async fn switch_audio_device(
+    State(state): State<AppState>,
+    Json(request): Json<SwitchDeviceRequest>,
+) -> Result<Json<ApiResponse<AudioDeviceList>>, ApiError> {
+    state
+        .audio_player
+        .switch_device(request.name.clone())
+        .map_err(|e| {
+            error!("Failed to switch the audio output device: {}", e);
+            ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to switch the output device: {}", e),
+            )
+        })?;
+
+    info!("Audio output switched to {:?}", request.name);
+    Ok(Json(ApiResponse::success(audio_device_list(&state))))
+}
+
+/// Move playback to another output device
+///
+/// The same as `POST /api/audio/devices/switch`, next to `GET /api/audio/device`.
+/// Should the device disappear later, playback falls back to the default device.
+#[utoipa::path(
+    post,
+    path = "/api/audio/device",
+    tag = "Audio",
+    request_body = SwitchDeviceRequest,
+    responses(
+        (status = 200, description = "Output devices after the switch", body = ApiResponseAudioDevices),
+        (status = 500, description = "The device could not be opened; the player keeps trying to reacquire one", body = ApiErrorResponse),
+    )
+)]
+async fn set_audio_device(
+    state: State<AppState>,
+    request: Json<SwitchDeviceRequest>,
+) -> Result<Json<ApiResponse<AudioDeviceList>>, ApiError> {
+    switch_audio_device(state, request).await
+}
+
+/// Get how often tracks were opened from their precached copy
+///
+/// With `audio.precache_mb` set, the next queued track is copied to a local cache
+/// before it plays, so tracks on slow or network storage start without stalling.
+#[utoipa::path(
+    get,
+    path = "/api/audio/precache",
+    tag = "Audio",
+    responses(
+        (status = 200, description = "Precache counters", body = ApiResponsePrecacheStats),
+        (status = 404, description = "Precaching is disabled", body = ApiErrorResponse),
+    )
+)]
+async fn get_precache_stats(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<PrecacheStats>>, ApiError> {
+    let precache = state.precache.clone().ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            "Precaching is disabled; set audio.precache_mb to enable it",
+        )
+    })?;
+    let stats = tokio::task::spawn_blocking(move || precache.stats())
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok(Json(ApiResponse::success(stats)))
+}
+
+/// Set volume request
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct VolumeRequest {
+    /// Volume level (0.0 to 1.0)
+    #[schema(example = 0.7)]
+    pub volume: f32,
+}
+
+/// Set audio volume
+#[utoipa::path(
+    post,
+    path = "/api/audio/volume",
+    tag = "Audio",
+    params(RevisionQuery),
+    request_body = VolumeRequest,
+    responses(
+        (status = 200, description = "Volume set", body = ApiResponseString),
+        (status = 400, description = "Volume is not a finite number", body = ApiErrorResponse),
+        (status = 403, description = "A guest asked for more than `api.guest_max_volume`", body = ApiErrorResponse),
+        (status = 409, description = "`if_revision` is no longer current", body = ApiErrorResponse),
+        (status = 500, description = "Volume could not be set", body = ApiErrorResponse),
+    )
+)]
+async fn set_audio_volume(
+    State(state): State<AppState>,
+    Query(revision): Query<RevisionQuery>,
+    Json(request): Json<VolumeRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    if !request.volume.is_finite() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Volume must be a number between 0.0 and 1.0",
+        ));
+    }
+    let volume = request.volume.clamp(0.0, 1.0);
+    let mut change = begin_playback_change(&state, &revision)?;
+
+    match state.audio_player.set_volume(volume) {
+        Ok(_) => {
+            info!("Volume set to {}", volume);
+            state
+                .event_bus
+                .emit(EventPayload::volume_changed(volume).with_revision(change.commit()));
+            Ok(Json(ApiResponse::success(format!(
+                "Volume set to {}",
+                volume
+            ))))
+        }
+        Err(e) => {
+            error!("Failed to set volume: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+}
+
+/// Set speed request
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SpeedRequest {
+    /// Speed factor, clamped to 0.5 to 2.0; 1.0 plays at the normal rate
+    #[schema(example = 1.25)]
+    pub speed: f32,
+}
+
+/// Set the playback speed
+///
+/// The current track changes speed without restarting, and later tracks play at the
+/// same speed until it is changed again. The pitch follows the speed. Positions and
+/// loops stay in track time, while `up_next` announcements count the time left to
+/// listen.
+#[utoipa::path(
+    post,
+    path = "/api/audio/speed",
+    tag = "Audio",
+    params(RevisionQuery),
+    request_body = SpeedRequest,
+    responses(
+        (status = 200, description = "Speed set", body = ApiResponseString),
+        (status = 400, description = "Speed is not a finite number", body = ApiErrorResponse),
+        (status = 409, description = "`if_revision` is no longer current", body = ApiErrorResponse),
+        (status = 500, description = "Speed could not be set", body = ApiErrorResponse),
+    )
+)]
+async fn set_audio_speed(
+    State(state): State<AppState>,
+    Query(revision): Query<RevisionQuery>,
+    Json(request): Json<SpeedRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    if !request.speed.is_finite() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Speed must be a number between {} and {}",
+                MIN_SPEED, MAX_SPEED
+            ),
+        ));
+    }
+    let speed = request.speed.clamp(MIN_SPEED, MAX_SPEED);
+    let mut change = begin_playback_change(&state, &revision)?;
+
+    match state.audio_player.set_speed(speed) {
+        Ok(_) => {
+            info!("Speed set to {}", speed);
+            change.commit();
+            drop(change);
+            emit_current_playback_state(&state);
+            Ok(Json(ApiResponse::success(format!(
+                "Speed set to {}",
+                speed
+            ))))
+        }
+        Err(e) => {
+            error!("Failed to set speed: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+}
+
+/// Preview request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PreviewRequest {
+    /// File path to audio file
+    #[schema(example = "/path/to/track.mp3")]
+    pub file_path: String,
+}
 
-    match state.audio_player.stop() {
-        Ok(_) => {
-            info!("Audio stopped");
-            emit_playback_event(
-                &state,
-                "stopped",
-                track_path_before_stop,
-                track_id_before_stop,
-                track_duration_before_stop,
-            );
-            Ok(Json(ApiResponse::success("Playback stopped".to_string())))
+/// Preview a file
+///
+/// Plays the file on a second sink mixed into the same output, at the preview
+/// volume, replacing any earlier preview. Main playback keeps playing, and its track,
+/// state and revision are unchanged.
+#[utoipa::path(
+    post,
+    path = "/api/audio/preview/play",
+    tag = "Audio",
+    request_body = PreviewRequest,
+    responses(
+        (status = 200, description = "Preview started", body = ApiResponsePreview),
+        (status = 404, description = "File not found", body = ApiErrorResponse),
+        (status = 500, description = "Preview failed", body = ApiErrorResponse),
+    )
+)]
+async fn play_preview(
+    State(state): State<AppState>,
+    Json(request): Json<PreviewRequest>,
+) -> Result<Json<ApiResponse<PreviewStatus>>, ApiError> {
+    let file_path = FsPath::new(&request.file_path);
+    if !file_path.is_file() {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "File not found"));
+    }
+
+    match state.audio_player.preview_play(file_path) {
+        Ok(()) => {
+            info!("Previewing {}", request.file_path);
+            Ok(Json(ApiResponse::success(
+                state.audio_player.preview_status(),
+            )))
         }
         Err(e) => {
-            error!("Failed to stop audio: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+            error!("Failed to preview {}: {}", request.file_path, e);
+            Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                e.to_string(),
+            ))
         }
     }
 }
 
-/// Get audio playback status
-async fn get_audio_status(
+/// Stop the preview
+#[utoipa::path(
+    post,
+    path = "/api/audio/preview/stop",
+    tag = "Audio",
+    responses(
+        (status = 200, description = "Preview stopped", body = ApiResponsePreview),
+        (status = 500, description = "Preview could not be stopped", body = ApiErrorResponse),
+    )
+)]
+async fn stop_preview(
     State(state): State<AppState>,
-) -> Result<Json<ApiResponse<AudioStatusResponse>>, StatusCode> {
-    let audio_state = state.audio_player.get_state();
-    let current_track = state.audio_player.get_current_track();
-    let volume = state.audio_player.get_volume();
+) -> Result<Json<ApiResponse<PreviewStatus>>, ApiError> {
+    match state.audio_player.preview_stop() {
+        Ok(()) => Ok(Json(ApiResponse::success(
+            state.audio_player.preview_status(),
+        ))),
+        Err(e) => {
+            error!("Failed to stop the preview: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
+}
 
-    let status = AudioStatusResponse {
-        state: format!("{:?}", audio_state),
-        current_track,
-        volume,
-    };
+/// Set the preview volume
+///
+/// The preview volume is independent of the main volume, but goes through the same
+/// volume curve.
+#[utoipa::path(
+    post,
+    path = "/api/audio/preview/volume",
+    tag = "Audio",
+    request_body = VolumeRequest,
+    responses(
+        (status = 200, description = "Preview volume set", body = ApiResponsePreview),
+        (status = 400, description = "Volume is not a finite number", body = ApiErrorResponse),
+        (status = 500, description = "Volume could not be set", body = ApiErrorResponse),
+    )
+)]
+async fn set_preview_volume(
+    State(state): State<AppState>,
+    Json(request): Json<VolumeRequest>,
+) -> Result<Json<ApiResponse<PreviewStatus>>, ApiError> {
+    if !request.volume.is_finite() {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Volume must be a number between 0.0 and 1.0",
+        ));
+    }
 
-    Ok(Json(ApiResponse::success(status)))
+    match state.audio_player.set_preview_volume(request.volume) {
+        Ok(()) => Ok(Json(ApiResponse::success(
+            state.audio_player.preview_status(),
+        ))),
+        Err(e) => {
+            error!("Failed to set the preview volume: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR.into())
+        }
+    }
 }
 
-/// Set volume request
+/// Set repeat mode request
 #[derive(Debug, Deserialize, ToSchema)]
-pub struct VolumeRequest {
-    /// Volume level (0.0 to 1.0)
-    #[schema(example = 0.7)]
-    pub volume: f32,
+pub struct RepeatModeRequest {
+    /// Repeat mode: none, one or all
+    #[schema(value_type = RepeatMode, example = "all")]
+    pub mode: String,
 }
 
-/// Set audio volume
-async fn set_audio_volume(
+/// Set shuffle request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ShuffleRequest {
+    /// Whether shuffle should be enabled
+    #[schema(example = true)]
+    pub enabled: bool,
+}
+
+/// Set the queue repeat mode
+///
+/// The setting is persisted and restored on the next start.
+#[utoipa::path(
+    post,
+    path = "/api/audio/repeat",
+    tag = "Audio",
+    request_body = RepeatModeRequest,
+    responses(
+        (status = 200, description = "Repeat mode set", body = ApiResponseString),
+        (status = 400, description = "Unknown repeat mode", body = ApiErrorResponse),
+    )
+)]
+async fn set_repeat_mode(
     State(state): State<AppState>,
-    Json(request): Json<VolumeRequest>,
-) -> Result<Json<ApiResponse<String>>, StatusCode> {
-    let volume = request.volume.max(0.0).min(1.0);
+    Json(request): Json<RepeatModeRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let mode: RepeatMode = request.mode.parse()?;
 
-    match state.audio_player.set_volume(volume) {
-        Ok(_) => {
-            info!("Volume set to {}", volume);
-            state.event_bus.emit(EventPayload::volume_changed(volume));
-            Ok(Json(ApiResponse::success(format!(
-                "Volume set to {}",
-                volume
-            ))))
+    state.playback_queue.set_repeat_mode(mode);
+    info!("Repeat mode set to {}", mode);
+    emit_current_playback_state(&state);
+
+    Ok(Json(ApiResponse::success(format!(
+        "Repeat mode set to {}",
+        mode
+    ))))
+}
+
+/// Enable or disable shuffle
+///
+/// The setting is persisted and restored on the next start.
+#[utoipa::path(
+    post,
+    path = "/api/audio/shuffle",
+    tag = "Audio",
+    request_body = ShuffleRequest,
+    responses(
+        (status = 200, description = "Shuffle set", body = ApiResponseString),
+    )
+)]
+async fn set_shuffle(
+    State(state): State<AppState>,
+    Json(request): Json<ShuffleRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    state.playback_queue.set_shuffle(request.enabled);
+    info!("Shuffle set to {}", request.enabled);
+    emit_current_playback_state(&state);
+
+    Ok(Json(ApiResponse::success(format!(
+        "Shuffle {}",
+        if request.enabled {
+            "enabled"
+        } else {
+            "disabled"
         }
-        Err(e) => {
-            error!("Failed to set volume: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+    ))))
+}
+
+/// Radio mode tops the queue up when fewer tracks than this are left to play
+const RADIO_MIN_UPCOMING: usize = 5;
+
+/// Number of upcoming tracks radio mode fills the queue up to
+const RADIO_QUEUE_TARGET: usize = 10;
+
+/// Start radio request
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RadioRequest {
+    /// Track whose genre, decade and duration the queued tracks should resemble
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub track_id: String,
+}
+
+/// Note a track that started playing in the queue history and count the play.
+fn record_track_started(state: &AppState, track_id: &str, file_path: &FsPath) {
+    state.playback_queue.record_played(track_id);
+    state.stats_store.record_play(file_path);
+    if let Err(e) = state.stats_store.save() {
+        warn!("Failed to save the play count of {:?}: {}", file_path, e);
+    }
+}
+
+/// Queue tracks similar to the radio seed when radio mode is on and few tracks are
+/// left to play. Recently played and already queued tracks are skipped. Returns the
+/// number of tracks queued.
+fn top_up_radio(state: &AppState) -> usize {
+    let Some(seed_id) = state.playback_queue.radio_seed() else {
+        return 0;
+    };
+    let upcoming = state.playback_queue.upcoming_len();
+    if upcoming >= RADIO_MIN_UPCOMING {
+        return 0;
+    }
+    let Some(seed) = state.library.get_track(&seed_id) else {
+        return 0;
+    };
+
+    let mut exclude: HashSet<String> = state.playback_queue.track_ids().into_iter().collect();
+    exclude.extend(
+        state
+            .playback_queue
+            .history(QUEUE_HISTORY_LIMIT)
+            .into_iter()
+            .map(|entry| entry.track_id),
+    );
+
+    let tracks = state.library.get_tracks();
+    let picks = similar_tracks(&seed, &tracks, &exclude, RADIO_QUEUE_TARGET - upcoming);
+    let mut last = None;
+    for track in &picks {
+        last = Some((
+            track.id.clone(),
+            state.playback_queue.push_back(track.id.clone()),
+        ));
+    }
+
+    if let Some((track_id, position)) = last {
+        info!(
+            "Radio queued {} track(s) similar to {}",
+            picks.len(),
+            seed_id
+        );
+        state.event_bus.emit(EventPayload::queue_updated(
+            Some(track_id),
+            Some(position),
+            state.playback_queue.len(),
+        ));
+    }
+    picks.len()
+}
+
+/// Start radio mode
+///
+/// Keeps the queue topped up with tracks similar to the seed track: same genre,
+/// same or adjacent decade and similar duration. Recently played tracks are skipped.
+/// The queue is refilled whenever a track starts playing and fewer than 5 tracks are
+/// left to play. Clearing the queue turns radio mode off.
+#[utoipa::path(
+    post,
+    path = "/api/audio/radio",
+    tag = "Audio",
+    request_body = RadioRequest,
+    responses(
+        (status = 200, description = "Radio mode started; the resulting queue", body = ApiResponseQueue),
+        (status = 404, description = "Seed track not found", body = ApiErrorResponse),
+    )
+)]
+async fn start_radio(
+    State(state): State<AppState>,
+    Json(request): Json<RadioRequest>,
+) -> Result<Json<ApiResponse<QueueResponse>>, ApiError> {
+    if state.library.get_track(&request.track_id).is_none() {
+        return Err(ApiError::new(StatusCode::NOT_FOUND, "Seed track not found"));
+    }
+
+    state.playback_queue.start_radio(request.track_id.clone());
+    info!("Radio mode started from {}", request.track_id);
+    state
+        .event_bus
+        .emit(EventPayload::radio_mode(Some(request.track_id)));
+    top_up_radio(&state);
+
+    Ok(Json(ApiResponse::success(queue_snapshot(&state))))
+}
+
+/// Stop radio mode
+///
+/// Tracks already queued by the radio stay in the queue.
+#[utoipa::path(
+    delete,
+    path = "/api/audio/radio",
+    tag = "Audio",
+    responses(
+        (status = 200, description = "Radio mode stopped", body = ApiResponseString),
+    )
+)]
+async fn stop_radio(State(state): State<AppState>) -> Result<Json<ApiResponse<String>>, ApiError> {
+    if state.playback_queue.stop_radio() {
+        info!("Radio mode stopped");
+        state.event_bus.emit(EventPayload::radio_mode(None));
+    }
+
+    Ok(Json(ApiResponse::success("Radio mode stopped".to_string())))
+}
+
+/// Number of history entries returned when no limit is given
+const DEFAULT_QUEUE_HISTORY_LIMIT: usize = 20;
+
+/// Queue history query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct QueueHistoryQuery {
+    /// Maximum number of entries to return (defaults to 20)
+    #[param(example = 20)]
+    pub limit: Option<usize>,
+}
+
+/// Clear queue query parameters
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ClearQueueQuery {
+    /// Also forget the recently played tracks
+    #[param(example = false)]
+    pub history: Option<bool>,
+}
+
+/// A recently played track
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct QueueHistoryItem {
+    #[serde(flatten)]
+    pub track: TrackResponse,
+    /// When the track started playing
+    #[serde(with = "serde_rfc3339")]
+    pub played_at: DateTime<Utc>,
+}
+
+/// Playback queue snapshot
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct QueueResponse {
+    /// Queued tracks in play order
+    pub tracks: Vec<TrackResponse>,
+    /// Position of the current track in `tracks`
+    #[schema(example = 0)]
+    pub current_index: Option<usize>,
+    /// Queue repeat mode (none, one, all)
+    #[schema(value_type = RepeatMode, example = "none")]
+    pub repeat_mode: String,
+    /// Whether shuffle is enabled
+    #[schema(example = false)]
+    pub shuffle: bool,
+    /// Recently played tracks, newest first
+    pub history: Vec<QueueHistoryItem>,
+}
+
+/// Recently played tracks of the queue, newest first. Tracks that have since been
+/// removed from the library are skipped.
+fn queue_history(state: &AppState, limit: usize) -> Vec<QueueHistoryItem> {
+    state
+        .playback_queue
+        .history(limit)
+        .into_iter()
+        .filter_map(|entry| {
+            let track = state.library.get_track(&entry.track_id)?;
+            Some(QueueHistoryItem {
+                track: TrackResponse::from(&track),
+                played_at: entry.played_at,
+            })
+        })
+        .collect()
+}
+
+fn queue_snapshot(state: &AppState) -> QueueResponse {
+    let current = state.playback_queue.current_index();
+    let mut tracks = Vec::new();
+    let mut current_index = None;
+
+    for (index, track_id) in state.playback_queue.track_ids().iter().enumerate() {
+        let Some(track) = state.library.get_track(track_id) else {
+            continue;
+        };
+        if current == Some(index) {
+            current_index = Some(tracks.len());
         }
+        tracks.push(TrackResponse::with_stats(&track, &state.stats_store));
+    }
+
+    QueueResponse {
+        tracks,
+        current_index,
+        repeat_mode: state.playback_queue.get_repeat_mode().to_string(),
+        shuffle: state.playback_queue.is_shuffle_enabled(),
+        history: queue_history(state, DEFAULT_QUEUE_HISTORY_LIMIT),
     }
 }
 
-/// Start the API server
-pub async fn start_server(state: AppState, port: u16) -> Result<()> {
-    let app = create_router(state);
+/// Get the playback queue
+///
+/// Tracks that have since been removed from the library are left out.
+#[utoipa::path(
+    get,
+    path = "/api/queue",
+    tag = "Queue",
+    responses(
+        (status = 200, description = "Queue snapshot", body = ApiResponseQueue),
+    )
+)]
+async fn get_queue(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<QueueResponse>>, ApiError> {
+    Ok(Json(ApiResponse::success(queue_snapshot(&state))))
+}
+
+/// Queue track request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EnqueueRequest {
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub track_id: String,
+    /// Queue the track right after the current one instead of at the end
+    #[serde(default)]
+    pub next: bool,
+}
+
+/// Queue a track
+///
+/// Adds a track of the library at the end of the queue, or right after the current
+/// track with `next`, without interrupting playback. Requests made with
+/// `api.guest_token` may queue `api.guest_enqueues_per_minute` tracks per minute.
+#[utoipa::path(
+    post,
+    path = "/api/queue",
+    tag = "Queue",
+    request_body = EnqueueRequest,
+    responses(
+        (status = 200, description = "Track queued; the resulting queue", body = ApiResponseQueue),
+        (status = 404, description = "Track not found", body = ApiErrorResponse),
+        (status = 429, description = "A guest queued too many tracks; see `Retry-After`", body = ApiErrorResponse),
+    )
+)]
+async fn enqueue_track(
+    State(state): State<AppState>,
+    Json(request): Json<EnqueueRequest>,
+) -> Result<Json<ApiResponse<QueueResponse>>, ApiError> {
+    let track = state
+        .library
+        .get_track(&request.track_id)
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Track not found"))?;
+    let position = if request.next {
+        state.playback_queue.insert_next(track.id.clone())
+    } else {
+        state.playback_queue.push_back(track.id.clone())
+    };
+
+    info!("Queued {} at position {}", track.display_name(), position);
+    state.event_bus.emit(EventPayload::queue_updated(
+        Some(track.id),
+        Some(position),
+        state.playback_queue.len(),
+    ));
+    Ok(Json(ApiResponse::success(queue_snapshot(&state))))
+}
+
+/// Clear the playback queue
+///
+/// The play history is kept unless `history=true` is given.
+#[utoipa::path(
+    delete,
+    path = "/api/queue",
+    tag = "Queue",
+    params(ClearQueueQuery),
+    responses(
+        (status = 200, description = "Queue cleared", body = ApiResponseQueue),
+    )
+)]
+async fn clear_queue(
+    State(state): State<AppState>,
+    Query(query): Query<ClearQueueQuery>,
+) -> Result<Json<ApiResponse<QueueResponse>>, ApiError> {
+    let radio_was_on = state.playback_queue.radio_seed().is_some();
+    state.playback_queue.clear();
+    if query.history.unwrap_or(false) {
+        state.playback_queue.clear_history();
+    }
+
+    info!("Queue cleared");
+    state
+        .event_bus
+        .emit(EventPayload::queue_updated(None, None, 0));
+    if radio_was_on {
+        state.event_bus.emit(EventPayload::radio_mode(None));
+    }
+    Ok(Json(ApiResponse::success(queue_snapshot(&state))))
+}
+
+/// Get recently played tracks
+///
+/// Only tracks that actually started playing are listed, newest first. This is kept
+/// separately from the long-term play statistics and is lost on restart.
+#[utoipa::path(
+    get,
+    path = "/api/queue/history",
+    tag = "Queue",
+    params(QueueHistoryQuery),
+    responses(
+        (status = 200, description = "Recently played tracks", body = ApiResponseQueueHistory),
+    )
+)]
+async fn get_queue_history(
+    State(state): State<AppState>,
+    Query(query): Query<QueueHistoryQuery>,
+) -> Result<Json<ApiResponse<Vec<QueueHistoryItem>>>, ApiError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_QUEUE_HISTORY_LIMIT)
+        .min(QUEUE_HISTORY_LIMIT);
+    Ok(Json(ApiResponse::success(queue_history(&state, limit))))
+}
+
+/// Seek to chapter request
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SeekChapterRequest {
+    /// Zero-based chapter index, as listed by the chapters endpoint
+    #[schema(example = 2)]
+    pub index: usize,
+}
+
+/// Seek to a chapter of the current track
+///
+/// A paused track stays paused at the start of the chapter.
+#[utoipa::path(
+    post,
+    path = "/api/audio/seek-chapter",
+    tag = "Audio",
+    request_body = SeekChapterRequest,
+    responses(
+        (status = 200, description = "Playback moved to the chapter", body = ApiResponseString),
+        (status = 404, description = "The current track has no such chapter", body = ApiErrorResponse),
+        (status = 409, description = "Nothing is playing", body = ApiErrorResponse),
+        (status = 500, description = "Seeking failed", body = ApiErrorResponse),
+    )
+)]
+async fn seek_chapter(
+    State(state): State<AppState>,
+    Json(request): Json<SeekChapterRequest>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let current = active_track_path(&state)
+        .ok_or_else(|| ApiError::new(StatusCode::CONFLICT, "Nothing is playing"))?;
+    let chapter = state
+        .library
+        .get_track_by_path(FsPath::new(&current))
+        .and_then(|track| track.metadata.chapters.into_iter().nth(request.index))
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::NOT_FOUND,
+                format!("The current track has no chapter {}", request.index),
+            )
+        })?;
+
+    state
+        .audio_player
+        .seek(Duration::from_secs_f64(chapter.start_seconds))
+        .map_err(|e| {
+            error!("Failed to seek to chapter {}: {}", request.index, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })?;
+
+    info!("Seeked to chapter {} ({})", request.index, chapter.title);
+    emit_current_playback_state(&state);
+    Ok(Json(ApiResponse::success(format!(
+        "Playing chapter {}: {}",
+        request.index, chapter.title
+    ))))
+}
+
+/// Region of the current track played over and over
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AudioLoop {
+    #[schema(example = 30.0)]
+    pub start_seconds: f64,
+    #[schema(example = 45.5)]
+    pub end_seconds: f64,
+}
+
+impl From<PlaybackLoop> for AudioLoop {
+    fn from(region: PlaybackLoop) -> Self {
+        Self {
+            start_seconds: region.start.as_secs_f64(),
+            end_seconds: region.end.as_secs_f64(),
+        }
+    }
+}
+
+/// Loop a region of the current track
+///
+/// Playback seeks back to `start_seconds` whenever it reaches `end_seconds`, which
+/// must come after it and within the track. The loop is cleared when another track
+/// plays or playback stops.
+#[utoipa::path(
+    post,
+    path = "/api/audio/loop",
+    tag = "Audio",
+    request_body = AudioLoop,
+    responses(
+        (status = 200, description = "Loop set", body = ApiResponseAudioLoop),
+        (status = 400, description = "The region is empty or extends past the track", body = ApiErrorResponse),
+        (status = 409, description = "Nothing is playing", body = ApiErrorResponse),
+        (status = 500, description = "The track's duration could not be read", body = ApiErrorResponse),
+    )
+)]
+async fn set_audio_loop(
+    State(state): State<AppState>,
+    Json(request): Json<AudioLoop>,
+) -> Result<Json<ApiResponse<AudioLoop>>, ApiError> {
+    let (Ok(start), Ok(end)) = (
+        Duration::try_from_secs_f64(request.start_seconds),
+        Duration::try_from_secs_f64(request.end_seconds),
+    ) else {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Loop positions must be zero or more seconds",
+        ));
+    };
+
+    state
+        .audio_player
+        .set_loop(start, end)
+        .map_err(|e| match e.downcast_ref::<LoopError>() {
+            Some(LoopError::NothingPlaying | LoopError::TrackChanged) => {
+                ApiError::new(StatusCode::CONFLICT, e.to_string())
+            }
+            Some(LoopError::Empty | LoopError::BeyondTrack(_)) => {
+                ApiError::new(StatusCode::BAD_REQUEST, e.to_string())
+            }
+            None => {
+                error!("Failed to set the loop: {}", e);
+                StatusCode::INTERNAL_SERVER_ERROR.into()
+            }
+        })?;
+
+    info!("Looping {:?} to {:?}", start, end);
+    Ok(Json(ApiResponse::success(request)))
+}
+
+/// Stop looping
+///
+/// Playback carries on to the end of the track.
+#[utoipa::path(
+    delete,
+    path = "/api/audio/loop",
+    tag = "Audio",
+    responses(
+        (status = 200, description = "Loop cleared", body = ApiResponseString),
+    )
+)]
+async fn clear_audio_loop(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    state.audio_player.clear_loop().map_err(|e| {
+        error!("Failed to clear the loop: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(Json(ApiResponse::success("Loop cleared".to_string())))
+}
+
+/// Stop playback after a while
+///
+/// Playback stops after `minutes`, with the usual `playback_state` event followed by
+/// a `sleep_timer_fired` event. With `fade`, the volume ramps down over the last 30
+/// seconds first. Starting a timer replaces the one already set.
+#[utoipa::path(
+    post,
+    path = "/api/audio/sleep-timer",
+    tag = "Audio",
+    request_body = SleepTimerRequest,
+    responses(
+        (status = 200, description = "Sleep timer set", body = ApiResponseSleepTimer),
+        (status = 400, description = "The number of minutes is not positive or longer than a day", body = ApiErrorResponse),
+    )
+)]
+async fn set_sleep_timer(
+    State(state): State<AppState>,
+    Json(request): Json<SleepTimerRequest>,
+) -> Result<Json<ApiResponse<SleepTimerStatus>>, ApiError> {
+    if !(request.minutes > 0.0 && request.minutes <= MAX_SLEEP_MINUTES) {
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Sleep timers last more than 0 and at most {} minutes",
+                MAX_SLEEP_MINUTES
+            ),
+        ));
+    }
+
+    let after = Duration::from_secs_f64(request.minutes * 60.0);
+    let status = state.sleep_timer.start(&state, after, request.fade);
+    info!("Sleep timer set for {:?}", after);
+    Ok(Json(ApiResponse::success(status)))
+}
 
-    let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", port)).await?;
-    info!("API server started on http://127.0.0.1:{}", port);
+/// Time left on the sleep timer
+#[utoipa::path(
+    get,
+    path = "/api/audio/sleep-timer",
+    tag = "Audio",
+    responses(
+        (status = 200, description = "Sleep timer under way", body = ApiResponseSleepTimer),
+        (status = 404, description = "No sleep timer is set", body = ApiErrorResponse),
+    )
+)]
+async fn get_sleep_timer(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<SleepTimerStatus>>, ApiError> {
+    state
+        .sleep_timer
+        .status()
+        .map(|status| Json(ApiResponse::success(status)))
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "No sleep timer is set"))
+}
+
+/// Cancel the sleep timer
+///
+/// Playback carries on, at full volume again if the timer was fading it out.
+#[utoipa::path(
+    delete,
+    path = "/api/audio/sleep-timer",
+    tag = "Audio",
+    responses(
+        (status = 200, description = "Sleep timer cancelled, or none was set", body = ApiResponseString),
+    )
+)]
+async fn cancel_sleep_timer(
+    State(state): State<AppState>,
+) -> Result<Json<ApiResponse<String>>, ApiError> {
+    let message = if state.sleep_timer.cancel(&state) {
+        info!("Sleep timer cancelled");
+        "Sleep timer cancelled"
+    } else {
+        "No sleep timer was set"
+    };
+    Ok(Json(ApiResponse::success(message.to_string())))
+}
+
+/// Re-broadcast the current playback state so remotes pick up settings changes.
+fn emit_current_playback_state(state: &AppState) {
+    let playback_state = format!("{:?}", state.audio_player.get_state()).to_lowercase();
+    let track_path = state.audio_player.get_current_track();
+    let (track_id, track_duration) = track_path
+        .as_deref()
+        .map(|path| lookup_track_metadata(state.library.as_ref(), FsPath::new(path)))
+        .unwrap_or((None, None));
+
+    emit_playback_event(
+        state,
+        &playback_state,
+        track_path,
+        track_id,
+        track_duration,
+        state.revision.current(),
+    );
+}
+
+/// Bind the API port, failing with a readable error when it is taken.
+pub async fn bind_server(port: u16) -> Result<TcpListener> {
+    TcpListener::bind(format!("127.0.0.1:{}", port))
+        .await
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::AddrInUse => anyhow::anyhow!(
+                "API port {} is already in use; set api.port to a free port",
+                port
+            ),
+            _ => anyhow::anyhow!("cannot listen on 127.0.0.1:{}: {}", port, e),
+        })
+}
+
+/// Serve the API on an already bound listener.
+pub async fn serve(listener: TcpListener, state: AppState) -> Result<()> {
+    let app = create_router(state);
+    if let Ok(address) = listener.local_addr() {
+        info!("API server started on http://{}", address);
+    }
 
     axum::serve(listener, app).await?;
     Ok(())
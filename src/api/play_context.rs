@@ -0,0 +1,194 @@
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{ApiError, AppState, TrackResponse};
+use crate::library::{artist_identifier, Track};
+
+/// Tracks listed on each side of the current one in a [`QueueWindow`]
+pub const QUEUE_WINDOW_TRACKS: usize = 3;
+
+/// Kind of list a track is played from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayContextType {
+    Album,
+    Playlist,
+    Artist,
+}
+
+impl PlayContextType {
+    pub(super) fn as_str(&self) -> &'static str {
+        match self {
+            PlayContextType::Album => "album",
+            PlayContextType::Playlist => "playlist",
+            PlayContextType::Artist => "artist",
+        }
+    }
+}
+
+/// The album, playlist or artist a track is played from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PlayContext {
+    #[serde(rename = "type")]
+    #[schema(example = "album")]
+    pub context_type: PlayContextType,
+    /// Album identifier, playlist identifier or artist name
+    #[schema(example = "1f3870be274f6c49b3e31a0c6728957f")]
+    pub id: String,
+}
+
+/// Play a track from an album, playlist or artist request
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PlayContextRequest {
+    pub context: PlayContext,
+    /// Track to start with; it must be part of the context
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub track_id: String,
+}
+
+/// The queue around its current track
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct QueueWindow {
+    /// Up to three tracks played before the current one, in queue order
+    pub previous: Vec<TrackResponse>,
+    pub current: TrackResponse,
+    /// Up to three tracks played after the current one, in queue order
+    pub next: Vec<TrackResponse>,
+    /// Position of the current track in the queue
+    #[schema(example = 4)]
+    pub position: usize,
+    /// Number of queued tracks
+    #[schema(example = 12)]
+    pub length: usize,
+}
+
+/// Tracks of a context in the order they are listed: albums by track number,
+/// playlists in their play order and artists album by album, oldest first.
+pub(super) fn context_track_ids(
+    state: &AppState,
+    context: &PlayContext,
+) -> Result<Vec<String>, ApiError> {
+    let mut tracks = match context.context_type {
+        PlayContextType::Playlist => {
+            let playlist = state
+                .playlist_manager
+                .get_playlist(&context.id)
+                .ok_or_else(|| unknown_context(context))?;
+            return Ok(playlist.tracks_in_play_order(&state.library));
+        }
+        PlayContextType::Album => state
+            .album_service
+            .album_tracks(&state.library, &context.id),
+        PlayContextType::Artist => {
+            let artist_id = artist_identifier(&context.id);
+            state
+                .library
+                .get_tracks()
+                .into_iter()
+                .filter(|track| {
+                    let artist = track
+                        .metadata
+                        .album_artist
+                        .as_deref()
+                        .or(track.metadata.artist.as_deref());
+                    artist_id.is_some() && artist.and_then(artist_identifier) == artist_id
+                })
+                .collect()
+        }
+    };
+    if tracks.is_empty() {
+        return Err(unknown_context(context));
+    }
+
+    if context.context_type == PlayContextType::Artist {
+        tracks.sort_by_cached_key(|track| {
+            (
+                track.metadata.year.is_none(),
+                track.metadata.year,
+                sort_text(&track.metadata.album),
+                album_position(track),
+            )
+        });
+    } else {
+        tracks.sort_by_cached_key(album_position);
+    }
+    Ok(tracks.into_iter().map(|track| track.id).collect())
+}
+
+/// Position of `track_id` in the tracks of a context; 400 when it is not one of them.
+pub(super) fn context_position(
+    track_ids: &[String],
+    track_id: &str,
+    context: &PlayContext,
+) -> Result<usize, ApiError> {
+    track_ids
+        .iter()
+        .position(|id| id == track_id)
+        .ok_or_else(|| {
+            ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Track {} is not in {} {}",
+                    track_id,
+                    context.context_type.as_str(),
+                    context.id
+                ),
+            )
+        })
+}
+
+/// The queue around its current track; `None` when nothing is current. Tracks that
+/// have since been removed from the library are left out.
+pub(super) fn queue_window(state: &AppState) -> Option<QueueWindow> {
+    let position = state.playback_queue.current_index()?;
+    let track_ids = state.playback_queue.track_ids();
+    let response = |track_id: &String| {
+        state
+            .library
+            .get_track(track_id)
+            .map(|track| TrackResponse::with_stats(&track, &state.stats_store))
+    };
+
+    let current = response(track_ids.get(position)?)?;
+    let previous = track_ids[position.saturating_sub(QUEUE_WINDOW_TRACKS)..position]
+        .iter()
+        .filter_map(response)
+        .collect();
+    let next = track_ids[position + 1..]
+        .iter()
+        .take(QUEUE_WINDOW_TRACKS)
+        .filter_map(response)
+        .collect();
+    Some(QueueWindow {
+        previous,
+        current,
+        next,
+        position,
+        length: track_ids.len(),
+    })
+}
+
+fn unknown_context(context: &PlayContext) -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_FOUND,
+        format!("Unknown {} {}", context.context_type.as_str(), context.id),
+    )
+}
+
+/// Order of a track within its album: by track number, unnumbered tracks last
+pub(super) fn album_position(track: &Track) -> (bool, Option<u32>, (bool, String), String) {
+    (
+        track.metadata.track_number.is_none(),
+        track.metadata.track_number,
+        sort_text(&track.metadata.title),
+        track.metadata.file_path.to_string_lossy().to_string(),
+    )
+}
+
+fn sort_text(value: &Option<String>) -> (bool, String) {
+    match value {
+        Some(value) => (false, value.to_lowercase()),
+        None => (true, String::new()),
+    }
+}
@@ -0,0 +1,124 @@
+use anyhow::Result;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use super::{active_track_path, AppState};
+use crate::audio::AudioState;
+
+/// Positions this close to the end count as having finished the track
+const FINISHED_MARGIN: Duration = Duration::from_secs(10);
+/// How often the position of a playing track is written to disk
+const SAVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Remembers where playback of long files, such as audiobooks and mixes, was left
+/// off, so playing them again resumes from there.
+///
+/// Positions are kept in the stats store. They are saved while the track plays and
+/// when it is paused, stopped or replaced; finishing the track forgets its position.
+pub struct ResumePositions {
+    /// Shortest duration of the tracks whose position is remembered, in seconds;
+    /// 0 disables resuming
+    min_duration: AtomicU64,
+    /// When a position was last written to disk
+    last_saved: Mutex<Option<Instant>>,
+}
+
+impl ResumePositions {
+    /// Remember the position of tracks lasting at least `min_duration_seconds`.
+    pub fn new(min_duration_seconds: u64) -> Self {
+        Self {
+            min_duration: AtomicU64::new(min_duration_seconds),
+            last_saved: Mutex::new(None),
+        }
+    }
+
+    pub fn set_min_duration(&self, min_duration_seconds: u64) {
+        self.min_duration
+            .store(min_duration_seconds, Ordering::Relaxed);
+    }
+
+    /// Whether the position of a track lasting `duration` seconds is remembered
+    pub fn applies(&self, duration: Option<u64>) -> bool {
+        let min_duration = self.min_duration.load(Ordering::Relaxed);
+        min_duration > 0 && duration.is_some_and(|duration| duration >= min_duration)
+    }
+
+    /// Saved position to start `path` from; `None` to start from the beginning.
+    pub fn start_position(&self, state: &AppState, path: &Path) -> Option<Duration> {
+        let duration = state.library.get_track_by_path(path)?.metadata.duration;
+        if !self.applies(duration) {
+            return None;
+        }
+        state
+            .stats_store
+            .resume_position(path)
+            .map(Duration::from_secs)
+    }
+
+    /// Play `path` from its saved position, or from the beginning when `from_start`
+    /// is set. The position of the track playing before is saved first.
+    pub fn play(&self, state: &AppState, path: &Path, from_start: bool) -> Result<()> {
+        if let Some(previous) = active_track_path(state) {
+            self.save(state, Path::new(&previous));
+        }
+        let start = if from_start {
+            None
+        } else {
+            self.start_position(state, path)
+        };
+
+        if let Some(start) = start {
+            info!("Resuming {:?} at {}s", path, start.as_secs());
+        }
+        state
+            .audio_player
+            .play_from(path, start.unwrap_or_default())
+    }
+
+    /// Save the position of the loaded track `path`, e.g. when it is paused or
+    /// stopped.
+    pub fn save(&self, state: &AppState, path: &Path) {
+        self.record(state, path, true);
+    }
+
+    /// Keep the position of the playing track current, writing it to disk at most
+    /// every [`SAVE_INTERVAL`].
+    pub fn tick(&self, state: &AppState) {
+        if state.audio_player.get_state() != AudioState::Playing {
+            return;
+        }
+        let Some(path) = state.audio_player.get_current_track() else {
+            return;
+        };
+        self.record(state, Path::new(&path), false);
+    }
+
+    fn record(&self, state: &AppState, path: &Path, persist: bool) {
+        let Some(duration) = state
+            .library
+            .get_track_by_path(path)
+            .and_then(|track| track.metadata.duration)
+            .filter(|duration| self.applies(Some(*duration)))
+        else {
+            return;
+        };
+
+        let elapsed = state.audio_player.get_position();
+        let finished = Duration::from_secs(duration).saturating_sub(elapsed) <= FINISHED_MARGIN;
+        let position = Some(elapsed.as_secs()).filter(|position| !finished && *position > 0);
+        let changed = state.stats_store.record_resume_position(path, position);
+
+        let mut last_saved = self.last_saved.lock().unwrap();
+        let due = last_saved.is_none_or(|saved| saved.elapsed() >= SAVE_INTERVAL);
+        // Finishing is written right away, so a restart does not resume a finished track
+        if persist || (changed && (due || position.is_none())) {
+            *last_saved = Some(Instant::now());
+            if let Err(e) = state.stats_store.save() {
+                warn!("Failed to save the resume position of {:?}: {}", path, e);
+            }
+        }
+    }
+}
@@ -0,0 +1,46 @@
+use std::sync::{RwLock, RwLockWriteGuard};
+
+/// Revision of the playback state and volume, increased by every change made through
+/// the API.
+///
+/// Clients pass the revision they last saw as `if_revision` to make a change
+/// conditional on nobody else having changed playback in the meantime. Changes are
+/// serialized: the revision stays locked from the precondition check until the change
+/// and its events are done, so events carry the revision of the change they report.
+#[derive(Debug, Default)]
+pub struct PlaybackRevision {
+    value: RwLock<u64>,
+}
+
+impl PlaybackRevision {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn current(&self) -> u64 {
+        *self.value.read().unwrap()
+    }
+
+    /// Start a change, or fail with the current revision when `expected` is given and
+    /// no longer current.
+    pub fn begin(&self, expected: Option<u64>) -> Result<RevisionChange<'_>, u64> {
+        let value = self.value.write().unwrap();
+        match expected {
+            Some(expected) if expected != *value => Err(*value),
+            _ => Ok(RevisionChange { value }),
+        }
+    }
+}
+
+/// A change in progress; other changes wait until it is dropped.
+pub struct RevisionChange<'a> {
+    value: RwLockWriteGuard<'a, u64>,
+}
+
+impl RevisionChange<'_> {
+    /// Record that the change happened and return the new revision.
+    pub fn commit(&mut self) -> u64 {
+        *self.value += 1;
+        *self.value
+    }
+}
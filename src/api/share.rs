@@ -0,0 +1,192 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use anyhow::{anyhow, Result};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+/// Hours a share link lasts when no expiry is asked for
+pub const DEFAULT_SHARE_HOURS: u32 = 24 * 7;
+/// Longest a share link may last, in hours
+pub const MAX_SHARE_HOURS: u32 = 24 * 365;
+
+/// Routes answering share links, which need no credentials: the guest policy lets
+/// them through whatever token a request carries. Creating and revoking links is not
+/// among them.
+pub const SHARE_PUBLIC_ROUTES: &[&str] = &[
+    "/api/share/:token",
+    "/api/share/:token/artwork",
+    "/api/share/:token/tracks/:track_id/stream",
+];
+
+/// What a share link shows
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ShareScope {
+    /// One track
+    Track {
+        #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+        id: String,
+    },
+    /// The tracks of an album
+    Album {
+        #[schema(example = "1f3870be274f6c49b3e31a0c6728957f")]
+        id: String,
+    },
+    /// Whichever track is playing when the link is opened
+    NowPlaying,
+}
+
+/// Contents of a share link, signed so they cannot be altered
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareClaims {
+    pub scope: ShareScope,
+    #[serde(with = "chrono::serde::ts_seconds")]
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Why a share link was refused
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ShareError {
+    /// Malformed, altered or signed with a secret that was since rotated
+    #[error("Unknown or revoked share link")]
+    Invalid,
+    #[error("This share link expired at {}", .expires_at.to_rfc3339())]
+    Expired { expires_at: DateTime<Utc> },
+}
+
+/// Signs and checks share links with a secret kept in the configuration directory.
+/// Links carry their scope and expiry, so nothing is stored per link; rotating the
+/// secret revokes every link handed out so far.
+pub struct ShareSigner {
+    path: PathBuf,
+    secret: RwLock<Vec<u8>>,
+    allow_stream: bool,
+}
+
+impl ShareSigner {
+    /// Use the secret stored at `path`, creating one when there is none.
+    /// `allow_stream` is `api.share_allow_stream`.
+    pub fn load(path: PathBuf, allow_stream: bool) -> Result<Self> {
+        let stored = match fs::read_to_string(&path) {
+            Ok(text) => Some(
+                decode_hex(text.trim())
+                    .filter(|secret| !secret.is_empty())
+                    .ok_or_else(|| anyhow!("The share secret in {:?} is malformed", path))?,
+            ),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error.into()),
+        };
+        let secret = match stored {
+            Some(secret) => secret,
+            None => {
+                let secret = new_secret();
+                store_secret(&path, &secret)?;
+                secret
+            }
+        };
+        Ok(Self {
+            path,
+            secret: RwLock::new(secret),
+            allow_stream,
+        })
+    }
+
+    /// Whether shared tracks may be streamed
+    pub fn allow_stream(&self) -> bool {
+        self.allow_stream
+    }
+
+    /// Token for a link sharing `claims`
+    pub fn sign(&self, claims: &ShareClaims) -> String {
+        let payload = URL_SAFE_NO_PAD
+            .encode(serde_json::to_vec(claims).expect("share claims serialize to JSON"));
+        let mac = self.mac(payload.as_bytes()).finalize().into_bytes();
+        format!("{}.{}", payload, URL_SAFE_NO_PAD.encode(mac))
+    }
+
+    /// The claims of `token` if it was signed with the current secret and has not
+    /// expired by `now`
+    pub fn verify(&self, token: &str, now: DateTime<Utc>) -> Result<ShareClaims, ShareError> {
+        let (payload, mac) = token.split_once('.').ok_or(ShareError::Invalid)?;
+        let mac = URL_SAFE_NO_PAD
+            .decode(mac)
+            .map_err(|_| ShareError::Invalid)?;
+        self.mac(payload.as_bytes())
+            .verify_slice(&mac)
+            .map_err(|_| ShareError::Invalid)?;
+        let claims: ShareClaims = URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .ok_or(ShareError::Invalid)?;
+        if claims.expires_at <= now {
+            return Err(ShareError::Expired {
+                expires_at: claims.expires_at,
+            });
+        }
+        Ok(claims)
+    }
+
+    /// HMAC-SHA256 of `message` under the current secret
+    fn mac(&self, message: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret.read().unwrap())
+            .expect("HMAC takes keys of any length");
+        mac.update(message);
+        mac
+    }
+
+    /// Replace the secret, revoking every link signed so far
+    pub fn rotate(&self) -> Result<()> {
+        let secret = new_secret();
+        store_secret(&self.path, &secret)?;
+        *self.secret.write().unwrap() = secret;
+        Ok(())
+    }
+}
+
+/// 256 random bits
+fn new_secret() -> Vec<u8> {
+    let mut secret = vec![0; 32];
+    OsRng.fill_bytes(&mut secret);
+    secret
+}
+
+/// Write `secret` as hex, readable by the owner only
+fn store_secret(path: &Path, secret: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let hex: String = secret.iter().map(|byte| format!("{:02x}", byte)).collect();
+    let temp_path = path.with_extension("tmp");
+    fs::write(&temp_path, hex)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&temp_path, fs::Permissions::from_mode(0o600))?;
+    }
+    if let Err(error) = fs::rename(&temp_path, path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
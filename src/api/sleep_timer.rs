@@ -0,0 +1,181 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use axum::extract::{Query, State};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinHandle;
+use tokio::time::{sleep_until, Instant};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use super::{stop_audio, AppState, RevisionQuery};
+use crate::audio::AudioState;
+use crate::events::EventPayload;
+
+/// How long a fading sleep timer takes to bring the volume down before it stops
+pub const SLEEP_FADE: Duration = Duration::from_secs(30);
+/// Longest sleep timer accepted, in minutes
+pub const MAX_SLEEP_MINUTES: f64 = 24.0 * 60.0;
+/// How long the volume takes to come back when a timer is cancelled mid-fade
+const RESTORE_FADE: Duration = Duration::from_millis(500);
+
+/// Request to stop playback after a while
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SleepTimerRequest {
+    /// Minutes until playback stops, fractions allowed
+    #[schema(example = 30)]
+    pub minutes: f64,
+    /// Fade the volume out over the last 30 seconds, or the whole timer when shorter
+    #[serde(default)]
+    pub fade: bool,
+}
+
+/// A sleep timer under way
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct SleepTimerStatus {
+    /// Seconds until playback stops
+    #[schema(example = 1799.5)]
+    pub remaining_seconds: f64,
+    pub fade: bool,
+    /// Whether the volume is fading out already
+    pub fading: bool,
+}
+
+struct Scheduled {
+    id: u64,
+    fires_at: Instant,
+    /// When the fade-out starts, for timers that fade
+    fade_from: Option<Instant>,
+    task: JoinHandle<()>,
+}
+
+impl Scheduled {
+    fn status(&self, now: Instant) -> SleepTimerStatus {
+        SleepTimerStatus {
+            remaining_seconds: self.fires_at.saturating_duration_since(now).as_secs_f64(),
+            fade: self.fade_from.is_some(),
+            fading: self.fade_from.is_some_and(|from| now >= from),
+        }
+    }
+}
+
+/// The sleep timer, of which there is at most one: starting another replaces it.
+///
+/// When the timer fires, playback is stopped as `POST /api/audio/stop` would, with
+/// its `playback_state` event, and a `sleep_timer_fired` event follows. A timer that
+/// fades ramps the output down beforehand without touching the volume setting;
+/// resuming from pause or playing another track during the fade brings the level
+/// back up, but the timer still stops playback when it fires.
+#[derive(Default)]
+pub struct SleepTimer {
+    scheduled: Mutex<Option<Scheduled>>,
+    next_id: AtomicU64,
+}
+
+impl SleepTimer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop playback after `after`, replacing any timer already set
+    pub fn start(&self, state: &AppState, after: Duration, fade: bool) -> SleepTimerStatus {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let now = Instant::now();
+        let fires_at = now + after;
+        let fade_from = fade.then(|| fires_at - SLEEP_FADE.min(after));
+        let task = tokio::spawn(fire(state.clone(), id, fires_at, fade_from));
+
+        let scheduled = Scheduled {
+            id,
+            fires_at,
+            fade_from,
+            task,
+        };
+        let status = scheduled.status(now);
+        let replaced = self.scheduled.lock().unwrap().replace(scheduled);
+        if let Some(replaced) = replaced {
+            Self::abort(state, replaced);
+        }
+        status
+    }
+
+    /// Cancel the timer, bringing the volume back if it was fading; `false` when none
+    /// was set
+    pub fn cancel(&self, state: &AppState) -> bool {
+        let cancelled = self.scheduled.lock().unwrap().take();
+        match cancelled {
+            Some(scheduled) => {
+                Self::abort(state, scheduled);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The timer under way, if any
+    pub fn status(&self) -> Option<SleepTimerStatus> {
+        self.scheduled
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|scheduled| scheduled.status(Instant::now()))
+    }
+
+    fn abort(state: &AppState, scheduled: Scheduled) {
+        scheduled.task.abort();
+        if scheduled
+            .fade_from
+            .is_some_and(|from| Instant::now() >= from)
+        {
+            if let Err(e) = state.audio_player.fade_volume(1.0, RESTORE_FADE) {
+                warn!(
+                    "Failed to restore the volume faded by the sleep timer: {}",
+                    e
+                );
+            }
+        }
+    }
+
+    /// Forget timer `id` as it fires; `false` when it was replaced or cancelled meanwhile
+    fn take_fired(&self, id: u64) -> bool {
+        let mut scheduled = self.scheduled.lock().unwrap();
+        if scheduled
+            .as_ref()
+            .is_some_and(|scheduled| scheduled.id == id)
+        {
+            *scheduled = None;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+async fn fire(state: AppState, id: u64, fires_at: Instant, fade_from: Option<Instant>) {
+    if let Some(fade_from) = fade_from {
+        sleep_until(fade_from).await;
+        if let Err(e) = state
+            .audio_player
+            .fade_volume(0.0, fires_at.saturating_duration_since(fade_from))
+        {
+            warn!("Failed to fade out for the sleep timer: {}", e);
+        }
+    }
+    sleep_until(fires_at).await;
+    if !state.sleep_timer.take_fired(id) {
+        return;
+    }
+
+    let track_path = state.audio_player.get_current_track();
+    if state.audio_player.get_state() != AudioState::Stopped {
+        if let Err(e) = stop_audio(State(state.clone()), Query(RevisionQuery::default())).await {
+            warn!("The sleep timer could not stop playback: {}", e.message);
+        }
+    }
+    info!("Sleep timer fired");
+    state.event_bus.emit(EventPayload::sleep_timer_fired(
+        track_path,
+        fade_from.is_some(),
+    ));
+}
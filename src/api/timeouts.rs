@@ -0,0 +1,116 @@
+use std::time::Duration;
+
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::{Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tracing::warn;
+
+use super::ApiError;
+use crate::config::RouteTimeouts;
+
+/// Default `api.timeouts.status_secs`
+pub const DEFAULT_STATUS_TIMEOUT_SECS: u64 = 5;
+/// Default `api.timeouts.default_secs`
+pub const DEFAULT_ROUTE_TIMEOUT_SECS: u64 = 30;
+/// Default `api.timeouts.long_secs`
+pub const DEFAULT_LONG_TIMEOUT_SECS: u64 = 600;
+
+/// Routes polled for status, which must answer quickly or not at all. Only reads
+/// get the status budget; changes through the same route get the default one.
+const STATUS_ROUTES: &[&str] = &[
+    "/api/health",
+    "/api/setup/status",
+    "/api/library/scan/status",
+    "/api/jobs/:id",
+    "/api/audio/status",
+    "/api/audio/device",
+];
+
+/// Routes reading or writing many files within the request
+const LONG_ROUTES: &[&str] = &[
+    "/api/maintenance",
+    "/api/setup/initialize",
+    "/api/library/genres/retag",
+    "/api/library/import/tag-stats",
+    "/api/library/waveforms",
+    "/api/library/tracks/:id/waveform",
+    "/api/library/tracks/bulk",
+    "/api/library/duplicates/:group_id/resolve",
+    "/api/library/albums/:id/edit",
+    "/api/playlists/import/csv",
+];
+
+/// How long requests may take before they are answered with 504, by kind of route.
+/// A zero budget never times out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteBudgets {
+    /// Health and status checks
+    pub status: Duration,
+    /// Routes not listed as status or long ones
+    pub default: Duration,
+    /// Maintenance, setup, imports and edits of many files
+    pub long: Duration,
+}
+
+impl RouteBudgets {
+    /// Budget of `method` requests to the route matching `path`, as registered with
+    /// the router
+    pub fn for_route(&self, method: &Method, path: &str) -> Duration {
+        if method == Method::GET && STATUS_ROUTES.contains(&path) {
+            self.status
+        } else if LONG_ROUTES.contains(&path) {
+            self.long
+        } else {
+            self.default
+        }
+    }
+}
+
+impl From<&RouteTimeouts> for RouteBudgets {
+    fn from(timeouts: &RouteTimeouts) -> Self {
+        Self {
+            status: Duration::from_secs(timeouts.status_secs),
+            default: Duration::from_secs(timeouts.default_secs),
+            long: Duration::from_secs(timeouts.long_secs),
+        }
+    }
+}
+
+impl Default for RouteBudgets {
+    fn default() -> Self {
+        Self::from(&RouteTimeouts::default())
+    }
+}
+
+/// Answer requests whose handler outlives the budget of their route with 504.
+///
+/// The handler is dropped at that point; work it handed to a blocking thread carries
+/// on, so long-running jobs should report their outcome through events instead.
+pub(super) async fn enforce_route_budget(
+    State(budgets): State<RouteBudgets>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let path = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| request.uri().path().to_string());
+    let budget = budgets.for_route(request.method(), &path);
+    if budget.is_zero() {
+        return next.run(request).await;
+    }
+
+    match tokio::time::timeout(budget, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => {
+            warn!("{} did not answer within {:?}", path, budget);
+            ApiError::new(
+                StatusCode::GATEWAY_TIMEOUT,
+                format!("The request did not finish within {:?}", budget),
+            )
+            .into_response()
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use anyhow::{bail, Result};
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use std::fs;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tokio::net::UnixListener;
+use tracing::{debug, info};
+
+use super::{create_router, AppState};
+use crate::utils::ensure_directory;
+
+/// Serve the API on a Unix domain socket at `path`.
+///
+/// A socket file left behind by a previous run is replaced; a socket another process
+/// still listens on, or any other kind of file, is an error. The socket is only
+/// accessible to the current user. WebSocket upgrades work as over TCP.
+pub async fn serve_unix_socket(state: AppState, path: PathBuf) -> Result<()> {
+    let listener = bind_unix_socket(&path)?;
+    let app = create_router(state);
+    info!("API server listening on unix socket {:?}", path);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let service = TowerToHyperService::new(app.clone());
+
+        tokio::spawn(async move {
+            let connection = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades();
+            if let Err(error) = connection.await {
+                debug!("Unix socket connection error: {}", error);
+            }
+        });
+    }
+}
+
+fn bind_unix_socket(path: &Path) -> Result<UnixListener> {
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            bail!("{} exists and is not a socket", path.display());
+        }
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            bail!("another process is already listening on {}", path.display());
+        }
+        fs::remove_file(path)?;
+        info!("Removed stale API socket {:?}", path);
+    }
+
+    if let Some(parent) = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        ensure_directory(parent)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
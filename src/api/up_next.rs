@@ -0,0 +1,139 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::debug;
+
+use super::AppState;
+use crate::audio::AudioState;
+use crate::events::EventPayload;
+use crate::playlist::RepeatMode;
+
+/// How often the playback position is checked
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Watches the playback position and emits an `up_next` event announcing the next
+/// queued track shortly before the current one ends.
+///
+/// Each track is announced once. Nothing is announced while repeat-one is active or
+/// when the queue has no next track. With precaching enabled, the next track is also
+/// fetched into the precache as soon as it is known, whatever the lead time.
+pub struct UpNextWatcher {
+    lead_seconds: AtomicU32,
+    /// Path of the track whose successor was announced
+    announced: Mutex<Option<String>>,
+    /// Path of the track last handed to the precache
+    precached: Mutex<Option<PathBuf>>,
+}
+
+impl UpNextWatcher {
+    /// Announce the next track `lead_seconds` before the current one ends; 0 disables
+    /// the announcements.
+    pub fn new(lead_seconds: u32) -> Self {
+        Self {
+            lead_seconds: AtomicU32::new(lead_seconds),
+            announced: Mutex::new(None),
+            precached: Mutex::new(None),
+        }
+    }
+
+    pub fn set_lead_seconds(&self, lead_seconds: u32) {
+        self.lead_seconds.store(lead_seconds, Ordering::Relaxed);
+    }
+
+    /// Check the playback position every half second until the runtime shuts down,
+    /// also keeping the resume position of long tracks current.
+    pub fn spawn(self: Arc<Self>, state: AppState) {
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(POLL_INTERVAL);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+            loop {
+                ticker.tick().await;
+                self.tick(&state);
+                state.resume_positions.tick(&state);
+            }
+        });
+    }
+
+    /// Check the playback position once, announcing the next track when it is due.
+    pub fn tick(&self, state: &AppState) {
+        self.precache_next(state);
+        let lead = self.lead_seconds.load(Ordering::Relaxed);
+        if lead == 0 || state.audio_player.get_state() != AudioState::Playing {
+            return;
+        }
+        let Some(path) = state.audio_player.get_current_track() else {
+            return;
+        };
+        let Some(duration) = state
+            .library
+            .get_track_by_path(Path::new(&path))
+            .and_then(|track| track.metadata.duration)
+        else {
+            return;
+        };
+
+        // Time left to listen to, which is shorter than the track time left when sped up
+        let remaining = Duration::from_secs(duration)
+            .saturating_sub(state.audio_player.get_position())
+            .div_f64(f64::from(state.audio_player.get_speed()));
+        let mut announced = self.announced.lock().unwrap();
+        if remaining > Duration::from_secs(u64::from(lead)) {
+            // Far from the end again, e.g. after seeking back or replaying the track
+            *announced = None;
+            return;
+        }
+        if announced.as_deref() == Some(path.as_str())
+            || state.playback_queue.get_repeat_mode() == RepeatMode::One
+        {
+            return;
+        }
+        let Some(next) = state
+            .playback_queue
+            .peek_next()
+            .and_then(|track_id| state.library.get_track(&track_id))
+        else {
+            return;
+        };
+
+        *announced = Some(path);
+        let seconds_until = remaining.as_secs_f64().ceil() as u32;
+        state
+            .event_bus
+            .emit(EventPayload::up_next(&next, seconds_until));
+    }
+
+    /// Fetch the next queued track into the precache on a thread of its own, once per
+    /// track, while something is playing.
+    fn precache_next(&self, state: &AppState) {
+        let Some(precache) = state.precache.clone() else {
+            return;
+        };
+        if state.audio_player.get_state() == AudioState::Stopped {
+            return;
+        }
+        let Some(path) = state
+            .playback_queue
+            .peek_next()
+            .and_then(|track_id| state.library.get_track(&track_id))
+            .map(|track| track.metadata.file_path)
+        else {
+            return;
+        };
+
+        let mut precached = self.precached.lock().unwrap();
+        if precached.as_ref() == Some(&path) {
+            return;
+        }
+        *precached = Some(path.clone());
+        let spawned = std::thread::Builder::new()
+            .name("hexendrum-precache".into())
+            .spawn(move || match precache.fetch(&path) {
+                Ok(fetched) => debug!("Precached {:?}: {:?}", path, fetched),
+                Err(e) => debug!("Cannot precache {:?}: {}", path, e),
+            });
+        if let Err(e) = spawned {
+            debug!("Cannot start precaching: {}", e);
+        }
+    }
+}
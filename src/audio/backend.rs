@@ -0,0 +1,507 @@
+use anyhow::{anyhow, Result};
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::{cpal, Sink, Source};
+use std::fs::File;
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, warn};
+
+use super::decode::open_decoder;
+use super::fade::{Fade, FadeOut};
+use super::output::{match_output_device, open_stream, DeviceStream};
+use super::resample::{BitDepthLimiter, LinearResampler};
+use super::silence::{SilenceSkipHandler, SilenceSkipper};
+use super::{probe_source_format, AudioDeviceInfo, OutputFormat, StreamRequest};
+
+/// Output backend driven by the audio thread.
+///
+/// The audio thread owns the backend exclusively, so implementations do not need to be
+/// thread-safe. This is also the seam used by tests to simulate devices.
+pub trait AudioBackend {
+    /// Acquire (or re-acquire) the output device.
+    fn open(&mut self) -> Result<()>;
+
+    /// Whether the device acquired by `open` is still usable.
+    fn is_device_alive(&mut self) -> bool;
+
+    /// Whether the device reports an active route to something listening, such as a
+    /// connected speaker. Only asked while auto-pause is enabled; devices that cannot
+    /// tell report `true`.
+    fn has_active_route(&mut self) -> bool {
+        true
+    }
+
+    /// Human-readable name of the current device, if known.
+    fn device_name(&self) -> Option<String> {
+        None
+    }
+
+    /// Parameters of the stream opened by `open`, if known.
+    fn device_info(&self) -> Option<AudioDeviceInfo> {
+        None
+    }
+
+    /// Names of the output devices that can be selected.
+    fn output_devices(&self) -> Vec<String> {
+        self.device_name().into_iter().collect()
+    }
+
+    /// Use the device matching `name`, or the default device for `None`, from the
+    /// next `open` on.
+    fn select_device(&mut self, _name: Option<String>) {}
+
+    /// Name of the device chosen with `select_device` or configured, `None` for the
+    /// default device.
+    fn selected_device(&self) -> Option<String> {
+        None
+    }
+
+    /// Start playing a file from `start_at`, replacing whatever is currently playing.
+    fn play(&mut self, path: &Path, start_at: Duration, volume: f32) -> Result<()>;
+
+    /// Pause the current source.
+    fn pause(&mut self);
+
+    /// Resume the current source.
+    fn resume(&mut self);
+
+    /// Stop and drop the current source.
+    fn stop(&mut self);
+
+    /// Set the output volume multiplier.
+    fn set_volume(&mut self, volume: f32);
+
+    /// Set the speed factor of the current source and the ones played after it.
+    fn set_speed(&mut self, _speed: f32) {}
+
+    /// Whether the current source has played to its end. Never true once stopped.
+    fn finished(&mut self) -> bool {
+        false
+    }
+
+    /// Queue a file to play after the current source without a gap.
+    fn enqueue(&mut self, _path: &Path) -> Result<()> {
+        Err(anyhow!("This audio backend cannot queue tracks"))
+    }
+
+    /// Number of sources left to play, the current one included.
+    fn source_count(&mut self) -> usize {
+        0
+    }
+
+    /// Set the conversions applied to files played from now on.
+    fn set_output_format(&mut self, _format: OutputFormat) {}
+
+    /// Set what is called when silence is skipped, see [`OutputFormat::skip_silence`].
+    fn set_silence_handler(&mut self, _handler: SilenceSkipHandler) {}
+
+    /// Start previewing a file on a second source mixed into the same output,
+    /// replacing any earlier preview. The main source must be left alone.
+    fn preview_play(&mut self, _path: &Path, _volume: f32) -> Result<()> {
+        Err(anyhow!("This audio backend cannot preview tracks"))
+    }
+
+    /// Stop and drop the preview source.
+    fn preview_stop(&mut self) {}
+
+    /// Set the preview volume multiplier.
+    fn set_preview_volume(&mut self, _volume: f32) {}
+
+    /// Whether the preview source has played to its end.
+    fn preview_finished(&mut self) -> bool {
+        false
+    }
+}
+
+/// Backend playing through the system output device via rodio.
+pub struct RodioBackend {
+    stream: Option<DeviceStream>,
+    device_name: Option<String>,
+    /// Device asked for, see [`match_output_device`]; the default device when unset
+    output_device: Option<String>,
+    sink: Option<Sink>,
+    /// Ramps the sources of `sink` out
+    fade_out: FadeOut,
+    preview: Option<Sink>,
+    format: OutputFormat,
+    /// Speed factor of `sink` and the sinks after it
+    speed: f32,
+    request: StreamRequest,
+    silence_handler: Option<SilenceSkipHandler>,
+}
+
+impl RodioBackend {
+    /// Create a backend without opening a device yet.
+    pub fn new() -> Self {
+        Self::with_stream_request(StreamRequest::default())
+    }
+
+    /// Create a backend that opens devices with the requested parameters where
+    /// they are supported.
+    pub fn with_stream_request(request: StreamRequest) -> Self {
+        Self {
+            stream: None,
+            device_name: None,
+            output_device: None,
+            sink: None,
+            fade_out: FadeOut::new(),
+            preview: None,
+            format: OutputFormat::default(),
+            speed: 1.0,
+            request,
+            silence_handler: None,
+        }
+    }
+
+    /// Open the device matching `name` rather than the default one, see
+    /// [`match_output_device`]
+    pub fn with_output_device(mut self, name: Option<String>) -> Self {
+        self.output_device = name;
+        self
+    }
+
+    /// The device to open: the one asked for, or the default one when it is missing
+    fn output_device(&self) -> Result<cpal::Device> {
+        let host = cpal::default_host();
+        if let Some(wanted) = self.output_device.as_deref() {
+            let mut devices: Vec<(String, cpal::Device)> = host
+                .output_devices()
+                .map(|devices| {
+                    devices
+                        .filter_map(|device| Some((device.name().ok()?, device)))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let names: Vec<String> = devices.iter().map(|(name, _)| name.clone()).collect();
+            match match_output_device(&names, wanted) {
+                Some(index) => return Ok(devices.swap_remove(index).1),
+                None => warn!(
+                    "Audio output device {:?} not found, using the default device",
+                    wanted
+                ),
+            }
+        }
+        host.default_output_device()
+            .ok_or_else(|| anyhow!("No audio output device available"))
+    }
+
+    /// Decode `path` from `start_at` with the conversions of the output format
+    fn open_source(
+        &self,
+        path: &Path,
+        start_at: Duration,
+    ) -> Result<Box<dyn Source<Item = f32> + Send>> {
+        let decoder = open_decoder(path)?;
+
+        let mut source: Box<dyn Source<Item = f32> + Send> = if start_at.is_zero() {
+            decoder
+        } else {
+            Box::new(decoder.skip_duration(start_at))
+        };
+
+        if let Some(settings) = self.format.skip_silence {
+            source = Box::new(SilenceSkipper::new(
+                source,
+                settings,
+                start_at,
+                self.silence_handler.clone(),
+            ));
+        }
+
+        if let Some(rate) = self.format.resample_to.filter(|rate| *rate > 0) {
+            if source.sample_rate() != rate {
+                debug!(
+                    "Resampling {:?} from {} Hz to {} Hz",
+                    path,
+                    source.sample_rate(),
+                    rate
+                );
+                source = Box::new(LinearResampler::new(source, rate));
+            }
+        }
+
+        if let Some(bits) = self.format.bit_depth_fallback {
+            let source_bits = probe_source_format(path)
+                .ok()
+                .and_then(|format| format.bits_per_sample);
+            if source_bits.is_some_and(|source_bits| source_bits > u32::from(bits)) {
+                debug!("Reducing {:?} to {} bits per sample", path, bits);
+                source = Box::new(BitDepthLimiter::new(source, bits));
+            }
+        }
+
+        Ok(source)
+    }
+}
+
+impl Default for RodioBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AudioBackend for RodioBackend {
+    fn open(&mut self) -> Result<()> {
+        self.stop();
+        self.preview_stop();
+        self.stream = None;
+
+        let device = self.output_device()?;
+        let stream = open_stream(&device, self.request)?;
+
+        debug!("Opened audio output device {:?}", stream.info);
+        self.device_name = stream.info.device_name.clone();
+        self.stream = Some(stream);
+        Ok(())
+    }
+
+    fn is_device_alive(&mut self) -> bool {
+        if self.stream.is_none() {
+            return false;
+        }
+
+        let Some(name) = self.device_name.as_deref() else {
+            return true;
+        };
+
+        match cpal::default_host().output_devices() {
+            Ok(mut devices) => devices.any(|device| device.name().ok().as_deref() == Some(name)),
+            Err(_) => false,
+        }
+    }
+
+    fn device_name(&self) -> Option<String> {
+        self.device_name.clone()
+    }
+
+    fn device_info(&self) -> Option<AudioDeviceInfo> {
+        self.stream.as_ref().map(|stream| stream.info.clone())
+    }
+
+    fn output_devices(&self) -> Vec<String> {
+        match cpal::default_host().output_devices() {
+            Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn select_device(&mut self, name: Option<String>) {
+        self.output_device = name;
+    }
+
+    fn selected_device(&self) -> Option<String> {
+        self.output_device.clone()
+    }
+
+    fn play(&mut self, path: &Path, start_at: Duration, volume: f32) -> Result<()> {
+        self.stop();
+
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or_else(|| anyhow!("Audio output device is not open"))?;
+        let source = self.open_source(path, start_at)?;
+        let fade_out = FadeOut::new();
+
+        let (sink, queue) = Sink::new_idle();
+        stream.mixer.add(queue);
+        sink.set_volume(volume);
+        sink.set_speed(self.speed);
+        sink.append(Fade::new(source, self.format.fade, fade_out.clone()));
+        sink.play();
+
+        self.sink = Some(sink);
+        self.fade_out = fade_out;
+        Ok(())
+    }
+
+    fn enqueue(&mut self, path: &Path) -> Result<()> {
+        let sink = self
+            .sink
+            .as_ref()
+            .ok_or_else(|| anyhow!("Nothing is playing"))?;
+        // Decoded up front, so a broken file is refused rather than cut short
+        sink.append(Fade::continuing(
+            self.open_source(path, Duration::ZERO)?,
+            self.format.fade,
+            self.fade_out.clone(),
+        ));
+        Ok(())
+    }
+
+    fn source_count(&mut self) -> usize {
+        self.sink.as_ref().map_or(0, Sink::len)
+    }
+
+    fn pause(&mut self) {
+        if let Some(sink) = self.sink.as_ref() {
+            sink.pause();
+        }
+    }
+
+    fn resume(&mut self) {
+        if let Some(sink) = self.sink.as_ref() {
+            sink.play();
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            if self.format.fade.is_zero() || sink.is_paused() {
+                sink.stop();
+            } else {
+                // Left to play its ramp-out, after which its sources end
+                self.fade_out.trigger();
+                sink.detach();
+            }
+        }
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        if let Some(sink) = self.sink.as_ref() {
+            sink.set_volume(volume);
+        }
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+        if let Some(sink) = self.sink.as_ref() {
+            sink.set_speed(speed);
+        }
+    }
+
+    fn finished(&mut self) -> bool {
+        self.sink.as_ref().is_some_and(Sink::empty)
+    }
+
+    fn set_output_format(&mut self, format: OutputFormat) {
+        self.format = format;
+    }
+
+    fn set_silence_handler(&mut self, handler: SilenceSkipHandler) {
+        self.silence_handler = Some(handler);
+    }
+
+    fn preview_play(&mut self, path: &Path, volume: f32) -> Result<()> {
+        self.preview_stop();
+
+        let stream = self
+            .stream
+            .as_ref()
+            .ok_or_else(|| anyhow!("Audio output device is not open"))?;
+
+        let decoder = open_decoder(path)?;
+
+        // The mixer converts the rate itself; the output format only applies to
+        // main playback.
+        let (sink, queue) = Sink::new_idle();
+        stream.mixer.add(queue);
+        sink.set_volume(volume);
+        sink.append(decoder);
+        sink.play();
+
+        self.preview = Some(sink);
+        Ok(())
+    }
+
+    fn preview_stop(&mut self) {
+        if let Some(sink) = self.preview.take() {
+            sink.stop();
+        }
+    }
+
+    fn set_preview_volume(&mut self, volume: f32) {
+        if let Some(sink) = self.preview.as_ref() {
+            sink.set_volume(volume);
+        }
+    }
+
+    fn preview_finished(&mut self) -> bool {
+        self.preview.as_ref().is_some_and(Sink::empty)
+    }
+}
+
+/// Backend that discards audio, for machines without an output device. It accepts
+/// any requested parameters.
+#[allow(dead_code)]
+pub struct NullBackend {
+    request: StreamRequest,
+    open: bool,
+    /// Whether files must exist to be played
+    check_files: bool,
+}
+
+#[allow(dead_code)]
+impl NullBackend {
+    /// Sample rate reported when none was requested
+    pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+    pub fn new(request: StreamRequest) -> Self {
+        Self {
+            request,
+            open: false,
+            check_files: true,
+        }
+    }
+
+    /// A backend that plays any path, whether or not a file is there, e.g. the
+    /// generated tracks of demo mode
+    pub fn without_files(request: StreamRequest) -> Self {
+        Self {
+            check_files: false,
+            ..Self::new(request)
+        }
+    }
+
+    fn check_file(&self, path: &Path) -> Result<()> {
+        if self.check_files {
+            File::open(path)?;
+        }
+        Ok(())
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn open(&mut self) -> Result<()> {
+        self.open = true;
+        Ok(())
+    }
+
+    fn is_device_alive(&mut self) -> bool {
+        self.open
+    }
+
+    fn device_name(&self) -> Option<String> {
+        Some("null".into())
+    }
+
+    fn device_info(&self) -> Option<AudioDeviceInfo> {
+        self.open.then(|| AudioDeviceInfo {
+            device_name: self.device_name(),
+            sample_rate: self
+                .request
+                .sample_rate
+                .unwrap_or(Self::DEFAULT_SAMPLE_RATE),
+            buffer_frames: self.request.buffer_frames,
+            channels: 2,
+            requested_sample_rate: self.request.sample_rate,
+            requested_buffer_frames: self.request.buffer_frames,
+        })
+    }
+
+    fn play(&mut self, path: &Path, _start_at: Duration, _volume: f32) -> Result<()> {
+        self.check_file(path)
+    }
+
+    fn preview_play(&mut self, path: &Path, _volume: f32) -> Result<()> {
+        self.check_file(path)
+    }
+
+    fn pause(&mut self) {}
+
+    fn resume(&mut self) {}
+
+    fn stop(&mut self) {}
+
+    fn set_volume(&mut self, _volume: f32) {}
+}
@@ -0,0 +1,437 @@
+use anyhow::{anyhow, bail, Result};
+use ogg::PacketReader;
+use opus_decoder::OpusMultistreamDecoder;
+use rodio::{Decoder, Source};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+use symphonia::core::{
+    audio::{Channels, SampleBuffer, SignalSpec},
+    codecs::{CodecParameters, Decoder as CodecDecoder, DecoderOptions, CODEC_TYPE_NULL},
+    errors::Error as SymphoniaError,
+    formats::{FormatOptions, FormatReader},
+    io::MediaSourceStream,
+    meta::MetadataOptions,
+    probe::Hint,
+};
+use tracing::debug;
+
+/// Undecodable packets in a row skipped before a file is given up on. rodio's own
+/// symphonia path stops after 3, which damaged frames at the start of a file exceed.
+pub const MAX_CONSECUTIVE_DECODE_ERRORS: usize = 32;
+
+/// Open `path` for playback as `f32` samples.
+///
+/// rodio's decoder is tried first. When it refuses the file, the file is decoded with
+/// [`SymphoniaSource`] instead, and Ogg files symphonia has no decoder for, such as
+/// Opus ones, with [`OggOpusSource`]. rodio's error only surfaces, alongside the
+/// others', if they fail too.
+pub fn open_decoder(path: &Path) -> Result<Box<dyn Source<Item = f32> + Send>> {
+    let rodio_error = match Decoder::new(BufReader::new(File::open(path)?)) {
+        Ok(decoder) => return Ok(Box::new(decoder.convert_samples())),
+        Err(error) => error,
+    };
+    debug!(
+        "rodio could not decode {:?} ({}), trying symphonia",
+        path, rodio_error
+    );
+    let symphonia_error = match SymphoniaSource::open(path) {
+        Ok(source) => return Ok(Box::new(source)),
+        Err(error) => error,
+    };
+    if !is_ogg(path) {
+        bail!(
+            "Failed to decode audio file: {} (symphonia: {})",
+            rodio_error,
+            symphonia_error
+        );
+    }
+    match OggOpusSource::open(path) {
+        Ok(source) => Ok(Box::new(source)),
+        Err(error) => Err(anyhow!(
+            "Failed to decode audio file: {} (symphonia: {}, opus: {})",
+            rodio_error,
+            symphonia_error,
+            error
+        )),
+    }
+}
+
+/// Whether the file at `path` starts with an Ogg page
+fn is_ogg(path: &Path) -> bool {
+    let mut magic = [0; 4];
+    File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| &magic == b"OggS")
+}
+
+/// A file decoded with symphonia, as a rodio [`Source`] of `f32` samples.
+///
+/// Plays the first track with a decoder, skipping packets of other tracks. Up to
+/// [`MAX_CONSECUTIVE_DECODE_ERRORS`] undecodable packets in a row are skipped, and
+/// the decoder is rebuilt when the stream asks for a reset. Each decoded packet is a
+/// frame of its own, so a change of channels or rate takes effect at its start.
+pub struct SymphoniaSource {
+    format: Box<dyn FormatReader>,
+    decoder: Box<dyn CodecDecoder>,
+    codec_params: CodecParameters,
+    track_id: u32,
+    spec: SignalSpec,
+    buffer: SampleBuffer<f32>,
+    /// Next sample of `buffer` to play; the next packet is decoded as soon as the
+    /// last one is played, so the length of the frame ahead is always known
+    position: usize,
+    ended: bool,
+    total_duration: Option<Duration>,
+}
+
+impl SymphoniaSource {
+    /// Probe `path` and decode its first packet, which sets the channels and rate
+    pub fn open(path: &Path) -> Result<Self> {
+        let mss = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(ext);
+        }
+
+        let probed = symphonia::default::get_probe().format(
+            &hint,
+            mss,
+            &FormatOptions {
+                enable_gapless: true,
+                ..Default::default()
+            },
+            &MetadataOptions::default(),
+        )?;
+        let format = probed.format;
+        let codecs = symphonia::default::get_codecs();
+        let (track_id, codec_params, decoder) = format
+            .tracks()
+            .iter()
+            .filter(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+            .find_map(|track| {
+                codecs
+                    .make(&track.codec_params, &DecoderOptions::default())
+                    .ok()
+                    .map(|decoder| (track.id, track.codec_params.clone(), decoder))
+            })
+            .ok_or_else(|| anyhow!("No decodable audio track found"))?;
+        let total_duration = codec_params
+            .n_frames
+            .zip(codec_params.sample_rate)
+            .filter(|(_, rate)| *rate > 0)
+            .map(|(frames, rate)| Duration::from_secs_f64(frames as f64 / f64::from(rate)));
+
+        // Replaced by the first packet decoded
+        let placeholder = SignalSpec::new(0, Channels::FRONT_LEFT);
+        let mut source = Self {
+            format,
+            decoder,
+            track_id,
+            codec_params,
+            spec: placeholder,
+            buffer: SampleBuffer::new(0, placeholder),
+            position: 0,
+            ended: false,
+            total_duration,
+        };
+        source
+            .decode_next()?
+            .then_some(source)
+            .ok_or_else(|| anyhow!("No decodable audio found"))
+    }
+
+    /// Decode the next packet of the track into `buffer`; `false` at the end
+    fn decode_next(&mut self) -> Result<bool> {
+        let mut errors = 0;
+        loop {
+            let packet = match self.format.next_packet() {
+                Ok(packet) => packet,
+                Err(SymphoniaError::IoError(_)) => return Ok(false),
+                Err(SymphoniaError::ResetRequired) => {
+                    self.reset_decoder()?;
+                    continue;
+                }
+                Err(error) => return Err(error.into()),
+            };
+            if packet.track_id() != self.track_id {
+                continue;
+            }
+            match self.decoder.decode(&packet) {
+                Ok(decoded) if decoded.frames() == 0 => continue,
+                Ok(decoded) => {
+                    let spec = *decoded.spec();
+                    if spec != self.spec || self.buffer.capacity() < decoded.capacity() {
+                        self.buffer = SampleBuffer::new(decoded.capacity() as u64, spec);
+                        self.spec = spec;
+                    }
+                    self.buffer.copy_interleaved_ref(decoded);
+                    self.position = 0;
+                    return Ok(true);
+                }
+                Err(SymphoniaError::DecodeError(error)) => {
+                    errors += 1;
+                    if errors > MAX_CONSECUTIVE_DECODE_ERRORS {
+                        return Err(anyhow!("{} undecodable packets: {}", errors, error));
+                    }
+                }
+                Err(SymphoniaError::ResetRequired) => self.reset_decoder()?,
+                Err(error) => return Err(error.into()),
+            }
+        }
+    }
+
+    fn reset_decoder(&mut self) -> Result<()> {
+        self.decoder = symphonia::default::get_codecs()
+            .make(&self.codec_params, &DecoderOptions::default())?;
+        Ok(())
+    }
+}
+
+impl Iterator for SymphoniaSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.ended {
+            return None;
+        }
+        let sample = *self.buffer.samples().get(self.position)?;
+        self.position += 1;
+        if self.position >= self.buffer.len() {
+            self.ended = match self.decode_next() {
+                Ok(decoded) => !decoded,
+                Err(error) => {
+                    debug!("Stopping symphonia playback: {}", error);
+                    true
+                }
+            };
+        }
+        Some(sample)
+    }
+}
+
+impl Source for SymphoniaSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.ended {
+            return Some(0);
+        }
+        Some(self.buffer.len() - self.position)
+    }
+
+    fn channels(&self) -> u16 {
+        self.spec.channels.count() as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.spec.rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+}
+
+/// Rate Opus is decoded at, whatever the rate of the original
+const OPUS_RATE: u32 = 48_000;
+/// Frames of the longest Opus packet, 120 ms
+const MAX_OPUS_PACKET_FRAMES: usize = 5760;
+
+/// An Ogg Opus file (RFC 7845) as a rodio [`Source`] of `f32` samples at 48 kHz.
+///
+/// symphonia reads Ogg but has no Opus decoder, so the packets of the first logical
+/// stream are decoded with `opus-decoder`. The pre-skip at the start and the padding
+/// past the end granule position are dropped and the header's output gain applied.
+/// Undecodable packets are skipped like in [`SymphoniaSource`].
+pub struct OggOpusSource {
+    reader: PacketReader<BufReader<File>>,
+    decoder: OpusMultistreamDecoder,
+    serial: u32,
+    channels: u16,
+    /// Linear output gain of the header
+    gain: f32,
+    /// Frames decoded at the start that are not played
+    pre_skip: u64,
+    /// Frames decoded so far, counting the pre-skip
+    decoded: u64,
+    buffer: Vec<f32>,
+    /// Next sample of `buffer` to play, see [`SymphoniaSource`]
+    position: usize,
+    ended: bool,
+    total_duration: Option<Duration>,
+}
+
+impl OggOpusSource {
+    /// Read the Opus headers of `path` and decode its first packet
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut reader = PacketReader::new(BufReader::new(File::open(path)?));
+        let head = reader
+            .read_packet()?
+            .ok_or_else(|| anyhow!("Empty Ogg stream"))?;
+        let data = &head.data;
+        if data.len() < 19 || !data.starts_with(b"OpusHead") {
+            bail!("Not an Ogg Opus stream");
+        }
+        if data[8] >> 4 != 0 {
+            bail!("Unsupported Opus header version {}", data[8]);
+        }
+        let channels = data[9];
+        let pre_skip = u16::from_le_bytes([data[10], data[11]]);
+        let gain_db = f32::from(i16::from_le_bytes([data[16], data[17]])) / 256.0;
+        let (streams, coupled, mapping) = match data[18] {
+            0 if (1..=2).contains(&channels) => (1, channels - 1, (0..channels).collect()),
+            0 => bail!("Opus mapping family 0 with {} channels", channels),
+            _ => {
+                let table = data
+                    .get(19..21 + usize::from(channels))
+                    .ok_or_else(|| anyhow!("Truncated Opus channel mapping"))?;
+                (table[0], table[1], table[2..].to_vec())
+            }
+        };
+        let decoder = OpusMultistreamDecoder::new(
+            OPUS_RATE,
+            usize::from(channels),
+            usize::from(streams),
+            usize::from(coupled),
+            &mapping,
+        )
+        .map_err(|error| anyhow!("Invalid Opus header: {}", error))?;
+        let serial = head.stream_serial();
+
+        // The end granule position gives the length, and the comment header follows
+        let end = last_granule_position(&mut reader, serial)?;
+        reader.seek_bytes(SeekFrom::Start(0))?;
+        let mut headers = 0;
+        while headers < 2 {
+            let packet = reader
+                .read_packet()?
+                .ok_or_else(|| anyhow!("Missing Opus comment header"))?;
+            if packet.stream_serial() == serial {
+                headers += 1;
+            }
+        }
+
+        let total_duration = end
+            .map(|end| end.saturating_sub(u64::from(pre_skip)))
+            .map(|frames| Duration::from_secs_f64(frames as f64 / f64::from(OPUS_RATE)));
+        let mut source = Self {
+            reader,
+            decoder,
+            serial,
+            channels: u16::from(channels),
+            gain: 10f32.powf(gain_db / 20.0),
+            pre_skip: u64::from(pre_skip),
+            decoded: 0,
+            buffer: Vec::new(),
+            position: 0,
+            ended: false,
+            total_duration,
+        };
+        source
+            .decode_next()?
+            .then_some(source)
+            .ok_or_else(|| anyhow!("No decodable audio found"))
+    }
+
+    /// Decode the next packet with frames to play into `buffer`; `false` at the end
+    fn decode_next(&mut self) -> Result<bool> {
+        let channels = usize::from(self.channels);
+        let mut pcm = vec![0.0; MAX_OPUS_PACKET_FRAMES * channels];
+        let mut errors = 0;
+        loop {
+            let Some(packet) = self.reader.read_packet()? else {
+                return Ok(false);
+            };
+            if packet.stream_serial() != self.serial {
+                continue;
+            }
+            let frames = match self.decoder.decode_float(&packet.data, &mut pcm, false) {
+                Ok(frames) => frames as u64,
+                Err(error) => {
+                    errors += 1;
+                    if errors > MAX_CONSECUTIVE_DECODE_ERRORS {
+                        return Err(anyhow!("{} undecodable packets: {}", errors, error));
+                    }
+                    continue;
+                }
+            };
+            let start = self.decoded;
+            self.decoded += frames;
+            let mut end = self.decoded;
+            if packet.last_in_stream() {
+                end = end.min(packet.absgp_page());
+            }
+            let first = start.max(self.pre_skip);
+            if first < end {
+                let samples =
+                    ((first - start) as usize * channels)..((end - start) as usize * channels);
+                self.buffer.clear();
+                self.buffer
+                    .extend(pcm[samples].iter().map(|sample| sample * self.gain));
+                self.position = 0;
+                return Ok(true);
+            }
+            if packet.last_in_stream() {
+                return Ok(false);
+            }
+        }
+    }
+}
+
+/// Granule position of the last page of stream `serial`, which is where its audio
+/// ends; `None` if it has no audio pages
+fn last_granule_position<R: Read + Seek>(
+    reader: &mut PacketReader<R>,
+    serial: u32,
+) -> Result<Option<u64>> {
+    let mut end = None;
+    while let Some(packet) = reader.read_packet()? {
+        if packet.stream_serial() == serial && packet.absgp_page() != u64::MAX {
+            end = Some(packet.absgp_page());
+        }
+    }
+    Ok(end.filter(|end| *end > 0))
+}
+
+impl Iterator for OggOpusSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.ended {
+            return None;
+        }
+        let sample = *self.buffer.get(self.position)?;
+        self.position += 1;
+        if self.position >= self.buffer.len() {
+            self.ended = match self.decode_next() {
+                Ok(decoded) => !decoded,
+                Err(error) => {
+                    debug!("Stopping Opus playback: {}", error);
+                    true
+                }
+            };
+        }
+        Some(sample)
+    }
+}
+
+impl Source for OggOpusSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        if self.ended {
+            return Some(0);
+        }
+        Some(self.buffer.len() - self.position)
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        OPUS_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.total_duration
+    }
+}
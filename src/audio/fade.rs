@@ -0,0 +1,164 @@
+use rodio::Source;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default `audio.start_fade_ms`
+pub const DEFAULT_START_FADE_MS: u32 = 50;
+
+/// Tells the [`Fade`]s sharing it to ramp out and end, for stopping a sink without a
+/// click. Sources not started yet end without playing.
+#[derive(Debug, Clone, Default)]
+pub struct FadeOut(Arc<AtomicBool>);
+
+impl FadeOut {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start ramping out
+    pub fn trigger(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Ramps an `f32` source in linearly when it starts and out once its [`FadeOut`] is
+/// triggered, so playback neither starts nor stops with a jump that clicks on some
+/// DACs.
+///
+/// Ramps last a whole number of frames, every channel of a frame getting the same
+/// gain: frame `i` of an `n`-frame ramp-in is scaled by `i / n`, starting from
+/// silence, and frame `i` of the ramp-out by `(n - 1 - i) / n` of the gain it started
+/// from, ending on silence. A ramp-out triggered mid-frame starts with the next frame.
+/// The channel count and sample rate are read once.
+pub struct Fade<S> {
+    source: S,
+    channels: u16,
+    /// Frames per ramp; 0 plays and ends without ramps
+    ramp_frames: u64,
+    ramp_in: bool,
+    fade_out: FadeOut,
+    /// Frames played so far
+    frames: u64,
+    /// Channel of the next sample within its frame
+    channel: u16,
+    /// Gain of the current frame
+    gain: f32,
+    /// Gain when the ramp-out began, and the frames it has played
+    ramping_out: Option<(f32, u64)>,
+    ended: bool,
+}
+
+impl<S> Fade<S>
+where
+    S: Source<Item = f32>,
+{
+    /// Ramp `source` in over `ramp` when it starts, and out over `ramp` once
+    /// `fade_out` is triggered
+    pub fn new(source: S, ramp: Duration, fade_out: FadeOut) -> Self {
+        Self::with_ramp_in(source, ramp, fade_out, true)
+    }
+
+    /// Like [`new`](Self::new), but starting at full level, for sources following
+    /// another one without a gap
+    pub fn continuing(source: S, ramp: Duration, fade_out: FadeOut) -> Self {
+        Self::with_ramp_in(source, ramp, fade_out, false)
+    }
+
+    fn with_ramp_in(source: S, ramp: Duration, fade_out: FadeOut, ramp_in: bool) -> Self {
+        let ramp_frames = (ramp.as_secs_f64() * f64::from(source.sample_rate())).round() as u64;
+        Self {
+            channels: source.channels().max(1),
+            source,
+            ramp_frames,
+            ramp_in: ramp_in && ramp_frames > 0,
+            fade_out,
+            frames: 0,
+            channel: 0,
+            gain: 1.0,
+            ramping_out: None,
+            ended: false,
+        }
+    }
+
+    /// Gain of the frame starting now, `None` once the source is to end
+    fn frame_gain(&mut self) -> Option<f32> {
+        if self.ramping_out.is_none() && self.fade_out.is_triggered() {
+            if self.frames == 0 || self.ramp_frames == 0 {
+                return None;
+            }
+            self.ramping_out = Some((self.gain, 0));
+        }
+
+        let ramp = self.ramp_frames as f32;
+        match self.ramping_out {
+            Some((_, played)) if played >= self.ramp_frames => None,
+            Some((from, played)) => Some(from * (ramp - 1.0 - played as f32) / ramp),
+            None if self.ramp_in && self.frames < self.ramp_frames => {
+                Some(self.frames as f32 / ramp)
+            }
+            None => Some(1.0),
+        }
+    }
+}
+
+impl<S> Iterator for Fade<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.ended {
+            return None;
+        }
+        if self.channel == 0 {
+            match self.frame_gain() {
+                Some(gain) => self.gain = gain,
+                None => {
+                    self.ended = true;
+                    return None;
+                }
+            }
+        }
+
+        let Some(sample) = self.source.next() else {
+            self.ended = true;
+            return None;
+        };
+        self.channel += 1;
+        if self.channel == self.channels {
+            self.channel = 0;
+            self.frames += 1;
+            if let Some((_, played)) = self.ramping_out.as_mut() {
+                *played += 1;
+            }
+        }
+        Some(sample * self.gain)
+    }
+}
+
+impl<S> Source for Fade<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
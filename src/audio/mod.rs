@@ -1,37 +1,277 @@
 use anyhow::{anyhow, Result};
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink};
+use rodio::OutputStream;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::BufReader;
 use std::path::{Path, PathBuf};
-use std::sync::mpsc::{self, Receiver, SyncSender};
-use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, SyncSender};
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::thread;
-use std::time::Duration;
-use tracing::{debug, error, info};
-// Symphonia imports removed since we're not using the full API yet
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, warn};
+use utoipa::ToSchema;
+
+use crate::events::{EventBus, EventPayload};
+pub use crate::utils::{is_supported_audio_format, DEFAULT_AUDIO_EXTENSIONS};
+
+mod backend;
+mod decode;
+mod fade;
+mod output;
+mod precache;
+mod replaygain;
+mod resample;
+mod silence;
+mod transcode;
+mod volume;
+
+#[allow(unused_imports)]
+pub use backend::NullBackend;
+pub use backend::{AudioBackend, RodioBackend};
+#[allow(unused_imports)]
+pub use decode::{open_decoder, OggOpusSource, SymphoniaSource, MAX_CONSECUTIVE_DECODE_ERRORS};
+pub use fade::DEFAULT_START_FADE_MS;
+#[allow(unused_imports)]
+pub use fade::{Fade, FadeOut};
+#[allow(unused_imports)]
+pub use output::match_output_device;
+#[allow(unused_imports)]
+pub use precache::PrecacheFetch;
+pub use precache::{Precache, PrecacheStats, DEFAULT_PRECACHE_CACHE_MB};
+pub use replaygain::{ReplayGain, ReplayGainMode};
+#[allow(unused_imports)]
+pub use resample::{BitDepthLimiter, LinearResampler};
+#[allow(unused_imports)]
+pub use silence::{rms, SilenceDetector, SilenceSkipper, SILENCE_WINDOW};
+pub use silence::{
+    SilenceSkip, SilenceSkipHandler, SilenceSkipped, DEFAULT_SILENCE_MIN_SECONDS,
+    DEFAULT_SILENCE_THRESHOLD_DB,
+};
+pub use transcode::{read_chunks, transcode_stream, Transcode, TranscodeCache};
+#[allow(unused_imports)]
+pub use transcode::{
+    transcoding_available, TranscodeFormat, DEFAULT_BITRATE_KBPS, MAX_BITRATE_KBPS,
+    MIN_BITRATE_KBPS,
+};
+pub use volume::VolumeCurve;
 
 /// Audio player state
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, ToSchema)]
 pub enum AudioState {
     Stopped,
     Playing,
     Paused,
     Loading,
+    /// The output device disappeared; the player is trying to reacquire it.
+    DeviceLost,
+}
+
+/// Slowest playback speed factor
+pub const MIN_SPEED: f32 = 0.5;
+/// Fastest playback speed factor
+pub const MAX_SPEED: f32 = 2.0;
+
+/// Volume previews start at until it is changed
+pub const DEFAULT_PREVIEW_VOLUME: f32 = 0.5;
+/// Longest the audio thread waits between checks for the end of a track
+const TRACK_END_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How often the volume is stepped during a pause, resume or stop fade
+const FADE_STEP_INTERVAL: Duration = Duration::from_millis(5);
+/// Longest the audio thread waits between checks for the end of an A-B loop
+const LOOP_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A region of the current track played over and over
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaybackLoop {
+    /// Track the loop was set on
+    pub path: PathBuf,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// Why an A-B loop could not be set
+#[derive(Debug, thiserror::Error)]
+pub enum LoopError {
+    #[error("Nothing is playing")]
+    NothingPlaying,
+    #[error("The current track changed")]
+    TrackChanged,
+    #[error("The loop must start before it ends")]
+    Empty,
+    #[error("The loop must end within the track, which lasts {:.3} s", .0.as_secs_f64())]
+    BeyondTrack(Duration),
+}
+
+/// State of the preview sink, which plays next to the main pipeline for cueing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct PreviewStatus {
+    /// File being previewed; `None` when no preview is playing
+    #[schema(example = "/path/to/track.mp3")]
+    pub track_path: Option<String>,
+    /// Seconds into the previewed file
+    #[schema(example = 12.5)]
+    pub position: f64,
+    /// Preview volume (0.0 to 1.0), independent of the main volume
+    #[schema(example = 0.5)]
+    pub volume: f32,
+}
+
+/// Conversions applied to decoded audio before it reaches the output device
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OutputFormat {
+    /// Resample sources with a different rate to this one
+    pub resample_to: Option<u32>,
+    /// Reduce sources with more bits per sample to this depth
+    pub bit_depth_fallback: Option<u16>,
+    /// Skip sustained silence, such as applause gaps on live albums
+    pub skip_silence: Option<SilenceSkip>,
+    /// Ramp files in when they start playing and out when stopped or replaced; zero
+    /// starts and stops them at once
+    pub fade: Duration,
+}
+
+/// Output stream parameters asked for in the configuration. Unset fields, or ones
+/// the device does not support, fall back to the device's defaults.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StreamRequest {
+    pub sample_rate: Option<u32>,
+    /// Frames per device buffer
+    pub buffer_frames: Option<u32>,
+}
+
+/// Parameters of the output stream actually opened on the device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct AudioDeviceInfo {
+    #[schema(example = "default")]
+    pub device_name: Option<String>,
+    #[schema(example = 48000)]
+    pub sample_rate: u32,
+    /// Frames per device buffer; `None` when the device picked its default size
+    #[schema(example = 4096)]
+    pub buffer_frames: Option<u32>,
+    #[schema(example = 2)]
+    pub channels: u16,
+    #[schema(example = 44100)]
+    pub requested_sample_rate: Option<u32>,
+    #[schema(example = 4096)]
+    pub requested_buffer_frames: Option<u32>,
+}
+
+impl AudioDeviceInfo {
+    /// How the opened stream differs from the requested one, e.g.
+    /// "sample rate 48000 Hz instead of 44100 Hz"
+    pub fn differences(&self) -> Vec<String> {
+        let mut differences = Vec::new();
+        if let Some(rate) = self
+            .requested_sample_rate
+            .filter(|rate| *rate != self.sample_rate)
+        {
+            differences.push(format!(
+                "sample rate {} Hz instead of {} Hz",
+                self.sample_rate, rate
+            ));
+        }
+        if let Some(frames) = self
+            .requested_buffer_frames
+            .filter(|frames| Some(*frames) != self.buffer_frames)
+        {
+            differences.push(match self.buffer_frames {
+                Some(actual) => format!("buffer of {} frames instead of {}", actual, frames),
+                None => format!("default buffer size instead of {} frames", frames),
+            });
+        }
+        differences
+    }
+}
+
+/// Sample format of the file being played, as stated by its codec parameters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SourceFormat {
+    #[schema(example = 96000)]
+    pub sample_rate: Option<u32>,
+    #[schema(example = 2)]
+    pub channels: Option<u16>,
+    #[schema(example = 24)]
+    pub bits_per_sample: Option<u32>,
+}
+
+/// Read the sample format of `path` without decoding it.
+pub fn probe_source_format(path: &Path) -> Result<SourceFormat> {
+    use symphonia::core::{
+        formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+    };
+
+    let mss = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let track = probed
+        .format
+        .default_track()
+        .ok_or_else(|| anyhow!("No default audio track found"))?;
+    let params = &track.codec_params;
+
+    Ok(SourceFormat {
+        sample_rate: params.sample_rate,
+        channels: params.channels.map(|channels| channels.count() as u16),
+        bits_per_sample: params.bits_per_sample.or(params.bits_per_coded_sample),
+    })
+}
+
+/// How the audio thread watches for and recovers from a lost output device.
+#[derive(Debug, Clone)]
+pub struct DeviceRecoveryPolicy {
+    /// How often the device is checked while a track is loaded.
+    pub check_interval: Duration,
+    /// Delay between attempts to reopen a lost device.
+    pub retry_delay: Duration,
+    /// Attempts before giving up and stopping playback.
+    pub max_attempts: u32,
+}
+
+impl Default for DeviceRecoveryPolicy {
+    fn default() -> Self {
+        Self {
+            check_interval: Duration::from_secs(2),
+            retry_delay: Duration::from_secs(2),
+            max_attempts: 5,
+        }
+    }
 }
 
 /// Audio player for handling music playback
 pub struct AudioPlayer {
     commands: mpsc::Sender<Command>,
     current_track: Arc<Mutex<Option<String>>>,
+    next_track: Arc<Mutex<Option<String>>>,
     volume: Arc<Mutex<f32>>,
     state: Arc<Mutex<AudioState>>,
+    clock: Arc<Mutex<PlaybackClock>>,
+    source_format: Arc<Mutex<Option<SourceFormat>>>,
+    device_info: Arc<Mutex<Option<AudioDeviceInfo>>>,
+    preview: Arc<Mutex<PreviewShared>>,
+    playback_loop: Arc<Mutex<Option<PlaybackLoop>>>,
 }
 
 type CommandResultSender = SyncSender<Result<(), anyhow::Error>>;
 
+/// Looks up the library id of the track at a path, for the events of the audio thread
+pub type TrackResolver = Arc<dyn Fn(&Path) -> Option<String> + Send + Sync>;
+
+/// Looks up the ReplayGain values of the track at a path when it starts playing
+pub type GainResolver = Arc<dyn Fn(&Path) -> ReplayGain + Send + Sync>;
+
 enum Command {
     Play {
         path: PathBuf,
+        start_at: Duration,
         respond_to: CommandResultSender,
     },
     Pause {
@@ -47,44 +287,168 @@ enum Command {
         volume: f32,
         respond_to: CommandResultSender,
     },
+    Seek {
+        position: Duration,
+        respond_to: CommandResultSender,
+    },
+    SetVolumeCurve {
+        curve: VolumeCurve,
+        respond_to: CommandResultSender,
+    },
+    SetOutputFormat {
+        format: OutputFormat,
+        respond_to: CommandResultSender,
+    },
+    SetAutoPause {
+        after: Option<Duration>,
+        respond_to: CommandResultSender,
+    },
+    SetPauseFade {
+        fade: Duration,
+        respond_to: CommandResultSender,
+    },
+    SetSpeed {
+        speed: f32,
+        respond_to: CommandResultSender,
+    },
+    FadeVolume {
+        to: f32,
+        over: Duration,
+        respond_to: CommandResultSender,
+    },
+    SetLoop {
+        region: Option<PlaybackLoop>,
+        respond_to: CommandResultSender,
+    },
+    SetPrecache {
+        precache: Option<Arc<Precache>>,
+        respond_to: CommandResultSender,
+    },
+    SetTrackResolver {
+        resolver: Option<TrackResolver>,
+        respond_to: CommandResultSender,
+    },
+    SetReplayGain {
+        mode: ReplayGainMode,
+        resolver: Option<GainResolver>,
+        respond_to: CommandResultSender,
+    },
+    EnqueueNext {
+        path: PathBuf,
+        respond_to: CommandResultSender,
+    },
+    PreviewPlay {
+        path: PathBuf,
+        respond_to: CommandResultSender,
+    },
+    PreviewStop {
+        respond_to: CommandResultSender,
+    },
+    SetPreviewVolume {
+        volume: f32,
+        respond_to: CommandResultSender,
+    },
+    SwitchDevice {
+        name: Option<String>,
+        respond_to: CommandResultSender,
+    },
+    ListOutputDevices {
+        respond_to: SyncSender<Vec<String>>,
+    },
     Shutdown,
 }
 
 impl AudioPlayer {
-    /// Create a new audio player
-    pub fn new() -> Result<Self> {
+    /// Create a new audio player on the default output device
+    #[allow(dead_code)]
+    pub fn new(event_bus: Option<Arc<EventBus>>) -> Result<Self> {
+        Self::with_stream_request(StreamRequest::default(), event_bus)
+    }
+
+    /// Create an audio player on the default output device, opening it with the
+    /// requested sample rate and buffer size where the device supports them
+    #[allow(dead_code)]
+    pub fn with_stream_request(
+        request: StreamRequest,
+        event_bus: Option<Arc<EventBus>>,
+    ) -> Result<Self> {
+        Self::with_output_device(None, request, event_bus)
+    }
+
+    /// Create an audio player on the output device matching `output_device` (see
+    /// [`match_output_device`]), or the default device when it is unset or missing
+    pub fn with_output_device(
+        output_device: Option<String>,
+        request: StreamRequest,
+        event_bus: Option<Arc<EventBus>>,
+    ) -> Result<Self> {
+        Self::with_backend(
+            move || {
+                Ok(Box::new(
+                    RodioBackend::with_stream_request(request).with_output_device(output_device),
+                ) as Box<dyn AudioBackend>)
+            },
+            DeviceRecoveryPolicy::default(),
+            event_bus,
+        )
+    }
+
+    /// Create an audio player driving a custom backend.
+    ///
+    /// The backend is constructed on the audio thread, since output streams usually
+    /// cannot be moved between threads.
+    pub fn with_backend<F>(
+        factory: F,
+        policy: DeviceRecoveryPolicy,
+        event_bus: Option<Arc<EventBus>>,
+    ) -> Result<Self>
+    where
+        F: FnOnce() -> Result<Box<dyn AudioBackend>> + Send + 'static,
+    {
         let (command_tx, command_rx) = mpsc::channel::<Command>();
         let current_track = Arc::new(Mutex::new(None));
+        let next_track = Arc::new(Mutex::new(None));
         let volume = Arc::new(Mutex::new(0.7));
         let state = Arc::new(Mutex::new(AudioState::Stopped));
+        let clock = Arc::new(Mutex::new(PlaybackClock::default()));
+        let source_format = Arc::new(Mutex::new(None));
+        let device_info = Arc::new(Mutex::new(None));
+        let preview = Arc::new(Mutex::new(PreviewShared::default()));
+        let playback_loop = Arc::new(Mutex::new(None));
 
-        let current_track_thread = Arc::clone(&current_track);
-        let volume_thread = Arc::clone(&volume);
-        let state_thread = Arc::clone(&state);
+        let shared = SharedState {
+            current_track: Arc::clone(&current_track),
+            next_track: Arc::clone(&next_track),
+            volume: Arc::clone(&volume),
+            state: Arc::clone(&state),
+            clock: Arc::clone(&clock),
+            source_format: Arc::clone(&source_format),
+            device_info: Arc::clone(&device_info),
+            preview: Arc::clone(&preview),
+            playback_loop: Arc::clone(&playback_loop),
+        };
 
         let (init_tx, init_rx) = mpsc::sync_channel(1);
 
         thread::Builder::new()
             .name("hexendrum-audio".into())
-            .spawn(move || match OutputStream::try_default() {
-                Ok((stream, stream_handle)) => {
-                    let _ = init_tx.send(Ok(()));
-                    let mut sink: Option<Sink> = None;
-                    let mut current_volume = *volume_thread.lock().unwrap();
-
-                    run_command_loop(
-                        command_rx,
-                        stream,
-                        stream_handle,
-                        &mut sink,
-                        &mut current_volume,
-                        &state_thread,
-                        &current_track_thread,
-                        &volume_thread,
-                    );
-                }
-                Err(e) => {
-                    let _ = init_tx.send(Err(anyhow!(e)));
+            .spawn(move || {
+                let backend = factory().and_then(|mut backend| {
+                    backend.open()?;
+                    Ok(backend)
+                });
+
+                match backend {
+                    Ok(backend) => {
+                        let mut audio_thread = AudioThread::new(backend, policy, shared, event_bus);
+                        // Before answering, so the device info is there once `new` returns
+                        audio_thread.device_opened();
+                        let _ = init_tx.send(Ok(()));
+                        audio_thread.run(command_rx);
+                    }
+                    Err(err) => {
+                        let _ = init_tx.send(Err(err));
+                    }
                 }
             })?;
 
@@ -92,8 +456,14 @@ impl AudioPlayer {
             Ok(Ok(())) => Ok(Self {
                 commands: command_tx,
                 current_track,
+                next_track,
                 volume,
                 state,
+                clock,
+                source_format,
+                device_info,
+                preview,
+                playback_loop,
             }),
             Ok(Err(err)) => Err(err),
             Err(e) => Err(anyhow!("Audio thread initialization failed: {}", e)),
@@ -101,8 +471,14 @@ impl AudioPlayer {
     }
 
     /// Play an audio file
+    #[allow(dead_code)]
     pub fn play(&self, file_path: &Path) -> Result<()> {
-        debug!("Attempting to play {:?}", file_path);
+        self.play_from(file_path, Duration::ZERO)
+    }
+
+    /// Play an audio file, starting `start_at` into it
+    pub fn play_from(&self, file_path: &Path, start_at: Duration) -> Result<()> {
+        debug!("Attempting to play {:?} from {:?}", file_path, start_at);
 
         {
             let mut state_guard = self.state.lock().unwrap();
@@ -113,6 +489,7 @@ impl AudioPlayer {
         self.commands
             .send(Command::Play {
                 path: file_path.to_path_buf(),
+                start_at,
                 respond_to: resp_tx,
             })
             .map_err(|e| anyhow!("Failed to send play command: {}", e))?;
@@ -181,6 +558,9 @@ impl AudioPlayer {
 
     /// Set volume (0.0 to 1.0)
     pub fn set_volume(&self, volume: f32) -> Result<()> {
+        if volume.is_nan() {
+            return Err(anyhow!("Volume must be a number"));
+        }
         let volume = volume.clamp(0.0, 1.0);
 
         let (resp_tx, resp_rx) = mpsc::sync_channel(1);
@@ -197,194 +577,1635 @@ impl AudioPlayer {
         }
     }
 
-    /// Get current volume
-    pub fn get_volume(&self) -> f32 {
-        *self.volume.lock().unwrap()
-    }
+    /// Jump to `position` in the current track, keeping it paused if it was paused
+    pub fn seek(&self, position: Duration) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::Seek {
+                position,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send seek command: {}", e))?;
 
-    /// Get current playback state
-    pub fn get_state(&self) -> AudioState {
-        self.state.lock().unwrap().clone()
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
     }
 
-    /// Get current track path
-    pub fn get_current_track(&self) -> Option<String> {
-        self.current_track.lock().unwrap().clone()
+    /// Play the region of the current track from `start` to `end` over and over,
+    /// seeking back to `start` whenever playback reaches `end`. Fails with a
+    /// [`LoopError`] unless something is playing and the region is within it; the
+    /// loop is cleared when another track plays or playback stops.
+    pub fn set_loop(&self, start: Duration, end: Duration) -> Result<()> {
+        if start >= end {
+            return Err(LoopError::Empty.into());
+        }
+        let path = self
+            .get_current_track()
+            .filter(|_| self.get_state() != AudioState::Stopped)
+            .ok_or(LoopError::NothingPlaying)?;
+        // Probed here rather than on the audio thread, which keeps playing meanwhile
+        let duration = get_audio_duration(Path::new(&path))?;
+        if end > duration {
+            return Err(LoopError::BeyondTrack(duration).into());
+        }
+        self.send_loop(Some(PlaybackLoop {
+            path: PathBuf::from(path),
+            start,
+            end,
+        }))
     }
-}
 
-impl Drop for AudioPlayer {
-    fn drop(&mut self) {
-        let _ = self.commands.send(Command::Shutdown);
+    /// Stop looping, playback carrying on to the end of the track
+    pub fn clear_loop(&self) -> Result<()> {
+        self.send_loop(None)
     }
-}
 
-fn run_command_loop(
-    command_rx: Receiver<Command>,
-    _stream: OutputStream,
-    stream_handle: OutputStreamHandle,
-    sink: &mut Option<Sink>,
-    current_volume: &mut f32,
-    state: &Arc<Mutex<AudioState>>,
-    current_track: &Arc<Mutex<Option<String>>>,
-    volume: &Arc<Mutex<f32>>,
-) {
-    for command in command_rx {
-        match command {
-            Command::Play { path, respond_to } => {
-                handle_stop_internal(sink, state, current_track);
-                {
-                    let mut state_guard = state.lock().unwrap();
-                    *state_guard = AudioState::Loading;
-                }
+    fn send_loop(&self, region: Option<PlaybackLoop>) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::SetLoop {
+                region,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send loop command: {}", e))?;
 
-                let result: Result<()> = (|| {
-                    let file = File::open(&path)?;
-                    let reader = BufReader::new(file);
-                    let decoder = Decoder::new(reader)
-                        .map_err(|e| anyhow!("Failed to decode audio file: {}", e))?;
-
-                    let new_sink = Sink::try_new(&stream_handle)
-                        .map_err(|e| anyhow!("Failed to create playback sink: {}", e))?;
-                    new_sink.set_volume(*current_volume);
-                    new_sink.append(decoder);
-                    new_sink.play();
-
-                    {
-                        let mut track_guard = current_track.lock().unwrap();
-                        *track_guard = Some(path.to_string_lossy().to_string());
-                    }
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
+    }
 
-                    {
-                        let mut state_guard = state.lock().unwrap();
-                        *state_guard = AudioState::Playing;
-                    }
+    /// The A-B loop set on the current track, if any
+    pub fn get_loop(&self) -> Option<PlaybackLoop> {
+        self.playback_loop.lock().unwrap().clone()
+    }
 
-                    *sink = Some(new_sink);
-                    Ok(())
-                })();
+    /// Change how the volume maps to the output level. The volume itself, as
+    /// returned by `get_volume`, is unchanged.
+    pub fn set_volume_curve(&self, curve: VolumeCurve) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::SetVolumeCurve {
+                curve,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send volume curve command: {}", e))?;
 
-                match result {
-                    Ok(()) => {
-                        let _ = respond_to.send(Ok(()));
-                    }
-                    Err(err) => {
-                        {
-                            let mut state_guard = state.lock().unwrap();
-                            *state_guard = AudioState::Stopped;
-                        }
-                        let mut track_guard = current_track.lock().unwrap();
-                        *track_guard = None;
-                        let _ = respond_to.send(Err(err));
-                    }
-                }
-            }
-            Command::Pause { respond_to } => {
-                if let Some(active_sink) = sink.as_ref() {
-                    active_sink.pause();
-                    let mut state_guard = state.lock().unwrap();
-                    *state_guard = AudioState::Paused;
-                    debug!("Playback paused");
-                }
-                let _ = respond_to.send(Ok(()));
-            }
-            Command::Resume { respond_to } => {
-                if let Some(active_sink) = sink.as_ref() {
-                    active_sink.play();
-                    let mut state_guard = state.lock().unwrap();
-                    *state_guard = AudioState::Playing;
-                    debug!("Playback resumed");
-                }
-                let _ = respond_to.send(Ok(()));
-            }
-            Command::Stop { respond_to } => {
-                handle_stop_internal(sink, state, current_track);
-                let _ = respond_to.send(Ok(()));
-            }
-            Command::SetVolume {
-                volume: new_volume,
-                respond_to,
-            } => {
-                *current_volume = new_volume;
-                {
-                    let mut volume_guard = volume.lock().unwrap();
-                    *volume_guard = new_volume;
-                }
-                if let Some(active_sink) = sink.as_ref() {
-                    active_sink.set_volume(new_volume);
-                }
-                let _ = respond_to.send(Ok(()));
-            }
-            Command::Shutdown => {
-                handle_stop_internal(sink, state, current_track);
-                break;
-            }
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
         }
     }
-}
 
-fn handle_stop_internal(
-    sink: &mut Option<Sink>,
-    state: &Arc<Mutex<AudioState>>,
-    current_track: &Arc<Mutex<Option<String>>>,
-) {
-    if let Some(active_sink) = sink.take() {
-        active_sink.stop();
-        debug!("Playback stopped");
-    }
+    /// Adjust the output level of each track by its ReplayGain values in `mode`, as
+    /// `resolver` finds them. Applies to the current track right away; tracks
+    /// `resolver` knows no values for, and all of them without a resolver, play at
+    /// unity gain. The volume multiplies with the gain.
+    pub fn set_replaygain(
+        &self,
+        mode: ReplayGainMode,
+        resolver: Option<GainResolver>,
+    ) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::SetReplayGain {
+                mode,
+                resolver,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send ReplayGain command: {}", e))?;
 
-    {
-        let mut track_guard = current_track.lock().unwrap();
-        *track_guard = None;
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
     }
 
-    {
-        let mut state_guard = state.lock().unwrap();
-        *state_guard = AudioState::Stopped;
-    }
-}
+    /// Change the conversions applied to decoded audio, from the next track on.
+    pub fn set_output_format(&self, format: OutputFormat) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::SetOutputFormat {
+                format,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send output format command: {}", e))?;
 
-/// Get audio file duration
-pub fn get_audio_duration(file_path: &Path) -> Result<Duration> {
-    use symphonia::core::{
-        codecs::DecoderOptions, errors::Error as SymphoniaError, formats::FormatOptions,
-        io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
-    };
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
+    }
 
-    let reader = File::open(file_path)?;
-    let mss = MediaSourceStream::new(Box::new(reader), Default::default());
+    /// Pause playback once nothing has been listening for `after`: the device reports
+    /// no active route, or it disappeared and is still being reacquired. Playback is
+    /// not resumed automatically. `None` disables the watchdog.
+    pub fn set_auto_pause(&self, after: Option<Duration>) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::SetAutoPause {
+                after,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send auto-pause command: {}", e))?;
 
-    let mut hint = Hint::new();
-    if let Some(ext) = file_path.extension().and_then(|ext| ext.to_str()) {
-        hint.with_extension(ext);
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
     }
 
-    let probed = symphonia::default::get_probe().format(
-        &hint,
-        mss,
-        &FormatOptions::default(),
-        &MetadataOptions::default(),
-    )?;
-
-    let mut format = probed.format;
-    let track = format
-        .default_track()
-        .ok_or_else(|| anyhow!("No default audio track found"))?;
-    let codec_params = track.codec_params.clone();
-    let track_id = track.id;
+    /// Ramp the volume down over `fade` before pausing or stopping, and back up over
+    /// it when resuming. The ramps run on the audio thread: `pause`, `resume` and
+    /// `stop` return, and report the new state, at once. Zero disables them.
+    pub fn set_pause_fade(&self, fade: Duration) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::SetPauseFade {
+                fade,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send pause fade command: {}", e))?;
 
-    if let (Some(n_frames), Some(sample_rate)) = (codec_params.n_frames, codec_params.sample_rate) {
-        let seconds = n_frames as f64 / sample_rate as f64;
-        return Ok(Duration::from_secs_f64(seconds));
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
     }
 
-    if let Some(sample_rate) = codec_params.sample_rate {
-        let mut decoder =
-            symphonia::default::get_codecs().make(&codec_params, &DecoderOptions::default())?;
-        let mut total_frames: u64 = 0;
+    /// Ramp the volume to `to`, a fraction of the volume set, over `over`, as before a
+    /// sleep timer stops playback. The volume set is left alone, and the level is
+    /// reset by playing a track, stopping, and resuming.
+    pub fn fade_volume(&self, to: f32, over: Duration) -> Result<()> {
+        if to.is_nan() {
+            return Err(anyhow!("Fade level must be a number"));
+        }
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::FadeVolume {
+                to: to.clamp(0.0, 1.0),
+                over,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send volume fade command: {}", e))?;
 
-        loop {
-            match format.next_packet() {
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
+    }
+
+    /// Open tracks from the copies in `precache` when it has them, from the next track
+    /// on. `None` always opens the tracks themselves.
+    pub fn set_precache(&self, precache: Option<Arc<Precache>>) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::SetPrecache {
+                precache,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send precache command: {}", e))?;
+
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
+    }
+
+    /// Queue a file to follow the current track without a gap, becoming the current
+    /// track once that one ends. Fails when nothing is playing, a track is queued
+    /// already or the file cannot be decoded; playback then stops at the end of the
+    /// current track as usual.
+    pub fn enqueue_next(&self, file_path: &Path) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::EnqueueNext {
+                path: file_path.to_path_buf(),
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send enqueue command: {}", e))?;
+
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
+    }
+
+    /// Name tracks by the id `resolver` finds for their path in the events the player
+    /// emits itself, such as `playback_finished`. `None` leaves the id out.
+    pub fn set_track_resolver(&self, resolver: Option<TrackResolver>) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::SetTrackResolver {
+                resolver,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send track resolver command: {}", e))?;
+
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
+    }
+
+    /// Preview a file on a second sink mixed into the same output, replacing any
+    /// earlier preview. Main playback, its track and state are left alone.
+    pub fn preview_play(&self, file_path: &Path) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::PreviewPlay {
+                path: file_path.to_path_buf(),
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send preview command: {}", e))?;
+
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
+    }
+
+    /// Stop the preview, if one is playing
+    pub fn preview_stop(&self) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::PreviewStop {
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send preview stop command: {}", e))?;
+
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
+    }
+
+    /// Set the preview volume (0.0 to 1.0), which goes through the same volume
+    /// curve as the main volume
+    pub fn set_preview_volume(&self, volume: f32) -> Result<()> {
+        if volume.is_nan() {
+            return Err(anyhow!("Volume must be a number"));
+        }
+        let volume = volume.clamp(0.0, 1.0);
+
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::SetPreviewVolume {
+                volume,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send preview volume command: {}", e))?;
+
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
+    }
+
+    /// What the preview sink is playing
+    pub fn preview_status(&self) -> PreviewStatus {
+        self.preview.lock().unwrap().status()
+    }
+
+    /// Play at `speed` times the normal rate, clamped to [`MIN_SPEED`]..=[`MAX_SPEED`],
+    /// from now on: the current track changes speed without restarting, and later
+    /// tracks start at it. The pitch follows the speed. Positions stay in track time.
+    pub fn set_speed(&self, speed: f32) -> Result<()> {
+        if speed.is_nan() {
+            return Err(anyhow!("Speed must be a number"));
+        }
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::SetSpeed {
+                speed: speed.clamp(MIN_SPEED, MAX_SPEED),
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send speed command: {}", e))?;
+
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
+    }
+
+    /// Speed factor playback runs at, 1.0 unless changed with [`set_speed`](Self::set_speed)
+    pub fn get_speed(&self) -> f32 {
+        self.clock.lock().unwrap().speed
+    }
+
+    /// Get current volume (0.0 to 1.0, before the volume curve is applied)
+    pub fn get_volume(&self) -> f32 {
+        *self.volume.lock().unwrap()
+    }
+
+    /// Get current playback state
+    pub fn get_state(&self) -> AudioState {
+        self.state.lock().unwrap().clone()
+    }
+
+    /// Get current track path
+    pub fn get_current_track(&self) -> Option<String> {
+        self.current_track.lock().unwrap().clone()
+    }
+
+    /// Track queued with [`enqueue_next`](Self::enqueue_next) that has not started yet
+    pub fn get_next_track(&self) -> Option<String> {
+        self.next_track.lock().unwrap().clone()
+    }
+
+    /// How far into the current track playback has progressed
+    pub fn get_position(&self) -> Duration {
+        self.clock.lock().unwrap().elapsed()
+    }
+
+    /// Sample format of the current track, when it could be read
+    pub fn get_source_format(&self) -> Option<SourceFormat> {
+        *self.source_format.lock().unwrap()
+    }
+
+    /// Parameters of the output stream currently open, when the backend reports them
+    pub fn device_info(&self) -> Option<AudioDeviceInfo> {
+        self.device_info.lock().unwrap().clone()
+    }
+
+    /// Reopen the output on the device matching `name`, or the default device for
+    /// `None`, carrying on with the current track where it was. The default device is
+    /// used when no device matches.
+    pub fn switch_device(&self, name: Option<String>) -> Result<()> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        self.commands
+            .send(Command::SwitchDevice {
+                name,
+                respond_to: resp_tx,
+            })
+            .map_err(|e| anyhow!("Failed to send device switch command: {}", e))?;
+
+        match resp_rx.recv() {
+            Ok(result) => result,
+            Err(e) => Err(anyhow!("Playback thread disconnected: {}", e)),
+        }
+    }
+
+    /// Names of the output devices that can be switched to
+    pub fn list_output_devices(&self) -> Vec<String> {
+        let (resp_tx, resp_rx) = mpsc::sync_channel(1);
+        if self
+            .commands
+            .send(Command::ListOutputDevices {
+                respond_to: resp_tx,
+            })
+            .is_err()
+        {
+            return Vec::new();
+        }
+        resp_rx.recv().unwrap_or_default()
+    }
+}
+
+impl Drop for AudioPlayer {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Shutdown);
+    }
+}
+
+struct SharedState {
+    current_track: Arc<Mutex<Option<String>>>,
+    /// Track queued to follow the current one without a gap
+    next_track: Arc<Mutex<Option<String>>>,
+    volume: Arc<Mutex<f32>>,
+    state: Arc<Mutex<AudioState>>,
+    clock: Arc<Mutex<PlaybackClock>>,
+    source_format: Arc<Mutex<Option<SourceFormat>>>,
+    device_info: Arc<Mutex<Option<AudioDeviceInfo>>>,
+    preview: Arc<Mutex<PreviewShared>>,
+    playback_loop: Arc<Mutex<Option<PlaybackLoop>>>,
+}
+
+impl SharedState {
+    fn set_state(&self, state: AudioState) {
+        *self.state.lock().unwrap() = state;
+    }
+
+    fn state(&self) -> AudioState {
+        self.state.lock().unwrap().clone()
+    }
+
+    fn clock(&self) -> MutexGuard<'_, PlaybackClock> {
+        self.clock.lock().unwrap()
+    }
+
+    fn playback_loop(&self) -> Option<PlaybackLoop> {
+        self.playback_loop.lock().unwrap().clone()
+    }
+
+    fn set_playback_loop(&self, region: Option<PlaybackLoop>) {
+        *self.playback_loop.lock().unwrap() = region;
+    }
+
+    fn set_current_track(&self, path: Option<&Path>) {
+        *self.current_track.lock().unwrap() = path.map(|path| path.to_string_lossy().to_string());
+        *self.source_format.lock().unwrap() =
+            path.and_then(|path| match probe_source_format(path) {
+                Ok(format) => Some(format),
+                Err(err) => {
+                    debug!("Could not read the sample format of {:?}: {}", path, err);
+                    None
+                }
+            });
+    }
+}
+
+/// Preview state shared between the audio thread and the player handle
+#[derive(Debug)]
+struct PreviewShared {
+    path: Option<PathBuf>,
+    clock: PlaybackClock,
+    volume: f32,
+}
+
+impl Default for PreviewShared {
+    fn default() -> Self {
+        Self {
+            path: None,
+            clock: PlaybackClock::default(),
+            volume: DEFAULT_PREVIEW_VOLUME,
+        }
+    }
+}
+
+impl PreviewShared {
+    fn status(&self) -> PreviewStatus {
+        PreviewStatus {
+            track_path: self
+                .path
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string()),
+            position: self.clock.elapsed().as_secs_f64(),
+            volume: self.volume,
+        }
+    }
+}
+
+/// Tracks how far into the current track playback has progressed, in track time: at
+/// a speed of 2, a second of playback moves it two seconds on.
+#[derive(Debug)]
+struct PlaybackClock {
+    started_at: Option<Instant>,
+    accumulated: Duration,
+    speed: f32,
+}
+
+impl Default for PlaybackClock {
+    fn default() -> Self {
+        Self {
+            started_at: None,
+            accumulated: Duration::ZERO,
+            speed: 1.0,
+        }
+    }
+}
+
+impl PlaybackClock {
+    fn start(&mut self, offset: Duration) {
+        self.accumulated = offset;
+        self.started_at = Some(Instant::now());
+    }
+
+    fn pause(&mut self) {
+        if let Some(started_at) = self.started_at.take() {
+            self.accumulated += self.track_time(started_at.elapsed());
+        }
+    }
+
+    /// Count playback from now on at `speed`; the position so far is kept
+    fn set_speed(&mut self, speed: f32) {
+        if let Some(started_at) = self.started_at.as_mut() {
+            self.accumulated += track_time(started_at.elapsed(), self.speed);
+            *started_at = Instant::now();
+        }
+        self.speed = speed;
+    }
+
+    fn track_time(&self, played: Duration) -> Duration {
+        track_time(played, self.speed)
+    }
+
+    fn resume(&mut self) {
+        if self.started_at.is_none() {
+            self.started_at = Some(Instant::now());
+        }
+    }
+
+    /// Move the position forward, e.g. past skipped silence
+    fn advance(&mut self, by: Duration) {
+        self.accumulated += by;
+    }
+
+    fn reset(&mut self) {
+        self.started_at = None;
+        self.accumulated = Duration::ZERO;
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.accumulated
+            + self
+                .started_at
+                .map(|started_at| self.track_time(started_at.elapsed()))
+                .unwrap_or_default()
+    }
+}
+
+/// How far into a track `played` of playback at `speed` gets; exact at speed 1
+fn track_time(played: Duration, speed: f32) -> Duration {
+    if speed == 1.0 {
+        played
+    } else {
+        played.mul_f64(f64::from(speed))
+    }
+}
+
+/// Keep the playback clock in step with silence skipped on the output thread and
+/// announce each skip, so progress bars can jump ahead.
+fn silence_handler(shared: &SharedState, event_bus: Option<Arc<EventBus>>) -> SilenceSkipHandler {
+    let clock = Arc::clone(&shared.clock);
+    let current_track = Arc::clone(&shared.current_track);
+    Arc::new(move |skipped: SilenceSkipped| {
+        let by = skipped.to.saturating_sub(skipped.from);
+        clock.lock().unwrap().advance(by);
+        debug!("Skipped {:?} of silence at {:?}", by, skipped.from);
+        if let Some(event_bus) = event_bus.as_ref() {
+            event_bus.emit(EventPayload::silence_skipped(
+                current_track.lock().unwrap().clone(),
+                skipped.from.as_secs_f64(),
+                skipped.to.as_secs_f64(),
+            ));
+        }
+    })
+}
+
+/// What happens once a [`VolumeFade`] has run its course
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FadeEnd {
+    Nothing,
+    Pause,
+    Stop,
+}
+
+/// A pause, resume, stop or sleep volume ramp under way
+struct VolumeFade {
+    from: f32,
+    to: f32,
+    started_at: Instant,
+    duration: Duration,
+    then: FadeEnd,
+}
+
+impl VolumeFade {
+    /// Fade level reached by now, and whether the fade is over
+    fn level(&self) -> (f32, bool) {
+        if self.duration.is_zero() {
+            return (self.to, true);
+        }
+        let progress =
+            (self.started_at.elapsed().as_secs_f32() / self.duration.as_secs_f32()).min(1.0);
+        (
+            self.from + (self.to - self.from) * progress,
+            progress >= 1.0,
+        )
+    }
+}
+
+/// What to restore once a lost device comes back.
+struct DeviceRecovery {
+    path: Option<PathBuf>,
+    position: Duration,
+    resume_playing: bool,
+    attempts: u32,
+    next_attempt: Instant,
+}
+
+struct AudioThread {
+    backend: Box<dyn AudioBackend>,
+    policy: DeviceRecoveryPolicy,
+    shared: SharedState,
+    event_bus: Option<Arc<EventBus>>,
+    current_path: Option<PathBuf>,
+    /// Track queued on the backend after the current one
+    next_path: Option<PathBuf>,
+    /// User-facing volume; the backend gets it mapped through `volume_curve`
+    current_volume: f32,
+    volume_curve: VolumeCurve,
+    recovery: Option<DeviceRecovery>,
+    last_device_check: Instant,
+    /// Whether the stream parameters differing from the requested ones was reported
+    mismatch_reported: bool,
+    /// How long nothing may listen before playback is paused; `None` when disabled
+    auto_pause_after: Option<Duration>,
+    /// Since when playback has gone unheard
+    unheard_since: Option<Instant>,
+    /// Local copies of tracks to open instead of the tracks themselves
+    precache: Option<Arc<Precache>>,
+    track_resolver: Option<TrackResolver>,
+    replaygain_mode: ReplayGainMode,
+    gain_resolver: Option<GainResolver>,
+    /// ReplayGain multiplier of the current track, 1.0 without one
+    track_gain: f32,
+    /// How long pause, resume and stop fades last; zero when disabled
+    pause_fade: Duration,
+    /// Multiplier of the pause, resume and stop fades, 1.0 outside them
+    fade_level: f32,
+    fade: Option<VolumeFade>,
+}
+
+impl AudioThread {
+    fn new(
+        mut backend: Box<dyn AudioBackend>,
+        policy: DeviceRecoveryPolicy,
+        shared: SharedState,
+        event_bus: Option<Arc<EventBus>>,
+    ) -> Self {
+        let current_volume = *shared.volume.lock().unwrap();
+        backend.set_silence_handler(silence_handler(&shared, event_bus.clone()));
+        Self {
+            backend,
+            policy,
+            shared,
+            event_bus,
+            current_path: None,
+            next_path: None,
+            current_volume,
+            volume_curve: VolumeCurve::default(),
+            recovery: None,
+            last_device_check: Instant::now(),
+            mismatch_reported: false,
+            auto_pause_after: None,
+            unheard_since: None,
+            precache: None,
+            track_resolver: None,
+            replaygain_mode: ReplayGainMode::Off,
+            gain_resolver: None,
+            track_gain: 1.0,
+            pause_fade: Duration::ZERO,
+            fade_level: 1.0,
+            fade: None,
+        }
+    }
+
+    fn run(mut self, command_rx: Receiver<Command>) {
+        let poll_interval = self
+            .policy
+            .check_interval
+            .min(self.policy.retry_delay)
+            .min(TRACK_END_POLL_INTERVAL);
+
+        loop {
+            let timeout = if self.fade.is_some() {
+                FADE_STEP_INTERVAL
+            } else if self.shared.playback_loop().is_some() {
+                poll_interval.min(LOOP_POLL_INTERVAL)
+            } else {
+                poll_interval
+            };
+            match command_rx.recv_timeout(timeout) {
+                Ok(Command::Shutdown) | Err(RecvTimeoutError::Disconnected) => {
+                    self.end_preview("stopped");
+                    self.stop();
+                    break;
+                }
+                Ok(command) => self.handle_command(command),
+                Err(RecvTimeoutError::Timeout) => {}
+            }
+
+            self.step_fade();
+            self.watch_device();
+            self.watch_loop();
+            self.watch_track_end();
+            self.watch_preview();
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::Play {
+                path,
+                start_at,
+                respond_to,
+            } => {
+                let result = self.play(path, start_at);
+                let _ = respond_to.send(result);
+            }
+            Command::Pause { respond_to } => {
+                if let Some(recovery) = self.recovery.as_mut() {
+                    recovery.resume_playing = false;
+                } else if self.current_path.is_some() {
+                    self.shared.clock().pause();
+                    self.shared.set_state(AudioState::Paused);
+                    self.fade_to(0.0, FadeEnd::Pause);
+                    debug!("Playback paused");
+                }
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::Resume { respond_to } => {
+                if let Some(recovery) = self.recovery.as_mut() {
+                    recovery.resume_playing = recovery.path.is_some();
+                } else if self.current_path.is_some() {
+                    // From wherever an unfinished pause fade had got to
+                    self.backend.set_volume(self.output_volume());
+                    self.backend.resume();
+                    self.shared.clock().resume();
+                    self.shared.set_state(AudioState::Playing);
+                    self.fade_to(1.0, FadeEnd::Nothing);
+                    debug!("Playback resumed");
+                }
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::Stop { respond_to } => {
+                if !self.pause_fade.is_zero()
+                    && self.recovery.is_none()
+                    && self.shared.state() == AudioState::Playing
+                {
+                    // Reported stopped at once, the source being dropped after the fade
+                    self.forget_playback();
+                    self.fade_to(0.0, FadeEnd::Stop);
+                } else {
+                    self.stop();
+                }
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::SetVolume {
+                volume: new_volume,
+                respond_to,
+            } => {
+                self.current_volume = new_volume;
+                *self.shared.volume.lock().unwrap() = new_volume;
+                self.backend.set_volume(self.output_volume());
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::SetVolumeCurve { curve, respond_to } => {
+                self.volume_curve = curve;
+                self.backend.set_volume(self.output_volume());
+                self.backend
+                    .set_preview_volume(self.preview_output_volume());
+                debug!("Volume curve set to {}", curve);
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::SetOutputFormat { format, respond_to } => {
+                self.backend.set_output_format(format);
+                debug!("Output format set to {:?}", format);
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::SetLoop { region, respond_to } => {
+                let result = match region {
+                    Some(region) if self.current_path.as_ref() != Some(&region.path) => {
+                        Err(LoopError::TrackChanged.into())
+                    }
+                    region => {
+                        debug!("Loop set to {:?}", region);
+                        self.shared.set_playback_loop(region);
+                        Ok(())
+                    }
+                };
+                let _ = respond_to.send(result);
+            }
+            Command::SetPauseFade { fade, respond_to } => {
+                self.pause_fade = fade;
+                debug!("Pause fade set to {:?}", fade);
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::SetSpeed { speed, respond_to } => {
+                self.shared.clock().set_speed(speed);
+                self.backend.set_speed(speed);
+                debug!("Speed set to {}", speed);
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::FadeVolume {
+                to,
+                over,
+                respond_to,
+            } => {
+                self.fade = Some(VolumeFade {
+                    from: self.fade_level,
+                    to,
+                    started_at: Instant::now(),
+                    duration: over,
+                    then: FadeEnd::Nothing,
+                });
+                self.step_fade();
+                debug!("Fading the volume to {} over {:?}", to, over);
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::SetAutoPause { after, respond_to } => {
+                self.auto_pause_after = after;
+                self.unheard_since = None;
+                debug!("Auto-pause set to {:?}", after);
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::SetPrecache {
+                precache,
+                respond_to,
+            } => {
+                debug!(
+                    "Precache {}",
+                    if precache.is_some() {
+                        "enabled"
+                    } else {
+                        "disabled"
+                    }
+                );
+                self.precache = precache;
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::SetTrackResolver {
+                resolver,
+                respond_to,
+            } => {
+                self.track_resolver = resolver;
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::SetReplayGain {
+                mode,
+                resolver,
+                respond_to,
+            } => {
+                self.replaygain_mode = mode;
+                self.gain_resolver = resolver;
+                self.track_gain = match self.current_path.clone() {
+                    Some(path) => self.gain_for(&path),
+                    None => 1.0,
+                };
+                self.backend.set_volume(self.output_volume());
+                debug!("ReplayGain mode set to {:?}", mode);
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::EnqueueNext { path, respond_to } => {
+                let result = self.enqueue_next(path);
+                let _ = respond_to.send(result);
+            }
+            Command::Seek {
+                position,
+                respond_to,
+            } => {
+                let result = self.seek(position);
+                let _ = respond_to.send(result);
+            }
+            Command::PreviewPlay { path, respond_to } => {
+                let result = self.preview_play(path);
+                let _ = respond_to.send(result);
+            }
+            Command::SwitchDevice { name, respond_to } => {
+                let result = self.switch_device(name);
+                let _ = respond_to.send(result);
+            }
+            Command::ListOutputDevices { respond_to } => {
+                let _ = respond_to.send(self.backend.output_devices());
+            }
+            Command::PreviewStop { respond_to } => {
+                self.end_preview("stopped");
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::SetPreviewVolume { volume, respond_to } => {
+                self.shared.preview.lock().unwrap().volume = volume;
+                self.backend
+                    .set_preview_volume(self.preview_output_volume());
+                let _ = respond_to.send(Ok(()));
+            }
+            Command::Shutdown => {
+                self.end_preview("stopped");
+                self.stop();
+            }
+        }
+    }
+
+    fn play(&mut self, path: PathBuf, start_at: Duration) -> Result<()> {
+        // Restarting the same track, as when the device changes, keeps its loop
+        let playback_loop = self
+            .shared
+            .playback_loop()
+            .filter(|region| region.path == path);
+        self.stop();
+        self.shared.set_state(AudioState::Loading);
+
+        if !self.backend.is_device_alive() {
+            // Reopening drops every sink, the preview's included
+            self.end_preview("stopped");
+            if let Err(err) = self.backend.open() {
+                self.shared.set_state(AudioState::Stopped);
+                return Err(anyhow!("Audio output device unavailable: {}", err));
+            }
+            self.device_opened();
+        }
+
+        let source = self.source_path(&path);
+        self.track_gain = self.gain_for(&path);
+        match self.backend.play(&source, start_at, self.output_volume()) {
+            Ok(()) => {
+                self.shared.clock().start(start_at);
+                self.shared.set_current_track(Some(&path));
+                self.shared.set_state(AudioState::Playing);
+                self.shared.set_playback_loop(playback_loop);
+                self.current_path = Some(path);
+                self.last_device_check = Instant::now();
+                Ok(())
+            }
+            Err(err) => {
+                if !self.backend.is_device_alive() {
+                    // The file is fine, the device is not: queue the track for when it returns.
+                    self.current_path = Some(path.clone());
+                    self.shared.set_current_track(Some(&path));
+                    self.shared.clock().start(start_at);
+                    self.enter_device_lost(true, err.to_string());
+                } else {
+                    self.shared.set_state(AudioState::Stopped);
+                    self.shared.set_current_track(None);
+                }
+                Err(err)
+            }
+        }
+    }
+
+    /// Reopen the output on another device and restart the current track there at
+    /// the position it had reached
+    fn switch_device(&mut self, name: Option<String>) -> Result<()> {
+        self.backend.select_device(name);
+        if self.recovery.is_some() {
+            // The next recovery attempt opens the newly selected device
+            return Ok(());
+        }
+
+        let playing = self.shared.state() == AudioState::Playing;
+        let position = self.shared.clock().elapsed();
+        // Previews are short-lived, so they are dropped rather than moved
+        self.end_preview("stopped");
+        if let Err(err) = self.backend.open() {
+            self.enter_device_lost(playing, err.to_string());
+            return Err(err);
+        }
+        self.device_opened();
+        self.last_device_check = Instant::now();
+        info!("Switched audio output to {:?}", self.backend.device_name());
+        self.emit(EventPayload::audio_device(
+            "switched",
+            self.backend.device_name(),
+            None,
+        ));
+
+        if self.current_path.is_some() {
+            self.seek(position)?;
+        }
+        Ok(())
+    }
+
+    fn seek(&mut self, position: Duration) -> Result<()> {
+        if let Some(recovery) = self.recovery.as_mut() {
+            if recovery.path.is_none() {
+                return Err(anyhow!("Nothing is playing"));
+            }
+            // Applied once the device comes back
+            recovery.position = position;
+            return Ok(());
+        }
+
+        let Some(path) = self.current_path.clone() else {
+            return Err(anyhow!("Nothing is playing"));
+        };
+        let paused = self.shared.state() == AudioState::Paused;
+
+        // Restarting the source at an offset is the only way to seek with every backend.
+        let source = self.source_path(&path);
+        if let Err(err) = self.backend.play(&source, position, self.output_volume()) {
+            if !self.backend.is_device_alive() {
+                self.shared.clock().start(position);
+                self.enter_device_lost(!paused, err.to_string());
+            } else {
+                self.stop();
+            }
+            return Err(err);
+        }
+
+        // Restarting dropped the queued track
+        if let Some(next) = self.next_path.clone() {
+            let source = self.source_path(&next);
+            if let Err(err) = self.backend.enqueue(&source) {
+                warn!("Cannot queue {:?} again after seeking: {}", next, err);
+                self.set_next(None);
+            }
+        }
+        if paused {
+            self.backend.pause();
+        }
+        self.shared.clock().start(position);
+        if paused {
+            self.shared.clock().pause();
+        }
+        debug!("Seeked to {:?}", position);
+        Ok(())
+    }
+
+    /// File to open to play `path`: its precached copy when there is one
+    fn source_path(&self, path: &Path) -> PathBuf {
+        match &self.precache {
+            Some(precache) => precache.resolve(path),
+            None => path.to_path_buf(),
+        }
+    }
+
+    fn stop(&mut self) {
+        self.fade = None;
+        self.fade_level = 1.0;
+        self.backend.stop();
+        self.forget_playback();
+    }
+
+    /// Stop reporting the current track, leaving its source to the backend
+    fn forget_playback(&mut self) {
+        if self.current_path.take().is_some() {
+            debug!("Playback stopped");
+        }
+        self.set_next(None);
+        self.shared.clock().reset();
+        self.shared.set_playback_loop(None);
+        self.recovery = None;
+        self.unheard_since = None;
+        self.shared.set_current_track(None);
+        self.shared.set_state(AudioState::Stopped);
+    }
+
+    fn watch_device(&mut self) {
+        if self.recovery.is_some() {
+            self.try_recover();
+            self.watch_listener(self.recovery.is_none());
+            return;
+        }
+
+        if self.current_path.is_none()
+            || self.last_device_check.elapsed() < self.policy.check_interval
+        {
+            return;
+        }
+
+        self.last_device_check = Instant::now();
+        if !self.backend.is_device_alive() {
+            let resume_playing = self.shared.state() == AudioState::Playing;
+            self.enter_device_lost(resume_playing, "Audio output device disconnected".into());
+            self.watch_listener(false);
+        } else if self.auto_pause_after.is_some() {
+            let listening = self.backend.has_active_route();
+            self.watch_listener(listening);
+        }
+    }
+
+    /// Pause playback that has gone unheard for `auto_pause_after`, explaining why
+    /// with an `auto_paused` device event. A lost device returns paused.
+    fn watch_listener(&mut self, listening: bool) {
+        let Some(after) = self.auto_pause_after else {
+            return;
+        };
+        let playing = match self.recovery.as_ref() {
+            Some(recovery) => recovery.resume_playing,
+            None => self.shared.state() == AudioState::Playing,
+        };
+        if listening || !playing {
+            self.unheard_since = None;
+            return;
+        }
+        if self
+            .unheard_since
+            .get_or_insert_with(Instant::now)
+            .elapsed()
+            < after
+        {
+            return;
+        }
+
+        self.unheard_since = None;
+        let message = match self.recovery.as_mut() {
+            Some(recovery) => {
+                recovery.resume_playing = false;
+                format!(
+                    "Audio output device gone for {:?}; playback stays paused when it returns",
+                    after
+                )
+            }
+            None => {
+                self.backend.pause();
+                self.shared.clock().pause();
+                self.shared.set_state(AudioState::Paused);
+                format!(
+                    "No active audio output route for {:?}; playback paused",
+                    after
+                )
+            }
+        };
+        info!("{}", message);
+        self.emit(EventPayload::audio_device(
+            "auto_paused",
+            self.backend.device_name(),
+            Some(message),
+        ));
+        if self.recovery.is_none() {
+            self.emit_playback_state("paused");
+        }
+    }
+
+    fn enter_device_lost(&mut self, resume_playing: bool, message: String) {
+        self.shared.clock().pause();
+        self.backend.stop();
+        // Recovery restarts the current track alone
+        self.set_next(None);
+        // Previews are short-lived, so they are dropped rather than restored
+        self.end_preview("stopped");
+
+        warn!("{}", message);
+        self.recovery = Some(DeviceRecovery {
+            path: self.current_path.clone(),
+            position: self.shared.clock().elapsed(),
+            resume_playing,
+            attempts: 0,
+            next_attempt: Instant::now() + self.policy.retry_delay,
+        });
+        self.shared.set_state(AudioState::DeviceLost);
+
+        self.emit(EventPayload::audio_device(
+            "lost",
+            self.backend.device_name(),
+            Some(message),
+        ));
+        self.emit_playback_state("devicelost");
+    }
+
+    fn try_recover(&mut self) {
+        let Some(recovery) = self.recovery.as_mut() else {
+            return;
+        };
+
+        if Instant::now() < recovery.next_attempt {
+            return;
+        }
+
+        recovery.attempts += 1;
+        recovery.next_attempt = Instant::now() + self.policy.retry_delay;
+        let attempts = recovery.attempts;
+
+        let source = recovery.path.clone().map(|path| self.source_path(&path));
+        let result = self.backend.open().and_then(|_| {
+            self.device_opened();
+            let recovery = self.recovery.as_ref().expect("recovery in progress");
+            match source {
+                Some(source) => {
+                    self.backend
+                        .play(&source, recovery.position, self.output_volume())?;
+                    if !recovery.resume_playing {
+                        self.backend.pause();
+                    }
+                    Ok(())
+                }
+                None => Ok(()),
+            }
+        });
+
+        match result {
+            Ok(()) => {
+                let recovery = self.recovery.take().expect("recovery in progress");
+                let state = match (&recovery.path, recovery.resume_playing) {
+                    (None, _) => AudioState::Stopped,
+                    (Some(_), true) => AudioState::Playing,
+                    (Some(_), false) => AudioState::Paused,
+                };
+
+                self.shared.clock().start(recovery.position);
+                if state != AudioState::Playing {
+                    self.shared.clock().pause();
+                }
+                self.last_device_check = Instant::now();
+                self.shared.set_state(state.clone());
+
+                info!(
+                    "Audio output device recovered after {} attempt(s); resuming at {:?}",
+                    attempts, recovery.position
+                );
+                self.emit(EventPayload::audio_device(
+                    "recovered",
+                    self.backend.device_name(),
+                    None,
+                ));
+                self.emit_playback_state(&format!("{:?}", state).to_lowercase());
+            }
+            Err(err) if self.backend.selected_device().is_some() => {
+                // The chosen device may be gone for good; carry on with the default one
+                let name = self.backend.selected_device();
+                self.backend.select_device(None);
+                let recovery = self.recovery.as_mut().expect("recovery in progress");
+                recovery.attempts = 0;
+                recovery.next_attempt = Instant::now();
+
+                let message = format!(
+                    "Audio output device {:?} unavailable ({}); falling back to the default device",
+                    name.unwrap_or_default(),
+                    err
+                );
+                warn!("{}", message);
+                self.emit(EventPayload::audio_device("fallback", None, Some(message)));
+            }
+            Err(err) if attempts >= self.policy.max_attempts => {
+                error!(
+                    "Audio output device unavailable after {} attempts: {}",
+                    attempts, err
+                );
+                self.stop();
+                self.emit(EventPayload::audio_device(
+                    "unavailable",
+                    None,
+                    Some(err.to_string()),
+                ));
+                self.emit_playback_state("stopped");
+            }
+            Err(err) => {
+                debug!("Audio device recovery attempt {} failed: {}", attempts, err);
+            }
+        }
+    }
+
+    fn preview_play(&mut self, path: PathBuf) -> Result<()> {
+        if self.recovery.is_some() || !self.backend.is_device_alive() {
+            return Err(anyhow!("Audio output device unavailable"));
+        }
+
+        self.backend.preview_stop();
+        if let Err(err) = self
+            .backend
+            .preview_play(&path, self.preview_output_volume())
+        {
+            self.end_preview("stopped");
+            return Err(err);
+        }
+
+        let mut preview = self.shared.preview.lock().unwrap();
+        preview.path = Some(path);
+        preview.clock.start(Duration::ZERO);
+        drop(preview);
+        debug!("Preview started");
+        self.emit_preview_state("playing");
+        Ok(())
+    }
+
+    /// Stop the preview, if any, announcing it with `state`.
+    fn end_preview(&mut self, state: &str) {
+        let mut preview = self.shared.preview.lock().unwrap();
+        if preview.path.is_none() {
+            return;
+        }
+        self.backend.preview_stop();
+        let path = preview.path.take();
+        preview.clock.reset();
+        let volume = preview.volume;
+        drop(preview);
+
+        debug!("Preview {}", state);
+        self.emit(EventPayload::audio_preview(
+            state,
+            path.map(|path| path.to_string_lossy().to_string()),
+            volume,
+        ));
+    }
+
+    fn enqueue_next(&mut self, path: PathBuf) -> Result<()> {
+        if self.recovery.is_some() || self.current_path.is_none() {
+            return Err(anyhow!("Nothing is playing"));
+        }
+        if self.next_path.is_some() {
+            return Err(anyhow!("A track is queued already"));
+        }
+
+        let source = self.source_path(&path);
+        if let Err(err) = self.backend.enqueue(&source) {
+            warn!("Cannot queue {:?} to follow gaplessly: {}", path, err);
+            self.emit(EventPayload::playback_error(
+                path.to_string_lossy().to_string(),
+                err.to_string(),
+            ));
+            return Err(err);
+        }
+        debug!("Queued {:?} to follow gaplessly", path);
+        self.set_next(Some(path));
+        Ok(())
+    }
+
+    fn set_next(&mut self, path: Option<PathBuf>) {
+        *self.shared.next_track.lock().unwrap() =
+            path.as_ref().map(|path| path.to_string_lossy().to_string());
+        self.next_path = path;
+    }
+
+    /// Move on to the queued track once the current one has drained, or stop once the
+    /// last one has played to its end, announcing it. Stopping by command drops the
+    /// sources first, so only a track ending by itself is announced.
+    fn watch_track_end(&mut self) {
+        if self.recovery.is_some() || self.shared.state() != AudioState::Playing {
+            return;
+        }
+        if self.next_path.is_some() && self.backend.source_count() <= 1 {
+            self.continue_with_next();
+            return;
+        }
+        if !self.backend.finished() {
+            return;
+        }
+        let Some(path) = self.current_path.clone() else {
+            return;
+        };
+
+        debug!("Finished playing {:?}", path);
+        self.stop();
+        self.emit(EventPayload::playback_finished(
+            path.to_string_lossy().to_string(),
+            self.track_id(&path),
+        ));
+        self.emit_playback_state("stopped");
+    }
+
+    /// Seek back to the start of the A-B loop once playback reaches its end, or the
+    /// track ends or moves on to the queued one first
+    fn watch_loop(&mut self) {
+        if self.recovery.is_some() || self.shared.state() != AudioState::Playing {
+            return;
+        }
+        let Some(region) = self.shared.playback_loop() else {
+            return;
+        };
+        let reached = self.shared.clock().elapsed() >= region.end
+            || (self.next_path.is_some() && self.backend.source_count() <= 1)
+            || self.backend.finished();
+        if !reached {
+            return;
+        }
+        if let Err(err) = self.seek(region.start) {
+            warn!("Failed to loop back to {:?}: {}", region.start, err);
+        }
+    }
+
+    /// Make the queued track current, as the backend has started it
+    fn continue_with_next(&mut self) {
+        let Some(next) = self.next_path.clone() else {
+            return;
+        };
+        debug!("Continued gaplessly with {:?}", next);
+        self.set_next(None);
+        self.shared.set_playback_loop(None);
+        self.track_gain = self.gain_for(&next);
+        self.backend.set_volume(self.output_volume());
+        self.shared.clock().start(Duration::ZERO);
+        self.shared.set_current_track(Some(&next));
+        self.emit(EventPayload::playback_state(
+            "playing",
+            Some(next.to_string_lossy().to_string()),
+            self.track_id(&next),
+            Some(self.current_volume),
+            None,
+        ));
+        self.current_path = Some(next);
+    }
+
+    /// ReplayGain multiplier of the track at `path` in the current mode
+    fn gain_for(&self, path: &Path) -> f32 {
+        if self.replaygain_mode == ReplayGainMode::Off {
+            return 1.0;
+        }
+        let gain = self
+            .gain_resolver
+            .as_ref()
+            .map(|resolver| resolver(path).multiplier(self.replaygain_mode))
+            .unwrap_or(1.0);
+        if gain != 1.0 {
+            debug!("Applying ReplayGain of {:.2}x to {:?}", gain, path);
+        }
+        gain
+    }
+
+    /// Library id of the track at `path`, when a resolver is set and knows it
+    fn track_id(&self, path: &Path) -> Option<String> {
+        self.track_resolver
+            .as_ref()
+            .and_then(|resolver| resolver(path))
+    }
+
+    fn watch_preview(&mut self) {
+        let previewing = self.shared.preview.lock().unwrap().path.is_some();
+        if previewing && self.backend.preview_finished() {
+            self.end_preview("ended");
+        }
+    }
+
+    /// Record the parameters of a freshly opened stream, reporting once per player
+    /// when they differ from the requested ones.
+    fn device_opened(&mut self) {
+        let info = self.backend.device_info();
+        if let Some(info) = info.as_ref().filter(|_| !self.mismatch_reported) {
+            let differences = info.differences();
+            if !differences.is_empty() {
+                self.mismatch_reported = true;
+                let message = format!("Opened with {}", differences.join(", "));
+                warn!("Audio output device {:?}: {}", info.device_name, message);
+                self.emit(EventPayload::audio_device(
+                    "mismatch",
+                    info.device_name.clone(),
+                    Some(message),
+                ));
+            }
+        }
+        *self.shared.device_info.lock().unwrap() = info;
+    }
+
+    /// Multiplier handed to the backend for the current volume, the current track's
+    /// ReplayGain and any pause fade
+    fn output_volume(&self) -> f32 {
+        self.volume_curve.apply(self.current_volume) * self.track_gain * self.fade_level
+    }
+
+    /// Fade the volume to `to`, a fraction of the output volume, then do `then`. A
+    /// fade under way is replaced, carrying on from the level it reached; without a
+    /// pause fade configured the level and `then` apply at once.
+    fn fade_to(&mut self, to: f32, then: FadeEnd) {
+        if self.pause_fade.is_zero() {
+            self.fade = None;
+            if self.fade_level != 1.0 {
+                // Disabled while a fade was under way
+                self.fade_level = 1.0;
+                self.backend.set_volume(self.output_volume());
+            }
+            self.end_fade(then);
+            return;
+        }
+        // A full fade takes `pause_fade`; partial ones keep the same slope
+        let duration = self.pause_fade.mul_f32((to - self.fade_level).abs());
+        self.fade = Some(VolumeFade {
+            from: self.fade_level,
+            to,
+            started_at: Instant::now(),
+            duration,
+            then,
+        });
+        self.step_fade();
+    }
+
+    /// Move the fade under way on, finishing it once it has run its course
+    fn step_fade(&mut self) {
+        let Some(fade) = self.fade.as_ref() else {
+            return;
+        };
+        let (level, done) = fade.level();
+        let then = fade.then;
+        self.fade_level = level;
+        self.backend.set_volume(self.output_volume());
+        if done {
+            self.fade = None;
+            self.end_fade(then);
+        }
+    }
+
+    fn end_fade(&mut self, then: FadeEnd) {
+        match then {
+            FadeEnd::Nothing => {}
+            FadeEnd::Pause => self.backend.pause(),
+            FadeEnd::Stop => {
+                // The playback was already forgotten when the stop came in
+                self.fade_level = 1.0;
+                self.backend.stop();
+            }
+        }
+    }
+
+    /// Multiplier handed to the backend for the preview volume
+    fn preview_output_volume(&self) -> f32 {
+        self.volume_curve
+            .apply(self.shared.preview.lock().unwrap().volume)
+    }
+
+    fn emit_preview_state(&self, state: &str) {
+        let status = self.shared.preview.lock().unwrap().status();
+        self.emit(EventPayload::audio_preview(
+            state,
+            status.track_path,
+            status.volume,
+        ));
+    }
+
+    fn emit_playback_state(&self, state: &str) {
+        self.emit(EventPayload::playback_state(
+            state,
+            self.current_path
+                .as_ref()
+                .map(|path| path.to_string_lossy().to_string()),
+            None,
+            Some(self.current_volume),
+            None,
+        ));
+    }
+
+    fn emit(&self, payload: EventPayload) {
+        if let Some(event_bus) = self.event_bus.as_ref() {
+            event_bus.emit(payload);
+        }
+    }
+}
+
+/// Codec, sample format and average bitrate of an audio file
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct TechnicalInfo {
+    /// Codec short name, such as `flac`, `mp3`, `vorbis` or `pcm_s16le`
+    #[schema(example = "flac")]
+    pub codec: Option<String>,
+    #[schema(example = 44100)]
+    pub sample_rate: Option<u32>,
+    #[schema(example = 2)]
+    pub channels: Option<u16>,
+    #[schema(example = 16)]
+    pub bits_per_sample: Option<u32>,
+    /// File size over duration, in kbit/s
+    #[schema(example = 987)]
+    pub bitrate_kbps: Option<u32>,
+}
+
+/// Duration and technical details of an audio file, read with a single probe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioProbe {
+    pub duration: Duration,
+    pub technical: TechnicalInfo,
+}
+
+/// Get audio file duration
+#[allow(dead_code)]
+pub fn get_audio_duration(file_path: &Path) -> Result<Duration> {
+    probe_audio(file_path).map(|probe| probe.duration)
+}
+
+/// Read the duration, codec and sample format of an audio file. The codec parameters
+/// come from the probe that the duration needs anyway, so this costs no more than
+/// [`get_audio_duration`].
+pub fn probe_audio(file_path: &Path) -> Result<AudioProbe> {
+    use symphonia::core::{
+        codecs::DecoderOptions, errors::Error as SymphoniaError, formats::FormatOptions,
+        io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+    };
+
+    let reader = File::open(file_path)?;
+    let file_size = reader.metadata()?.len();
+    let mss = MediaSourceStream::new(Box::new(reader), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(ext) = file_path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+
+    let mut format = probed.format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| anyhow!("No default audio track found"))?;
+    let codec_params = track.codec_params.clone();
+    let track_id = track.id;
+
+    let technical = TechnicalInfo {
+        codec: symphonia::default::get_codecs()
+            .get_codec(codec_params.codec)
+            .map(|codec| codec.short_name.to_string()),
+        sample_rate: codec_params.sample_rate,
+        channels: codec_params
+            .channels
+            .map(|channels| channels.count() as u16),
+        bits_per_sample: codec_params
+            .bits_per_sample
+            .or(codec_params.bits_per_coded_sample),
+        bitrate_kbps: None,
+    };
+    let probe = |seconds: f64| AudioProbe {
+        duration: Duration::from_secs_f64(seconds),
+        technical: TechnicalInfo {
+            bitrate_kbps: (seconds > 0.0)
+                .then(|| (file_size as f64 * 8.0 / seconds / 1000.0).round() as u32),
+            ..technical.clone()
+        },
+    };
+
+    if let (Some(n_frames), Some(sample_rate)) = (codec_params.n_frames, codec_params.sample_rate) {
+        let seconds = n_frames as f64 / sample_rate as f64;
+        return Ok(probe(seconds));
+    }
+
+    if let Some(sample_rate) = codec_params.sample_rate {
+        let mut decoder =
+            symphonia::default::get_codecs().make(&codec_params, &DecoderOptions::default())?;
+        let mut total_frames: u64 = 0;
+
+        loop {
+            match format.next_packet() {
                 Ok(packet) => {
                     if packet.track_id() != track_id {
                         continue;
@@ -414,22 +2235,11 @@ pub fn get_audio_duration(file_path: &Path) -> Result<Duration> {
 
         if total_frames > 0 {
             let seconds = total_frames as f64 / sample_rate as f64;
-            return Ok(Duration::from_secs_f64(seconds));
+            return Ok(probe(seconds));
         }
     }
 
-    Ok(Duration::from_secs(0))
-}
-
-/// Check if a file is a supported audio format
-pub fn is_supported_audio_format(file_path: &Path) -> bool {
-    if let Some(extension) = file_path.extension() {
-        if let Some(ext_str) = extension.to_str() {
-            let supported = ["mp3", "flac", "ogg", "wav", "m4a", "aac"];
-            return supported.contains(&ext_str.to_lowercase().as_str());
-        }
-    }
-    false
+    Ok(probe(0.0))
 }
 
 /// Initialize the audio system
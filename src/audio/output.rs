@@ -0,0 +1,186 @@
+use anyhow::{anyhow, Result};
+use rodio::cpal::traits::{DeviceTrait, StreamTrait};
+use rodio::cpal::{
+    self, BufferSize, FromSample, SampleFormat, SampleRate, SizedSample, StreamConfig,
+    SupportedBufferSize, SupportedStreamConfig,
+};
+use rodio::dynamic_mixer::{self, DynamicMixer, DynamicMixerController};
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+use super::{AudioDeviceInfo, StreamRequest};
+
+/// Output stream opened on a device with explicit parameters.
+///
+/// rodio's `OutputStream` always uses the device's default buffer size, so the
+/// stream is built with cpal directly and fed from a rodio mixer.
+pub(super) struct DeviceStream {
+    _stream: cpal::Stream,
+    pub mixer: Arc<DynamicMixerController<f32>>,
+    pub info: AudioDeviceInfo,
+}
+
+/// Index of the device in `names` that `wanted` names: an exact match ignoring case,
+/// or else the first name containing it ignoring case, so "usb" finds
+/// "USB Audio DAC"
+pub fn match_output_device(names: &[String], wanted: &str) -> Option<usize> {
+    let wanted = wanted.trim().to_lowercase();
+    if wanted.is_empty() {
+        return None;
+    }
+    let names: Vec<String> = names.iter().map(|name| name.to_lowercase()).collect();
+    names
+        .iter()
+        .position(|name| *name == wanted)
+        .or_else(|| names.iter().position(|name| name.contains(&wanted)))
+}
+
+/// Open `device` with the requested parameters, falling back to the device's default
+/// sample rate and buffer size when it refuses them.
+pub(super) fn open_stream(device: &cpal::Device, request: StreamRequest) -> Result<DeviceStream> {
+    let default_config = device
+        .default_output_config()
+        .map_err(|e| anyhow!("Failed to read the default output config: {}", e))?;
+
+    let mut configs = Vec::new();
+    if let Some(config) = request
+        .sample_rate
+        .and_then(|rate| supported_config(device, &default_config, rate))
+    {
+        configs.push(config);
+    }
+    configs.push(default_config);
+
+    let mut last_error = None;
+    for config in configs {
+        let mut buffer_sizes = Vec::new();
+        if let Some(frames) = request.buffer_frames.filter(|frames| *frames > 0) {
+            buffer_sizes.push(BufferSize::Fixed(clamp_buffer(
+                config.buffer_size(),
+                frames,
+            )));
+        }
+        buffer_sizes.push(BufferSize::Default);
+
+        for buffer_size in buffer_sizes {
+            let stream_config = StreamConfig {
+                buffer_size,
+                ..config.config()
+            };
+            match build_stream(device, &stream_config, config.sample_format()) {
+                Ok((mixer, stream)) => {
+                    stream
+                        .play()
+                        .map_err(|e| anyhow!("Failed to start audio output stream: {}", e))?;
+                    let info = AudioDeviceInfo {
+                        device_name: device.name().ok(),
+                        sample_rate: stream_config.sample_rate.0,
+                        buffer_frames: match buffer_size {
+                            BufferSize::Fixed(frames) => Some(frames),
+                            BufferSize::Default => None,
+                        },
+                        channels: stream_config.channels,
+                        requested_sample_rate: request.sample_rate,
+                        requested_buffer_frames: request.buffer_frames,
+                    };
+                    return Ok(DeviceStream {
+                        _stream: stream,
+                        mixer,
+                        info,
+                    });
+                }
+                Err(err) => {
+                    debug!(
+                        "Output device refused {:?} ({:?}): {}",
+                        stream_config,
+                        config.sample_format(),
+                        err
+                    );
+                    last_error = Some(err);
+                }
+            }
+        }
+    }
+
+    Err(anyhow!(
+        "Failed to open audio output stream: {}",
+        last_error.map_or_else(|| "no usable config".to_string(), |e| e.to_string())
+    ))
+}
+
+/// A supported config at `rate` with the default config's channels, preferring its
+/// sample format too.
+fn supported_config(
+    device: &cpal::Device,
+    default_config: &SupportedStreamConfig,
+    rate: u32,
+) -> Option<SupportedStreamConfig> {
+    if default_config.sample_rate().0 == rate {
+        return Some(default_config.clone());
+    }
+
+    device
+        .supported_output_configs()
+        .ok()?
+        .filter(|range| range.channels() == default_config.channels())
+        .filter_map(|range| {
+            let same_format = range.sample_format() == default_config.sample_format();
+            range
+                .try_with_sample_rate(SampleRate(rate))
+                .map(|config| (same_format, config))
+        })
+        .max_by_key(|(same_format, _)| *same_format)
+        .map(|(_, config)| config)
+}
+
+fn clamp_buffer(supported: &SupportedBufferSize, frames: u32) -> u32 {
+    match supported {
+        SupportedBufferSize::Range { min, max } if min <= max => frames.clamp(*min, *max),
+        _ => frames,
+    }
+}
+
+fn build_stream(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    format: SampleFormat,
+) -> Result<(Arc<DynamicMixerController<f32>>, cpal::Stream), cpal::BuildStreamError> {
+    let (controller, mixer) = dynamic_mixer::mixer::<f32>(config.channels, config.sample_rate.0);
+    let stream = match format {
+        SampleFormat::F32 => build_typed_stream::<f32>(device, config, mixer),
+        SampleFormat::F64 => build_typed_stream::<f64>(device, config, mixer),
+        SampleFormat::I8 => build_typed_stream::<i8>(device, config, mixer),
+        SampleFormat::I16 => build_typed_stream::<i16>(device, config, mixer),
+        SampleFormat::I32 => build_typed_stream::<i32>(device, config, mixer),
+        SampleFormat::I64 => build_typed_stream::<i64>(device, config, mixer),
+        SampleFormat::U8 => build_typed_stream::<u8>(device, config, mixer),
+        SampleFormat::U16 => build_typed_stream::<u16>(device, config, mixer),
+        SampleFormat::U32 => build_typed_stream::<u32>(device, config, mixer),
+        SampleFormat::U64 => build_typed_stream::<u64>(device, config, mixer),
+        _ => Err(cpal::BuildStreamError::StreamConfigNotSupported),
+    }?;
+    Ok((controller, stream))
+}
+
+fn build_typed_stream<T>(
+    device: &cpal::Device,
+    config: &StreamConfig,
+    mut mixer: DynamicMixer<f32>,
+) -> Result<cpal::Stream, cpal::BuildStreamError>
+where
+    T: SizedSample + FromSample<f32>,
+{
+    device.build_output_stream::<T, _, _>(
+        config,
+        move |data, _| {
+            for sample in data.iter_mut() {
+                *sample = mixer
+                    .next()
+                    .map(<T as cpal::Sample>::from_sample)
+                    .unwrap_or(T::EQUILIBRIUM);
+            }
+        },
+        |err| warn!("Audio output stream error: {}", err),
+        None,
+    )
+}
@@ -0,0 +1,236 @@
+use anyhow::Result;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+use tracing::debug;
+use utoipa::ToSchema;
+
+/// Default `audio.precache_cache_mb`
+pub const DEFAULT_PRECACHE_CACHE_MB: u64 = 512;
+
+/// What [`Precache::fetch`] did with a track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrecacheFetch {
+    /// Copied into the cache
+    Copied,
+    /// A current copy was cached already
+    Cached,
+    /// Too large to copy, so only its start was read to warm the storage's caches
+    ReadAhead,
+}
+
+/// Counters of the precache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct PrecacheStats {
+    /// Tracks opened from a cached copy
+    #[schema(example = 42)]
+    pub hits: u64,
+    /// Tracks opened from their source, having no current copy
+    #[schema(example = 3)]
+    pub misses: u64,
+    /// Copies in the cache
+    #[schema(example = 12)]
+    pub files: u64,
+    /// Size of the copies in bytes
+    #[schema(example = 104857600)]
+    pub bytes: u64,
+    /// Size limit of the cache in bytes, from `audio.precache_cache_mb`
+    #[schema(example = 536870912)]
+    pub max_bytes: u64,
+}
+
+/// Local copies of tracks about to play, for music on slow or network storage whose
+/// first read can stall the start of a track.
+///
+/// Tracks up to `precache_bytes` are copied whole; of larger ones that much is read
+/// ahead without copying. A copy is named after its source's path, modification time
+/// and size, so a changed source never plays from a stale copy, which is deleted once
+/// noticed. The least recently played copies are evicted past `max_bytes`.
+#[derive(Debug)]
+pub struct Precache {
+    dir: PathBuf,
+    precache_bytes: u64,
+    max_bytes: u64,
+    /// Serializes copies and eviction
+    lock: Mutex<()>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Precache {
+    pub fn new(dir: PathBuf, precache_bytes: u64, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            precache_bytes,
+            max_bytes,
+            lock: Mutex::new(()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Start of the names of all copies of `path`
+    fn prefix(path: &Path) -> String {
+        let digest = format!("{:x}", Sha256::digest(path.to_string_lossy().as_bytes()));
+        format!("{}-", &digest[..32])
+    }
+
+    /// Name of the copy of `path` as it is now; the extension is kept for decoders
+    /// that go by it
+    fn cache_name(path: &Path, metadata: &fs::Metadata) -> String {
+        let modified = metadata
+            .modified()
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut hasher = Sha256::new();
+        hasher.update(modified.as_nanos().to_le_bytes());
+        hasher.update(metadata.len().to_le_bytes());
+        let stamp = format!("{:x}", hasher.finalize());
+        let extension = path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        format!("{}{}{}", Self::prefix(path), &stamp[..16], extension)
+    }
+
+    /// The current copy of `path`, deleting stale ones
+    pub fn cached(&self, path: &Path) -> Option<PathBuf> {
+        let metadata = fs::metadata(path).ok()?;
+        let current = Self::cache_name(path, &metadata);
+        let prefix = Self::prefix(path);
+        let mut found = None;
+        for entry in fs::read_dir(&self.dir).ok()?.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with(&prefix) || name.ends_with(".part") {
+                continue;
+            }
+            if name == current {
+                found = Some(entry.path());
+            } else {
+                debug!("Discarding the stale copy of {:?}", path);
+                fs::remove_file(entry.path()).ok();
+            }
+        }
+        found
+    }
+
+    /// What to open to play `path`: its current copy, marked as recently used, or
+    /// `path` itself
+    pub fn resolve(&self, path: &Path) -> PathBuf {
+        let Some(copy) = self.cached(path) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return path.to_path_buf();
+        };
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        if let Ok(file) = fs::File::options().append(true).open(&copy) {
+            file.set_modified(SystemTime::now()).ok();
+        }
+        copy
+    }
+
+    /// Copy `path` into the cache, or read its start ahead when it is too large.
+    /// Blocks while reading.
+    pub fn fetch(&self, path: &Path) -> Result<PrecacheFetch> {
+        let metadata = fs::metadata(path)?;
+        if metadata.len() > self.precache_bytes {
+            let read = io::copy(
+                &mut fs::File::open(path)?.take(self.precache_bytes),
+                &mut io::sink(),
+            )?;
+            debug!("Read {} bytes of {:?} ahead", read, path);
+            return Ok(PrecacheFetch::ReadAhead);
+        }
+
+        let _guard = self.lock.lock().unwrap();
+        if self.cached(path).is_some() {
+            return Ok(PrecacheFetch::Cached);
+        }
+        fs::create_dir_all(&self.dir)?;
+        let name = Self::cache_name(path, &metadata);
+        let partial = self
+            .dir
+            .join(format!("{}.{}.part", name, uuid::Uuid::new_v4().simple()));
+        let copied = fs::copy(path, &partial).and_then(|_| {
+            // Changed while being copied: the copy may mix both versions
+            let after = fs::metadata(path)?;
+            if Self::cache_name(path, &after) != name {
+                return Err(io::Error::other("the file changed while it was copied"));
+            }
+            fs::rename(&partial, self.dir.join(&name))
+        });
+        if let Err(error) = copied {
+            fs::remove_file(&partial).ok();
+            return Err(error.into());
+        }
+        debug!("Precached {:?}", path);
+        self.evict_locked()?;
+        Ok(PrecacheFetch::Copied)
+    }
+
+    /// Delete the least recently used copies until the cache fits its size limit.
+    /// Returns the number of bytes freed.
+    pub fn evict(&self) -> Result<u64> {
+        let _guard = self.lock.lock().unwrap();
+        self.evict_locked()
+    }
+
+    fn evict_locked(&self) -> Result<u64> {
+        let mut entries = self.entries()?;
+        let total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        entries.sort();
+        let mut freed = 0;
+        for (_, size, path) in entries {
+            if total - freed <= self.max_bytes {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => freed += size,
+                Err(e) => debug!("Cannot evict {:?}: {}", path, e),
+            }
+        }
+        Ok(freed)
+    }
+
+    /// Copies in the cache with when they were last used and their size
+    fn entries(&self) -> Result<Vec<(SystemTime, u64, PathBuf)>> {
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        for entry in dir {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "part") {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if metadata.is_file() {
+                entries.push((
+                    metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                    metadata.len(),
+                    path,
+                ));
+            }
+        }
+        Ok(entries)
+    }
+
+    pub fn stats(&self) -> PrecacheStats {
+        let entries = self.entries().unwrap_or_default();
+        PrecacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            files: entries.len() as u64,
+            bytes: entries.iter().map(|(_, size, _)| size).sum(),
+            max_bytes: self.max_bytes,
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use std::path::Path;
+
+use lofty::{file::TaggedFileExt, probe::Probe, tag::ItemKey, tag::Tag};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Which ReplayGain values playback is adjusted by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayGainMode {
+    /// Play tracks as they are
+    #[default]
+    Off,
+    /// Bring every track to the same loudness
+    Track,
+    /// Bring every album to the same loudness, keeping the differences between its
+    /// tracks
+    Album,
+}
+
+/// ReplayGain values of a track, as written by loudness scanners such as `rsgain`
+/// or foobar2000
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ReplayGain {
+    /// Gain bringing the track to the reference loudness, in dB
+    /// (REPLAYGAIN_TRACK_GAIN)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = -6.5)]
+    pub track_gain_db: Option<f32>,
+    /// Highest sample of the track, 1.0 being full scale (REPLAYGAIN_TRACK_PEAK)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = 0.98)]
+    pub track_peak: Option<f32>,
+    /// Gain bringing the track's album to the reference loudness, in dB
+    /// (REPLAYGAIN_ALBUM_GAIN)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = -7.2)]
+    pub album_gain_db: Option<f32>,
+    /// Highest sample of the album (REPLAYGAIN_ALBUM_PEAK)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = 1.0)]
+    pub album_peak: Option<f32>,
+}
+
+impl ReplayGain {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// The values found in `tags`, the first tag holding each winning
+    pub fn from_tags<'a>(tags: impl IntoIterator<Item = &'a Tag>) -> Self {
+        let mut gain = Self::default();
+        for tag in tags {
+            let read =
+                |key: ItemKey, parse: fn(&str) -> Option<f32>| tag.get_string(&key).and_then(parse);
+            gain.track_gain_db = gain
+                .track_gain_db
+                .or_else(|| read(ItemKey::ReplayGainTrackGain, parse_gain));
+            gain.track_peak = gain
+                .track_peak
+                .or_else(|| read(ItemKey::ReplayGainTrackPeak, parse_peak));
+            gain.album_gain_db = gain
+                .album_gain_db
+                .or_else(|| read(ItemKey::ReplayGainAlbumGain, parse_gain));
+            gain.album_peak = gain
+                .album_peak
+                .or_else(|| read(ItemKey::ReplayGainAlbumPeak, parse_peak));
+        }
+        gain
+    }
+
+    /// The values tagged in the file at `path`; none when it cannot be read
+    pub fn read(path: &Path) -> Self {
+        match Probe::open(path).and_then(|probe| probe.read()) {
+            Ok(tagged_file) => Self::from_tags(
+                tagged_file
+                    .primary_tag()
+                    .into_iter()
+                    .chain(tagged_file.tags()),
+            ),
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Multiplier applied to the output in `mode`: 1.0 when off or untagged. Album
+    /// mode falls back to the track gain for tracks without an album gain and the
+    /// other way round. Gains are lowered where the peak would otherwise clip.
+    pub fn multiplier(&self, mode: ReplayGainMode) -> f32 {
+        let (gain_db, peak) = match mode {
+            ReplayGainMode::Off => return 1.0,
+            ReplayGainMode::Track => match self.track_gain_db {
+                Some(gain) => (Some(gain), self.track_peak),
+                None => (self.album_gain_db, self.album_peak),
+            },
+            ReplayGainMode::Album => match self.album_gain_db {
+                Some(gain) => (Some(gain), self.album_peak),
+                None => (self.track_gain_db, self.track_peak),
+            },
+        };
+        let Some(gain_db) = gain_db else {
+            return 1.0;
+        };
+
+        let multiplier = 10f32.powf(gain_db / 20.0);
+        match peak.filter(|peak| *peak > 0.0) {
+            Some(peak) => multiplier.min(1.0 / peak),
+            None => multiplier,
+        }
+    }
+}
+
+/// A gain such as "-6.54 dB" or "+2.1"
+fn parse_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let number = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .or_else(|| value.strip_suffix("DB"))
+        .unwrap_or(value);
+    number
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|gain| gain.is_finite())
+}
+
+/// A peak such as "0.988312"
+fn parse_peak(value: &str) -> Option<f32> {
+    value
+        .trim()
+        .parse::<f32>()
+        .ok()
+        .filter(|peak| peak.is_finite() && *peak >= 0.0)
+}
@@ -0,0 +1,173 @@
+use rodio::Source;
+use std::time::Duration;
+
+/// Converts an `f32` source to another sample rate by linear interpolation between
+/// frames.
+///
+/// Good enough to hand a device the rate it was opened with, which avoids the crackle
+/// some cheap USB outputs produce on mismatched streams. The channel count is read
+/// once, so sources changing it mid-stream are not supported.
+pub struct LinearResampler<S> {
+    source: S,
+    channels: usize,
+    to_rate: u32,
+    /// Input frames advanced per output frame
+    step: f64,
+    /// Position between `current` and `next`, from 0.0 to 1.0
+    position: f64,
+    current: Vec<f32>,
+    next: Vec<f32>,
+    /// Channel of the next sample to return within the output frame
+    channel: usize,
+    exhausted: bool,
+}
+
+impl<S> LinearResampler<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(mut source: S, to_rate: u32) -> Self {
+        let channels = usize::from(source.channels().max(1));
+        let step = f64::from(source.sample_rate().max(1)) / f64::from(to_rate.max(1));
+        let current = read_frame(&mut source, channels);
+        let next = read_frame(&mut source, channels);
+        let exhausted = current.is_none();
+
+        Self {
+            source,
+            channels,
+            to_rate: to_rate.max(1),
+            step,
+            position: 0.0,
+            current: current.unwrap_or_default(),
+            next: next.unwrap_or_default(),
+            channel: 0,
+            exhausted,
+        }
+    }
+
+    /// Move the interpolation window forward to the next output frame.
+    fn advance(&mut self) {
+        self.position += self.step;
+        while self.position >= 1.0 && !self.exhausted {
+            self.position -= 1.0;
+            match read_frame(&mut self.source, self.channels) {
+                Some(frame) => self.current = std::mem::replace(&mut self.next, frame),
+                None if !self.next.is_empty() => {
+                    self.current = std::mem::take(&mut self.next);
+                }
+                None => self.exhausted = true,
+            }
+        }
+    }
+}
+
+fn read_frame<S>(source: &mut S, channels: usize) -> Option<Vec<f32>>
+where
+    S: Source<Item = f32>,
+{
+    let frame: Vec<f32> = source.by_ref().take(channels).collect();
+    (frame.len() == channels).then_some(frame)
+}
+
+impl<S> Iterator for LinearResampler<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.exhausted {
+            return None;
+        }
+
+        let current = self.current[self.channel];
+        let sample = match self.next.get(self.channel) {
+            Some(next) => current + (next - current) * self.position as f32,
+            None => current,
+        };
+
+        self.channel += 1;
+        if self.channel == self.channels {
+            self.channel = 0;
+            self.advance();
+        }
+        Some(sample)
+    }
+}
+
+impl<S> Source for LinearResampler<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.to_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
+
+/// Rounds samples to the grid of a lower bit depth.
+pub struct BitDepthLimiter<S> {
+    source: S,
+    /// Quantization steps between 0.0 and 1.0
+    levels: f32,
+}
+
+impl<S> BitDepthLimiter<S>
+where
+    S: Source<Item = f32>,
+{
+    /// Limit `source` to `bits` bits per sample, from 2 to 24.
+    pub fn new(source: S, bits: u16) -> Self {
+        let bits = bits.clamp(2, 24);
+        Self {
+            source,
+            levels: (1u32 << (bits - 1)) as f32,
+        }
+    }
+}
+
+impl<S> Iterator for BitDepthLimiter<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        self.source
+            .next()
+            .map(|sample| (sample * self.levels).round() / self.levels)
+    }
+}
+
+impl<S> Source for BitDepthLimiter<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.source.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.source.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.source.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
@@ -0,0 +1,199 @@
+use rodio::Source;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Default `audio.silence_threshold_db`
+pub const DEFAULT_SILENCE_THRESHOLD_DB: f32 = -60.0;
+/// Default `audio.silence_min_seconds`
+pub const DEFAULT_SILENCE_MIN_SECONDS: f32 = 5.0;
+/// Length of the blocks whose level is measured
+pub const SILENCE_WINDOW: Duration = Duration::from_millis(50);
+
+/// When playback fast-forwards through silence
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SilenceSkip {
+    /// RMS level below which audio counts as silent, in dBFS
+    pub threshold_db: f32,
+    /// How long audio must stay silent before the rest of the silence is skipped.
+    /// Quieter stretches shorter than this always play in full.
+    pub min_silence: Duration,
+}
+
+/// A stretch of silence that was skipped, as positions in the track
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SilenceSkipped {
+    pub from: Duration,
+    pub to: Duration,
+}
+
+/// Called from the output thread whenever a [`SilenceSkipper`] skipped silence
+pub type SilenceSkipHandler = Arc<dyn Fn(SilenceSkipped) + Send + Sync>;
+
+/// Decides window by window whether audio is played or skipped.
+///
+/// Silent windows play until the silence has lasted `min_silence`; from then on they
+/// are skipped until a window above the threshold comes along.
+#[derive(Debug, Clone)]
+pub struct SilenceDetector {
+    /// Threshold as an RMS amplitude
+    threshold: f32,
+    min_silence: Duration,
+    /// How long the current run of silent windows has played
+    silent_for: Duration,
+}
+
+impl SilenceDetector {
+    pub fn new(settings: SilenceSkip) -> Self {
+        Self {
+            threshold: 10f32.powf(settings.threshold_db / 20.0),
+            min_silence: settings.min_silence,
+            silent_for: Duration::ZERO,
+        }
+    }
+
+    /// Whether a window of `duration` holding `samples` should be skipped
+    pub fn skip(&mut self, samples: &[f32], duration: Duration) -> bool {
+        if rms(samples) >= self.threshold {
+            self.silent_for = Duration::ZERO;
+            return false;
+        }
+        if self.silent_for >= self.min_silence {
+            return true;
+        }
+        self.silent_for += duration;
+        false
+    }
+}
+
+/// Root mean square of `samples`; 0.0 when there are none
+pub fn rms(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = samples
+        .iter()
+        .map(|sample| f64::from(*sample) * f64::from(*sample))
+        .sum();
+    (sum / samples.len() as f64).sqrt() as f32
+}
+
+/// Drops sustained silence from a source, reporting each skipped stretch.
+///
+/// The source is read a [`SILENCE_WINDOW`] ahead of what is played, so skipping is
+/// a straight jump rather than sped-up playback.
+pub struct SilenceSkipper<S> {
+    source: S,
+    detector: SilenceDetector,
+    channels: u16,
+    sample_rate: u32,
+    /// Samples per window, a whole number of frames
+    window_len: usize,
+    window: Vec<f32>,
+    /// Next sample of `window` to return
+    index: usize,
+    /// Track position at the end of `window`
+    position: Duration,
+    /// Where the silence being skipped started
+    skipping_from: Option<Duration>,
+    on_skip: Option<SilenceSkipHandler>,
+}
+
+impl<S> SilenceSkipper<S>
+where
+    S: Source<Item = f32>,
+{
+    /// Skip silence in `source`, which starts `start_at` into the track.
+    pub fn new(
+        source: S,
+        settings: SilenceSkip,
+        start_at: Duration,
+        on_skip: Option<SilenceSkipHandler>,
+    ) -> Self {
+        let channels = source.channels().max(1);
+        let sample_rate = source.sample_rate().max(1);
+        let frames = (u64::from(sample_rate) * SILENCE_WINDOW.as_millis() as u64 / 1000).max(1);
+
+        Self {
+            source,
+            detector: SilenceDetector::new(settings),
+            channels,
+            sample_rate,
+            window_len: frames as usize * usize::from(channels),
+            window: Vec::new(),
+            index: 0,
+            position: start_at,
+            skipping_from: None,
+            on_skip,
+        }
+    }
+
+    /// Read windows until one is to be played; false once the source is exhausted.
+    fn fill(&mut self) -> bool {
+        loop {
+            self.window.clear();
+            self.index = 0;
+            self.window
+                .extend(self.source.by_ref().take(self.window_len));
+            if self.window.is_empty() {
+                self.finish_skip(self.position);
+                return false;
+            }
+
+            let frames = self.window.len() / usize::from(self.channels);
+            let duration = Duration::from_secs_f64(frames as f64 / f64::from(self.sample_rate));
+            let start = self.position;
+            self.position += duration;
+
+            if self.detector.skip(&self.window, duration) {
+                self.skipping_from.get_or_insert(start);
+            } else {
+                self.finish_skip(start);
+                return true;
+            }
+        }
+    }
+
+    /// Report the silence skipped so far, if any, as ending at `to`.
+    fn finish_skip(&mut self, to: Duration) {
+        if let (Some(from), Some(on_skip)) = (self.skipping_from.take(), self.on_skip.as_ref()) {
+            on_skip(SilenceSkipped { from, to });
+        }
+    }
+}
+
+impl<S> Iterator for SilenceSkipper<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.index >= self.window.len() && !self.fill() {
+            return None;
+        }
+        let sample = self.window[self.index];
+        self.index += 1;
+        Some(sample)
+    }
+}
+
+impl<S> Source for SilenceSkipper<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.source.total_duration()
+    }
+}
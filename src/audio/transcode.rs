@@ -0,0 +1,455 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use anyhow::Result;
+use futures_util::Stream;
+use sha2::{Digest, Sha256};
+use tracing::debug;
+
+/// Bitrate used when a transcoded stream does not ask for one, in kbit/s
+pub const DEFAULT_BITRATE_KBPS: u32 = 128;
+/// Lowest bitrate a transcoded stream may ask for, in kbit/s
+pub const MIN_BITRATE_KBPS: u32 = 16;
+/// Highest bitrate a transcoded stream may ask for, in kbit/s
+pub const MAX_BITRATE_KBPS: u32 = 320;
+
+/// Chunks of a streamed response body
+pub type ByteStream = Pin<Box<dyn Stream<Item = io::Result<Vec<u8>>> + Send>>;
+
+/// Format a track can be transcoded to for streaming
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TranscodeFormat {
+    /// Opus in an Ogg container
+    Opus,
+}
+
+impl TranscodeFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Opus => "opus",
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Opus => "audio/ogg; codecs=opus",
+        }
+    }
+
+    /// Extension of cached transcodes
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Opus => "opus",
+        }
+    }
+}
+
+impl FromStr for TranscodeFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "opus" => Ok(Self::Opus),
+            other => Err(format!(
+                "Unknown transcode format '{}', expected opus",
+                other
+            )),
+        }
+    }
+}
+
+/// How a track is transcoded for streaming
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transcode {
+    pub format: TranscodeFormat,
+    pub bitrate_kbps: u32,
+    /// Seconds into the track the stream starts at
+    pub start: f64,
+}
+
+impl Transcode {
+    /// Validate the `transcode`, `bitrate` and `start` parameters of a stream request.
+    /// `None` when no transcoding was asked for; `bitrate` and `start` are only
+    /// accepted together with a format.
+    pub fn from_params(
+        format: Option<&str>,
+        bitrate_kbps: Option<u32>,
+        start: Option<f64>,
+    ) -> Result<Option<Self>, String> {
+        let Some(format) = format.filter(|format| !format.trim().is_empty()) else {
+            if bitrate_kbps.is_some() {
+                return Err("bitrate requires transcode".into());
+            }
+            if start.is_some() {
+                return Err(
+                    "start requires transcode; seek the original file with a Range header".into(),
+                );
+            }
+            return Ok(None);
+        };
+
+        let format = format.parse::<TranscodeFormat>()?;
+        let bitrate_kbps = bitrate_kbps.unwrap_or(DEFAULT_BITRATE_KBPS);
+        if !(MIN_BITRATE_KBPS..=MAX_BITRATE_KBPS).contains(&bitrate_kbps) {
+            return Err(format!(
+                "bitrate must be between {} and {} kbit/s",
+                MIN_BITRATE_KBPS, MAX_BITRATE_KBPS
+            ));
+        }
+        let start = start.unwrap_or(0.0);
+        if !start.is_finite() || start < 0.0 {
+            return Err("start must be a non-negative number of seconds".into());
+        }
+
+        Ok(Some(Self {
+            format,
+            bitrate_kbps,
+            start,
+        }))
+    }
+
+    /// Value of the `X-Transcode` header of a stream transcoded this way
+    pub fn header_value(&self) -> String {
+        format!("{}; bitrate={}", self.format.name(), self.bitrate_kbps)
+    }
+}
+
+/// Read `reader` to the end in chunks of up to 64 KiB
+pub fn read_chunks<R>(reader: R) -> ByteStream
+where
+    R: tokio::io::AsyncRead + Send + Unpin + 'static,
+{
+    use tokio::io::AsyncReadExt;
+
+    Box::pin(futures_util::stream::unfold(
+        Some(reader),
+        |reader| async move {
+            let mut reader = reader?;
+            let mut chunk = vec![0; 64 * 1024];
+            match reader.read(&mut chunk).await {
+                Ok(0) => None,
+                Ok(read) => {
+                    chunk.truncate(read);
+                    Some((Ok(chunk), Some(reader)))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        },
+    ))
+}
+
+/// Whether streams can be transcoded: the `transcode` feature is enabled and `ffmpeg`
+/// runs. Checked once.
+#[cfg_attr(not(feature = "transcode"), allow(dead_code))]
+pub fn transcoding_available() -> bool {
+    #[cfg(feature = "transcode")]
+    {
+        static AVAILABLE: std::sync::OnceLock<bool> = std::sync::OnceLock::new();
+        *AVAILABLE.get_or_init(|| {
+            let available = std::process::Command::new("ffmpeg")
+                .arg("-version")
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .is_ok_and(|status| status.success());
+            if !available {
+                tracing::warn!("ffmpeg is not available - streams are sent untranscoded");
+            }
+            available
+        })
+    }
+    #[cfg(not(feature = "transcode"))]
+    {
+        false
+    }
+}
+
+/// Transcode `path` with ffmpeg and stream the result, recording it in `cache` under
+/// the given key once complete. `None` when transcoding is unavailable.
+#[cfg(feature = "transcode")]
+pub fn transcode_stream(
+    path: &Path,
+    transcode: &Transcode,
+    cache: Option<(std::sync::Arc<TranscodeCache>, String)>,
+) -> Result<Option<ByteStream>> {
+    use std::process::Stdio;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::process::Command;
+
+    if !transcoding_available() {
+        return Ok(None);
+    }
+
+    let mut command = Command::new("ffmpeg");
+    command.args(["-hide_banner", "-loglevel", "error", "-nostdin"]);
+    if transcode.start > 0.0 {
+        command.arg("-ss").arg(format!("{:.3}", transcode.start));
+    }
+    command.arg("-i").arg(path);
+    command.args(["-map", "0:a:0", "-vn"]);
+    match transcode.format {
+        TranscodeFormat::Opus => command.args(["-c:a", "libopus", "-f", "ogg"]),
+    };
+    command
+        .arg("-b:a")
+        .arg(format!("{}k", transcode.bitrate_kbps))
+        .arg("pipe:1")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+
+    let mut child = command.spawn()?;
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("ffmpeg has no output"))?;
+
+    // The cache entry is written next to the stream and only kept when ffmpeg finished
+    let cache = cache.and_then(|(cache, key)| {
+        let partial = cache.partial_path(&key);
+        fs::create_dir_all(&cache.dir).ok()?;
+        let file = fs::File::create(&partial).ok()?;
+        Some(CacheWriter {
+            cache,
+            key,
+            partial: Some(partial),
+            file: tokio::fs::File::from_std(file),
+        })
+    });
+
+    struct Transcoder {
+        child: tokio::process::Child,
+        stdout: tokio::process::ChildStdout,
+        cache: Option<CacheWriter>,
+    }
+
+    let path = path.to_path_buf();
+    let stream = futures_util::stream::unfold(
+        Some(Transcoder {
+            child,
+            stdout,
+            cache,
+        }),
+        move |state| {
+            let path = path.clone();
+            async move {
+                let mut state = state?;
+                let mut chunk = vec![0; 64 * 1024];
+                match state.stdout.read(&mut chunk).await {
+                    Ok(0) => {
+                        match state.child.wait().await {
+                            Ok(status) if status.success() => {
+                                if let Some(writer) = state.cache.take() {
+                                    writer.finish().await;
+                                }
+                            }
+                            Ok(status) => {
+                                tracing::warn!("Transcoding {:?} failed: ffmpeg {}", path, status)
+                            }
+                            Err(e) => tracing::warn!("Transcoding {:?} failed: {}", path, e),
+                        }
+                        None
+                    }
+                    Ok(read) => {
+                        chunk.truncate(read);
+                        if let Some(writer) = state.cache.as_mut() {
+                            if let Err(e) = writer.file.write_all(&chunk).await {
+                                debug!("Not caching the transcode of {:?}: {}", path, e);
+                                state.cache = None;
+                            }
+                        }
+                        Some((Ok(chunk), Some(state)))
+                    }
+                    Err(e) => Some((Err(e), None)),
+                }
+            }
+        },
+    );
+
+    Ok(Some(Box::pin(stream)))
+}
+
+/// Transcoding is compiled out without the `transcode` feature; streams are sent
+/// untranscoded.
+#[cfg(not(feature = "transcode"))]
+pub fn transcode_stream(
+    _path: &Path,
+    _transcode: &Transcode,
+    _cache: Option<(std::sync::Arc<TranscodeCache>, String)>,
+) -> Result<Option<ByteStream>> {
+    Ok(None)
+}
+
+/// A transcode being written to the cache, dropped unless it completes
+#[cfg(feature = "transcode")]
+struct CacheWriter {
+    cache: std::sync::Arc<TranscodeCache>,
+    key: String,
+    partial: Option<PathBuf>,
+    file: tokio::fs::File,
+}
+
+#[cfg(feature = "transcode")]
+impl CacheWriter {
+    async fn finish(mut self) {
+        use tokio::io::AsyncWriteExt;
+
+        let Some(partial) = self.partial.take() else {
+            return;
+        };
+        if let Err(e) = self.file.flush().await {
+            debug!("Not caching transcode {}: {}", self.key, e);
+            fs::remove_file(&partial).ok();
+            return;
+        }
+        let cache = self.cache.clone();
+        let key = self.key.clone();
+        let stored = tokio::task::spawn_blocking(move || cache.store(&partial, &key)).await;
+        if let Ok(Err(e)) = stored {
+            tracing::warn!("Failed to cache transcode {}: {}", self.key, e);
+        }
+    }
+}
+
+#[cfg(feature = "transcode")]
+impl Drop for CacheWriter {
+    fn drop(&mut self) {
+        if let Some(partial) = self.partial.take() {
+            fs::remove_file(partial).ok();
+        }
+    }
+}
+
+/// Complete transcodes kept on disk so streaming a track again at the same bitrate
+/// does not transcode it again. The least recently streamed transcodes are evicted
+/// once the cache grows past its size limit.
+#[derive(Debug)]
+#[cfg_attr(not(feature = "transcode"), allow(dead_code))]
+pub struct TranscodeCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Serializes eviction
+    lock: Mutex<()>,
+}
+
+#[cfg_attr(not(feature = "transcode"), allow(dead_code))]
+impl TranscodeCache {
+    pub fn new(dir: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            dir,
+            max_bytes,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Cache key of `path` transcoded as `transcode`, or `None` for streams starting
+    /// past the beginning, which are not cached
+    pub fn key(path: &Path, modified: SystemTime, transcode: &Transcode) -> Option<String> {
+        if transcode.start > 0.0 {
+            return None;
+        }
+
+        let modified = modified
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut hasher = Sha256::new();
+        hasher.update(path.to_string_lossy().as_bytes());
+        hasher.update(modified.as_nanos().to_le_bytes());
+        hasher.update(transcode.format.name());
+        hasher.update(transcode.bitrate_kbps.to_le_bytes());
+        let digest = format!("{:x}", hasher.finalize());
+        Some(format!(
+            "{}.{}",
+            &digest[..32],
+            transcode.format.extension()
+        ))
+    }
+
+    /// Cached transcode stored under `key`, marked as recently used
+    pub fn get(&self, key: &str) -> Option<PathBuf> {
+        let path = self.dir.join(key);
+        let file = fs::File::options().append(true).open(&path).ok()?;
+        file.set_modified(SystemTime::now()).ok();
+        Some(path)
+    }
+
+    /// Where a transcode is written before [`TranscodeCache::store`] adds it, unique
+    /// per writer
+    pub fn partial_path(&self, key: &str) -> PathBuf {
+        self.dir
+            .join(format!("{}.{}.part", key, uuid::Uuid::new_v4().simple()))
+    }
+
+    /// Add the complete transcode at `partial` under `key`, then evict the least
+    /// recently used transcodes until the cache fits its size limit
+    pub fn store(&self, partial: &Path, key: &str) -> Result<PathBuf> {
+        let path = self.dir.join(key);
+        fs::rename(partial, &path)?;
+        self.evict()?;
+        Ok(path)
+    }
+
+    /// Delete the least recently used transcodes until the cache fits its size limit.
+    /// Returns the number of bytes freed.
+    pub fn evict(&self) -> Result<u64> {
+        let _guard = self.lock.lock().unwrap();
+
+        let dir = match fs::read_dir(&self.dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut entries = Vec::new();
+        let mut total = 0;
+        for entry in dir {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "part") {
+                continue;
+            }
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            total += metadata.len();
+            entries.push((
+                metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH),
+                metadata.len(),
+                path,
+            ));
+        }
+
+        entries.sort();
+        let mut freed = 0;
+        for (_, size, path) in entries {
+            if total - freed <= self.max_bytes {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => freed += size,
+                Err(e) => debug!("Cannot evict {:?}: {}", path, e),
+            }
+        }
+        Ok(freed)
+    }
+
+    /// Total size of the cached transcodes in bytes
+    #[allow(dead_code)]
+    pub fn size(&self) -> u64 {
+        fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.path().extension().is_none_or(|ext| ext != "part"))
+            .filter_map(|entry| entry.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum()
+    }
+}
@@ -0,0 +1,99 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Range covered by the logarithmic curve: full volume down to -60 dB.
+const LOGARITHMIC_RANGE_DB: f32 = 60.0;
+
+/// Maps the user-facing volume (0.0 to 1.0) to the multiplier applied to the output.
+///
+/// Perceived loudness is roughly logarithmic, so with a linear mapping most of the
+/// audible change happens at the bottom of the slider.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum VolumeCurve {
+    /// The multiplier equals the volume
+    #[default]
+    Linear,
+    /// Each step of the slider changes the level by the same number of decibels
+    Logarithmic,
+    /// The multiplier is `volume ^ exponent`
+    CustomExponent(f32),
+}
+
+impl VolumeCurve {
+    /// Output multiplier for a user-facing `volume`. Both are clamped to 0.0 to 1.0,
+    /// and 0.0 and 1.0 map to themselves.
+    pub fn apply(&self, volume: f32) -> f32 {
+        let volume = if volume.is_nan() {
+            0.0
+        } else {
+            volume.clamp(0.0, 1.0)
+        };
+        if volume == 0.0 {
+            return 0.0;
+        }
+
+        let multiplier = match self {
+            Self::Linear => volume,
+            Self::Logarithmic => 10f32.powf(LOGARITHMIC_RANGE_DB * (volume - 1.0) / 20.0),
+            Self::CustomExponent(exponent) => volume.powf(*exponent),
+        };
+        multiplier.clamp(0.0, 1.0)
+    }
+}
+
+impl std::fmt::Display for VolumeCurve {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Linear => f.write_str("linear"),
+            Self::Logarithmic => f.write_str("logarithmic"),
+            Self::CustomExponent(exponent) => write!(f, "custom_exponent({})", exponent),
+        }
+    }
+}
+
+impl std::str::FromStr for VolumeCurve {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self> {
+        let value = value.trim().to_lowercase();
+        match value.as_str() {
+            "linear" => return Ok(Self::Linear),
+            "logarithmic" | "log" => return Ok(Self::Logarithmic),
+            _ => {}
+        }
+
+        let exponent = value
+            .strip_prefix("custom_exponent(")
+            .and_then(|rest| rest.strip_suffix(')'))
+            .ok_or_else(|| {
+                anyhow!(
+                    "Invalid volume curve '{}': expected linear, logarithmic or custom_exponent(<number>)",
+                    value
+                )
+            })?;
+        let exponent: f32 = exponent
+            .trim()
+            .parse()
+            .map_err(|_| anyhow!("Invalid volume curve exponent '{}'", exponent))?;
+        if !exponent.is_finite() || exponent <= 0.0 {
+            return Err(anyhow!(
+                "Volume curve exponent must be a positive number, got {}",
+                exponent
+            ));
+        }
+        Ok(Self::CustomExponent(exponent))
+    }
+}
+
+impl Serialize for VolumeCurve {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for VolumeCurve {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
@@ -1,10 +1,26 @@
-use anyhow::Result;
 use config::{Config as ConfigFile, Environment, File};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::audio::{
+    OutputFormat, ReplayGainMode, SilenceSkip, StreamRequest, VolumeCurve,
+    DEFAULT_AUDIO_EXTENSIONS, DEFAULT_PRECACHE_CACHE_MB, DEFAULT_SILENCE_MIN_SECONDS,
+    DEFAULT_SILENCE_THRESHOLD_DB, DEFAULT_START_FADE_MS,
+};
+use crate::events::NowPlayingTemplate;
+use crate::library::{DeleteMode, DuplicatePreferences, ReadOnlyPaths, ScanConflict};
+use crate::playlist::{MusicRoot, MusicRoots, RepeatMode};
+
+mod paths;
+pub use paths::Paths;
+#[allow(unused_imports)]
+pub use paths::{DATA_DIR_ENV, DATA_DIR_FLAG};
 
 /// Application configuration
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct Config {
     /// Audio settings
@@ -22,6 +38,48 @@ pub struct Config {
     /// External services configuration
     #[serde(default)]
     pub services: ServicesConfig,
+    /// API server settings
+    #[serde(default)]
+    pub api: ApiConfig,
+    /// HTTP endpoints notified about backend events
+    #[serde(default)]
+    pub webhooks: Vec<WebhookConfig>,
+    /// Event log settings
+    #[serde(default)]
+    pub events: EventsConfig,
+    /// Outputs for other applications, such as streaming overlays
+    #[serde(default)]
+    pub integrations: IntegrationsConfig,
+}
+
+/// Integrations with other applications
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IntegrationsConfig {
+    /// Keep this text file showing the current track, e.g. for OBS overlays, or
+    /// print it to standard output when set to "-" (disabled when unset)
+    pub now_playing_file: Option<PathBuf>,
+    /// Text written for the current track; placeholders are `{title}`, `{artist}`,
+    /// `{album}`, `{position}` and `{duration}`, and unknown ones are refused
+    pub now_playing_template: NowPlayingTemplate,
+    /// Seconds between updates of templates showing `{position}`
+    pub now_playing_refresh_secs: u64,
+    /// Show the current track in the Windows and macOS media overlay and take its
+    /// play, pause, skip and seek controls and the media keys. Needs a build with the
+    /// `media-controls` feature; read at startup
+    pub media_session: bool,
+}
+
+/// Event log configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventsConfig {
+    /// Append every backend event to this JSON Lines file (disabled when unset)
+    pub log_file: Option<PathBuf>,
+    /// Size in megabytes at which the log file is rotated
+    pub log_max_size_mb: u64,
+    /// Log files kept, including the current one
+    pub log_max_files: usize,
 }
 
 /// Audio playback configuration
@@ -30,26 +88,232 @@ pub struct Config {
 pub struct AudioConfig {
     /// Default volume (0.0 to 1.0)
     pub default_volume: f32,
-    /// Audio output device
+    /// Output device to play on, matched by name ignoring case (part of the name will
+    /// do); the default device when unset or when no device matches. Listed at
+    /// `GET /api/audio/devices`
     pub output_device: Option<String>,
-    /// Sample rate
+    /// Sample rate requested from the output device (0 = device default). Read
+    /// when the device is opened, so changes apply after a restart
     pub sample_rate: u32,
-    /// Buffer size
+    /// Frames per output buffer requested from the device (0 = device default)
     pub buffer_size: usize,
+    /// How the volume slider maps to output level: linear, logarithmic or
+    /// custom_exponent(<number>)
+    #[serde(deserialize_with = "deserialize_volume_curve")]
+    pub volume_curve: VolumeCurve,
+    /// Even out loudness with the tracks' ReplayGain tags: off, track (every track
+    /// at the same loudness) or album (every album, keeping the differences within
+    /// it). Untagged tracks play at unity gain
+    pub replaygain_mode: ReplayGainMode,
+    /// Resample files with another sample rate to this one, e.g. for output devices
+    /// that crackle on mismatched streams
+    pub resample_to: Option<u32>,
+    /// Reduce files with more bits per sample to this depth
+    pub bit_depth_fallback: Option<u16>,
+    /// Milliseconds playback is ramped in over when it starts, and out over when it is
+    /// stopped or moves to another track, against clicks on some DACs (0 = disabled).
+    /// Gapless transitions are not ramped
+    pub start_fade_ms: u32,
+    /// Milliseconds the volume is ramped down over before pausing or stopping, and back
+    /// up over when resuming (0 = disabled)
+    pub fade_on_pause_ms: u32,
+    /// Seconds before a track ends that the next one is announced with an `up_next`
+    /// event (0 = disabled)
+    pub up_next_lead_seconds: u32,
+    /// Fast-forward through silence lasting longer than `silence_min_seconds`, such as
+    /// applause gaps between tracks on live albums
+    pub skip_silence: bool,
+    /// RMS level below which audio counts as silent, in dBFS
+    pub silence_threshold_db: f32,
+    /// Seconds audio must stay below the threshold before the rest is skipped; quiet
+    /// passages shorter than this always play in full
+    pub silence_min_seconds: f32,
+    /// Remember where tracks lasting at least this many minutes, such as audiobooks
+    /// and mixes, were left off and resume from there when they are played again
+    /// (0 = disabled)
+    pub resume_min_minutes: u32,
+    /// Pause playback after this many minutes with nothing listening: the output
+    /// device reports no active route, or it disappeared and has not come back
+    /// (0 = disabled). Playback is resumed by hand
+    pub auto_pause_on_silence_minutes: u32,
+    /// Copy the next track into a local cache before it plays when it is at most this
+    /// many megabytes, or read this much of it ahead when larger, for music on slow or
+    /// network storage (0 = disabled). Read at startup
+    pub precache_mb: u64,
+    /// Megabytes the precached copies may take before the least recently played are
+    /// evicted
+    pub precache_cache_mb: u64,
 }
 
 /// Music library configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
 pub struct LibraryConfig {
-    /// Default music directories to scan
-    pub music_directories: Vec<PathBuf>,
-    /// Supported audio file extensions
+    /// Default music directories to scan, as paths or as
+    /// `{ path = "...", read_only = true }` tables
+    pub music_directories: Vec<MusicDirectory>,
+    /// Extensions of the files scans and refreshes pick up, without the dot. Read at
+    /// startup
     pub supported_extensions: Vec<String>,
     /// Auto-scan on startup
     pub auto_scan: bool,
     /// Scan interval in seconds (0 = disabled)
     pub scan_interval: u64,
+    /// What deleting a track through the API does to its file: forbid, trash or permanent
+    pub delete_mode: DeleteMode,
+    /// Days a trashed file can be restored before it is purged; files in the home
+    /// trash are only forgotten, and left for the desktop to empty
+    pub trash_retention_days: u32,
+    /// Keep cached tracks whose modification time changed but whose content
+    /// fingerprint (size plus first and last 64 KiB) did not, instead of re-reading
+    /// their metadata. Costs extra reads when the cache is saved.
+    pub content_fingerprints: bool,
+    /// List albums sharing a title and artist, such as a reissue or deluxe edition,
+    /// as separate editions when their release years or track totals differ
+    pub album_disambiguation: bool,
+    /// List albums crediting more distinct artists than this, none of whose tracks has
+    /// an album artist tag, under "Various Artists"; 0 disables
+    pub various_artists_threshold: usize,
+    /// Never write tags or sidecars to, delete or move library files, e.g. when the
+    /// library is a network share mounted by several machines. The library cache and
+    /// playlists are stored locally and keep working.
+    pub read_only: bool,
+    /// Which copy of a duplicated track is recommended as the one to keep
+    pub duplicates: DuplicatePreferences,
+    /// Genre spellings and the names they are listed under, such as
+    /// `"hip hop/rap" = "Hip-Hop"`, next to the built-in aliases. Compared ignoring
+    /// case and punctuation.
+    pub genre_aliases: BTreeMap<String, String>,
+    /// What API requests changing tracks do while a scan runs: wait for it to finish,
+    /// or reject, answering 409 with a Retry-After hint
+    pub scan_conflict: ScanConflict,
+    /// Audio files a scan handles between the `library_scan` events reporting its
+    /// progress. Read at startup
+    pub scan_progress_interval: usize,
+    /// Count artists guessed from the file names of untagged tracks in the artist
+    /// listing; guessed titles and artists are shown and searched either way
+    pub list_guessed_artists: bool,
+    /// Locale artist, album and track listings are sorted for, such as `sv-SE`; the
+    /// root order, which sorts accented letters with their base letter, when unset
+    /// or unknown
+    pub sort_locale: Option<String>,
+}
+
+/// A music directory, written in the config as a plain path or as a table
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "MusicDirectoryEntry", into = "MusicDirectoryEntry")]
+pub struct MusicDirectory {
+    pub path: PathBuf,
+    /// Whether files under this directory must not be modified, like `library.read_only`
+    /// for this directory alone
+    pub read_only: bool,
+    /// Name portable playlist paths refer to this directory by; its last path
+    /// component by default. Give the same name on every machine sharing playlists.
+    pub name: Option<String>,
+}
+
+impl MusicDirectory {
+    /// Name portable playlist paths refer to this directory by
+    pub fn logical_name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => self
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_else(|| self.path.to_string_lossy().to_string()),
+        }
+    }
+}
+
+impl From<PathBuf> for MusicDirectory {
+    fn from(path: PathBuf) -> Self {
+        Self {
+            path,
+            read_only: false,
+            name: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum MusicDirectoryEntry {
+    Path(PathBuf),
+    Table {
+        path: PathBuf,
+        #[serde(default)]
+        read_only: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        name: Option<String>,
+    },
+}
+
+impl From<MusicDirectoryEntry> for MusicDirectory {
+    fn from(entry: MusicDirectoryEntry) -> Self {
+        match entry {
+            MusicDirectoryEntry::Path(path) => path.into(),
+            MusicDirectoryEntry::Table {
+                path,
+                read_only,
+                name,
+            } => Self {
+                path,
+                read_only,
+                name,
+            },
+        }
+    }
+}
+
+impl From<MusicDirectory> for MusicDirectoryEntry {
+    fn from(directory: MusicDirectory) -> Self {
+        if directory.read_only || directory.name.is_some() {
+            Self::Table {
+                path: directory.path,
+                read_only: directory.read_only,
+                name: directory.name,
+            }
+        } else {
+            Self::Path(directory.path)
+        }
+    }
+}
+
+impl LibraryConfig {
+    /// Paths of the configured music directories
+    pub fn music_directory_paths(&self) -> Vec<PathBuf> {
+        self.music_directories
+            .iter()
+            .map(|directory| directory.path.clone())
+            .collect()
+    }
+
+    /// The music directories by the names portable playlist paths refer to them by
+    pub fn music_roots(&self) -> MusicRoots {
+        MusicRoots::new(
+            self.music_directories
+                .iter()
+                .map(|directory| MusicRoot {
+                    name: directory.logical_name(),
+                    path: directory.path.clone(),
+                })
+                .collect(),
+        )
+    }
+
+    /// Library files that must not be modified
+    pub fn read_only_paths(&self) -> ReadOnlyPaths {
+        ReadOnlyPaths {
+            everything: self.read_only,
+            roots: self
+                .music_directories
+                .iter()
+                .filter(|directory| directory.read_only)
+                .map(|directory| directory.path.clone())
+                .collect(),
+        }
+    }
 }
 
 /// GUI configuration
@@ -76,18 +340,114 @@ pub struct PlaylistConfig {
     pub auto_save: bool,
     /// Max playlist history
     pub max_history: usize,
+    /// Repeat mode applied on first start (none, one, all)
+    #[serde(deserialize_with = "deserialize_repeat_mode")]
+    pub default_repeat_mode: RepeatMode,
+    /// Whether shuffle is enabled on first start
+    pub default_shuffle: bool,
+    /// Write the paths of playlist entries relative to the music directories, as a
+    /// directory's `name` and the path below it, for playlist folders synced between
+    /// machines whose music lives elsewhere. Existing playlists are converted by the
+    /// `playlist_paths` maintenance task.
+    pub portable_paths: bool,
 }
 
-/// Third-party services configuration
+/// Parse a repeat mode leniently, so a typo does not discard the whole config file.
+fn deserialize_repeat_mode<'de, D>(deserializer: D) -> Result<RepeatMode, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    Ok(value.parse().unwrap_or_else(|e| {
+        warn!("{}, falling back to 'none'", e);
+        RepeatMode::None
+    }))
+}
+
+/// Parse a volume curve leniently, like the repeat mode.
+fn deserialize_volume_curve<'de, D>(deserializer: D) -> Result<VolumeCurve, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    Ok(value.parse().unwrap_or_else(|e| {
+        warn!("{}, falling back to 'linear'", e);
+        VolumeCurve::Linear
+    }))
+}
+
+/// API server configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(default)]
+pub struct ApiConfig {
+    /// TCP port the API listens on (127.0.0.1 only)
+    pub port: u16,
+    /// Whether to listen on the TCP port; disable to only serve `unix_socket`
+    pub listen_tcp: bool,
+    /// Also serve the API on this Unix domain socket (Unix only)
+    pub unix_socket: Option<PathBuf>,
+    /// Keep up to this many MiB of transcoded streams so they are not transcoded
+    /// again; 0 caches nothing
+    pub transcode_cache_mb: u64,
+    /// Largest request body, in MiB, accepted by import endpoints such as the CSV
+    /// playlist import; larger ones are refused with 413
+    pub max_import_mb: u64,
+    /// How long requests may take before they are answered with 504
+    pub timeouts: RouteTimeouts,
+    /// Token of the owner's requests, sent as `Authorization: Bearer`; required along
+    /// with `guest_token`, when every request needs one of the two
+    pub auth_token: Option<String>,
+    /// Token of the guest profile: requests sending it as `Authorization: Bearer` may
+    /// browse the library, queue tracks, skip and set the volume, but change nothing
+    /// else
+    pub guest_token: Option<String>,
+    /// Tracks guests may queue per minute
+    pub guest_enqueues_per_minute: u32,
+    /// Highest volume guests may set, from 0.0 to 1.0
+    pub guest_max_volume: f32,
+    /// Whether share links may stream the tracks they share, rather than only show
+    /// their metadata and artwork
+    pub share_allow_stream: bool,
+    /// Minutes finished jobs (scans, verifications, maintenance runs) stay listed at
+    /// `/api/jobs`
+    pub job_history_minutes: u64,
+    /// Whether finished jobs are saved, so their history survives restarts
+    pub persist_jobs: bool,
+}
+
+/// Seconds a request may take before it is answered with 504, by kind of route; 0
+/// never times out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RouteTimeouts {
+    /// Health and status checks, such as `/api/health` and `/api/audio/status`
+    pub status_secs: u64,
+    /// Routes that are neither status checks nor long ones
+    pub default_secs: u64,
+    /// Maintenance, setup, playlist imports and edits of many files
+    pub long_secs: u64,
+}
+
+impl Default for RouteTimeouts {
+    fn default() -> Self {
+        Self {
+            status_secs: crate::api::DEFAULT_STATUS_TIMEOUT_SECS,
+            default_secs: crate::api::DEFAULT_ROUTE_TIMEOUT_SECS,
+            long_secs: crate::api::DEFAULT_LONG_TIMEOUT_SECS,
+        }
+    }
+}
+
+/// Third-party services configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ServicesConfig {
     /// Last.fm integration settings
     pub lastfm: LastFmConfig,
 }
 
 /// Last.fm API credentials
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(default)]
 pub struct LastFmConfig {
     /// Public API key
@@ -96,14 +456,67 @@ pub struct LastFmConfig {
     pub shared_secret: String,
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self {
-            audio: AudioConfig::default(),
-            library: LibraryConfig::default(),
-            gui: GuiConfig::default(),
-            playlist: PlaylistConfig::default(),
-            services: ServicesConfig::default(),
+/// A URL that receives backend events as JSON `POST` requests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// Receiver URL
+    pub url: String,
+    /// Event types to deliver (e.g. `playback_state`); empty delivers every event
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Sent as the `X-Hexendrum-Token` header so receivers can verify the sender
+    #[serde(default)]
+    pub secret: Option<String>,
+    /// Request timeout in seconds
+    #[serde(default = "default_webhook_timeout")]
+    pub timeout_secs: u64,
+    /// Delivery attempts after the first failure
+    #[serde(default = "default_webhook_retries")]
+    pub max_retries: u32,
+}
+
+fn default_webhook_timeout() -> u64 {
+    5
+}
+
+fn default_webhook_retries() -> u32 {
+    2
+}
+
+impl AudioConfig {
+    /// Conversions the audio player applies before output
+    pub fn output_format(&self) -> OutputFormat {
+        OutputFormat {
+            resample_to: self.resample_to,
+            bit_depth_fallback: self.bit_depth_fallback,
+            skip_silence: self.skip_silence.then(|| SilenceSkip {
+                threshold_db: self.silence_threshold_db,
+                min_silence: Duration::try_from_secs_f32(self.silence_min_seconds)
+                    .unwrap_or_default(),
+            }),
+            fade: Duration::from_millis(u64::from(self.start_fade_ms)),
+        }
+    }
+
+    /// How long pause, resume and stop fades last
+    pub fn pause_fade(&self) -> Duration {
+        Duration::from_millis(u64::from(self.fade_on_pause_ms))
+    }
+
+    /// How long playback may go unheard before it is paused; `None` when disabled
+    pub fn auto_pause_after(&self) -> Option<Duration> {
+        Some(self.auto_pause_on_silence_minutes)
+            .filter(|minutes| *minutes > 0)
+            .map(|minutes| Duration::from_secs(u64::from(minutes) * 60))
+    }
+
+    /// Output stream parameters asked of the device; 0 leaves the choice to it
+    pub fn stream_request(&self) -> StreamRequest {
+        StreamRequest {
+            sample_rate: Some(self.sample_rate).filter(|rate| *rate > 0),
+            buffer_frames: u32::try_from(self.buffer_size)
+                .ok()
+                .filter(|frames| *frames > 0),
         }
     }
 }
@@ -115,6 +528,20 @@ impl Default for AudioConfig {
             output_device: None,
             sample_rate: 44100,
             buffer_size: 4096,
+            volume_curve: VolumeCurve::Linear,
+            replaygain_mode: ReplayGainMode::Off,
+            resample_to: None,
+            bit_depth_fallback: None,
+            start_fade_ms: DEFAULT_START_FADE_MS,
+            fade_on_pause_ms: 0,
+            up_next_lead_seconds: 10,
+            skip_silence: false,
+            silence_threshold_db: DEFAULT_SILENCE_THRESHOLD_DB,
+            silence_min_seconds: DEFAULT_SILENCE_MIN_SECONDS,
+            resume_min_minutes: 20,
+            auto_pause_on_silence_minutes: 0,
+            precache_mb: 0,
+            precache_cache_mb: DEFAULT_PRECACHE_CACHE_MB,
         }
     }
 }
@@ -124,16 +551,56 @@ impl Default for LibraryConfig {
         Self {
             music_directories: vec![dirs::home_dir()
                 .unwrap_or_else(|| PathBuf::from("~"))
-                .join("Music")],
-            supported_extensions: vec![
-                "mp3".to_string(),
-                "flac".to_string(),
-                "ogg".to_string(),
-                "wav".to_string(),
-                "m4a".to_string(),
-            ],
+                .join("Music")
+                .into()],
+            supported_extensions: DEFAULT_AUDIO_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
             auto_scan: true,
             scan_interval: 300, // 5 minutes
+            delete_mode: DeleteMode::Forbid,
+            trash_retention_days: 30,
+            content_fingerprints: false,
+            album_disambiguation: false,
+            various_artists_threshold: crate::library::DEFAULT_VARIOUS_ARTISTS_THRESHOLD,
+            read_only: false,
+            duplicates: DuplicatePreferences::default(),
+            genre_aliases: BTreeMap::new(),
+            scan_conflict: ScanConflict::Wait,
+            scan_progress_interval: crate::library::DEFAULT_SCAN_PROGRESS_INTERVAL,
+            list_guessed_artists: false,
+            sort_locale: None,
+        }
+    }
+}
+
+impl Default for EventsConfig {
+    fn default() -> Self {
+        Self {
+            log_file: None,
+            log_max_size_mb: 10,
+            log_max_files: 3,
+        }
+    }
+}
+
+impl Default for ApiConfig {
+    fn default() -> Self {
+        Self {
+            port: crate::api::DEFAULT_PORT,
+            listen_tcp: true,
+            unix_socket: None,
+            transcode_cache_mb: 0,
+            max_import_mb: crate::api::DEFAULT_IMPORT_LIMIT_MB,
+            timeouts: RouteTimeouts::default(),
+            auth_token: None,
+            guest_token: None,
+            guest_enqueues_per_minute: crate::api::DEFAULT_GUEST_ENQUEUES_PER_MINUTE,
+            guest_max_volume: crate::api::DEFAULT_GUEST_MAX_VOLUME,
+            share_allow_stream: false,
+            job_history_minutes: crate::api::DEFAULT_JOB_HISTORY_MINUTES,
+            persist_jobs: false,
         }
     }
 }
@@ -152,64 +619,110 @@ impl Default for GuiConfig {
 impl Default for PlaylistConfig {
     fn default() -> Self {
         Self {
-            playlist_directory: dirs::config_dir()
-                .unwrap_or_else(|| PathBuf::from("~/.config"))
-                .join("hexendrum")
-                .join("playlists"),
+            playlist_directory: Paths::standard().playlist_dir(),
             auto_save: true,
             max_history: 100,
+            default_repeat_mode: RepeatMode::None,
+            default_shuffle: false,
+            portable_paths: false,
         }
     }
 }
 
-impl Default for ServicesConfig {
+impl Default for IntegrationsConfig {
     fn default() -> Self {
         Self {
-            lastfm: LastFmConfig::default(),
+            now_playing_file: None,
+            now_playing_template: NowPlayingTemplate::default(),
+            now_playing_refresh_secs: 5,
+            media_session: true,
         }
     }
 }
 
-impl Default for LastFmConfig {
-    fn default() -> Self {
-        Self {
-            api_key: String::new(),
-            shared_secret: String::new(),
-        }
-    }
+/// Why the configuration could not be loaded, saved or located
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    /// The config file or a `HEXENDRUM_` environment variable cannot be parsed or
+    /// holds a value of the wrong type
+    #[error("Invalid configuration in {}: {source}", path.display())]
+    Invalid {
+        path: PathBuf,
+        #[source]
+        source: config::ConfigError,
+    },
+    /// A command line flag was given without its value
+    #[error("{0} requires a path")]
+    MissingFlagValue(&'static str),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] toml::ser::Error),
 }
 
 impl Config {
-    /// Load configuration from file and environment
-    pub fn load() -> Result<Self> {
-        let config_dir = dirs::config_dir()
-            .unwrap_or_else(|| PathBuf::from("~/.config"))
-            .join("hexendrum");
-
-        let config_file = config_dir.join("config.toml");
+    /// Load configuration from the config file in `paths` and the environment
+    pub fn load(paths: &Paths) -> Result<Self, ConfigError> {
+        let config_file = paths.config_file();
 
-        let config = ConfigFile::builder()
+        ConfigFile::builder()
             .add_source(File::from(config_file.as_path()).required(false))
             .add_source(Environment::with_prefix("HEXENDRUM"))
-            .build()?;
-
-        let config: Config = config.try_deserialize()?;
-        Ok(config)
+            .build()
+            .and_then(ConfigFile::try_deserialize)
+            .map_err(|source| ConfigError::Invalid {
+                path: config_file,
+                source,
+            })
     }
 
-    /// Save configuration to file
+    /// Save configuration to the config file in `paths`
     #[allow(dead_code)]
-    pub fn save(&self) -> Result<()> {
-        let config_dir = dirs::config_dir()
-            .unwrap_or_else(|| PathBuf::from("~/.config"))
-            .join("hexendrum");
-
-        std::fs::create_dir_all(&config_dir)?;
+    pub fn save(&self, paths: &Paths) -> Result<(), ConfigError> {
+        std::fs::create_dir_all(&paths.config_dir)?;
 
-        let config_file = config_dir.join("config.toml");
         let config_str = toml::to_string_pretty(self)?;
-        std::fs::write(config_file, config_str)?;
+        std::fs::write(paths.config_file(), config_str)?;
 
         Ok(())
     }
 }
+
+/// How often the configuration file is checked for changes.
+const CONFIG_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Reload the configuration whenever the config file changes on disk, passing the new
+/// configuration to `on_change`. Files that fail to parse are reported and ignored.
+pub fn watch_config_file<F>(paths: Paths, on_change: F) -> tokio::task::JoinHandle<()>
+where
+    F: Fn(Config) + Send + 'static,
+{
+    fn modified(path: &std::path::Path) -> Option<std::time::SystemTime> {
+        std::fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    tokio::spawn(async move {
+        let path = paths.config_file();
+        let mut last_modified = modified(&path);
+
+        loop {
+            tokio::time::sleep(CONFIG_POLL_INTERVAL).await;
+
+            let current = modified(&path);
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+
+            match Config::load(&paths) {
+                Ok(config) => {
+                    info!("Configuration file changed, reloading");
+                    on_change(config);
+                }
+                Err(error) => warn!("Ignoring invalid configuration change: {}", error),
+            }
+        }
+    })
+}
@@ -0,0 +1,163 @@
+use std::path::PathBuf;
+
+use super::ConfigError;
+
+/// Environment variable selecting a data directory, see [`Paths::resolve`].
+pub const DATA_DIR_ENV: &str = "HEXENDRUM_DATA_DIR";
+
+/// Command line flag selecting a data directory, see [`Paths::resolve`].
+pub const DATA_DIR_FLAG: &str = "--data-dir";
+
+/// Where configuration, caches and other state are stored.
+///
+/// By default these follow the platform conventions (XDG directories on Linux). With a
+/// data directory everything lives under that one directory instead, which allows
+/// running from removable media.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Paths {
+    /// Configuration and persistent state
+    pub config_dir: PathBuf,
+    /// Caches that can be rebuilt
+    pub cache_dir: PathBuf,
+    /// freedesktop home trash used for deleted tracks, if any
+    pub home_trash: Option<PathBuf>,
+}
+
+impl Paths {
+    /// Platform default locations.
+    pub fn standard() -> Self {
+        let home = || dirs::home_dir().unwrap_or_else(|| PathBuf::from("~"));
+        let config_dir = dirs::config_dir().unwrap_or_else(|| home().join(".config"));
+        let cache_dir = dirs::cache_dir().unwrap_or_else(|| home().join(".cache"));
+        let home_trash = if cfg!(target_os = "linux") {
+            dirs::data_dir().map(|dir| dir.join("Trash"))
+        } else {
+            None
+        };
+
+        Self {
+            config_dir: config_dir.join("hexendrum"),
+            cache_dir: cache_dir.join("hexendrum"),
+            home_trash,
+        }
+    }
+
+    /// Keep everything under `base`: configuration at the top level and caches in a
+    /// `cache` subdirectory. Deleted tracks go to the trash folder next to them, as
+    /// the home trash would be outside `base`.
+    pub fn portable(base: impl Into<PathBuf>) -> Self {
+        let base = base.into();
+        Self {
+            cache_dir: base.join("cache"),
+            config_dir: base,
+            home_trash: None,
+        }
+    }
+
+    /// Use `data_dir` when given, then `HEXENDRUM_DATA_DIR`, and the platform
+    /// defaults otherwise. Relative data directories are resolved against the
+    /// current directory.
+    pub fn resolve(data_dir: Option<PathBuf>) -> Result<Self, ConfigError> {
+        let data_dir = data_dir.or_else(|| {
+            std::env::var_os(DATA_DIR_ENV)
+                .filter(|value| !value.is_empty())
+                .map(PathBuf::from)
+        });
+
+        match data_dir {
+            Some(dir) => Ok(Self::portable(std::path::absolute(dir)?)),
+            None => Ok(Self::standard()),
+        }
+    }
+
+    /// Remove `--data-dir <path>` (or `--data-dir=<path>`) from `args`, returning the path.
+    pub fn take_data_dir_arg(args: &mut Vec<String>) -> Result<Option<PathBuf>, ConfigError> {
+        let Some(index) = args
+            .iter()
+            .position(|arg| arg == DATA_DIR_FLAG || arg.starts_with("--data-dir="))
+        else {
+            return Ok(None);
+        };
+
+        let flag = args.remove(index);
+        let value = match flag.split_once('=') {
+            Some((_, value)) => value.to_string(),
+            None if index < args.len() => args.remove(index),
+            None => return Err(ConfigError::MissingFlagValue(DATA_DIR_FLAG)),
+        };
+        if value.is_empty() {
+            return Err(ConfigError::MissingFlagValue(DATA_DIR_FLAG));
+        }
+        Ok(Some(PathBuf::from(value)))
+    }
+
+    pub fn config_file(&self) -> PathBuf {
+        self.config_dir.join("config.toml")
+    }
+
+    pub fn playlist_dir(&self) -> PathBuf {
+        self.config_dir.join("playlists")
+    }
+
+    pub fn queue_state_file(&self) -> PathBuf {
+        self.config_dir.join("queue_state.json")
+    }
+
+    pub fn stats_file(&self) -> PathBuf {
+        self.config_dir.join("stats.json")
+    }
+
+    pub fn album_overrides_file(&self) -> PathBuf {
+        self.config_dir.join("album_overrides.json")
+    }
+
+    /// Secret signing share links, see [`crate::api::ShareSigner`]
+    pub fn share_secret_file(&self) -> PathBuf {
+        self.config_dir.join("share_secret")
+    }
+
+    pub fn trash_journal_file(&self) -> PathBuf {
+        self.config_dir.join("trash_journal.json")
+    }
+
+    /// Finished jobs, when `api.persist_jobs` is set, see [`crate::api::JobManager`]
+    pub fn job_history_file(&self) -> PathBuf {
+        self.cache_dir.join("jobs.json")
+    }
+
+    /// Lock file held by the running backend, see [`crate::instance`]
+    pub fn instance_lock_file(&self) -> PathBuf {
+        self.cache_dir.join("hexendrum.lock")
+    }
+
+    pub fn library_cache_file(&self) -> PathBuf {
+        self.cache_dir.join("library_cache.json")
+    }
+
+    pub fn album_art_dir(&self) -> PathBuf {
+        self.cache_dir.join("album_art")
+    }
+
+    pub fn artist_art_dir(&self) -> PathBuf {
+        self.cache_dir.join("artist_art")
+    }
+
+    pub fn transcode_cache_dir(&self) -> PathBuf {
+        self.cache_dir.join("transcoded")
+    }
+
+    /// Local copies of tracks about to play, see [`crate::audio::Precache`]
+    pub fn precache_dir(&self) -> PathBuf {
+        self.cache_dir.join("precache")
+    }
+
+    pub fn waveform_cache_dir(&self) -> PathBuf {
+        self.cache_dir.join("waveforms")
+    }
+}
+
+impl Default for Paths {
+    fn default() -> Self {
+        Self::standard()
+    }
+}
@@ -0,0 +1,396 @@
+//! One-shot `hexendrum ctl` commands that control a running backend through its API.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::ErrorKind;
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::api::{
+    ApiErrorResponse, ApiResponse, AudioStatusResponse, PlayBehavior, PlayRequest, TrackResponse,
+    VolumeRequest, DEFAULT_PORT,
+};
+use crate::config::{ApiConfig, Config, Paths};
+use crate::maintenance::{MaintenanceReport, MaintenanceRequest};
+
+const USAGE: &str =
+    "Usage: hexendrum [--data-dir <path>] ctl <command> [--json] [--port <port> | --socket <path>]
+
+Commands:
+  status                 Show the playback status
+  play <path|track-id>   Play a file or library track
+  pause                  Pause playback
+  resume                 Resume playback
+  stop                   Stop playback
+  volume <0.0-1.0>       Set the volume";
+
+/// A control command sent to the backend.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CtlCommand {
+    Status,
+    Play(String),
+    Pause,
+    Resume,
+    Stop,
+    Volume(f32),
+}
+
+/// Where the backend API is reached.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CtlAddress {
+    /// TCP port on 127.0.0.1
+    Tcp(u16),
+    /// Unix domain socket path
+    Unix(PathBuf),
+}
+
+impl Default for CtlAddress {
+    fn default() -> Self {
+        Self::Tcp(DEFAULT_PORT)
+    }
+}
+
+/// The backend API to control and the token to send it.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CtlTarget {
+    pub address: CtlAddress,
+    /// Owner token sent as `Authorization: Bearer`
+    pub auth_token: Option<String>,
+}
+
+impl CtlTarget {
+    /// The target configured for the local backend: its Unix socket when TCP is
+    /// disabled, otherwise its TCP port, with `api.auth_token`.
+    pub fn from_config(config: &ApiConfig) -> Self {
+        let address = match &config.unix_socket {
+            Some(path) if !config.listen_tcp => CtlAddress::Unix(path.clone()),
+            _ => CtlAddress::Tcp(config.port),
+        };
+        Self {
+            address,
+            auth_token: config.auth_token.clone(),
+        }
+    }
+}
+
+/// Parsed `hexendrum ctl` invocation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CtlOptions {
+    pub command: CtlCommand,
+    /// Print the response data as JSON instead of a human readable line
+    pub json: bool,
+    pub target: CtlTarget,
+}
+
+impl CtlOptions {
+    /// Parse the arguments following `ctl`, connecting to `target` unless `--port` or
+    /// `--socket` is given.
+    pub fn parse(args: &[String], target: CtlTarget) -> Result<Self> {
+        let mut json = false;
+        let mut target = target;
+        let mut positional = Vec::new();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--json" => json = true,
+                "--port" => {
+                    let value = args.next().ok_or_else(|| anyhow!("--port needs a value"))?;
+                    let port = value
+                        .parse()
+                        .with_context(|| format!("invalid port '{}'", value))?;
+                    target.address = CtlAddress::Tcp(port);
+                }
+                "--socket" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--socket needs a value"))?;
+                    target.address = CtlAddress::Unix(PathBuf::from(value));
+                }
+                _ => positional.push(arg.as_str()),
+            }
+        }
+
+        let command = match positional.as_slice() {
+            ["status"] => CtlCommand::Status,
+            ["pause"] => CtlCommand::Pause,
+            ["resume"] => CtlCommand::Resume,
+            ["stop"] => CtlCommand::Stop,
+            ["play", target] => CtlCommand::Play(target.to_string()),
+            ["volume", level] => {
+                let level: f32 = level
+                    .parse()
+                    .with_context(|| format!("invalid volume '{}'", level))?;
+                if !(0.0..=1.0).contains(&level) {
+                    bail!("volume must be between 0.0 and 1.0");
+                }
+                CtlCommand::Volume(level)
+            }
+            [] => bail!("missing command"),
+            other => bail!("unknown command '{}'", other.join(" ")),
+        };
+
+        Ok(Self {
+            command,
+            json,
+            target,
+        })
+    }
+}
+
+/// Run `hexendrum ctl` with the arguments following `ctl` and return the exit code.
+pub async fn run(args: &[String], paths: &Paths) -> i32 {
+    let config = Config::load(paths).unwrap_or_default();
+    let options = match CtlOptions::parse(args, CtlTarget::from_config(&config.api)) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("hexendrum ctl: {}\n\n{}", error, USAGE);
+            return 2;
+        }
+    };
+
+    match execute(&options).await {
+        Ok(output) => {
+            println!("{}", output);
+            0
+        }
+        Err(error) => {
+            eprintln!("hexendrum ctl: {}", error);
+            1
+        }
+    }
+}
+
+/// Send the command to the backend and return the text to print.
+pub async fn execute(options: &CtlOptions) -> Result<String> {
+    let client = Client {
+        target: options.target.clone(),
+    };
+
+    let message = match &options.command {
+        CtlCommand::Status => {
+            let status: AudioStatusResponse = client.get("/api/audio/status").await?;
+            return if options.json {
+                Ok(serde_json::to_string_pretty(&status)?)
+            } else {
+                Ok(format_status(&status))
+            };
+        }
+        CtlCommand::Play(target) => {
+            let request = PlayRequest {
+                file_path: client.resolve_track(target).await?,
+                behavior: PlayBehavior::Interrupt,
+                from_start: false,
+            };
+            client.post("/api/audio/play", &request).await?
+        }
+        CtlCommand::Pause => client.post("/api/audio/pause", &()).await?,
+        CtlCommand::Resume => client.post("/api/audio/resume", &()).await?,
+        CtlCommand::Stop => client.post("/api/audio/stop", &()).await?,
+        CtlCommand::Volume(volume) => {
+            let request = VolumeRequest { volume: *volume };
+            client.post("/api/audio/volume", &request).await?
+        }
+    };
+
+    if options.json {
+        Ok(serde_json::to_string_pretty(&message)?)
+    } else {
+        Ok(message)
+    }
+}
+
+/// Ask the backend at `target` to shut down.
+pub async fn request_shutdown(target: &CtlTarget) -> Result<String> {
+    let client = Client {
+        target: target.clone(),
+    };
+    client.post("/api/system/shutdown", &()).await
+}
+
+/// Ask the backend at `target` to run the maintenance tasks of `request`.
+pub async fn request_maintenance(
+    target: &CtlTarget,
+    request: &MaintenanceRequest,
+) -> Result<MaintenanceReport> {
+    let client = Client {
+        target: target.clone(),
+    };
+    client.post("/api/maintenance", request).await
+}
+
+fn format_status(status: &AudioStatusResponse) -> String {
+    let track = status.current_track.as_deref().unwrap_or("no track");
+    format!(
+        "{}: {}\nvolume {}%, repeat {}, shuffle {}",
+        status.state,
+        track,
+        (status.volume * 100.0).round() as i32,
+        status.repeat_mode,
+        if status.shuffle { "on" } else { "off" }
+    )
+}
+
+/// Minimal HTTP/1.1 client for the backend on localhost.
+struct Client {
+    target: CtlTarget,
+}
+
+impl Client {
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T> {
+        self.send("GET", path, None).await
+    }
+
+    async fn post<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T> {
+        let body = serde_json::to_string(body)?;
+        self.send("POST", path, Some(body)).await
+    }
+
+    /// Turn a `play` argument into a file path: existing files are used as-is,
+    /// anything else is looked up as a library track identifier.
+    async fn resolve_track(&self, target: &str) -> Result<String> {
+        let path = Path::new(target);
+        if path.is_file() {
+            return Ok(std::path::absolute(path)?.to_string_lossy().to_string());
+        }
+
+        let tracks: Vec<TrackResponse> = self.get("/api/library/tracks").await?;
+        tracks
+            .into_iter()
+            .find(|track| track.id == target)
+            .map(|track| track.path)
+            .ok_or_else(|| anyhow!("'{}' is neither a file nor a library track id", target))
+    }
+
+    async fn send<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<String>,
+    ) -> Result<T> {
+        let body = body.unwrap_or_default();
+        let authorization = self
+            .target
+            .auth_token
+            .as_ref()
+            .map(|token| format!("Authorization: Bearer {}\r\n", token))
+            .unwrap_or_default();
+        let request = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n{}Content-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            authorization,
+            body.len(),
+            body
+        );
+
+        let response = match &self.target.address {
+            CtlAddress::Tcp(port) => {
+                let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, *port))
+                    .await
+                    .map_err(|error| {
+                        connect_error(error, format!("nothing is listening on 127.0.0.1:{}", port))
+                    })?;
+                exchange(stream, &request).await?
+            }
+            #[cfg(unix)]
+            CtlAddress::Unix(socket) => {
+                let stream = tokio::net::UnixStream::connect(socket)
+                    .await
+                    .map_err(|error| {
+                        connect_error(error, format!("no backend at {}", socket.display()))
+                    })?;
+                exchange(stream, &request).await?
+            }
+            #[cfg(not(unix))]
+            CtlAddress::Unix(_) => bail!("Unix sockets are not supported on this platform"),
+        };
+        let (status, body) = parse_response(&response)?;
+
+        if !(200..300).contains(&status) {
+            let message = serde_json::from_slice::<ApiErrorResponse>(&body)
+                .map(|error| error.error)
+                .unwrap_or_else(|_| String::from_utf8_lossy(&body).trim().to_string());
+            bail!("backend responded with HTTP {}: {}", status, message);
+        }
+
+        let response: ApiResponse<T> =
+            serde_json::from_slice(&body).context("unexpected response from the backend")?;
+        response
+            .data
+            .ok_or_else(|| anyhow!(response.error.unwrap_or_else(|| "empty response".into())))
+    }
+}
+
+fn connect_error(error: std::io::Error, detail: String) -> anyhow::Error {
+    match error.kind() {
+        ErrorKind::ConnectionRefused | ErrorKind::NotFound => {
+            anyhow!("backend not running ({})", detail)
+        }
+        _ => error.into(),
+    }
+}
+
+/// Send `request` and read the response until the backend closes the connection.
+async fn exchange<S>(mut stream: S, request: &str) -> Result<Vec<u8>>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    stream.write_all(request.as_bytes()).await?;
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).await?;
+    Ok(response)
+}
+
+/// Split a raw HTTP response into its status code and (de-chunked) body.
+fn parse_response(response: &[u8]) -> Result<(u16, Vec<u8>)> {
+    let split = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| anyhow!("malformed response from the backend"))?;
+    let head = String::from_utf8_lossy(&response[..split]);
+    let body = &response[split + 4..];
+
+    let status = head
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| anyhow!("malformed status line from the backend"))?;
+
+    let chunked = head.lines().any(|line| {
+        let line = line.to_ascii_lowercase();
+        line.starts_with("transfer-encoding:") && line.contains("chunked")
+    });
+
+    if chunked {
+        Ok((status, decode_chunked(body)?))
+    } else {
+        Ok((status, body.to_vec()))
+    }
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut decoded = Vec::new();
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or_else(|| anyhow!("malformed chunked response"))?;
+        let size_field = String::from_utf8_lossy(&body[..line_end]);
+        let size = usize::from_str_radix(size_field.split(';').next().unwrap_or("").trim(), 16)
+            .map_err(|_| anyhow!("malformed chunk size"))?;
+        body = &body[line_end + 2..];
+        if size == 0 {
+            return Ok(decoded);
+        }
+        let chunk = body
+            .get(..size)
+            .ok_or_else(|| anyhow!("truncated chunked response"))?;
+        decoded.extend_from_slice(chunk);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
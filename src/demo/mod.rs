@@ -0,0 +1,283 @@
+//! `hexendrum --demo`: the API over a generated library.
+//!
+//! Frontend development and demos need a backend without a music collection, an audio
+//! device or a configuration file. [`state`] builds an [`AppState`] around a library of
+//! tracks generated from a fixed seed and kept in memory, playlists that are never
+//! saved and an audio player whose null backend plays nothing while the position moves
+//! on as usual. Scans leave the generated library alone, and album artwork is never
+//! fetched. What the remaining services keep on disk (statistics, the share secret,
+//! waveforms) goes to a scratch directory.
+
+use anyhow::Result;
+use chrono::{TimeZone, Utc};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::Notify;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::api::{
+    self, AppState, JobManager, PlaybackRevision, ResumePositions, RouteBudgets, ShareSigner,
+    SleepTimer, UpNextWatcher,
+};
+use crate::audio::{AudioBackend, AudioPlayer, DeviceRecoveryPolicy, NullBackend, TechnicalInfo};
+use crate::config::{Config, Paths};
+use crate::events::{EventBus, WebhookDispatcher};
+use crate::library::{
+    AlbumService, Library, StatsStore, Track, TrackMetadata, Trash, VerificationJob, WaveformCache,
+};
+use crate::playlist::{PlaybackQueue, PlaylistManager};
+
+/// Command-line flag starting the backend in demo mode
+pub const DEMO_FLAG: &str = "--demo";
+
+/// Environment variable starting the backend in demo mode when set to `1`
+pub const DEMO_ENV: &str = "HEXENDRUM_DEMO";
+
+/// Seed of the library served in demo mode
+pub const DEMO_SEED: u64 = 0x4845_5845_4e44_5255;
+
+/// Number of artists in a generated library, each with two to four albums
+const ARTISTS: usize = 16;
+
+/// Where the generated files would be, were they real
+const DEMO_ROOT: &str = "/demo";
+
+const ADJECTIVES: &[&str] = &[
+    "Silent", "Electric", "Hollow", "Golden", "Midnight", "Crimson", "Distant", "Northern",
+    "Broken", "Velvet", "Frozen", "Burning", "Paper", "Glass", "Wild", "Quiet", "Neon", "Lonely",
+    "Restless", "Scarlet",
+];
+
+const NOUNS: &[&str] = &[
+    "Harbor",
+    "Garden",
+    "Signal",
+    "River",
+    "Machine",
+    "Lantern",
+    "Horizon",
+    "Echo",
+    "Forest",
+    "Mirror",
+    "Engine",
+    "Tide",
+    "Orchard",
+    "Comet",
+    "Valley",
+    "Static",
+    "Satellite",
+    "Meadow",
+    "Avenue",
+    "Parade",
+];
+
+const GENRES: &[&str] = &[
+    "Rock",
+    "Jazz",
+    "Electronic",
+    "Hip-Hop",
+    "Classical",
+    "Folk",
+    "Ambient",
+    "Pop",
+];
+
+/// Whether `args` or the environment ask for demo mode
+pub fn requested(args: &[String]) -> bool {
+    args.iter().any(|arg| arg == DEMO_FLAG)
+        || std::env::var(DEMO_ENV).is_ok_and(|value| value == "1")
+}
+
+/// SplitMix64, so a seed always yields the same library
+struct SeededRng(u64);
+
+impl SeededRng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `range`
+    fn between(&mut self, range: std::ops::RangeInclusive<u64>) -> u64 {
+        range.start() + self.next() % (range.end() - range.start() + 1)
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.next() as usize % items.len()]
+    }
+
+    /// "Adjective Noun", different from every name in `taken`
+    fn name(&mut self, taken: &mut HashSet<String>) -> String {
+        loop {
+            let name = format!("{} {}", self.pick(ADJECTIVES), self.pick(NOUNS));
+            if taken.insert(name.clone()) {
+                return name;
+            }
+        }
+    }
+}
+
+/// A library of a few hundred tracks by [`ARTISTS`] artists, the same for the same
+/// `seed`. The tracks carry full tags and technical details but have no files.
+pub fn generate_tracks(seed: u64) -> Vec<Track> {
+    let mut rng = SeededRng(seed);
+    let last_modified = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    let mut artist_names = HashSet::new();
+    let mut tracks = Vec::new();
+
+    for _ in 0..ARTISTS {
+        let artist = match rng.between(0..=2) {
+            0 => format!("The {}s", rng.name(&mut artist_names)),
+            _ => rng.name(&mut artist_names),
+        };
+        let genre = rng.pick(GENRES);
+        let mut album_titles = HashSet::new();
+        for _ in 0..rng.between(2..=4) {
+            let album = rng.name(&mut album_titles);
+            let year = rng.between(1965..=2024) as i32;
+            let track_total = rng.between(6..=14) as u32;
+            for track_number in 1..=track_total {
+                let title = rng.name(&mut HashSet::new());
+                let seconds = rng.between(95..=480);
+                let file_path = Path::new(DEMO_ROOT)
+                    .join(&artist)
+                    .join(&album)
+                    .join(format!("{:02} {}.flac", track_number, title));
+                tracks.push(Track {
+                    metadata: TrackMetadata {
+                        title: Some(title),
+                        artist: Some(artist.clone()),
+                        album: Some(album.clone()),
+                        album_artist: None,
+                        track_number: Some(track_number),
+                        track_total: Some(track_total),
+                        year: Some(year),
+                        genre: Some(genre.to_string()),
+                        composer: None,
+                        work: None,
+                        movement: None,
+                        movement_number: None,
+                        duration: Some(seconds),
+                        chapters: Vec::new(),
+                        file_size: seconds * 112_500,
+                        last_modified,
+                        file_path,
+                        metadata_source: Default::default(),
+                        guessed: Default::default(),
+                        technical: Some(TechnicalInfo {
+                            codec: Some("flac".into()),
+                            sample_rate: Some(44100),
+                            channels: Some(2),
+                            bits_per_sample: Some(16),
+                            bitrate_kbps: Some(900),
+                        }),
+                        musicbrainz: Default::default(),
+                        replaygain: Default::default(),
+                    },
+                    id: Uuid::from_u64_pair(rng.next(), rng.next()).to_string(),
+                });
+            }
+        }
+    }
+
+    tracks
+}
+
+/// The application state of demo mode, serving the library generated from `seed`,
+/// with the remaining services keeping their files under `paths`
+pub fn state(paths: &Paths, config: &Config, seed: u64) -> Result<AppState> {
+    let library = Arc::new(Library::in_memory());
+    for track in generate_tracks(seed) {
+        library.add_track(track);
+    }
+
+    let event_bus = Arc::new(EventBus::new(None));
+    let request = config.audio.stream_request();
+    let audio_player = AudioPlayer::with_backend(
+        move || Ok(Box::new(NullBackend::without_files(request)) as Box<dyn AudioBackend>),
+        DeviceRecoveryPolicy::default(),
+        Some(event_bus.clone()),
+    )?;
+
+    let jobs = Arc::new(JobManager::new().with_event_bus(event_bus.clone()));
+    Ok(AppState {
+        library,
+        playlist_manager: Arc::new(PlaylistManager::in_memory()),
+        playback_queue: Arc::new(PlaybackQueue::new()),
+        audio_player: Arc::new(audio_player),
+        album_service: Arc::new(
+            AlbumService::with_paths(paths, None)
+                .with_album_disambiguation(config.library.album_disambiguation)
+                .with_various_artists_threshold(config.library.various_artists_threshold)
+                .with_event_bus(event_bus.clone()),
+        ),
+        stats_store: Arc::new(StatsStore::with_path(paths.stats_file())),
+        verification_job: Arc::new(VerificationJob::new()),
+        webhooks: WebhookDispatcher::start(&event_bus, Vec::new()),
+        trash: Arc::new(Trash::with_paths(
+            paths.trash_journal_file(),
+            None,
+            config.library.trash_retention_days,
+        )),
+        delete_mode: config.library.delete_mode,
+        duplicate_preferences: config.library.duplicates.clone(),
+        event_bus,
+        paths: paths.clone(),
+        shutdown: Arc::new(Notify::new()),
+        event_log: None,
+        revision: Arc::new(PlaybackRevision::new()),
+        transcode_cache: None,
+        precache: None,
+        max_import_bytes: usize::try_from(config.api.max_import_mb * 1024 * 1024)
+            .unwrap_or(usize::MAX),
+        resume_positions: Arc::new(ResumePositions::new(
+            u64::from(config.audio.resume_min_minutes) * 60,
+        )),
+        jobs,
+        route_budgets: RouteBudgets::from(&config.api.timeouts),
+        guest_policy: None,
+        waveforms: Arc::new(WaveformCache::new(paths.waveform_cache_dir())),
+        shares: Arc::new(ShareSigner::load(paths.share_secret_file(), false)?),
+        sleep_timer: Arc::new(SleepTimer::new()),
+    })
+}
+
+/// Serve the demo library on the default API port until asked to shut down, keeping
+/// scratch files in a temporary directory removed afterwards
+pub async fn run() -> Result<()> {
+    let config = Config::default();
+    let scratch_dir: PathBuf =
+        std::env::temp_dir().join(format!("hexendrum-demo-{}", std::process::id()));
+    let paths = Paths::portable(&scratch_dir);
+    let state = state(&paths, &config, DEMO_SEED)?;
+    info!(
+        "Demo mode: serving {} generated tracks, nothing is read from or written to the music library",
+        state.library.track_count()
+    );
+
+    Arc::new(UpNextWatcher::new(config.audio.up_next_lead_seconds)).spawn(state.clone());
+    let listener = api::bind_server(config.api.port).await?;
+    info!("API server running at http://127.0.0.1:{}", config.api.port);
+    let shutdown = state.shutdown.clone();
+    let result = tokio::select! {
+        result = api::serve(listener, state) => result,
+        _ = shutdown.notified() => {
+            // Let the shutdown request's response reach the client
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            Ok(())
+        }
+    };
+
+    if let Err(e) = std::fs::remove_dir_all(&scratch_dir) {
+        warn!(
+            "Failed to remove the demo scratch directory {:?}: {}",
+            scratch_dir, e
+        );
+    }
+    result
+}
@@ -0,0 +1,555 @@
+//! `hexendrum doctor`: checks that the environment the backend runs in is usable.
+//!
+//! Every check is a separate function returning a [`CheckResult`], so it can be run
+//! and tested on its own. [`run_all`] combines them into a [`DoctorReport`], which is
+//! printed by the CLI and served at `GET /api/health/doctor`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use utoipa::ToSchema;
+use walkdir::WalkDir;
+
+use crate::audio::{is_supported_audio_format, AudioBackend, RodioBackend};
+use crate::config::{Config, Paths, WebhookConfig};
+use crate::library::LAST_FM_ENDPOINT;
+
+/// Music directories are only counted up to this many audio files.
+const SAMPLE_FILE_LIMIT: usize = 1000;
+
+/// Outcome of a single check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    /// Something is missing or unusual, but the backend still works
+    Warn,
+    /// The backend cannot work properly until this is fixed
+    Fail,
+}
+
+impl CheckStatus {
+    pub fn label(self) -> &'static str {
+        match self {
+            Self::Pass => "PASS",
+            Self::Warn => "WARN",
+            Self::Fail => "FAIL",
+        }
+    }
+}
+
+/// Result of one doctor check.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct CheckResult {
+    /// What was checked
+    #[schema(example = "music directory /home/user/Music")]
+    pub name: String,
+    pub status: CheckStatus,
+    /// What was found
+    #[schema(example = "1234 audio files")]
+    pub message: String,
+    /// How to fix a warning or failure
+    #[schema(example = "Create the directory or remove it from library.music_directories")]
+    pub hint: Option<String>,
+}
+
+impl CheckResult {
+    pub fn pass(name: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Pass,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    pub fn warn(name: impl Into<String>, message: impl Into<String>, hint: &str) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Warn,
+            message: message.into(),
+            hint: Some(hint.to_string()),
+        }
+    }
+
+    pub fn fail(name: impl Into<String>, message: impl Into<String>, hint: &str) -> Self {
+        Self {
+            name: name.into(),
+            status: CheckStatus::Fail,
+            message: message.into(),
+            hint: Some(hint.to_string()),
+        }
+    }
+}
+
+/// Results of all checks, in the order they ran.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DoctorReport {
+    /// Worst status of all checks
+    pub status: CheckStatus,
+    pub checks: Vec<CheckResult>,
+}
+
+impl DoctorReport {
+    pub fn new(checks: Vec<CheckResult>) -> Self {
+        let status = checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(CheckStatus::Pass);
+        Self { status, checks }
+    }
+
+    /// Whether any check failed.
+    pub fn has_failures(&self) -> bool {
+        self.status == CheckStatus::Fail
+    }
+
+    /// Render the report as a table with the remediation hints below each row.
+    pub fn render(&self) -> String {
+        let width = self
+            .checks
+            .iter()
+            .map(|check| check.name.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let mut output = String::new();
+        for check in &self.checks {
+            output.push_str(&format!(
+                "{}  {:<width$}  {}\n",
+                check.status.label(),
+                check.name,
+                check.message,
+                width = width
+            ));
+            if let Some(hint) = &check.hint {
+                output.push_str(&format!(
+                    "      {:<width$}  -> {}\n",
+                    "",
+                    hint,
+                    width = width
+                ));
+            }
+        }
+
+        let count = |status| {
+            self.checks
+                .iter()
+                .filter(|check| check.status == status)
+                .count()
+        };
+        output.push_str(&format!(
+            "\n{} passed, {} warning(s), {} failed",
+            count(CheckStatus::Pass),
+            count(CheckStatus::Warn),
+            count(CheckStatus::Fail)
+        ));
+        output
+    }
+}
+
+/// Run every check.
+///
+/// With `from_backend` the checks run inside a backend that already owns the audio
+/// device and the API port, so those two checks are left out.
+pub fn run_all(paths: &Paths, from_backend: bool) -> DoctorReport {
+    let (config_check, config) = check_config(paths);
+    let mut checks = vec![config_check];
+
+    if !from_backend {
+        checks.push(check_audio_device());
+    }
+    if config.library.music_directories.is_empty() {
+        checks.push(CheckResult::warn(
+            "music directories",
+            "none configured",
+            "Add directories to library.music_directories in the config file",
+        ));
+    }
+    for directory in &config.library.music_directories {
+        checks.push(check_music_directory(
+            &directory.path,
+            &config.library.supported_extensions,
+        ));
+    }
+    checks.push(check_writable_directory(
+        "cache directory",
+        &paths.cache_dir,
+    ));
+    checks.push(check_writable_directory(
+        "playlist directory",
+        &paths.playlist_dir(),
+    ));
+    checks.push(check_curl());
+    checks.push(check_lastfm(&config.services.lastfm.api_key));
+    if !from_backend && config.api.listen_tcp {
+        checks.push(check_port(config.api.port));
+    }
+
+    DoctorReport::new(checks)
+}
+
+/// Run `hexendrum doctor`, print the report and return the exit code.
+pub async fn run(paths: &Paths) -> i32 {
+    let paths = paths.clone();
+    let report = match tokio::task::spawn_blocking(move || run_all(&paths, false)).await {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("hexendrum doctor: {}", error);
+            return 2;
+        }
+    };
+
+    println!("{}", report.render());
+    if report.has_failures() {
+        1
+    } else {
+        0
+    }
+}
+
+/// Parse and validate the config file, returning the configuration in effect
+/// (the defaults when the file cannot be used).
+pub fn check_config(paths: &Paths) -> (CheckResult, Config) {
+    let name = "configuration";
+    let path = paths.config_file();
+
+    let config = match Config::load(paths) {
+        Ok(config) => config,
+        Err(error) => {
+            return (
+                CheckResult::fail(
+                    name,
+                    format!("{} could not be parsed: {}", path.display(), error),
+                    "Fix the reported setting; the backend falls back to defaults until then",
+                ),
+                Config::default(),
+            );
+        }
+    };
+
+    let problems = config_problems(&config);
+    if !problems.is_empty() {
+        return (
+            CheckResult::fail(
+                name,
+                problems.join("; "),
+                "Correct the reported values in the config file",
+            ),
+            config,
+        );
+    }
+
+    if !path.exists() {
+        return (
+            CheckResult::pass(
+                name,
+                format!("{} not found, using defaults", path.display()),
+            ),
+            config,
+        );
+    }
+
+    let unknown = fs::read_to_string(&path)
+        .ok()
+        .and_then(|content| content.parse::<toml::Table>().ok())
+        .map(|table| unknown_config_keys(&table))
+        .unwrap_or_default();
+    if !unknown.is_empty() {
+        return (
+            CheckResult::warn(
+                name,
+                format!("unknown setting(s): {}", unknown.join(", ")),
+                "Check these keys for typos; unknown settings are ignored",
+            ),
+            config,
+        );
+    }
+
+    (
+        CheckResult::pass(name, format!("{} is valid", path.display())),
+        config,
+    )
+}
+
+/// Values that parse but cannot work.
+fn config_problems(config: &Config) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    if !(0.0..=1.0).contains(&config.audio.default_volume) {
+        problems.push(format!(
+            "audio.default_volume {} is not between 0.0 and 1.0",
+            config.audio.default_volume
+        ));
+    }
+    if config.api.listen_tcp && config.api.port == 0 {
+        problems.push("api.port must not be 0".to_string());
+    }
+    if !config.api.listen_tcp && config.api.unix_socket.is_none() {
+        problems.push("api.listen_tcp is disabled and no api.unix_socket is set".to_string());
+    }
+    if config.library.supported_extensions.is_empty() {
+        problems.push("library.supported_extensions is empty".to_string());
+    }
+    for webhook in &config.webhooks {
+        if !(webhook.url.starts_with("http://") || webhook.url.starts_with("https://")) {
+            problems.push(format!(
+                "webhook url '{}' is not an http(s) URL",
+                webhook.url
+            ));
+        }
+    }
+
+    problems
+}
+
+/// Dotted paths of keys in `table` that the configuration does not know.
+fn unknown_config_keys(table: &toml::Table) -> Vec<String> {
+    // Fill in the optional settings so that they show up in the serialized defaults
+    let mut reference = Config::default();
+    reference.audio.output_device = Some(String::new());
+    reference.gui.window_position = Some((0, 0));
+    reference.api.unix_socket = Some(PathBuf::new());
+    reference.webhooks = vec![WebhookConfig {
+        url: String::new(),
+        events: Vec::new(),
+        secret: Some(String::new()),
+        timeout_secs: 0,
+        max_retries: 0,
+    }];
+
+    let Ok(toml::Value::Table(reference)) = toml::Value::try_from(&reference) else {
+        return Vec::new();
+    };
+    let mut unknown = Vec::new();
+    collect_unknown_keys(table, &reference, "", &mut unknown);
+    unknown
+}
+
+fn collect_unknown_keys(
+    table: &toml::Table,
+    reference: &toml::Table,
+    prefix: &str,
+    unknown: &mut Vec<String>,
+) {
+    for (key, value) in table {
+        let path = format!("{}{}", prefix, key);
+        match (value, reference.get(key)) {
+            (_, None) => unknown.push(path),
+            (toml::Value::Table(value), Some(toml::Value::Table(known))) => {
+                collect_unknown_keys(value, known, &format!("{}.", path), unknown);
+            }
+            (toml::Value::Array(values), Some(toml::Value::Array(known))) => {
+                if let Some(toml::Value::Table(known)) = known.first() {
+                    for (index, value) in values.iter().enumerate() {
+                        if let toml::Value::Table(value) = value {
+                            let prefix = format!("{}[{}].", path, index);
+                            collect_unknown_keys(value, known, &prefix, unknown);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Open the default audio output device. Failing to do so is only a warning, as the
+/// backend keeps trying to acquire a device.
+pub fn check_audio_device() -> CheckResult {
+    let name = "audio device";
+    let mut backend = RodioBackend::new();
+    match backend.open() {
+        Ok(()) => CheckResult::pass(
+            name,
+            backend
+                .device_name()
+                .unwrap_or_else(|| "default output device".to_string()),
+        ),
+        Err(error) => CheckResult::warn(
+            name,
+            error.to_string(),
+            "Connect an output device or check that the sound server is running",
+        ),
+    }
+}
+
+/// Check that a music directory can be read and count the audio files in it.
+pub fn check_music_directory(directory: &Path, extensions: &[String]) -> CheckResult {
+    let name = format!("music directory {}", directory.display());
+
+    if let Err(error) = fs::read_dir(directory) {
+        let hint = if error.kind() == ErrorKind::NotFound {
+            "Create the directory or remove it from library.music_directories"
+        } else {
+            "Make the directory readable by the user running Hexendrum"
+        };
+        return CheckResult::fail(name, format!("cannot be read: {}", error), hint);
+    }
+
+    let count = WalkDir::new(directory)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter(|entry| is_supported_audio_format(entry.path(), extensions))
+        .take(SAMPLE_FILE_LIMIT)
+        .count();
+
+    match count {
+        0 => CheckResult::warn(
+            name,
+            "no audio files found",
+            "Check the path and library.supported_extensions",
+        ),
+        SAMPLE_FILE_LIMIT => CheckResult::pass(name, format!("at least {} audio files", count)),
+        _ => CheckResult::pass(name, format!("{} audio file(s)", count)),
+    }
+}
+
+/// Check that `directory` exists (creating it if needed) and that files can be
+/// written to it.
+pub fn check_writable_directory(name: &str, directory: &Path) -> CheckResult {
+    let name = format!("{} {}", name, directory.display());
+    let probe = directory.join(".hexendrum-doctor");
+
+    let result = fs::create_dir_all(directory).and_then(|_| fs::write(&probe, b"ok"));
+    let _ = fs::remove_file(&probe);
+
+    match result {
+        Ok(()) => CheckResult::pass(name, "writable"),
+        Err(error) => CheckResult::fail(
+            name,
+            format!("not writable: {}", error),
+            "Fix the directory permissions or choose another location with --data-dir",
+        ),
+    }
+}
+
+/// Check that curl, used to download artwork and deliver webhooks, is installed.
+pub fn check_curl() -> CheckResult {
+    let name = "curl";
+    match Command::new("curl").arg("--version").output() {
+        Ok(output) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string();
+            CheckResult::pass(name, version)
+        }
+        Ok(output) => CheckResult::warn(
+            name,
+            format!("curl --version exited with {}", output.status),
+            "Reinstall curl; artwork downloads and webhooks depend on it",
+        ),
+        Err(error) => CheckResult::warn(
+            name,
+            format!("not available: {}", error),
+            "Install curl to enable artwork downloads and webhooks",
+        ),
+    }
+}
+
+/// Check that a Last.fm API key is configured and accepted by Last.fm.
+pub fn check_lastfm(api_key: &str) -> CheckResult {
+    check_lastfm_at(LAST_FM_ENDPOINT, api_key)
+}
+
+/// [`check_lastfm`] against another Last.fm compatible endpoint.
+pub fn check_lastfm_at(endpoint: &str, api_key: &str) -> CheckResult {
+    let name = "Last.fm";
+    let api_key = api_key.trim();
+    if api_key.is_empty() {
+        return CheckResult::warn(
+            name,
+            "no API key configured, artwork lookups are disabled",
+            "Set services.lastfm.api_key in the config file",
+        );
+    }
+
+    let params: BTreeMap<&str, &str> = [
+        ("method", "artist.getinfo"),
+        ("artist", "Cher"),
+        ("api_key", api_key),
+        ("format", "json"),
+    ]
+    .into();
+    let url = match serde_urlencoded::to_string(&params) {
+        Ok(query) => format!("{}?{}", endpoint, query),
+        Err(error) => return CheckResult::fail(name, error.to_string(), "Check the API key"),
+    };
+
+    let output = match Command::new("curl")
+        .args(["-sS", "--max-time", "10", &url])
+        .output()
+    {
+        Ok(output) => output,
+        Err(error) => {
+            return CheckResult::warn(
+                name,
+                format!("could not run curl: {}", error),
+                "Install curl to enable artwork downloads",
+            )
+        }
+    };
+    if !output.status.success() {
+        return CheckResult::warn(
+            name,
+            format!(
+                "not reachable: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ),
+            "Check the network connection",
+        );
+    }
+
+    let response: serde_json::Value = match serde_json::from_slice(&output.stdout) {
+        Ok(response) => response,
+        Err(_) => {
+            return CheckResult::warn(
+                name,
+                "unexpected response",
+                "Check the network connection and any proxy in between",
+            )
+        }
+    };
+    match response.get("error").and_then(|code| code.as_i64()) {
+        None => CheckResult::pass(name, "API key accepted"),
+        Some(code) => {
+            let message = response
+                .get("message")
+                .and_then(|message| message.as_str())
+                .unwrap_or("request failed");
+            let hint = if matches!(code, 10 | 26) {
+                "Check services.lastfm.api_key; the key was rejected"
+            } else {
+                "Try again later"
+            };
+            CheckResult::fail(name, format!("error {}: {}", code, message), hint)
+        }
+    }
+}
+
+/// Check that the API port on 127.0.0.1 is free.
+pub fn check_port(port: u16) -> CheckResult {
+    let name = format!("API port {}", port);
+    match TcpListener::bind((Ipv4Addr::LOCALHOST, port)) {
+        Ok(_) => CheckResult::pass(name, "available"),
+        Err(error) if error.kind() == ErrorKind::AddrInUse => CheckResult::fail(
+            name,
+            "already in use",
+            "Stop the other process (maybe a running Hexendrum) or change api.port",
+        ),
+        Err(error) => CheckResult::fail(
+            name,
+            format!("cannot be bound: {}", error),
+            "Choose another api.port",
+        ),
+    }
+}
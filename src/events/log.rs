@@ -0,0 +1,270 @@
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde_json::Value;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc};
+use tracing::warn;
+
+use super::{EventBus, EventMessage};
+use crate::config::EventsConfig;
+
+/// Lines buffered for the writer before new events are dropped.
+const EVENT_LOG_QUEUE_CAPACITY: usize = 1024;
+
+/// Appends every event to a JSON Lines file, rotating it by size.
+///
+/// The log file is `log_file`; rotated files are `log_file.1` (newest) up to
+/// `log_file.<max_files - 1>`. Writes happen on a blocking task fed by a bounded
+/// queue, so a slow disk loses log lines instead of holding up the bus.
+pub struct EventLog {
+    path: PathBuf,
+    max_files: usize,
+    /// Serializes reads against rotation
+    files: Arc<Mutex<()>>,
+    dropped: AtomicU64,
+}
+
+impl EventLog {
+    /// Start logging events from `event_bus` as configured, if a log file is set.
+    pub fn start(event_bus: &EventBus, config: &EventsConfig) -> Option<Arc<Self>> {
+        let path = config.log_file.clone()?;
+        let max_bytes = config.log_max_size_mb.max(1) * 1024 * 1024;
+        Some(Self::start_with_limit(
+            event_bus,
+            path,
+            max_bytes,
+            config.log_max_files,
+        ))
+    }
+
+    /// Start logging to `path`, rotating once it would grow beyond `max_bytes`.
+    pub fn start_with_limit(
+        event_bus: &EventBus,
+        path: PathBuf,
+        max_bytes: u64,
+        max_files: usize,
+    ) -> Arc<Self> {
+        let log = Arc::new(Self {
+            path,
+            max_files: max_files.max(1),
+            files: Arc::new(Mutex::new(())),
+            dropped: AtomicU64::new(0),
+        });
+
+        let (queue, receiver) = mpsc::channel(EVENT_LOG_QUEUE_CAPACITY);
+        let writer = LogWriter {
+            path: log.path.clone(),
+            max_bytes,
+            max_files: log.max_files,
+            files: log.files.clone(),
+        };
+        tokio::task::spawn_blocking(move || writer.run(receiver));
+        tokio::spawn(forward_loop(
+            Arc::downgrade(&log),
+            event_bus.subscribe(),
+            queue,
+        ));
+
+        log
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Events discarded because the writer fell behind
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Read back up to `limit` of the most recent entries at or after `since`, oldest
+    /// first. Lines that are not valid JSON are skipped.
+    pub fn read_entries(&self, since: Option<DateTime<Utc>>, limit: usize) -> Result<Vec<Value>> {
+        let _files = self.files.lock().unwrap();
+        let mut entries = Vec::new();
+
+        for path in self.files_oldest_first() {
+            let file = match File::open(&path) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("cannot read {:?}", path)),
+            };
+
+            for line in BufReader::new(file).lines() {
+                let Ok(entry) = serde_json::from_str::<Value>(&line?) else {
+                    continue;
+                };
+                let recent_enough = match since {
+                    Some(since) => entry
+                        .get("timestamp")
+                        .and_then(Value::as_str)
+                        .and_then(|timestamp| DateTime::parse_from_rfc3339(timestamp).ok())
+                        .is_some_and(|timestamp| timestamp >= since),
+                    None => true,
+                };
+                if recent_enough {
+                    entries.push(entry);
+                }
+            }
+        }
+
+        let skip = entries.len().saturating_sub(limit);
+        Ok(entries.split_off(skip))
+    }
+
+    fn files_oldest_first(&self) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = (1..self.max_files)
+            .rev()
+            .map(|index| rotated_path(&self.path, index))
+            .collect();
+        files.push(self.path.clone());
+        files
+    }
+}
+
+/// Parse the `since` filter of the event log: an RFC 3339 timestamp, Unix seconds, or
+/// an age such as `30s`, `15m`, `2h` or `7d` counted back from `now`.
+pub fn parse_since(value: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Ok(timestamp.with_timezone(&Utc));
+    }
+    if let Ok(seconds) = value.parse::<i64>() {
+        return DateTime::from_timestamp(seconds, 0)
+            .ok_or_else(|| anyhow!("timestamp {} is out of range", seconds));
+    }
+
+    let invalid = || {
+        anyhow!(
+            "invalid since '{}': expected an RFC 3339 timestamp, Unix seconds or an age like 15m",
+            value
+        )
+    };
+    let split = value.len().checked_sub(1).ok_or_else(invalid)?;
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount
+        .parse()
+        .ok()
+        .filter(|amount| *amount >= 0)
+        .ok_or_else(invalid)?;
+    let age = match unit {
+        "s" => ChronoDuration::try_seconds(amount),
+        "m" => ChronoDuration::try_minutes(amount),
+        "h" => ChronoDuration::try_hours(amount),
+        "d" => ChronoDuration::try_days(amount),
+        _ => None,
+    }
+    .ok_or_else(invalid)?;
+
+    now.checked_sub_signed(age).ok_or_else(invalid)
+}
+
+fn rotated_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+async fn forward_loop(
+    log: std::sync::Weak<EventLog>,
+    mut receiver: broadcast::Receiver<EventMessage>,
+    queue: mpsc::Sender<String>,
+) {
+    loop {
+        let message = match receiver.recv().await {
+            Ok(message) => message,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("Event log lagged, skipped {} events", skipped);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let Some(log) = log.upgrade() else {
+            break;
+        };
+
+        let line = match serde_json::to_string(&message) {
+            Ok(line) => line,
+            Err(error) => {
+                warn!("Failed to serialize event for the event log: {}", error);
+                continue;
+            }
+        };
+        if let Err(mpsc::error::TrySendError::Full(_)) = queue.try_send(line) {
+            if log.dropped.fetch_add(1, Ordering::Relaxed) == 0 {
+                warn!("Event log writer is falling behind, dropping events");
+            }
+        }
+    }
+}
+
+struct LogWriter {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    files: Arc<Mutex<()>>,
+}
+
+impl LogWriter {
+    fn run(self, mut receiver: mpsc::Receiver<String>) {
+        let mut file: Option<File> = None;
+
+        while let Some(line) = receiver.blocking_recv() {
+            if let Err(error) = self.append(&mut file, &line) {
+                warn!("Failed to write event log {:?}: {}", self.path, error);
+                file = None;
+            }
+        }
+    }
+
+    fn append(&self, file: &mut Option<File>, line: &str) -> Result<()> {
+        let _files = self.files.lock().unwrap();
+
+        let size = fs::metadata(&self.path).map(|meta| meta.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 + 1 > self.max_bytes {
+            *file = None;
+            self.rotate()?;
+        }
+
+        if file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            *file = Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.path)?,
+            );
+        }
+
+        let file = file.as_mut().expect("log file was just opened");
+        file.write_all(line.as_bytes())?;
+        file.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn rotate(&self) -> Result<()> {
+        if self.max_files == 1 {
+            fs::remove_file(&self.path)?;
+            return Ok(());
+        }
+
+        let oldest = rotated_path(&self.path, self.max_files - 1);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for index in (1..self.max_files - 1).rev() {
+            let from = rotated_path(&self.path, index);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, index + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        Ok(())
+    }
+}
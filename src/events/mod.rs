@@ -1,9 +1,35 @@
+use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
 use serde::Serialize;
+use std::collections::HashSet;
+use std::future::Future;
 use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+use crate::library::{LibraryDelta, Track};
+
+mod log;
+mod now_playing;
+mod webhooks;
+pub use log::{parse_since, EventLog};
+#[allow(unused_imports)]
+pub use now_playing::{NowPlaying, DEFAULT_NOW_PLAYING_TEMPLATE, NOW_PLAYING_STDOUT};
+pub use now_playing::{NowPlayingTemplate, NowPlayingWriter};
+pub use webhooks::{WebhookDispatcher, WebhookStatus};
 
 const DEFAULT_EVENT_CAPACITY: usize = 128;
 
+tokio::task_local! {
+    /// Who caused the events emitted by the current task, see [`attributed`]
+    static EVENT_SOURCE: &'static str;
+}
+
+/// Run `future` with the events it emits attributed to `source`, such as `"guest"`.
+/// Events emitted from tasks it spawns are not attributed.
+pub async fn attributed<F: Future>(source: &'static str, future: F) -> F::Output {
+    EVENT_SOURCE.scope(source, future).await
+}
+
 /// Broadcast bus for backend events.
 #[derive(Clone)]
 pub struct EventBus {
@@ -30,9 +56,14 @@ impl EventBus {
 }
 
 /// Envelope for broadcast events, including timestamp metadata.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct EventMessage {
+    #[serde(with = "crate::utils::serde_rfc3339")]
     pub timestamp: DateTime<Utc>,
+    /// Who caused the event, when it was not the owner: `"guest"` for requests made
+    /// with `api.guest_token`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
     #[serde(flatten)]
     pub payload: EventPayload,
 }
@@ -41,13 +72,14 @@ impl EventMessage {
     pub fn new(payload: EventPayload) -> Self {
         Self {
             timestamp: Utc::now(),
+            source: EVENT_SOURCE.try_with(|source| source.to_string()).ok(),
             payload,
         }
     }
 }
 
 /// Event payloads emitted by the backend.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum EventPayload {
     PlaybackState {
@@ -56,9 +88,28 @@ pub enum EventPayload {
         track_id: Option<String>,
         volume: Option<f32>,
         track_duration: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        repeat_mode: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        shuffle: Option<bool>,
+        /// Playback revision after the change; absent on events raised by the audio
+        /// device itself
+        #[serde(skip_serializing_if = "Option::is_none")]
+        revision: Option<u64>,
+    },
+    /// A track played to its end by itself and playback stopped; stopping it does
+    /// not raise this
+    PlaybackFinished {
+        track_path: String,
+        track_id: Option<String>,
     },
+    /// Playback could not use a track, such as one queued to follow gaplessly that
+    /// cannot be decoded
+    PlaybackError { track_path: String, message: String },
     VolumeChanged {
         volume: f32,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        revision: Option<u64>,
     },
     LibraryScan {
         status: String,
@@ -67,10 +118,158 @@ pub enum EventPayload {
     },
     LibraryUpdated {
         total_tracks: usize,
+        /// Library change sequence after the update. A gap to the last one seen means
+        /// events were missed and the tracks should be fetched again.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sequence: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        added: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        removed: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        updated: Option<usize>,
+        /// Tracks added, removed or updated, when no more than 100 changed
+        #[serde(skip_serializing_if = "Option::is_none")]
+        track_ids: Option<Vec<String>>,
     },
+    LibraryVerify {
+        status: String,
+        processed: usize,
+        total: usize,
+        skipped: usize,
+        failed: usize,
+    },
+    AudioDevice {
+        status: String,
+        device: Option<String>,
+        message: Option<String>,
+    },
+    QueueUpdated {
+        track_id: Option<String>,
+        position: Option<usize>,
+        length: usize,
+    },
+    RadioMode {
+        enabled: bool,
+        seed_track_id: Option<String>,
+    },
+    /// Announces the next track shortly before the current one ends
+    UpNext {
+        track: UpNextTrack,
+        seconds_until: u32,
+    },
+    /// Playback skipped a stretch of silence; positions are seconds into the track
+    SilenceSkipped {
+        track_path: Option<String>,
+        from_seconds: f64,
+        to_seconds: f64,
+    },
+    /// The preview sink started (`playing`), was stopped (`stopped`) or played its
+    /// file to the end (`ended`); main playback is unaffected
+    AudioPreview {
+        state: String,
+        track_path: Option<String>,
+        volume: f32,
+    },
+    /// Progress of a maintenance run; `task` is the task about to run
+    Maintenance {
+        status: String,
+        task: Option<String>,
+        completed: usize,
+        total: usize,
+    },
+    /// Artwork of an album was stored, replaced or deleted. `artwork_url` changes with
+    /// the image and is `null` once the album has none.
+    AlbumArtworkUpdated {
+        album_id: String,
+        artwork_url: Option<String>,
+    },
+    /// A job was queued, made progress or finished; `state` is one of `queued`,
+    /// `running`, `completed`, `failed` and `cancelled`
+    JobProgress {
+        job_id: u64,
+        kind: String,
+        state: String,
+        current: usize,
+        total: Option<usize>,
+        message: Option<String>,
+    },
+    /// The sleep timer stopped playback, after fading it out with `fade`;
+    /// `track_path` is the track it stopped
+    SleepTimerFired {
+        track_path: Option<String>,
+        fade: bool,
+    },
+}
+
+/// The track announced by an `up_next` event
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct UpNextTrack {
+    pub id: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Duration in seconds
+    pub duration: Option<u64>,
+}
+
+impl From<&Track> for UpNextTrack {
+    fn from(track: &Track) -> Self {
+        Self {
+            id: track.id.clone(),
+            title: track.metadata.title.clone(),
+            artist: track.metadata.artist.clone(),
+            album: track.metadata.album.clone(),
+            duration: track.metadata.duration,
+        }
+    }
 }
 
 impl EventPayload {
+    /// Every value of the `type` tag.
+    pub const TYPES: [&'static str; 17] = [
+        "playback_state",
+        "playback_finished",
+        "playback_error",
+        "volume_changed",
+        "library_scan",
+        "library_updated",
+        "library_verify",
+        "audio_device",
+        "queue_updated",
+        "radio_mode",
+        "up_next",
+        "silence_skipped",
+        "audio_preview",
+        "maintenance",
+        "album_artwork_updated",
+        "job_progress",
+        "sleep_timer_fired",
+    ];
+
+    /// The `type` tag this payload is serialized with.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::PlaybackState { .. } => "playback_state",
+            Self::PlaybackFinished { .. } => "playback_finished",
+            Self::PlaybackError { .. } => "playback_error",
+            Self::VolumeChanged { .. } => "volume_changed",
+            Self::LibraryScan { .. } => "library_scan",
+            Self::LibraryUpdated { .. } => "library_updated",
+            Self::LibraryVerify { .. } => "library_verify",
+            Self::AudioDevice { .. } => "audio_device",
+            Self::QueueUpdated { .. } => "queue_updated",
+            Self::RadioMode { .. } => "radio_mode",
+            Self::UpNext { .. } => "up_next",
+            Self::SilenceSkipped { .. } => "silence_skipped",
+            Self::AudioPreview { .. } => "audio_preview",
+            Self::Maintenance { .. } => "maintenance",
+            Self::AlbumArtworkUpdated { .. } => "album_artwork_updated",
+            Self::JobProgress { .. } => "job_progress",
+            Self::SleepTimerFired { .. } => "sleep_timer_fired",
+        }
+    }
+
     pub fn playback_state(
         state: impl Into<String>,
         track_path: Option<String>,
@@ -84,11 +283,70 @@ impl EventPayload {
             track_id,
             volume,
             track_duration,
+            repeat_mode: None,
+            shuffle: None,
+            revision: None,
+        }
+    }
+
+    pub fn playback_finished(track_path: impl Into<String>, track_id: Option<String>) -> Self {
+        Self::PlaybackFinished {
+            track_path: track_path.into(),
+            track_id,
+        }
+    }
+
+    pub fn sleep_timer_fired(track_path: Option<String>, fade: bool) -> Self {
+        Self::SleepTimerFired { track_path, fade }
+    }
+
+    pub fn playback_error(track_path: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::PlaybackError {
+            track_path: track_path.into(),
+            message: message.into(),
+        }
+    }
+
+    /// Attach the queue's repeat and shuffle settings to a playback state event.
+    pub fn with_queue_settings(self, repeat: impl Into<String>, shuffle_enabled: bool) -> Self {
+        match self {
+            Self::PlaybackState {
+                state,
+                track_path,
+                track_id,
+                volume,
+                track_duration,
+                revision,
+                ..
+            } => Self::PlaybackState {
+                state,
+                track_path,
+                track_id,
+                volume,
+                track_duration,
+                repeat_mode: Some(repeat.into()),
+                shuffle: Some(shuffle_enabled),
+                revision,
+            },
+            other => other,
         }
     }
 
+    /// Attach the playback revision to a playback state or volume event.
+    pub fn with_revision(mut self, value: u64) -> Self {
+        if let Self::PlaybackState { revision, .. } | Self::VolumeChanged { revision, .. } =
+            &mut self
+        {
+            *revision = Some(value);
+        }
+        self
+    }
+
     pub fn volume_changed(volume: f32) -> Self {
-        Self::VolumeChanged { volume }
+        Self::VolumeChanged {
+            volume,
+            revision: None,
+        }
     }
 
     pub fn library_scan(
@@ -103,7 +361,188 @@ impl EventPayload {
         }
     }
 
+    /// `library_updated` without a sequence number or delta
+    #[allow(dead_code)]
     pub fn library_updated(total_tracks: usize) -> Self {
-        Self::LibraryUpdated { total_tracks }
+        Self::LibraryUpdated {
+            total_tracks,
+            sequence: None,
+            added: None,
+            removed: None,
+            updated: None,
+            track_ids: None,
+        }
+    }
+
+    /// `library_updated` at change `sequence`, with what changed when it is known
+    pub fn library_changed(
+        total_tracks: usize,
+        sequence: u64,
+        delta: Option<LibraryDelta>,
+    ) -> Self {
+        match delta {
+            Some(delta) => Self::LibraryUpdated {
+                total_tracks,
+                sequence: Some(sequence),
+                added: Some(delta.added),
+                removed: Some(delta.removed),
+                updated: Some(delta.updated),
+                track_ids: delta.track_ids,
+            },
+            None => Self::LibraryUpdated {
+                total_tracks,
+                sequence: Some(sequence),
+                added: None,
+                removed: None,
+                updated: None,
+                track_ids: None,
+            },
+        }
+    }
+
+    pub fn audio_device(
+        status: impl Into<String>,
+        device: Option<String>,
+        message: Option<String>,
+    ) -> Self {
+        Self::AudioDevice {
+            status: status.into(),
+            device,
+            message,
+        }
+    }
+
+    pub fn queue_updated(track_id: Option<String>, position: Option<usize>, length: usize) -> Self {
+        Self::QueueUpdated {
+            track_id,
+            position,
+            length,
+        }
+    }
+
+    pub fn radio_mode(seed_track_id: Option<String>) -> Self {
+        Self::RadioMode {
+            enabled: seed_track_id.is_some(),
+            seed_track_id,
+        }
+    }
+
+    pub fn up_next(track: &Track, seconds_until: u32) -> Self {
+        Self::UpNext {
+            track: track.into(),
+            seconds_until,
+        }
+    }
+
+    pub fn silence_skipped(track_path: Option<String>, from_seconds: f64, to_seconds: f64) -> Self {
+        Self::SilenceSkipped {
+            track_path,
+            from_seconds,
+            to_seconds,
+        }
+    }
+
+    pub fn audio_preview(
+        state: impl Into<String>,
+        track_path: Option<String>,
+        volume: f32,
+    ) -> Self {
+        Self::AudioPreview {
+            state: state.into(),
+            track_path,
+            volume,
+        }
+    }
+
+    pub fn maintenance(
+        status: impl Into<String>,
+        task: Option<String>,
+        completed: usize,
+        total: usize,
+    ) -> Self {
+        Self::Maintenance {
+            status: status.into(),
+            task,
+            completed,
+            total,
+        }
+    }
+
+    pub fn album_artwork_updated(album_id: impl Into<String>, artwork_url: Option<String>) -> Self {
+        Self::AlbumArtworkUpdated {
+            album_id: album_id.into(),
+            artwork_url,
+        }
+    }
+
+    pub fn job_progress(
+        job_id: u64,
+        kind: impl Into<String>,
+        state: impl Into<String>,
+        current: usize,
+        total: Option<usize>,
+        message: Option<String>,
+    ) -> Self {
+        Self::JobProgress {
+            job_id,
+            kind: kind.into(),
+            state: state.into(),
+            current,
+            total,
+            message,
+        }
+    }
+
+    pub fn library_verify(
+        status: impl Into<String>,
+        processed: usize,
+        total: usize,
+        skipped: usize,
+        failed: usize,
+    ) -> Self {
+        Self::LibraryVerify {
+            status: status.into(),
+            processed,
+            total,
+            skipped,
+            failed,
+        }
+    }
+}
+
+/// Event types a subscriber wants to receive.
+#[derive(Debug, Clone, Default)]
+pub struct EventFilter {
+    /// `None` accepts every event
+    types: Option<HashSet<&'static str>>,
+}
+
+impl EventFilter {
+    /// Parse a comma separated list of event type names such as
+    /// `playback_state,library_scan`. Unknown names are an error.
+    pub fn parse(list: &str) -> Result<Self> {
+        let mut types = HashSet::new();
+        for name in list
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+        {
+            let known = EventPayload::TYPES
+                .iter()
+                .find(|known| known.eq_ignore_ascii_case(name))
+                .ok_or_else(|| anyhow!("unknown event type '{}'", name))?;
+            types.insert(*known);
+        }
+
+        Ok(Self {
+            types: (!types.is_empty()).then_some(types),
+        })
+    }
+
+    /// Whether the subscriber wants `payload`.
+    pub fn accepts(&self, payload: &EventPayload) -> bool {
+        self.types
+            .as_ref()
+            .is_none_or(|types| types.contains(payload.type_name()))
     }
 }
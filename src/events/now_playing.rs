@@ -0,0 +1,338 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+
+use super::{EventBus, EventPayload};
+use crate::config::IntegrationsConfig;
+use crate::library::{Library, Track};
+use crate::utils::format_duration;
+
+/// Default `integrations.now_playing_template`
+pub const DEFAULT_NOW_PLAYING_TEMPLATE: &str = "{artist} — {title}";
+/// `integrations.now_playing_file` value writing to standard output instead of a file
+pub const NOW_PLAYING_STDOUT: &str = "-";
+
+/// Placeholder of a [`NowPlayingTemplate`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Title,
+    Artist,
+    Album,
+    Position,
+    Duration,
+}
+
+impl Field {
+    const ALL: [(&'static str, Self); 5] = [
+        ("title", Self::Title),
+        ("artist", Self::Artist),
+        ("album", Self::Album),
+        ("position", Self::Position),
+        ("duration", Self::Duration),
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+/// Text written for the current track, such as `{artist} — {title}`. Placeholders are
+/// `{title}`, `{artist}`, `{album}`, `{position}` and `{duration}`; `{{` and `}}`
+/// write a literal brace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct NowPlayingTemplate {
+    source: String,
+    parts: Vec<Part>,
+}
+
+impl NowPlayingTemplate {
+    /// Parse `template`, refusing unknown placeholders and unbalanced braces
+    pub fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' if chars.next_if_eq(&'{').is_some() => text.push('{'),
+                '}' if chars.next_if_eq(&'}').is_some() => text.push('}'),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(ch) => name.push(ch),
+                            None => {
+                                return Err(anyhow!(
+                                    "Unclosed placeholder in now-playing template {:?}",
+                                    template
+                                ))
+                            }
+                        }
+                    }
+                    let field = Field::ALL
+                        .iter()
+                        .find(|(known, _)| *known == name)
+                        .map(|(_, field)| *field)
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "Unknown placeholder {{{}}} in now-playing template {:?}; use {}",
+                                name,
+                                template,
+                                Field::ALL
+                                    .iter()
+                                    .map(|(known, _)| format!("{{{}}}", known))
+                                    .collect::<Vec<_>>()
+                                    .join(", ")
+                            )
+                        })?;
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(field));
+                }
+                '}' => {
+                    return Err(anyhow!(
+                        "Unmatched '}}' in now-playing template {:?}; write '}}}}' for a brace",
+                        template
+                    ))
+                }
+                ch => text.push(ch),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        Ok(Self {
+            source: template.to_string(),
+            parts,
+        })
+    }
+
+    /// Whether the text changes as the track plays, so it needs refreshing
+    pub fn uses_position(&self) -> bool {
+        self.parts.contains(&Part::Field(Field::Position))
+    }
+
+    /// Text for `track` at `position`; unknown tags are left empty, except the title,
+    /// which falls back to the file name
+    pub fn render(&self, track: &NowPlaying, position: Duration) -> String {
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Field(Field::Title) => rendered.push_str(&track.title),
+                Part::Field(Field::Artist) => {
+                    rendered.push_str(track.artist.as_deref().unwrap_or_default())
+                }
+                Part::Field(Field::Album) => {
+                    rendered.push_str(track.album.as_deref().unwrap_or_default())
+                }
+                Part::Field(Field::Position) => rendered.push_str(&format_duration(position)),
+                Part::Field(Field::Duration) => rendered.push_str(
+                    &track
+                        .duration
+                        .map(|duration| format_duration(Duration::from_secs(duration)))
+                        .unwrap_or_default(),
+                ),
+            }
+        }
+        rendered
+    }
+}
+
+impl Default for NowPlayingTemplate {
+    fn default() -> Self {
+        Self::parse(DEFAULT_NOW_PLAYING_TEMPLATE).expect("default template is valid")
+    }
+}
+
+impl TryFrom<String> for NowPlayingTemplate {
+    type Error = anyhow::Error;
+
+    fn try_from(template: String) -> Result<Self> {
+        Self::parse(&template)
+    }
+}
+
+impl From<NowPlayingTemplate> for String {
+    fn from(template: NowPlayingTemplate) -> Self {
+        template.source
+    }
+}
+
+impl fmt::Display for NowPlayingTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// The track a [`NowPlayingTemplate`] is rendered for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NowPlaying {
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Seconds
+    pub duration: Option<u64>,
+}
+
+impl NowPlaying {
+    fn new(track: Option<&Track>, track_path: Option<&str>, duration: Option<u64>) -> Self {
+        let file_name = || {
+            track_path
+                .map(Path::new)
+                .or(track.map(|track| track.metadata.file_path.as_path()))
+                .and_then(Path::file_stem)
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default()
+        };
+        let metadata = track.map(|track| &track.metadata);
+        Self {
+            title: metadata
+                .and_then(|metadata| metadata.title.clone())
+                .unwrap_or_else(file_name),
+            artist: metadata.and_then(|metadata| metadata.artist.clone()),
+            album: metadata.and_then(|metadata| metadata.album.clone()),
+            duration: duration.or(metadata.and_then(|metadata| metadata.duration)),
+        }
+    }
+}
+
+/// Where the now-playing text goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Output {
+    /// Replaced atomically on every change, so readers never see a partial line
+    File(PathBuf),
+    /// A line per change
+    Stdout,
+}
+
+impl Output {
+    fn write(&self, text: &str) -> Result<()> {
+        match self {
+            Self::Stdout => {
+                println!("{}", text);
+                Ok(())
+            }
+            Self::File(path) => {
+                let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+                temp_name.push(".tmp");
+                let temp_path = path.with_file_name(temp_name);
+                fs::write(&temp_path, text)?;
+                if let Err(error) = fs::rename(&temp_path, path) {
+                    let _ = fs::remove_file(&temp_path);
+                    return Err(error.into());
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Keeps a text file, or standard output, showing the track being played, for
+/// overlays in streaming software. The text follows `playback_state` events: it is
+/// written when a track starts, kept while paused and emptied once playback stops.
+/// Templates showing `{position}` are refreshed every `now_playing_refresh_secs`.
+pub struct NowPlayingWriter;
+
+impl NowPlayingWriter {
+    /// Start following `event_bus` as configured, if a now-playing file is set.
+    /// `library` names the tracks, and `position` reads the playback position.
+    pub fn start(
+        event_bus: &EventBus,
+        config: &IntegrationsConfig,
+        library: Arc<Library>,
+        position: impl Fn() -> Duration + Send + 'static,
+    ) -> Option<JoinHandle<()>> {
+        let path = config.now_playing_file.clone()?;
+        let output = if path.as_os_str() == NOW_PLAYING_STDOUT {
+            Output::Stdout
+        } else {
+            Output::File(path)
+        };
+        let template = config.now_playing_template.clone();
+        let refresh = template
+            .uses_position()
+            .then(|| Duration::from_secs(config.now_playing_refresh_secs.max(1)));
+        let mut receiver = event_bus.subscribe();
+
+        Some(tokio::spawn(async move {
+            let mut current: Option<NowPlaying> = None;
+            let mut written: Option<String> = None;
+            if let Output::File(_) = output {
+                // Left over from the last run
+                if let Err(error) = output.write("") {
+                    warn!("Failed to write the now-playing text: {}", error);
+                }
+                written = Some(String::new());
+            }
+            let mut ticker = tokio::time::interval(refresh.unwrap_or(Duration::from_secs(3600)));
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    event = receiver.recv() => match event {
+                        Ok(message) => {
+                            let EventPayload::PlaybackState {
+                                state,
+                                track_path,
+                                track_id,
+                                track_duration,
+                                ..
+                            } = message.payload
+                            else {
+                                continue;
+                            };
+                            match state.as_str() {
+                                "playing" | "paused" => {
+                                    let track = track_id
+                                        .as_deref()
+                                        .and_then(|id| library.get_track(id))
+                                        .or_else(|| {
+                                            library.get_track_by_path(Path::new(track_path.as_deref()?))
+                                        });
+                                    if track.is_some() || track_path.is_some() {
+                                        current = Some(NowPlaying::new(
+                                            track.as_ref(),
+                                            track_path.as_deref(),
+                                            track_duration,
+                                        ));
+                                    }
+                                }
+                                "stopped" => current = None,
+                                _ => {}
+                            }
+                        }
+                        Err(RecvError::Lagged(skipped)) => {
+                            debug!("Now-playing writer skipped {} events", skipped);
+                            continue;
+                        }
+                        Err(RecvError::Closed) => break,
+                    },
+                    _ = ticker.tick(), if refresh.is_some() && current.is_some() => {}
+                }
+
+                let text = current
+                    .as_ref()
+                    .map(|track| template.render(track, position()))
+                    .unwrap_or_default();
+                if written.as_deref() != Some(text.as_str()) {
+                    if let Err(error) = output.write(&text) {
+                        warn!("Failed to write the now-playing text: {}", error);
+                    }
+                    written = Some(text);
+                }
+            }
+        }))
+    }
+}
@@ -0,0 +1,266 @@
+use serde::Serialize;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{debug, warn};
+
+use super::{EventBus, EventMessage};
+use crate::config::WebhookConfig;
+
+/// Events buffered per hook before new ones are dropped.
+const WEBHOOK_QUEUE_CAPACITY: usize = 64;
+
+/// Delay before the first retry; doubled for every further attempt.
+const WEBHOOK_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Header carrying the configured secret.
+const WEBHOOK_TOKEN_HEADER: &str = "X-Hexendrum-Token";
+
+/// Delivery counters and configuration of a single hook.
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookStatus {
+    pub url: String,
+    pub events: Vec<String>,
+    /// Events accepted by the receiver
+    pub delivered: u64,
+    /// Events that could not be delivered after all retries
+    pub failed: u64,
+    /// Events discarded because the hook's queue was full
+    pub dropped: u64,
+}
+
+#[derive(Default)]
+struct HookCounters {
+    delivered: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+struct Hook {
+    config: WebhookConfig,
+    queue: mpsc::Sender<String>,
+    counters: Arc<HookCounters>,
+}
+
+impl Hook {
+    fn spawn(config: WebhookConfig) -> Self {
+        let (queue, receiver) = mpsc::channel(WEBHOOK_QUEUE_CAPACITY);
+        let counters = Arc::new(HookCounters::default());
+        tokio::spawn(deliver_loop(config.clone(), receiver, counters.clone()));
+
+        Self {
+            config,
+            queue,
+            counters,
+        }
+    }
+
+    fn accepts(&self, event_type: &str) -> bool {
+        self.config.events.is_empty()
+            || self
+                .config
+                .events
+                .iter()
+                .any(|filter| filter.eq_ignore_ascii_case(event_type))
+    }
+
+    fn enqueue(&self, body: &str) {
+        match self.queue.try_send(body.to_string()) {
+            Ok(()) => {}
+            Err(mpsc::error::TrySendError::Full(_)) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+                warn!(
+                    "Webhook queue for {} is full, dropping event",
+                    self.config.url
+                );
+            }
+            Err(mpsc::error::TrySendError::Closed(_)) => {}
+        }
+    }
+
+    fn status(&self) -> WebhookStatus {
+        WebhookStatus {
+            url: self.config.url.clone(),
+            events: self.config.events.clone(),
+            delivered: self.counters.delivered.load(Ordering::Relaxed),
+            failed: self.counters.failed.load(Ordering::Relaxed),
+            dropped: self.counters.dropped.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Forwards events from the [`EventBus`] to configured webhooks.
+///
+/// Every hook has its own bounded queue and delivery task, so a slow or unreachable
+/// receiver only ever loses its own events and never holds up the bus.
+pub struct WebhookDispatcher {
+    hooks: Mutex<Vec<Arc<Hook>>>,
+}
+
+impl WebhookDispatcher {
+    /// Start dispatching events from `event_bus` to `hooks`.
+    pub fn start(event_bus: &EventBus, hooks: Vec<WebhookConfig>) -> Arc<Self> {
+        let dispatcher = Arc::new(Self {
+            hooks: Mutex::new(Vec::new()),
+        });
+        dispatcher.reload(hooks);
+
+        let receiver = event_bus.subscribe();
+        tokio::spawn(dispatch_loop(Arc::downgrade(&dispatcher), receiver));
+
+        dispatcher
+    }
+
+    /// Replace the configured hooks. Hooks whose configuration is unchanged keep
+    /// their queue and counters; removed hooks finish delivering what they queued.
+    pub fn reload(&self, configs: Vec<WebhookConfig>) {
+        let mut hooks = self.hooks.lock().unwrap();
+        let previous = std::mem::take(&mut *hooks);
+
+        for config in configs {
+            if config.url.trim().is_empty() {
+                warn!("Ignoring webhook without a URL");
+                continue;
+            }
+
+            let hook = previous
+                .iter()
+                .find(|hook| hook.config == config)
+                .cloned()
+                .unwrap_or_else(|| Arc::new(Hook::spawn(config)));
+            hooks.push(hook);
+        }
+    }
+
+    /// Delivery counters for every configured hook.
+    pub fn status(&self) -> Vec<WebhookStatus> {
+        self.hooks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|hook| hook.status())
+            .collect()
+    }
+
+    fn dispatch(&self, message: &EventMessage) {
+        let hooks = self.hooks.lock().unwrap().clone();
+        if hooks.is_empty() {
+            return;
+        }
+
+        let body = match serde_json::to_value(message) {
+            Ok(body) => body,
+            Err(error) => {
+                warn!("Failed to serialize event for webhooks: {}", error);
+                return;
+            }
+        };
+        let event_type = body
+            .get("type")
+            .and_then(|value| value.as_str())
+            .unwrap_or_default();
+        let serialized = body.to_string();
+
+        for hook in hooks.iter().filter(|hook| hook.accepts(event_type)) {
+            hook.enqueue(&serialized);
+        }
+    }
+}
+
+async fn dispatch_loop(
+    dispatcher: std::sync::Weak<WebhookDispatcher>,
+    mut receiver: broadcast::Receiver<EventMessage>,
+) {
+    loop {
+        match receiver.recv().await {
+            Ok(message) => {
+                let Some(dispatcher) = dispatcher.upgrade() else {
+                    break;
+                };
+                dispatcher.dispatch(&message);
+            }
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("Webhook dispatcher lagged, skipped {} events", skipped);
+            }
+            Err(broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+async fn deliver_loop(
+    config: WebhookConfig,
+    mut receiver: mpsc::Receiver<String>,
+    counters: Arc<HookCounters>,
+) {
+    while let Some(body) = receiver.recv().await {
+        let mut attempt = 0;
+        loop {
+            match post_json(&config, &body).await {
+                Ok(()) => {
+                    counters.delivered.fetch_add(1, Ordering::Relaxed);
+                    break;
+                }
+                Err(error) if attempt < config.max_retries => {
+                    debug!("Webhook {} failed, retrying: {}", config.url, error);
+                    tokio::time::sleep(WEBHOOK_RETRY_DELAY * 2u32.pow(attempt.min(6))).await;
+                    attempt += 1;
+                }
+                Err(error) => {
+                    counters.failed.fetch_add(1, Ordering::Relaxed);
+                    warn!("Webhook {} failed: {}", config.url, error);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+/// POST `body` to the hook with curl. Options are passed on stdin so the secret never
+/// appears in the process list.
+async fn post_json(config: &WebhookConfig, body: &str) -> anyhow::Result<()> {
+    let mut options = vec![
+        format!("url = {}", curl_quote(&config.url)),
+        "request = \"POST\"".to_string(),
+        "header = \"Content-Type: application/json\"".to_string(),
+        format!("data-binary = {}", curl_quote(body)),
+    ];
+    if let Some(secret) = config.secret.as_deref().filter(|s| !s.is_empty()) {
+        options.push(format!(
+            "header = {}",
+            curl_quote(&format!("{}: {}", WEBHOOK_TOKEN_HEADER, secret))
+        ));
+    }
+
+    let mut child = Command::new("curl")
+        .args(["-sS", "-o", "/dev/null", "-w", "%{http_code}", "-K", "-"])
+        .arg("--max-time")
+        .arg(config.timeout_secs.max(1).to_string())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(options.join("\n").as_bytes()).await?;
+    }
+
+    let output = child.wait_with_output().await?;
+    let status = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() {
+        anyhow::bail!("{}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    if !status.starts_with('2') {
+        anyhow::bail!("receiver responded with HTTP {}", status.trim());
+    }
+
+    Ok(())
+}
+
+fn curl_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
@@ -0,0 +1,242 @@
+//! Single-instance guard for the backend.
+//!
+//! Two backends sharing a data directory would overwrite each other's library cache
+//! and playlists, so the first one takes an exclusive lock on a file in the cache
+//! directory and records its PID and API address there. Later instances report that
+//! address and exit, or with `--takeover` ask the running one to shut down first.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::config::{ApiConfig, Paths};
+use crate::ctl::{self, CtlAddress, CtlTarget};
+
+/// Command line flag asking a running instance to shut down and taking its place.
+pub const TAKEOVER_FLAG: &str = "--takeover";
+
+/// Exit code used when another instance already runs on the same data directory.
+pub const EXIT_ALREADY_RUNNING: i32 = 3;
+
+/// How long `--takeover` waits for the running instance to exit.
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(10);
+
+const TAKEOVER_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// What a running instance records in its lock file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstanceInfo {
+    pub pid: u32,
+    /// TCP port of the API, when it listens on one
+    pub port: Option<u16>,
+    /// Unix socket of the API, if any
+    pub unix_socket: Option<PathBuf>,
+    #[serde(with = "crate::utils::serde_rfc3339")]
+    pub started_at: DateTime<Utc>,
+}
+
+impl InstanceInfo {
+    /// Describe the current process serving the API configured in `api`.
+    pub fn current(api: &ApiConfig) -> Self {
+        Self {
+            pid: std::process::id(),
+            port: api.listen_tcp.then_some(api.port),
+            unix_socket: api.unix_socket.clone(),
+            started_at: Utc::now(),
+        }
+    }
+
+    /// Human readable API address, e.g. `http://127.0.0.1:3030`.
+    pub fn address(&self) -> String {
+        match (self.port, &self.unix_socket) {
+            (Some(port), _) => format!("http://127.0.0.1:{}", port),
+            (None, Some(socket)) => format!("unix:{}", socket.display()),
+            (None, None) => "no API address".to_string(),
+        }
+    }
+
+    /// Where `hexendrum ctl` would reach this instance.
+    pub fn ctl_address(&self) -> Option<CtlAddress> {
+        match (self.port, &self.unix_socket) {
+            (Some(port), _) => Some(CtlAddress::Tcp(port)),
+            (None, Some(socket)) => Some(CtlAddress::Unix(socket.clone())),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Result of trying to become the running instance.
+#[derive(Debug)]
+pub enum LockOutcome {
+    Acquired(InstanceLock),
+    /// Another live instance holds the lock. Its details are missing when they could
+    /// not be read, e.g. while it is still writing them.
+    Held(Option<InstanceInfo>),
+}
+
+/// Exclusive claim on a data directory, released when dropped.
+#[derive(Debug)]
+pub struct InstanceLock {
+    file: File,
+    path: PathBuf,
+}
+
+impl InstanceLock {
+    /// Try to take the lock at `path`, recording `info` in it.
+    ///
+    /// The lock is an OS file lock, so it is released even if the holder crashes.
+    /// Where file locks are unsupported (some network filesystems) the PID recorded in
+    /// the file is checked for liveness instead.
+    pub fn acquire(path: &Path, info: &InstanceInfo) -> Result<LockOutcome> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("cannot open lock file {:?}", path))?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Ok(LockOutcome::Held(read_info(&mut file))),
+            Err(TryLockError::Error(error)) => {
+                tracing::warn!(
+                    "File locking unavailable for {:?} ({}); checking the recorded PID instead",
+                    path,
+                    error
+                );
+                if let Some(other) = read_info(&mut file) {
+                    if other.pid != info.pid && is_process_alive(other.pid) {
+                        return Ok(LockOutcome::Held(Some(other)));
+                    }
+                }
+            }
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&serde_json::to_vec_pretty(info)?)?;
+        file.sync_all()?;
+
+        Ok(LockOutcome::Acquired(Self {
+            file,
+            path: path.to_path_buf(),
+        }))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for InstanceLock {
+    fn drop(&mut self) {
+        // The file itself stays: removing it would let a process that already opened
+        // it lock an unlinked file while a third one creates a new lock file.
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
+
+fn read_info(file: &mut File) -> Option<InstanceInfo> {
+    let mut content = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut content).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Whether a process with `pid` exists.
+pub fn is_process_alive(pid: u32) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        Path::new("/proc").join(pid.to_string()).exists()
+    }
+
+    #[cfg(all(unix, not(target_os = "linux")))]
+    {
+        std::process::Command::new("kill")
+            .args(["-0", &pid.to_string()])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .map(|status| status.success())
+            .unwrap_or(true)
+    }
+
+    // Without a cheap check, assume the recorded instance is still running.
+    #[cfg(not(unix))]
+    {
+        let _ = pid;
+        true
+    }
+}
+
+/// Become the running instance for `paths`, or return the exit code to stop with.
+///
+/// With `takeover` a running instance is asked to shut down through its API, and the
+/// lock is taken once it has exited.
+pub async fn claim(
+    paths: &Paths,
+    api: &ApiConfig,
+    takeover: bool,
+) -> std::result::Result<InstanceLock, i32> {
+    let lock_file = paths.instance_lock_file();
+    let info = InstanceInfo::current(api);
+    let acquire = || {
+        InstanceLock::acquire(&lock_file, &info).map_err(|error| {
+            eprintln!("hexendrum: {:#}", error);
+            1
+        })
+    };
+
+    let other = match acquire()? {
+        LockOutcome::Acquired(lock) => return Ok(lock),
+        LockOutcome::Held(other) => other,
+    };
+    let describe = |other: &Option<InstanceInfo>| match other {
+        Some(other) => format!("pid {}, API at {}", other.pid, other.address()),
+        None => "details unavailable".to_string(),
+    };
+
+    if !takeover {
+        eprintln!(
+            "hexendrum: another instance is already running ({}); use `hexendrum ctl` to control it or {} to replace it",
+            describe(&other),
+            TAKEOVER_FLAG
+        );
+        return Err(EXIT_ALREADY_RUNNING);
+    }
+
+    let mut target = CtlTarget::from_config(api);
+    if let Some(address) = other.as_ref().and_then(InstanceInfo::ctl_address) {
+        target.address = address;
+    }
+    eprintln!(
+        "hexendrum: asking the running instance ({}) to shut down",
+        describe(&other)
+    );
+    if let Err(error) = ctl::request_shutdown(&target).await {
+        eprintln!("hexendrum: could not reach the running instance: {}", error);
+        return Err(EXIT_ALREADY_RUNNING);
+    }
+
+    let deadline = tokio::time::Instant::now() + TAKEOVER_TIMEOUT;
+    while tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(TAKEOVER_POLL_INTERVAL).await;
+        if let LockOutcome::Acquired(lock) = acquire()? {
+            return Ok(lock);
+        }
+    }
+
+    eprintln!(
+        "hexendrum: the running instance did not exit within {} seconds",
+        TAKEOVER_TIMEOUT.as_secs()
+    );
+    Err(EXIT_ALREADY_RUNNING)
+}
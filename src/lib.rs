@@ -10,6 +10,11 @@
 pub mod api;
 pub mod audio;
 pub mod config;
+pub mod ctl;
+pub mod demo;
+pub mod diagnostics;
+pub mod instance;
+pub mod maintenance;
 
 pub mod events;
 pub mod library;
@@ -32,7 +37,6 @@ pub const APP_NAME: &str = "Hexendrum";
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
     use tempfile::tempdir;
 
     #[test]
@@ -52,42 +56,11 @@ mod tests {
     #[test]
     fn test_library_creation() {
         let workspace = tempdir().expect("failed to create temp workspace");
-        let cache_dir = workspace.path().join("cache");
-        let config_dir = workspace.path().join("config");
+        let paths = config::Paths::portable(workspace.path());
 
-        fs::create_dir(&cache_dir).expect("failed to create cache dir");
-        fs::create_dir(&config_dir).expect("failed to create config dir");
-
-        let old_cache = std::env::var("XDG_CACHE_HOME").ok();
-        let old_config = std::env::var("XDG_CONFIG_HOME").ok();
-        let old_home = std::env::var("HOME").ok();
-
-        std::env::set_var("XDG_CACHE_HOME", &cache_dir);
-        std::env::set_var("XDG_CONFIG_HOME", &config_dir);
-        std::env::set_var("HOME", workspace.path());
-
-        let library = Library::new();
+        let library = Library::with_paths(&paths);
         assert_eq!(library.track_count(), 0);
         assert!(!library.is_scanning());
-
-        drop(library);
-
-        if let Some(old) = old_cache {
-            std::env::set_var("XDG_CACHE_HOME", old);
-        } else {
-            std::env::remove_var("XDG_CACHE_HOME");
-        }
-
-        if let Some(old) = old_config {
-            std::env::set_var("XDG_CONFIG_HOME", old);
-        } else {
-            std::env::remove_var("XDG_CONFIG_HOME");
-        }
-
-        if let Some(old) = old_home {
-            std::env::set_var("HOME", old);
-        } else {
-            std::env::remove_var("HOME");
-        }
+        assert!(paths.cache_dir.is_dir());
     }
 }
@@ -1,30 +1,107 @@
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, Mutex};
 
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Value};
 use tokio::fs;
 use tokio::process::Command;
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
 use utoipa::ToSchema;
 
-use super::{Library, Track};
+use super::artwork::{ArtworkDedupReport, ArtworkStore};
+use super::collation::{Collator, Section};
+use super::editions::{split_editions, AlbumDisambiguation, AlbumEdition};
+use super::musicbrainz::normalize_mbid;
+use super::{Library, Track, TrackMetadata, TrackTagUpdate};
+use crate::config::Paths;
+use crate::events::{EventBus, EventPayload};
 use crate::utils::ensure_directory;
+use crate::utils::schema::{Schema, SchemaError};
 
 const LAST_FM_IMAGE_PRIORITY: [&str; 5] = ["mega", "extralarge", "large", "medium", "small"];
-const LAST_FM_ENDPOINT: &str = "https://ws.audioscrobbler.com/2.0/";
+/// Last.fm web service root
+pub const LAST_FM_ENDPOINT: &str = "https://ws.audioscrobbler.com/2.0/";
+/// Primary artist of albums credited to more artists than the
+/// `library.various_artists_threshold`
+pub const VARIOUS_ARTISTS: &str = "Various Artists";
+/// Default `library.various_artists_threshold`
+pub const DEFAULT_VARIOUS_ARTISTS_THRESHOLD: usize = 4;
+/// Last.fm serves this placeholder instead of real artist photos for most artists.
+const LAST_FM_PLACEHOLDER_IMAGE: &str = "2a96cbd8b46e442fc41c2b86b821562f";
+/// Image files looked up in album folders before asking remote providers.
+const LOCAL_ARTIST_IMAGE_NAMES: [&str; 3] = ["artist.jpg", "artist.jpeg", "artist.png"];
+/// How long a failed artist image lookup is remembered before retrying.
+const ARTIST_IMAGE_MISS_TTL: std::time::Duration = std::time::Duration::from_secs(7 * 24 * 60 * 60);
 
 #[derive(Debug, Clone)]
 struct AlbumAggregate {
     id: String,
+    edition: Option<String>,
     title: String,
-    primary_artist: Option<String>,
-    artists: HashSet<String>,
+    artist_credits: Vec<ArtistCredit>,
+    /// Whether any track has an album artist tag
+    has_album_artist: bool,
     track_count: usize,
     sample_track: Option<Track>,
+    /// Earliest release year among the tracks
+    year: Option<i32>,
+    /// Latest modification time among the tracks
+    last_added: Option<DateTime<Utc>>,
+    musicbrainz_release_id: Option<String>,
+}
+
+/// Ordering of album search results
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlbumSort {
+    /// Alphabetically by title
+    #[default]
+    Title,
+    /// Alphabetically by primary artist, then title
+    Artist,
+    /// Most tracks first
+    TrackCount,
+    /// Oldest release first; albums without a year come last
+    Year,
+    /// Most recently added first. Tracks carry no date added, so this uses the
+    /// latest file modification time of the album's tracks.
+    RecentlyAdded,
+}
+
+/// Album search criteria, see [`AlbumService::search_albums_page`]
+#[derive(Debug, Clone, Default)]
+pub struct AlbumSearch {
+    pub query: Option<String>,
+    pub sort: AlbumSort,
+    pub offset: usize,
+    /// Return every album from `offset` on when unset
+    pub limit: Option<usize>,
+    /// Only albums tagged with this MusicBrainz release, normalized with
+    /// [`normalize_mbid`]
+    pub musicbrainz_release_id: Option<String>,
+}
+
+/// A page of album search results
+#[derive(Debug, Clone)]
+pub struct AlbumPage {
+    /// Number of albums matching the query
+    pub total: usize,
+    pub albums: Vec<AlbumSummary>,
+    /// Sections of all matching albums when sorted by title or artist
+    pub sections: Vec<Section>,
+}
+
+/// An aggregated album with overrides applied, before its artwork is resolved
+struct AlbumCandidate {
+    summary: AlbumSummary,
+    manual_artwork_path: Option<PathBuf>,
+    sample_track: Option<Track>,
+    year: Option<i32>,
+    last_added: Option<DateTime<Utc>>,
 }
 
 /// Summary data for an album aggregated from the library
@@ -32,16 +109,79 @@ struct AlbumAggregate {
 pub struct AlbumSummary {
     pub id: String,
     pub title: String,
+    /// Edition of an album split by disambiguation, e.g. "1998"
+    pub edition: Option<String>,
     pub primary_artist: Option<String>,
     pub artists: Vec<String>,
+    /// Artists credited on the album's tracks, most credited first
+    pub artist_credits: Vec<ArtistCredit>,
     pub track_count: usize,
     pub artwork_path: Option<PathBuf>,
     pub metadata: Option<AlbumMetadata>,
     pub is_manual: bool,
+    /// MusicBrainz release the tracks are tagged with, or else the one Last.fm
+    /// matched the album with
+    pub musicbrainz_release_id: Option<String>,
+}
+
+/// An artist credited on an album's tracks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ArtistCredit {
+    #[schema(example = "Queen")]
+    pub artist: String,
+    /// Number of the album's tracks crediting the artist
+    #[schema(example = 12)]
+    pub track_count: usize,
+}
+
+/// Artists credited on `tracks` with their number of tracks, most credited first and
+/// ties in alphabetical order, so the result does not depend on the order of `tracks`
+pub fn artist_credits<'a>(tracks: impl IntoIterator<Item = &'a Track>) -> Vec<ArtistCredit> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for track in tracks {
+        if let Some(artist) = track
+            .metadata
+            .artist
+            .as_deref()
+            .map(str::trim)
+            .filter(|artist| !artist.is_empty())
+        {
+            *counts.entry(artist).or_default() += 1;
+        }
+    }
+
+    let mut credits: Vec<ArtistCredit> = counts
+        .into_iter()
+        .map(|(artist, track_count)| ArtistCredit {
+            artist: artist.to_string(),
+            track_count,
+        })
+        .collect();
+    credits.sort_by(|a, b| {
+        b.track_count
+            .cmp(&a.track_count)
+            .then_with(|| a.artist.cmp(&b.artist))
+    });
+    credits
+}
+
+/// Primary artist of an album crediting `credits`: [`VARIOUS_ARTISTS`] when it credits
+/// more than `threshold` artists and none of its tracks has an album artist tag,
+/// otherwise the most credited artist. A threshold of 0 never picks
+/// [`VARIOUS_ARTISTS`].
+pub fn album_primary_artist(
+    credits: &[ArtistCredit],
+    has_album_artist: bool,
+    threshold: usize,
+) -> Option<String> {
+    if threshold > 0 && !has_album_artist && credits.len() > threshold {
+        return Some(VARIOUS_ARTISTS.to_string());
+    }
+    credits.first().map(|credit| credit.artist.clone())
 }
 
 /// Rich metadata about an album sourced from manual overrides or remote providers.
-#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct AlbumMetadata {
     pub summary: Option<String>,
     pub url: Option<String>,
@@ -111,9 +251,13 @@ pub struct ManualAlbumUpdate {
     pub search_album: Option<String>,
     pub search_artist: Option<String>,
     pub refresh_artwork: bool,
+    pub disambiguation: Option<AlbumDisambiguation>,
+    /// MusicBrainz release the album's tracks are tagged with, looked up on Last.fm
+    /// before the search names
+    pub musicbrainz_release_id: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct AlbumOverrideRecord {
     pub album_id: String,
     pub title: Option<String>,
@@ -122,6 +266,13 @@ pub struct AlbumOverrideRecord {
     pub search_artist: Option<String>,
     pub metadata: Option<AlbumMetadata>,
     pub artwork_path: Option<String>,
+    /// Whether the album is split into editions
+    #[serde(default, skip_serializing_if = "AlbumDisambiguation::is_auto")]
+    pub disambiguation: AlbumDisambiguation,
+    /// MusicBrainz release Last.fm matched an untagged album with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub musicbrainz_release_id: Option<String>,
+    #[serde(with = "crate::utils::serde_rfc3339")]
     pub updated_at: DateTime<Utc>,
 }
 
@@ -135,61 +286,197 @@ impl AlbumOverrideRecord {
             search_artist: None,
             metadata: None,
             artwork_path: None,
+            disambiguation: AlbumDisambiguation::Auto,
+            musicbrainz_release_id: None,
             updated_at: Utc::now(),
         }
     }
+
+    /// Fill the fields this record leaves unset from `other`. Returns the names of
+    /// the fields both set differently, which keep this record's value.
+    fn merge(&mut self, other: AlbumOverrideRecord) -> Vec<&'static str> {
+        fn merge_field<T: PartialEq>(
+            field: &mut Option<T>,
+            other: Option<T>,
+            name: &'static str,
+            conflicts: &mut Vec<&'static str>,
+        ) {
+            match (field.as_ref(), other) {
+                (None, other) => *field = other,
+                (Some(value), Some(other)) if *value != other => conflicts.push(name),
+                _ => {}
+            }
+        }
+
+        let mut conflicts = Vec::new();
+        merge_field(&mut self.title, other.title, "title", &mut conflicts);
+        merge_field(
+            &mut self.primary_artist,
+            other.primary_artist,
+            "primary_artist",
+            &mut conflicts,
+        );
+        merge_field(
+            &mut self.search_album,
+            other.search_album,
+            "search_album",
+            &mut conflicts,
+        );
+        merge_field(
+            &mut self.search_artist,
+            other.search_artist,
+            "search_artist",
+            &mut conflicts,
+        );
+        merge_field(
+            &mut self.metadata,
+            other.metadata,
+            "metadata",
+            &mut conflicts,
+        );
+        merge_field(
+            &mut self.artwork_path,
+            other.artwork_path,
+            "artwork_path",
+            &mut conflicts,
+        );
+        merge_field(
+            &mut self.musicbrainz_release_id,
+            other.musicbrainz_release_id,
+            "musicbrainz_release_id",
+            &mut conflicts,
+        );
+        if self.disambiguation.is_auto() {
+            self.disambiguation = other.disambiguation;
+        } else if !other.disambiguation.is_auto() && other.disambiguation != self.disambiguation {
+            conflicts.push("disambiguation");
+        }
+        conflicts
+    }
+}
+
+/// Outcome of a bulk album edit for a single file
+#[derive(Debug, Clone)]
+pub struct AlbumEditFileResult {
+    pub track_id: String,
+    pub path: PathBuf,
+    pub error: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Report returned after applying a bulk metadata edit to an album
+#[derive(Debug, Clone)]
+pub struct AlbumEditReport {
+    /// Album identifier before the edit
+    pub previous_album_id: String,
+    /// Album identifier after the edit (may differ when artist or title changed)
+    pub album_id: String,
+    /// Per-file results
+    pub files: Vec<AlbumEditFileResult>,
+    /// Manual override fields the new album already set differently, which were kept
+    /// over those of the edited album
+    pub override_conflicts: Vec<&'static str>,
+}
+
+impl AlbumEditReport {
+    /// Number of files that were updated successfully
+    pub fn succeeded(&self) -> usize {
+        self.files
+            .iter()
+            .filter(|file| file.error.is_none())
+            .count()
+    }
+
+    /// Number of files that could not be updated
+    pub fn failed(&self) -> usize {
+        self.files.len() - self.succeeded()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[schema(rename_all = "lowercase")]
 pub enum AlbumExportFormat {
     Json,
     Yaml,
 }
 
+/// Versions of the album override store
+///
+/// 1: the records are kept under `records`, next to the version
+const OVERRIDE_SCHEMA: Schema = Schema {
+    name: "album override store",
+    migrations: &[records_under_key],
+};
+
+/// Move the records of an unversioned store, a bare list, under `records`
+fn records_under_key(document: &mut Value) {
+    if document.is_array() {
+        *document = json!({ "records": document.take() });
+    }
+}
+
+/// The album override store as saved
+#[derive(Serialize, Deserialize)]
+struct StoredOverrides {
+    version: u32,
+    #[serde(default)]
+    records: Vec<AlbumOverrideRecord>,
+}
+
 #[derive(Clone)]
 struct AlbumOverrideStore {
     path: PathBuf,
     data: Arc<Mutex<HashMap<String, AlbumOverrideRecord>>>,
+    /// Why the store on disk was refused, e.g. written by a newer Hexendrum, so it
+    /// is not saved over
+    refused: Option<SchemaError>,
 }
 
 impl AlbumOverrideStore {
-    fn new() -> Self {
-        let path = dirs::config_dir()
-            .unwrap_or_else(|| {
-                dirs::home_dir()
-                    .unwrap_or_else(|| PathBuf::from("~"))
-                    .join(".config")
-            })
-            .join("hexendrum")
-            .join("album_overrides.json");
-
-        let data = Self::load_records(&path);
+    fn new(path: PathBuf) -> Self {
+        let (data, refused) = match Self::load_records(&path) {
+            Ok(data) => (data, None),
+            Err(error) => {
+                warn!("{}", error);
+                (HashMap::new(), Some(error))
+            }
+        };
 
         Self {
             path,
             data: Arc::new(Mutex::new(data)),
+            refused,
         }
     }
 
-    fn load_records(path: &Path) -> HashMap<String, AlbumOverrideRecord> {
+    fn load_records(path: &Path) -> Result<HashMap<String, AlbumOverrideRecord>, SchemaError> {
         if !path.exists() {
-            return HashMap::new();
+            return Ok(HashMap::new());
         }
 
-        match std::fs::read_to_string(path) {
-            Ok(content) => match serde_json::from_str::<Vec<AlbumOverrideRecord>>(&content) {
-                Ok(records) => records
-                    .into_iter()
-                    .map(|record| (record.album_id.clone(), record))
-                    .collect(),
+        let mut document = match std::fs::read_to_string(path) {
+            Ok(content) => match serde_json::from_str::<Value>(&content) {
+                Ok(document) => document,
                 Err(error) => {
                     warn!("Failed to parse album override file {:?}: {}", path, error);
-                    HashMap::new()
+                    return Ok(HashMap::new());
                 }
             },
             Err(error) => {
                 warn!("Failed to read album override file {:?}: {}", path, error);
-                HashMap::new()
+                return Ok(HashMap::new());
+            }
+        };
+        OVERRIDE_SCHEMA.upgrade(&mut document, path)?;
+
+        match serde_json::from_value::<StoredOverrides>(document) {
+            Ok(stored) => Ok(stored
+                .records
+                .into_iter()
+                .map(|record| (record.album_id.clone(), record))
+                .collect()),
+            Err(error) => {
+                warn!("Failed to parse album override file {:?}: {}", path, error);
+                Ok(HashMap::new())
             }
         }
     }
@@ -199,6 +486,19 @@ impl AlbumOverrideStore {
         data.get(album_id).cloned()
     }
 
+    fn contains(&self, album_id: &str) -> bool {
+        self.data.lock().unwrap().contains_key(album_id)
+    }
+
+    fn disambiguation(&self, album_id: &str) -> AlbumDisambiguation {
+        self.data
+            .lock()
+            .unwrap()
+            .get(album_id)
+            .map(|record| record.disambiguation)
+            .unwrap_or_default()
+    }
+
     fn set(&self, record: AlbumOverrideRecord) -> Result<AlbumOverrideRecord> {
         {
             let mut data = self.data.lock().unwrap();
@@ -208,16 +508,76 @@ impl AlbumOverrideStore {
         Ok(record)
     }
 
+    /// Move the record stored under `old_id` to `new_id`. When `new_id` already has
+    /// a record, the moved one fills the fields it leaves unset; fields both set
+    /// differently keep the existing value and are returned.
+    fn migrate(&self, old_id: &str, new_id: &str) -> Result<Vec<&'static str>> {
+        let conflicts = {
+            let mut data = self.data.lock().unwrap();
+            let mut record = match data.remove(old_id) {
+                Some(record) => record,
+                None => return Ok(Vec::new()),
+            };
+
+            let conflicts = match data.get_mut(new_id) {
+                Some(existing) => existing.merge(record),
+                None => {
+                    record.album_id = new_id.to_string();
+                    data.insert(new_id.to_string(), record);
+                    Vec::new()
+                }
+            };
+            if let Some(record) = data.get_mut(new_id) {
+                record.updated_at = Utc::now();
+            }
+            conflicts
+        };
+
+        self.save()?;
+        Ok(conflicts)
+    }
+
+    /// Point records at the new location of artwork files moved from `(old, new)`.
+    fn move_artwork(&self, moved: &[(PathBuf, PathBuf)]) -> Result<()> {
+        let mut changed = false;
+        {
+            let mut data = self.data.lock().unwrap();
+            for record in data.values_mut() {
+                let Some(artwork_path) = record.artwork_path.as_deref() else {
+                    continue;
+                };
+                if let Some((_, new_path)) = moved
+                    .iter()
+                    .find(|(old_path, _)| Path::new(artwork_path) == old_path)
+                {
+                    record.artwork_path = Some(new_path.to_string_lossy().to_string());
+                    changed = true;
+                }
+            }
+        }
+
+        if changed {
+            self.save()?;
+        }
+        Ok(())
+    }
+
     fn save(&self) -> Result<()> {
+        if let Some(error) = &self.refused {
+            return Err(error.clone().into());
+        }
         if let Some(parent) = self.path.parent() {
             ensure_directory(parent)?;
         }
 
-        let snapshot: Vec<AlbumOverrideRecord> = {
-            let data = self.data.lock().unwrap();
-            let mut records: Vec<_> = data.values().cloned().collect();
-            records.sort_by(|a, b| a.album_id.cmp(&b.album_id));
-            records
+        let snapshot = StoredOverrides {
+            version: OVERRIDE_SCHEMA.current(),
+            records: {
+                let data = self.data.lock().unwrap();
+                let mut records: Vec<_> = data.values().cloned().collect();
+                records.sort_by(|a, b| a.album_id.cmp(&b.album_id));
+                records
+            },
         };
 
         let content = serde_json::to_string_pretty(&snapshot)?;
@@ -244,36 +604,113 @@ impl AlbumOverrideStore {
 #[derive(Clone)]
 pub struct AlbumService {
     cache_dir: PathBuf,
+    artist_cache_dir: PathBuf,
+    artwork: ArtworkStore,
     lastfm_api_key: Option<String>,
+    /// Last.fm API root, [`LAST_FM_ENDPOINT`] unless changed for tests
+    lastfm_endpoint: String,
     overrides: AlbumOverrideStore,
+    disambiguation: bool,
+    various_artists_threshold: usize,
+    /// Where `album_artwork_updated` events go
+    event_bus: Option<Arc<EventBus>>,
 }
 
 impl AlbumService {
-    /// Create a new album service
+    /// Create a new album service storing its caches in the default locations
+    #[allow(dead_code)]
     pub fn new(lastfm_api_key: Option<String>) -> Self {
-        let cache_dir = dirs::cache_dir()
-            .unwrap_or_else(|| {
-                dirs::home_dir()
-                    .unwrap_or_else(|| PathBuf::from("~"))
-                    .join(".cache")
-            })
-            .join("hexendrum")
-            .join("album_art");
+        Self::with_paths(&Paths::standard(), lastfm_api_key)
+    }
 
-        if let Err(error) = ensure_directory(&cache_dir) {
-            warn!(
-                "Failed to ensure album art cache directory {:?}: {}",
-                cache_dir, error
-            );
+    /// Create a new album service storing its caches and overrides under `paths`
+    pub fn with_paths(paths: &Paths, lastfm_api_key: Option<String>) -> Self {
+        let cache_dir = paths.album_art_dir();
+        let artist_cache_dir = paths.artist_art_dir();
+
+        for directory in [&cache_dir, &artist_cache_dir] {
+            if let Err(error) = ensure_directory(directory) {
+                warn!(
+                    "Failed to ensure artwork cache directory {:?}: {}",
+                    directory, error
+                );
+            }
         }
 
-        let overrides = AlbumOverrideStore::new();
+        let overrides = AlbumOverrideStore::new(paths.album_overrides_file());
+        let artwork = ArtworkStore::new(cache_dir.clone());
 
-        Self {
+        let service = Self {
             cache_dir,
+            artist_cache_dir,
+            artwork,
             lastfm_api_key: lastfm_api_key.filter(|value| !value.trim().is_empty()),
+            lastfm_endpoint: LAST_FM_ENDPOINT.to_string(),
             overrides,
+            disambiguation: false,
+            various_artists_threshold: DEFAULT_VARIOUS_ARTISTS_THRESHOLD,
+            event_bus: None,
+        };
+        let report = service.deduplicate_artwork();
+        if report.files > 0 {
+            info!(
+                "Moved {} cached artwork file(s) into the store: {} duplicate(s), {} bytes saved",
+                report.files, report.duplicates, report.bytes_saved
+            );
         }
+        service
+    }
+
+    /// Split albums sharing a title and artist into editions by release year and
+    /// track total, see [`split_editions`].
+    pub fn with_album_disambiguation(mut self, enabled: bool) -> Self {
+        self.disambiguation = enabled;
+        self
+    }
+
+    /// List albums crediting more than `threshold` artists and no album artist under
+    /// [`VARIOUS_ARTISTS`]; 0 always lists the most credited artist.
+    pub fn with_various_artists_threshold(mut self, threshold: usize) -> Self {
+        self.various_artists_threshold = threshold;
+        self
+    }
+
+    /// Announce artwork stored, replaced or deleted from now on with
+    /// `album_artwork_updated` events.
+    pub fn with_event_bus(mut self, event_bus: Arc<EventBus>) -> Self {
+        self.event_bus = Some(event_bus);
+        self
+    }
+
+    /// Run `change` on the artwork store, then emit an `album_artwork_updated` event
+    /// for each album whose image it stored, replaced or deleted.
+    fn announce_artwork_changes<T>(&self, change: impl FnOnce() -> T) -> T {
+        let Some(event_bus) = self.event_bus.as_ref() else {
+            return change();
+        };
+        let before = self.artwork.album_hashes();
+        let result = change();
+        let after = self.artwork.album_hashes();
+
+        let mut changed: Vec<&String> = after
+            .iter()
+            .filter(|(album_id, hash)| before.get(*album_id) != Some(*hash))
+            .map(|(album_id, _)| album_id)
+            .chain(
+                before
+                    .keys()
+                    .filter(|album_id| !after.contains_key(*album_id)),
+            )
+            .collect();
+        changed.sort();
+        for album_id in changed {
+            let artwork_url = self
+                .artwork
+                .path(album_id)
+                .map(|path| album_artwork_url(album_id, &path));
+            event_bus.emit(EventPayload::album_artwork_updated(album_id, artwork_url));
+        }
+        result
     }
 
     /// Return the album artwork cache directory
@@ -281,6 +718,111 @@ impl AlbumService {
         &self.cache_dir
     }
 
+    /// Send Last.fm requests to `endpoint` instead of [`LAST_FM_ENDPOINT`].
+    #[allow(dead_code)]
+    pub fn with_lastfm_endpoint(mut self, endpoint: &str) -> Self {
+        self.lastfm_endpoint = endpoint.to_string();
+        self
+    }
+
+    /// Count filesystem calls made on the artwork cache with `counter`.
+    #[allow(dead_code)]
+    pub fn with_fs_call_counter(mut self, counter: Arc<AtomicUsize>) -> Self {
+        self.artwork.fs_calls = counter;
+        self
+    }
+
+    /// Re-read the artwork cache directory, picking up files added or removed by
+    /// other processes.
+    #[allow(dead_code)]
+    pub fn rescan_artwork_cache(&self) {
+        self.deduplicate_artwork();
+    }
+
+    /// Move loose `<album id>.jpg` files in the cache directory, as stored by earlier
+    /// versions, into the content-addressed artwork store, keeping identical images
+    /// once. Manual overrides pointing at a moved file follow it.
+    pub fn deduplicate_artwork(&self) -> ArtworkDedupReport {
+        let (report, moved) = self.announce_artwork_changes(|| self.artwork.rescan());
+        if let Err(error) = self.overrides.move_artwork(&moved) {
+            warn!(
+                "Failed to update album overrides with moved artwork: {}",
+                error
+            );
+        }
+        report
+    }
+
+    /// Forget cached artwork that has disappeared from disk, e.g. when serving it
+    /// failed with `NotFound`.
+    pub fn forget_artwork(&self, album_id: &str) {
+        self.announce_artwork_changes(|| self.artwork.forget(album_id));
+    }
+
+    /// Cached artwork no album in the library or with a manual override refers to,
+    /// sorted by path. Artwork shared by several albums stays while one of them does.
+    pub fn unused_artwork(&self, library: &Library) -> Vec<PathBuf> {
+        let album_ids = self.album_ids(library);
+        self.deduplicate_artwork();
+        self.artwork
+            .unused(|id| album_ids.contains(id) || self.overrides.contains(id))
+    }
+
+    /// Delete the artwork listed by [`AlbumService::unused_artwork`] and return the
+    /// files that were removed.
+    pub fn evict_unused_artwork(&self, library: &Library) -> Vec<PathBuf> {
+        let album_ids = self.album_ids(library);
+        self.deduplicate_artwork();
+        self.announce_artwork_changes(|| {
+            self.artwork
+                .evict(|id| album_ids.contains(id) || self.overrides.contains(id))
+        })
+    }
+
+    fn album_ids(&self, library: &Library) -> HashSet<String> {
+        self.album_editions(library.get_tracks())
+            .into_iter()
+            .map(|edition| edition.id)
+            .collect()
+    }
+
+    /// Tracks of an album, or of one edition when the album was split.
+    pub fn album_tracks(&self, library: &Library, album_id: &str) -> Vec<Track> {
+        self.album_editions(library.get_tracks())
+            .into_iter()
+            .find(|edition| edition.id == album_id)
+            .map(|edition| edition.tracks)
+            .unwrap_or_default()
+    }
+
+    /// Group tracks by album, splitting albums into editions when disambiguation is
+    /// enabled or forced by a manual override of the unsplit album identifier.
+    fn album_editions(&self, tracks: Vec<Track>) -> Vec<AlbumEdition> {
+        let mut albums: HashMap<String, Vec<Track>> = HashMap::new();
+        for track in tracks {
+            if let Some(album_id) = listed_album_id(&track) {
+                albums.entry(album_id).or_default().push(track);
+            }
+        }
+
+        albums
+            .into_iter()
+            .flat_map(
+                |(album_id, tracks)| match self.overrides.disambiguation(&album_id) {
+                    AlbumDisambiguation::Auto if self.disambiguation => {
+                        split_editions(&album_id, tracks, false)
+                    }
+                    AlbumDisambiguation::Split => split_editions(&album_id, tracks, true),
+                    _ => vec![AlbumEdition {
+                        id: album_id,
+                        label: None,
+                        tracks,
+                    }],
+                },
+            )
+            .collect()
+    }
+
     /// Export manual album overrides as JSON or YAML.
     pub fn export_overrides(&self, format: AlbumExportFormat) -> Result<String> {
         self.overrides.export(format)
@@ -292,74 +834,133 @@ impl AlbumService {
     }
 
     /// Search albums using the library data, optionally filtering by query
+    #[allow(dead_code)]
     pub async fn search_albums(&self, library: &Library, query: Option<&str>) -> Vec<AlbumSummary> {
-        let query = query
+        let search = AlbumSearch {
+            query: query.map(str::to_string),
+            ..AlbumSearch::default()
+        };
+        self.search_albums_page(library, &search).await.albums
+    }
+
+    /// The album of the MusicBrainz release `mbid`, in any spelling of it
+    pub async fn album_by_musicbrainz_id(
+        &self,
+        library: &Library,
+        mbid: &str,
+    ) -> Option<AlbumSummary> {
+        let search = AlbumSearch {
+            musicbrainz_release_id: Some(normalize_mbid(mbid)?),
+            ..AlbumSearch::default()
+        };
+        self.search_albums_page(library, &search)
+            .await
+            .albums
+            .into_iter()
+            .next()
+    }
+
+    /// Search albums and return one sorted page of them.
+    ///
+    /// Artwork is only resolved for the albums on the returned page.
+    pub async fn search_albums_page(&self, library: &Library, search: &AlbumSearch) -> AlbumPage {
+        let query = search
+            .query
+            .as_deref()
             .map(|value| value.trim().to_lowercase())
             .filter(|value| !value.is_empty());
 
-        let mut aggregates: HashMap<String, AlbumAggregate> = HashMap::new();
-
-        for track in library.get_tracks() {
-            let album_title = match track.metadata.album.as_deref() {
-                Some(title) if !title.trim().is_empty() => title.trim(),
-                _ => continue,
+        let mut aggregates: Vec<AlbumAggregate> = Vec::new();
+
+        for edition in self.album_editions(library.get_tracks()) {
+            let mut entry = AlbumAggregate {
+                id: edition.id,
+                edition: edition.label,
+                title: String::new(),
+                artist_credits: artist_credits(&edition.tracks),
+                has_album_artist: edition.tracks.iter().any(|track| {
+                    track
+                        .metadata
+                        .album_artist
+                        .as_deref()
+                        .is_some_and(|artist| !artist.trim().is_empty())
+                }),
+                track_count: 0,
+                sample_track: None,
+                year: None,
+                last_added: None,
+                musicbrainz_release_id: None,
             };
 
-            let artist = track
-                .metadata
-                .artist
-                .as_ref()
-                .map(|s| s.trim())
-                .filter(|s| !s.is_empty());
-            let album_id = album_identifier(artist, album_title);
-
-            let entry = aggregates
-                .entry(album_id.clone())
-                .or_insert_with(|| AlbumAggregate {
-                    id: album_id.clone(),
-                    title: album_title.to_string(),
-                    primary_artist: artist.map(|s| s.to_string()),
-                    artists: HashSet::new(),
-                    track_count: 0,
-                    sample_track: None,
-                });
-
-            if entry.primary_artist.is_none() {
-                entry.primary_artist = artist.map(|s| s.to_string());
-            }
-
-            if let Some(artist_value) = artist {
-                entry.artists.insert(artist_value.to_string());
-            }
+            for track in edition.tracks {
+                if entry.title.is_empty() {
+                    if let Some(album_title) = track.metadata.album.as_deref() {
+                        entry.title = album_title.trim().to_string();
+                    }
+                }
 
-            entry.track_count += 1;
+                entry.track_count += 1;
+                if entry.musicbrainz_release_id.is_none() {
+                    entry.musicbrainz_release_id = track.metadata.musicbrainz.release_id.clone();
+                }
 
-            if entry.sample_track.is_none() {
-                entry.sample_track = Some(track.clone());
+                if let Some(year) = track.metadata.year {
+                    entry.year = Some(entry.year.map_or(year, |current| current.min(year)));
+                }
+                let modified = track.metadata.last_modified;
+                entry.last_added = Some(
+                    entry
+                        .last_added
+                        .map_or(modified, |current| current.max(modified)),
+                );
+
+                if entry.sample_track.is_none() {
+                    entry.sample_track = Some(track);
+                }
             }
+
+            aggregates.push(entry);
         }
 
-        let mut summaries: Vec<AlbumSummary> = Vec::new();
+        let mut candidates: Vec<AlbumCandidate> = Vec::new();
 
-        for aggregate in aggregates.into_values() {
+        for aggregate in aggregates {
+            let override_record = self.overrides.get(&aggregate.id);
+            let musicbrainz_release_id = aggregate.musicbrainz_release_id.clone().or_else(|| {
+                override_record
+                    .as_ref()
+                    .and_then(|record| record.musicbrainz_release_id.clone())
+            });
+            if search.musicbrainz_release_id.is_some()
+                && musicbrainz_release_id != search.musicbrainz_release_id
+            {
+                continue;
+            }
             if let Some(ref q) = query {
                 let matches_title = aggregate.title.to_lowercase().contains(q);
                 let matches_artist = aggregate
-                    .artists
+                    .artist_credits
                     .iter()
-                    .any(|artist| artist.to_lowercase().contains(q));
+                    .any(|credit| credit.artist.to_lowercase().contains(q));
 
                 if !matches_title && !matches_artist {
                     continue;
                 }
             }
 
-            let mut artists: Vec<String> = aggregate.artists.into_iter().collect();
+            let mut artists: Vec<String> = aggregate
+                .artist_credits
+                .iter()
+                .map(|credit| credit.artist.clone())
+                .collect();
             artists.sort();
 
-            let override_record = self.overrides.get(&aggregate.id);
             let mut title = aggregate.title.clone();
-            let mut primary_artist = aggregate.primary_artist.clone();
+            let mut primary_artist = album_primary_artist(
+                &aggregate.artist_credits,
+                aggregate.has_album_artist,
+                self.various_artists_threshold,
+            );
             let mut metadata = None;
             let mut manual_artwork_path: Option<PathBuf> = None;
 
@@ -376,13 +977,64 @@ impl AlbumService {
                 manual_artwork_path = record.artwork_path.as_ref().map(PathBuf::from);
             }
 
-            let artwork_path = if let Some(path) = manual_artwork_path {
+            candidates.push(AlbumCandidate {
+                summary: AlbumSummary {
+                    id: aggregate.id,
+                    title,
+                    edition: aggregate.edition,
+                    primary_artist,
+                    artists,
+                    artist_credits: aggregate.artist_credits,
+                    track_count: aggregate.track_count,
+                    artwork_path: None,
+                    metadata,
+                    is_manual: override_record.is_some(),
+                    musicbrainz_release_id,
+                },
+                manual_artwork_path,
+                sample_track: aggregate.sample_track,
+                year: aggregate.year,
+                last_added: aggregate.last_added,
+            });
+        }
+
+        let collator = library.collator();
+        sort_album_candidates(&mut candidates, search.sort, &collator);
+
+        let total = candidates.len();
+        let sections = match search.sort {
+            AlbumSort::Title => collator.sections(
+                candidates
+                    .iter()
+                    .map(|candidate| candidate.summary.title.as_str()),
+            ),
+            AlbumSort::Artist => collator.sections(candidates.iter().map(|candidate| {
+                candidate
+                    .summary
+                    .primary_artist
+                    .as_deref()
+                    .unwrap_or_default()
+            })),
+            _ => Vec::new(),
+        };
+        let limit = search.limit.unwrap_or(usize::MAX);
+        let mut albums = Vec::new();
+
+        for candidate in candidates.into_iter().skip(search.offset).take(limit) {
+            let AlbumCandidate {
+                mut summary,
+                manual_artwork_path,
+                sample_track,
+                ..
+            } = candidate;
+
+            summary.artwork_path = if let Some(path) = manual_artwork_path {
                 Some(path)
-            } else if let Some(sample_track) = aggregate.sample_track.as_ref() {
+            } else if let Some(sample_track) = sample_track.as_ref() {
                 self.ensure_artwork(
-                    &aggregate.id,
-                    primary_artist.as_deref(),
-                    &title,
+                    &summary.id,
+                    summary.primary_artist.as_deref(),
+                    &summary.title,
                     sample_track,
                 )
                 .await
@@ -390,20 +1042,14 @@ impl AlbumService {
                 None
             };
 
-            summaries.push(AlbumSummary {
-                id: aggregate.id,
-                title,
-                primary_artist,
-                artists,
-                track_count: aggregate.track_count,
-                artwork_path,
-                metadata,
-                is_manual: override_record.is_some(),
-            });
+            albums.push(summary);
         }
 
-        summaries.sort_by(|a, b| a.title.to_lowercase().cmp(&b.title.to_lowercase()));
-        summaries
+        AlbumPage {
+            total,
+            albums,
+            sections,
+        }
     }
 
     /// Manually override album metadata and refresh artwork/remote metadata when possible.
@@ -422,9 +1068,12 @@ impl AlbumService {
             search_album,
             search_artist,
             refresh_artwork,
+            disambiguation,
+            musicbrainz_release_id,
         } = update;
 
         if !refresh_artwork
+            && disambiguation.is_none()
             && title.is_none()
             && primary_artist.is_none()
             && search_album.is_none()
@@ -456,6 +1105,10 @@ impl AlbumService {
             record.search_artist = normalize_override_string(value);
         }
 
+        if let Some(value) = disambiguation {
+            record.disambiguation = value;
+        }
+
         record.updated_at = Utc::now();
 
         if let Some(api_key) = &self.lastfm_api_key {
@@ -464,10 +1117,19 @@ impl AlbumService {
                 .clone()
                 .or_else(|| record.primary_artist.clone());
             let lookup_album = record.search_album.clone().or_else(|| record.title.clone());
+            let names = lookup_artist.as_deref().zip(lookup_album.as_deref());
+            // A release the tracks are tagged with identifies the album better than any
+            // name, one matched earlier is next best
+            let tagged = musicbrainz_release_id.as_deref().and_then(normalize_mbid);
+            let mbid = tagged
+                .clone()
+                .or_else(|| record.musicbrainz_release_id.clone());
 
-            if let (Some(artist), Some(album)) = (lookup_artist.as_deref(), lookup_album.as_deref())
-            {
-                if let Some(info) = self.fetch_lastfm_album_info(api_key, artist, album).await {
+            if mbid.is_some() || names.is_some() {
+                if let Some(info) = self
+                    .fetch_lastfm_album_info(api_key, mbid.as_deref(), names)
+                    .await
+                {
                     if let Some(url) = info.image_url {
                         if refresh_artwork || record.artwork_path.is_none() {
                             if let Some(path) = self.store_artwork_from_url(album_id, &url).await {
@@ -479,6 +1141,10 @@ impl AlbumService {
                     if let Some(metadata) = info.metadata {
                         record.metadata = Some(metadata);
                     }
+
+                    if tagged.is_none() && record.musicbrainz_release_id.is_none() {
+                        record.musicbrainz_release_id = info.musicbrainz_release_id;
+                    }
                 }
             }
         }
@@ -492,14 +1158,191 @@ impl AlbumService {
         self.overrides.set(record)
     }
 
+    /// Apply a metadata edit to every track of an album.
+    ///
+    /// Tags are written file by file; failures are reported per file and do not abort
+    /// the remaining updates. Albums with files in read-only directories are refused
+    /// with a [`ReadOnlyError`](super::ReadOnlyError) before any file is touched. When the edit changes the album identifier, the manual
+    /// override and cached artwork are moved to the new identifier.
+    pub fn edit_album(
+        &self,
+        library: &Library,
+        album_id: &str,
+        update: TrackTagUpdate,
+    ) -> Result<AlbumEditReport> {
+        if update.is_empty() {
+            return Err(anyhow!(
+                "at least one field must be provided when editing album metadata"
+            ));
+        }
+
+        if update.title.is_some() {
+            return Err(anyhow!("track titles cannot be edited in bulk"));
+        }
+
+        let tracks = self.album_tracks(library, album_id);
+        for track in &tracks {
+            library.read_only().check(&track.metadata.file_path)?;
+        }
+        let track_ids: Vec<String> = tracks.into_iter().map(|track| track.id).collect();
+
+        let mut edited_track_id: Option<String> = None;
+        let mut files = Vec::with_capacity(track_ids.len());
+
+        for (track_id, result) in library.update_tracks_tags(&track_ids, &update) {
+            match result {
+                Ok(track) => {
+                    if edited_track_id.is_none() {
+                        edited_track_id = Some(track.id);
+                    }
+                    files.push(AlbumEditFileResult {
+                        track_id,
+                        path: track.metadata.file_path,
+                        error: None,
+                    });
+                }
+                Err(error) => {
+                    warn!("Failed to edit tags for track {}: {}", track_id, error);
+                    let path = library
+                        .get_track(&track_id)
+                        .map(|track| track.metadata.file_path)
+                        .unwrap_or_default();
+                    files.push(AlbumEditFileResult {
+                        track_id,
+                        path,
+                        error: Some(error.to_string()),
+                    });
+                }
+            }
+        }
+
+        // The edit may also move the tracks to another edition
+        let new_album_id = edited_track_id
+            .and_then(|track_id| {
+                self.album_editions(library.get_tracks())
+                    .into_iter()
+                    .find(|edition| edition.tracks.iter().any(|track| track.id == track_id))
+            })
+            .map(|edition| edition.id)
+            .unwrap_or_else(|| album_id.to_string());
+        let override_conflicts = if new_album_id != album_id {
+            self.migrate_album(album_id, &new_album_id)?
+        } else {
+            Vec::new()
+        };
+
+        Ok(AlbumEditReport {
+            previous_album_id: album_id.to_string(),
+            album_id: new_album_id,
+            files,
+            override_conflicts,
+        })
+    }
+
+    /// Move the manual override and cached artwork of an album to a new identifier.
+    /// The artwork file itself stays where it is, so overrides keep pointing at it.
+    /// Returns the override fields that conflicted, see
+    /// [`AlbumEditReport::override_conflicts`].
+    fn migrate_album(&self, old_id: &str, new_id: &str) -> Result<Vec<&'static str>> {
+        self.announce_artwork_changes(|| self.artwork.rename(old_id, new_id));
+        self.overrides.migrate(old_id, new_id)
+    }
+
     /// Get the cached artwork path for an album if it exists
+    ///
+    /// Answered from the in-memory index; files deleted behind the service's back are
+    /// dropped with [`AlbumService::forget_artwork`] or a rescan.
     pub fn cached_artwork_path(&self, album_id: &str) -> Option<PathBuf> {
-        let path = self.cache_dir.join(format!("{}.jpg", album_id));
+        self.artwork.path(album_id)
+    }
+
+    /// Resolve an image for an artist, caching it under `artist_art/`.
+    ///
+    /// An `artist.jpg` in any of the artist's album folders is preferred over Last.fm.
+    /// Failed lookups are remembered for a week so that unknown artists do not trigger
+    /// a remote request on every page view.
+    pub async fn resolve_artist_image(&self, library: &Library, artist: &str) -> Option<PathBuf> {
+        let artist_id = artist_identifier(artist)?;
+        let path = self.artist_cache_dir.join(format!("{}.jpg", artist_id));
         if path.exists() {
-            Some(path)
-        } else {
-            None
+            return Some(path);
+        }
+
+        let miss_marker = self.artist_cache_dir.join(format!("{}.miss", artist_id));
+        if is_recent_miss(&miss_marker) {
+            return None;
         }
+
+        let bytes = match self.find_local_artist_image(library, &artist_id).await {
+            Some(bytes) => Some(bytes),
+            None => self.fetch_lastfm_artist_image(artist).await,
+        };
+
+        let Some(bytes) = bytes else {
+            if let Err(error) = fs::write(&miss_marker, []).await {
+                warn!(
+                    "Failed to record artist image miss at {:?}: {}",
+                    miss_marker, error
+                );
+            }
+            return None;
+        };
+
+        if let Err(error) = fs::write(&path, &bytes).await {
+            warn!("Failed to store artist image at {:?}: {}", path, error);
+            return None;
+        }
+        let _ = fs::remove_file(&miss_marker).await;
+
+        Some(path)
+    }
+
+    async fn find_local_artist_image(&self, library: &Library, artist_id: &str) -> Option<Vec<u8>> {
+        let mut folders: Vec<PathBuf> = library
+            .get_tracks()
+            .into_iter()
+            .filter(|track| {
+                let artist = track
+                    .metadata
+                    .album_artist
+                    .as_deref()
+                    .or(track.metadata.artist.as_deref());
+                artist.and_then(artist_identifier).as_deref() == Some(artist_id)
+            })
+            .filter_map(|track| track.metadata.file_path.parent().map(Path::to_path_buf))
+            .collect();
+        folders.sort();
+        folders.dedup();
+
+        for folder in folders {
+            for name in LOCAL_ARTIST_IMAGE_NAMES {
+                if let Ok(bytes) = fs::read(folder.join(name)).await {
+                    return Some(bytes);
+                }
+            }
+        }
+
+        None
+    }
+
+    async fn fetch_lastfm_artist_image(&self, artist: &str) -> Option<Vec<u8>> {
+        let api_key = self.lastfm_api_key.as_deref()?;
+        let params = [
+            ("method", "artist.getinfo"),
+            ("artist", artist),
+            ("autocorrect", "1"),
+            ("api_key", api_key),
+            ("format", "json"),
+        ];
+
+        let image_url = self
+            .perform_request(&params, |value| {
+                extract_image_url(value.get("artist")?.get("image"))
+            })
+            .await
+            .filter(|url| !url.contains(LAST_FM_PLACEHOLDER_IMAGE))?;
+
+        self.fetch_bytes(&image_url).await
     }
 
     async fn ensure_artwork(
@@ -522,9 +1365,15 @@ impl AlbumService {
             .map(|value| value.to_string())
             .or_else(|| track.metadata.artist.clone())?;
 
+        let mbid = track.metadata.musicbrainz.release_id.clone().or_else(|| {
+            self.overrides
+                .get(album_id)
+                .and_then(|record| record.musicbrainz_release_id)
+        });
         let image_url = self
             .fetch_lastfm_image_url(
                 &api_key,
+                mbid.as_deref(),
                 &artist,
                 album_title,
                 track.metadata.title.as_deref(),
@@ -534,48 +1383,83 @@ impl AlbumService {
         self.store_artwork_from_url(album_id, &image_url).await
     }
 
-    async fn store_artwork_from_url(&self, album_id: &str, image_url: &str) -> Option<PathBuf> {
+    /// Download an image, from any URL curl understands, and store it as the artwork
+    /// of an album.
+    pub async fn store_artwork_from_url(&self, album_id: &str, image_url: &str) -> Option<PathBuf> {
         let bytes = self.fetch_bytes(image_url).await?;
-        let path = self.cache_dir.join(format!("{}.jpg", album_id));
-
-        if let Err(error) = fs::write(&path, &bytes).await {
-            warn!("Failed to store album artwork at {:?}: {}", path, error);
-            return None;
+        match self.store_artwork(album_id, &bytes) {
+            Ok(path) => Some(path),
+            Err(error) => {
+                warn!("Failed to store artwork of album {}: {}", album_id, error);
+                None
+            }
         }
+    }
 
-        Some(path)
+    /// Store `bytes` as the artwork of an album, replacing any it had. A manual
+    /// override pointing at the old image follows it to the new one.
+    pub fn store_artwork(&self, album_id: &str, bytes: &[u8]) -> Result<PathBuf> {
+        let path = self.announce_artwork_changes(|| self.artwork.store(album_id, bytes))?;
+        if let Some(mut record) = self
+            .overrides
+            .get(album_id)
+            .filter(|record| record.artwork_path.is_some())
+        {
+            record.artwork_path = Some(path.to_string_lossy().to_string());
+            self.overrides.set(record)?;
+        }
+        Ok(path)
     }
 
+    /// Album info from Last.fm, looked up by the MusicBrainz release `mbid` when
+    /// given and by artist and album name when that finds nothing.
     async fn fetch_lastfm_album_info(
         &self,
         api_key: &str,
-        artist: &str,
-        album: &str,
+        mbid: Option<&str>,
+        names: Option<(&str, &str)>,
     ) -> Option<LastfmAlbumInfo> {
-        let params = [
-            ("method", "album.getinfo"),
-            ("artist", artist),
-            ("album", album),
-            ("api_key", api_key),
-            ("format", "json"),
-        ];
-
-        let value = self.fetch_lastfm_value(&params).await?;
-        let album_value = value.get("album")?;
+        let mut value = None;
+        if let Some(mbid) = mbid {
+            let params = [
+                ("method", "album.getinfo"),
+                ("mbid", mbid),
+                ("api_key", api_key),
+                ("format", "json"),
+            ];
+            value = self.fetch_lastfm_value(&params).await;
+        }
+        if value.is_none() {
+            let (artist, album) = names?;
+            let params = [
+                ("method", "album.getinfo"),
+                ("artist", artist),
+                ("album", album),
+                ("api_key", api_key),
+                ("format", "json"),
+            ];
+            value = self.fetch_lastfm_value(&params).await;
+        }
+        let album_value = value?.get("album")?.clone();
 
-        let metadata = AlbumMetadata::from_lastfm(album_value);
+        let metadata = AlbumMetadata::from_lastfm(&album_value);
         let image_url = extract_image_url(album_value.get("image"));
+        let musicbrainz_release_id = album_value
+            .get("mbid")
+            .and_then(|value| value.as_str())
+            .and_then(normalize_mbid);
 
         Some(LastfmAlbumInfo {
             image_url,
             metadata: Some(metadata),
+            musicbrainz_release_id,
         })
     }
 
     async fn fetch_lastfm_value(&self, params: &[(&str, &str)]) -> Option<Value> {
         let params: Vec<(&str, &str)> = params.iter().map(|(k, v)| (*k, *v)).collect();
         let query = serde_urlencoded::to_string(&params).ok()?;
-        let url = format!("{}?{}", LAST_FM_ENDPOINT, query);
+        let url = format!("{}?{}", self.lastfm_endpoint, query);
 
         let bytes = self.fetch_bytes(&url).await?;
         let value = serde_json::from_slice::<Value>(&bytes).ok()?;
@@ -590,10 +1474,28 @@ impl AlbumService {
     async fn fetch_lastfm_image_url(
         &self,
         api_key: &str,
+        mbid: Option<&str>,
         artist: &str,
         album: &str,
         track_title: Option<&str>,
     ) -> Option<String> {
+        if let Some(mbid) = mbid {
+            let params = [
+                ("method", "album.getinfo"),
+                ("mbid", mbid),
+                ("api_key", api_key),
+                ("format", "json"),
+            ];
+            if let Some(url) = self
+                .perform_request(&params, |value| {
+                    extract_image_url(value.get("album")?.get("image"))
+                })
+                .await
+            {
+                return Some(url);
+            }
+        }
+
         let mut params = vec![
             ("method", "album.getinfo"),
             ("artist", artist),
@@ -689,6 +1591,8 @@ impl AlbumService {
 struct LastfmAlbumInfo {
     image_url: Option<String>,
     metadata: Option<AlbumMetadata>,
+    /// Release Last.fm knows the album as
+    musicbrainz_release_id: Option<String>,
 }
 
 fn normalize_override_string(value: String) -> Option<String> {
@@ -727,6 +1631,29 @@ fn build_search_term(artist: &str, album: &str, track_title: Option<&str>) -> St
     }
 }
 
+/// Identifier of the album a track is listed under, ignoring blank album and artist
+/// tags. Editions are not told apart, see [`AlbumService::album_tracks`].
+fn listed_album_id(track: &Track) -> Option<String> {
+    let album = track
+        .metadata
+        .album
+        .as_deref()
+        .map(str::trim)
+        .filter(|album| !album.is_empty())?;
+    Some(track_album_identifier(&track.metadata, album))
+}
+
+/// URL serving the artwork of an album. It names the stored image, so it changes
+/// whenever the image does and clients holding the old one know to reload it.
+pub fn album_artwork_url(album_id: &str, artwork_path: &Path) -> String {
+    let version = artwork_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy())
+        .unwrap_or_default();
+    let version: String = version.chars().take(16).collect();
+    format!("/api/library/albums/{}/artwork?v={}", album_id, version)
+}
+
 pub fn album_identifier(artist: Option<&str>, album: &str) -> String {
     use sha2::{Digest, Sha256};
 
@@ -744,6 +1671,38 @@ pub fn album_identifier(artist: Option<&str>, album: &str) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// Identifier of the album titled `album` that a track with `metadata` is on: from its
+/// MusicBrainz release when tagged with one, which tells releases apart regardless of
+/// how their titles and artists are spelled, otherwise from its artist and title.
+pub fn track_album_identifier(metadata: &TrackMetadata, album: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    match metadata.musicbrainz.release_id.as_deref() {
+        Some(release_id) => format!(
+            "{:x}",
+            Sha256::digest(format!("musicbrainz:{}", release_id).as_bytes())
+        ),
+        None => album_identifier(metadata.artist.as_deref(), album),
+    }
+}
+
+/// Stable identifier for an artist, shared by every credit that names the same primary
+/// artist ("Artist feat. X" and "Artist" map to the same identifier).
+pub fn artist_identifier(artist: &str) -> Option<String> {
+    use sha2::{Digest, Sha256};
+
+    let normalized = normalize_primary_artist(Some(artist))?;
+    Some(format!("{:x}", Sha256::digest(normalized.as_bytes())))
+}
+
+fn is_recent_miss(marker: &Path) -> bool {
+    std::fs::metadata(marker)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|age| age < ARTIST_IMAGE_MISS_TTL)
+}
+
 fn normalize_album_title(album: &str) -> String {
     let lowered = album.to_lowercase();
     let stripped = strip_bracketed(&lowered);
@@ -766,8 +1725,8 @@ fn normalize_album_title(album: &str) -> String {
     let has_soundtrack = raw_tokens
         .iter()
         .any(|token| matches!(*token, "soundtrack" | "soundtracks" | "ost"));
-    let has_original = raw_tokens.iter().any(|token| *token == "original");
-    let has_score = raw_tokens.iter().any(|token| *token == "score");
+    let has_original = raw_tokens.contains(&"original");
+    let has_score = raw_tokens.contains(&"score");
 
     let mut tokens: Vec<&str> = Vec::new();
 
@@ -799,7 +1758,7 @@ fn normalize_album_title(album: &str) -> String {
     }
 }
 
-fn strip_bracketed(input: &str) -> String {
+pub(super) fn strip_bracketed(input: &str) -> String {
     let mut result = String::with_capacity(input.len());
     let mut depth = 0usize;
 
@@ -809,9 +1768,7 @@ fn strip_bracketed(input: &str) -> String {
                 depth += 1;
             }
             ')' | ']' | '}' | '>' => {
-                if depth > 0 {
-                    depth -= 1;
-                }
+                depth = depth.saturating_sub(1);
             }
             _ => {
                 if depth == 0 {
@@ -824,6 +1781,38 @@ fn strip_bracketed(input: &str) -> String {
     result
 }
 
+fn sort_album_candidates(candidates: &mut [AlbumCandidate], sort: AlbumSort, collator: &Collator) {
+    candidates.sort_by_cached_key(|candidate| {
+        (
+            collator.sort_key(&candidate.summary.title),
+            candidate.summary.id.clone(),
+        )
+    });
+
+    // Stable sorts, so albums tied on the chosen key stay in title order
+    match sort {
+        AlbumSort::Title => {}
+        AlbumSort::Artist => candidates.sort_by_cached_key(|candidate| {
+            candidate
+                .summary
+                .primary_artist
+                .as_deref()
+                .map(|artist| collator.sort_key(artist))
+                // Albums without an artist come last
+                .map_or((1, None), |artist| (0, Some(artist)))
+        }),
+        AlbumSort::TrackCount => {
+            candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.summary.track_count))
+        }
+        AlbumSort::Year => {
+            candidates.sort_by_key(|candidate| (candidate.year.is_none(), candidate.year))
+        }
+        AlbumSort::RecentlyAdded => {
+            candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.last_added))
+        }
+    }
+}
+
 fn is_year_token(token: &str) -> bool {
     if token.len() != 4 {
         return false;
@@ -835,7 +1824,7 @@ fn is_year_token(token: &str) -> bool {
     }
 }
 
-fn normalize_primary_artist(artist: Option<&str>) -> Option<String> {
+pub(super) fn normalize_primary_artist(artist: Option<&str>) -> Option<String> {
     let artist = artist?.trim();
     if artist.is_empty() {
         return None;
@@ -909,7 +1898,7 @@ fn truncate_at_secondary_markers(value: &str) -> &str {
 
     SECONDARY_MARKERS
         .iter()
-        .filter_map(|marker| value.find(marker).map(|index| index))
+        .filter_map(|marker| value.find(marker))
         .min()
         .map(|index| &value[..index])
         .unwrap_or(value)
@@ -0,0 +1,331 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use tracing::warn;
+
+use crate::utils::ensure_directory;
+
+/// Subdirectory of the artwork cache holding each distinct image once
+const OBJECTS_DIR: &str = "objects";
+/// Album id -> image hash, next to the objects
+const INDEX_FILE: &str = "index.json";
+
+/// Outcome of moving loose `<album id>.jpg` files into the artwork store
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArtworkDedupReport {
+    /// Loose files moved into the store
+    pub files: usize,
+    /// Files whose image was already stored
+    pub duplicates: usize,
+    /// Size of the duplicates, no longer stored twice
+    pub bytes_saved: u64,
+}
+
+#[derive(Default)]
+struct ArtworkIndex {
+    /// Album id -> hash of its image
+    albums: HashMap<String, String>,
+    /// Hashes with a file under `objects/`
+    objects: HashSet<String>,
+}
+
+/// Album artwork stored by content: every distinct image once under
+/// `objects/<sha256>.jpg`, with `index.json` mapping album ids to their image, so box
+/// sets and reissues sharing a cover share the file.
+///
+/// The index is kept in memory so listings do not stat one file per album.
+#[derive(Clone, Default)]
+pub(super) struct ArtworkStore {
+    cache_dir: PathBuf,
+    index: Arc<Mutex<ArtworkIndex>>,
+    /// Filesystem calls made on the artwork cache, for diagnostics and tests
+    pub(super) fs_calls: Arc<AtomicUsize>,
+}
+
+impl ArtworkStore {
+    /// Open the store in `cache_dir`, reading its index.
+    pub(super) fn new(cache_dir: PathBuf) -> Self {
+        let index_path = cache_dir.join(INDEX_FILE);
+        let albums = match std::fs::read_to_string(&index_path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!("Failed to parse artwork index {:?}: {}", index_path, error);
+                HashMap::new()
+            }),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(error) => {
+                warn!("Failed to read artwork index {:?}: {}", index_path, error);
+                HashMap::new()
+            }
+        };
+
+        Self {
+            cache_dir,
+            index: Arc::new(Mutex::new(ArtworkIndex {
+                albums,
+                objects: HashSet::new(),
+            })),
+            fs_calls: Arc::default(),
+        }
+    }
+
+    fn object_path(&self, hash: &str) -> PathBuf {
+        self.cache_dir
+            .join(OBJECTS_DIR)
+            .join(format!("{}.jpg", hash))
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ArtworkIndex> {
+        self.index.lock().unwrap()
+    }
+
+    fn count_fs_call(&self) {
+        self.fs_calls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Path of the image stored for an album
+    pub(super) fn path(&self, album_id: &str) -> Option<PathBuf> {
+        let index = self.lock();
+        let hash = index.albums.get(album_id)?;
+        Some(self.object_path(hash))
+    }
+
+    /// Store `bytes` as the image of an album, returning where it is kept. An image
+    /// no other album uses any more is deleted.
+    pub(super) fn store(&self, album_id: &str, bytes: &[u8]) -> Result<PathBuf> {
+        let hash = format!("{:x}", Sha256::digest(bytes));
+        let path = self.object_path(&hash);
+
+        let mut index = self.lock();
+        if !index.objects.contains(&hash) {
+            ensure_directory(&self.cache_dir.join(OBJECTS_DIR))?;
+            self.count_fs_call();
+            std::fs::write(&path, bytes)?;
+            index.objects.insert(hash.clone());
+        }
+        if let Some(previous) = index.albums.insert(album_id.to_string(), hash) {
+            self.delete_if_unreferenced(&mut index, &previous);
+        }
+        self.save(&index)?;
+        Ok(path)
+    }
+
+    /// Album id -> hash of its image, to tell which albums a change touched
+    pub(super) fn album_hashes(&self) -> HashMap<String, String> {
+        self.lock().albums.clone()
+    }
+
+    /// Forget the image of an album whose file disappeared, e.g. when serving it
+    /// failed with `NotFound`.
+    pub(super) fn forget(&self, album_id: &str) {
+        self.lock().albums.remove(album_id);
+    }
+
+    /// Move the image of `old_id` to `new_id`, unless that has one already.
+    pub(super) fn rename(&self, old_id: &str, new_id: &str) {
+        let mut index = self.lock();
+        if index.albums.contains_key(new_id) {
+            return;
+        }
+        let Some(hash) = index.albums.remove(old_id) else {
+            return;
+        };
+        index.albums.insert(new_id.to_string(), hash);
+        if let Err(error) = self.save(&index) {
+            warn!("Failed to save the artwork index: {}", error);
+        }
+    }
+
+    /// Images no album in use refers to, sorted by path. Albums sharing an image
+    /// keep it as long as one of them is in use.
+    pub(super) fn unused(&self, in_use: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+        let index = self.lock();
+        let mut unused: Vec<PathBuf> = unused_hashes(&index, &in_use)
+            .iter()
+            .map(|hash| self.object_path(hash))
+            .collect();
+        unused.sort();
+        unused
+    }
+
+    /// Drop the albums not in use and delete the images listed by
+    /// [`ArtworkStore::unused`], returning the files that were removed.
+    pub(super) fn evict(&self, in_use: impl Fn(&str) -> bool) -> Vec<PathBuf> {
+        let mut index = self.lock();
+        let album_count = index.albums.len();
+        let mut evicted = Vec::new();
+        let mut kept = HashSet::new();
+        for hash in unused_hashes(&index, &in_use) {
+            let path = self.object_path(&hash);
+            self.count_fs_call();
+            match std::fs::remove_file(&path) {
+                Ok(()) => {}
+                Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+                Err(error) => {
+                    warn!("Failed to evict album artwork {:?}: {}", path, error);
+                    kept.insert(hash);
+                    continue;
+                }
+            }
+            index.objects.remove(&hash);
+            evicted.push(path);
+        }
+        index
+            .albums
+            .retain(|album_id, hash| in_use(album_id) || kept.contains(hash));
+        if index.albums.len() != album_count {
+            if let Err(error) = self.save(&index) {
+                warn!("Failed to save the artwork index: {}", error);
+            }
+        }
+        evicted.sort();
+        evicted
+    }
+
+    /// Re-read the cache directory: loose `<album id>.jpg` files, left by older
+    /// versions or copied in by hand, are moved into the store, and albums whose image
+    /// was deleted behind the store's back are dropped. Returns what was saved and the
+    /// `(old, new)` paths of the moved files.
+    pub(super) fn rescan(&self) -> (ArtworkDedupReport, Vec<(PathBuf, PathBuf)>) {
+        let mut index = self.lock();
+        let previous = index.albums.clone();
+        index.objects = self.list_objects();
+
+        let mut report = ArtworkDedupReport::default();
+        let mut moved = Vec::new();
+        for (album_id, path) in self.list_loose_files() {
+            match self.adopt(&mut index, &path) {
+                Ok((hash, duplicate_bytes)) => {
+                    report.files += 1;
+                    if let Some(bytes) = duplicate_bytes {
+                        report.duplicates += 1;
+                        report.bytes_saved += bytes;
+                    }
+                    moved.push((path, self.object_path(&hash)));
+                    index.albums.insert(album_id, hash);
+                }
+                Err(error) => warn!("Failed to move album artwork {:?}: {}", path, error),
+            }
+        }
+
+        let ArtworkIndex { albums, objects } = &mut *index;
+        albums.retain(|_, hash| objects.contains(hash));
+        if index.albums != previous {
+            if let Err(error) = self.save(&index) {
+                warn!("Failed to save the artwork index: {}", error);
+            }
+        }
+        (report, moved)
+    }
+
+    /// Move a loose file into the store, returning its hash and, when the image was
+    /// stored already, the size of the duplicate that was removed.
+    fn adopt(&self, index: &mut ArtworkIndex, path: &Path) -> Result<(String, Option<u64>)> {
+        self.count_fs_call();
+        let bytes = std::fs::read(path)?;
+        let hash = format!("{:x}", Sha256::digest(&bytes));
+
+        self.count_fs_call();
+        if index.objects.contains(&hash) {
+            std::fs::remove_file(path)?;
+            return Ok((hash, Some(bytes.len() as u64)));
+        }
+        ensure_directory(&self.cache_dir.join(OBJECTS_DIR))?;
+        std::fs::rename(path, self.object_path(&hash))?;
+        index.objects.insert(hash.clone());
+        Ok((hash, None))
+    }
+
+    fn list_objects(&self) -> HashSet<String> {
+        let objects_dir = self.cache_dir.join(OBJECTS_DIR);
+        self.count_fs_call();
+        match std::fs::read_dir(&objects_dir) {
+            Ok(entries) => jpg_files(entries).map(|(hash, _)| hash).collect(),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => HashSet::new(),
+            Err(error) => {
+                warn!(
+                    "Failed to list artwork directory {:?}: {}",
+                    objects_dir, error
+                );
+                HashSet::new()
+            }
+        }
+    }
+
+    /// `(album id, path)` of the `.jpg` files directly in the cache directory
+    fn list_loose_files(&self) -> Vec<(String, PathBuf)> {
+        self.count_fs_call();
+        match std::fs::read_dir(&self.cache_dir) {
+            Ok(entries) => {
+                let mut files: Vec<_> = jpg_files(entries).collect();
+                files.sort();
+                files
+            }
+            Err(error) => {
+                warn!(
+                    "Failed to list artwork cache directory {:?}: {}",
+                    self.cache_dir, error
+                );
+                Vec::new()
+            }
+        }
+    }
+
+    /// Delete the image `hash` when no album refers to it any more.
+    fn delete_if_unreferenced(&self, index: &mut ArtworkIndex, hash: &str) {
+        if index.albums.values().any(|other| other == hash) {
+            return;
+        }
+        let path = self.object_path(hash);
+        self.count_fs_call();
+        match std::fs::remove_file(&path) {
+            Ok(()) => {
+                index.objects.remove(hash);
+            }
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                index.objects.remove(hash);
+            }
+            Err(error) => warn!("Failed to delete album artwork {:?}: {}", path, error),
+        }
+    }
+
+    fn save(&self, index: &ArtworkIndex) -> Result<()> {
+        let sorted: BTreeMap<&String, &String> = index.albums.iter().collect();
+        let content = serde_json::to_string_pretty(&sorted)?;
+        ensure_directory(&self.cache_dir)?;
+        self.count_fs_call();
+        std::fs::write(self.cache_dir.join(INDEX_FILE), content)?;
+        Ok(())
+    }
+}
+
+/// Stored images no album in use refers to, files no album refers to included
+fn unused_hashes(index: &ArtworkIndex, in_use: &impl Fn(&str) -> bool) -> Vec<String> {
+    let used: HashSet<&String> = index
+        .albums
+        .iter()
+        .filter(|(album_id, _)| in_use(album_id))
+        .map(|(_, hash)| hash)
+        .collect();
+    index
+        .objects
+        .iter()
+        .filter(|hash| !used.contains(hash))
+        .cloned()
+        .collect()
+}
+
+/// `(file stem, path)` of the `.jpg` files among `entries`
+fn jpg_files(entries: std::fs::ReadDir) -> impl Iterator<Item = (String, PathBuf)> {
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "jpg"))
+        .filter_map(|path| {
+            let stem = path.file_stem()?.to_string_lossy().to_string();
+            Some((stem, path))
+        })
+}
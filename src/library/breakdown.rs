@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::{Collator, GenreIndex, Track};
+
+/// Tracks sharing an artist or genre
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct NameCount {
+    #[schema(example = "Boards of Canada")]
+    pub name: String,
+    #[schema(example = 4)]
+    pub track_count: usize,
+}
+
+/// Tracks released in a decade
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct DecadeCount {
+    /// First year of the decade
+    #[schema(example = 1990)]
+    pub decade: i32,
+    #[schema(example = 7)]
+    pub track_count: usize,
+}
+
+/// Composition of a set of tracks: how long they play and how they spread over
+/// artists, genres and decades.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TrackBreakdown {
+    pub track_count: usize,
+    /// Seconds, of the tracks with a known duration
+    pub total_duration: u64,
+    /// Seconds, over the tracks with a known duration
+    pub average_duration: Option<u64>,
+    /// Most tracks first, ties in collation order; tracks without an artist are left out
+    pub artists: Vec<NameCount>,
+    /// By canonical genre name, ordered as `artists`
+    pub genres: Vec<NameCount>,
+    /// Oldest decade first; tracks without a year are left out
+    pub decades: Vec<DecadeCount>,
+}
+
+impl TrackBreakdown {
+    /// Break `tracks` down, naming genres as `genres` lists them
+    pub fn build<'a>(
+        tracks: impl IntoIterator<Item = &'a Track>,
+        genres: &GenreIndex,
+        collator: &Collator,
+    ) -> Self {
+        let mut breakdown = Self::default();
+        let mut timed = 0u64;
+        let mut artists: HashMap<String, usize> = HashMap::new();
+        let mut genre_counts: HashMap<String, usize> = HashMap::new();
+        let mut decades: HashMap<i32, usize> = HashMap::new();
+
+        for track in tracks {
+            let metadata = &track.metadata;
+            breakdown.track_count += 1;
+            if let Some(duration) = metadata.duration {
+                breakdown.total_duration += duration;
+                timed += 1;
+            }
+            if let Some(artist) = non_empty(&metadata.artist) {
+                *artists.entry(artist.to_string()).or_default() += 1;
+            }
+            if let Some(genre) = non_empty(&metadata.genre) {
+                let name = genres.canonical(genre).unwrap_or(genre);
+                *genre_counts.entry(name.to_string()).or_default() += 1;
+            }
+            if let Some(year) = metadata.year {
+                *decades.entry(year.div_euclid(10) * 10).or_default() += 1;
+            }
+        }
+
+        breakdown.average_duration = (timed > 0).then(|| breakdown.total_duration / timed);
+        breakdown.artists = ranked(artists, collator);
+        breakdown.genres = ranked(genre_counts, collator);
+        breakdown.decades = decades
+            .into_iter()
+            .map(|(decade, track_count)| DecadeCount {
+                decade,
+                track_count,
+            })
+            .collect();
+        breakdown.decades.sort_by_key(|decade| decade.decade);
+        breakdown
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+fn ranked(counts: HashMap<String, usize>, collator: &Collator) -> Vec<NameCount> {
+    let mut counts: Vec<NameCount> = counts
+        .into_iter()
+        .map(|(name, track_count)| NameCount { name, track_count })
+        .collect();
+    counts.sort_by_cached_key(|count| {
+        (
+            std::cmp::Reverse(count.track_count),
+            collator.sort_key(&count.name),
+        )
+    });
+    counts
+}
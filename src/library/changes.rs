@@ -0,0 +1,144 @@
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+
+use super::Track;
+
+/// Most track ids listed in a delta; larger deltas only carry counts
+pub const MAX_DELTA_TRACK_IDS: usize = 100;
+/// Changes kept to answer [`ChangeLog::since`]
+const CHANGE_LOG_LIMIT: usize = 256;
+
+/// Tracks added, removed and updated by one or more changes of the library
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LibraryDelta {
+    pub added: usize,
+    pub removed: usize,
+    pub updated: usize,
+    /// Affected tracks, unless more than [`MAX_DELTA_TRACK_IDS`] changed
+    pub track_ids: Option<Vec<String>>,
+}
+
+impl LibraryDelta {
+    /// A delta listing the tracks it affects
+    pub fn new(added: Vec<String>, removed: Vec<String>, updated: Vec<String>) -> Self {
+        let mut delta = Self::counts(added.len(), removed.len(), updated.len());
+        if delta.total() <= MAX_DELTA_TRACK_IDS {
+            delta.track_ids = Some(added.into_iter().chain(removed).chain(updated).collect());
+        }
+        delta
+    }
+
+    pub fn added(track_ids: Vec<String>) -> Self {
+        Self::new(track_ids, Vec::new(), Vec::new())
+    }
+
+    pub fn removed(track_ids: Vec<String>) -> Self {
+        Self::new(Vec::new(), track_ids, Vec::new())
+    }
+
+    pub fn updated(track_ids: Vec<String>) -> Self {
+        Self::new(Vec::new(), Vec::new(), track_ids)
+    }
+
+    /// A delta without track ids, e.g. for a full scan that gives every track a new id
+    pub fn counts(added: usize, removed: usize, updated: usize) -> Self {
+        Self {
+            added,
+            removed,
+            updated,
+            track_ids: None,
+        }
+    }
+
+    pub fn total(&self) -> usize {
+        self.added + self.removed + self.updated
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total() == 0
+    }
+
+    /// Add `other` to this delta. Tracks changed twice are listed once.
+    fn merge(&mut self, other: &LibraryDelta) {
+        self.added += other.added;
+        self.removed += other.removed;
+        self.updated += other.updated;
+        self.track_ids = match (self.track_ids.take(), &other.track_ids) {
+            (Some(mut track_ids), Some(other_ids)) => {
+                for track_id in other_ids {
+                    if !track_ids.contains(track_id) {
+                        track_ids.push(track_id.clone());
+                    }
+                }
+                (track_ids.len() <= MAX_DELTA_TRACK_IDS).then_some(track_ids)
+            }
+            _ => None,
+        };
+    }
+}
+
+/// Numbered log of the latest library changes
+#[derive(Debug, Default)]
+pub(super) struct ChangeLog {
+    /// Sequence number of the latest change; 0 before the first one
+    sequence: u64,
+    /// `(sequence, delta)` of the latest changes, oldest first
+    entries: VecDeque<(u64, LibraryDelta)>,
+    /// Latest state of the tracks changed while a scan runs, by path; `None` for
+    /// removed tracks. Kept only during scans.
+    journal: Option<HashMap<PathBuf, Option<Track>>>,
+}
+
+impl ChangeLog {
+    /// Record a change, returning its sequence number.
+    pub(super) fn record(&mut self, delta: LibraryDelta) -> u64 {
+        self.sequence += 1;
+        if self.entries.len() == CHANGE_LOG_LIMIT {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((self.sequence, delta));
+        self.sequence
+    }
+
+    /// Start keeping the changes a scan must not overwrite.
+    pub(super) fn start_journal(&mut self) {
+        self.journal = Some(HashMap::new());
+    }
+
+    /// Note the new state of the track at `path`, `None` when it was removed, if a
+    /// scan is running.
+    pub(super) fn journal(&mut self, path: PathBuf, track: Option<Track>) {
+        if let Some(journal) = &mut self.journal {
+            journal.insert(path, track);
+        }
+    }
+
+    /// Stop journaling, returning the changes made since [`ChangeLog::start_journal`].
+    pub(super) fn take_journal(&mut self) -> HashMap<PathBuf, Option<Track>> {
+        self.journal.take().unwrap_or_default()
+    }
+
+    pub(super) fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Everything that changed after `sequence`, or `None` when those changes are
+    /// no longer all in the log.
+    pub(super) fn since(&self, sequence: u64) -> Option<LibraryDelta> {
+        let oldest = self
+            .entries
+            .front()
+            .map_or(self.sequence + 1, |entry| entry.0);
+        if sequence + 1 < oldest && sequence < self.sequence {
+            return None;
+        }
+        let mut delta = LibraryDelta {
+            track_ids: Some(Vec::new()),
+            ..Default::default()
+        };
+        for (_, change) in self.entries.iter().filter(|entry| entry.0 > sequence) {
+            delta.merge(change);
+        }
+        Some(delta)
+    }
+}
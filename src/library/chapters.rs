@@ -0,0 +1,356 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use tracing::debug;
+use utoipa::ToSchema;
+
+/// Largest ID3v2 tag or MP4 `moov` box read while looking for chapters.
+const MAX_CHAPTER_SOURCE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// A chapter marker inside a long file such as a DJ mix or an audiobook.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct Chapter {
+    /// Chapter title, "Chapter N" when the file does not name it
+    #[schema(example = "Side B")]
+    pub title: String,
+    /// Offset of the chapter from the start of the file
+    #[schema(example = 1834.5)]
+    pub start_seconds: f64,
+}
+
+/// Build chapters from `CHAPTERxxx` / `CHAPTERxxxNAME` Vorbis comments, as used by
+/// FLAC and Ogg files.
+pub fn parse_vorbis_chapters<'a>(
+    fields: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> Vec<Chapter> {
+    let mut starts: BTreeMap<u32, f64> = BTreeMap::new();
+    let mut names: BTreeMap<u32, String> = BTreeMap::new();
+
+    for (key, value) in fields {
+        let key = key.to_ascii_uppercase();
+        let Some(rest) = key.strip_prefix("CHAPTER") else {
+            continue;
+        };
+        let (number, is_name) = match rest.strip_suffix("NAME") {
+            Some(number) => (number, true),
+            None => (rest, false),
+        };
+        if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+            continue;
+        }
+        let Ok(number) = number.parse::<u32>() else {
+            continue;
+        };
+
+        if is_name {
+            names.insert(number, value.trim().to_string());
+        } else if let Some(start) = parse_timestamp(value) {
+            starts.insert(number, start);
+        }
+    }
+
+    let chapters = starts
+        .into_iter()
+        .map(|(number, start_seconds)| (names.remove(&number), start_seconds))
+        .collect();
+    finish(chapters)
+}
+
+/// Read ID3v2 `CHAP` frames or MP4 Nero (`chpl`) chapters straight from the file.
+///
+/// Vorbis comment chapters are read together with the other tags, see
+/// [`parse_vorbis_chapters`]. Files without chapters, and files that cannot be read,
+/// yield an empty list.
+pub fn read_container_chapters(path: &Path) -> Vec<Chapter> {
+    match read_chapter_source(path) {
+        Ok(Some(ChapterSource::Id3v2(tag))) => parse_id3v2_chapters(&tag),
+        Ok(Some(ChapterSource::Mp4Moov(moov))) => parse_mp4_chapters(&moov),
+        Ok(None) => Vec::new(),
+        Err(error) => {
+            debug!("Could not read chapters from {:?}: {}", path, error);
+            Vec::new()
+        }
+    }
+}
+
+enum ChapterSource {
+    /// A complete ID3v2 tag, header included
+    Id3v2(Vec<u8>),
+    /// Payload of the top-level `moov` box
+    Mp4Moov(Vec<u8>),
+}
+
+fn read_chapter_source(path: &Path) -> std::io::Result<Option<ChapterSource>> {
+    let mut file = File::open(path)?;
+    let mut header = [0u8; 10];
+    if file.read_exact(&mut header).is_err() {
+        return Ok(None);
+    }
+
+    if &header[..3] == b"ID3" {
+        let size = synchsafe(&header[6..10]) as u64;
+        if size > MAX_CHAPTER_SOURCE_BYTES {
+            return Ok(None);
+        }
+        let mut tag = header.to_vec();
+        tag.resize(10 + size as usize, 0);
+        file.read_exact(&mut tag[10..])?;
+        return Ok(Some(ChapterSource::Id3v2(tag)));
+    }
+
+    if &header[4..8] != b"ftyp" {
+        return Ok(None);
+    }
+
+    // Walk the top-level boxes; `moov` often sits after the media data.
+    let file_len = file.metadata()?.len();
+    let mut offset = 0;
+    while offset + 8 <= file_len {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut box_header = [0u8; 16];
+        file.read_exact(&mut box_header[..8])?;
+        let mut size = u32::from_be_bytes(box_header[..4].try_into().unwrap()) as u64;
+        let mut header_len = 8;
+        if size == 1 {
+            file.read_exact(&mut box_header[8..16])?;
+            size = u64::from_be_bytes(box_header[8..16].try_into().unwrap());
+            header_len = 16;
+        } else if size == 0 {
+            size = file_len - offset;
+        }
+        if size < header_len {
+            break;
+        }
+        // A box running past the end of the file is corrupt; its size may even be
+        // crafted to overflow the offset
+        let Some(next) = offset.checked_add(size).filter(|next| *next <= file_len) else {
+            break;
+        };
+
+        if &box_header[4..8] == b"moov" {
+            let payload_len = size - header_len;
+            if payload_len > MAX_CHAPTER_SOURCE_BYTES {
+                return Ok(None);
+            }
+            let mut moov = vec![0u8; payload_len as usize];
+            file.read_exact(&mut moov)?;
+            return Ok(Some(ChapterSource::Mp4Moov(moov)));
+        }
+        offset = next;
+    }
+
+    Ok(None)
+}
+
+/// Parse the `CHAP` frames of an ID3v2.3 or ID3v2.4 tag, header included.
+pub fn parse_id3v2_chapters(tag: &[u8]) -> Vec<Chapter> {
+    if tag.len() < 10 || &tag[..3] != b"ID3" {
+        return Vec::new();
+    }
+    let version = tag[3];
+    if version != 3 && version != 4 {
+        return Vec::new();
+    }
+    let flags = tag[5];
+    let end = (10 + synchsafe(&tag[6..10]) as usize).min(tag.len());
+    let mut body = tag[10..end].to_vec();
+
+    if version == 3 && flags & 0x80 != 0 {
+        body = remove_unsynchronisation(&body);
+    }
+    if flags & 0x40 != 0 {
+        // Skip the extended header
+        let Some(size) = body.get(..4) else {
+            return Vec::new();
+        };
+        let skip = if version == 3 {
+            4 + u32::from_be_bytes(size.try_into().unwrap()) as usize
+        } else {
+            synchsafe(size) as usize
+        };
+        body = body.get(skip..).unwrap_or_default().to_vec();
+    }
+
+    let chapters = id3_frames(&body, version)
+        .filter(|(id, _)| id == b"CHAP")
+        .filter_map(|(_, frame)| parse_chap_frame(frame, version))
+        .collect();
+    finish(chapters)
+}
+
+fn parse_chap_frame(frame: &[u8], version: u8) -> Option<(Option<String>, f64)> {
+    let id_end = frame.iter().position(|b| *b == 0)?;
+    let times = frame.get(id_end + 1..id_end + 17)?;
+    let start_ms = u32::from_be_bytes(times[..4].try_into().unwrap());
+
+    let title = id3_frames(&frame[id_end + 17..], version)
+        .find(|(id, _)| id == b"TIT2")
+        .and_then(|(_, sub_frame)| decode_id3_text(sub_frame));
+
+    Some((title, f64::from(start_ms) / 1000.0))
+}
+
+/// Iterate `(frame id, frame body)` pairs of an ID3v2 frame sequence.
+fn id3_frames(mut data: &[u8], version: u8) -> impl Iterator<Item = ([u8; 4], &[u8])> {
+    std::iter::from_fn(move || {
+        if data.len() < 10 || data[0] == 0 {
+            return None;
+        }
+        let id: [u8; 4] = data[..4].try_into().unwrap();
+        let size = if version == 4 {
+            synchsafe(&data[4..8])
+        } else {
+            u32::from_be_bytes(data[4..8].try_into().unwrap())
+        } as usize;
+        let body = data.get(10..10 + size)?;
+        data = &data[10 + size..];
+        Some((id, body))
+    })
+}
+
+fn decode_id3_text(frame: &[u8]) -> Option<String> {
+    let (encoding, text) = frame.split_first()?;
+    let text = match encoding {
+        0 => text.iter().map(|b| *b as char).collect(),
+        1 => decode_utf16(text, None),
+        2 => decode_utf16(text, Some(false)),
+        3 => String::from_utf8_lossy(text).to_string(),
+        _ => return None,
+    };
+    let text = text.trim_end_matches('\0').trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Decode UTF-16 text, reading the byte order from a BOM unless `little_endian` is given.
+fn decode_utf16(mut bytes: &[u8], little_endian: Option<bool>) -> String {
+    let little_endian = match little_endian {
+        Some(little_endian) => little_endian,
+        None => match bytes {
+            [0xFF, 0xFE, rest @ ..] => {
+                bytes = rest;
+                true
+            }
+            [0xFE, 0xFF, rest @ ..] => {
+                bytes = rest;
+                false
+            }
+            _ => false,
+        },
+    };
+
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| {
+            if little_endian {
+                u16::from_le_bytes([pair[0], pair[1]])
+            } else {
+                u16::from_be_bytes([pair[0], pair[1]])
+            }
+        })
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Parse Nero chapters from the payload of an MP4 `moov` box (`moov/udta/chpl`).
+pub fn parse_mp4_chapters(moov: &[u8]) -> Vec<Chapter> {
+    let Some(chpl) = find_mp4_box(moov, b"udta").and_then(|udta| find_mp4_box(udta, b"chpl"))
+    else {
+        return Vec::new();
+    };
+    let Some((version, rest)) = chpl.split_first() else {
+        return Vec::new();
+    };
+    // Flags, plus a reserved word in version 1
+    let skip = if *version == 0 { 3 } else { 7 };
+    let Some((count, mut entries)) = rest.get(skip..).and_then(|rest| rest.split_first()) else {
+        return Vec::new();
+    };
+
+    let mut chapters = Vec::new();
+    for _ in 0..*count {
+        let Some(header) = entries.get(..9) else {
+            break;
+        };
+        // Start time in 100 ns units
+        let start = u64::from_be_bytes(header[..8].try_into().unwrap());
+        let title_len = header[8] as usize;
+        let Some(title) = entries.get(9..9 + title_len) else {
+            break;
+        };
+        let title = String::from_utf8_lossy(title).trim().to_string();
+        chapters.push((
+            (!title.is_empty()).then_some(title),
+            start as f64 / 10_000_000.0,
+        ));
+        entries = &entries[9 + title_len..];
+    }
+    finish(chapters)
+}
+
+fn find_mp4_box<'a>(mut data: &'a [u8], name: &[u8; 4]) -> Option<&'a [u8]> {
+    while data.len() >= 8 {
+        let size = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+        let size = if size == 0 { data.len() } else { size };
+        if size < 8 || size > data.len() {
+            return None;
+        }
+        if &data[4..8] == name {
+            return Some(&data[8..size]);
+        }
+        data = &data[size..];
+    }
+    None
+}
+
+/// Sort chapters by start time and name untitled ones after their position.
+fn finish(mut chapters: Vec<(Option<String>, f64)>) -> Vec<Chapter> {
+    chapters.sort_by(|a, b| a.1.total_cmp(&b.1));
+    chapters
+        .into_iter()
+        .enumerate()
+        .map(|(index, (title, start_seconds))| Chapter {
+            title: title
+                .filter(|title| !title.is_empty())
+                .unwrap_or_else(|| format!("Chapter {}", index + 1)),
+            start_seconds,
+        })
+        .collect()
+}
+
+/// Parse a `HH:MM:SS.mmm` timestamp; hours and minutes may be omitted.
+fn parse_timestamp(value: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    let parts: Vec<&str> = value.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+    for part in parts {
+        let part: f64 = part.trim().parse().ok()?;
+        if !part.is_finite() || part < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + part;
+    }
+    Some(seconds)
+}
+
+fn synchsafe(bytes: &[u8]) -> u32 {
+    bytes
+        .iter()
+        .take(4)
+        .fold(0, |size, byte| (size << 7) | u32::from(byte & 0x7F))
+}
+
+fn remove_unsynchronisation(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len());
+    let mut previous = 0u8;
+    for &byte in data {
+        if !(previous == 0xFF && byte == 0x00) {
+            output.push(byte);
+        }
+        previous = byte;
+    }
+    output
+}
@@ -0,0 +1,280 @@
+use std::cmp::Ordering;
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+use utoipa::ToSchema;
+
+use super::suggest::fold_char;
+use super::Track;
+
+/// Section of names that do not start with a letter of the locale's alphabet
+pub const OTHER_SECTION: &str = "#";
+
+/// Weight of a run of spaces and punctuation, below every letter and digit
+const SEPARATOR_WEIGHT: u32 = 1;
+const DIGIT_WEIGHT: u32 = 100;
+const LETTER_WEIGHT: u32 = 1_000;
+/// Letters a locale sorts after "z", such as the Swedish "å", come after this
+const EXTRA_LETTER_WEIGHT: u32 = LETTER_WEIGHT + 100;
+/// Other scripts, such as CJK, sort after the Latin alphabet by code point
+const OTHER_SCRIPT_WEIGHT: u32 = 100_000;
+
+/// Letters sorted as letters of their own, in alphabet order, with the letters
+/// sorted the same as each of them
+type Tailoring = &'static [(char, &'static [char])];
+
+const DANISH_NORWEGIAN: Tailoring = &[('æ', &['ä']), ('ø', &['ö']), ('å', &[])];
+const SWEDISH_FINNISH: Tailoring = &[('å', &[]), ('ä', &['æ']), ('ö', &['ø'])];
+
+/// Locales with their own alphabets, by language subtag
+const LOCALES: &[(&str, Tailoring)] = &[
+    ("da", DANISH_NORWEGIAN),
+    ("nb", DANISH_NORWEGIAN),
+    ("nn", DANISH_NORWEGIAN),
+    ("no", DANISH_NORWEGIAN),
+    ("sv", SWEDISH_FINNISH),
+    ("fi", SWEDISH_FINNISH),
+];
+/// Languages sorting accented letters with their base letters, as the root order does
+const ROOT_LANGUAGES: &[&str] = &["en", "de", "fr", "it", "nl", "pt", "ga", "is", "und"];
+
+/// Locale-aware ordering of artist, album and track names.
+///
+/// Letters with diacritics sort with their base letter ("Édith" next to "Edith"),
+/// case and accents only break ties, and spaces and punctuation separate words.
+/// Locales with extra letters tailor the alphabet: Swedish sorts "å", "ä" and "ö"
+/// after "z", Danish and Norwegian "æ", "ø" and "å", and Spanish "ñ" after "n".
+/// Digits come before letters and other scripts, such as CJK, after them by code
+/// point. Unknown locales use the root order.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Collator {
+    tailoring: Tailoring,
+    /// Whether "ñ" is a letter of its own, as in Spanish
+    spanish_n: bool,
+}
+
+/// Key comparing names in collation order; see [`Collator::sort_key`]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SortKey {
+    /// Letters, digits and word breaks
+    primary: Vec<u32>,
+    /// Accents, compared when the letters are the same
+    secondary: Vec<u32>,
+    /// The name itself, so case differences and equal keys order stably
+    tertiary: String,
+}
+
+/// Ordering of track listings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TrackSort {
+    /// Alphabetically by title, or file name for untitled tracks
+    #[default]
+    Title,
+    /// Alphabetically by artist, then album and track number
+    Artist,
+    /// Alphabetically by album, then track number
+    Album,
+}
+
+/// A run of consecutive names starting with the same letter in a sorted listing
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Section {
+    /// Uppercase letter, or `#` for names not starting with a letter
+    #[schema(example = "A")]
+    pub label: String,
+    /// Position of the first name of the section in the listing
+    #[schema(example = 0)]
+    pub offset: usize,
+    /// Names in the section
+    #[schema(example = 12)]
+    pub count: usize,
+}
+
+impl Collator {
+    /// Collator for a BCP 47 locale such as `sv-SE` or `de`; `None` or an unknown
+    /// language uses the root order.
+    pub fn for_locale(locale: Option<&str>) -> Self {
+        let Some(locale) = locale.map(str::trim).filter(|locale| !locale.is_empty()) else {
+            return Self::default();
+        };
+        let language = locale
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_lowercase();
+
+        if language == "es" {
+            return Self {
+                spanish_n: true,
+                ..Self::default()
+            };
+        }
+        if let Some((_, tailoring)) = LOCALES.iter().find(|(code, _)| *code == language) {
+            return Self {
+                tailoring,
+                ..Self::default()
+            };
+        }
+        if !ROOT_LANGUAGES.contains(&language.as_str()) {
+            warn!("Unknown sort locale {:?}, using the root order", locale);
+        }
+        Self::default()
+    }
+
+    /// Position of `ch` in the alphabet after "z", if the locale tailors it
+    fn extra_letter(&self, ch: char) -> Option<(u32, char)> {
+        if self.spanish_n && ch == 'ñ' {
+            // Between "n" and "o"
+            return Some((letter_weight('n') * 10 + 5, 'ñ'));
+        }
+        self.tailoring
+            .iter()
+            .enumerate()
+            .find(|(_, (letter, equivalents))| *letter == ch || equivalents.contains(&ch))
+            .map(|(position, (letter, _))| ((EXTRA_LETTER_WEIGHT + position as u32) * 10, *letter))
+    }
+
+    /// Key ordering `text` by this collation
+    pub fn sort_key(&self, text: &str) -> SortKey {
+        let mut primary = Vec::with_capacity(text.len());
+        let mut secondary = Vec::with_capacity(text.len());
+        for ch in text.chars().flat_map(char::to_lowercase) {
+            if !ch.is_alphanumeric() {
+                if primary.last().is_some_and(|last| *last != SEPARATOR_WEIGHT) {
+                    primary.push(SEPARATOR_WEIGHT);
+                    secondary.push(0);
+                }
+                continue;
+            }
+            if let Some((weight, _)) = self.extra_letter(ch) {
+                primary.push(weight);
+                secondary.push(0);
+            } else if ch.is_ascii_lowercase() {
+                primary.push(letter_weight(ch) * 10);
+                secondary.push(0);
+            } else if let Some(base) = fold_char(ch) {
+                for base in base.chars() {
+                    primary.push(letter_weight(base) * 10);
+                    secondary.push(ch as u32);
+                }
+            } else if let Some(digit) = ch.to_digit(10) {
+                primary.push(DIGIT_WEIGHT + digit);
+                secondary.push(0);
+            } else {
+                primary.push(OTHER_SCRIPT_WEIGHT + ch as u32);
+                secondary.push(0);
+            }
+        }
+        if primary.last() == Some(&SEPARATOR_WEIGHT) {
+            primary.pop();
+            secondary.pop();
+        }
+        SortKey {
+            primary,
+            secondary,
+            tertiary: text.to_string(),
+        }
+    }
+
+    /// Compare two names by this collation
+    #[allow(dead_code)]
+    pub fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.sort_key(a).cmp(&self.sort_key(b))
+    }
+
+    /// Sort `items` by the collation of the name `name` gives each of them
+    pub fn sort_by_name<T>(&self, items: &mut [T], name: impl Fn(&T) -> &str) {
+        items.sort_by_cached_key(|item| self.sort_key(name(item)));
+    }
+
+    /// Sort `tracks` by `sort`; tracks missing the sorted tag come last
+    pub fn sort_tracks(&self, tracks: &mut [Track], sort: TrackSort) {
+        let key = |value: &Option<String>| match value {
+            Some(value) => (false, self.sort_key(value)),
+            None => (true, self.sort_key("")),
+        };
+        tracks.sort_by_cached_key(|track| {
+            let metadata = &track.metadata;
+            let title = self.sort_key(&track_title(track));
+            let position = (metadata.track_number.is_none(), metadata.track_number);
+            match sort {
+                TrackSort::Title => (key(&None), key(&None), position, title),
+                TrackSort::Artist => (key(&metadata.artist), key(&metadata.album), position, title),
+                TrackSort::Album => (key(&metadata.album), key(&None), position, title),
+            }
+        });
+    }
+
+    /// Section `text` is listed under: its first letter, uppercase and without
+    /// diacritics unless the locale tailors it, or [`OTHER_SECTION`].
+    pub fn section(&self, text: &str) -> String {
+        let Some(first) = text
+            .chars()
+            .flat_map(char::to_lowercase)
+            .find(|ch| ch.is_alphanumeric())
+        else {
+            return OTHER_SECTION.to_string();
+        };
+        let letter = if let Some((_, letter)) = self.extra_letter(first) {
+            Some(letter)
+        } else if first.is_ascii_lowercase() {
+            Some(first)
+        } else {
+            fold_char(first).and_then(|base| base.chars().next())
+        };
+        match letter {
+            Some(letter) => letter.to_uppercase().collect(),
+            None => OTHER_SECTION.to_string(),
+        }
+    }
+
+    /// Sections of a listing sorted by this collation. Names in other scripts sort
+    /// after Z, so they may form a second `#` section after the one for digits.
+    pub fn sections<'a>(&self, names: impl IntoIterator<Item = &'a str>) -> Vec<Section> {
+        let mut sections: Vec<Section> = Vec::new();
+        for (offset, name) in names.into_iter().enumerate() {
+            let label = self.section(name);
+            match sections.last_mut() {
+                Some(section) if section.label == label => section.count += 1,
+                _ => sections.push(Section {
+                    label,
+                    offset,
+                    count: 1,
+                }),
+            }
+        }
+        sections
+    }
+
+    /// Sections of tracks sorted with [`Collator::sort_tracks`]; tracks missing the
+    /// sorted tag are listed under [`OTHER_SECTION`].
+    pub fn track_sections(&self, tracks: &[Track], sort: TrackSort) -> Vec<Section> {
+        let names: Vec<String> = tracks
+            .iter()
+            .map(|track| match sort {
+                TrackSort::Title => track_title(track),
+                TrackSort::Artist => track.metadata.artist.clone().unwrap_or_default(),
+                TrackSort::Album => track.metadata.album.clone().unwrap_or_default(),
+            })
+            .collect();
+        self.sections(names.iter().map(String::as_str))
+    }
+}
+
+/// Title a track is listed under: its title tag, or its file name
+pub fn track_title(track: &Track) -> String {
+    match &track.metadata.title {
+        Some(title) => title.clone(),
+        None => track
+            .metadata
+            .file_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    }
+}
+
+fn letter_weight(letter: char) -> u32 {
+    LETTER_WEIGHT + (letter as u32 - 'a' as u32)
+}
@@ -0,0 +1,109 @@
+use std::collections::{BTreeSet, HashMap};
+
+use super::albums::track_album_identifier;
+use super::Track;
+
+/// Track numbers above this are treated as tagging mistakes rather than real positions.
+const MAX_TRACK_NUMBER: u32 = 999;
+
+/// An album with track numbers that are not in the library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncompleteAlbum {
+    pub album_id: String,
+    pub title: String,
+    pub primary_artist: Option<String>,
+    /// Tracks of the album in the library
+    pub track_count: usize,
+    /// Highest TRACKTOTAL of the album's tracks
+    pub track_total: Option<u32>,
+    /// Numbers from 1 up to the track total (or the highest track number) with no track
+    pub missing_track_numbers: Vec<u32>,
+}
+
+#[derive(Default)]
+struct AlbumNumbering {
+    title: String,
+    primary_artist: Option<String>,
+    track_count: usize,
+    track_total: Option<u32>,
+    numbers: BTreeSet<u32>,
+}
+
+/// Find albums whose track numbers have gaps, or whose track total exceeds the tracks
+/// present. Albums are grouped like the album listing; albums without any track
+/// numbers are skipped. Sorted by album title.
+pub fn find_incomplete_albums(tracks: &[Track]) -> Vec<IncompleteAlbum> {
+    let mut albums: HashMap<String, AlbumNumbering> = HashMap::new();
+
+    for track in tracks {
+        let metadata = &track.metadata;
+        let Some(title) = metadata
+            .album
+            .as_deref()
+            .map(str::trim)
+            .filter(|title| !title.is_empty())
+        else {
+            continue;
+        };
+        let artist = metadata
+            .artist
+            .as_deref()
+            .map(str::trim)
+            .filter(|artist| !artist.is_empty());
+
+        let album = albums
+            .entry(track_album_identifier(metadata, title))
+            .or_insert_with(|| AlbumNumbering {
+                title: title.to_string(),
+                ..Default::default()
+            });
+        if album.primary_artist.is_none() {
+            album.primary_artist = artist.map(str::to_string);
+        }
+        album.track_count += 1;
+
+        if let Some(total) = metadata
+            .track_total
+            .filter(|total| (1..=MAX_TRACK_NUMBER).contains(total))
+        {
+            album.track_total = album.track_total.max(Some(total));
+        }
+        if let Some(number) = metadata
+            .track_number
+            .filter(|number| (1..=MAX_TRACK_NUMBER).contains(number))
+        {
+            album.numbers.insert(number);
+        }
+    }
+
+    let mut incomplete: Vec<IncompleteAlbum> = albums
+        .into_iter()
+        .filter_map(|(album_id, album)| {
+            let highest = *album.numbers.last()?;
+            let expected = album.track_total.unwrap_or(0).max(highest);
+            let missing_track_numbers: Vec<u32> = (1..=expected)
+                .filter(|number| !album.numbers.contains(number))
+                .collect();
+            if missing_track_numbers.is_empty() {
+                return None;
+            }
+
+            Some(IncompleteAlbum {
+                album_id,
+                title: album.title,
+                primary_artist: album.primary_artist,
+                track_count: album.track_count,
+                track_total: album.track_total,
+                missing_track_numbers,
+            })
+        })
+        .collect();
+
+    incomplete.sort_by(|a, b| {
+        a.title
+            .to_lowercase()
+            .cmp(&b.title.to_lowercase())
+            .then_with(|| a.album_id.cmp(&b.album_id))
+    });
+    incomplete
+}
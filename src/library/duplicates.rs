@@ -0,0 +1,281 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use utoipa::ToSchema;
+
+use super::albums::normalize_primary_artist;
+use super::matching::{normalize_title, DURATION_TOLERANCE_SECS};
+use super::{Track, TrackMetadata};
+
+/// Effective bitrates closer than this fraction count as equal when picking a keeper,
+/// so embedded artwork or tag padding does not decide between two copies of one encode.
+pub const BITRATE_TOLERANCE: f64 = 0.05;
+
+/// What makes one copy of a duplicated track worth keeping over another
+/// (`[library.duplicates]`)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DuplicatePreferences {
+    /// Container formats (file extensions) from most to least preferred; formats not
+    /// listed rank last
+    pub formats: Vec<String>,
+    /// Prefer the copy with the higher effective bitrate
+    pub prefer_higher_bitrate: bool,
+    /// Prefer the copy with more complete tags
+    pub prefer_complete_tags: bool,
+}
+
+impl Default for DuplicatePreferences {
+    fn default() -> Self {
+        Self {
+            formats: ["flac", "wav", "m4a", "ogg", "mp3"]
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
+            prefer_higher_bitrate: true,
+            prefer_complete_tags: true,
+        }
+    }
+}
+
+/// Tracks that look like copies of the same recording: same normalized title and
+/// primary artist, durations within [`DURATION_TOLERANCE_SECS`] of each other
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct DuplicateGroup {
+    /// Identifier derived from the shared title, artist and duration
+    #[schema(example = "3a7bd3e2360a3d29eea436fcfb7e44c735d117c42d1c1835420b6b9942dd4f1b")]
+    pub id: String,
+    #[schema(example = "Paranoid Android")]
+    pub title: String,
+    #[schema(example = "Radiohead")]
+    pub artist: Option<String>,
+    /// Tracks of the group, ordered by file path
+    pub track_ids: Vec<String>,
+}
+
+/// Find tracks that look like copies of the same recording. Tracks without a title
+/// are skipped. Groups are sorted by artist, then title.
+pub fn find_duplicate_groups(tracks: &[Track]) -> Vec<DuplicateGroup> {
+    let mut candidates: HashMap<(String, Option<String>), Vec<&Track>> = HashMap::new();
+    for track in tracks {
+        let Some(title) = track
+            .metadata
+            .title
+            .as_deref()
+            .map(normalize_title)
+            .filter(|title| !title.is_empty())
+        else {
+            continue;
+        };
+        let artist = normalize_primary_artist(track.metadata.artist.as_deref())
+            .or_else(|| normalize_primary_artist(track.metadata.album_artist.as_deref()));
+        candidates.entry((title, artist)).or_default().push(track);
+    }
+
+    let mut groups = Vec::new();
+    for ((title, artist), mut tracks) in candidates {
+        if tracks.len() < 2 {
+            continue;
+        }
+        tracks.sort_by(|a, b| {
+            a.metadata
+                .duration
+                .cmp(&b.metadata.duration)
+                .then_with(|| a.metadata.file_path.cmp(&b.metadata.file_path))
+        });
+
+        // Tracks of unknown length join the first cluster rather than forming their own
+        let unknown = tracks
+            .iter()
+            .take_while(|track| track.metadata.duration.is_none())
+            .count();
+        let mut clusters: Vec<Vec<&Track>> = Vec::new();
+        let mut previous: Option<u64> = None;
+        for track in &tracks[unknown..] {
+            let duration = track.metadata.duration.unwrap_or_default();
+            match previous {
+                Some(last) if duration - last <= DURATION_TOLERANCE_SECS => {
+                    clusters.last_mut().unwrap().push(track)
+                }
+                _ => clusters.push(vec![track]),
+            }
+            previous = Some(duration);
+        }
+        match clusters.first_mut() {
+            Some(first) => first.extend(&tracks[..unknown]),
+            None => clusters.push(tracks[..unknown].to_vec()),
+        }
+
+        for mut cluster in clusters.into_iter().filter(|cluster| cluster.len() > 1) {
+            let shortest = cluster.iter().filter_map(|t| t.metadata.duration).min();
+            cluster.sort_by(|a, b| a.metadata.file_path.cmp(&b.metadata.file_path));
+            let display = cluster[0];
+            groups.push(DuplicateGroup {
+                id: group_identifier(&title, artist.as_deref(), shortest),
+                title: display.metadata.title.clone().unwrap_or_default(),
+                artist: display
+                    .metadata
+                    .artist
+                    .clone()
+                    .or_else(|| display.metadata.album_artist.clone()),
+                track_ids: cluster.iter().map(|track| track.id.clone()).collect(),
+            });
+        }
+    }
+
+    groups.sort_by(|a, b| {
+        a.artist
+            .cmp(&b.artist)
+            .then_with(|| a.title.cmp(&b.title))
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    groups
+}
+
+fn group_identifier(title: &str, artist: Option<&str>, duration: Option<u64>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(title.as_bytes());
+    if let Some(artist) = artist {
+        hasher.update("::");
+        hasher.update(artist.as_bytes());
+    }
+    if let Some(duration) = duration {
+        hasher.update("@");
+        hasher.update(duration.to_string().as_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// How one copy of a duplicated track compares to the others
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct DuplicateCandidate {
+    pub track_id: String,
+    #[schema(example = "/home/user/Music/Radiohead/OK Computer/02 Paranoid Android.flac")]
+    pub path: String,
+    /// Container format, from the file extension
+    #[schema(example = "flac")]
+    pub format: String,
+    /// Duration in seconds
+    #[schema(example = 383)]
+    pub duration: Option<u64>,
+    /// File size in bytes
+    #[schema(example = 41_527_391)]
+    pub file_size: u64,
+    /// File size over duration, in kbit/s
+    #[schema(example = 867)]
+    pub bitrate_kbps: Option<u32>,
+    #[schema(example = 44100)]
+    pub sample_rate: Option<u32>,
+    #[schema(example = 16)]
+    pub bit_depth: Option<u32>,
+    /// Share of the descriptive tags that are set, from 0.0 to 1.0
+    #[schema(example = 0.875)]
+    pub tag_completeness: f32,
+    /// Whether this is the copy recommended to keep
+    pub recommended: bool,
+}
+
+impl DuplicateCandidate {
+    /// Describe `track`, probing its file for the sample rate and bit depth.
+    pub fn measure(track: &Track) -> Self {
+        let metadata = &track.metadata;
+        let source = crate::audio::probe_source_format(&metadata.file_path).ok();
+        Self {
+            track_id: track.id.clone(),
+            path: metadata.file_path.to_string_lossy().to_string(),
+            format: metadata
+                .file_path
+                .extension()
+                .map(|ext| ext.to_string_lossy().to_lowercase())
+                .unwrap_or_default(),
+            duration: metadata.duration,
+            file_size: metadata.file_size,
+            bitrate_kbps: effective_bitrate_kbps(metadata.file_size, metadata.duration),
+            sample_rate: source.as_ref().and_then(|source| source.sample_rate),
+            bit_depth: source.and_then(|source| source.bits_per_sample),
+            tag_completeness: tag_completeness(metadata),
+            recommended: false,
+        }
+    }
+}
+
+/// Average bitrate of a file of `file_size` bytes playing for `duration` seconds,
+/// tags and artwork included
+pub fn effective_bitrate_kbps(file_size: u64, duration: Option<u64>) -> Option<u32> {
+    let duration = duration.filter(|duration| *duration > 0)?;
+    Some((file_size * 8 / duration / 1000) as u32)
+}
+
+/// Share of the descriptive tags (title, artist, album, album artist, track number,
+/// year and genre) that are set
+pub fn tag_completeness(metadata: &TrackMetadata) -> f32 {
+    let text = |value: &Option<String>| {
+        value
+            .as_deref()
+            .is_some_and(|value| !value.trim().is_empty())
+    };
+    let present = [
+        text(&metadata.title),
+        text(&metadata.artist),
+        text(&metadata.album),
+        text(&metadata.album_artist),
+        metadata.track_number.is_some(),
+        metadata.year.is_some(),
+        text(&metadata.genre),
+    ];
+    present.iter().filter(|present| **present).count() as f32 / present.len() as f32
+}
+
+/// Index of the copy to keep: the most preferred format, then the higher effective
+/// bitrate, then the more complete tags, as enabled in `preferences`. Copies that tie
+/// on everything keep their order, so the first of them wins.
+pub fn recommend_keeper(
+    candidates: &[DuplicateCandidate],
+    preferences: &DuplicatePreferences,
+) -> Option<usize> {
+    let format_rank = |candidate: &DuplicateCandidate| {
+        preferences
+            .formats
+            .iter()
+            .position(|format| format.eq_ignore_ascii_case(&candidate.format))
+            .unwrap_or(preferences.formats.len())
+    };
+
+    let better = |a: &DuplicateCandidate, b: &DuplicateCandidate| {
+        format_rank(b)
+            .cmp(&format_rank(a))
+            .then_with(|| {
+                if !preferences.prefer_higher_bitrate {
+                    return Ordering::Equal;
+                }
+                match (a.bitrate_kbps, b.bitrate_kbps) {
+                    (Some(a), Some(b)) => {
+                        let (a, b) = (f64::from(a), f64::from(b));
+                        if (a - b).abs() <= a.max(b) * BITRATE_TOLERANCE {
+                            Ordering::Equal
+                        } else {
+                            a.total_cmp(&b)
+                        }
+                    }
+                    (a, b) => a.is_some().cmp(&b.is_some()),
+                }
+            })
+            .then_with(|| {
+                if preferences.prefer_complete_tags {
+                    a.tag_completeness.total_cmp(&b.tag_completeness)
+                } else {
+                    Ordering::Equal
+                }
+            })
+    };
+
+    let mut keeper: Option<usize> = None;
+    for (index, candidate) in candidates.iter().enumerate() {
+        if keeper.is_none_or(|best| better(candidate, &candidates[best]) == Ordering::Greater) {
+            keeper = Some(index);
+        }
+    }
+    keeper
+}
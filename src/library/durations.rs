@@ -0,0 +1,122 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::audio::{probe_audio, AudioProbe, TechnicalInfo};
+
+/// Reads the duration and technical details of an audio file
+pub trait AudioProber: Send + Sync {
+    fn probe(&self, file_path: &Path) -> Result<AudioProbe>;
+}
+
+/// Probes files with symphonia, decoding the whole file when its container does not
+/// record how long it is
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SymphoniaProber;
+
+impl AudioProber for SymphoniaProber {
+    fn probe(&self, file_path: &Path) -> Result<AudioProbe> {
+        probe_audio(file_path)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedProbe {
+    #[serde(with = "crate::utils::serde_rfc3339")]
+    mtime: DateTime<Utc>,
+    size: u64,
+    duration: Duration,
+    technical: TechnicalInfo,
+}
+
+/// Probes of audio files, kept while a file has the modification time and size it
+/// was probed at, so scans of unchanged files do not read their audio again.
+/// Files that cannot be probed are not remembered and are tried again next time.
+pub struct DurationCache {
+    prober: Arc<dyn AudioProber>,
+    entries: Mutex<HashMap<PathBuf, CachedProbe>>,
+}
+
+impl Default for DurationCache {
+    fn default() -> Self {
+        Self {
+            prober: Arc::new(SymphoniaProber),
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+impl DurationCache {
+    /// Probe files with `prober` from now on
+    #[allow(dead_code)]
+    pub fn set_prober(&mut self, prober: Arc<dyn AudioProber>) {
+        self.prober = prober;
+    }
+
+    /// Probe `file_path`, whose metadata is `file`, unless it was probed as it is now
+    pub fn probe(&self, file_path: &Path, file: &fs::Metadata) -> Result<AudioProbe> {
+        let mtime: DateTime<Utc> = file.modified()?.into();
+        let size = file.len();
+        if let Some(cached) = self
+            .entries
+            .lock()
+            .unwrap()
+            .get(file_path)
+            .filter(|cached| cached.mtime == mtime && cached.size == size)
+        {
+            return Ok(AudioProbe {
+                duration: cached.duration,
+                technical: cached.technical.clone(),
+            });
+        }
+
+        let probe = self.prober.probe(file_path)?;
+        self.entries.lock().unwrap().insert(
+            file_path.to_path_buf(),
+            CachedProbe {
+                mtime,
+                size,
+                duration: probe.duration,
+                technical: probe.technical.clone(),
+            },
+        );
+        Ok(probe)
+    }
+
+    /// Replace the probes held with those saved at `path`. A missing or invalid file
+    /// leaves none.
+    pub fn load(&self, path: &Path) {
+        let entries = match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring invalid duration cache {:?}: {}", path, e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+        *self.entries.lock().unwrap() = entries;
+    }
+
+    /// Forget the probes of files not in `keep`, then save the rest to `path`
+    pub fn save<'a>(&self, path: &Path, keep: impl IntoIterator<Item = &'a Path>) -> Result<()> {
+        let mut entries = self.entries.lock().unwrap();
+        let mut kept = HashMap::new();
+        for file_path in keep {
+            if let Some((file_path, cached)) = entries.remove_entry(file_path) {
+                kept.insert(file_path, cached);
+            }
+        }
+        *entries = kept;
+        fs::write(path, serde_json::to_string(&*entries)?)?;
+        Ok(())
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::Track;
+
+/// Whether an album is split into editions, set per album with a manual override
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AlbumDisambiguation {
+    /// Follow `library.album_disambiguation`
+    #[default]
+    Auto,
+    /// Never split the album
+    Merge,
+    /// Split the album into editions even when disambiguation is disabled or the
+    /// editions share no tracks
+    Split,
+}
+
+impl AlbumDisambiguation {
+    pub fn is_auto(&self) -> bool {
+        *self == Self::Auto
+    }
+}
+
+/// The tracks of one edition of an album
+#[derive(Debug, Clone)]
+pub struct AlbumEdition {
+    /// Album identifier, suffixed with the edition's year and track total when the
+    /// album was split
+    pub id: String,
+    /// Edition shown next to the title, e.g. "1998" or "2005, 16 tracks"; `None`
+    /// when the album was not split
+    pub label: Option<String>,
+    pub tracks: Vec<Track>,
+}
+
+/// Release year and track total shared by the tracks of an edition
+type EditionKey = (Option<i32>, Option<u32>);
+
+/// Split the tracks of one album into editions by release year and track total.
+///
+/// Tracks missing a year or total join the largest edition they agree with, so a few
+/// untagged tracks do not form an edition of their own. Unless `force` is set, the
+/// album is only split when the editions overlap: a title appears in two of them, or
+/// two editions of different years share a track number. This keeps compilations
+/// tagged with each song's original year and discs with their own track totals
+/// together.
+pub fn split_editions(album_id: &str, tracks: Vec<Track>, force: bool) -> Vec<AlbumEdition> {
+    let editions = cluster_tracks(&tracks);
+    if editions.len() < 2 || !(force || editions_overlap(&tracks, &editions)) {
+        return vec![AlbumEdition {
+            id: album_id.to_string(),
+            label: None,
+            tracks,
+        }];
+    }
+
+    let mut tracks: Vec<Option<Track>> = tracks.into_iter().map(Some).collect();
+    editions
+        .into_iter()
+        .map(|(key, indices)| AlbumEdition {
+            id: edition_id(album_id, key),
+            label: Some(edition_label(key)),
+            tracks: indices
+                .into_iter()
+                .filter_map(|index| tracks[index].take())
+                .collect(),
+        })
+        .collect()
+}
+
+/// Group track indices by edition key, see [`split_editions`].
+fn cluster_tracks(tracks: &[Track]) -> BTreeMap<EditionKey, Vec<usize>> {
+    let keys: Vec<EditionKey> = tracks
+        .iter()
+        .map(|track| {
+            (
+                track.metadata.year,
+                track.metadata.track_total.filter(|total| *total > 0),
+            )
+        })
+        .collect();
+    let any_year = keys.iter().any(|(year, _)| year.is_some());
+    let any_total = keys.iter().any(|(_, total)| total.is_some());
+
+    let mut editions: BTreeMap<EditionKey, Vec<usize>> = BTreeMap::new();
+    let mut partial = Vec::new();
+    for (index, (year, total)) in keys.iter().enumerate() {
+        if (year.is_some() || !any_year) && (total.is_some() || !any_total) {
+            editions.entry((*year, *total)).or_default().push(index);
+        } else {
+            partial.push(index);
+        }
+    }
+
+    // Tracks that know their year or total are placed before untagged ones
+    partial.sort_by_key(|index| {
+        let (year, total) = keys[*index];
+        usize::from(year.is_none()) + usize::from(total.is_none())
+    });
+    for index in partial {
+        let (year, total) = keys[index];
+        let target = editions
+            .iter()
+            .rev()
+            .filter(|((edition_year, edition_total), _)| {
+                year.is_none_or(|year| *edition_year == Some(year))
+                    && total.is_none_or(|total| *edition_total == Some(total))
+            })
+            .max_by_key(|(_, members)| members.len())
+            .map(|(key, _)| *key)
+            .unwrap_or((year, total));
+        editions.entry(target).or_default().push(index);
+    }
+
+    editions
+}
+
+fn editions_overlap(tracks: &[Track], editions: &BTreeMap<EditionKey, Vec<usize>>) -> bool {
+    let mut titles: HashMap<String, EditionKey> = HashMap::new();
+    let mut numbers: HashMap<u32, EditionKey> = HashMap::new();
+
+    for (key, indices) in editions {
+        for metadata in indices.iter().map(|index| &tracks[*index].metadata) {
+            if let Some(title) = metadata
+                .title
+                .as_deref()
+                .map(|title| title.trim().to_lowercase())
+                .filter(|title| !title.is_empty())
+            {
+                if titles
+                    .insert(title, *key)
+                    .is_some_and(|other| other != *key)
+                {
+                    return true;
+                }
+            }
+            if let Some(number) = metadata.track_number {
+                if numbers
+                    .insert(number, *key)
+                    .is_some_and(|other| other.0 != key.0)
+                {
+                    return true;
+                }
+            }
+        }
+    }
+
+    false
+}
+
+fn edition_id(album_id: &str, (year, total): EditionKey) -> String {
+    format!("{}-{}-{}", album_id, year.unwrap_or(0), total.unwrap_or(0))
+}
+
+fn edition_label((year, total): EditionKey) -> String {
+    match (year, total) {
+        (Some(year), Some(total)) => format!("{}, {} tracks", year, total),
+        (Some(year), None) => year.to_string(),
+        (None, Some(total)) => format!("{} tracks", total),
+        (None, None) => "Unknown edition".to_string(),
+    }
+}
@@ -0,0 +1,46 @@
+use std::io;
+use std::path::PathBuf;
+
+use super::ReadOnlyError;
+use crate::utils::schema::SchemaError;
+
+/// Why a [`Library`](super::Library) operation failed
+#[derive(Debug, thiserror::Error)]
+pub enum LibraryError {
+    /// The library cache exists but cannot be parsed
+    #[error("The library cache {} is corrupt: {source}", path.display())]
+    CacheCorrupt {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// A music directory exists but its contents cannot be listed
+    #[error("Cannot read the music directory {}: {source}", path.display())]
+    DirectoryUnreadable {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("Track not found: {0}")]
+    TrackNotFound(String),
+    #[error("A library scan is already in progress")]
+    ScanInProgress,
+    /// The scan was cancelled with [`Library::cancel_scan`](super::Library::cancel_scan)
+    #[error("The library scan was cancelled")]
+    ScanCancelled,
+    /// The library cache was written by a newer version
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+    #[error(transparent)]
+    ReadOnly(#[from] ReadOnlyError),
+    /// The tags or sidecar of a file could not be read or written
+    #[error("Cannot update the metadata of {}: {error:#}", path.display())]
+    Metadata { path: PathBuf, error: anyhow::Error },
+    /// A file could not be opened or its tags could not be read
+    #[error("Cannot read {}: {error:#}", path.display())]
+    Unreadable { path: PathBuf, error: anyhow::Error },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
@@ -0,0 +1,142 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Directory names that say nothing about the artist or album of the files in them
+const GENERIC_DIRECTORIES: &[&str] = &[
+    "music",
+    "my music",
+    "audio",
+    "mp3",
+    "flac",
+    "downloads",
+    "unknown",
+    "unknown album",
+    "unknown artist",
+    "various",
+    "various artists",
+    "va",
+    "compilations",
+];
+
+/// Title and artist guessed from the path of a file missing those tags.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FilenameGuess {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+/// Fields of a track guessed from its file path rather than read from tags.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct GuessedFields {
+    #[serde(default)]
+    pub title: bool,
+    #[serde(default)]
+    pub artist: bool,
+}
+
+impl GuessedFields {
+    pub fn is_empty(&self) -> bool {
+        !self.title && !self.artist
+    }
+}
+
+/// Guess the title and artist of a track from common file naming patterns.
+///
+/// Handles "01 - Artist - Title.mp3" and "Artist - Title.mp3", en and em dashes
+/// included, as well as "Artist/Album/03 Title.flac", where the artist is taken from
+/// the directory above the album when the file name starts with a track number.
+/// Underscores count as spaces and trailing `[...]` groups, such as release tags or
+/// video ids, are dropped.
+pub fn guess_from_path(path: &Path) -> FilenameGuess {
+    let Some(stem) = path.file_stem() else {
+        return FilenameGuess::default();
+    };
+    let name = clean_name(&stem.to_string_lossy());
+    let (numbered, rest) = strip_track_number(&name);
+
+    let rest = rest
+        .replace(" \u{2013} ", " - ")
+        .replace(" \u{2014} ", " - ");
+    let parts: Vec<&str> = rest
+        .split(" - ")
+        .map(str::trim)
+        .filter(|part| !part.is_empty() && !part.chars().all(|c| c.is_ascii_digit()))
+        .collect();
+    match parts.as_slice() {
+        [] => FilenameGuess::default(),
+        [title] => FilenameGuess {
+            title: Some(title.to_string()),
+            artist: if numbered {
+                artist_directory(path)
+            } else {
+                None
+            },
+        },
+        [artist, title @ ..] => FilenameGuess {
+            title: Some(title.join(" - ")),
+            artist: Some(artist.to_string()),
+        },
+    }
+}
+
+/// Replace underscores, drop trailing `[...]` groups and collapse whitespace
+fn clean_name(name: &str) -> String {
+    let mut name = name.replace('_', " ");
+    loop {
+        let trimmed = name.trim_end();
+        let Some(open) = trimmed.strip_suffix(']').and_then(|inner| inner.rfind('[')) else {
+            break;
+        };
+        if open == 0 {
+            break;
+        }
+        name.truncate(open);
+    }
+    name.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Split off a leading track number such as "03 ", "3. ", "03 - " or "1-03 ",
+/// returning whether there was one. Numbers that are not zero padded need a
+/// separator other than a space, so titles like "99 Luftballons" stay whole.
+fn strip_track_number(name: &str) -> (bool, &str) {
+    let digits = name.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 || digits > 3 {
+        return (false, name);
+    }
+    let mut rest = &name[digits..];
+    // Disc and track, as in "1-03"
+    if let Some(track) = rest.strip_prefix('-') {
+        let track_digits = track.chars().take_while(char::is_ascii_digit).count();
+        if (1..=3).contains(&track_digits) && track[track_digits..].starts_with(' ') {
+            rest = &track[track_digits..];
+        }
+    }
+
+    let separators = rest
+        .chars()
+        .take_while(|c| matches!(c, ' ' | '.' | '-' | ')'))
+        .count();
+    let separator = &rest[..separators];
+    let title = &rest[separators..];
+    let padded = name.starts_with('0') || rest.len() != name.len() - digits;
+    if title.is_empty() || separator.is_empty() || (separator == " " && !padded) {
+        return (false, name);
+    }
+    (true, title)
+}
+
+/// Name of the directory above the album directory holding `path`, unless either
+/// is a generic name such as "Music"
+fn artist_directory(path: &Path) -> Option<String> {
+    let album_dir = path.parent()?;
+    let artist_dir = album_dir.parent()?;
+    let album = album_dir.file_name()?.to_string_lossy();
+    let artist = clean_name(&artist_dir.file_name()?.to_string_lossy());
+    let generic = |name: &str| GENERIC_DIRECTORIES.contains(&name.trim().to_lowercase().as_str());
+    if artist.is_empty() || generic(&album) || generic(&artist) {
+        return None;
+    }
+    Some(artist)
+}
@@ -0,0 +1,40 @@
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Bytes hashed from each end of a file
+pub const FINGERPRINT_CHUNK: u64 = 64 * 1024;
+
+/// Cheap fingerprint of a file's content: its size and its first and last 64 KiB,
+/// hashed together.
+///
+/// Tag edits rewrite the start of a file and truncation changes its size and end, so
+/// this tells a file whose content changed apart from one that was only touched or
+/// copied, without reading all of it.
+pub fn content_fingerprint(path: &Path) -> Result<String> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+
+    let mut hasher = Sha256::new();
+    hasher.update(size.to_le_bytes());
+
+    let mut buffer = Vec::with_capacity(FINGERPRINT_CHUNK as usize);
+    file.by_ref()
+        .take(FINGERPRINT_CHUNK)
+        .read_to_end(&mut buffer)?;
+    hasher.update(&buffer);
+
+    if size > FINGERPRINT_CHUNK {
+        let tail_start = size
+            .saturating_sub(FINGERPRINT_CHUNK)
+            .max(FINGERPRINT_CHUNK);
+        buffer.clear();
+        file.seek(SeekFrom::Start(tail_start))?;
+        file.take(FINGERPRINT_CHUNK).read_to_end(&mut buffer)?;
+        hasher.update(&buffer);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
@@ -0,0 +1,248 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{fold_words, Track};
+
+/// Spellings of common genres and the name they are listed under. Keys are compared
+/// with [`genre_key`], so "Hip Hop" also covers "hip-hop" and "HipHop".
+pub const BUILTIN_GENRE_ALIASES: &[(&str, &str)] = &[
+    ("Hip-Hop", "Hip-Hop"),
+    ("Hip-Hop/Rap", "Hip-Hop"),
+    ("Rap/Hip-Hop", "Hip-Hop"),
+    ("Hip-Hop & Rap", "Hip-Hop"),
+    ("Rap & Hip-Hop", "Hip-Hop"),
+    ("R&B", "R&B"),
+    ("RnB", "R&B"),
+    ("R'n'B", "R&B"),
+    ("Rhythm & Blues", "R&B"),
+    ("R&B/Soul", "R&B"),
+    ("Drum & Bass", "Drum & Bass"),
+    ("Drum 'n' Bass", "Drum & Bass"),
+    ("DnB", "Drum & Bass"),
+    ("Rock & Roll", "Rock & Roll"),
+    ("Rock 'n' Roll", "Rock & Roll"),
+    ("Electronic", "Electronic"),
+    ("Electronica", "Electronic"),
+    ("Electronica/Dance", "Electronic"),
+    ("Alternative Rock", "Alternative Rock"),
+    ("Alt Rock", "Alternative Rock"),
+    ("Alt-Rock", "Alternative Rock"),
+    ("Lo-Fi", "Lo-Fi"),
+    ("Trip-Hop", "Trip-Hop"),
+    ("Post-Rock", "Post-Rock"),
+    ("Synthpop", "Synthpop"),
+    ("K-Pop", "K-Pop"),
+    ("J-Pop", "J-Pop"),
+    ("Singer-Songwriter", "Singer-Songwriter"),
+    ("Soundtrack", "Soundtrack"),
+    ("Soundtracks", "Soundtrack"),
+    ("OST", "Soundtrack"),
+    ("Original Soundtrack", "Soundtrack"),
+];
+
+/// Key genre spellings are compared by: lowercase, without diacritics, punctuation
+/// or spaces, with "&" read as "and". "Hip-Hop", "hip hop" and "HipHop" share
+/// "hiphop"; "Drum & Bass" and "drum and bass" share "drumandbass".
+pub fn genre_key(genre: &str) -> String {
+    fold_words(&genre.replace('&', " and ")).replace(' ', "")
+}
+
+/// Maps genre spellings to canonical names: the built-in aliases, extended or
+/// overridden by `library.genre_aliases`
+#[derive(Debug, Clone)]
+pub struct GenreNormalizer {
+    /// Genre key -> canonical name
+    aliases: HashMap<String, String>,
+}
+
+impl Default for GenreNormalizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GenreNormalizer {
+    /// A normalizer with the built-in aliases
+    pub fn new() -> Self {
+        let normalizer = Self {
+            aliases: HashMap::new(),
+        };
+        normalizer.with_aliases(
+            BUILTIN_GENRE_ALIASES
+                .iter()
+                .map(|(alias, genre)| (alias.to_string(), genre.to_string())),
+        )
+    }
+
+    /// Add `(alias, canonical name)` pairs, replacing aliases with the same key.
+    /// Every canonical name is also an alias of itself, so its spelling wins over the
+    /// ones found in tags.
+    pub fn with_aliases(mut self, aliases: impl IntoIterator<Item = (String, String)>) -> Self {
+        for (alias, genre) in aliases {
+            let genre = genre.trim();
+            let (alias_key, genre_key) = (genre_key(&alias), genre_key(genre));
+            if alias_key.is_empty() || genre_key.is_empty() {
+                continue;
+            }
+            self.aliases.insert(alias_key, genre.to_string());
+            self.aliases.insert(genre_key, genre.to_string());
+        }
+        self
+    }
+
+    /// Canonical name of an aliased genre; `None` for genres without an alias
+    pub fn alias(&self, genre: &str) -> Option<&str> {
+        self.aliases.get(&genre_key(genre)).map(String::as_str)
+    }
+
+    /// Key a spelling is grouped under: its canonical name's key when aliased
+    fn group_key(&self, genre: &str) -> String {
+        match self.alias(genre) {
+            Some(canonical) => genre_key(canonical),
+            None => genre_key(genre),
+        }
+    }
+}
+
+/// A genre after normalization
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct GenreSummary {
+    /// Canonical name
+    #[schema(example = "Hip-Hop")]
+    pub name: String,
+    #[schema(example = 42)]
+    pub track_count: usize,
+    /// Tag values listed under this genre
+    #[schema(example = json!(["Hip-Hop", "hip hop", "Hip-Hop/Rap"]))]
+    pub spellings: Vec<String>,
+}
+
+/// A genre tag value as found in the files
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct RawGenre {
+    #[schema(example = "hip hop")]
+    pub value: String,
+    #[schema(example = 7)]
+    pub track_count: usize,
+    /// Canonical name the value is listed under
+    #[schema(example = "Hip-Hop")]
+    pub genre: String,
+}
+
+/// Genres of a library, with the raw tag values each one gathers
+#[derive(Debug, Default)]
+pub struct GenreIndex {
+    /// Sorted by name
+    genres: Vec<GenreSummary>,
+    /// Raw value -> (track count, index into `genres`)
+    raw: BTreeMap<String, (usize, usize)>,
+}
+
+impl GenreIndex {
+    /// Group the genre tags of `tracks`. Aliased genres take their canonical name;
+    /// others are listed under their most common spelling.
+    pub fn build<'a>(
+        tracks: impl IntoIterator<Item = &'a Track>,
+        normalizer: &GenreNormalizer,
+    ) -> Self {
+        let mut raw_counts: HashMap<&str, usize> = HashMap::new();
+        for track in tracks {
+            if let Some(genre) = track
+                .metadata
+                .genre
+                .as_deref()
+                .map(str::trim)
+                .filter(|genre| !genre.is_empty())
+            {
+                *raw_counts.entry(genre).or_default() += 1;
+            }
+        }
+
+        let mut groups: HashMap<String, Vec<(&str, usize)>> = HashMap::new();
+        for (value, count) in raw_counts {
+            let key = normalizer.group_key(value);
+            if !key.is_empty() {
+                groups.entry(key).or_default().push((value, count));
+            }
+        }
+
+        let mut genres: Vec<(GenreSummary, Vec<(&str, usize)>)> = groups
+            .into_values()
+            .map(|mut spellings| {
+                // Most common spelling first, ties in alphabetical order
+                spellings.sort_by(|(a, a_count), (b, b_count)| {
+                    b_count.cmp(a_count).then_with(|| a.cmp(b))
+                });
+                let name = normalizer
+                    .alias(spellings[0].0)
+                    .unwrap_or(spellings[0].0)
+                    .to_string();
+                let summary = GenreSummary {
+                    name,
+                    track_count: spellings.iter().map(|(_, count)| count).sum(),
+                    spellings: spellings
+                        .iter()
+                        .map(|(value, _)| value.to_string())
+                        .collect(),
+                };
+                (summary, spellings)
+            })
+            .collect();
+        genres.sort_by(|(a, _), (b, _)| {
+            a.name
+                .to_lowercase()
+                .cmp(&b.name.to_lowercase())
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        let mut index = Self::default();
+        for (position, (summary, spellings)) in genres.into_iter().enumerate() {
+            for (value, count) in spellings {
+                index.raw.insert(value.to_string(), (count, position));
+            }
+            index.genres.push(summary);
+        }
+        index
+    }
+
+    /// Normalized genres, by name
+    pub fn genres(&self) -> &[GenreSummary] {
+        &self.genres
+    }
+
+    /// Genre tag values as found in the files, by value
+    pub fn raw_genres(&self) -> Vec<RawGenre> {
+        self.raw
+            .iter()
+            .map(|(value, (track_count, position))| RawGenre {
+                value: value.clone(),
+                track_count: *track_count,
+                genre: self.genres[*position].name.clone(),
+            })
+            .collect()
+    }
+
+    /// Canonical name of a genre tag value found in the library
+    pub fn canonical(&self, value: &str) -> Option<&str> {
+        self.raw
+            .get(value.trim())
+            .map(|(_, position)| self.genres[*position].name.as_str())
+    }
+}
+
+/// A file whose genre tag differs from its canonical name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct GenreRetagFile {
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub track_id: String,
+    #[schema(value_type = String, example = "/music/track.flac")]
+    pub path: std::path::PathBuf,
+    #[schema(example = "hip hop")]
+    pub from: String,
+    #[schema(example = "Hip-Hop")]
+    pub to: String,
+    /// Why the tag could not be rewritten; `None` when it was, or on a dry run
+    pub error: Option<String>,
+}
@@ -0,0 +1,327 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tokio::task::JoinSet;
+use tracing::{debug, info, warn};
+
+use super::stats::{IntegrityRecord, IntegrityStatus, StatsStore};
+use super::Track;
+
+/// Files verified at the same time. Kept low so verification never competes with the
+/// playback decoder for CPU.
+pub const VERIFY_CONCURRENCY: usize = 2;
+
+/// Decode errors tolerated before a file is considered corrupt. MP3 files commonly
+/// carry a single undecodable frame after their tags.
+const MAX_DECODE_ERRORS: usize = 1;
+
+/// How often progress is reported while verifying.
+const PROGRESS_INTERVAL: usize = 10;
+
+/// Result of verifying a single file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IntegrityCheck {
+    pub status: IntegrityStatus,
+    pub error: Option<String>,
+}
+
+impl IntegrityCheck {
+    fn ok() -> Self {
+        Self {
+            status: IntegrityStatus::Ok,
+            error: None,
+        }
+    }
+
+    fn corrupt(error: impl Into<String>) -> Self {
+        Self {
+            status: IntegrityStatus::Corrupt,
+            error: Some(error.into()),
+        }
+    }
+
+    fn unreadable(error: impl Into<String>) -> Self {
+        Self {
+            status: IntegrityStatus::Unreadable,
+            error: Some(error.into()),
+        }
+    }
+}
+
+/// Decode a file from start to end without producing audio, checking that it is
+/// structurally sound and not truncated.
+pub fn verify_file(path: &Path) -> IntegrityCheck {
+    use symphonia::core::{
+        codecs::DecoderOptions, errors::Error as SymphoniaError, formats::FormatOptions,
+        io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+    };
+
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) => return IntegrityCheck::unreadable(format!("cannot open file: {}", e)),
+    };
+
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = match symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    ) {
+        Ok(probed) => probed,
+        Err(e) => return IntegrityCheck::corrupt(format!("unrecognized container: {}", e)),
+    };
+
+    let mut format = probed.format;
+    let Some(track) = format.default_track() else {
+        return IntegrityCheck::corrupt("no audio track found");
+    };
+    let track_id = track.id;
+    let codec_params = track.codec_params.clone();
+
+    let mut decoder =
+        match symphonia::default::get_codecs().make(&codec_params, &DecoderOptions::default()) {
+            Ok(decoder) => decoder,
+            Err(e) => return IntegrityCheck::unreadable(format!("unsupported codec: {}", e)),
+        };
+
+    let mut packets: usize = 0;
+    let mut decode_errors: usize = 0;
+    let mut frames: u64 = 0;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                break
+            }
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(e) => {
+                return IntegrityCheck::corrupt(format!(
+                    "container error after {} packets: {}",
+                    packets, e
+                ))
+            }
+        };
+
+        if packet.track_id() != track_id {
+            continue;
+        }
+        packets += 1;
+
+        match decoder.decode(&packet) {
+            Ok(decoded) => frames = frames.saturating_add(decoded.frames() as u64),
+            Err(SymphoniaError::DecodeError(e)) => {
+                decode_errors += 1;
+                if decode_errors > MAX_DECODE_ERRORS {
+                    return IntegrityCheck::corrupt(format!(
+                        "decode error at packet {}: {}",
+                        packets, e
+                    ));
+                }
+            }
+            Err(SymphoniaError::ResetRequired) => decoder.reset(),
+            Err(e) => {
+                return IntegrityCheck::corrupt(format!(
+                    "decode failure at packet {}: {}",
+                    packets, e
+                ))
+            }
+        }
+    }
+
+    if packets == 0 || frames == 0 {
+        return IntegrityCheck::corrupt("no decodable audio data");
+    }
+
+    if let Some(expected) = codec_params.n_frames {
+        // Allow for encoder padding and priming frames.
+        let tolerance = (expected / 50).max(4096);
+        if frames.saturating_add(tolerance) < expected {
+            return IntegrityCheck::corrupt(format!(
+                "truncated: decoded {} of {} frames",
+                frames, expected
+            ));
+        }
+    }
+
+    IntegrityCheck::ok()
+}
+
+/// Progress of a verification run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct VerifyProgress {
+    /// started, running, completed or cancelled
+    pub status: String,
+    pub processed: usize,
+    pub total: usize,
+    /// Files skipped because they were verified before and have not changed
+    pub skipped: usize,
+    /// Files found corrupt or unreadable so far
+    pub failed: usize,
+}
+
+/// Background library verification job. Only one run may be active at a time.
+#[derive(Default)]
+pub struct VerificationJob {
+    running: AtomicBool,
+    cancel_requested: AtomicBool,
+}
+
+impl VerificationJob {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a verification run is in progress.
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Ask the running job to stop. Returns false if nothing was running.
+    pub fn cancel(&self) -> bool {
+        if !self.is_running() {
+            return false;
+        }
+        self.cancel_requested.store(true, Ordering::SeqCst);
+        true
+    }
+
+    /// Start verifying `tracks` in the background, reporting progress through
+    /// `on_progress`. Returns false without doing anything if a run is already active.
+    pub fn start<F>(
+        self: &Arc<Self>,
+        tracks: Vec<Track>,
+        stats: Arc<StatsStore>,
+        on_progress: F,
+    ) -> bool
+    where
+        F: FnMut(&VerifyProgress) + Send + 'static,
+    {
+        if self
+            .running
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return false;
+        }
+        self.cancel_requested.store(false, Ordering::SeqCst);
+
+        let job = Arc::clone(self);
+        tokio::spawn(async move {
+            if let Err(e) = job.run(tracks, stats, on_progress).await {
+                warn!("Library verification failed: {}", e);
+            }
+            job.running.store(false, Ordering::SeqCst);
+        });
+
+        true
+    }
+
+    async fn run<F>(
+        &self,
+        tracks: Vec<Track>,
+        stats: Arc<StatsStore>,
+        mut on_progress: F,
+    ) -> Result<()>
+    where
+        F: FnMut(&VerifyProgress),
+    {
+        let mut progress = VerifyProgress {
+            status: "started".to_string(),
+            total: tracks.len(),
+            ..Default::default()
+        };
+        on_progress(&progress);
+        progress.status = "running".to_string();
+
+        let mut pending = tracks.into_iter();
+        let mut in_flight: JoinSet<(PathBuf, DateTime<Utc>, IntegrityCheck)> = JoinSet::new();
+
+        loop {
+            while in_flight.len() < VERIFY_CONCURRENCY
+                && !self.cancel_requested.load(Ordering::SeqCst)
+            {
+                let Some(track) = pending.next() else {
+                    break;
+                };
+
+                let path = track.metadata.file_path.clone();
+                let modified = file_modified(&path).unwrap_or(track.metadata.last_modified);
+
+                let unchanged_ok = stats.integrity(&path).is_some_and(|record| {
+                    record.status == IntegrityStatus::Ok && record.file_modified == modified
+                });
+                if unchanged_ok {
+                    progress.processed += 1;
+                    progress.skipped += 1;
+                    continue;
+                }
+
+                in_flight.spawn_blocking(move || {
+                    let check = verify_file(&path);
+                    (path, modified, check)
+                });
+            }
+
+            let Some(joined) = in_flight.join_next().await else {
+                break;
+            };
+            let (path, modified, check) =
+                joined.map_err(|e| anyhow!("verification task failed: {}", e))?;
+
+            if check.status != IntegrityStatus::Ok {
+                progress.failed += 1;
+                debug!(
+                    "Integrity check failed for {:?}: {}",
+                    path,
+                    check.error.as_deref().unwrap_or("unknown error")
+                );
+            }
+
+            stats.record_integrity(
+                &path,
+                IntegrityRecord {
+                    status: check.status,
+                    error: check.error,
+                    checked_at: Utc::now(),
+                    file_modified: modified,
+                },
+            );
+
+            progress.processed += 1;
+            if progress.processed.is_multiple_of(PROGRESS_INTERVAL) {
+                on_progress(&progress);
+            }
+        }
+
+        stats.save()?;
+
+        progress.status = if self.cancel_requested.load(Ordering::SeqCst) {
+            "cancelled".to_string()
+        } else {
+            "completed".to_string()
+        };
+
+        info!(
+            "Library verification {}: {} of {} files processed, {} skipped, {} failed",
+            progress.status, progress.processed, progress.total, progress.skipped, progress.failed
+        );
+        on_progress(&progress);
+        Ok(())
+    }
+}
+
+fn file_modified(path: &Path) -> Option<DateTime<Utc>> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(DateTime::<Utc>::from(modified))
+}
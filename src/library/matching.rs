@@ -0,0 +1,245 @@
+use serde::Serialize;
+
+use super::albums::{normalize_primary_artist, strip_bracketed};
+use super::Track;
+
+/// Durations closer than this (in seconds) are considered the same recording.
+pub const DURATION_TOLERANCE_SECS: u64 = 3;
+
+/// Matches scoring below this are discarded.
+pub const MIN_MATCH_CONFIDENCE: f32 = 0.6;
+
+/// Matches scoring below this should be reviewed by the user.
+pub const LOW_CONFIDENCE_THRESHOLD: f32 = 0.85;
+
+/// A track described by an external source (e.g. a row in an exported playlist).
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct TrackQuery {
+    pub title: String,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    /// Duration in seconds
+    pub duration: Option<u64>,
+}
+
+/// Library track selected for a query.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TrackMatch {
+    pub track_id: String,
+    /// Confidence between 0.0 and 1.0
+    pub confidence: f32,
+}
+
+impl TrackMatch {
+    /// Whether the match should be reviewed before being trusted.
+    pub fn is_low_confidence(&self) -> bool {
+        self.confidence < LOW_CONFIDENCE_THRESHOLD
+    }
+}
+
+/// Matches external track descriptions against a set of library tracks.
+///
+/// Track names are normalized once up front so that matching many queries against the
+/// same library stays cheap.
+pub struct TrackMatcher {
+    candidates: Vec<Candidate>,
+}
+
+struct Candidate {
+    track_id: String,
+    title: String,
+    artists: Vec<String>,
+    album: Option<String>,
+    duration: Option<u64>,
+}
+
+impl TrackMatcher {
+    pub fn new<'a>(tracks: impl IntoIterator<Item = &'a Track>) -> Self {
+        let candidates = tracks
+            .into_iter()
+            .filter_map(|track| {
+                let title = normalize_title(track.metadata.title.as_deref()?);
+                if title.is_empty() {
+                    return None;
+                }
+
+                Some(Candidate {
+                    track_id: track.id.clone(),
+                    title,
+                    artists: artist_segments(track.metadata.artist.as_deref()),
+                    album: track.metadata.album.as_deref().map(normalize_title),
+                    duration: track.metadata.duration,
+                })
+            })
+            .collect();
+
+        Self { candidates }
+    }
+
+    /// Find the best match for a query, if any scores above [`MIN_MATCH_CONFIDENCE`].
+    pub fn find(&self, query: &TrackQuery) -> Option<TrackMatch> {
+        let title = normalize_title(&query.title);
+        if title.is_empty() {
+            return None;
+        }
+
+        let artists = artist_segments(query.artist.as_deref());
+        let album = query.album.as_deref().map(normalize_title);
+
+        self.candidates
+            .iter()
+            .map(|candidate| {
+                let confidence = score_candidate(
+                    candidate,
+                    &title,
+                    &artists,
+                    album.as_deref(),
+                    query.duration,
+                );
+                (candidate, confidence)
+            })
+            .filter(|(_, confidence)| *confidence >= MIN_MATCH_CONFIDENCE)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(candidate, confidence)| TrackMatch {
+                track_id: candidate.track_id.clone(),
+                confidence,
+            })
+    }
+}
+
+/// Find the library track that best matches `query`.
+///
+/// Tracks whose normalized artist and title match exactly and whose duration is within
+/// [`DURATION_TOLERANCE_SECS`] score 1.0. Otherwise a fuzzy score is computed from title
+/// and artist similarity, weighted down by any duration mismatch.
+#[allow(dead_code)]
+pub fn match_track(query: &TrackQuery, tracks: &[Track]) -> Option<TrackMatch> {
+    TrackMatcher::new(tracks).find(query)
+}
+
+fn score_candidate(
+    candidate: &Candidate,
+    title: &str,
+    artists: &[String],
+    album: Option<&str>,
+    duration: Option<u64>,
+) -> f32 {
+    let title_score = if candidate.title == title {
+        1.0
+    } else {
+        similarity(&candidate.title, title)
+    };
+
+    let artist_score = match (artists.is_empty(), candidate.artists.is_empty()) {
+        (false, false) => {
+            if artists
+                .iter()
+                .any(|artist| candidate.artists.contains(artist))
+            {
+                1.0
+            } else {
+                similarity(&candidate.artists.join(" "), &artists.join(" "))
+            }
+        }
+        // Without artist information on one side, neither confirm nor rule out.
+        _ => 0.5,
+    };
+
+    let duration_factor = match (candidate.duration, duration) {
+        (Some(a), Some(b)) => match a.abs_diff(b) {
+            diff if diff <= DURATION_TOLERANCE_SECS => 1.0,
+            diff if diff <= DURATION_TOLERANCE_SECS * 4 => 0.9,
+            _ => 0.7,
+        },
+        _ => 0.95,
+    };
+
+    if title_score == 1.0 && artist_score == 1.0 {
+        return duration_factor;
+    }
+
+    let mut score = (0.65 * title_score + 0.35 * artist_score) * duration_factor;
+
+    if let (Some(query_album), Some(candidate_album)) = (album, candidate.album.as_deref()) {
+        if query_album == candidate_album {
+            score = (score + 0.05).min(0.99);
+        }
+    }
+
+    score.min(0.99)
+}
+
+fn artist_segments(artist: Option<&str>) -> Vec<String> {
+    normalize_primary_artist(artist)
+        .map(|value| value.split('|').map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Normalize a track title for comparison: case, bracketed notes ("(feat. X)",
+/// "[Remastered]"), trailing " - Remastered 2011" style suffixes and punctuation are
+/// removed.
+pub fn normalize_title(title: &str) -> String {
+    const VERSION_MARKERS: [&str; 9] = [
+        "remaster",
+        "live",
+        "radio edit",
+        "single version",
+        "album version",
+        "mono",
+        "stereo",
+        "bonus track",
+        "edit",
+    ];
+
+    let lowered = title.to_lowercase();
+    let mut stripped = strip_bracketed(&lowered);
+
+    if let Some(index) = stripped.rfind(" - ") {
+        let suffix = &stripped[index + 3..];
+        if VERSION_MARKERS.iter().any(|marker| suffix.contains(marker)) {
+            stripped.truncate(index);
+        }
+    }
+
+    for marker in [" feat. ", " feat ", " ft. ", " featuring "] {
+        if let Some(index) = stripped.find(marker) {
+            stripped.truncate(index);
+        }
+    }
+
+    let sanitized: String = stripped
+        .chars()
+        .map(|ch| if ch.is_alphanumeric() { ch } else { ' ' })
+        .collect();
+
+    sanitized.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Similarity between two strings in the range 0.0..=1.0, based on edit distance.
+pub fn similarity(a: &str, b: &str) -> f32 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let longest = a.len().max(b.len());
+
+    if longest == 0 {
+        return 1.0;
+    }
+
+    1.0 - levenshtein(&a, &b) as f32 / longest as f32
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
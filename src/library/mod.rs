@@ -1,23 +1,154 @@
 use anyhow::Result;
 use chrono::{DateTime, Utc};
-use dirs;
-use lofty::{file::TaggedFileExt, prelude::Accessor, probe::Probe};
+use lofty::{
+    file::TaggedFileExt,
+    prelude::Accessor,
+    probe::Probe,
+    tag::{ItemKey, TagType},
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
 use tracing::{debug, info, warn};
 use walkdir::WalkDir;
 
-use crate::audio::is_supported_audio_format;
+use crate::audio::{
+    is_supported_audio_format, ReplayGain, TechnicalInfo, DEFAULT_AUDIO_EXTENSIONS,
+};
+use crate::config::Paths;
 use crate::utils::ensure_directory;
+use crate::utils::schema::{self, Schema, SchemaError};
 
 mod albums;
+mod artwork;
+mod breakdown;
+mod changes;
+mod chapters;
+mod collation;
+mod completeness;
+mod duplicates;
+mod durations;
+mod editions;
+mod error;
+mod filename_guess;
+mod fingerprint;
+mod genres;
+mod integrity;
+mod matching;
+mod musicbrainz;
+mod radio;
+mod read_only;
+mod replace;
+mod scan_guard;
+mod search;
+mod sidecar;
+mod stats;
+mod suggest;
+mod tag_stats;
+mod tags;
+mod trash;
+mod waveform;
+mod works;
+pub use albums::{
+    album_artwork_url, track_album_identifier, AlbumEditFileResult, AlbumEditReport,
+    AlbumExportFormat, AlbumMetadata, AlbumOverrideRecord, AlbumSearch, AlbumService, AlbumSort,
+    AlbumSummary, ArtistCredit, ManualAlbumUpdate, DEFAULT_VARIOUS_ARTISTS_THRESHOLD,
+    LAST_FM_ENDPOINT,
+};
+#[allow(unused_imports)]
 pub use albums::{
-    album_identifier, AlbumExportFormat, AlbumMetadata, AlbumOverrideRecord, AlbumService,
-    AlbumSummary, ManualAlbumUpdate,
+    album_identifier, album_primary_artist, artist_credits, artist_identifier, AlbumPage,
+    VARIOUS_ARTISTS,
+};
+#[allow(unused_imports)]
+pub use artwork::ArtworkDedupReport;
+pub use breakdown::{DecadeCount, NameCount, TrackBreakdown};
+use changes::ChangeLog;
+pub use changes::LibraryDelta;
+#[allow(unused_imports)]
+pub use changes::MAX_DELTA_TRACK_IDS;
+pub use chapters::Chapter;
+#[allow(unused_imports)]
+pub use chapters::{
+    parse_id3v2_chapters, parse_mp4_chapters, parse_vorbis_chapters, read_container_chapters,
+};
+#[allow(unused_imports)]
+pub use collation::OTHER_SECTION;
+pub use collation::{Collator, Section, TrackSort};
+pub use completeness::{find_incomplete_albums, IncompleteAlbum};
+#[allow(unused_imports)]
+pub use duplicates::{effective_bitrate_kbps, tag_completeness, BITRATE_TOLERANCE};
+pub use duplicates::{
+    find_duplicate_groups, recommend_keeper, DuplicateCandidate, DuplicateGroup,
+    DuplicatePreferences,
 };
+pub use durations::AudioProber;
+use durations::DurationCache;
+#[allow(unused_imports)]
+pub use durations::SymphoniaProber;
+pub use editions::AlbumDisambiguation;
+#[allow(unused_imports)]
+pub use editions::{split_editions, AlbumEdition};
+pub use error::LibraryError;
+pub use filename_guess::GuessedFields;
+#[allow(unused_imports)]
+pub use filename_guess::{guess_from_path, FilenameGuess};
+#[allow(unused_imports)]
+pub use fingerprint::{content_fingerprint, FINGERPRINT_CHUNK};
+#[allow(unused_imports)]
+pub use genres::{genre_key, BUILTIN_GENRE_ALIASES};
+pub use genres::{GenreIndex, GenreNormalizer, GenreRetagFile, GenreSummary, RawGenre};
+pub use integrity::VerificationJob;
+#[allow(unused_imports)]
+pub use integrity::{verify_file, IntegrityCheck, VerifyProgress};
+#[allow(unused_imports)]
+pub use matching::match_track;
+pub use matching::{TrackMatch, TrackMatcher, TrackQuery};
+pub use musicbrainz::{normalize_mbid, MusicBrainzIds};
+pub use radio::similar_tracks;
+#[allow(unused_imports)]
+pub use radio::similarity;
+pub use read_only::{ReadOnlyError, ReadOnlyPaths};
+pub use replace::{MetadataReplace, MetadataReplaceFile, ReplaceField, ReplaceMatch};
+#[allow(unused_imports)]
+pub use replace::{ReplaceError, MAX_FIND_LENGTH, MAX_REGEX_SIZE};
+use scan_guard::ScanState;
+#[allow(unused_imports)]
+pub use scan_guard::SCAN_RETRY_AFTER;
+pub use scan_guard::{ScanConflict, ScanInProgressError};
+pub use search::TrackSearch;
+#[allow(unused_imports)]
+pub use search::{Comparison, TechnicalFilter};
+#[allow(unused_imports)]
+pub use sidecar::SIDECAR_SUFFIX;
+pub use sidecar::{
+    orphaned_sidecars, read_sidecar, sidecar_path, update_sidecar, MetadataSource, SidecarMetadata,
+};
+pub use stats::{IntegrityRecord, StatsStore};
+#[allow(unused_imports)]
+pub use stats::{IntegrityStatus, TrackStats};
+#[allow(unused_imports)]
+pub use suggest::{fold_words, SuggestionIndex};
+pub use suggest::{SuggestionGroup, SuggestionType};
+pub use tag_stats::{import_tag_stats, TagStatsImport};
+#[allow(unused_imports)]
+pub use tag_stats::{read_tag_stats, RatingScale, TagStats, MAX_RATING};
+pub use tags::{write_track_tags, TrackTagUpdate};
+pub use trash::{DeleteMode, Trash};
+#[allow(unused_imports)]
+pub use trash::{TrashEntry, FALLBACK_TRASH_DIR};
+#[allow(unused_imports)]
+pub use waveform::{compute_waveform, Peak, Waveform, WAVEFORM_CONCURRENCY};
+pub use waveform::{WaveformCache, WaveformPrecompute, WAVEFORM_BUCKETS};
+pub use works::{group_works, Work};
+#[allow(unused_imports)]
+pub use works::{parse_work_title, ParsedWorkTitle, WorkMovement};
 
 fn merge_metadata_from_tag(
     tag: &dyn Accessor,
@@ -66,7 +197,7 @@ fn merge_metadata_from_tag(
 }
 
 /// Track metadata
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TrackMetadata {
     /// Track title
     pub title: Option<String>,
@@ -74,20 +205,61 @@ pub struct TrackMetadata {
     pub artist: Option<String>,
     /// Album name
     pub album: Option<String>,
+    /// Album artist
+    #[serde(default)]
+    pub album_artist: Option<String>,
     /// Track number
     pub track_number: Option<u32>,
+    /// Number of tracks on the album (TRACKTOTAL, or the "4" of "3/4")
+    #[serde(default)]
+    pub track_total: Option<u32>,
     /// Year
     pub year: Option<i32>,
     /// Genre
     pub genre: Option<String>,
+    /// Composer
+    #[serde(default)]
+    pub composer: Option<String>,
+    /// Work the track is part of (WORK tag), for classical music
+    #[serde(default)]
+    pub work: Option<String>,
+    /// Movement name within the work (MOVEMENT tag)
+    #[serde(default)]
+    pub movement: Option<String>,
+    /// Movement number within the work
+    #[serde(default)]
+    pub movement_number: Option<u32>,
     /// Duration in seconds
     pub duration: Option<u64>,
+    /// Chapter markers of long files, ordered by start time
+    ///
+    /// Kept out of the library cache and stored in a sidecar file instead, since most
+    /// tracks have none.
+    #[serde(default, skip_serializing)]
+    pub chapters: Vec<Chapter>,
     /// File size in bytes
     pub file_size: u64,
     /// Last modified time
+    #[serde(with = "crate::utils::serde_rfc3339")]
     pub last_modified: DateTime<Utc>,
     /// File path
     pub file_path: PathBuf,
+    /// Whether a sidecar overrides some of the file's tags
+    #[serde(default)]
+    pub metadata_source: MetadataSource,
+    /// Title and artist guessed from the file path because the tags lack them. Never
+    /// written back to the file.
+    #[serde(default, skip_serializing_if = "GuessedFields::is_empty")]
+    pub guessed: GuessedFields,
+    /// Codec, sample format and average bitrate, read by the probe for the duration
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub technical: Option<TechnicalInfo>,
+    /// MusicBrainz identifiers of the recording, release and artist
+    #[serde(default, skip_serializing_if = "MusicBrainzIds::is_empty")]
+    pub musicbrainz: MusicBrainzIds,
+    /// Loudness adjustment tagged by a ReplayGain scanner
+    #[serde(default, skip_serializing_if = "ReplayGain::is_empty")]
+    pub replaygain: ReplayGain,
 }
 
 /// A music track
@@ -95,15 +267,27 @@ pub struct TrackMetadata {
 pub struct Track {
     /// Track metadata
     pub metadata: TrackMetadata,
-    /// Unique identifier
+    /// Unique identifier, derived from the file path by [`track_identifier`]
     pub id: String,
 }
 
+/// Identifier of the track of the file at `file_path`: the SHA-256 of its canonical
+/// path, so the file keeps it across scans, runs and rebuilt caches, and a moved file
+/// gets another. The path is taken as given when it cannot be canonicalized, e.g. when
+/// the file is gone.
+pub fn track_identifier(file_path: &Path) -> String {
+    use sha2::{Digest, Sha256};
+
+    let canonical = fs::canonicalize(file_path);
+    let path = canonical.as_deref().unwrap_or(file_path);
+    format!("{:x}", Sha256::digest(path.as_os_str().as_encoded_bytes()))
+}
+
 impl Track {
     /// Create a new track from a file path
-    pub fn new(file_path: PathBuf) -> Result<Self> {
+    pub fn new(file_path: PathBuf) -> Result<Self, LibraryError> {
         let metadata = TrackMetadata::from_file(&file_path)?;
-        let id = uuid::Uuid::new_v4().to_string();
+        let id = track_identifier(&file_path);
 
         Ok(Self { metadata, id })
     }
@@ -129,7 +313,10 @@ impl Track {
     /// Get album artist info
     #[allow(dead_code)]
     pub fn album_artist(&self) -> Option<String> {
-        self.metadata.artist.clone()
+        self.metadata
+            .album_artist
+            .clone()
+            .or_else(|| self.metadata.artist.clone())
     }
 
     /// Get album info
@@ -137,11 +324,57 @@ impl Track {
     pub fn album(&self) -> Option<String> {
         self.metadata.album.clone()
     }
+
+    /// Get the stable album identifier for this track, if it has an album tag
+    #[allow(dead_code)]
+    pub fn album_id(&self) -> Option<String> {
+        self.metadata
+            .album
+            .as_deref()
+            .map(|album| track_album_identifier(&self.metadata, album))
+    }
 }
 
 impl TrackMetadata {
     /// Create metadata from a file
-    pub fn from_file(file_path: &Path) -> Result<Self> {
+    pub fn from_file(file_path: &Path) -> Result<Self, LibraryError> {
+        let (metadata, sidecar_error) = Self::from_file_checked(file_path)?;
+        if let Some(error) = sidecar_error {
+            warn!("Ignoring sidecar of {:?}: {:#}", file_path, error);
+        }
+        Ok(metadata)
+    }
+
+    /// Create metadata from a file and merge its sidecar over the tags. A sidecar that
+    /// cannot be used is skipped and its error returned alongside the metadata.
+    pub fn from_file_checked(
+        file_path: &Path,
+    ) -> Result<(Self, Option<anyhow::Error>), LibraryError> {
+        Self::read(file_path, None).map_err(|error| LibraryError::Unreadable {
+            path: file_path.to_path_buf(),
+            error,
+        })
+    }
+
+    /// Like [`TrackMetadata::from_file_checked`], taking the duration and technical
+    /// details from `durations` when it probed the file as it is now
+    fn read(
+        file_path: &Path,
+        durations: Option<&DurationCache>,
+    ) -> Result<(Self, Option<anyhow::Error>)> {
+        let mut metadata = Self::from_tags(file_path, durations)?;
+        let sidecar_error = match read_sidecar(file_path) {
+            Ok(Some(sidecar)) => {
+                sidecar.apply_to(&mut metadata);
+                None
+            }
+            Ok(None) => None,
+            Err(error) => Some(error),
+        };
+        Ok((metadata, sidecar_error))
+    }
+
+    fn from_tags(file_path: &Path, durations: Option<&DurationCache>) -> Result<Self> {
         let metadata = std::fs::metadata(file_path)?;
         let file_size = metadata.len();
         let last_modified = metadata.modified()?.into();
@@ -150,9 +383,18 @@ impl TrackMetadata {
         let mut title = None;
         let mut artist = None;
         let mut album = None;
+        let mut album_artist = None;
         let mut track_number = None;
+        let mut track_total = None;
         let mut year = None;
         let mut genre = None;
+        let mut composer = None;
+        let mut work = None;
+        let mut movement = None;
+        let mut movement_number = None;
+        let mut chapters = Vec::new();
+        let mut musicbrainz = MusicBrainzIds::default();
+        let mut replaygain = ReplayGain::default();
 
         if let Ok(tagged_file) = Probe::open(file_path).and_then(|p| p.read()) {
             if let Some(primary_tag) = tagged_file.primary_tag() {
@@ -178,78 +420,510 @@ impl TrackMetadata {
                     &mut genre,
                 );
             }
+
+            let find_string = |key: ItemKey| {
+                tagged_file
+                    .primary_tag()
+                    .into_iter()
+                    .chain(tagged_file.tags())
+                    .find_map(|tag| tag.get_string(&key))
+                    .map(|value| value.trim().to_string())
+                    .filter(|value| !value.is_empty())
+            };
+
+            track_total = tagged_file
+                .primary_tag()
+                .into_iter()
+                .chain(tagged_file.tags())
+                .find_map(|tag| tag.track_total())
+                .filter(|total| *total > 0);
+            musicbrainz = MusicBrainzIds::from_tags(
+                tagged_file
+                    .primary_tag()
+                    .into_iter()
+                    .chain(tagged_file.tags()),
+            );
+            replaygain = ReplayGain::from_tags(
+                tagged_file
+                    .primary_tag()
+                    .into_iter()
+                    .chain(tagged_file.tags()),
+            );
+            album_artist = find_string(ItemKey::AlbumArtist);
+            composer = find_string(ItemKey::Composer);
+            work = find_string(ItemKey::Work);
+            movement = find_string(ItemKey::Movement);
+            // Stored as "2" or "2/4"
+            movement_number = find_string(ItemKey::MovementNumber).and_then(|value| {
+                value
+                    .split('/')
+                    .next()
+                    .and_then(|number| number.trim().parse().ok())
+            });
+
+            chapters = chapters::parse_vorbis_chapters(
+                tagged_file
+                    .tags()
+                    .iter()
+                    .filter(|tag| tag.tag_type() == TagType::VorbisComments)
+                    .flat_map(|tag| tag.items())
+                    .filter_map(|item| match item.key() {
+                        ItemKey::Unknown(key) => Some((key.as_str(), item.value().text()?)),
+                        _ => None,
+                    }),
+            );
         }
 
-        // Try to get duration using symphonia
-        let duration = crate::audio::get_audio_duration(file_path)
-            .ok()
-            .map(|d| d.as_secs());
+        if chapters.is_empty() {
+            chapters = chapters::read_container_chapters(file_path);
+        }
+
+        let mut guessed = GuessedFields::default();
+        if title.is_none() || artist.is_none() {
+            let guess = guess_from_path(file_path);
+            if title.is_none() && guess.title.is_some() {
+                title = guess.title;
+                guessed.title = true;
+            }
+            if artist.is_none() && guess.artist.is_some() {
+                artist = guess.artist;
+                guessed.artist = true;
+            }
+        }
+
+        // Try to get duration and technical details using symphonia
+        let probe = match durations {
+            Some(durations) => durations.probe(file_path, &metadata),
+            None => crate::audio::probe_audio(file_path),
+        }
+        .ok();
+        let duration = probe.as_ref().map(|probe| probe.duration.as_secs());
+        let technical = probe.map(|probe| probe.technical);
 
         Ok(Self {
             title,
             artist,
             album,
+            album_artist,
             track_number,
+            track_total,
             year,
             genre,
+            composer,
+            work,
+            movement,
+            movement_number,
             duration,
+            chapters,
             file_size,
             last_modified,
             file_path: file_path.to_path_buf(),
+            metadata_source: MetadataSource::File,
+            guessed,
+            technical,
+            musicbrainz,
+            replaygain,
         })
     }
 }
 
+/// Most threads a scan reads files with
+pub const MAX_SCAN_THREADS: usize = 16;
+/// Files a scan handles between progress reports, unless set otherwise
+pub const DEFAULT_SCAN_PROGRESS_INTERVAL: usize = 50;
+
+/// How far a scan has got, see [`Library::scan_directories_reporting`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanProgress {
+    /// Audio files read, or found unchanged, so far
+    pub processed: usize,
+    /// Audio files found under the directories scanned
+    pub total: usize,
+}
+
+/// Counts the files a scan has handled, reporting every `interval` files and the last
+struct ScanProgressReporter<'a> {
+    processed: Mutex<usize>,
+    total: usize,
+    interval: usize,
+    on_progress: &'a (dyn Fn(ScanProgress) + Sync),
+}
+
+impl ScanProgressReporter<'_> {
+    /// Count one more file handled. Reports are made under the lock, so they arrive
+    /// in order whichever thread makes them.
+    fn advance(&self) {
+        let mut processed = self.processed.lock().unwrap();
+        *processed += 1;
+        if processed.is_multiple_of(self.interval) || *processed == self.total {
+            (self.on_progress)(ScanProgress {
+                processed: *processed,
+                total: self.total,
+            });
+        }
+    }
+}
+
 /// Cache entry for a track - includes file modification time for validation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CachedTrack {
     track: Track,
+    #[serde(with = "crate::utils::serde_rfc3339")]
     file_mtime: DateTime<Utc>,
+    /// Modification time of the track's sidecar, if it had one
+    #[serde(default, with = "crate::utils::serde_rfc3339::option")]
+    sidecar_mtime: Option<DateTime<Utc>>,
+    /// Content fingerprint, recorded when fingerprinting is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fingerprint: Option<String>,
 }
 
+/// Content fingerprint of a file, with the modification time it was taken at
+#[derive(Debug, Clone)]
+struct FileFingerprint {
+    mtime: DateTime<Utc>,
+    hash: String,
+}
+
+/// Versions of the library cache format. Caches of older versions are loaded, and
+/// what their entries lack is read from the files once.
+///
+/// 1: tracks carry technical details
+/// 2: tracks carry MusicBrainz identifiers
+/// 3: tracks carry ReplayGain values
+const CACHE_SCHEMA: Schema = Schema {
+    name: "library cache",
+    migrations: &[
+        schema::optional_fields,
+        schema::optional_fields,
+        schema::optional_fields,
+    ],
+};
+
+const CACHE_VERSION: u32 = CACHE_SCHEMA.current();
+
 /// Library cache structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct LibraryCache {
+    /// Format version, 0 for caches written before it was recorded
+    #[serde(default)]
+    version: u32,
     tracks: Vec<CachedTrack>,
+    /// Random ids tracks had before ids were derived from paths, by the ids that
+    /// replaced them
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    previous_ids: HashMap<String, String>,
+    #[serde(with = "crate::utils::serde_rfc3339")]
     cached_at: DateTime<Utc>,
 }
 
+/// A sidecar that was skipped during a scan
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SidecarError {
+    /// Path of the sidecar file
+    pub path: PathBuf,
+    pub error: String,
+}
+
+/// Outcome of a library scan
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanReport {
+    /// Tracks in the library after the scan
+    pub tracks: usize,
+    /// Audio files found under the directories scanned
+    pub files: usize,
+    /// Sidecars that could not be read or did not match the sidecar schema. Their
+    /// tracks were added with the file's own tags.
+    pub sidecar_errors: Vec<SidecarError>,
+}
+
+/// Outcome of [`Library::refresh`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RefreshReport {
+    /// Audio files found that were not in the library yet
+    pub added: usize,
+    /// Tracks re-read because their file was modified
+    pub updated: usize,
+    /// Tracks dropped because their file is gone
+    pub removed: usize,
+}
+
+impl RefreshReport {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Whether the tracks cached by a previous run have been loaded
+#[derive(Default)]
+struct CacheLoad {
+    loaded: Mutex<bool>,
+    /// Wakes blocking waiters once the cache is loaded
+    loaded_changed: Condvar,
+    /// Wakes async waiters once the cache is loaded
+    notify: Notify,
+}
+
 /// Music library
 pub struct Library {
     tracks: Arc<Mutex<HashMap<String, Track>>>,
     track_paths: Arc<Mutex<HashMap<PathBuf, String>>>,
-    is_scanning: Arc<Mutex<bool>>,
+    scan: Arc<ScanState>,
+    /// What changes of the tracks requested during a scan do
+    scan_conflict: ScanConflict,
+    /// Sleep after each file a scan reads
+    scan_pause: Duration,
+    /// Files a scan handles between progress reports
+    scan_progress_interval: usize,
+    /// Extensions of the files scans and refreshes pick up
+    extensions: Vec<String>,
+    /// List artists guessed from file names in [`Library::get_artists`]
+    list_guessed_artists: bool,
+    /// Order of artist, album and track listings
+    collator: Collator,
+    last_scan_report: Arc<Mutex<Option<ScanReport>>>,
     cache_path: PathBuf,
+    /// Whether cached tracks carry content fingerprints, so a file whose modification
+    /// time changed can be kept when its content did not
+    content_fingerprints: bool,
+    fingerprints: Arc<Mutex<HashMap<PathBuf, FileFingerprint>>>,
+    /// Files whose tags and sidecars must not be written
+    read_only: ReadOnlyPaths,
+    /// Built on the first suggestion request and dropped whenever tracks change
+    suggestions: Arc<Mutex<Option<Arc<SuggestionIndex>>>>,
+    /// Spellings of genres and the names they are listed under
+    genre_normalizer: GenreNormalizer,
+    /// Built on the first genre request and dropped whenever tracks change
+    genres: Arc<Mutex<Option<Arc<GenreIndex>>>>,
+    /// Numbered log of the latest changes to the tracks
+    changes: Arc<Mutex<ChangeLog>>,
+    cache_load: Arc<CacheLoad>,
+    /// Why the cache on disk was refused, e.g. written by a newer Hexendrum, so it is
+    /// not saved over
+    refused_cache: Mutex<Option<SchemaError>>,
+    /// Random ids tracks had before ids were derived from paths, by the ids that
+    /// replaced them
+    renamed_ids: Mutex<HashMap<String, String>>,
+    /// The part of `renamed_ids` renamed while loading the cache this run; only these
+    /// are kept in the cache, so old ids are dropped once a later run has loaded them
+    /// and relinked the playlists
+    fresh_renamed_ids: Mutex<HashMap<String, String>>,
+    /// Durations and technical details of the files read, so scans skip probing
+    /// unchanged ones
+    durations: DurationCache,
+    /// Whether the tracks only live in memory, see [`Library::in_memory`]
+    in_memory: bool,
 }
 
 impl Library {
-    /// Create a new music library
+    /// Create a new music library cached in the default location
     pub fn new() -> Self {
-        let cache_dir = dirs::cache_dir()
-            .unwrap_or_else(|| {
-                dirs::home_dir()
-                    .unwrap_or_else(|| PathBuf::from("~"))
-                    .join(".cache")
-            })
-            .join("hexendrum");
+        Self::with_paths(&Paths::standard())
+    }
 
-        ensure_directory(&cache_dir).ok();
+    /// Create a new music library cached under `paths`
+    pub fn with_paths(paths: &Paths) -> Self {
+        Self::with_content_fingerprints(paths, false)
+    }
+
+    /// Create a new music library cached under `paths`, optionally validating the
+    /// cache with content fingerprints
+    ///
+    /// Fingerprinting reads up to 128 KiB of every track when the cache is saved, and
+    /// again for each track whose modification time changed when it is loaded.
+    pub fn with_content_fingerprints(paths: &Paths, content_fingerprints: bool) -> Self {
+        let library = Self::deferred(paths, content_fingerprints);
+        library.finish_loading();
+        library
+    }
 
-        let cache_path = cache_dir.join("library_cache.json");
+    /// Create a new music library cached under `paths` without loading the cache, so
+    /// it is ready to serve right away
+    ///
+    /// The library stays empty and [`Library::is_ready`] false until
+    /// [`Library::load_in_background`] has loaded the cache. Scans and refreshes wait
+    /// for it.
+    pub fn deferred(paths: &Paths, content_fingerprints: bool) -> Self {
+        ensure_directory(&paths.cache_dir).ok();
+
+        Self {
+            content_fingerprints,
+            ..Self::unloaded(paths.library_cache_file())
+        }
+    }
 
+    /// Create a library that keeps its tracks in memory only, e.g. generated ones for
+    /// demos
+    ///
+    /// Nothing is loaded from or saved to a cache, and scans and refreshes leave the
+    /// tracks alone, reporting the library as it is.
+    pub fn in_memory() -> Self {
         let library = Self {
+            in_memory: true,
+            ..Self::unloaded(PathBuf::new())
+        };
+        library.finish_loading();
+        library
+    }
+
+    fn unloaded(cache_path: PathBuf) -> Self {
+        Self {
             tracks: Arc::new(Mutex::new(HashMap::new())),
             track_paths: Arc::new(Mutex::new(HashMap::new())),
-            is_scanning: Arc::new(Mutex::new(false)),
+            scan: Arc::new(ScanState::default()),
+            scan_conflict: ScanConflict::default(),
+            scan_pause: Duration::ZERO,
+            scan_progress_interval: DEFAULT_SCAN_PROGRESS_INTERVAL,
+            extensions: DEFAULT_AUDIO_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+            list_guessed_artists: false,
+            collator: Collator::default(),
+            last_scan_report: Arc::new(Mutex::new(None)),
             cache_path,
-        };
+            content_fingerprints: false,
+            fingerprints: Arc::new(Mutex::new(HashMap::new())),
+            read_only: ReadOnlyPaths::default(),
+            suggestions: Arc::new(Mutex::new(None)),
+            genre_normalizer: GenreNormalizer::new(),
+            genres: Arc::new(Mutex::new(None)),
+            changes: Arc::new(Mutex::new(ChangeLog::default())),
+            cache_load: Arc::new(CacheLoad::default()),
+            refused_cache: Mutex::new(None),
+            renamed_ids: Mutex::new(HashMap::new()),
+            fresh_renamed_ids: Mutex::new(HashMap::new()),
+            durations: DurationCache::default(),
+            in_memory: false,
+        }
+    }
+
+    /// Load the cache of a [`Library::deferred`] library on a blocking task. The
+    /// handle resolves to the number of tracks loaded.
+    pub fn load_in_background(self: &Arc<Self>) -> JoinHandle<usize> {
+        let library = self.clone();
+        tokio::task::spawn_blocking(move || library.finish_loading())
+    }
 
-        // Try to load from cache automatically on creation
-        if let Err(e) = library.load_from_cache() {
-            debug!("Failed to auto-load from cache: {}", e);
+    /// Load the cache and wake everyone waiting for it
+    fn finish_loading(&self) -> usize {
+        if !self.in_memory {
+            self.durations.load(&self.get_durations_path());
         }
+        let count = self.load_from_cache().unwrap_or_else(|e| {
+            if matches!(e, LibraryError::Schema(_)) {
+                warn!("{}", e);
+            } else {
+                debug!("Failed to auto-load from cache: {}", e);
+            }
+            0
+        });
 
-        library
+        *self.cache_load.loaded.lock().unwrap() = true;
+        self.cache_load.loaded_changed.notify_all();
+        self.cache_load.notify.notify_waiters();
+        count
+    }
+
+    /// Whether the cache has been loaded. Until then the library only holds tracks
+    /// added since it was created.
+    pub fn is_ready(&self) -> bool {
+        *self.cache_load.loaded.lock().unwrap()
+    }
+
+    /// Wait until the cache has been loaded
+    pub async fn ready(&self) {
+        loop {
+            // Registered before checking, so a load finishing in between still wakes us
+            let notified = self.cache_load.notify.notified();
+            if self.is_ready() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Block until the cache has been loaded
+    fn wait_until_ready(&self) {
+        let mut loaded = self.cache_load.loaded.lock().unwrap();
+        while !*loaded {
+            loaded = self.cache_load.loaded_changed.wait(loaded).unwrap();
+        }
+    }
+
+    /// Refuse tag and sidecar writes to files under `read_only`. The library cache
+    /// is stored locally and keeps being saved.
+    pub fn with_read_only(mut self, read_only: ReadOnlyPaths) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// Pick up files with one of `extensions` in scans and refreshes, rather than
+    /// [`DEFAULT_AUDIO_EXTENSIONS`]
+    pub fn with_extensions(mut self, extensions: impl IntoIterator<Item = String>) -> Self {
+        self.extensions = extensions.into_iter().collect();
+        self
+    }
+
+    /// Read the durations and technical details of files with `prober` rather than
+    /// [`SymphoniaProber`]
+    #[allow(dead_code)]
+    pub fn with_prober(mut self, prober: impl AudioProber + 'static) -> Self {
+        self.durations.set_prober(Arc::new(prober));
+        self
+    }
+
+    /// Include artists guessed from file names in [`Library::get_artists`]
+    pub fn with_guessed_artists(mut self, list_guessed_artists: bool) -> Self {
+        self.list_guessed_artists = list_guessed_artists;
+        self
+    }
+
+    /// Sort artist, album and track listings by `collator` rather than the root order
+    pub fn with_collator(mut self, collator: Collator) -> Self {
+        self.collator = collator;
+        self
+    }
+
+    /// Order of artist, album and track listings
+    pub fn collator(&self) -> Collator {
+        self.collator
+    }
+
+    /// Make changes of the tracks requested while a scan runs wait for it, or refuse
+    /// them, when they go through [`Library::guard_mutation`]
+    pub fn with_scan_conflict(mut self, scan_conflict: ScanConflict) -> Self {
+        self.scan_conflict = scan_conflict;
+        self
+    }
+
+    /// Report the progress of scans every `interval` files, at least 1
+    pub fn with_scan_progress_interval(mut self, interval: usize) -> Self {
+        self.scan_progress_interval = interval.max(1);
+        self
+    }
+
+    /// Sleep for `pause` after each file a scan or refresh reads, slowing them down on purpose,
+    /// e.g. to exercise changes made while one runs
+    #[allow(dead_code)]
+    pub fn with_scan_pause(mut self, pause: Duration) -> Self {
+        self.scan_pause = pause;
+        self
+    }
+
+    /// List the `(alias, canonical name)` pairs under their canonical names, next to
+    /// the built-in aliases
+    pub fn with_genre_aliases(
+        mut self,
+        aliases: impl IntoIterator<Item = (String, String)>,
+    ) -> Self {
+        self.genre_normalizer = self.genre_normalizer.with_aliases(aliases);
+        self
+    }
+
+    /// Files whose tags, sidecars and location must not be changed
+    pub fn read_only(&self) -> &ReadOnlyPaths {
+        &self.read_only
     }
 
     /// Get cache file path
@@ -257,38 +931,126 @@ impl Library {
         &self.cache_path
     }
 
+    /// Sidecar next to the cache holding the chapters of tracks that have any
+    fn get_chapters_path(&self) -> PathBuf {
+        self.cache_path.with_file_name("library_chapters.json")
+    }
+
+    /// Sidecar next to the cache holding the durations of the files read, with the
+    /// modification time and size they were read at
+    fn get_durations_path(&self) -> PathBuf {
+        self.cache_path.with_file_name("duration_cache.json")
+    }
+
+    /// Read the metadata of a file, probing its duration only if it changed since
+    /// it was last probed
+    fn read_metadata(&self, file_path: &Path) -> Result<(TrackMetadata, Option<anyhow::Error>)> {
+        TrackMetadata::read(file_path, Some(&self.durations))
+    }
+
+    /// [`Library::read_metadata`], logging a sidecar that cannot be used as
+    /// [`TrackMetadata::from_file`] does
+    fn read_track_metadata(&self, file_path: &Path) -> Result<TrackMetadata> {
+        let (metadata, sidecar_error) = self.read_metadata(file_path)?;
+        if let Some(error) = sidecar_error {
+            warn!("Ignoring sidecar of {:?}: {:#}", file_path, error);
+        }
+        Ok(metadata)
+    }
+
+    fn load_chapters(&self) -> HashMap<String, Vec<Chapter>> {
+        let path = self.get_chapters_path();
+        match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring invalid chapters cache {:?}: {}", path, e);
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        }
+    }
+
     /// Load library from cache
-    pub fn load_from_cache(&self) -> Result<usize> {
+    pub fn load_from_cache(&self) -> Result<usize, LibraryError> {
         let cache_path = self.get_cache_path();
 
-        if !cache_path.exists() {
+        if self.in_memory || !cache_path.exists() {
             debug!("No cache file found at {:?}", cache_path);
             return Ok(0);
         }
 
         let content = fs::read_to_string(cache_path)?;
-        let cache: LibraryCache = serde_json::from_str(&content)?;
+        let corrupt = |source| LibraryError::CacheCorrupt {
+            path: cache_path.to_path_buf(),
+            source,
+        };
+        let mut document: serde_json::Value = serde_json::from_str(&content).map_err(corrupt)?;
+        let version = CACHE_SCHEMA
+            .upgrade(&mut document, cache_path)
+            .inspect_err(|e| *self.refused_cache.lock().unwrap() = Some(e.clone()))?;
+        let cache: LibraryCache = serde_json::from_value(document).map_err(corrupt)?;
+        let outdated = version < CACHE_VERSION;
 
         let mut tracks_map = HashMap::new();
         let mut track_paths_map = HashMap::new();
+        let mut fingerprints = HashMap::new();
         let mut loaded_count = 0;
         let mut invalidated_count = 0;
-
-        for cached_track in cache.tracks {
-            let file_path = &cached_track.track.metadata.file_path;
+        let mut revalidated_count = 0;
+        let mut chapters = self.load_chapters();
+        let mut renamed = cache.previous_ids;
+        let mut fresh_renamed = HashMap::new();
+
+        for mut cached_track in cache.tracks {
+            let file_path = cached_track.track.metadata.file_path.clone();
+
+            // Random ids of older versions give way to ones derived from the paths, for
+            // entries about to be read again too; the old ones are kept, so playlist
+            // entries can be pointed at the new ones whenever they are next linked
+            if uuid::Uuid::parse_str(&cached_track.track.id).is_ok() {
+                let id = track_identifier(&file_path);
+                let previous_id = std::mem::replace(&mut cached_track.track.id, id.clone());
+                if let Some(track_chapters) = chapters.remove(&previous_id) {
+                    chapters.insert(id.clone(), track_chapters);
+                }
+                fresh_renamed.insert(previous_id, id);
+            }
 
             // Check if file still exists and modification time matches
             if file_path.exists() {
-                if let Ok(metadata) = std::fs::metadata(file_path) {
+                if let Ok(metadata) = std::fs::metadata(&file_path) {
                     if let Ok(file_mtime) = metadata.modified() {
                         let file_mtime_utc: DateTime<Utc> = file_mtime.into();
-
-                        // If file hasn't changed, use cached data
-                        if file_mtime_utc == cached_track.file_mtime {
-                            tracks_map
-                                .insert(cached_track.track.id.clone(), cached_track.track.clone());
-                            track_paths_map
-                                .insert(file_path.clone(), cached_track.track.id.clone());
+                        let sidecar_unchanged =
+                            sidecar::sidecar_modified(&file_path) == cached_track.sidecar_mtime;
+
+                        // If neither the file nor its sidecar changed, use cached data.
+                        // A file that was only touched or copied keeps its entry when its
+                        // content fingerprint still matches.
+                        let unchanged = if file_mtime_utc == cached_track.file_mtime {
+                            sidecar_unchanged
+                        } else if sidecar_unchanged
+                            && self.content_still_matches(&file_path, &cached_track)
+                        {
+                            debug!("File touched but unchanged: {:?}", file_path);
+                            cached_track.track.metadata.last_modified = file_mtime_utc;
+                            revalidated_count += 1;
+                            true
+                        } else {
+                            false
+                        };
+
+                        if unchanged {
+                            if let Some(hash) = cached_track.fingerprint.take() {
+                                fingerprints.insert(
+                                    file_path.clone(),
+                                    FileFingerprint {
+                                        mtime: file_mtime_utc,
+                                        hash,
+                                    },
+                                );
+                            }
+                            track_paths_map.insert(file_path, cached_track.track.id.clone());
+                            tracks_map.insert(cached_track.track.id.clone(), cached_track.track);
                             loaded_count += 1;
                             continue;
                         } else {
@@ -303,25 +1065,120 @@ impl Library {
             }
         }
 
+        for track in tracks_map.values_mut() {
+            if let Some(track_chapters) = chapters.remove(&track.id) {
+                track.metadata.chapters = track_chapters;
+            }
+        }
+
+        let renamed_count = fresh_renamed.len();
+        if renamed_count > 0 {
+            info!(
+                "Gave {} cached tracks ids derived from their paths",
+                renamed_count
+            );
+        }
+        renamed.extend(fresh_renamed.clone());
+        *self.renamed_ids.lock().unwrap() = renamed;
+        *self.fresh_renamed_ids.lock().unwrap() = fresh_renamed;
+
+        if outdated {
+            info!(
+                "Reading the details {} cached tracks lack from their files",
+                tracks_map.len()
+            );
+            for track in tracks_map.values_mut() {
+                if track.metadata.technical.is_none() {
+                    track.metadata.technical = crate::audio::probe_audio(&track.metadata.file_path)
+                        .ok()
+                        .map(|probe| probe.technical);
+                }
+                if version < 2 {
+                    track.metadata.musicbrainz = MusicBrainzIds::read(&track.metadata.file_path);
+                }
+                if version < 3 {
+                    track.metadata.replaygain = ReplayGain::read(&track.metadata.file_path);
+                }
+            }
+        }
+
         // Update library with cached tracks
         {
             let mut tracks = self.tracks.lock().unwrap();
             let mut track_paths = self.track_paths.lock().unwrap();
+            let loaded = LibraryDelta::added(tracks_map.keys().cloned().collect());
             *tracks = tracks_map;
             *track_paths = track_paths_map;
+            self.tracks_changed(loaded);
         }
+        *self.fingerprints.lock().unwrap() = fingerprints;
 
         info!(
-            "Loaded {} tracks from cache ({} invalidated)",
-            loaded_count, invalidated_count
+            "Loaded {} tracks from cache ({} invalidated, {} kept by fingerprint)",
+            loaded_count, invalidated_count, revalidated_count
         );
 
+        // Record the new modification times so the next load does not fingerprint the
+        // same files again, the details an outdated cache lacked and the new ids
+        if revalidated_count > 0 || (outdated && loaded_count > 0) || renamed_count > 0 {
+            if let Err(e) = self.save_to_cache() {
+                warn!("Failed to update cache after revalidation: {}", e);
+            }
+        }
+
         Ok(loaded_count)
     }
 
+    /// Whether fingerprinting is enabled and the file's content still matches the
+    /// fingerprint recorded in its cache entry
+    fn content_still_matches(&self, file_path: &Path, cached_track: &CachedTrack) -> bool {
+        if !self.content_fingerprints {
+            return false;
+        }
+        let Some(cached) = cached_track.fingerprint.as_deref() else {
+            return false;
+        };
+
+        match content_fingerprint(file_path) {
+            Ok(current) => current == cached,
+            Err(e) => {
+                debug!("Cannot fingerprint {:?}: {}", file_path, e);
+                false
+            }
+        }
+    }
+
+    /// Fingerprint of the file at `mtime`, reusing the one already taken when the file
+    /// has not been modified since
+    fn fingerprint_for(
+        &self,
+        file_path: &Path,
+        mtime: DateTime<Utc>,
+        known: &HashMap<PathBuf, FileFingerprint>,
+    ) -> Option<String> {
+        if !self.content_fingerprints {
+            return None;
+        }
+        if let Some(known) = known.get(file_path).filter(|known| known.mtime == mtime) {
+            return Some(known.hash.clone());
+        }
+
+        content_fingerprint(file_path)
+            .map_err(|e| debug!("Cannot fingerprint {:?}: {}", file_path, e))
+            .ok()
+    }
+
     /// Save library to cache
-    pub fn save_to_cache(&self) -> Result<()> {
+    pub fn save_to_cache(&self) -> Result<(), LibraryError> {
+        if self.in_memory {
+            return Ok(());
+        }
+        if let Some(error) = self.refused_cache.lock().unwrap().clone() {
+            return Err(error.into());
+        }
         let tracks = self.tracks.lock().unwrap();
+        let mut fingerprints = self.fingerprints.lock().unwrap();
+        let mut taken = HashMap::new();
 
         let cached_tracks: Vec<CachedTrack> = tracks
             .values()
@@ -332,9 +1189,21 @@ impl Library {
                 if let Ok(metadata) = std::fs::metadata(file_path) {
                     if let Ok(mtime) = metadata.modified() {
                         let mtime_utc: DateTime<Utc> = mtime.into();
+                        let fingerprint = self.fingerprint_for(file_path, mtime_utc, &fingerprints);
+                        if let Some(hash) = &fingerprint {
+                            taken.insert(
+                                file_path.clone(),
+                                FileFingerprint {
+                                    mtime: mtime_utc,
+                                    hash: hash.clone(),
+                                },
+                            );
+                        }
                         return Some(CachedTrack {
                             track: track.clone(),
                             file_mtime: mtime_utc,
+                            sidecar_mtime: sidecar::sidecar_modified(file_path),
+                            fingerprint,
                         });
                     }
                 }
@@ -342,9 +1211,13 @@ impl Library {
                 None
             })
             .collect();
+        *fingerprints = taken;
+        drop(fingerprints);
 
         let cache = LibraryCache {
+            version: CACHE_VERSION,
             tracks: cached_tracks,
+            previous_ids: self.fresh_renamed_ids.lock().unwrap().clone(),
             cached_at: Utc::now(),
         };
 
@@ -358,6 +1231,21 @@ impl Library {
         let content = serde_json::to_string_pretty(&cache)?;
         fs::write(cache_path, content)?;
 
+        let chapters: HashMap<&str, &[Chapter]> = tracks
+            .values()
+            .filter(|track| !track.metadata.chapters.is_empty())
+            .map(|track| (track.id.as_str(), track.metadata.chapters.as_slice()))
+            .collect();
+        fs::write(self.get_chapters_path(), serde_json::to_string(&chapters)?)?;
+        if let Err(e) = self.durations.save(
+            &self.get_durations_path(),
+            tracks
+                .values()
+                .map(|track| track.metadata.file_path.as_path()),
+        ) {
+            warn!("Failed to save the duration cache: {}", e);
+        }
+
         info!("Saved {} tracks to cache", cache.tracks.len());
 
         Ok(())
@@ -365,36 +1253,93 @@ impl Library {
 
     /// Clear the cache file
     #[allow(dead_code)]
-    pub fn clear_cache(&self) -> Result<()> {
+    pub fn clear_cache(&self) -> Result<(), LibraryError> {
         let cache_path = self.get_cache_path();
         if cache_path.exists() {
             fs::remove_file(cache_path)?;
             info!("Cache cleared");
         }
+        *self.refused_cache.lock().unwrap() = None;
+        let chapters_path = self.get_chapters_path();
+        if chapters_path.exists() {
+            fs::remove_file(chapters_path)?;
+        }
+        self.durations.clear();
+        let durations_path = self.get_durations_path();
+        if durations_path.exists() {
+            fs::remove_file(durations_path)?;
+        }
         Ok(())
     }
 
-    /// Scan directories for music files
-    pub fn scan_directories(&self, directories: &[PathBuf]) -> Result<()> {
+    /// Scan directories for music files, merging what is found into the library
+    ///
+    /// Files not modified since they were read keep their track and its id, modified
+    /// files are read again and new ones added. Tracks of files that are gone are
+    /// dropped if they were under one of `directories`; tracks elsewhere are left alone.
+    ///
+    /// If a scan is already in progress, returns the report of the previous scan. A
+    /// scan requested while the cache is loading starts once it is loaded. Directories
+    /// that do not exist are skipped, keeping their tracks; one that exists but cannot
+    /// be listed fails the scan with [`LibraryError::DirectoryUnreadable`].
+    #[allow(dead_code)]
+    pub fn scan_directories(&self, directories: &[PathBuf]) -> Result<ScanReport, LibraryError> {
+        self.scan_directories_with(directories, false)
+    }
+
+    /// Like [`Library::scan_directories`], or with `full_rescan` read every file again
+    /// and replace the library with what is found; ids derive from paths, so tracks
+    /// keep theirs
+    #[allow(dead_code)]
+    pub fn scan_directories_with(
+        &self,
+        directories: &[PathBuf],
+        full_rescan: bool,
+    ) -> Result<ScanReport, LibraryError> {
+        self.scan_directories_reporting(directories, full_rescan, &|_| {})
+    }
+
+    /// Like [`Library::scan_directories_with`], calling `on_progress` once the audio
+    /// files under `directories` are counted, then every
+    /// [`Library::with_scan_progress_interval`] files handled and after the last. It is
+    /// called from the threads reading files, in order.
+    pub fn scan_directories_reporting(
+        &self,
+        directories: &[PathBuf],
+        full_rescan: bool,
+        on_progress: &(dyn Fn(ScanProgress) + Sync),
+    ) -> Result<ScanReport, LibraryError> {
+        self.wait_until_ready();
+        if self.in_memory {
+            let report = ScanReport {
+                tracks: self.track_count(),
+                ..ScanReport::default()
+            };
+            *self.last_scan_report.lock().unwrap() = Some(report.clone());
+            return Ok(report);
+        }
         eprintln!("Starting library scan...");
         eprintln!("Directories to scan: {:?}", directories);
 
-        let mut is_scanning = self.is_scanning.lock().unwrap();
-        if *is_scanning {
+        if !self.begin_scan() {
             eprintln!("Library scan already in progress");
-            return Ok(());
+            return Ok(self.last_scan_report().unwrap_or_default());
         }
-        *is_scanning = true;
-        drop(is_scanning);
+        self.scan.allow_cancel();
 
-        let mut new_tracks = HashMap::new();
-        let mut new_track_paths = HashMap::new();
+        let mut report = ScanReport::default();
 
+        // Find every file first, so they can be read in parallel
+        let mut files = Vec::new();
+        let mut scanned = Vec::new();
         for directory in directories {
             eprintln!("Scanning directory: {:?}", directory);
             if directory.exists() && directory.is_dir() {
                 eprintln!("Directory exists and is valid");
-                self.scan_directory(directory, &mut new_tracks, &mut new_track_paths)?;
+                if let Err(e) = self.scan_directory(directory, &mut files) {
+                    return self.abandon_scan(e);
+                }
+                scanned.push(directory.as_path());
             } else {
                 eprintln!(
                     "Directory does not exist or is not a directory: {:?}",
@@ -403,15 +1348,162 @@ impl Library {
             }
         }
 
+        report.files = files.len();
+        on_progress(ScanProgress {
+            processed: 0,
+            total: files.len(),
+        });
+        let progress = ScanProgressReporter {
+            processed: Mutex::new(0),
+            total: files.len(),
+            interval: self.scan_progress_interval,
+            on_progress,
+        };
+
+        // Files not modified since they were read keep their track, unless everything
+        // is read again
+        let known: HashMap<PathBuf, (String, DateTime<Utc>)> = if full_rescan {
+            HashMap::new()
+        } else {
+            self.tracks
+                .lock()
+                .unwrap()
+                .values()
+                .map(|track| {
+                    (
+                        track.metadata.file_path.clone(),
+                        (track.id.clone(), track.metadata.last_modified),
+                    )
+                })
+                .collect()
+        };
+        let mut present = HashSet::new();
+        let mut to_read = Vec::new();
+        for path in files {
+            if self.scan.is_cancel_requested() {
+                return self.abandon_scan(LibraryError::ScanCancelled);
+            }
+            let unchanged = known.get(&path).is_some_and(|(_, last_modified)| {
+                fs::metadata(&path)
+                    .and_then(|meta| meta.modified())
+                    .is_ok_and(|modified| DateTime::<Utc>::from(modified) == *last_modified)
+            });
+            if unchanged {
+                progress.advance();
+                present.insert(path);
+            } else {
+                to_read.push(path);
+            }
+        }
+
+        let read = self.read_files(&to_read, &progress);
+        if self.scan.is_cancel_requested() {
+            return self.abandon_scan(LibraryError::ScanCancelled);
+        }
+        let mut read_tracks = Vec::new();
+        for (path, read) in to_read.iter().zip(read) {
+            let Some((metadata, sidecar_error)) = read else {
+                eprintln!("Failed to create track from: {:?}", path);
+                continue;
+            };
+            if let Some(error) = sidecar_error {
+                warn!("Ignoring sidecar of {:?}: {:#}", path, error);
+                report.sidecar_errors.push(SidecarError {
+                    path: sidecar_path(path),
+                    error: format!("{:#}", error),
+                });
+            }
+            let track = Track {
+                metadata,
+                id: known
+                    .get(path)
+                    .map(|(id, _)| id.clone())
+                    .unwrap_or_else(|| track_identifier(path)),
+            };
+            eprintln!("Successfully created track: {}", track.display_name());
+            present.insert(path.clone());
+            read_tracks.push(track);
+        }
+
         // Update the library
         {
             let mut tracks = self.tracks.lock().unwrap();
             let mut track_paths = self.track_paths.lock().unwrap();
+            // Tracks edited, added or removed while the scan ran are newer than what it
+            // read
+            let journal = self.changes.lock().unwrap().take_journal();
+
+            let delta = if full_rescan {
+                let mut new_tracks = HashMap::new();
+                let mut new_track_paths = HashMap::new();
+                for track in read_tracks {
+                    new_track_paths.insert(track.metadata.file_path.clone(), track.id.clone());
+                    new_tracks.insert(track.id.clone(), track);
+                }
+                for (path, change) in journal {
+                    if let Some(id) = new_track_paths.remove(&path) {
+                        new_tracks.remove(&id);
+                    }
+                    if let Some(track) = change {
+                        new_track_paths.insert(path, track.id.clone());
+                        new_tracks.insert(track.id.clone(), track);
+                    }
+                }
 
-            eprintln!("Library scan completed. Total tracks: {}", new_tracks.len());
+                // Tracks read again count as updated, whether their file changed or not
+                let kept = new_track_paths
+                    .keys()
+                    .filter(|path| track_paths.contains_key(*path))
+                    .count();
+                let delta = LibraryDelta::counts(
+                    new_track_paths.len() - kept,
+                    track_paths.len() - kept,
+                    kept,
+                );
+                *tracks = new_tracks;
+                *track_paths = new_track_paths;
+                delta
+            } else {
+                // Only tracks under the directories scanned can have disappeared
+                let removed: Vec<String> = tracks
+                    .values()
+                    .filter(|track| {
+                        let path = &track.metadata.file_path;
+                        scanned.iter().any(|directory| path.starts_with(directory))
+                            && !present.contains(path)
+                            && !journal.contains_key(path)
+                    })
+                    .map(|track| track.id.clone())
+                    .collect();
+                for id in &removed {
+                    if let Some(track) = tracks.remove(id) {
+                        track_paths.remove(&track.metadata.file_path);
+                    }
+                }
+
+                let mut added = Vec::new();
+                let mut updated = Vec::new();
+                for track in read_tracks {
+                    let path = &track.metadata.file_path;
+                    if journal.contains_key(path) {
+                        continue;
+                    }
+                    if track_paths.contains_key(path) {
+                        updated.push(track.id.clone());
+                    } else {
+                        added.push(track.id.clone());
+                    }
+                    track_paths.insert(path.clone(), track.id.clone());
+                    tracks.insert(track.id.clone(), track);
+                }
+                LibraryDelta::new(added, removed, updated)
+            };
 
-            *tracks = new_tracks;
-            *track_paths = new_track_paths;
+            eprintln!("Library scan completed. Total tracks: {}", tracks.len());
+            report.tracks = tracks.len();
+            if full_rescan || !delta.is_empty() {
+                self.tracks_changed(delta);
+            }
         }
 
         // Save to cache after scanning
@@ -419,41 +1511,185 @@ impl Library {
             warn!("Failed to save library to cache: {}", e);
         }
 
-        let mut is_scanning = self.is_scanning.lock().unwrap();
-        *is_scanning = false;
+        if !report.sidecar_errors.is_empty() {
+            warn!(
+                "Skipped {} invalid sidecar file(s) during scan",
+                report.sidecar_errors.len()
+            );
+        }
+        *self.last_scan_report.lock().unwrap() = Some(report.clone());
+        self.scan.finish();
 
-        Ok(())
+        Ok(report)
     }
 
-    /// Scan a single directory
+    /// Bring the library up to date without rebuilding it: tracks whose file was
+    /// modified are re-read, tracks whose file is gone are dropped and audio files
+    /// under `directories` that are not in the library yet are added.
+    ///
+    /// Unlike [`Library::scan_directories`], every track is checked whichever
+    /// directory it is in, and sidecar errors are not reported. Fails if a scan is in
+    /// progress. Waits for the cache to be loaded first.
+    pub fn refresh(&self, directories: &[PathBuf]) -> Result<RefreshReport, LibraryError> {
+        self.wait_until_ready();
+        if self.in_memory {
+            return Ok(RefreshReport::default());
+        }
+        if !self.begin_scan() {
+            return Err(LibraryError::ScanInProgress);
+        }
+
+        let known: Vec<(String, PathBuf, DateTime<Utc>)> = self
+            .tracks
+            .lock()
+            .unwrap()
+            .values()
+            .map(|track| {
+                (
+                    track.id.clone(),
+                    track.metadata.file_path.clone(),
+                    track.metadata.last_modified,
+                )
+            })
+            .collect();
+
+        let mut removed = Vec::new();
+        let mut updated = Vec::new();
+        for (id, path, last_modified) in known {
+            let modified = match fs::metadata(&path).and_then(|meta| meta.modified()) {
+                Ok(modified) => DateTime::<Utc>::from(modified),
+                Err(_) => {
+                    removed.push(id);
+                    continue;
+                }
+            };
+            if modified == last_modified {
+                continue;
+            }
+            let metadata = self.read_track_metadata(&path);
+            self.pause_after_read();
+            match metadata {
+                Ok(metadata) => updated.push(Track { metadata, id }),
+                Err(e) => warn!("Keeping {:?}, which can no longer be read: {}", path, e),
+            }
+        }
+
+        let mut added = Vec::new();
+        {
+            let track_paths = self.track_paths.lock().unwrap();
+            for directory in directories.iter().filter(|directory| directory.is_dir()) {
+                for entry in WalkDir::new(directory)
+                    .follow_links(false)
+                    .into_iter()
+                    .filter_entry(|e| e.file_name() != trash::FALLBACK_TRASH_DIR)
+                    .filter_map(|e| e.ok())
+                {
+                    let path = entry.path();
+                    if !path.is_file()
+                        || !is_supported_audio_format(path, &self.extensions)
+                        || track_paths.contains_key(path)
+                    {
+                        continue;
+                    }
+                    let metadata = self.read_track_metadata(path);
+                    self.pause_after_read();
+                    match metadata {
+                        Ok(metadata) => added.push(Track {
+                            metadata,
+                            id: track_identifier(path),
+                        }),
+                        Err(e) => debug!("Skipping unreadable file {:?}: {}", path, e),
+                    }
+                }
+            }
+        }
+
+        let report;
+        {
+            let mut tracks = self.tracks.lock().unwrap();
+            let mut track_paths = self.track_paths.lock().unwrap();
+
+            // Tracks changed while the refresh ran are newer than what it read
+            let journal = self.changes.lock().unwrap().take_journal();
+            removed.retain(|id| {
+                tracks
+                    .get(id)
+                    .is_some_and(|track| !journal.contains_key(&track.metadata.file_path))
+            });
+            updated.retain(|track| {
+                tracks.contains_key(&track.id) && !journal.contains_key(&track.metadata.file_path)
+            });
+            added.retain(|track| !journal.contains_key(&track.metadata.file_path));
+
+            report = RefreshReport {
+                added: added.len(),
+                updated: updated.len(),
+                removed: removed.len(),
+            };
+            let delta = LibraryDelta::new(
+                added.iter().map(|track| track.id.clone()).collect(),
+                removed.clone(),
+                updated.iter().map(|track| track.id.clone()).collect(),
+            );
+            for id in &removed {
+                if let Some(track) = tracks.remove(id) {
+                    track_paths.remove(&track.metadata.file_path);
+                }
+            }
+            for track in updated.into_iter().chain(added) {
+                track_paths.insert(track.metadata.file_path.clone(), track.id.clone());
+                tracks.insert(track.id.clone(), track);
+            }
+            if !delta.is_empty() {
+                self.tracks_changed(delta);
+            }
+        }
+
+        if !report.is_empty() {
+            if let Err(e) = self.save_to_cache() {
+                warn!("Failed to save library to cache after refresh: {}", e);
+            }
+        }
+
+        self.scan.finish();
+        Ok(report)
+    }
+
+    /// Report of the last completed scan, if any
+    pub fn last_scan_report(&self) -> Option<ScanReport> {
+        self.last_scan_report.lock().unwrap().clone()
+    }
+
+    /// Scan a single directory, adding the audio files found to `files`
     fn scan_directory(
         &self,
         directory: &Path,
-        tracks: &mut HashMap<String, Track>,
-        track_paths: &mut HashMap<PathBuf, String>,
-    ) -> Result<()> {
+        files: &mut Vec<PathBuf>,
+    ) -> Result<(), LibraryError> {
         eprintln!("Scanning directory contents: {:?}", directory);
+        fs::read_dir(directory).map_err(|source| LibraryError::DirectoryUnreadable {
+            path: directory.to_path_buf(),
+            source,
+        })?;
         let mut file_count = 0;
         let mut audio_file_count = 0;
 
         for entry in WalkDir::new(directory)
             .follow_links(false)
             .into_iter()
+            .filter_entry(|e| e.file_name() != trash::FALLBACK_TRASH_DIR)
             .filter_map(|e| e.ok())
         {
+            if self.scan.is_cancel_requested() {
+                return Err(LibraryError::ScanCancelled);
+            }
             let path = entry.path();
             file_count += 1;
 
-            if path.is_file() && is_supported_audio_format(path) {
+            if path.is_file() && is_supported_audio_format(path, &self.extensions) {
                 audio_file_count += 1;
                 eprintln!("Found audio file: {:?}", path);
-                if let Ok(track) = Track::new(path.to_path_buf()) {
-                    eprintln!("Successfully created track: {}", track.display_name());
-                    tracks.insert(track.id.clone(), track.clone());
-                    track_paths.insert(path.to_path_buf(), track.id);
-                } else {
-                    eprintln!("Failed to create track from: {:?}", path);
-                }
+                files.push(path.to_path_buf());
             }
         }
 
@@ -464,6 +1700,52 @@ impl Library {
         Ok(())
     }
 
+    /// Read the metadata of `files` with up to [`MAX_SCAN_THREADS`] threads, one per
+    /// core. Blocks until all are read, and returns what was read of each file in the
+    /// order given, `None` for files that could not be. Each file read advances
+    /// `progress`. Once the scan is cancelled no more files are read, and what was
+    /// read is incomplete.
+    fn read_files(
+        &self,
+        files: &[PathBuf],
+        progress: &ScanProgressReporter,
+    ) -> Vec<Option<(TrackMetadata, Option<anyhow::Error>)>> {
+        let threads = std::thread::available_parallelism()
+            .map_or(1, |cores| cores.get())
+            .min(MAX_SCAN_THREADS)
+            .min(files.len());
+        let next = AtomicUsize::new(0);
+
+        let mut read: Vec<_> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut read = Vec::new();
+                        loop {
+                            if self.scan.is_cancel_requested() {
+                                break;
+                            }
+                            let index = next.fetch_add(1, Ordering::Relaxed);
+                            let Some(path) = files.get(index) else {
+                                break;
+                            };
+                            read.push((index, self.read_metadata(path).ok()));
+                            progress.advance();
+                            self.pause_after_read();
+                        }
+                        read
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("scan thread panicked"))
+                .collect()
+        });
+        read.sort_unstable_by_key(|(index, _)| *index);
+        read.into_iter().map(|(_, read)| read).collect()
+    }
+
     /// Get all tracks
     pub fn get_tracks(&self) -> Vec<Track> {
         let tracks = self.tracks.lock().unwrap();
@@ -477,6 +1759,12 @@ impl Library {
         tracks.get(id).cloned()
     }
 
+    /// Id that replaced `previous_id`, the random id a track had before ids were
+    /// derived from paths, see [`track_identifier`]
+    pub fn renamed_track_id(&self, previous_id: &str) -> Option<String> {
+        self.renamed_ids.lock().unwrap().get(previous_id).cloned()
+    }
+
     /// Get track by file path
     #[allow(dead_code)]
     pub fn get_track_by_path(&self, path: &Path) -> Option<Track> {
@@ -489,31 +1777,319 @@ impl Library {
         }
     }
 
-    /// Search tracks by query
+    /// Tracks tagged with the MusicBrainz recording `mbid`, in any spelling of it.
+    /// Several files of one recording are all returned.
+    pub fn get_tracks_by_musicbrainz_id(&self, mbid: &str) -> Vec<Track> {
+        let Some(mbid) = normalize_mbid(mbid) else {
+            return Vec::new();
+        };
+        let tracks = self.tracks.lock().unwrap();
+        let mut found: Vec<Track> = tracks
+            .values()
+            .filter(|track| track.metadata.musicbrainz.track_id.as_deref() == Some(mbid.as_str()))
+            .cloned()
+            .collect();
+        found.sort_by(|a, b| a.metadata.file_path.cmp(&b.metadata.file_path));
+        found
+    }
+
+    /// Get all tracks belonging to an album identifier
+    #[allow(dead_code)]
+    pub fn get_tracks_by_album_id(&self, album_id: &str) -> Vec<Track> {
+        let tracks = self.tracks.lock().unwrap();
+        tracks
+            .values()
+            .filter(|track| track.album_id().as_deref() == Some(album_id))
+            .cloned()
+            .collect()
+    }
+
+    /// Apply the same tag update to several tracks.
+    ///
+    /// Each file is updated independently; a failure on one track does not stop the
+    /// others. The cache is saved once after all files have been processed.
+    pub fn update_tracks_tags(
+        &self,
+        track_ids: &[String],
+        update: &TrackTagUpdate,
+    ) -> Vec<(String, Result<Track, LibraryError>)> {
+        let results: Vec<(String, Result<Track, LibraryError>)> = track_ids
+            .iter()
+            .map(|id| (id.clone(), self.apply_tag_update(id, update)))
+            .collect();
+
+        if results.iter().any(|(_, result)| result.is_ok()) {
+            if let Err(e) = self.save_to_cache() {
+                warn!("Failed to update cache after tag edit: {}", e);
+            }
+        }
+
+        results
+    }
+
+    /// Merge `update` into a track's sidecar, creating it if needed, and reload the
+    /// track's metadata with the sidecar applied
+    pub fn update_track_sidecar(
+        &self,
+        track_id: &str,
+        update: SidecarMetadata,
+    ) -> Result<Track, LibraryError> {
+        let track = self
+            .get_track(track_id)
+            .ok_or_else(|| LibraryError::TrackNotFound(track_id.to_string()))?;
+
+        let path = &track.metadata.file_path;
+        self.read_only.check(path)?;
+        update_sidecar(path, update).map_err(|error| LibraryError::Metadata {
+            path: path.clone(),
+            error,
+        })?;
+        let metadata = TrackMetadata::from_file(path)?;
+        let track = self.store_edited_track(Track {
+            metadata,
+            id: track.id,
+        });
+        if let Err(e) = self.save_to_cache() {
+            warn!("Failed to update cache after sidecar edit: {}", e);
+        }
+
+        Ok(track)
+    }
+
+    fn apply_tag_update(
+        &self,
+        track_id: &str,
+        update: &TrackTagUpdate,
+    ) -> Result<Track, LibraryError> {
+        let mut track = self
+            .get_track(track_id)
+            .ok_or_else(|| LibraryError::TrackNotFound(track_id.to_string()))?;
+
+        let path = &track.metadata.file_path;
+        self.read_only.check(path)?;
+        write_track_tags(path, update).map_err(|error| LibraryError::Metadata {
+            path: path.clone(),
+            error,
+        })?;
+
+        update.apply_to(&mut track.metadata);
+        if let Ok(file_metadata) = std::fs::metadata(&track.metadata.file_path) {
+            track.metadata.file_size = file_metadata.len();
+            if let Ok(modified) = file_metadata.modified() {
+                track.metadata.last_modified = modified.into();
+            }
+        }
+
+        Ok(self.store_edited_track(track))
+    }
+
+    /// Replace a track after its file or sidecar was written. A scan that finished in
+    /// the meantime gave the file a new id, which the track takes over.
+    fn store_edited_track(&self, mut track: Track) -> Track {
+        let mut tracks = self.tracks.lock().unwrap();
+        let track_paths = self.track_paths.lock().unwrap();
+        if let Some(id) = track_paths.get(&track.metadata.file_path) {
+            track.id = id.clone();
+        }
+        tracks.insert(track.id.clone(), track.clone());
+        self.changes
+            .lock()
+            .unwrap()
+            .journal(track.metadata.file_path.clone(), Some(track.clone()));
+        self.tracks_changed(LibraryDelta::updated(vec![track.id.clone()]));
+        track
+    }
+
+    /// Search tracks by query; see [`TrackSearch`] for the filters it may contain
     pub fn search_tracks(&self, query: &str) -> Vec<Track> {
         let tracks = self.tracks.lock().unwrap();
-        let query_lower = query.to_lowercase();
+        let search = TrackSearch::parse(query);
 
         tracks
             .values()
-            .filter(|track| {
-                let title = track.metadata.title.as_deref().unwrap_or("").to_lowercase();
-                let artist = track
-                    .metadata
-                    .artist
-                    .as_deref()
-                    .unwrap_or("")
-                    .to_lowercase();
-                let album = track.metadata.album.as_deref().unwrap_or("").to_lowercase();
-
-                title.contains(&query_lower)
-                    || artist.contains(&query_lower)
-                    || album.contains(&query_lower)
-            })
+            .filter(|track| search.matches(&track.metadata))
             .cloned()
             .collect()
     }
 
+    /// Search-as-you-type completions for `query`, see [`SuggestionIndex::suggest`]
+    pub fn suggest(
+        &self,
+        query: &str,
+        types: &[SuggestionType],
+        limit: usize,
+    ) -> Vec<SuggestionGroup> {
+        let index = {
+            // Tracks are locked first, as when they change, so the index cannot be
+            // built from tracks that changed before it is stored
+            let tracks = self.tracks.lock().unwrap();
+            let mut suggestions = self.suggestions.lock().unwrap();
+            suggestions
+                .get_or_insert_with(|| Arc::new(SuggestionIndex::build(tracks.values())))
+                .clone()
+        };
+        index.suggest(query, types, limit)
+    }
+
+    /// Normalized genres and the raw tag values behind them, see [`GenreIndex::build`]
+    pub fn genre_index(&self) -> Arc<GenreIndex> {
+        // Locked in the same order as `suggest`
+        let tracks = self.tracks.lock().unwrap();
+        let mut genres = self.genres.lock().unwrap();
+        genres
+            .get_or_insert_with(|| {
+                Arc::new(GenreIndex::build(tracks.values(), &self.genre_normalizer))
+            })
+            .clone()
+    }
+
+    /// Break down the tracks of `track_ids` by artist, genre and decade, see
+    /// [`TrackBreakdown::build`]. Also returns how many ids are not in the library.
+    pub fn breakdown<'a>(
+        &self,
+        track_ids: impl IntoIterator<Item = &'a str>,
+    ) -> (TrackBreakdown, usize) {
+        let genres = self.genre_index();
+        let tracks = self.tracks.lock().unwrap();
+        let mut missing = 0;
+        let found: Vec<&Track> = track_ids
+            .into_iter()
+            .filter_map(|id| {
+                let track = tracks.get(id);
+                missing += usize::from(track.is_none());
+                track
+            })
+            .collect();
+        let breakdown = TrackBreakdown::build(found, &genres, &self.collator);
+        (breakdown, missing)
+    }
+
+    /// Rewrite the genre tag of every track whose genre differs from its canonical
+    /// name. With `dry_run` the files are only listed. Files are written
+    /// independently, and ones that could not be are reported with the error.
+    pub fn retag_genres(&self, dry_run: bool) -> Vec<GenreRetagFile> {
+        let index = self.genre_index();
+        let mut files: Vec<GenreRetagFile> = self
+            .get_tracks()
+            .into_iter()
+            .filter_map(|track| {
+                let from = track.metadata.genre?;
+                let to = index.canonical(&from)?.to_string();
+                (from != to).then_some(GenreRetagFile {
+                    track_id: track.id,
+                    path: track.metadata.file_path,
+                    from,
+                    to,
+                    error: None,
+                })
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        if dry_run {
+            return files;
+        }
+
+        let mut retagged = false;
+        for file in &mut files {
+            let update = TrackTagUpdate {
+                genre: Some(file.to.clone()),
+                ..TrackTagUpdate::default()
+            };
+            match self.apply_tag_update(&file.track_id, &update) {
+                Ok(_) => retagged = true,
+                Err(error) => {
+                    warn!("Failed to retag genre of {:?}: {}", file.path, error);
+                    file.error = Some(error.to_string());
+                }
+            }
+        }
+        if retagged {
+            if let Err(e) = self.save_to_cache() {
+                warn!("Failed to update cache after tag edit: {}", e);
+            }
+        }
+        files
+    }
+
+    /// Rewrite `field` in the tags of every track whose value `replace` changes. With
+    /// `dry_run` the files are only listed. Files are written independently, and ones
+    /// that could not be are reported with the error. `progress` is told how many of
+    /// the files were handled so far, out of how many; once `stop` returns true the
+    /// remaining files are left alone and only those handled are returned.
+    pub fn replace_metadata(
+        &self,
+        field: ReplaceField,
+        replace: &MetadataReplace,
+        dry_run: bool,
+        mut progress: impl FnMut(usize, usize),
+        stop: impl Fn() -> bool,
+    ) -> Vec<MetadataReplaceFile> {
+        let mut files: Vec<MetadataReplaceFile> = self
+            .get_tracks()
+            .into_iter()
+            .filter_map(|track| {
+                let from = field.value(&track.metadata)?.to_string();
+                let to = replace.apply(&from)?;
+                Some(MetadataReplaceFile {
+                    track_id: track.id,
+                    path: track.metadata.file_path,
+                    from,
+                    to,
+                    error: None,
+                })
+            })
+            .collect();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        let total = files.len();
+        if dry_run {
+            progress(total, total);
+            return files;
+        }
+
+        let mut rewritten = false;
+        let mut handled = 0;
+        for file in &mut files {
+            if stop() {
+                break;
+            }
+            match self.apply_tag_update(&file.track_id, &field.update(file.to.clone())) {
+                Ok(_) => rewritten = true,
+                Err(error) => {
+                    warn!("Failed to rewrite the tags of {:?}: {}", file.path, error);
+                    file.error = Some(error.to_string());
+                }
+            }
+            handled += 1;
+            progress(handled, total);
+        }
+        files.truncate(handled);
+        if rewritten {
+            if let Err(e) = self.save_to_cache() {
+                warn!("Failed to update cache after tag edit: {}", e);
+            }
+        }
+        files
+    }
+
+    /// Drop the suggestion and genre indexes after tracks were added, removed or edited
+    fn tracks_changed(&self, delta: LibraryDelta) {
+        *self.suggestions.lock().unwrap() = None;
+        *self.genres.lock().unwrap() = None;
+        self.changes.lock().unwrap().record(delta);
+    }
+
+    /// Sequence number of the latest change to the tracks; 0 before the first one
+    pub fn change_sequence(&self) -> u64 {
+        self.changes.lock().unwrap().sequence()
+    }
+
+    /// The latest change sequence number, with the tracks added, removed and updated
+    /// after change `sequence`; `None` when that is too far back to tell
+    pub fn changes_since(&self, sequence: u64) -> (u64, Option<LibraryDelta>) {
+        let changes = self.changes.lock().unwrap();
+        (changes.sequence(), changes.since(sequence))
+    }
+
     /// Get tracks by artist
     #[allow(dead_code)]
     pub fn get_tracks_by_artist(&self, artist: &str) -> Vec<Track> {
@@ -536,19 +2112,23 @@ impl Library {
             .collect()
     }
 
-    /// Get all artists
+    /// Get all artists. Artists guessed from file names are left out unless
+    /// [`Library::with_guessed_artists`] is set.
     pub fn get_artists(&self) -> Vec<String> {
         let tracks = self.tracks.lock().unwrap();
         let mut artists = std::collections::HashSet::new();
 
         for track in tracks.values() {
+            if track.metadata.guessed.artist && !self.list_guessed_artists {
+                continue;
+            }
             if let Some(artist) = &track.metadata.artist {
                 artists.insert(artist.clone());
             }
         }
 
         let mut artists: Vec<_> = artists.into_iter().collect();
-        artists.sort();
+        self.collator.sort_by_name(&mut artists, String::as_str);
         artists
     }
 
@@ -564,7 +2144,7 @@ impl Library {
         }
 
         let mut albums: Vec<_> = albums.into_iter().collect();
-        albums.sort();
+        self.collator.sort_by_name(&mut albums, String::as_str);
         albums
     }
 
@@ -575,9 +2155,70 @@ impl Library {
     }
 
     /// Check if library is currently scanning
-    #[allow(dead_code)]
     pub fn is_scanning(&self) -> bool {
-        *self.is_scanning.lock().unwrap()
+        self.scan.is_scanning()
+    }
+
+    /// Ask the running scan to stop before the next file it lists or reads. It fails
+    /// with [`LibraryError::ScanCancelled`] and leaves the library as it was before it
+    /// started: nothing it read is merged, and the cache is not saved. Refreshes
+    /// cannot be cancelled. False if no scan is running.
+    pub fn cancel_scan(&self) -> bool {
+        let cancelled = self.scan.request_cancel();
+        if cancelled {
+            info!("Library scan cancellation requested");
+        }
+        cancelled
+    }
+
+    /// Wait until no scan or refresh is running
+    pub async fn scan_finished(&self) {
+        self.scan.finished().await
+    }
+
+    /// Hold back a change of the tracks while a scan runs: wait for it to finish, or
+    /// fail with a [`ScanInProgressError`] under [`ScanConflict::Reject`].
+    ///
+    /// Changes made during a scan anyway are kept when the scan replaces the tracks,
+    /// so this is about callers seeing the scanned library, not about losing edits.
+    pub async fn guard_mutation(&self) -> Result<(), ScanInProgressError> {
+        match self.scan_conflict {
+            ScanConflict::Wait => {
+                self.scan_finished().await;
+                Ok(())
+            }
+            ScanConflict::Reject if self.is_scanning() => Err(ScanInProgressError {
+                retry_after: scan_guard::SCAN_RETRY_AFTER,
+            }),
+            ScanConflict::Reject => Ok(()),
+        }
+    }
+
+    /// Sleep for the pause set with [`Library::with_scan_pause`], if any
+    fn pause_after_read(&self) {
+        if !self.scan_pause.is_zero() {
+            std::thread::sleep(self.scan_pause);
+        }
+    }
+
+    /// Mark a scan or refresh as started, journaling changes of the tracks until it
+    /// replaces them; false if one is running already.
+    fn begin_scan(&self) -> bool {
+        if !self.scan.begin() {
+            return false;
+        }
+        self.changes.lock().unwrap().start_journal();
+        true
+    }
+
+    /// End a scan that stopped early without touching the tracks
+    fn abandon_scan(&self, error: LibraryError) -> Result<ScanReport, LibraryError> {
+        self.changes.lock().unwrap().take_journal();
+        self.scan.finish();
+        if matches!(error, LibraryError::ScanCancelled) {
+            info!("Library scan cancelled");
+        }
+        Err(error)
     }
 
     /// Get all track IDs that exist in the library
@@ -593,14 +2234,37 @@ impl Library {
         tracks.contains_key(track_id)
     }
 
+    /// Add a track to the library, keeping its identifier (e.g., when a file is restored)
+    pub fn add_track(&self, track: Track) {
+        {
+            let mut tracks = self.tracks.lock().unwrap();
+            let mut track_paths = self.track_paths.lock().unwrap();
+            track_paths.insert(track.metadata.file_path.clone(), track.id.clone());
+            self.changes
+                .lock()
+                .unwrap()
+                .journal(track.metadata.file_path.clone(), Some(track.clone()));
+            self.tracks_changed(LibraryDelta::added(vec![track.id.clone()]));
+            tracks.insert(track.id.clone(), track);
+        }
+
+        if let Err(e) = self.save_to_cache() {
+            warn!("Failed to update cache after adding track: {}", e);
+        }
+    }
+
     /// Remove a track from the library (e.g., when file is deleted)
-    #[allow(dead_code)]
     pub fn remove_track(&self, track_id: &str) -> bool {
         let mut tracks = self.tracks.lock().unwrap();
         let mut track_paths = self.track_paths.lock().unwrap();
 
         if let Some(track) = tracks.remove(track_id) {
             track_paths.remove(&track.metadata.file_path);
+            self.changes
+                .lock()
+                .unwrap()
+                .journal(track.metadata.file_path, None);
+            self.tracks_changed(LibraryDelta::removed(vec![track.id]));
             // Update cache after removal
             drop(tracks);
             drop(track_paths);
@@ -614,17 +2278,16 @@ impl Library {
     }
 }
 
+impl Default for Library {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Initialize the library system
-pub async fn init() -> Result<()> {
+pub async fn init(paths: &Paths) -> Result<(), LibraryError> {
     // Check if cache exists for logging
-    let cache_path = dirs::cache_dir()
-        .unwrap_or_else(|| {
-            dirs::home_dir()
-                .unwrap_or_else(|| PathBuf::from("~"))
-                .join(".cache")
-        })
-        .join("hexendrum")
-        .join("library_cache.json");
+    let cache_path = paths.library_cache_file();
 
     if cache_path.exists() {
         info!("Library cache found at {:?}", cache_path);
@@ -0,0 +1,67 @@
+use std::path::Path;
+
+use lofty::{file::TaggedFileExt, probe::Probe, tag::ItemKey, tag::Tag};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// MusicBrainz identifiers of a track, as tagged by Picard and other taggers.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MusicBrainzIds {
+    /// Recording (MUSICBRAINZ_TRACKID)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "b1a9c0e9-d987-4042-ae91-78d6a3267d69")]
+    pub track_id: Option<String>,
+    /// Release the track is on (MUSICBRAINZ_ALBUMID)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "1dc4c347-a1db-32aa-b14f-bc9cc507b843")]
+    pub release_id: Option<String>,
+    /// First credited artist (MUSICBRAINZ_ARTISTID)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "0383dadf-2a4e-4d10-a46a-e9e041da8eb3")]
+    pub artist_id: Option<String>,
+}
+
+impl MusicBrainzIds {
+    pub fn is_empty(&self) -> bool {
+        self.track_id.is_none() && self.release_id.is_none() && self.artist_id.is_none()
+    }
+
+    /// The identifiers found in `tags`, the first tag holding each winning
+    pub fn from_tags<'a>(tags: impl IntoIterator<Item = &'a Tag>) -> Self {
+        let mut ids = Self::default();
+        for tag in tags {
+            let read = |key: ItemKey| tag.get_string(&key).and_then(normalize_mbid);
+            ids.track_id = ids
+                .track_id
+                .or_else(|| read(ItemKey::MusicBrainzRecordingId));
+            ids.release_id = ids
+                .release_id
+                .or_else(|| read(ItemKey::MusicBrainzReleaseId));
+            ids.artist_id = ids.artist_id.or_else(|| read(ItemKey::MusicBrainzArtistId));
+        }
+        ids
+    }
+
+    /// The identifiers tagged in the file at `path`; none when it cannot be read
+    pub fn read(path: &Path) -> Self {
+        match Probe::open(path).and_then(|probe| probe.read()) {
+            Ok(tagged_file) => Self::from_tags(
+                tagged_file
+                    .primary_tag()
+                    .into_iter()
+                    .chain(tagged_file.tags()),
+            ),
+            Err(_) => Self::default(),
+        }
+    }
+}
+
+/// `value` as a lowercase hyphenated MBID, or `None` when it is not one. Of
+/// multi-valued tags such as "id1; id2" the first is kept.
+pub fn normalize_mbid(value: &str) -> Option<String> {
+    let first = value.split([';', ',', '/']).next()?.trim();
+    Uuid::parse_str(first)
+        .ok()
+        .map(|id| id.hyphenated().to_string())
+}
@@ -0,0 +1,85 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+use super::{Track, TrackMetadata};
+
+/// Weight of a matching genre in [`similarity`]
+const GENRE_WEIGHT: f32 = 0.6;
+/// Weight of a release year in the same decade
+const SAME_DECADE_WEIGHT: f32 = 0.3;
+/// Weight of a release year in the decade before or after
+const ADJACENT_DECADE_WEIGHT: f32 = 0.15;
+/// Weight of an identical duration; shrinks as the durations drift apart
+const DURATION_WEIGHT: f32 = 0.1;
+
+fn normalized_genre(metadata: &TrackMetadata) -> Option<String> {
+    metadata
+        .genre
+        .as_deref()
+        .map(|genre| genre.trim().to_lowercase())
+        .filter(|genre| !genre.is_empty())
+}
+
+/// How similar `candidate` is to `seed`, from 0.0 (nothing in common) to 1.0.
+///
+/// Tracks score for sharing a genre, for being released in the same or an adjacent
+/// decade and for having a similar duration. Fields missing on either track add
+/// nothing. Tempo is not considered since tracks carry no BPM yet.
+pub fn similarity(seed: &TrackMetadata, candidate: &TrackMetadata) -> f32 {
+    let mut score = 0.0;
+
+    if let (Some(seed_genre), Some(genre)) = (normalized_genre(seed), normalized_genre(candidate)) {
+        if seed_genre == genre {
+            score += GENRE_WEIGHT;
+        }
+    }
+
+    if let (Some(seed_year), Some(year)) = (seed.year, candidate.year) {
+        match (seed_year.div_euclid(10) - year.div_euclid(10)).abs() {
+            0 => score += SAME_DECADE_WEIGHT,
+            1 => score += ADJACENT_DECADE_WEIGHT,
+            _ => {}
+        }
+    }
+
+    if let (Some(seed_duration), Some(duration)) = (seed.duration, candidate.duration) {
+        let longest = seed_duration.max(duration);
+        if longest > 0 {
+            let difference = seed_duration.abs_diff(duration) as f32 / longest as f32;
+            score += DURATION_WEIGHT * (1.0 - difference);
+        }
+    }
+
+    score
+}
+
+/// Pick up to `count` tracks similar to `seed`, most similar first.
+///
+/// The seed itself, tracks in `exclude` and tracks without a matching genre or decade
+/// are never picked. Ties are broken by track id so the choice is stable.
+pub fn similar_tracks<'a>(
+    seed: &Track,
+    candidates: &'a [Track],
+    exclude: &HashSet<String>,
+    count: usize,
+) -> Vec<&'a Track> {
+    let mut scored: Vec<(f32, &Track)> = candidates
+        .iter()
+        .filter(|track| track.id != seed.id && !exclude.contains(&track.id))
+        .map(|track| (similarity(&seed.metadata, &track.metadata), track))
+        // Duration alone is too weak a signal to call two tracks similar
+        .filter(|(score, _)| *score > DURATION_WEIGHT)
+        .collect();
+
+    scored.sort_by(|(a_score, a), (b_score, b)| {
+        b_score
+            .partial_cmp(a_score)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| a.id.cmp(&b.id))
+    });
+    scored
+        .into_iter()
+        .take(count)
+        .map(|(_, track)| track)
+        .collect()
+}
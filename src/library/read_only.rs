@@ -0,0 +1,35 @@
+use std::path::{Path, PathBuf};
+
+/// Library files that must never be modified, e.g. on a network share mounted
+/// read-only by several machines
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadOnlyPaths {
+    /// Every library file is read-only (`library.read_only`)
+    pub everything: bool,
+    /// Directories whose files are read-only
+    pub roots: Vec<PathBuf>,
+}
+
+/// A tag, sidecar or file change refused because the file is read-only
+#[derive(Debug, thiserror::Error)]
+#[error("{} is in a read-only library directory", path.display())]
+pub struct ReadOnlyError {
+    pub path: PathBuf,
+}
+
+impl ReadOnlyPaths {
+    /// Whether `path` is read-only
+    pub fn contains(&self, path: &Path) -> bool {
+        self.everything || self.roots.iter().any(|root| path.starts_with(root))
+    }
+
+    /// Fail with a [`ReadOnlyError`] when `path` must not be modified.
+    pub fn check(&self, path: &Path) -> Result<(), ReadOnlyError> {
+        if self.contains(path) {
+            return Err(ReadOnlyError {
+                path: path.to_path_buf(),
+            });
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use std::path::PathBuf;
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::{TrackMetadata, TrackTagUpdate};
+
+/// Longest `find` pattern accepted, in characters
+pub const MAX_FIND_LENGTH: usize = 500;
+/// Most memory a compiled regular expression may take, which bounds the patterns
+/// accepted by what they expand to rather than how they are written
+pub const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// Tag field a find-and-replace rewrites
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplaceField {
+    Title,
+    Artist,
+    AlbumArtist,
+    Album,
+    Genre,
+}
+
+impl ReplaceField {
+    /// The field's value in `metadata`
+    pub fn value(self, metadata: &TrackMetadata) -> Option<&str> {
+        match self {
+            Self::Title => metadata.title.as_deref(),
+            Self::Artist => metadata.artist.as_deref(),
+            Self::AlbumArtist => metadata.album_artist.as_deref(),
+            Self::Album => metadata.album.as_deref(),
+            Self::Genre => metadata.genre.as_deref(),
+        }
+    }
+
+    /// Tag update setting the field to `value`
+    pub fn update(self, value: String) -> TrackTagUpdate {
+        let mut update = TrackTagUpdate::default();
+        let field = match self {
+            Self::Title => &mut update.title,
+            Self::Artist => &mut update.artist,
+            Self::AlbumArtist => &mut update.album_artist,
+            Self::Album => &mut update.album,
+            Self::Genre => &mut update.genre,
+        };
+        *field = Some(value);
+        update
+    }
+}
+
+/// How the `find` of a find-and-replace is matched
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplaceMatch {
+    /// The whole value is `find`, and is replaced as a whole
+    #[default]
+    Exact,
+    /// Every occurrence of `find` within the value is replaced
+    Substring,
+    /// Every match of the regular expression `find` is replaced; the replacement may
+    /// refer to groups as `$1` or `${name}`
+    Regex,
+}
+
+/// Why a find-and-replace was refused
+#[derive(Debug, thiserror::Error)]
+pub enum ReplaceError {
+    #[error("Nothing to find")]
+    EmptyFind,
+    #[error("The pattern is longer than {MAX_FIND_LENGTH} characters")]
+    FindTooLong,
+    #[error("Invalid regular expression: {0}")]
+    InvalidRegex(String),
+}
+
+/// A find-and-replace over one kind of tag value.
+///
+/// Values are compared as they are, and the replaced ones trimmed, as tags are
+/// written; a value is changed when the result differs from it. Regular expressions
+/// use the `regex` crate's syntax, without backreferences or lookaround, and match in
+/// time linear in the value, so no pattern can hang a scan of the library; patterns
+/// are limited to [`MAX_FIND_LENGTH`] characters and [`MAX_REGEX_SIZE`] compiled.
+#[derive(Debug, Clone)]
+pub struct MetadataReplace {
+    find: Finder,
+    replace: String,
+}
+
+#[derive(Debug, Clone)]
+enum Finder {
+    Exact(String),
+    Substring(String),
+    Regex(Regex),
+}
+
+impl MetadataReplace {
+    pub fn new(find: &str, replace: &str, mode: ReplaceMatch) -> Result<Self, ReplaceError> {
+        if find.is_empty() {
+            return Err(ReplaceError::EmptyFind);
+        }
+        if find.chars().count() > MAX_FIND_LENGTH {
+            return Err(ReplaceError::FindTooLong);
+        }
+        let find = match mode {
+            ReplaceMatch::Exact => Finder::Exact(find.to_string()),
+            ReplaceMatch::Substring => Finder::Substring(find.to_string()),
+            ReplaceMatch::Regex => Finder::Regex(
+                RegexBuilder::new(find)
+                    .size_limit(MAX_REGEX_SIZE)
+                    .dfa_size_limit(MAX_REGEX_SIZE)
+                    .build()
+                    .map_err(|error| ReplaceError::InvalidRegex(error.to_string()))?,
+            ),
+        };
+        Ok(Self {
+            find,
+            replace: replace.to_string(),
+        })
+    }
+
+    /// `value` after the replacement, `None` when it is left as it is
+    pub fn apply(&self, value: &str) -> Option<String> {
+        let replaced = match &self.find {
+            Finder::Exact(find) => (value == find).then(|| self.replace.clone())?,
+            Finder::Substring(find) => {
+                if !value.contains(find.as_str()) {
+                    return None;
+                }
+                value.replace(find.as_str(), &self.replace)
+            }
+            Finder::Regex(regex) => {
+                if !regex.is_match(value) {
+                    return None;
+                }
+                regex.replace_all(value, self.replace.as_str()).into_owned()
+            }
+        };
+        let replaced = replaced.trim();
+        (replaced != value).then(|| replaced.to_string())
+    }
+}
+
+/// A file whose tag a find-and-replace changed, or would change
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct MetadataReplaceFile {
+    #[schema(example = "550e8400-e29b-41d4-a716-446655440000")]
+    pub track_id: String,
+    #[schema(value_type = String, example = "/music/track.flac")]
+    pub path: PathBuf,
+    #[schema(example = "Unknown Artist ")]
+    pub from: String,
+    /// The new value; empty when the tag is removed
+    #[schema(example = "Unknown Artist")]
+    pub to: String,
+    /// Why the tag could not be rewritten; `None` when it was, or on a dry run
+    pub error: Option<String>,
+}
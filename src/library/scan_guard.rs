@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+
+/// How long clients refused with a [`ScanInProgressError`] are told to wait
+pub const SCAN_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+/// What happens to a change of the tracks requested while a scan or refresh runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScanConflict {
+    /// The change waits for the scan to finish
+    #[default]
+    Wait,
+    /// The change is refused with a [`ScanInProgressError`]
+    Reject,
+}
+
+/// A change of the tracks refused because a scan is in progress
+#[derive(Debug, thiserror::Error)]
+#[error("A library scan is in progress")]
+pub struct ScanInProgressError {
+    /// When trying again is likely to succeed
+    pub retry_after: Duration,
+}
+
+/// Whether a scan or refresh is running, and whether it was asked to stop
+#[derive(Default)]
+pub(super) struct ScanState {
+    scanning: Mutex<bool>,
+    /// Whether the running one stops when asked; refreshes do not
+    cancellable: AtomicBool,
+    cancel_requested: AtomicBool,
+    /// Wakes async waiters once the scan finishes
+    notify: Notify,
+}
+
+impl ScanState {
+    /// Mark a scan as started; false if one is running already.
+    pub(super) fn begin(&self) -> bool {
+        let mut scanning = self.scanning.lock().unwrap();
+        if *scanning {
+            return false;
+        }
+        *scanning = true;
+        self.cancellable.store(false, Ordering::SeqCst);
+        self.cancel_requested.store(false, Ordering::SeqCst);
+        true
+    }
+
+    /// Let the running scan be cancelled
+    pub(super) fn allow_cancel(&self) {
+        self.cancellable.store(true, Ordering::SeqCst);
+    }
+
+    /// Ask the running scan to stop; false if none that can be is running.
+    pub(super) fn request_cancel(&self) -> bool {
+        let scanning = self.scanning.lock().unwrap();
+        if !*scanning || !self.cancellable.load(Ordering::SeqCst) {
+            return false;
+        }
+        self.cancel_requested.store(true, Ordering::SeqCst);
+        true
+    }
+
+    pub(super) fn is_cancel_requested(&self) -> bool {
+        self.cancel_requested.load(Ordering::SeqCst)
+    }
+
+    pub(super) fn finish(&self) {
+        *self.scanning.lock().unwrap() = false;
+        self.notify.notify_waiters();
+    }
+
+    pub(super) fn is_scanning(&self) -> bool {
+        *self.scanning.lock().unwrap()
+    }
+
+    /// Wait until no scan is running
+    pub(super) async fn finished(&self) {
+        loop {
+            // Registered before checking, so a scan finishing in between still wakes us
+            let notified = self.notify.notified();
+            if !self.is_scanning() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::cmp::Ordering;
+
+use super::TrackMetadata;
+
+/// Comparison of a numeric filter, such as the `>` of `samplerate:>48000`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    /// Split a leading `<`, `<=`, `=`, `>=` or `>` off `value`; none means equal
+    fn split(value: &str) -> (Self, &str) {
+        for (prefix, comparison) in [
+            ("<=", Self::LessOrEqual),
+            (">=", Self::GreaterOrEqual),
+            ("<", Self::Less),
+            (">", Self::Greater),
+            ("=", Self::Equal),
+        ] {
+            if let Some(rest) = value.strip_prefix(prefix) {
+                return (comparison, rest);
+            }
+        }
+        (Self::Equal, value)
+    }
+
+    fn holds(self, ordering: Ordering) -> bool {
+        match self {
+            Self::Less => ordering.is_lt(),
+            Self::LessOrEqual => ordering.is_le(),
+            Self::Equal => ordering.is_eq(),
+            Self::GreaterOrEqual => ordering.is_ge(),
+            Self::Greater => ordering.is_gt(),
+        }
+    }
+}
+
+/// Filter on the technical details of a track
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TechnicalFilter {
+    /// `format:flac`: the codec, such as `flac`, `mp3` or `pcm` for any PCM codec, or
+    /// the file extension, such as `wav`
+    Format(String),
+    /// `samplerate:>48000`, in Hz or with a `k`/`khz` suffix as in `samplerate:44.1k`
+    SampleRate(Comparison, u32),
+}
+
+impl TechnicalFilter {
+    /// Parse a `key:value` search term; `None` for other terms, which are searched as text
+    fn parse(term: &str) -> Option<Self> {
+        let (key, value) = term.split_once(':')?;
+        match key.to_lowercase().as_str() {
+            "format" | "codec" if !value.is_empty() => Some(Self::Format(value.to_lowercase())),
+            "samplerate" => {
+                let (comparison, value) = Comparison::split(value);
+                Some(Self::SampleRate(comparison, parse_sample_rate(value)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether `metadata` passes the filter; tracks without technical details pass
+    /// only format filters matching their extension
+    pub fn matches(&self, metadata: &TrackMetadata) -> bool {
+        let technical = metadata.technical.as_ref();
+        match self {
+            Self::Format(format) => {
+                let codec = technical.and_then(|technical| technical.codec.as_deref());
+                let extension = metadata
+                    .file_path
+                    .extension()
+                    .map(|extension| extension.to_string_lossy().to_lowercase());
+                codec.is_some_and(|codec| {
+                    codec == format
+                        || codec
+                            .strip_prefix(format.as_str())
+                            .is_some_and(|rest| rest.starts_with('_'))
+                }) || extension.as_deref() == Some(format.as_str())
+            }
+            Self::SampleRate(comparison, rate) => technical
+                .and_then(|technical| technical.sample_rate)
+                .is_some_and(|sample_rate| comparison.holds(sample_rate.cmp(rate))),
+        }
+    }
+}
+
+/// A track search: text matched against titles, artists and albums, narrowed by
+/// technical filters such as `format:flac samplerate:>48000`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrackSearch {
+    /// Lowercase text left after taking out the filters
+    pub text: String,
+    pub filters: Vec<TechnicalFilter>,
+}
+
+impl TrackSearch {
+    /// Split `query` into text and filters. Terms that look like filters but are not
+    /// known ones, such as "Live:", stay part of the text.
+    pub fn parse(query: &str) -> Self {
+        let mut filters = Vec::new();
+        let mut words = Vec::new();
+        for term in query.split_whitespace() {
+            match TechnicalFilter::parse(term) {
+                Some(filter) => filters.push(filter),
+                None => words.push(term),
+            }
+        }
+        let text = if filters.is_empty() {
+            query.to_lowercase()
+        } else {
+            words.join(" ").to_lowercase()
+        };
+        Self { text, filters }
+    }
+
+    pub fn matches(&self, metadata: &TrackMetadata) -> bool {
+        let contains = |value: &Option<String>| {
+            value
+                .as_deref()
+                .unwrap_or("")
+                .to_lowercase()
+                .contains(&self.text)
+        };
+        (contains(&metadata.title) || contains(&metadata.artist) || contains(&metadata.album))
+            && self.filters.iter().all(|filter| filter.matches(metadata))
+    }
+}
+
+/// Sample rate in Hz from "48000", "48k" or "44.1khz"
+fn parse_sample_rate(value: &str) -> Option<u32> {
+    let value = value.to_lowercase();
+    let (number, scale) = match value
+        .strip_suffix("khz")
+        .or_else(|| value.strip_suffix('k'))
+    {
+        Some(kilohertz) => (kilohertz, 1000.0),
+        None => (value.strip_suffix("hz").unwrap_or(&value), 1.0),
+    };
+    let rate = number.parse::<f64>().ok()? * scale;
+    (rate.is_finite() && rate >= 0.0 && rate <= u32::MAX as f64).then(|| rate.round() as u32)
+}
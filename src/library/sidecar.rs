@@ -0,0 +1,182 @@
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use utoipa::ToSchema;
+use walkdir::WalkDir;
+
+use super::{trash::FALLBACK_TRASH_DIR, TrackMetadata};
+
+/// Appended to an audio file's name to get its sidecar, e.g. `song.flac.hexendrum.json`.
+pub const SIDECAR_SUFFIX: &str = ".hexendrum.json";
+
+/// Where the metadata of a track comes from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MetadataSource {
+    /// Tags embedded in the audio file
+    #[default]
+    File,
+    /// A `.hexendrum.json` sidecar overriding some of the embedded tags
+    Sidecar,
+}
+
+/// Metadata kept in a sidecar file next to a track that cannot be retagged.
+/// Fields that are present take precedence over the file's tags.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(deny_unknown_fields)]
+pub struct SidecarMetadata {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "Bohemian Rhapsody")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "Queen")]
+    pub artist: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "A Night at the Opera")]
+    pub album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = 1975)]
+    pub year: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = "Rock")]
+    pub genre: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = 11)]
+    pub track_number: Option<u32>,
+}
+
+impl SidecarMetadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Copy the fields present in `other` over these.
+    pub fn merge(&mut self, other: SidecarMetadata) {
+        let SidecarMetadata {
+            title,
+            artist,
+            album,
+            year,
+            genre,
+            track_number,
+        } = other;
+        self.title = title.or(self.title.take());
+        self.artist = artist.or(self.artist.take());
+        self.album = album.or(self.album.take());
+        self.year = year.or(self.year);
+        self.genre = genre.or(self.genre.take());
+        self.track_number = track_number.or(self.track_number);
+    }
+
+    /// Override the fields of `metadata` that the sidecar sets.
+    pub fn apply_to(&self, metadata: &mut TrackMetadata) {
+        if self.is_empty() {
+            return;
+        }
+
+        let text = |value: &Option<String>| {
+            value
+                .as_deref()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        if let Some(title) = text(&self.title) {
+            metadata.title = Some(title);
+            metadata.guessed.title = false;
+        }
+        if let Some(artist) = text(&self.artist) {
+            metadata.artist = Some(artist);
+            metadata.guessed.artist = false;
+        }
+        if let Some(album) = text(&self.album) {
+            metadata.album = Some(album);
+        }
+        if let Some(genre) = text(&self.genre) {
+            metadata.genre = Some(genre);
+        }
+        if self.year.is_some() {
+            metadata.year = self.year;
+        }
+        if self.track_number.is_some() {
+            metadata.track_number = self.track_number;
+        }
+        metadata.metadata_source = MetadataSource::Sidecar;
+    }
+}
+
+/// Path of the sidecar belonging to `audio_path`.
+pub fn sidecar_path(audio_path: &Path) -> PathBuf {
+    let mut name = audio_path.file_name().unwrap_or_default().to_os_string();
+    name.push(SIDECAR_SUFFIX);
+    audio_path.with_file_name(name)
+}
+
+/// Read the sidecar of `audio_path`. Returns `None` when there is none and an error
+/// when it is not valid sidecar JSON.
+pub fn read_sidecar(audio_path: &Path) -> Result<Option<SidecarMetadata>> {
+    let path = sidecar_path(audio_path);
+    let content = match fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error).with_context(|| format!("cannot read {:?}", path)),
+    };
+
+    serde_json::from_str(&content)
+        .map(Some)
+        .with_context(|| format!("invalid sidecar {:?}", path))
+}
+
+/// Merge `update` into the sidecar of `audio_path`, creating it if needed, and return
+/// the sidecar as written. An existing sidecar that cannot be parsed is replaced.
+pub fn update_sidecar(audio_path: &Path, update: SidecarMetadata) -> Result<SidecarMetadata> {
+    if !audio_path.is_file() {
+        return Err(anyhow!("audio file {:?} does not exist", audio_path));
+    }
+
+    let mut sidecar = read_sidecar(audio_path).ok().flatten().unwrap_or_default();
+    sidecar.merge(update);
+
+    let path = sidecar_path(audio_path);
+    let temp_path = path.with_extension("json.tmp");
+    fs::write(&temp_path, serde_json::to_string_pretty(&sidecar)?)?;
+    if let Err(error) = fs::rename(&temp_path, &path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(error.into());
+    }
+
+    Ok(sidecar)
+}
+
+/// Modification time of the sidecar of `audio_path`, if it exists.
+pub fn sidecar_modified(audio_path: &Path) -> Option<chrono::DateTime<chrono::Utc>> {
+    fs::metadata(sidecar_path(audio_path))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(Into::into)
+}
+
+/// Sidecars under `directories` whose audio file no longer exists, sorted by path.
+pub fn orphaned_sidecars(directories: &[PathBuf]) -> Vec<PathBuf> {
+    let mut orphans: Vec<PathBuf> = directories
+        .iter()
+        .flat_map(|directory| {
+            WalkDir::new(directory)
+                .follow_links(false)
+                .into_iter()
+                .filter_entry(|e| e.file_name() != FALLBACK_TRASH_DIR)
+                .filter_map(|e| e.ok())
+        })
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_str()?;
+            let audio_name = name.strip_suffix(SIDECAR_SUFFIX)?;
+            let audio_path = entry.path().with_file_name(audio_name);
+            (!audio_name.is_empty() && !audio_path.exists()).then(|| entry.into_path())
+        })
+        .collect();
+    orphans.sort();
+    orphans.dedup();
+    orphans
+}
@@ -0,0 +1,631 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::{info, warn};
+use utoipa::ToSchema;
+
+use crate::config::Paths;
+
+/// Outcome of a file integrity check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityStatus {
+    /// The file decoded cleanly from start to end
+    Ok,
+    /// The file could be opened but failed to decode, or ended early
+    Corrupt,
+    /// The file could not be opened at all
+    Unreadable,
+}
+
+impl IntegrityStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IntegrityStatus::Ok => "ok",
+            IntegrityStatus::Corrupt => "corrupt",
+            IntegrityStatus::Unreadable => "unreadable",
+        }
+    }
+}
+
+/// Result of the last integrity check of a file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntegrityRecord {
+    pub status: IntegrityStatus,
+    /// Why the file failed verification
+    pub error: Option<String>,
+    /// When the check ran
+    #[serde(with = "crate::utils::serde_rfc3339")]
+    pub checked_at: DateTime<Utc>,
+    /// Modification time of the file when it was checked
+    #[serde(with = "crate::utils::serde_rfc3339")]
+    pub file_modified: DateTime<Utc>,
+}
+
+/// Version of the snapshot and event log formats
+pub const STATS_FORMAT_VERSION: u32 = 1;
+/// Size of the event log past which saving folds it into the snapshot
+pub const DEFAULT_STATS_LOG_LIMIT: u64 = 1024 * 1024;
+
+/// Statistics tracked per file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackStats {
+    #[serde(default)]
+    pub integrity: Option<IntegrityRecord>,
+    /// Where playback of a long file was left off, in seconds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resume_position: Option<u64>,
+    /// Times the file started playing
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub play_count: u32,
+    /// When the file last started playing
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "crate::utils::serde_rfc3339::option"
+    )]
+    pub last_played: Option<DateTime<Utc>>,
+    /// Stars, 1–5
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<u8>,
+    /// Whether the file is marked as a favorite
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub favorite: bool,
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Contents of the snapshot file
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    tracks: HashMap<String, TrackStats>,
+}
+
+/// First line of the event log
+#[derive(Serialize, Deserialize)]
+struct LogHeader {
+    stats_log_version: u32,
+}
+
+/// A change to the stats of one file, as written to the event log.
+///
+/// Events carry resulting values rather than increments, so replaying one the
+/// snapshot already holds, after a crash between writing the snapshot and truncating
+/// the log, changes nothing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum StatsEvent {
+    Integrity {
+        path: String,
+        record: IntegrityRecord,
+    },
+    ResumePosition {
+        path: String,
+        position: Option<u64>,
+    },
+    Played {
+        path: String,
+        play_count: u32,
+        #[serde(with = "crate::utils::serde_rfc3339")]
+        at: DateTime<Utc>,
+    },
+    /// Rating set by hand, or cleared
+    Rated {
+        path: String,
+        rating: Option<u8>,
+    },
+    Favorited {
+        path: String,
+        favorite: bool,
+    },
+    /// Rating and play count taken over from the file's tags
+    TagStats {
+        path: String,
+        rating: Option<u8>,
+        play_count: u32,
+    },
+    /// Stats of a duplicate copy folded into the copy kept in its place
+    Merged {
+        path: String,
+        play_count: u32,
+        #[serde(
+            default,
+            skip_serializing_if = "Option::is_none",
+            with = "crate::utils::serde_rfc3339::option"
+        )]
+        last_played: Option<DateTime<Utc>>,
+        rating: Option<u8>,
+        #[serde(default, skip_serializing_if = "is_false")]
+        favorite: bool,
+    },
+}
+
+impl StatsEvent {
+    fn path(&self) -> &String {
+        match self {
+            StatsEvent::Integrity { path, .. }
+            | StatsEvent::ResumePosition { path, .. }
+            | StatsEvent::Played { path, .. }
+            | StatsEvent::Rated { path, .. }
+            | StatsEvent::Favorited { path, .. }
+            | StatsEvent::TagStats { path, .. }
+            | StatsEvent::Merged { path, .. } => path,
+        }
+    }
+
+    fn apply(&self, tracks: &mut HashMap<String, TrackStats>) {
+        let path = self.path();
+        let stats = tracks.entry(path.clone()).or_default();
+        match self {
+            StatsEvent::Integrity { record, .. } => stats.integrity = Some(record.clone()),
+            StatsEvent::ResumePosition { position, .. } => stats.resume_position = *position,
+            StatsEvent::Played { play_count, at, .. } => {
+                stats.play_count = *play_count;
+                stats.last_played = Some(*at);
+            }
+            StatsEvent::Rated { rating, .. } => stats.rating = *rating,
+            StatsEvent::Favorited { favorite, .. } => stats.favorite = *favorite,
+            StatsEvent::TagStats {
+                rating, play_count, ..
+            } => {
+                stats.rating = *rating;
+                stats.play_count = *play_count;
+            }
+            StatsEvent::Merged {
+                play_count,
+                last_played,
+                rating,
+                favorite,
+                ..
+            } => {
+                stats.play_count = *play_count;
+                stats.last_played = *last_played;
+                stats.rating = *rating;
+                stats.favorite = *favorite;
+            }
+        }
+        if *stats == TrackStats::default() {
+            tracks.remove(path);
+        }
+    }
+}
+
+struct StatsData {
+    tracks: HashMap<String, TrackStats>,
+    /// Events not written to the log yet
+    pending: Vec<StatsEvent>,
+    /// Size of the event log
+    log_bytes: u64,
+}
+
+/// Persistent per-track statistics, keyed by file path.
+///
+/// File paths are used rather than track ids so records survive rescans. Changes are
+/// appended to an event log (`stats.jsonl` next to `stats.json`) instead of rewriting
+/// every record on each play; once the log outgrows its limit, or when the store is
+/// compacted, it is folded into the snapshot. A partly written last line, left by a
+/// crash, is dropped when the log is read.
+pub struct StatsStore {
+    path: PathBuf,
+    log_path: PathBuf,
+    log_limit: u64,
+    data: Arc<Mutex<StatsData>>,
+}
+
+impl StatsStore {
+    /// Open the store in the default configuration directory.
+    pub fn new() -> Self {
+        Self::with_path(Paths::standard().stats_file())
+    }
+
+    /// Open the store backed by a specific snapshot file, with its event log next to
+    /// it.
+    pub fn with_path(path: PathBuf) -> Self {
+        let log_path = path.with_extension("jsonl");
+        let mut tracks = Self::load_snapshot(&path);
+        let log_bytes = Self::replay_log(&log_path, &mut tracks);
+
+        Self {
+            path,
+            log_path,
+            log_limit: DEFAULT_STATS_LOG_LIMIT,
+            data: Arc::new(Mutex::new(StatsData {
+                tracks,
+                pending: Vec::new(),
+                log_bytes,
+            })),
+        }
+    }
+
+    /// Fold the event log into the snapshot once it grows past `bytes`
+    #[allow(dead_code)]
+    pub fn with_log_limit(mut self, bytes: u64) -> Self {
+        self.log_limit = bytes;
+        self
+    }
+
+    fn load_snapshot(path: &Path) -> HashMap<String, TrackStats> {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return HashMap::new(),
+            Err(error) => {
+                warn!("Failed to read stats file {:?}: {}", path, error);
+                return HashMap::new();
+            }
+        };
+
+        match serde_json::from_str::<Snapshot>(&content) {
+            Ok(snapshot) => {
+                if snapshot.version > STATS_FORMAT_VERSION {
+                    warn!(
+                        "Stats file {:?} was written by a newer version (format {}); fields it does not know are dropped when it is saved",
+                        path, snapshot.version
+                    );
+                }
+                snapshot.tracks
+            }
+            // Written before the snapshot was versioned: the records alone
+            Err(_) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!("Failed to parse stats file {:?}: {}", path, error);
+                HashMap::new()
+            }),
+        }
+    }
+
+    /// Apply the events of the log at `log_path` to `tracks`, returning the size of
+    /// the log. A partly written last line is cut off; a log in a newer format is
+    /// moved aside, as its events cannot be read.
+    fn replay_log(log_path: &Path, tracks: &mut HashMap<String, TrackStats>) -> u64 {
+        let content = match std::fs::read(log_path) {
+            Ok(content) => content,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return 0,
+            Err(error) => {
+                warn!("Failed to read stats log {:?}: {}", log_path, error);
+                return 0;
+            }
+        };
+
+        let complete = content
+            .iter()
+            .rposition(|byte| *byte == b'\n')
+            .map_or(0, |end| end + 1);
+        if complete < content.len() {
+            warn!(
+                "Dropping a partly written line at the end of stats log {:?}",
+                log_path
+            );
+            let truncated = std::fs::OpenOptions::new()
+                .write(true)
+                .open(log_path)
+                .and_then(|file| file.set_len(complete as u64));
+            if let Err(error) = truncated {
+                warn!("Failed to truncate stats log {:?}: {}", log_path, error);
+            }
+        }
+
+        let mut lines = content[..complete]
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty());
+        let version = lines
+            .next()
+            .and_then(|line| serde_json::from_slice::<LogHeader>(line).ok())
+            .map(|header| header.stats_log_version);
+        if version.is_none_or(|version| version > STATS_FORMAT_VERSION) {
+            let aside = log_path.with_extension("jsonl.unsupported");
+            warn!(
+                "Stats log {:?} is in an unknown format; moving it to {:?}",
+                log_path, aside
+            );
+            if let Err(error) = std::fs::rename(log_path, &aside) {
+                warn!("Failed to move stats log {:?}: {}", log_path, error);
+            }
+            return 0;
+        }
+
+        let mut skipped = 0;
+        for line in lines {
+            match serde_json::from_slice::<StatsEvent>(line) {
+                Ok(event) => event.apply(tracks),
+                Err(_) => skipped += 1,
+            }
+        }
+        if skipped > 0 {
+            warn!(
+                "Skipped {} unreadable event(s) in stats log {:?}",
+                skipped, log_path
+            );
+        }
+        complete as u64
+    }
+
+    fn lock(&self) -> MutexGuard<'_, StatsData> {
+        self.data.lock().unwrap()
+    }
+
+    /// Apply an event and queue it for the log
+    fn record(data: &mut StatsData, event: StatsEvent) {
+        event.apply(&mut data.tracks);
+        data.pending.push(event);
+    }
+
+    /// Stats recorded for a file.
+    pub fn get(&self, file_path: &Path) -> Option<TrackStats> {
+        self.lock()
+            .tracks
+            .get(&*file_path.to_string_lossy())
+            .cloned()
+    }
+
+    /// Last integrity check recorded for a file.
+    pub fn integrity(&self, file_path: &Path) -> Option<IntegrityRecord> {
+        self.get(file_path).and_then(|stats| stats.integrity)
+    }
+
+    /// Record an integrity check result. Call [`StatsStore::save`] to persist it.
+    pub fn record_integrity(&self, file_path: &Path, record: IntegrityRecord) {
+        let event = StatsEvent::Integrity {
+            path: file_path.to_string_lossy().to_string(),
+            record,
+        };
+        Self::record(&mut self.lock(), event);
+    }
+
+    /// Position, in seconds, to resume a long file from.
+    pub fn resume_position(&self, file_path: &Path) -> Option<u64> {
+        self.get(file_path).and_then(|stats| stats.resume_position)
+    }
+
+    /// Remember where playback of a file was left off, or forget it with `None`.
+    /// Returns whether the position changed. Call [`StatsStore::save`] to persist it.
+    pub fn record_resume_position(&self, file_path: &Path, position: Option<u64>) -> bool {
+        let mut data = self.lock();
+        let key = file_path.to_string_lossy();
+        let current = data
+            .tracks
+            .get(&*key)
+            .and_then(|stats| stats.resume_position);
+        if current == position {
+            return false;
+        }
+        let event = StatsEvent::ResumePosition {
+            path: key.to_string(),
+            position,
+        };
+        Self::record(&mut data, event);
+        true
+    }
+
+    /// Times a file started playing.
+    #[allow(dead_code)]
+    pub fn play_count(&self, file_path: &Path) -> u32 {
+        self.get(file_path).map_or(0, |stats| stats.play_count)
+    }
+
+    /// Count a play of a file, returning its new play count. Call
+    /// [`StatsStore::save`] to persist it.
+    pub fn record_play(&self, file_path: &Path) -> u32 {
+        let mut data = self.lock();
+        let key = file_path.to_string_lossy();
+        let play_count = data.tracks.get(&*key).map_or(0, |stats| stats.play_count) + 1;
+        let event = StatsEvent::Played {
+            path: key.to_string(),
+            play_count,
+            at: Utc::now(),
+        };
+        Self::record(&mut data, event);
+        play_count
+    }
+
+    /// Rating of a file, in stars.
+    #[allow(dead_code)]
+    pub fn rating(&self, file_path: &Path) -> Option<u8> {
+        self.get(file_path).and_then(|stats| stats.rating)
+    }
+
+    /// Rate a file, in stars, or clear its rating with `None`. Returns whether the
+    /// rating changed. Call [`StatsStore::save`] to persist it.
+    pub fn set_rating(&self, file_path: &Path, rating: Option<u8>) -> bool {
+        let mut data = self.lock();
+        let key = file_path.to_string_lossy();
+        if data.tracks.get(&*key).and_then(|stats| stats.rating) == rating {
+            return false;
+        }
+        let event = StatsEvent::Rated {
+            path: key.to_string(),
+            rating,
+        };
+        Self::record(&mut data, event);
+        true
+    }
+
+    /// Mark a file as a favorite, or unmark it. Returns whether that changed. Call
+    /// [`StatsStore::save`] to persist it.
+    pub fn set_favorite(&self, file_path: &Path, favorite: bool) -> bool {
+        let mut data = self.lock();
+        let key = file_path.to_string_lossy();
+        if data.tracks.get(&*key).is_some_and(|stats| stats.favorite) == favorite {
+            return false;
+        }
+        let event = StatsEvent::Favorited {
+            path: key.to_string(),
+            favorite,
+        };
+        Self::record(&mut data, event);
+        true
+    }
+
+    /// Merge a rating and play count read from a file's tags: the rating is taken when
+    /// the file has none, and the play count when it is higher than the local one.
+    /// Returns whether each was taken. Call [`StatsStore::save`] to persist them.
+    pub fn merge_tag_stats(
+        &self,
+        file_path: &Path,
+        rating: Option<u8>,
+        play_count: Option<u32>,
+    ) -> (bool, bool) {
+        let mut data = self.lock();
+        let key = file_path.to_string_lossy();
+        let current = data.tracks.get(&*key).cloned().unwrap_or_default();
+        let takes_rating = current.rating.is_none() && rating.is_some();
+        let takes_play_count = play_count.is_some_and(|count| count > current.play_count);
+        if takes_rating || takes_play_count {
+            let event = StatsEvent::TagStats {
+                path: key.to_string(),
+                rating: current.rating.or(rating),
+                play_count: current.play_count.max(play_count.unwrap_or(0)),
+            };
+            Self::record(&mut data, event);
+        }
+        (takes_rating, takes_play_count)
+    }
+
+    /// Fold the stats of a duplicate copy into those of the copy kept in its place:
+    /// play counts are summed, the higher rating and the later last play are kept,
+    /// and either being a favorite makes the keeper one. Call [`StatsStore::save`] to
+    /// persist them.
+    pub fn merge_duplicate(&self, discarded: &Path, keeper: &Path) {
+        let mut data = self.lock();
+        let Some(copy) = data.tracks.get(&*discarded.to_string_lossy()).cloned() else {
+            return;
+        };
+        let key = keeper.to_string_lossy();
+        let current = data.tracks.get(&*key).cloned().unwrap_or_default();
+        let event = StatsEvent::Merged {
+            path: key.to_string(),
+            play_count: current.play_count.saturating_add(copy.play_count),
+            last_played: current.last_played.max(copy.last_played),
+            rating: current.rating.max(copy.rating),
+            favorite: current.favorite || copy.favorite,
+        };
+        Self::record(&mut data, event);
+    }
+
+    /// Files whose last integrity check failed.
+    pub fn integrity_failures(&self) -> Vec<(PathBuf, IntegrityRecord)> {
+        let data = self.lock();
+        let mut failures: Vec<(PathBuf, IntegrityRecord)> = data
+            .tracks
+            .iter()
+            .filter_map(|(path, stats)| {
+                let record = stats.integrity.as_ref()?;
+                (record.status != IntegrityStatus::Ok)
+                    .then(|| (PathBuf::from(path), record.clone()))
+            })
+            .collect();
+        failures.sort_by(|a, b| a.0.cmp(&b.0));
+        failures
+    }
+
+    /// Files with records that no longer exist, sorted by path.
+    pub fn stale_records(&self) -> Vec<PathBuf> {
+        let data = self.lock();
+        let mut stale: Vec<PathBuf> = data
+            .tracks
+            .keys()
+            .map(PathBuf::from)
+            .filter(|path| !path.exists())
+            .collect();
+        stale.sort();
+        stale
+    }
+
+    /// Drop the records of files that no longer exist and fold the event log into
+    /// the snapshot. Returns the files whose records were dropped.
+    pub fn compact(&self) -> Result<Vec<PathBuf>> {
+        let stale = self.stale_records();
+        let mut data = self.lock();
+        for path in &stale {
+            data.tracks.remove(&*path.to_string_lossy());
+        }
+        self.write_snapshot(&mut data)?;
+        Ok(stale)
+    }
+
+    /// Append the changes recorded since the last save to the event log, folding the
+    /// log into the snapshot once it outgrows its limit.
+    pub fn save(&self) -> Result<()> {
+        let mut data = self.lock();
+        if data.pending.is_empty() {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        if data.log_bytes == 0 {
+            lines.push_str(&serde_json::to_string(&LogHeader {
+                stats_log_version: STATS_FORMAT_VERSION,
+            })?);
+            lines.push('\n');
+        }
+        for event in &data.pending {
+            lines.push_str(&serde_json::to_string(event)?);
+            lines.push('\n');
+        }
+
+        if let Some(parent) = self.log_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut log = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log_path)?;
+        log.write_all(lines.as_bytes())?;
+        data.pending.clear();
+        data.log_bytes += lines.len() as u64;
+
+        if data.log_bytes > self.log_limit {
+            info!(
+                "Folding the stats log ({} bytes) into {:?}",
+                data.log_bytes, self.path
+            );
+            self.write_snapshot(&mut data)?;
+        }
+        Ok(())
+    }
+
+    /// Write every record to the snapshot and empty the event log
+    fn write_snapshot(&self, data: &mut StatsData) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let snapshot = Snapshot {
+            version: STATS_FORMAT_VERSION,
+            tracks: std::mem::take(&mut data.tracks),
+        };
+        let content = serde_json::to_string(&snapshot);
+        data.tracks = snapshot.tracks;
+        let temp_path = self.path.with_extension("json.tmp");
+        std::fs::write(&temp_path, content?)?;
+        if let Err(error) = std::fs::rename(&temp_path, &self.path) {
+            let _ = std::fs::remove_file(&temp_path);
+            return Err(error.into());
+        }
+
+        // The snapshot holds everything the log did
+        data.pending.clear();
+        match std::fs::remove_file(&self.log_path) {
+            Ok(()) => {}
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {}
+            Err(error) => return Err(error.into()),
+        }
+        data.log_bytes = 0;
+        Ok(())
+    }
+}
+
+impl Default for StatsStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,320 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Bound;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use super::Track;
+
+/// What a search-as-you-type suggestion completes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SuggestionType {
+    Artist,
+    Album,
+    Title,
+}
+
+impl SuggestionType {
+    pub const ALL: [Self; 3] = [Self::Artist, Self::Album, Self::Title];
+}
+
+impl FromStr for SuggestionType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "artist" | "artists" => Ok(Self::Artist),
+            "album" | "albums" => Ok(Self::Album),
+            "title" | "titles" => Ok(Self::Title),
+            other => Err(format!(
+                "Unknown suggestion type '{}', expected artist, album or title",
+                other
+            )),
+        }
+    }
+}
+
+/// Suggestions of one type, best match first
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SuggestionGroup {
+    #[serde(rename = "type")]
+    pub kind: SuggestionType,
+    #[schema(example = json!(["Radiohead", "Rage Against the Machine"]))]
+    pub suggestions: Vec<String>,
+}
+
+/// A distinct artist, album or title
+#[derive(Debug)]
+struct Entry {
+    kind: SuggestionType,
+    /// Most common spelling in the library
+    text: String,
+    /// Folded words joined by single spaces
+    folded: String,
+    /// Tracks carrying the string, used to rank equally good matches
+    tracks: usize,
+}
+
+/// Distinct artist, album and title strings of a library, indexed by their lowercase,
+/// diacritic-folded words, so suggestions cost a lookup per keystroke instead of a
+/// pass over every track.
+#[derive(Debug, Default)]
+pub struct SuggestionIndex {
+    entries: Vec<Entry>,
+    /// Folded word -> entries containing it
+    tokens: BTreeMap<String, Vec<u32>>,
+}
+
+/// How well an entry matches, best first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchTier {
+    /// The string starts with the query
+    Prefix,
+    /// Each query word starts a word of the string
+    WordPrefix,
+    /// Each query word appears inside a word of the string
+    Substring,
+}
+
+impl SuggestionIndex {
+    pub fn build<'a>(tracks: impl IntoIterator<Item = &'a Track>) -> Self {
+        let mut spellings: HashMap<(SuggestionType, String), HashMap<&str, usize>> = HashMap::new();
+        for track in tracks {
+            let metadata = &track.metadata;
+            let artist = metadata.artist.as_deref();
+            let album_artist = metadata
+                .album_artist
+                .as_deref()
+                .filter(|album_artist| Some(*album_artist) != artist);
+            let values = [
+                (SuggestionType::Artist, artist),
+                (SuggestionType::Artist, album_artist),
+                (SuggestionType::Album, metadata.album.as_deref()),
+                (SuggestionType::Title, metadata.title.as_deref()),
+            ];
+
+            for (kind, value) in values {
+                let Some(value) = value.map(str::trim).filter(|value| !value.is_empty()) else {
+                    continue;
+                };
+                let folded = fold_words(value);
+                if folded.is_empty() {
+                    continue;
+                }
+                *spellings
+                    .entry((kind, folded))
+                    .or_default()
+                    .entry(value)
+                    .or_default() += 1;
+            }
+        }
+
+        let mut index = Self::default();
+        for ((kind, folded), spellings) in spellings {
+            let tracks = spellings.values().sum();
+            let text = spellings
+                .into_iter()
+                .max_by(|(a, a_count), (b, b_count)| a_count.cmp(b_count).then_with(|| b.cmp(a)))
+                .map(|(text, _)| text.to_string())
+                .unwrap_or_default();
+
+            let id = index.entries.len() as u32;
+            let mut words: Vec<&str> = folded.split(' ').collect();
+            words.sort_unstable();
+            words.dedup();
+            for word in words {
+                index.tokens.entry(word.to_string()).or_default().push(id);
+            }
+            index.entries.push(Entry {
+                kind,
+                text,
+                folded,
+                tracks,
+            });
+        }
+        index
+    }
+
+    /// Up to `limit` distinct strings of each of `types` matching `query`, grouped in
+    /// the order of `types`.
+    ///
+    /// Strings starting with the query rank first, then strings whose words start with
+    /// the query's words, then strings containing them; ties go to the string found on
+    /// more tracks. Matching ignores case and diacritics.
+    pub fn suggest(
+        &self,
+        query: &str,
+        types: &[SuggestionType],
+        limit: usize,
+    ) -> Vec<SuggestionGroup> {
+        let query = fold_words(query);
+        let words: Vec<&str> = query.split(' ').filter(|word| !word.is_empty()).collect();
+
+        let mut groups: Vec<(SuggestionType, Vec<(MatchTier, u32)>)> =
+            types.iter().map(|kind| (*kind, Vec::new())).collect();
+        if words.is_empty() || limit == 0 {
+            return finish(groups, &self.entries);
+        }
+
+        let word_prefix = self.matching(&words, |token, word| token.starts_with(word), true);
+        for id in &word_prefix {
+            let entry = &self.entries[*id as usize];
+            if let Some((_, matches)) = groups.iter_mut().find(|(kind, _)| *kind == entry.kind) {
+                let tier = if entry.folded.starts_with(&query) {
+                    MatchTier::Prefix
+                } else {
+                    MatchTier::WordPrefix
+                };
+                matches.push((tier, *id));
+            }
+        }
+
+        // Substring matches are only looked for when prefix matches leave room
+        if groups.iter().any(|(_, matches)| matches.len() < limit) {
+            let substring = self.matching(&words, |token, word| token.contains(word), false);
+            let mut is_prefix_match = vec![false; self.entries.len()];
+            for id in &word_prefix {
+                is_prefix_match[*id as usize] = true;
+            }
+            for id in substring {
+                if is_prefix_match[id as usize] {
+                    continue;
+                }
+                let kind = self.entries[id as usize].kind;
+                if let Some((_, matches)) = groups.iter_mut().find(|(group, _)| *group == kind) {
+                    matches.push((MatchTier::Substring, id));
+                }
+            }
+        }
+
+        for (_, matches) in groups.iter_mut() {
+            let key = |(tier, id): &(MatchTier, u32)| {
+                let entry = &self.entries[*id as usize];
+                (*tier, Reverse(entry.tracks), entry.text.as_str())
+            };
+            if matches.len() > limit {
+                matches.select_nth_unstable_by(limit - 1, |a, b| key(a).cmp(&key(b)));
+                matches.truncate(limit);
+            }
+            matches.sort_unstable_by(|a, b| key(a).cmp(&key(b)));
+        }
+        finish(groups, &self.entries)
+    }
+
+    /// Entries with, for every query word, a word accepted by `matches`. With
+    /// `prefix_only`, only index words starting with the query word are looked at.
+    fn matching(
+        &self,
+        words: &[&str],
+        matches: impl Fn(&str, &str) -> bool,
+        prefix_only: bool,
+    ) -> Vec<u32> {
+        // Number of query words an entry matched so far; an entry only counts for a
+        // word once it matched all the words before it
+        let mut matched_words = vec![0usize; self.entries.len()];
+        let mut result = Vec::new();
+
+        for (position, word) in words.iter().enumerate() {
+            let candidates: Box<dyn Iterator<Item = (&String, &Vec<u32>)>> = if prefix_only {
+                Box::new(
+                    self.tokens
+                        .range::<str, _>((Bound::Included(*word), Bound::Unbounded))
+                        .take_while(|(token, _)| token.starts_with(word)),
+                )
+            } else {
+                Box::new(self.tokens.iter())
+            };
+
+            let mut any = false;
+            for (_, ids) in candidates.filter(|(token, _)| matches(token, word)) {
+                for id in ids {
+                    let count = &mut matched_words[*id as usize];
+                    if *count == position {
+                        *count += 1;
+                        any = true;
+                        if *count == words.len() {
+                            result.push(*id);
+                        }
+                    }
+                }
+            }
+            if !any {
+                return Vec::new();
+            }
+        }
+
+        result
+    }
+}
+
+fn finish(
+    groups: Vec<(SuggestionType, Vec<(MatchTier, u32)>)>,
+    entries: &[Entry],
+) -> Vec<SuggestionGroup> {
+    groups
+        .into_iter()
+        .map(|(kind, matches)| SuggestionGroup {
+            kind,
+            suggestions: matches
+                .into_iter()
+                .map(|(_, id)| entries[id as usize].text.clone())
+                .collect(),
+        })
+        .collect()
+}
+
+/// Lowercase `text`, strip diacritics and join its words with single spaces, e.g.
+/// "Björk / Sigur Rós" becomes "bjork sigur ros".
+pub fn fold_words(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    let mut separated = true;
+    for ch in text.chars().flat_map(char::to_lowercase) {
+        if ch.is_alphanumeric() {
+            match fold_char(ch) {
+                Some(base) => folded.push_str(base),
+                None => folded.push(ch),
+            }
+            separated = false;
+        } else if !separated {
+            folded.push(' ');
+            separated = true;
+        }
+    }
+    if folded.ends_with(' ') {
+        folded.pop();
+    }
+    folded
+}
+
+/// Base letters of a lowercase Latin letter with diacritics
+pub(super) fn fold_char(ch: char) -> Option<&'static str> {
+    Some(match ch {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'ď' | 'đ' | 'ð' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'ĵ' => "j",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ś' | 'ŝ' | 'ş' | 'š' | 'ș' => "s",
+        'ţ' | 'ť' | 'ŧ' | 'ț' => "t",
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        'ß' => "ss",
+        'æ' => "ae",
+        'œ' => "oe",
+        'þ' => "th",
+        _ => return None,
+    })
+}
@@ -0,0 +1,205 @@
+use std::path::Path;
+
+use anyhow::Result;
+use lofty::config::ParseOptions;
+use lofty::file::FileType;
+use lofty::flac::FlacFile;
+use lofty::id3::v2::{Frame, Id3v2Tag};
+use lofty::iff::aiff::AiffFile;
+use lofty::iff::wav::WavFile;
+use lofty::mpeg::MpegFile;
+use lofty::prelude::{AudioFile, TaggedFileExt};
+use lofty::probe::Probe;
+use lofty::tag::{ItemKey, ItemValue};
+use serde::Serialize;
+use tracing::debug;
+use utoipa::ToSchema;
+
+use super::{StatsStore, Track};
+
+/// Highest rating, in stars
+pub const MAX_RATING: u8 = 5;
+
+/// Scale a rating is stored on in file tags
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RatingScale {
+    /// ID3 POPM byte, 1–255 with 0 for unrated. Windows Media Player, MusicBee and
+    /// foobar2000 write 1, 64, 128, 196 and 255 for one to five stars, and MusicBee
+    /// 13, 54, 118, 186 and 242 for half stars.
+    Popm,
+    /// Stars, 0–5, as foobar2000 writes to `RATING`
+    Stars,
+    /// 0–100, as MusicBee and MediaMonkey write to `RATING`, 20 per star
+    Percent,
+    /// 0.0–1.0, as `FMPS_Rating` is specified, 0.2 per star
+    Fraction,
+}
+
+impl RatingScale {
+    /// Rating in whole stars, 1–5; half stars round up and 0 is unrated
+    pub fn to_rating(self, value: f64) -> Option<u8> {
+        if !value.is_finite() || value <= 0.0 {
+            return None;
+        }
+        let stars = match self {
+            // Bands centred on the Windows Media Player values
+            Self::Popm => {
+                return Some(match value.min(255.0) as u8 {
+                    0 => return None,
+                    1..=31 => 1,
+                    32..=95 => 2,
+                    96..=159 => 3,
+                    160..=223 => 4,
+                    _ => 5,
+                })
+            }
+            Self::Stars => value,
+            Self::Percent => value / 20.0,
+            Self::Fraction => value * 5.0,
+        };
+        // Tolerate float noise such as 0.6 * 5 = 3.0000000000000004
+        Some(((stars - 1e-6).ceil() as u8).clamp(1, MAX_RATING))
+    }
+
+    /// Scale of a `RATING` text tag, which players write on different scales: up to 5
+    /// is stars, up to 100 percent and above that a POPM byte
+    pub fn guess(value: f64) -> Self {
+        if value <= f64::from(MAX_RATING) {
+            Self::Stars
+        } else if value <= 100.0 {
+            Self::Percent
+        } else {
+            Self::Popm
+        }
+    }
+}
+
+/// Rating and play count found in the tags of a file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TagStats {
+    /// Stars, 1–5
+    pub rating: Option<u8>,
+    pub play_count: Option<u32>,
+}
+
+impl TagStats {
+    fn add_rating(&mut self, rating: Option<u8>) {
+        self.rating = self.rating.max(rating);
+    }
+
+    fn add_play_count(&mut self, play_count: Option<u32>) {
+        self.play_count = self.play_count.max(play_count.filter(|count| *count > 0));
+    }
+}
+
+/// Read the rating and play count of `path` from ID3 POPM frames, `FMPS_Rating` and
+/// `FMPS_Playcount` tags and `RATING` tags. When several are present the highest
+/// rating and play count win.
+pub fn read_tag_stats(path: &Path) -> Result<TagStats> {
+    let tagged_file = Probe::open(path)?.read()?;
+    let mut stats = TagStats::default();
+
+    for frame in id3v2_tag(path).iter().flatten() {
+        if let Frame::Popularimeter(popm) = frame {
+            stats.add_rating(RatingScale::Popm.to_rating(f64::from(popm.rating)));
+            stats.add_play_count(Some(u32::try_from(popm.counter).unwrap_or(u32::MAX)));
+        }
+    }
+
+    for item in tagged_file.tags().iter().flat_map(|tag| tag.items()) {
+        match (item.key(), item.value()) {
+            (ItemKey::Popularimeter, ItemValue::Text(text)) => {
+                if let Some(value) = parse_number(text) {
+                    stats.add_rating(RatingScale::guess(value).to_rating(value));
+                }
+            }
+            (ItemKey::Unknown(key), ItemValue::Text(text)) => {
+                let Some(value) = parse_number(text) else {
+                    continue;
+                };
+                if key.eq_ignore_ascii_case("FMPS_Rating") {
+                    stats.add_rating(RatingScale::Fraction.to_rating(value));
+                } else if key.eq_ignore_ascii_case("FMPS_Playcount") && value >= 0.0 {
+                    // Fractional counts record partial plays
+                    stats.add_play_count(Some(value.floor().min(f64::from(u32::MAX)) as u32));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(stats)
+}
+
+/// ID3v2 tag of `path`, read on its own as lofty leaves POPM frames out of the
+/// generic tags
+fn id3v2_tag(path: &Path) -> Option<Id3v2Tag> {
+    let probe = Probe::open(path).ok()?.guess_file_type().ok()?;
+    let file_type = probe.file_type()?;
+    let mut reader = probe.into_inner();
+    let options = ParseOptions::new().read_properties(false);
+    match file_type {
+        FileType::Mpeg => MpegFile::read_from(&mut reader, options)
+            .ok()?
+            .remove_id3v2(),
+        FileType::Wav => WavFile::read_from(&mut reader, options)
+            .ok()?
+            .remove_id3v2(),
+        FileType::Aiff => AiffFile::read_from(&mut reader, options)
+            .ok()?
+            .remove_id3v2(),
+        FileType::Flac => FlacFile::read_from(&mut reader, options)
+            .ok()?
+            .remove_id3v2(),
+        _ => None,
+    }
+}
+
+fn parse_number(text: &str) -> Option<f64> {
+    text.trim()
+        .parse()
+        .ok()
+        .filter(|value: &f64| value.is_finite())
+}
+
+/// Outcome of importing ratings and play counts from file tags
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct TagStatsImport {
+    /// Tracks whose tags were read
+    #[schema(example = 1200)]
+    pub scanned: usize,
+    /// Tracks that had no rating and took the one from their tags
+    #[schema(example = 310)]
+    pub ratings: usize,
+    /// Tracks whose play count was raised to the one in their tags
+    #[schema(example = 845)]
+    pub play_counts: usize,
+    /// Tracks whose tags could not be read
+    #[schema(example = 2)]
+    pub failed: usize,
+}
+
+/// Merge the ratings and play counts in the tags of `tracks` into `stats`. Ratings
+/// only fill in tracks without one and play counts only ever rise, so local listening
+/// history is never lost. Call [`StatsStore::save`] to persist the changes.
+pub fn import_tag_stats<'a>(
+    tracks: impl IntoIterator<Item = &'a Track>,
+    stats: &StatsStore,
+) -> TagStatsImport {
+    let mut report = TagStatsImport::default();
+    for track in tracks {
+        let path = &track.metadata.file_path;
+        let tags = match read_tag_stats(path) {
+            Ok(tags) => tags,
+            Err(error) => {
+                debug!("Cannot read the tags of {:?}: {}", path, error);
+                report.failed += 1;
+                continue;
+            }
+        };
+        report.scanned += 1;
+        let (rating, play_count) = stats.merge_tag_stats(path, tags.rating, tags.play_count);
+        report.ratings += usize::from(rating);
+        report.play_counts += usize::from(play_count);
+    }
+    report
+}
@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use lofty::{
+    config::WriteOptions,
+    file::{AudioFile, TaggedFileExt},
+    prelude::Accessor,
+    probe::Probe,
+    tag::{ItemKey, Tag},
+};
+
+use super::TrackMetadata;
+
+/// Field updates applied to a track's tags. `None` leaves the field untouched,
+/// while an empty string removes the value from the file.
+#[derive(Debug, Clone, Default)]
+pub struct TrackTagUpdate {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album_artist: Option<String>,
+    pub album: Option<String>,
+    pub genre: Option<String>,
+    pub year: Option<i32>,
+}
+
+impl TrackTagUpdate {
+    /// Whether the update would change nothing.
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none()
+            && self.artist.is_none()
+            && self.album_artist.is_none()
+            && self.album.is_none()
+            && self.genre.is_none()
+            && self.year.is_none()
+    }
+
+    /// Apply the update to in-memory metadata, mirroring what was written to the file.
+    pub fn apply_to(&self, metadata: &mut TrackMetadata) {
+        if let Some(value) = &self.title {
+            metadata.title = normalize_tag_value(value);
+            metadata.guessed.title = false;
+        }
+
+        if let Some(value) = &self.artist {
+            metadata.artist = normalize_tag_value(value);
+            metadata.guessed.artist = false;
+        }
+
+        if let Some(value) = &self.album_artist {
+            metadata.album_artist = normalize_tag_value(value);
+        }
+
+        if let Some(value) = &self.album {
+            metadata.album = normalize_tag_value(value);
+        }
+
+        if let Some(value) = &self.genre {
+            metadata.genre = normalize_tag_value(value);
+        }
+
+        if let Some(value) = self.year {
+            metadata.year = (value > 0).then_some(value);
+        }
+    }
+}
+
+/// Write tag updates to a single audio file.
+///
+/// The tags are written to a temporary copy next to the original which then replaces
+/// it, so a failed write never leaves a half-written file behind.
+pub fn write_track_tags(path: &Path, update: &TrackTagUpdate) -> Result<()> {
+    if update.is_empty() {
+        return Ok(());
+    }
+
+    let temp_path = temporary_path(path)?;
+    std::fs::copy(path, &temp_path)?;
+
+    let result = write_tags_in_place(&temp_path, update)
+        .and_then(|_| std::fs::rename(&temp_path, path).map_err(Into::into));
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+
+    result
+}
+
+fn write_tags_in_place(path: &Path, update: &TrackTagUpdate) -> Result<()> {
+    let mut tagged_file = Probe::open(path)?.guess_file_type()?.read()?;
+
+    if tagged_file.primary_tag().is_none() {
+        let tag_type = tagged_file.primary_tag_type();
+        tagged_file.insert_tag(Tag::new(tag_type));
+    }
+
+    let tag = tagged_file
+        .primary_tag_mut()
+        .ok_or_else(|| anyhow!("file format does not support tags"))?;
+
+    apply_to_tag(tag, update);
+
+    tagged_file.save_to_path(path, WriteOptions::default())?;
+    Ok(())
+}
+
+fn apply_to_tag(tag: &mut Tag, update: &TrackTagUpdate) {
+    if let Some(value) = update.title.as_deref() {
+        match normalize_tag_value(value) {
+            Some(value) => tag.set_title(value),
+            None => tag.remove_title(),
+        }
+    }
+
+    if let Some(value) = update.artist.as_deref() {
+        match normalize_tag_value(value) {
+            Some(value) => tag.set_artist(value),
+            None => tag.remove_artist(),
+        }
+    }
+
+    if let Some(value) = update.album_artist.as_deref() {
+        match normalize_tag_value(value) {
+            Some(value) => {
+                tag.insert_text(ItemKey::AlbumArtist, value);
+            }
+            None => tag.remove_key(&ItemKey::AlbumArtist),
+        }
+    }
+
+    if let Some(value) = update.album.as_deref() {
+        match normalize_tag_value(value) {
+            Some(value) => tag.set_album(value),
+            None => tag.remove_album(),
+        }
+    }
+
+    if let Some(value) = update.genre.as_deref() {
+        match normalize_tag_value(value) {
+            Some(value) => tag.set_genre(value),
+            None => tag.remove_genre(),
+        }
+    }
+
+    if let Some(year) = update.year {
+        match u32::try_from(year) {
+            Ok(year) if year > 0 => tag.set_year(year),
+            _ => tag.remove_year(),
+        }
+    }
+}
+
+fn temporary_path(path: &Path) -> Result<PathBuf> {
+    let file_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("invalid file name: {:?}", path))?;
+
+    Ok(path.with_file_name(format!(".{}.hexendrum-tmp", file_name)))
+}
+
+fn normalize_tag_value(value: &str) -> Option<String> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        None
+    } else {
+        Some(trimmed.to_string())
+    }
+}
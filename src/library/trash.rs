@@ -0,0 +1,358 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, Local, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+use crate::config::Paths;
+use crate::utils::ensure_directory;
+
+/// Name of the trash folder created next to deleted files when the platform trash
+/// cannot be used.
+pub const FALLBACK_TRASH_DIR: &str = ".hexendrum-trash";
+
+/// How often expired trash entries are purged.
+const PURGE_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60 * 60);
+
+/// What deleting a track does to its file.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeleteMode {
+    /// Deleting files is not allowed
+    #[default]
+    Forbid,
+    /// Files are moved to the trash and can be restored
+    Trash,
+    /// Files are unlinked
+    Permanent,
+}
+
+/// A file moved to the trash.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrashEntry {
+    pub track_id: String,
+    pub original_path: PathBuf,
+    pub trash_path: PathBuf,
+    /// freedesktop `.trashinfo` file describing the entry, if one was written
+    pub info_path: Option<PathBuf>,
+    #[serde(with = "crate::utils::serde_rfc3339")]
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// Moves deleted files to the trash and keeps a journal so they can be restored.
+///
+/// On Linux the freedesktop home trash is used when the file lives on the same
+/// filesystem; otherwise files go to a `.hexendrum-trash` folder next to them.
+pub struct Trash {
+    journal_path: PathBuf,
+    home_trash: Option<PathBuf>,
+    retention: Duration,
+    entries: Mutex<Vec<TrashEntry>>,
+}
+
+impl Trash {
+    /// Open the deletion journal in the default configuration directory.
+    #[allow(dead_code)]
+    pub fn new(retention_days: u32) -> Self {
+        let paths = Paths::standard();
+        Self::with_paths(paths.trash_journal_file(), paths.home_trash, retention_days)
+    }
+
+    /// Open a journal at `journal_path`, using `home_trash` as the freedesktop trash.
+    pub fn with_paths(
+        journal_path: PathBuf,
+        home_trash: Option<PathBuf>,
+        retention_days: u32,
+    ) -> Self {
+        let entries = Self::load_journal(&journal_path);
+
+        Self {
+            journal_path,
+            home_trash,
+            retention: Duration::days(i64::from(retention_days)),
+            entries: Mutex::new(entries),
+        }
+    }
+
+    fn load_journal(path: &Path) -> Vec<TrashEntry> {
+        if !path.exists() {
+            return Vec::new();
+        }
+
+        match fs::read_to_string(path) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+                warn!("Failed to parse trash journal {:?}: {}", path, error);
+                Vec::new()
+            }),
+            Err(error) => {
+                warn!("Failed to read trash journal {:?}: {}", path, error);
+                Vec::new()
+            }
+        }
+    }
+
+    /// Move a track's file to the trash and record it in the journal.
+    pub fn move_to_trash(&self, track_id: &str, path: &Path) -> Result<TrashEntry> {
+        let original_path = std::path::absolute(path)?;
+        if !original_path.is_file() {
+            return Err(anyhow!("{} is not a file", original_path.display()));
+        }
+
+        let deleted_at = Utc::now();
+        let (trash_path, info_path) = match self.move_to_home_trash(&original_path, deleted_at) {
+            Ok(paths) => paths,
+            Err(error) => {
+                if self.home_trash.is_some() {
+                    warn!(
+                        "Could not use the home trash for {:?}, falling back to {}: {}",
+                        original_path, FALLBACK_TRASH_DIR, error
+                    );
+                }
+                (self.move_to_fallback_trash(&original_path)?, None)
+            }
+        };
+
+        let entry = TrashEntry {
+            track_id: track_id.to_string(),
+            original_path,
+            trash_path,
+            info_path,
+            deleted_at,
+        };
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.push(entry.clone());
+        self.save(&entries)?;
+
+        info!("Moved {:?} to {:?}", entry.original_path, entry.trash_path);
+        Ok(entry)
+    }
+
+    fn move_to_home_trash(
+        &self,
+        path: &Path,
+        deleted_at: DateTime<Utc>,
+    ) -> Result<(PathBuf, Option<PathBuf>)> {
+        let trash = self
+            .home_trash
+            .as_ref()
+            .ok_or_else(|| anyhow!("no home trash on this platform"))?;
+        let files_dir = trash.join("files");
+        let info_dir = trash.join("info");
+        ensure_directory(&files_dir)?;
+        ensure_directory(&info_dir)?;
+
+        let file_name = unique_name(path, |name| {
+            files_dir.join(name).exists() || info_dir.join(format!("{}.trashinfo", name)).exists()
+        })?;
+        let trash_path = files_dir.join(&file_name);
+        let info_path = info_dir.join(format!("{}.trashinfo", file_name));
+
+        // Writing the info file first reserves the name, as required by the spec.
+        let info = format!(
+            "[Trash Info]\nPath={}\nDeletionDate={}\n",
+            percent_encode_path(path),
+            deleted_at.with_timezone(&Local).format("%Y-%m-%dT%H:%M:%S")
+        );
+        fs::write(&info_path, info)?;
+
+        // A rename only works within one filesystem; other files use the fallback.
+        if let Err(error) = fs::rename(path, &trash_path) {
+            let _ = fs::remove_file(&info_path);
+            return Err(error.into());
+        }
+
+        Ok((trash_path, Some(info_path)))
+    }
+
+    fn move_to_fallback_trash(&self, path: &Path) -> Result<PathBuf> {
+        let parent = path
+            .parent()
+            .ok_or_else(|| anyhow!("{} has no parent directory", path.display()))?;
+        let trash_dir = parent.join(FALLBACK_TRASH_DIR);
+        ensure_directory(&trash_dir)?;
+
+        let file_name = unique_name(path, |name| trash_dir.join(name).exists())?;
+        let trash_path = trash_dir.join(file_name);
+        fs::rename(path, &trash_path)?;
+        Ok(trash_path)
+    }
+
+    /// Whether a deletion of the track within the retention window is recorded.
+    pub fn is_restorable(&self, track_id: &str) -> bool {
+        self.restorable_index(&self.entries.lock().unwrap(), track_id)
+            .is_some()
+    }
+
+    /// Where the most recently deleted file of a track used to live, if it is journaled.
+    pub fn original_path(&self, track_id: &str) -> Option<PathBuf> {
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|entry| entry.track_id == track_id)
+            .map(|entry| entry.original_path.clone())
+    }
+
+    /// Whether a restorable deletion of a file that lived at `path` is journaled.
+    pub fn is_restorable_path(&self, path: &Path) -> bool {
+        let cutoff = Utc::now() - self.retention;
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|entry| entry.original_path == path && entry.deleted_at >= cutoff)
+    }
+
+    fn restorable_index(&self, entries: &[TrashEntry], track_id: &str) -> Option<usize> {
+        let cutoff = Utc::now() - self.retention;
+        entries
+            .iter()
+            .rposition(|entry| entry.track_id == track_id && entry.deleted_at >= cutoff)
+    }
+
+    /// Move the most recently trashed file of a track back to its original location.
+    pub fn restore(&self, track_id: &str) -> Result<TrashEntry> {
+        let mut entries = self.entries.lock().unwrap();
+        let index = self
+            .restorable_index(&entries, track_id)
+            .ok_or_else(|| anyhow!("no restorable deletion recorded for track {}", track_id))?;
+        let entry = entries[index].clone();
+
+        if entry.original_path.exists() {
+            return Err(anyhow!(
+                "{} already exists, refusing to overwrite it",
+                entry.original_path.display()
+            ));
+        }
+        if let Some(parent) = entry.original_path.parent() {
+            ensure_directory(parent)?;
+        }
+
+        fs::rename(&entry.trash_path, &entry.original_path)?;
+        if let Some(info_path) = &entry.info_path {
+            let _ = fs::remove_file(info_path);
+        }
+        remove_empty_fallback_dir(&entry.trash_path);
+
+        entries.remove(index);
+        self.save(&entries)?;
+
+        info!("Restored {:?} from the trash", entry.original_path);
+        Ok(entry)
+    }
+
+    /// Drop journal entries older than the retention window, permanently deleting
+    /// their files from the `.hexendrum-trash` folders. Files in the home trash are
+    /// left for the desktop to empty. Returns the number of entries purged.
+    pub fn purge_expired(&self) -> Result<usize> {
+        let mut entries = self.entries.lock().unwrap();
+        let cutoff = Utc::now() - self.retention;
+
+        let (expired, kept): (Vec<TrashEntry>, Vec<TrashEntry>) = entries
+            .drain(..)
+            .partition(|entry| entry.deleted_at < cutoff);
+        *entries = kept;
+
+        for entry in expired.iter().filter(|entry| is_in_fallback_trash(entry)) {
+            if let Err(error) = fs::remove_file(&entry.trash_path) {
+                if error.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to purge {:?}: {}", entry.trash_path, error);
+                }
+            }
+            remove_empty_fallback_dir(&entry.trash_path);
+        }
+
+        if !expired.is_empty() {
+            self.save(&entries)?;
+            info!("Purged {} expired trash entries", expired.len());
+        }
+
+        Ok(expired.len())
+    }
+
+    /// Purge expired entries now and then periodically in the background.
+    pub fn spawn_purge_job(self: &Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let trash = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(error) = trash.purge_expired() {
+                    warn!("Failed to purge expired trash entries: {}", error);
+                }
+            }
+        })
+    }
+
+    fn save(&self, entries: &[TrashEntry]) -> Result<()> {
+        if let Some(parent) = self.journal_path.parent() {
+            ensure_directory(parent)?;
+        }
+        let content = serde_json::to_string_pretty(entries)?;
+        fs::write(&self.journal_path, content)?;
+        Ok(())
+    }
+}
+
+/// Pick a file name for `path` that `taken` reports as free, appending a counter
+/// before the extension on collisions.
+fn unique_name(path: &Path, taken: impl Fn(&str) -> bool) -> Result<String> {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .ok_or_else(|| anyhow!("{} has no file name", path.display()))?;
+    let extension = path
+        .extension()
+        .map(|ext| format!(".{}", ext.to_string_lossy()))
+        .unwrap_or_default();
+
+    let mut name = format!("{}{}", stem, extension);
+    let mut counter = 1;
+    while taken(&name) {
+        counter += 1;
+        name = format!("{}.{}{}", stem, counter, extension);
+    }
+    Ok(name)
+}
+
+/// Whether an entry's file was moved to a `.hexendrum-trash` folder rather than the
+/// home trash
+fn is_in_fallback_trash(entry: &TrashEntry) -> bool {
+    entry.info_path.is_none()
+        && entry
+            .trash_path
+            .parent()
+            .and_then(Path::file_name)
+            .is_some_and(|name| name == FALLBACK_TRASH_DIR)
+}
+
+fn remove_empty_fallback_dir(trash_path: &Path) {
+    if let Some(dir) = trash_path.parent() {
+        if dir
+            .file_name()
+            .is_some_and(|name| name == FALLBACK_TRASH_DIR)
+        {
+            // Fails harmlessly while other files remain in it.
+            let _ = fs::remove_dir(dir);
+        }
+    }
+}
+
+/// Percent-encode a path for the `Path=` key of a `.trashinfo` file.
+fn percent_encode_path(path: &Path) -> String {
+    use std::fmt::Write;
+
+    let mut encoded = String::new();
+    for byte in path.to_string_lossy().bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            let _ = write!(encoded, "%{:02X}", byte);
+        }
+    }
+    encoded
+}
@@ -0,0 +1,397 @@
+use anyhow::{anyhow, bail, Result};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+use utoipa::ToSchema;
+
+use super::Track;
+
+/// Peaks stored per track; requests for fewer points are downsampled from these
+pub const WAVEFORM_BUCKETS: usize = 800;
+
+/// Tracks decoded at the same time. Kept low so waveforms never compete with the
+/// playback decoder for CPU.
+pub const WAVEFORM_CONCURRENCY: usize = 2;
+
+/// Start of a cached waveform file, followed by the format version
+const CACHE_MAGIC: &[u8; 4] = b"HXWF";
+const CACHE_VERSION: u8 = 1;
+/// Magic, version, modification time (seconds and nanoseconds), size and peak count
+const CACHE_HEADER_LEN: usize = 4 + 1 + 8 + 4 + 8 + 4;
+
+/// Lowest and highest sample of a stretch of audio, across all channels, scaled
+/// from -1.0..1.0 to -127..127
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Peak {
+    pub min: i8,
+    pub max: i8,
+}
+
+impl Peak {
+    fn merge(self, other: Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+}
+
+/// Peaks of a track from start to end, for drawing its waveform
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Waveform {
+    pub peaks: Vec<Peak>,
+}
+
+impl Waveform {
+    /// At most `points` peaks spanning the whole track, each merging the stored peaks
+    /// it covers. Waveforms are never upsampled, so short tracks may have fewer.
+    pub fn downsample(&self, points: usize) -> Vec<Peak> {
+        downsample(&self.peaks, points)
+    }
+}
+
+fn downsample(peaks: &[Peak], points: usize) -> Vec<Peak> {
+    if points == 0 || peaks.len() <= points {
+        return peaks.to_vec();
+    }
+    (0..points)
+        .map(|point| {
+            let start = point * peaks.len() / points;
+            let end = ((point + 1) * peaks.len() / points).max(start + 1);
+            peaks[start..end]
+                .iter()
+                .copied()
+                .reduce(Peak::merge)
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+/// `sample` on the -127..127 scale of [`Peak`]
+fn quantize(sample: f32) -> i8 {
+    (sample.clamp(-1.0, 1.0) * 127.0).round() as i8
+}
+
+/// Collects the peaks of blocks of `block_frames` frames as samples are decoded
+struct EnvelopeBuilder {
+    block_frames: u64,
+    frames: u64,
+    min: f32,
+    max: f32,
+    blocks: Vec<Peak>,
+}
+
+impl EnvelopeBuilder {
+    fn new(block_frames: u64) -> Self {
+        Self {
+            block_frames: block_frames.max(1),
+            frames: 0,
+            min: f32::INFINITY,
+            max: f32::NEG_INFINITY,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Add interleaved samples of `channels` channels
+    fn push(&mut self, samples: &[f32], channels: usize) {
+        for frame in samples.chunks(channels.max(1)) {
+            for sample in frame {
+                self.min = self.min.min(*sample);
+                self.max = self.max.max(*sample);
+            }
+            self.frames += 1;
+            if self.frames == self.block_frames {
+                self.finish_block();
+            }
+        }
+    }
+
+    fn finish_block(&mut self) {
+        if self.frames > 0 {
+            self.blocks.push(Peak {
+                min: quantize(self.min),
+                max: quantize(self.max),
+            });
+        }
+        self.frames = 0;
+        self.min = f32::INFINITY;
+        self.max = f32::NEG_INFINITY;
+    }
+
+    fn finish(mut self, buckets: usize) -> Waveform {
+        self.finish_block();
+        Waveform {
+            peaks: downsample(&self.blocks, buckets),
+        }
+    }
+}
+
+/// Decode `path` into at most `buckets` peaks
+pub fn compute_waveform(path: &Path, buckets: usize) -> Result<Waveform> {
+    use symphonia::core::{
+        audio::SampleBuffer, codecs::DecoderOptions, errors::Error as SymphoniaError,
+        formats::FormatOptions, io::MediaSourceStream, meta::MetadataOptions, probe::Hint,
+    };
+
+    let mss = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let probed = symphonia::default::get_probe().format(
+        &hint,
+        mss,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    )?;
+    let mut format = probed.format;
+    let track = format
+        .default_track()
+        .ok_or_else(|| anyhow!("No default audio track found"))?;
+    let track_id = track.id;
+    let codec_params = track.codec_params.clone();
+    let mut decoder =
+        symphonia::default::get_codecs().make(&codec_params, &DecoderOptions::default())?;
+
+    // Several blocks per bucket when the length is known, so buckets line up with the
+    // track closely; otherwise blocks of about 5 ms at 48 kHz
+    let block_frames = codec_params
+        .n_frames
+        .map(|frames| frames / (buckets.max(1) as u64 * 4))
+        .unwrap_or(256);
+    let mut envelope = EnvelopeBuilder::new(block_frames);
+    let mut samples: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(_)) | Err(SymphoniaError::ResetRequired) => break,
+            Err(error) => return Err(error.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                let spec = *decoded.spec();
+                let buffer = match samples.as_mut() {
+                    Some(buffer)
+                        if buffer.capacity() >= decoded.capacity() * spec.channels.count() =>
+                    {
+                        buffer
+                    }
+                    _ => samples.insert(SampleBuffer::new(decoded.capacity() as u64, spec)),
+                };
+                buffer.copy_interleaved_ref(decoded);
+                envelope.push(buffer.samples(), spec.channels.count());
+            }
+            Err(SymphoniaError::DecodeError(error)) => {
+                debug!("Skipping undecodable packet of {:?}: {}", path, error)
+            }
+            Err(SymphoniaError::ResetRequired) => decoder.reset(),
+            Err(error) => return Err(error.into()),
+        }
+    }
+
+    let waveform = envelope.finish(buckets);
+    if waveform.peaks.is_empty() {
+        bail!("No decodable audio in {:?}", path);
+    }
+    Ok(waveform)
+}
+
+/// Modification time and size a cached waveform was computed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileStamp {
+    modified_secs: u64,
+    modified_nanos: u32,
+    size: u64,
+}
+
+impl FileStamp {
+    fn of(path: &Path) -> Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        Ok(Self {
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+            size: metadata.len(),
+        })
+    }
+}
+
+fn encode(stamp: FileStamp, waveform: &Waveform) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(CACHE_HEADER_LEN + waveform.peaks.len() * 2);
+    bytes.extend_from_slice(CACHE_MAGIC);
+    bytes.push(CACHE_VERSION);
+    bytes.extend_from_slice(&stamp.modified_secs.to_le_bytes());
+    bytes.extend_from_slice(&stamp.modified_nanos.to_le_bytes());
+    bytes.extend_from_slice(&stamp.size.to_le_bytes());
+    bytes.extend_from_slice(&(waveform.peaks.len() as u32).to_le_bytes());
+    for peak in &waveform.peaks {
+        bytes.extend_from_slice(&[peak.min as u8, peak.max as u8]);
+    }
+    bytes
+}
+
+/// The waveform in `bytes`, if it was computed for a file with `stamp`
+fn decode(bytes: &[u8], stamp: FileStamp) -> Option<Waveform> {
+    let (header, data) = bytes.split_at_checked(CACHE_HEADER_LEN)?;
+    if &header[..4] != CACHE_MAGIC || header[4] != CACHE_VERSION {
+        return None;
+    }
+    let stored = FileStamp {
+        modified_secs: u64::from_le_bytes(header[5..13].try_into().ok()?),
+        modified_nanos: u32::from_le_bytes(header[13..17].try_into().ok()?),
+        size: u64::from_le_bytes(header[17..25].try_into().ok()?),
+    };
+    let count = u32::from_le_bytes(header[25..29].try_into().ok()?) as usize;
+    if stored != stamp || data.len() != count * 2 {
+        return None;
+    }
+    Some(Waveform {
+        peaks: data
+            .chunks_exact(2)
+            .map(|pair| Peak {
+                min: pair[0] as i8,
+                max: pair[1] as i8,
+            })
+            .collect(),
+    })
+}
+
+/// Outcome of computing the waveforms of many tracks ahead of time
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, ToSchema)]
+pub struct WaveformPrecompute {
+    /// Tracks whose waveform was computed
+    #[schema(example = 120)]
+    pub computed: usize,
+    /// Tracks whose waveform was cached already
+    #[schema(example = 1080)]
+    pub cached: usize,
+    /// Tracks that could not be decoded
+    #[schema(example = 2)]
+    pub failed: usize,
+}
+
+/// Waveforms of tracks, computed on first use and kept in a binary file per track.
+/// A cached waveform is used as long as its file keeps the same modification time
+/// and size.
+pub struct WaveformCache {
+    dir: PathBuf,
+    permits: Semaphore,
+}
+
+impl WaveformCache {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            permits: Semaphore::new(WAVEFORM_CONCURRENCY),
+        }
+    }
+
+    fn cache_path(&self, path: &Path) -> PathBuf {
+        let key = Sha256::digest(path.to_string_lossy().as_bytes());
+        self.dir.join(format!("{:x}.peaks", key))
+    }
+
+    /// The cached waveform of `path`, if it is still current
+    pub fn cached(&self, path: &Path) -> Option<Waveform> {
+        let stamp = FileStamp::of(path).ok()?;
+        decode(&fs::read(self.cache_path(path)).ok()?, stamp)
+    }
+
+    /// The waveform of `path`, computing and caching it when needed. Returns whether
+    /// it was computed. Blocks while decoding.
+    pub fn get_or_compute(&self, path: &Path) -> Result<(Waveform, bool)> {
+        if let Some(waveform) = self.cached(path) {
+            return Ok((waveform, false));
+        }
+        let stamp = FileStamp::of(path)?;
+        let waveform = compute_waveform(path, WAVEFORM_BUCKETS)?;
+        if let Err(error) = self.store(path, stamp, &waveform) {
+            warn!("Failed to cache the waveform of {:?}: {}", path, error);
+        }
+        Ok((waveform, true))
+    }
+
+    fn store(&self, path: &Path, stamp: FileStamp, waveform: &Waveform) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let cache_path = self.cache_path(path);
+        let temp_path = cache_path.with_extension("tmp");
+        fs::write(&temp_path, encode(stamp, waveform))?;
+        if let Err(error) = fs::rename(&temp_path, &cache_path) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(error.into());
+        }
+        Ok(())
+    }
+
+    /// The waveform of `path`, decoding at most [`WAVEFORM_CONCURRENCY`] tracks at a
+    /// time across all callers
+    pub async fn load(self: &Arc<Self>, path: PathBuf) -> Result<Waveform> {
+        let _permit = self.permits.acquire().await?;
+        let cache = self.clone();
+        tokio::task::spawn_blocking(move || {
+            cache.get_or_compute(&path).map(|(waveform, _)| waveform)
+        })
+        .await?
+    }
+
+    /// Compute the missing waveforms of `tracks` with [`WAVEFORM_CONCURRENCY`]
+    /// threads. Blocks until all are done.
+    #[allow(dead_code)]
+    pub fn precompute(&self, tracks: &[Track]) -> WaveformPrecompute {
+        self.precompute_with(tracks, |_| {}, || false)
+    }
+
+    /// Like [`WaveformCache::precompute`], calling `on_progress` with the number of
+    /// tracks done after each one and stopping early once `cancelled` returns true
+    pub fn precompute_with(
+        &self,
+        tracks: &[Track],
+        on_progress: impl Fn(usize) + Sync,
+        cancelled: impl Fn() -> bool + Sync,
+    ) -> WaveformPrecompute {
+        let next = AtomicUsize::new(0);
+        let done = AtomicUsize::new(0);
+        let computed = AtomicUsize::new(0);
+        let cached = AtomicUsize::new(0);
+        let failed = AtomicUsize::new(0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..WAVEFORM_CONCURRENCY {
+                scope.spawn(|| {
+                    while let Some(track) = tracks.get(next.fetch_add(1, Ordering::Relaxed)) {
+                        if cancelled() {
+                            break;
+                        }
+                        let path = &track.metadata.file_path;
+                        match self.get_or_compute(path) {
+                            Ok((_, true)) => computed.fetch_add(1, Ordering::Relaxed),
+                            Ok((_, false)) => cached.fetch_add(1, Ordering::Relaxed),
+                            Err(error) => {
+                                debug!("Cannot compute the waveform of {:?}: {}", path, error);
+                                failed.fetch_add(1, Ordering::Relaxed)
+                            }
+                        };
+                        on_progress(done.fetch_add(1, Ordering::Relaxed) + 1);
+                    }
+                });
+            }
+        });
+
+        WaveformPrecompute {
+            computed: computed.into_inner(),
+            cached: cached.into_inner(),
+            failed: failed.into_inner(),
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+
+use super::albums::normalize_primary_artist;
+use super::Track;
+
+/// Work and movement parsed from a classical track title.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsedWorkTitle {
+    pub work: String,
+    pub movement: Option<String>,
+    pub movement_number: Option<u32>,
+}
+
+/// A movement (or the single track) of a work.
+#[derive(Debug, Clone)]
+pub struct WorkMovement {
+    pub track_id: String,
+    pub title: Option<String>,
+    pub movement: Option<String>,
+    pub movement_number: Option<u32>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub track_number: Option<u32>,
+    pub duration: Option<u64>,
+}
+
+/// Tracks of one composition, gathered across albums and performers.
+#[derive(Debug, Clone)]
+pub struct Work {
+    pub composer: String,
+    pub title: String,
+    pub movements: Vec<WorkMovement>,
+}
+
+/// Split a track title following the common "Work: Movement" naming into its parts.
+///
+/// Handles "Symphony No. 5 in C minor, Op. 67: II. Andante con moto" as well as
+/// "Piano Sonata No. 14 - I. Adagio sostenuto". A " - " separator is only accepted
+/// when it is followed by a movement number, so suffixes such as " - Live" are left
+/// alone. Returns `None` when the title does not name a movement.
+pub fn parse_work_title(title: &str) -> Option<ParsedWorkTitle> {
+    let title = title.trim();
+
+    if let Some((work, rest)) = title.split_once(": ") {
+        let work = work.trim();
+        let rest = rest.trim();
+        if !work.is_empty() && !rest.is_empty() {
+            let (movement_number, movement) = match split_movement_number(rest) {
+                Some((number, name)) => (Some(number), name),
+                None => (None, Some(rest.to_string())),
+            };
+            return Some(ParsedWorkTitle {
+                work: work.to_string(),
+                movement,
+                movement_number,
+            });
+        }
+    }
+
+    let (work, rest) = title.rsplit_once(" - ")?;
+    let (number, movement) = split_movement_number(rest.trim())?;
+    let work = work.trim();
+    if work.is_empty() {
+        return None;
+    }
+
+    Some(ParsedWorkTitle {
+        work: work.to_string(),
+        movement,
+        movement_number: Some(number),
+    })
+}
+
+/// Group tracks with a composer tag by composer and work, optionally keeping only one
+/// composer. WORK/MOVEMENT tags take precedence over names parsed from the title.
+/// Movements are ordered by movement number, then by album and track number.
+pub fn group_works(tracks: &[Track], composer: Option<&str>) -> Vec<Work> {
+    let composer_filter = composer.and_then(|value| normalize_primary_artist(Some(value)));
+    let mut works: HashMap<(String, String), Work> = HashMap::new();
+
+    for track in tracks {
+        let metadata = &track.metadata;
+        let Some(track_composer) = metadata.composer.as_deref() else {
+            continue;
+        };
+        let Some(composer_key) = normalize_primary_artist(Some(track_composer)) else {
+            continue;
+        };
+        if composer_filter
+            .as_ref()
+            .is_some_and(|filter| *filter != composer_key)
+        {
+            continue;
+        }
+
+        let title = metadata
+            .title
+            .as_deref()
+            .map(|title| strip_composer_prefix(title, track_composer));
+        let parsed = title.and_then(parse_work_title);
+
+        let (work_title, movement, movement_number) = match (&metadata.work, parsed) {
+            (Some(work), parsed) => {
+                let from_title = title.and_then(split_movement_number);
+                let movement = metadata
+                    .movement
+                    .clone()
+                    .or_else(|| parsed.as_ref().and_then(|p| p.movement.clone()))
+                    .or_else(|| from_title.as_ref().and_then(|(_, name)| name.clone()));
+                let number = metadata
+                    .movement_number
+                    .or_else(|| parsed.as_ref().and_then(|p| p.movement_number))
+                    .or_else(|| from_title.map(|(number, _)| number));
+                (work.clone(), movement, number)
+            }
+            (None, Some(parsed)) => (parsed.work, parsed.movement, parsed.movement_number),
+            (None, None) => match title {
+                Some(title) => (title.to_string(), None, metadata.movement_number),
+                None => continue,
+            },
+        };
+
+        let key = (composer_key, work_key(&work_title));
+        let work = works.entry(key).or_insert_with(|| Work {
+            composer: track_composer.to_string(),
+            title: work_title,
+            movements: Vec::new(),
+        });
+
+        work.movements.push(WorkMovement {
+            track_id: track.id.clone(),
+            title: metadata.title.clone(),
+            movement,
+            movement_number,
+            artist: metadata.artist.clone(),
+            album: metadata.album.clone(),
+            track_number: metadata.track_number,
+            duration: metadata.duration,
+        });
+    }
+
+    let mut works: Vec<Work> = works.into_values().collect();
+    for work in &mut works {
+        work.movements.sort_by(|a, b| {
+            a.movement_number
+                .unwrap_or(u32::MAX)
+                .cmp(&b.movement_number.unwrap_or(u32::MAX))
+                .then_with(|| a.album.cmp(&b.album))
+                .then_with(|| a.track_number.cmp(&b.track_number))
+                .then_with(|| a.title.cmp(&b.title))
+        });
+    }
+    works.sort_by(|a, b| {
+        a.composer
+            .to_lowercase()
+            .cmp(&b.composer.to_lowercase())
+            .then_with(|| a.title.to_lowercase().cmp(&b.title.to_lowercase()))
+    });
+
+    works
+}
+
+/// Drop a leading "Composer: " from titles such as "Beethoven: Symphony No. 5: I. Allegro".
+fn strip_composer_prefix<'a>(title: &'a str, composer: &str) -> &'a str {
+    let candidates = [
+        composer.trim(),
+        composer.split_whitespace().last().unwrap_or(""),
+    ];
+
+    for candidate in candidates.into_iter().filter(|c| !c.is_empty()) {
+        let Some(prefix) = title.get(..candidate.len()) else {
+            continue;
+        };
+        if prefix.eq_ignore_ascii_case(candidate) {
+            if let Some(rest) = title[candidate.len()..].strip_prefix(": ") {
+                return rest.trim_start();
+            }
+        }
+    }
+
+    title
+}
+
+/// Parse a leading movement number such as "II. ", "3. " or "IV - " and return it with
+/// the remaining movement name.
+fn split_movement_number(text: &str) -> Option<(u32, Option<String>)> {
+    let text = text.trim_start();
+    let end = text
+        .find(|c: char| !(c.is_ascii_digit() || "IVXLC".contains(c)))
+        .unwrap_or(text.len());
+    let (token, rest) = text.split_at(end);
+    let number = parse_movement_token(token)?;
+
+    let rest = if let Some(rest) = rest.strip_prefix(['.', ')']) {
+        if !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+            return None;
+        }
+        rest
+    } else if let Some(rest) = rest.strip_prefix(" - ") {
+        rest
+    } else if rest.is_empty() {
+        rest
+    } else {
+        return None;
+    };
+
+    let name = rest.trim();
+    Some((number, (!name.is_empty()).then(|| name.to_string())))
+}
+
+fn parse_movement_token(token: &str) -> Option<u32> {
+    if token.is_empty() {
+        return None;
+    }
+    if token.bytes().all(|b| b.is_ascii_digit()) {
+        return token.parse().ok().filter(|number| *number > 0);
+    }
+    roman_to_number(token)
+}
+
+fn roman_to_number(token: &str) -> Option<u32> {
+    let value = |c: char| match c {
+        'I' => Some(1),
+        'V' => Some(5),
+        'X' => Some(10),
+        'L' => Some(50),
+        'C' => Some(100),
+        _ => None,
+    };
+
+    let digits: Vec<u32> = token.chars().map(value).collect::<Option<_>>()?;
+    let mut total = 0;
+    for (index, digit) in digits.iter().enumerate() {
+        match digits.get(index + 1) {
+            Some(next) if next > digit => total -= *digit as i64,
+            _ => total += *digit as i64,
+        }
+    }
+
+    let total = u32::try_from(total).ok().filter(|total| *total > 0)?;
+    // Reject malformed numerals such as "IIII" or "VX" by re-encoding the value.
+    (number_to_roman(total) == token).then_some(total)
+}
+
+fn number_to_roman(mut number: u32) -> String {
+    const NUMERALS: [(u32, &str); 9] = [
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+
+    let mut roman = String::new();
+    for (value, numeral) in NUMERALS {
+        while number >= value {
+            roman.push_str(numeral);
+            number -= value;
+        }
+    }
+    roman
+}
+
+fn work_key(work: &str) -> String {
+    work.to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_end_matches(['.', ',', ';', ':'])
+        .to_string()
+}
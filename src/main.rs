@@ -1,24 +1,58 @@
 use anyhow::Result;
-use dirs;
 use events::{EventBus, EventPayload};
 use std::sync::Arc;
-use tracing::{debug, error, info, Level};
+use tracing::{debug, error, info, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 
 mod api;
 mod audio;
 mod config;
+mod ctl;
+mod demo;
+mod diagnostics;
 mod events;
+mod instance;
 mod library;
+mod maintenance;
 mod playlist;
 mod utils;
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    let show_cli_playbar = std::env::args().any(|arg| arg == "--cli-playbar");
+fn main() -> Result<()> {
+    api::run_backend(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()?
+            .block_on(run())
+    })
+}
+
+async fn run() -> Result<()> {
+    let mut args: Vec<String> = std::env::args().collect();
+    let paths = match config::Paths::take_data_dir_arg(&mut args).and_then(config::Paths::resolve) {
+        Ok(paths) => paths,
+        Err(e) => {
+            eprintln!("hexendrum: {}", e);
+            std::process::exit(2);
+        }
+    };
+
+    if args.get(1).map(String::as_str) == Some("ctl") {
+        std::process::exit(ctl::run(&args[2..], &paths).await);
+    }
+
+    if args.get(1).map(String::as_str) == Some("doctor") {
+        std::process::exit(diagnostics::run(&paths).await);
+    }
+
+    if args.get(1).map(String::as_str) == Some("maintenance") {
+        std::process::exit(maintenance::run(&args[2..], &paths).await);
+    }
+
+    let show_cli_playbar = args.iter().any(|arg| arg == "--cli-playbar");
+    let takeover = args.iter().any(|arg| arg == instance::TAKEOVER_FLAG);
 
     // Initialize logging
-    let _subscriber = FmtSubscriber::builder()
+    FmtSubscriber::builder()
         .with_max_level(Level::INFO)
         .with_target(false)
         .with_thread_ids(true)
@@ -28,6 +62,32 @@ async fn main() -> Result<()> {
         .init();
 
     info!("Starting Hexendrum Music Player Backend...");
+    if demo::requested(&args) {
+        return demo::run().await;
+    }
+    debug!(
+        "Using configuration in {:?} and caches in {:?}",
+        paths.config_dir, paths.cache_dir
+    );
+
+    // Load configuration
+    let config = match config::Config::load(&paths) {
+        Ok(config) => config,
+        Err(error) => {
+            debug!(
+                "Could not load config file, using defaults (no auto-scan): {}",
+                error
+            );
+            config::Config::default()
+        }
+    };
+
+    // Make sure no other backend uses the same data directory
+    let instance_lock = match instance::claim(&paths, &config.api, takeover).await {
+        Ok(lock) => lock,
+        Err(code) => std::process::exit(code),
+    };
+    debug!("Holding instance lock {:?}", instance_lock.path());
 
     // Initialize the audio system
     match audio::init().await {
@@ -39,11 +99,11 @@ async fn main() -> Result<()> {
     }
 
     // Initialize the library system
-    match library::init().await {
+    match library::init(&paths).await {
         Ok(_) => info!("Library system initialized successfully"),
         Err(e) => {
             error!("Failed to initialize library system: {}", e);
-            return Err(e);
+            return Err(e.into());
         }
     }
 
@@ -52,73 +112,74 @@ async fn main() -> Result<()> {
         Ok(_) => info!("Playlist system initialized successfully"),
         Err(e) => {
             error!("Failed to initialize playlist system: {}", e);
-            return Err(e);
+            return Err(e.into());
         }
     }
 
     // Initialize library and playlist manager instances
-    let library = Arc::new(library::Library::new());
-
-    // Check if library loaded from cache
-    let cached_track_count = library.track_count();
-    if cached_track_count > 0 {
-        info!("Loaded {} tracks from cache", cached_track_count);
-    } else {
-        info!("No tracks loaded from cache - library is empty");
+    let read_only = config.library.read_only_paths();
+    if read_only.everything {
+        info!("Library is read-only - tags, sidecars and files will not be modified");
     }
-
-    // Load configuration
-    let config = match config::Config::load() {
-        Ok(config) => config,
-        Err(error) => {
-            debug!(
-                "Could not load config file, using defaults (no auto-scan): {}",
-                error
-            );
-            config::Config::default()
-        }
-    };
+    let library = Arc::new(
+        library::Library::deferred(&paths, config.library.content_fingerprints)
+            .with_read_only(read_only)
+            .with_extensions(config.library.supported_extensions.clone())
+            .with_genre_aliases(config.library.genre_aliases.clone())
+            .with_scan_conflict(config.library.scan_conflict)
+            .with_scan_progress_interval(config.library.scan_progress_interval)
+            .with_guessed_artists(config.library.list_guessed_artists)
+            .with_collator(library::Collator::for_locale(
+                config.library.sort_locale.as_deref(),
+            )),
+    );
 
     let event_bus = Arc::new(EventBus::new(None));
 
-    if show_cli_playbar {
-        info!("CLI playbar enabled (--cli-playbar)");
-        spawn_cli_playbar(event_bus.clone());
-    }
-
-    if config.library.auto_scan && !config.library.music_directories.is_empty() {
-        info!(
-            "Auto-scan enabled - scanning {} directory(ies)...",
-            config.library.music_directories.len()
-        );
-        let library_clone = library.clone();
-        let directories = config.library.music_directories.clone();
-        let event_bus_clone = event_bus.clone();
+    // Load the cache in the background so the API is up right away; until it is loaded
+    // library endpoints answer with partial data
+    let since = library.change_sequence();
+    let cache_load = library.load_in_background();
+    {
+        let library = library.clone();
+        let event_bus = event_bus.clone();
         tokio::spawn(async move {
-            event_bus_clone.emit(EventPayload::library_scan("started", None, None));
-            match library_clone.scan_directories(&directories) {
-                Ok(_) => {
-                    let count = library_clone.track_count();
-                    info!("Auto-scan completed: {} tracks found", count);
-                    event_bus_clone.emit(EventPayload::library_scan("completed", None, None));
-                    event_bus_clone.emit(EventPayload::library_updated(count));
-                }
-                Err(error) => {
-                    error!("Auto-scan failed: {}", error);
-                    event_bus_clone.emit(EventPayload::library_scan("failed", None, None));
-                }
+            match cache_load.await {
+                Ok(0) => info!("No tracks loaded from cache - library is empty"),
+                Ok(count) => info!("Loaded {} tracks from cache", count),
+                Err(e) => error!("Loading the library cache failed: {}", e),
             }
+            let (sequence, delta) = library.changes_since(since);
+            event_bus.emit(EventPayload::library_changed(
+                library.track_count(),
+                sequence,
+                delta,
+            ));
         });
-    } else if config.library.music_directories.is_empty() {
-        info!("No music directories configured - skipping auto-scan");
     }
 
+    let webhooks = events::WebhookDispatcher::start(&event_bus, config.webhooks.clone());
+    if !config.webhooks.is_empty() {
+        info!("Webhooks enabled for {} endpoint(s)", config.webhooks.len());
+    }
+    let event_log = events::EventLog::start(&event_bus, &config.events);
+    if let Some(event_log) = event_log.as_ref() {
+        info!("Logging events to {:?}", event_log.path());
+    }
     let lastfm_api_key = config.services.lastfm.api_key.trim().to_string();
-    let album_service = Arc::new(library::AlbumService::new(if lastfm_api_key.is_empty() {
-        None
-    } else {
-        Some(lastfm_api_key.clone())
-    }));
+    let album_service = Arc::new(
+        library::AlbumService::with_paths(
+            &paths,
+            if lastfm_api_key.is_empty() {
+                None
+            } else {
+                Some(lastfm_api_key.clone())
+            },
+        )
+        .with_album_disambiguation(config.library.album_disambiguation)
+        .with_various_artists_threshold(config.library.various_artists_threshold)
+        .with_event_bus(event_bus.clone()),
+    );
 
     if lastfm_api_key.is_empty() {
         info!("Last.fm API key not configured - album artwork caching disabled");
@@ -129,24 +190,48 @@ async fn main() -> Result<()> {
         );
     }
 
-    let playlist_dir = dirs::config_dir()
-        .unwrap_or_else(|| std::path::PathBuf::from("~/.config"))
-        .join("hexendrum")
-        .join("playlists");
-
-    let playlist_manager = match playlist::PlaylistManager::new(playlist_dir.clone()) {
+    let playlist_manager = match playlist::PlaylistManager::new(paths.playlist_dir()) {
         Ok(manager) => {
+            let manager = manager
+                .with_music_roots(config.library.music_roots())
+                .with_portable_paths(config.playlist.portable_paths);
+            if let Err(e) = manager.load_all_playlists() {
+                warn!("Failed to load playlists: {}", e);
+            }
             info!("Playlist manager initialized");
             Arc::new(manager)
         }
         Err(e) => {
             error!("Failed to create playlist manager: {}", e);
-            return Err(e);
+            return Err(e.into());
         }
     };
+    {
+        // Entries saved on another machine keep that library's track ids; link them to
+        // the tracks at their paths here once the library is loaded
+        let library = library.clone();
+        let playlist_manager = playlist_manager.clone();
+        tokio::spawn(async move {
+            library.ready().await;
+            if let Err(e) = playlist_manager.relink_entries(&library, false) {
+                warn!("Linking playlist entries by path failed: {}", e);
+            }
+        });
+    }
+
+    // Shared playback queue used by the API, restoring repeat/shuffle from the last run
+    let playback_queue = Arc::new(playlist::PlaybackQueue::with_state_file(
+        paths.queue_state_file(),
+        config.playlist.default_repeat_mode,
+        config.playlist.default_shuffle,
+    ));
 
     // Create audio player instance
-    let audio_player = match audio::AudioPlayer::new() {
+    let audio_player = match audio::AudioPlayer::with_output_device(
+        config.audio.output_device.clone(),
+        config.audio.stream_request(),
+        Some(event_bus.clone()),
+    ) {
         Ok(player) => {
             info!("Audio player initialized");
             Arc::new(player)
@@ -156,42 +241,268 @@ async fn main() -> Result<()> {
             return Err(e);
         }
     };
+    if let Err(e) = audio_player.set_volume_curve(config.audio.volume_curve) {
+        warn!("Failed to apply the volume curve: {}", e);
+    }
+    if let Err(e) = audio_player.set_output_format(config.audio.output_format()) {
+        warn!("Failed to apply the output format: {}", e);
+    }
+    if let Err(e) = audio_player.set_auto_pause(config.audio.auto_pause_after()) {
+        warn!("Failed to apply auto-pause: {}", e);
+    }
+    if let Err(e) = audio_player.set_pause_fade(config.audio.pause_fade()) {
+        warn!("Failed to apply the pause fade: {}", e);
+    }
+    let precache = (config.audio.precache_mb > 0).then(|| {
+        Arc::new(audio::Precache::new(
+            paths.precache_dir(),
+            config.audio.precache_mb * 1024 * 1024,
+            config.audio.precache_cache_mb * 1024 * 1024,
+        ))
+    });
+    // The limit may have been lowered since the last run
+    if let Some(Err(e)) = precache.as_ref().map(|precache| precache.evict()) {
+        warn!("Failed to trim the precache: {}", e);
+    }
+    if let Err(e) = audio_player.set_precache(precache.clone()) {
+        warn!("Failed to enable precaching: {}", e);
+    }
+    let resolver_library = library.clone();
+    let track_resolver: audio::TrackResolver = Arc::new(move |path: &std::path::Path| {
+        resolver_library
+            .get_track_by_path(path)
+            .map(|track| track.id)
+    });
+    if let Err(e) = audio_player.set_track_resolver(Some(track_resolver)) {
+        warn!("Failed to name finished tracks: {}", e);
+    }
+    let gain_library = library.clone();
+    let gain_resolver: audio::GainResolver = Arc::new(move |path: &std::path::Path| {
+        gain_library
+            .get_track_by_path(path)
+            .map(|track| track.metadata.replaygain)
+            .unwrap_or_else(|| audio::ReplayGain::read(path))
+    });
+    if let Err(e) =
+        audio_player.set_replaygain(config.audio.replaygain_mode, Some(gain_resolver.clone()))
+    {
+        warn!("Failed to apply ReplayGain: {}", e);
+    }
+
+    if show_cli_playbar {
+        info!("CLI playbar enabled (--cli-playbar)");
+        let position_player = audio_player.clone();
+        spawn_cli_playbar(event_bus.clone(), move || position_player.get_position());
+    }
+
+    let position_player = audio_player.clone();
+    if events::NowPlayingWriter::start(
+        &event_bus,
+        &config.integrations,
+        library.clone(),
+        move || position_player.get_position(),
+    )
+    .is_some()
+    {
+        let path = config
+            .integrations
+            .now_playing_file
+            .clone()
+            .unwrap_or_default();
+        info!("Writing the current track to {:?}", path);
+    }
+
+    let up_next = Arc::new(api::UpNextWatcher::new(config.audio.up_next_lead_seconds));
+    let resume_positions = Arc::new(api::ResumePositions::new(
+        u64::from(config.audio.resume_min_minutes) * 60,
+    ));
+
+    let reloaded_webhooks = webhooks.clone();
+    let reloaded_player = audio_player.clone();
+    let reloaded_up_next = up_next.clone();
+    let reloaded_resume_positions = resume_positions.clone();
+    config::watch_config_file(paths.clone(), move |config| {
+        reloaded_webhooks.reload(config.webhooks);
+        reloaded_up_next.set_lead_seconds(config.audio.up_next_lead_seconds);
+        reloaded_resume_positions.set_min_duration(u64::from(config.audio.resume_min_minutes) * 60);
+        if let Err(e) = reloaded_player.set_volume_curve(config.audio.volume_curve) {
+            warn!("Failed to apply the volume curve: {}", e);
+        }
+        if let Err(e) = reloaded_player.set_output_format(config.audio.output_format()) {
+            warn!("Failed to apply the output format: {}", e);
+        }
+        if let Err(e) = reloaded_player.set_auto_pause(config.audio.auto_pause_after()) {
+            warn!("Failed to apply auto-pause: {}", e);
+        }
+        if let Err(e) = reloaded_player.set_pause_fade(config.audio.pause_fade()) {
+            warn!("Failed to apply the pause fade: {}", e);
+        }
+        if let Err(e) = reloaded_player
+            .set_replaygain(config.audio.replaygain_mode, Some(gain_resolver.clone()))
+        {
+            warn!("Failed to apply ReplayGain: {}", e);
+        }
+    });
+
+    let trash = Arc::new(library::Trash::with_paths(
+        paths.trash_journal_file(),
+        paths.home_trash.clone(),
+        config.library.trash_retention_days,
+    ));
+    trash.spawn_purge_job();
+
+    let shutdown = Arc::new(tokio::sync::Notify::new());
+
+    let mut jobs = api::JobManager::new()
+        .with_history(std::time::Duration::from_secs(
+            config.api.job_history_minutes * 60,
+        ))
+        .with_event_bus(event_bus.clone());
+    if config.api.persist_jobs {
+        jobs = jobs.with_history_file(paths.job_history_file());
+    }
 
     // Create API state
     let api_state = api::AppState {
         library: library.clone(),
         playlist_manager: playlist_manager.clone(),
+        playback_queue: playback_queue.clone(),
         audio_player: audio_player.clone(),
         album_service: album_service.clone(),
+        stats_store: Arc::new(library::StatsStore::with_path(paths.stats_file())),
+        verification_job: Arc::new(library::VerificationJob::new()),
+        webhooks: webhooks.clone(),
+        trash: trash.clone(),
+        delete_mode: config.library.delete_mode,
+        duplicate_preferences: config.library.duplicates.clone(),
         event_bus: event_bus.clone(),
+        paths: paths.clone(),
+        shutdown: shutdown.clone(),
+        event_log,
+        revision: Arc::new(api::PlaybackRevision::new()),
+        transcode_cache: (config.api.transcode_cache_mb > 0).then(|| {
+            Arc::new(audio::TranscodeCache::new(
+                paths.transcode_cache_dir(),
+                config.api.transcode_cache_mb * 1024 * 1024,
+            ))
+        }),
+        precache,
+        max_import_bytes: usize::try_from(config.api.max_import_mb * 1024 * 1024)
+            .unwrap_or(usize::MAX),
+        resume_positions,
+        jobs: Arc::new(jobs),
+        route_budgets: api::RouteBudgets::from(&config.api.timeouts),
+        guest_policy: api::GuestPolicy::from_config(&config.api)?.map(Arc::new),
+        waveforms: Arc::new(library::WaveformCache::new(paths.waveform_cache_dir())),
+        sleep_timer: Arc::new(api::SleepTimer::new()),
+        shares: Arc::new(api::ShareSigner::load(
+            paths.share_secret_file(),
+            config.api.share_allow_stream,
+        )?),
     };
+    if api_state.guest_policy.is_some() {
+        info!(
+            "Guest token enabled - guests may browse, queue, skip and set the volume, and \
+             other requests need the auth token"
+        );
+    }
+    up_next.spawn(api_state.clone());
 
-    // Start API server on port 3030
-    let api_port = 3030;
-    info!("Starting API server on port {}...", api_port);
-
-    // Spawn API server in background
-    let api_state_clone = api_state.clone();
-    tokio::spawn(async move {
-        if let Err(e) = api::start_server(api_state_clone, api_port).await {
-            error!("API server error: {}", e);
+    // Scanned as a job, so it can be followed and cancelled like one started by a client
+    if config.library.auto_scan && !config.library.music_directories.is_empty() {
+        info!(
+            "Auto-scan enabled - scanning {} directory(ies)...",
+            config.library.music_directories.len()
+        );
+        if let Err(e) =
+            api::start_scan_job(&api_state, config.library.music_directory_paths(), false)
+        {
+            error!("Auto-scan failed to start: {}", e);
         }
-    });
+    } else if config.library.music_directories.is_empty() {
+        info!("No music directories configured - skipping auto-scan");
+    }
+
+    if api::MediaSession::start(&api_state, config.integrations.media_session).is_some() {
+        info!("Registered with the system media session");
+    }
+
+    // Start API server. Failing to bind the port is fatal, so a second backend or an
+    // unrelated process holding the port does not leave a silently unreachable one.
+    let api_port = config.api.port;
+    let mut api_server = None;
+    if config.api.listen_tcp {
+        info!("Starting API server on port {}...", api_port);
+        let listener = match api::bind_server(api_port).await {
+            Ok(listener) => listener,
+            Err(e) => {
+                error!("Failed to start API server: {}", e);
+                return Err(e);
+            }
+        };
+
+        // Serve the API in background
+        api_server = Some(tokio::spawn(api::serve(listener, api_state.clone())));
+    }
+
+    #[cfg(unix)]
+    if let Some(socket_path) = config.api.unix_socket.clone() {
+        let api_state_clone = api_state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = api::serve_unix_socket(api_state_clone, socket_path).await {
+                error!("API unix socket error: {}", e);
+            }
+        });
+    }
+
+    #[cfg(not(unix))]
+    if config.api.unix_socket.is_some() {
+        warn!("api.unix_socket is only supported on Unix platforms");
+    }
 
     info!("Hexendrum backend services are ready");
-    info!("API server running at http://127.0.0.1:{}", api_port);
-    info!(
-        "Swagger UI available at http://127.0.0.1:{}/swagger-ui",
-        api_port
-    );
+    if config.api.listen_tcp {
+        info!("API server running at http://127.0.0.1:{}", api_port);
+        info!(
+            "Swagger UI available at http://127.0.0.1:{}/swagger-ui",
+            api_port
+        );
+    } else if config.api.unix_socket.is_none() {
+        warn!("api.listen_tcp is disabled and no api.unix_socket is set; the API is not reachable");
+    }
 
-    // Keep the backend running
-    loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    // Keep the backend running until asked to shut down or the API server fails
+    let server_failed = async {
+        match api_server {
+            Some(handle) => match handle.await {
+                Ok(Ok(())) => anyhow::anyhow!("API server stopped unexpectedly"),
+                Ok(Err(e)) => e,
+                Err(e) => anyhow::anyhow!("API server task failed: {}", e),
+            },
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = shutdown.notified() => {
+            info!("Shutting down Hexendrum backend");
+            // Let the shutdown request's response reach the client
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            drop(instance_lock);
+            Ok(())
+        }
+        error = server_failed => {
+            error!("API server error: {}", error);
+            Err(error)
+        }
     }
 }
 
-fn spawn_cli_playbar(event_bus: Arc<EventBus>) {
+/// Print a one-line playbar, redrawn on playback events and every second while
+/// playing, with the position read from the audio thread
+fn spawn_cli_playbar(
+    event_bus: Arc<EventBus>,
+    position: impl Fn() -> std::time::Duration + Send + 'static,
+) {
     tokio::spawn(async move {
         use tokio::time::{interval, Duration, MissedTickBehavior};
 
@@ -201,7 +512,6 @@ fn spawn_cli_playbar(event_bus: Arc<EventBus>) {
         let mut receiver = event_bus.subscribe();
         let mut track_label: Option<String> = None;
         let mut duration: Option<u64> = None;
-        let mut progress: u64 = 0;
         let mut playing = false;
         let mut volume = 0.7f32;
 
@@ -210,48 +520,101 @@ fn spawn_cli_playbar(event_bus: Arc<EventBus>) {
                 event = receiver.recv() => {
                     match event {
                         Ok(message) => match message.payload {
-                            EventPayload::PlaybackState { state, track_path, track_id, volume: vol, track_duration } => {
+                            EventPayload::PlaybackState { state, track_path, track_id, volume: vol, track_duration, .. } => {
                                 if let Some(v) = vol {
                                     volume = v;
                                 }
 
                                 if let Some(d) = track_duration {
                                     duration = Some(d);
-                                    if progress > d {
-                                        progress = d;
-                                    }
                                 }
 
                                 if let Some(identifier) = track_path.or(track_id) {
-                                    if track_label.as_deref() != Some(identifier.as_str()) {
-                                        track_label = Some(identifier);
-                                        progress = 0;
-                                    }
+                                    track_label = Some(identifier);
                                 }
 
                                 match state.as_str() {
                                     "playing" => playing = true,
-                                    "paused" => playing = false,
-                                    "stopped" => {
-                                        playing = false;
-                                        progress = 0;
-                                    }
+                                    "paused" | "devicelost" | "stopped" => playing = false,
                                     _ => {}
                                 }
 
-                                render_cli_playbar(&track_label, progress, duration, volume, playing);
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
+                            }
+                            EventPayload::PlaybackFinished { track_path, .. } => {
+                                playing = false;
+                                println!("\n[finished] {}", track_path);
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
+                            }
+                            EventPayload::PlaybackError { track_path, message } => {
+                                println!("\n[error] {}: {}", track_path, message);
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
                             }
-                            EventPayload::VolumeChanged { volume: vol } => {
+                            EventPayload::VolumeChanged { volume: vol, .. } => {
                                 volume = vol;
-                                render_cli_playbar(&track_label, progress, duration, volume, playing);
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
                             }
-                            EventPayload::LibraryScan { status, .. } => {
-                                println!("\n[scan] {}", status);
-                                render_cli_playbar(&track_label, progress, duration, volume, playing);
+                            EventPayload::LibraryScan { status, processed, total } => {
+                                match (processed, total) {
+                                    (Some(processed), Some(total)) if total > 0 => println!(
+                                        "\n[scan] {} {}/{} ({}%)",
+                                        status,
+                                        processed,
+                                        total,
+                                        processed * 100 / total
+                                    ),
+                                    _ => println!("\n[scan] {}", status),
+                                }
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
                             }
-                            EventPayload::LibraryUpdated { total_tracks } => {
+                            EventPayload::LibraryUpdated { total_tracks, .. } => {
                                 println!("\n[library] tracks: {}", total_tracks);
-                                render_cli_playbar(&track_label, progress, duration, volume, playing);
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
+                            }
+                            EventPayload::LibraryVerify { status, processed, total, failed, .. } => {
+                                println!("\n[verify] {} {}/{} ({} failed)", status, processed, total, failed);
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
+                            }
+                            EventPayload::QueueUpdated { length, .. } => {
+                                println!("\n[queue] tracks: {}", length);
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
+                            }
+                            EventPayload::RadioMode { enabled, .. } => {
+                                println!("\n[radio] {}", if enabled { "on" } else { "off" });
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
+                            }
+                            EventPayload::UpNext { track, seconds_until } => {
+                                let label = track.title.unwrap_or(track.id);
+                                println!("\n[up next] {} in {}s", label, seconds_until);
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
+                            }
+                            EventPayload::SilenceSkipped { .. } => {
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
+                            }
+                            EventPayload::AudioPreview { state, track_path, .. } => {
+                                println!("\n[preview] {} {}", state, track_path.unwrap_or_default());
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
+                            }
+                            EventPayload::Maintenance { status, task, completed, total } => {
+                                match task {
+                                    Some(task) => println!("\n[maintenance] {} {} ({}/{})", status, task, completed, total),
+                                    None => println!("\n[maintenance] {} ({}/{})", status, completed, total),
+                                }
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
+                            }
+                            EventPayload::AudioDevice { status, message, .. } => {
+                                match message {
+                                    Some(message) => println!("\n[audio] device {}: {}", status, message),
+                                    None => println!("\n[audio] device {}", status),
+                                }
+                                render_cli_playbar(&track_label, position(), duration, volume, playing);
+                            }
+                            // Only of interest to album grids
+                            EventPayload::AlbumArtworkUpdated { .. } => {}
+                            // Scans, verifications and maintenance have their own events
+                            EventPayload::JobProgress { .. } => {}
+                            EventPayload::SleepTimerFired { .. } => {
+                                println!("\n[sleep timer] playback stopped");
                             }
                         },
                         Err(_) => break,
@@ -259,13 +622,7 @@ fn spawn_cli_playbar(event_bus: Arc<EventBus>) {
                 }
                 _ = ticker.tick() => {
                     if playing {
-                        progress = progress.saturating_add(1);
-                        if let Some(d) = duration {
-                            if progress > d {
-                                progress = d;
-                            }
-                        }
-                        render_cli_playbar(&track_label, progress, duration, volume, playing);
+                        render_cli_playbar(&track_label, position(), duration, volume, playing);
                     }
                 }
             }
@@ -275,7 +632,7 @@ fn spawn_cli_playbar(event_bus: Arc<EventBus>) {
 
 fn render_cli_playbar(
     track_label: &Option<String>,
-    progress: u64,
+    position: std::time::Duration,
     duration: Option<u64>,
     volume: f32,
     playing: bool,
@@ -288,6 +645,7 @@ fn render_cli_playbar(
         .map(|s| truncate_title(s, 40))
         .unwrap_or_else(|| "No track".to_string());
 
+    let progress = duration.map_or(position.as_secs(), |d| position.as_secs().min(d));
     let elapsed = format_seconds(progress);
     let total = duration
         .map(format_seconds)
@@ -309,21 +667,17 @@ fn render_cli_playbar(
 }
 
 fn format_seconds(seconds: u64) -> String {
-    let mins = seconds / 60;
-    let secs = seconds % 60;
-    format!("{}:{:02}", mins, secs)
+    utils::format_duration_long(std::time::Duration::from_secs(seconds))
 }
 
 fn truncate_title(title: &str, max_chars: usize) -> String {
     let mut result = String::with_capacity(max_chars);
-    let mut count = 0;
-    for ch in title.chars() {
+    for (count, ch) in title.chars().enumerate() {
         if count + 1 >= max_chars {
             result.push('…');
             return result;
         }
         result.push(ch);
-        count += 1;
     }
     result
 }
@@ -0,0 +1,530 @@
+//! `hexendrum maintenance`: housekeeping tasks run in one go.
+//!
+//! [`Maintenance::run`] executes the selected [`MaintenanceTask`]s in sequence and reports each
+//! one's duration and outcome in a [`MaintenanceReport`]. It is served at
+//! `POST /api/maintenance` and run by the CLI, which hands the request to the running
+//! backend when there is one. None of the tasks touch playback: the rescan keeps the
+//! identifiers of unchanged tracks, so the queue and the playing track stay valid.
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use utoipa::ToSchema;
+
+use crate::config::{Config, Paths};
+use crate::ctl::{self, CtlTarget};
+use crate::instance::{InstanceInfo, InstanceLock, LockOutcome};
+use crate::library::{orphaned_sidecars, AlbumService, Library, StatsStore, Trash, SIDECAR_SUFFIX};
+use crate::playlist::PlaylistManager;
+
+const USAGE: &str =
+    "Usage: hexendrum [--data-dir <path>] maintenance [--tasks <list>] [--dry-run] [--json]
+
+Tasks (comma separated, all by default):
+  rescan              Re-read modified tracks, drop deleted ones and add new files
+  save_cache          Rewrite the library cache with the current tracks
+  playlist_paths      Link playlist entries to tracks by file path and rewrite
+                      playlists with paths in the form playlist.portable_paths asks
+  playlist_cleanup    Remove playlist entries whose track is gone
+  artwork_cache       Delete cached artwork of albums no longer in the library
+  orphaned_sidecars   Delete .hexendrum.json sidecars whose audio file is gone
+  compact_stats       Drop statistics of files that no longer exist and fold the
+                      stats log into the snapshot
+
+With --dry-run the cleanup tasks only list what they would remove.";
+
+/// A housekeeping task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MaintenanceTask {
+    /// Re-read modified tracks, drop deleted ones and add new files
+    Rescan,
+    /// Rewrite the library cache, dropping entries of removed tracks
+    SaveCache,
+    /// Link playlist entries to tracks by file path, then rewrite the playlists with
+    /// their paths in the configured form
+    PlaylistPaths,
+    /// Remove playlist entries whose track is no longer in the library
+    PlaylistCleanup,
+    /// Delete cached artwork of albums that are no longer in the library
+    ArtworkCache,
+    /// Delete `.hexendrum.json` sidecars whose audio file is gone
+    OrphanedSidecars,
+    /// Drop statistics recorded for files that no longer exist and fold the stats
+    /// log into the snapshot
+    CompactStats,
+}
+
+impl MaintenanceTask {
+    /// Every task, in the order they run.
+    pub const ALL: [Self; 7] = [
+        Self::Rescan,
+        Self::SaveCache,
+        Self::PlaylistPaths,
+        Self::PlaylistCleanup,
+        Self::ArtworkCache,
+        Self::OrphanedSidecars,
+        Self::CompactStats,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Rescan => "rescan",
+            Self::SaveCache => "save_cache",
+            Self::PlaylistPaths => "playlist_paths",
+            Self::PlaylistCleanup => "playlist_cleanup",
+            Self::ArtworkCache => "artwork_cache",
+            Self::OrphanedSidecars => "orphaned_sidecars",
+            Self::CompactStats => "compact_stats",
+        }
+    }
+
+    /// Parse a task name such as `playlist_cleanup`.
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|task| task.as_str().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| anyhow!("unknown maintenance task '{}'", name.trim()))
+    }
+}
+
+/// Which tasks to run and how.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceRequest {
+    /// Tasks to run; every task when empty. Tasks always run in the order of
+    /// [`MaintenanceTask::ALL`], so a rescan happens before the cleanups.
+    #[serde(default)]
+    #[schema(example = json!(["rescan", "playlist_cleanup"]))]
+    pub tasks: Vec<MaintenanceTask>,
+    /// Only list what the cleanup tasks would remove
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Directories searched for new tracks and orphaned sidecars, instead of
+    /// `library.music_directories`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!(["/home/user/Music"]))]
+    pub directories: Option<Vec<String>>,
+}
+
+impl MaintenanceRequest {
+    /// The selected tasks, deduplicated and in running order.
+    pub fn selected_tasks(&self) -> Vec<MaintenanceTask> {
+        if self.tasks.is_empty() {
+            return MaintenanceTask::ALL.to_vec();
+        }
+        let mut tasks = self.tasks.clone();
+        tasks.sort();
+        tasks.dedup();
+        tasks
+    }
+}
+
+/// Outcome of one task.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TaskReport {
+    pub task: MaintenanceTask,
+    /// Whether the task finished without error
+    pub success: bool,
+    /// How long the task took
+    #[schema(example = 42)]
+    pub duration_ms: u64,
+    /// Number of items the task changed, or would change in a dry run
+    #[schema(example = 3)]
+    pub affected: usize,
+    /// What the task did, or why it failed
+    #[schema(example = "2 added, 1 updated, 0 removed")]
+    pub message: String,
+    /// Files or playlist entries removed, or that would be removed in a dry run
+    #[serde(default)]
+    pub removed: Vec<String>,
+}
+
+/// Outcome of a maintenance run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct MaintenanceReport {
+    /// Whether this was a dry run that left files and playlists untouched
+    pub dry_run: bool,
+    /// How long the whole run took
+    #[schema(example = 120)]
+    pub duration_ms: u64,
+    /// One entry per task, in the order they ran
+    pub tasks: Vec<TaskReport>,
+}
+
+impl MaintenanceReport {
+    /// Whether any task failed.
+    pub fn has_failures(&self) -> bool {
+        self.tasks.iter().any(|task| !task.success)
+    }
+
+    /// Render the report as a table, one row per task.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        for task in &self.tasks {
+            output.push_str(&format!(
+                "{}  {:<17}  {:>6} ms  {}\n",
+                if task.success { "OK  " } else { "FAIL" },
+                task.task.as_str(),
+                task.duration_ms,
+                task.message
+            ));
+            for removed in &task.removed {
+                output.push_str(&format!("      {:<17}  -> {}\n", "", removed));
+            }
+        }
+
+        output.push_str(&format!(
+            "\n{} task(s) in {} ms{}",
+            self.tasks.len(),
+            self.duration_ms,
+            if self.dry_run {
+                ", dry run: nothing was removed"
+            } else {
+                ""
+            }
+        ));
+        output
+    }
+}
+
+/// What the tasks operate on.
+pub struct Maintenance<'a> {
+    pub library: &'a Library,
+    pub playlist_manager: &'a PlaylistManager,
+    pub album_service: &'a AlbumService,
+    pub stats_store: &'a StatsStore,
+    /// Sidecars of trashed files are kept, so restoring a file brings its metadata back
+    pub trash: &'a Trash,
+    /// Directories searched for new tracks and orphaned sidecars
+    pub music_directories: Vec<PathBuf>,
+}
+
+impl Maintenance<'_> {
+    /// Run the tasks selected by `request` in sequence. `on_progress` is called with
+    /// each task before it starts, along with the number of tasks completed so far.
+    ///
+    /// A failing task is reported and the remaining tasks still run.
+    pub fn run<F>(&self, request: &MaintenanceRequest, mut on_progress: F) -> MaintenanceReport
+    where
+        F: FnMut(MaintenanceTask, usize),
+    {
+        let started = Instant::now();
+        let mut reports = Vec::new();
+
+        for (index, task) in request.selected_tasks().into_iter().enumerate() {
+            on_progress(task, index);
+            let task_started = Instant::now();
+            let (success, affected, message, removed) = match self.run_task(task, request.dry_run) {
+                Ok(outcome) => (true, outcome.affected, outcome.message, outcome.removed),
+                Err(error) => {
+                    tracing::warn!("Maintenance task {} failed: {:#}", task.as_str(), error);
+                    (false, 0, format!("{:#}", error), Vec::new())
+                }
+            };
+            reports.push(TaskReport {
+                task,
+                success,
+                duration_ms: task_started.elapsed().as_millis() as u64,
+                affected,
+                message,
+                removed,
+            });
+        }
+
+        MaintenanceReport {
+            dry_run: request.dry_run,
+            duration_ms: started.elapsed().as_millis() as u64,
+            tasks: reports,
+        }
+    }
+
+    fn run_task(&self, task: MaintenanceTask, dry_run: bool) -> Result<TaskOutcome> {
+        match task {
+            MaintenanceTask::Rescan => {
+                let report = self.library.refresh(&self.music_directories)?;
+                Ok(TaskOutcome::new(
+                    report.added + report.updated + report.removed,
+                    format!(
+                        "{} added, {} updated, {} removed",
+                        report.added, report.updated, report.removed
+                    ),
+                ))
+            }
+            MaintenanceTask::SaveCache => {
+                self.library.save_to_cache()?;
+                let tracks = self.library.track_count();
+                Ok(TaskOutcome::new(
+                    tracks,
+                    format!("{} track(s) cached", tracks),
+                ))
+            }
+            MaintenanceTask::PlaylistPaths => {
+                // Relinked entries are no longer orphans for the cleanup that follows
+                let relinked = self
+                    .playlist_manager
+                    .relink_entries(self.library, dry_run)?;
+                let rewritten = if dry_run {
+                    0
+                } else {
+                    self.playlist_manager.rewrite_playlists()?
+                };
+                Ok(TaskOutcome::new(
+                    relinked.len(),
+                    format!(
+                        "{} entry(ies) {}linked by path, {} playlist(s) rewritten",
+                        relinked.len(),
+                        if dry_run { "would be " } else { "" },
+                        rewritten
+                    ),
+                ))
+            }
+            MaintenanceTask::PlaylistCleanup => {
+                let orphans = if dry_run {
+                    self.playlist_manager
+                        .find_orphaned_entries(self.library, None)?
+                } else {
+                    self.playlist_manager.cleanup_missing_tracks(self.library)?
+                };
+                let removed = orphans
+                    .iter()
+                    .map(|orphan| {
+                        format!(
+                            "{} #{}: {}",
+                            orphan.playlist_name, orphan.position, orphan.track_id
+                        )
+                    })
+                    .collect();
+                Ok(TaskOutcome::removed(
+                    removed,
+                    "playlist entry(ies)",
+                    dry_run,
+                ))
+            }
+            MaintenanceTask::ArtworkCache => {
+                let evicted = if dry_run {
+                    self.album_service.unused_artwork(self.library)
+                } else {
+                    self.album_service.evict_unused_artwork(self.library)
+                };
+                Ok(TaskOutcome::removed(
+                    display_paths(&evicted),
+                    "cached artwork file(s)",
+                    dry_run,
+                ))
+            }
+            MaintenanceTask::OrphanedSidecars => {
+                // Sidecars on read-only shares are left alone
+                let orphans: Vec<PathBuf> = orphaned_sidecars(&self.music_directories)
+                    .into_iter()
+                    .filter(|sidecar| !self.library.read_only().contains(sidecar))
+                    .filter(|sidecar| {
+                        let sidecar = sidecar.to_string_lossy();
+                        let audio_path = sidecar.strip_suffix(SIDECAR_SUFFIX).unwrap_or(&sidecar);
+                        !self.trash.is_restorable_path(Path::new(audio_path))
+                    })
+                    .collect();
+                let mut removed = Vec::new();
+                for sidecar in orphans {
+                    if !dry_run {
+                        std::fs::remove_file(&sidecar)
+                            .with_context(|| format!("cannot remove {}", sidecar.display()))?;
+                    }
+                    removed.push(sidecar);
+                }
+                Ok(TaskOutcome::removed(
+                    display_paths(&removed),
+                    "orphaned sidecar(s)",
+                    dry_run,
+                ))
+            }
+            MaintenanceTask::CompactStats => {
+                let stale = if dry_run {
+                    self.stats_store.stale_records()
+                } else {
+                    self.stats_store.compact()?
+                };
+                Ok(TaskOutcome::removed(
+                    display_paths(&stale),
+                    "statistics record(s)",
+                    dry_run,
+                ))
+            }
+        }
+    }
+}
+
+struct TaskOutcome {
+    affected: usize,
+    message: String,
+    removed: Vec<String>,
+}
+
+impl TaskOutcome {
+    fn new(affected: usize, message: String) -> Self {
+        Self {
+            affected,
+            message,
+            removed: Vec::new(),
+        }
+    }
+
+    fn removed(removed: Vec<String>, what: &str, dry_run: bool) -> Self {
+        let verb = if dry_run {
+            "would be removed"
+        } else {
+            "removed"
+        };
+        Self {
+            affected: removed.len(),
+            message: format!("{} {} {}", removed.len(), what, verb),
+            removed,
+        }
+    }
+}
+
+fn display_paths(paths: &[PathBuf]) -> Vec<String> {
+    paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect()
+}
+
+/// Parsed `hexendrum maintenance` invocation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaintenanceOptions {
+    pub request: MaintenanceRequest,
+    /// Print the report as JSON instead of a table
+    pub json: bool,
+}
+
+impl MaintenanceOptions {
+    /// Parse the arguments following `maintenance`.
+    pub fn parse(args: &[String]) -> Result<Self> {
+        let mut request = MaintenanceRequest::default();
+        let mut json = false;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--json" => json = true,
+                "--dry-run" => request.dry_run = true,
+                "--tasks" => {
+                    let value = args
+                        .next()
+                        .ok_or_else(|| anyhow!("--tasks needs a value"))?;
+                    for name in value.split(',').filter(|name| !name.trim().is_empty()) {
+                        request.tasks.push(MaintenanceTask::parse(name)?);
+                    }
+                    if request.tasks.is_empty() {
+                        bail!("--tasks needs at least one task");
+                    }
+                }
+                other => bail!("unexpected argument '{}'", other),
+            }
+        }
+
+        Ok(Self { request, json })
+    }
+}
+
+/// Run `hexendrum maintenance` with the arguments following `maintenance` and return
+/// the exit code.
+///
+/// A running backend is asked to do the work, since it owns the library and playlists.
+/// Otherwise the tasks run in this process, which holds the instance lock meanwhile so
+/// no backend starts halfway through.
+pub async fn run(args: &[String], paths: &Paths) -> i32 {
+    let options = match MaintenanceOptions::parse(args) {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("hexendrum maintenance: {}\n\n{}", error, USAGE);
+            return 2;
+        }
+    };
+
+    let report = match execute(&options.request, paths).await {
+        Ok(report) => report,
+        Err(error) => {
+            eprintln!("hexendrum maintenance: {:#}", error);
+            return 1;
+        }
+    };
+
+    if options.json {
+        match serde_json::to_string_pretty(&report) {
+            Ok(json) => println!("{}", json),
+            Err(error) => {
+                eprintln!("hexendrum maintenance: {}", error);
+                return 1;
+            }
+        }
+    } else {
+        println!("{}", report.render());
+    }
+
+    if report.has_failures() {
+        1
+    } else {
+        0
+    }
+}
+
+async fn execute(request: &MaintenanceRequest, paths: &Paths) -> Result<MaintenanceReport> {
+    let config = Config::load(paths).unwrap_or_default();
+    let info = InstanceInfo::current(&config.api);
+    let lock = match InstanceLock::acquire(&paths.instance_lock_file(), &info)? {
+        LockOutcome::Acquired(lock) => lock,
+        LockOutcome::Held(other) => {
+            let mut target = CtlTarget::from_config(&config.api);
+            if let Some(address) = other.as_ref().and_then(InstanceInfo::ctl_address) {
+                target.address = address;
+            }
+            return ctl::request_maintenance(&target, request).await;
+        }
+    };
+
+    let paths = paths.clone();
+    let request = request.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        let library =
+            Library::with_content_fingerprints(&paths, config.library.content_fingerprints)
+                .with_read_only(config.library.read_only_paths());
+        let playlist_manager = PlaylistManager::new(paths.playlist_dir())?
+            .with_music_roots(config.library.music_roots())
+            .with_portable_paths(config.playlist.portable_paths);
+        playlist_manager.load_all_playlists()?;
+        let lastfm_api_key = config.services.lastfm.api_key.trim().to_string();
+        let album_service = AlbumService::with_paths(
+            &paths,
+            (!lastfm_api_key.is_empty()).then_some(lastfm_api_key),
+        )
+        .with_album_disambiguation(config.library.album_disambiguation);
+        let stats_store = StatsStore::with_path(paths.stats_file());
+        let trash = Trash::with_paths(
+            paths.trash_journal_file(),
+            paths.home_trash.clone(),
+            config.library.trash_retention_days,
+        );
+
+        let maintenance = Maintenance {
+            library: &library,
+            playlist_manager: &playlist_manager,
+            album_service: &album_service,
+            stats_store: &stats_store,
+            trash: &trash,
+            music_directories: request
+                .directories
+                .as_ref()
+                .map(|directories| directories.iter().map(PathBuf::from).collect())
+                .unwrap_or_else(|| config.library.music_directory_paths()),
+        };
+        Ok::<_, anyhow::Error>(maintenance.run(&request, |task, _| {
+            eprintln!("Running {}...", task.as_str());
+        }))
+    })
+    .await??;
+
+    drop(lock);
+    Ok(report)
+}
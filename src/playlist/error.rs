@@ -0,0 +1,30 @@
+use std::io;
+
+use super::PlaylistConflict;
+use crate::utils::schema::SchemaError;
+
+/// Why a [`PlaylistManager`](super::PlaylistManager) operation failed
+#[derive(Debug, thiserror::Error)]
+pub enum PlaylistError {
+    #[error("Playlist not found: {0}")]
+    PlaylistNotFound(String),
+    /// An import clashed with an existing playlist under
+    /// [`ImportConflictPolicy::Fail`](super::ImportConflictPolicy::Fail)
+    #[error(transparent)]
+    Conflict(Box<PlaylistConflict>),
+    #[error("The root folder cannot be renamed")]
+    RootFolder,
+    #[error("Invalid repeat mode '{0}': expected none, one or all")]
+    InvalidRepeatMode(String),
+    #[error("CSV is missing a track title column")]
+    MissingTitleColumn,
+    /// The playlist file was written by a newer version
+    #[error(transparent)]
+    Schema(#[from] SchemaError),
+    #[error("Invalid CSV: {0}")]
+    Csv(#[from] csv::Error),
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
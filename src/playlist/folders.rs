@@ -0,0 +1,117 @@
+use super::{PlaylistError, PlaylistManager, PlaylistSummary};
+use crate::utils::natural_cmp;
+
+/// Separates the levels of a folder path, as in "Workout/Running"
+pub const FOLDER_SEPARATOR: char = '/';
+
+/// Normalize a folder path: levels are trimmed and empty ones dropped, so
+/// " Workout//Running/ " becomes "Workout/Running". `None` stands for the root.
+pub fn normalize_folder(folder: &str) -> Option<String> {
+    let levels: Vec<&str> = folder
+        .split(FOLDER_SEPARATOR)
+        .map(str::trim)
+        .filter(|level| !level.is_empty())
+        .collect();
+    (!levels.is_empty()).then(|| levels.join(&FOLDER_SEPARATOR.to_string()))
+}
+
+/// Where `folder` ends up when the folder `from` is renamed to `to`, or `None` when
+/// it is neither `from` nor inside it. Both paths must be normalized.
+pub fn renamed_folder(folder: &str, from: &str, to: Option<&str>) -> Option<Option<String>> {
+    let rest = folder.strip_prefix(from)?;
+    if !rest.is_empty() && !rest.starts_with(FOLDER_SEPARATOR) {
+        return None;
+    }
+    Some(normalize_folder(&format!(
+        "{}{}",
+        to.unwrap_or_default(),
+        rest
+    )))
+}
+
+/// A virtual folder of playlists
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaylistFolder {
+    /// Last level of the path; empty for the root
+    pub name: String,
+    /// Full path, e.g. "Workout/Running"; empty for the root
+    pub path: String,
+    /// Subfolders in natural order
+    pub folders: Vec<PlaylistFolder>,
+    /// Playlists directly in this folder, in natural order of their names
+    pub playlists: Vec<PlaylistSummary>,
+}
+
+impl PlaylistFolder {
+    fn new(name: &str, path: String) -> Self {
+        Self {
+            name: name.to_string(),
+            path,
+            folders: Vec::new(),
+            playlists: Vec::new(),
+        }
+    }
+
+    fn sort(&mut self) {
+        self.folders.sort_by(|a, b| natural_cmp(&a.name, &b.name));
+        self.playlists
+            .sort_by(|a, b| natural_cmp(&a.name, &b.name).then_with(|| a.id.cmp(&b.id)));
+        for folder in &mut self.folders {
+            folder.sort();
+        }
+    }
+}
+
+/// Nest `playlists` under their folders. Playlists without a folder sit at the root,
+/// and folders only exist while a playlist is in them or below them.
+pub fn playlist_tree(playlists: Vec<PlaylistSummary>) -> PlaylistFolder {
+    let mut root = PlaylistFolder::new("", String::new());
+    for playlist in playlists {
+        let mut folder = &mut root;
+        if let Some(path) = playlist.folder.as_deref().and_then(normalize_folder) {
+            for level in path.split(FOLDER_SEPARATOR) {
+                let index = match folder.folders.iter().position(|child| child.name == level) {
+                    Some(index) => index,
+                    None => {
+                        let child_path = if folder.path.is_empty() {
+                            level.to_string()
+                        } else {
+                            format!("{}{}{}", folder.path, FOLDER_SEPARATOR, level)
+                        };
+                        folder.folders.push(PlaylistFolder::new(level, child_path));
+                        folder.folders.len() - 1
+                    }
+                };
+                folder = &mut folder.folders[index];
+            }
+        }
+        folder.playlists.push(playlist);
+    }
+    root.sort();
+    root
+}
+
+impl PlaylistManager {
+    /// Nest every playlist under its folder, see [`playlist_tree`].
+    pub fn playlist_tree(&self) -> PlaylistFolder {
+        playlist_tree(self.playlist_summaries())
+    }
+
+    /// Rename the folder `from` to `to` by moving every playlist in it or below it;
+    /// an empty `to` moves them to the root. Returns the number of playlists moved.
+    pub fn rename_folder(&self, from: &str, to: &str) -> Result<usize, PlaylistError> {
+        let from = normalize_folder(from).ok_or(PlaylistError::RootFolder)?;
+        let to = normalize_folder(to);
+
+        let moved = self.change_playlists(|playlist| {
+            let folder = playlist
+                .folder
+                .as_deref()
+                .and_then(|folder| renamed_folder(folder, &from, to.as_deref()))?;
+            let mut renamed = playlist.clone();
+            renamed.folder = folder;
+            Some(renamed)
+        });
+        Ok(moved.len())
+    }
+}
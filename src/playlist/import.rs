@@ -0,0 +1,382 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+use tracing::{info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::{
+    next_modified_at, Playlist, PlaylistError, PlaylistManager, PlaylistSlot, PlaylistSummary,
+};
+use crate::library::{Library, TrackMatch, TrackMatcher, TrackQuery};
+
+/// Suffix appended to the name of an imported playlist renamed to avoid a clash
+pub const IMPORTED_SUFFIX: &str = " (imported)";
+
+/// What importing a playlist does when one with the same id or name already exists
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportConflictPolicy {
+    /// Import under a new name with " (imported)" appended
+    #[default]
+    Rename,
+    /// Append the imported entries the existing playlist does not have yet
+    Merge,
+    /// Replace the existing playlist's contents, keeping its id and creation time
+    Replace,
+    /// Refuse the import
+    Fail,
+}
+
+/// What an import did with the imported playlist
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportAction {
+    /// No playlist clashed; a new one was created
+    Created,
+    /// A new playlist was created under another name
+    Renamed,
+    /// Entries were appended to the existing playlist
+    Merged,
+    /// The existing playlist's contents were replaced
+    Replaced,
+}
+
+/// An import refused by [`ImportConflictPolicy::Fail`]
+#[derive(Debug, thiserror::Error)]
+#[error("Playlist '{}' ({}) already exists", existing.name, existing.id)]
+pub struct PlaylistConflict {
+    pub existing: PlaylistSummary,
+}
+
+/// Where an imported playlist ended up
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlaylistImportOutcome {
+    pub playlist_id: String,
+    pub playlist_name: String,
+    pub action: ImportAction,
+    /// Entries added to the stored playlist
+    pub entries_added: usize,
+}
+
+/// A single track row read from an exported playlist CSV.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CsvTrackRow {
+    /// 1-based line number in the source file (the header is line 1)
+    pub line: usize,
+    pub query: TrackQuery,
+}
+
+/// A CSV row matched to a library track.
+#[derive(Debug, Clone, Serialize)]
+pub struct CsvImportMatch {
+    pub row: CsvTrackRow,
+    pub track: TrackMatch,
+}
+
+/// Outcome of importing a playlist CSV.
+#[derive(Debug, Clone, Serialize)]
+pub struct CsvImportReport {
+    /// Created or updated playlist, `None` for dry runs or when nothing matched
+    pub playlist_id: Option<String>,
+    pub playlist_name: String,
+    /// How a clash with an existing playlist was handled, `None` when nothing was stored
+    pub action: Option<ImportAction>,
+    pub dry_run: bool,
+    pub total_rows: usize,
+    pub matched: Vec<CsvImportMatch>,
+    pub unmatched: Vec<CsvTrackRow>,
+}
+
+impl CsvImportReport {
+    /// Matches that should be reviewed by the user.
+    pub fn low_confidence(&self) -> impl Iterator<Item = &CsvImportMatch> {
+        self.matched
+            .iter()
+            .filter(|entry| entry.track.is_low_confidence())
+    }
+}
+
+const TITLE_COLUMNS: [&str; 5] = ["track name", "title", "name", "song", "track"];
+const ARTIST_COLUMNS: [&str; 5] = [
+    "artist name(s)",
+    "artist name",
+    "artist names",
+    "artists",
+    "artist",
+];
+const ALBUM_COLUMNS: [&str; 3] = ["album name", "album", "album title"];
+const DURATION_MS_COLUMNS: [&str; 4] = [
+    "duration (ms)",
+    "track duration (ms)",
+    "duration_ms",
+    "duration ms",
+];
+const DURATION_COLUMNS: [&str; 2] = ["duration", "length"];
+
+/// Parse an exported playlist CSV.
+///
+/// Columns are located by header name, which covers Exportify's Spotify exports
+/// ("Track Name", "Artist Name(s)", "Album Name", "Duration (ms)") as well as the simpler
+/// "Title"/"Artist"/"Album"/"Duration" layout produced by most YouTube Music exporters.
+/// Rows without a title are skipped.
+pub fn parse_playlist_csv(content: &str) -> Result<Vec<CsvTrackRow>, PlaylistError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(content.trim_start_matches('\u{feff}').as_bytes());
+
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|header| header.to_lowercase())
+        .collect();
+
+    let find_column = |names: &[&str]| {
+        names
+            .iter()
+            .find_map(|name| headers.iter().position(|header| header == name))
+    };
+
+    let title_column = find_column(&TITLE_COLUMNS).ok_or(PlaylistError::MissingTitleColumn)?;
+    let artist_column = find_column(&ARTIST_COLUMNS);
+    let album_column = find_column(&ALBUM_COLUMNS);
+    let duration_ms_column = find_column(&DURATION_MS_COLUMNS);
+    let duration_column = find_column(&DURATION_COLUMNS);
+
+    let mut rows = Vec::new();
+
+    for (index, record) in reader.records().enumerate() {
+        let record = record?;
+        let field = |column: Option<usize>| {
+            column
+                .and_then(|column| record.get(column))
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+
+        let Some(title) = field(Some(title_column)) else {
+            continue;
+        };
+
+        let duration = field(duration_ms_column)
+            .and_then(|value| value.parse::<u64>().ok())
+            .map(|ms| (ms + 500) / 1000)
+            .or_else(|| field(duration_column).and_then(|value| parse_duration(&value)));
+
+        rows.push(CsvTrackRow {
+            line: index + 2,
+            query: TrackQuery {
+                title,
+                artist: field(artist_column),
+                album: field(album_column),
+                duration,
+            },
+        });
+    }
+
+    Ok(rows)
+}
+
+/// Parse "m:ss", "h:mm:ss" or plain seconds.
+fn parse_duration(value: &str) -> Option<u64> {
+    value.split(':').try_fold(0u64, |total, part| {
+        let part = part.trim();
+        let seconds = part
+            .parse::<u64>()
+            .ok()
+            .or_else(|| part.parse::<f64>().ok().map(|value| value.round() as u64))?;
+        Some(total * 60 + seconds)
+    })
+}
+
+impl PlaylistManager {
+    /// Store an imported playlist. When a playlist with the same id, or else the same
+    /// name (ignoring case), exists, `policy` decides what happens; with
+    /// [`ImportConflictPolicy::Fail`] the error is a [`PlaylistError::Conflict`].
+    pub fn import_playlist(
+        &self,
+        mut playlist: Playlist,
+        policy: ImportConflictPolicy,
+    ) -> Result<PlaylistImportOutcome, PlaylistError> {
+        let mut playlists = self.playlists.write().unwrap();
+        let mut existing: Vec<Arc<Playlist>> = playlists.values().map(|slot| slot.get()).collect();
+        existing.sort_by_key(|existing| existing.created_at);
+        let conflict = existing
+            .iter()
+            .position(|existing| existing.id == playlist.id)
+            .or_else(|| {
+                existing
+                    .iter()
+                    .position(|existing| same_name(&existing.name, &playlist.name))
+            });
+
+        let action = match (conflict, policy) {
+            (None, _) => ImportAction::Created,
+            (Some(index), ImportConflictPolicy::Fail) => {
+                return Err(PlaylistError::Conflict(Box::new(PlaylistConflict {
+                    existing: PlaylistSummary::from(existing[index].as_ref()),
+                })));
+            }
+            (Some(index), ImportConflictPolicy::Rename) => {
+                if existing[index].id == playlist.id {
+                    playlist.id = Uuid::new_v4().to_string();
+                }
+                playlist.name = unused_name(&existing, &playlist.name);
+                ImportAction::Renamed
+            }
+            (Some(index), policy) => {
+                // Change the existing playlist once edits in progress are done
+                let slot = playlists[&existing[index].id].clone();
+                drop(playlists);
+                let _edit = slot.edit.lock().unwrap();
+                let current = slot.get();
+
+                let (stored, action, added) = if policy == ImportConflictPolicy::Merge {
+                    let mut merged = current.as_ref().clone();
+                    let mut present: HashSet<String> = merged
+                        .entries
+                        .iter()
+                        .map(|entry| entry.track_id.clone())
+                        .collect();
+                    let before = merged.entries.len();
+                    merged.entries.extend(
+                        playlist
+                            .entries
+                            .into_iter()
+                            .filter(|entry| present.insert(entry.track_id.clone())),
+                    );
+                    let added = merged.entries.len() - before;
+                    if added > 0 {
+                        merged.modified_at = next_modified_at(current.modified_at);
+                    }
+                    (merged, ImportAction::Merged, added)
+                } else {
+                    playlist.id = current.id.clone();
+                    playlist.created_at = current.created_at;
+                    playlist.file_path = current.file_path.clone();
+                    playlist.modified_at = next_modified_at(current.modified_at);
+                    let added = playlist.entries.len();
+                    (playlist, ImportAction::Replaced, added)
+                };
+                return Ok(self.store_import(&slot.set(stored), action, added));
+            }
+        };
+
+        let entries_added = playlist.entries.len();
+        playlists.insert(playlist.id.clone(), PlaylistSlot::new(playlist.clone()));
+        drop(playlists);
+        Ok(self.store_import(&playlist, action, entries_added))
+    }
+
+    /// Save an imported playlist, already stored in memory
+    fn store_import(
+        &self,
+        playlist: &Playlist,
+        action: ImportAction,
+        entries_added: usize,
+    ) -> PlaylistImportOutcome {
+        if let Err(e) = self.save_playlist(playlist) {
+            warn!(
+                "Failed to save imported playlist '{}': {}",
+                playlist.name, e
+            );
+        }
+
+        PlaylistImportOutcome {
+            playlist_id: playlist.id.clone(),
+            playlist_name: playlist.name.clone(),
+            action,
+            entries_added,
+        }
+    }
+
+    /// Import a playlist from an exported CSV, matching each row against the library.
+    ///
+    /// Low confidence matches are still added so the playlist keeps its order; the report
+    /// lists them for review. A clash with an existing playlist of the same name is
+    /// handled per `policy`. With `dry_run` nothing is created.
+    pub fn import_csv(
+        &self,
+        library: &Library,
+        name: &str,
+        content: &str,
+        dry_run: bool,
+        policy: ImportConflictPolicy,
+    ) -> Result<CsvImportReport, PlaylistError> {
+        let rows = parse_playlist_csv(content)?;
+        let tracks = library.get_tracks();
+        let matcher = TrackMatcher::new(&tracks);
+
+        let mut matched = Vec::new();
+        let mut unmatched = Vec::new();
+
+        for row in rows.iter() {
+            match matcher.find(&row.query) {
+                Some(track) => matched.push(CsvImportMatch {
+                    row: row.clone(),
+                    track,
+                }),
+                None => unmatched.push(row.clone()),
+            }
+        }
+
+        let mut report = CsvImportReport {
+            playlist_id: None,
+            playlist_name: name.to_string(),
+            action: None,
+            dry_run,
+            total_rows: rows.len(),
+            matched,
+            unmatched,
+        };
+
+        if dry_run || report.matched.is_empty() {
+            return Ok(report);
+        }
+
+        let mut playlist = Playlist::new(name.to_string(), None);
+        for entry in report.matched.iter() {
+            if let Some(track) = library.get_track(&entry.track.track_id) {
+                playlist.add_track(&track);
+            }
+        }
+
+        let outcome = self.import_playlist(playlist, policy)?;
+
+        info!(
+            "Imported playlist '{}' ({:?}): {} of {} rows matched",
+            outcome.playlist_name,
+            outcome.action,
+            report.matched.len(),
+            report.total_rows
+        );
+
+        report.playlist_id = Some(outcome.playlist_id);
+        report.playlist_name = outcome.playlist_name;
+        report.action = Some(outcome.action);
+        Ok(report)
+    }
+}
+
+fn same_name(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+/// `name` with [`IMPORTED_SUFFIX`] appended, numbered when that name is taken too
+fn unused_name(playlists: &[Arc<Playlist>], name: &str) -> String {
+    let taken = |candidate: &str| {
+        playlists
+            .iter()
+            .any(|playlist| same_name(&playlist.name, candidate))
+    };
+
+    let renamed = format!("{}{}", name, IMPORTED_SUFFIX);
+    if !taken(&renamed) {
+        return renamed;
+    }
+    (2..)
+        .map(|number| format!("{} (imported {})", name, number))
+        .find(|candidate| !taken(candidate))
+        .unwrap()
+}
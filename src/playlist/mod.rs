@@ -1,13 +1,34 @@
-use anyhow::Result;
 use chrono::{DateTime, Utc};
+use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::path::PathBuf;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
 use tracing::{debug, info, warn};
+use utoipa::ToSchema;
 use uuid::Uuid;
 
 use crate::library::{Library, Track};
+use crate::utils::schema::{self, Schema};
+
+mod error;
+mod folders;
+mod import;
+mod portable;
+
+pub use error::PlaylistError;
+pub use folders::PlaylistFolder;
+#[allow(unused_imports)]
+pub use folders::{normalize_folder, playlist_tree, renamed_folder, FOLDER_SEPARATOR};
+pub use import::{
+    CsvImportMatch, CsvImportReport, CsvTrackRow, ImportAction, ImportConflictPolicy,
+    PlaylistConflict,
+};
+#[allow(unused_imports)]
+pub use import::{PlaylistImportOutcome, IMPORTED_SUFFIX};
+#[allow(unused_imports)]
+pub use portable::PortableLocation;
+pub use portable::{EntryPath, MusicRoot, MusicRoots};
 
 /// Playlist entry
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,11 +36,23 @@ pub struct PlaylistEntry {
     /// Track ID
     pub track_id: String,
     /// Added timestamp
+    #[serde(with = "crate::utils::serde_rfc3339")]
     pub added_at: DateTime<Utc>,
     /// Play count
     pub play_count: u32,
     /// Last played timestamp
+    #[serde(with = "crate::utils::serde_rfc3339::option")]
     pub last_played: Option<DateTime<Utc>>,
+    /// Free-form note about the entry
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Pinned entries are listed and played before the others
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+    /// File of the track, to link the entry to it by path when the id does not match,
+    /// e.g. in a library scanned on another machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<EntryPath>,
 }
 
 /// A music playlist
@@ -32,13 +65,41 @@ pub struct Playlist {
     /// Playlist description
     pub description: Option<String>,
     /// Created timestamp
+    #[serde(with = "crate::utils::serde_rfc3339")]
     pub created_at: DateTime<Utc>,
     /// Modified timestamp
+    #[serde(with = "crate::utils::serde_rfc3339")]
     pub modified_at: DateTime<Utc>,
     /// Playlist entries
     pub entries: Vec<PlaylistEntry>,
     /// Playlist file path (if saved)
     pub file_path: Option<PathBuf>,
+    /// Order the tracks are queued in when the playlist is played
+    #[serde(default)]
+    pub play_order: PlayOrder,
+    /// Repeat mode set when the playlist is played; the queue's mode is kept if unset
+    #[serde(default)]
+    pub default_repeat: Option<RepeatMode>,
+    /// Virtual folder path such as "Workout/Running"; `None` at the root
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder: Option<String>,
+}
+
+/// Order a playlist is played in, independent of the stored entry order
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PlayOrder {
+    /// Entry order
+    #[default]
+    Stored,
+    /// A new random order each time the playlist is played
+    Shuffle,
+    /// By track title
+    Title,
+    /// By artist, then album and track number
+    Artist,
+    /// Most recently added entries first
+    AddedDesc,
 }
 
 #[allow(dead_code)]
@@ -54,6 +115,9 @@ impl Playlist {
             modified_at: now,
             entries: Vec::new(),
             file_path: None,
+            play_order: PlayOrder::default(),
+            default_repeat: None,
+            folder: None,
         }
     }
 
@@ -64,6 +128,9 @@ impl Playlist {
             added_at: Utc::now(),
             play_count: 0,
             last_played: None,
+            note: None,
+            pinned: false,
+            path: Some(EntryPath::Local(track.metadata.file_path.clone())),
         };
 
         self.entries.push(entry);
@@ -133,6 +200,107 @@ impl Playlist {
             .sum()
     }
 
+    /// Entries with their stored positions, pinned entries first
+    ///
+    /// Pinned and unpinned entries each keep their stored relative order.
+    pub fn listed_entries(&self) -> Vec<(usize, &PlaylistEntry)> {
+        let mut entries: Vec<(usize, &PlaylistEntry)> = self.entries.iter().enumerate().collect();
+        entries.sort_by_key(|(_, entry)| !entry.pinned);
+        entries
+    }
+
+    /// Change the note and pin of the first entry of `track_id`. A blank note removes
+    /// it. Returns the stored position of the entry, or `None` if the track is not in
+    /// the playlist.
+    pub fn update_entry(
+        &mut self,
+        track_id: &str,
+        note: Option<Option<String>>,
+        pinned: Option<bool>,
+    ) -> Option<usize> {
+        let position = self
+            .entries
+            .iter()
+            .position(|entry| entry.track_id == track_id)?;
+        let entry = &mut self.entries[position];
+
+        if let Some(note) = note {
+            entry.note = note
+                .map(|note| note.trim().to_string())
+                .filter(|note| !note.is_empty());
+        }
+        if let Some(pinned) = pinned {
+            entry.pinned = pinned;
+        }
+        self.modified_at = Utc::now();
+
+        Some(position)
+    }
+
+    /// Track ids in the playlist's play order, skipping tracks no longer in the library
+    ///
+    /// The stored play order starts with the pinned entries, like
+    /// [`Playlist::listed_entries`]. The stored entry order is left as it is.
+    pub fn tracks_in_play_order(&self, library: &Library) -> Vec<String> {
+        let mut tracks: Vec<(&PlaylistEntry, Track)> = self
+            .listed_entries()
+            .into_iter()
+            .filter_map(|(_, entry)| {
+                library
+                    .get_track(&entry.track_id)
+                    .map(|track| (entry, track))
+            })
+            .collect();
+
+        match self.play_order {
+            PlayOrder::Stored => {}
+            PlayOrder::Shuffle => tracks.sort_by_cached_key(|_| Uuid::new_v4()),
+            PlayOrder::Title => {
+                tracks.sort_by_cached_key(|(_, track)| sort_text(&track.metadata.title))
+            }
+            PlayOrder::Artist => tracks.sort_by_cached_key(|(_, track)| {
+                (
+                    sort_text(&track.metadata.artist),
+                    sort_text(&track.metadata.album),
+                    track.metadata.track_number,
+                    sort_text(&track.metadata.title),
+                )
+            }),
+            PlayOrder::AddedDesc => {
+                tracks.sort_by_key(|(entry, _)| std::cmp::Reverse(entry.added_at))
+            }
+        }
+
+        tracks.into_iter().map(|(_, track)| track.id).collect()
+    }
+
+    /// Render the playlist as an extended M3U file, in listing order
+    ///
+    /// Entries whose track is no longer in the library are left out. Notes follow the
+    /// `#EXTINF` line of their entry as comments.
+    pub fn to_m3u(&self, library: &Library) -> String {
+        let mut output = format!("#EXTM3U\n#PLAYLIST:{}\n", single_line(&self.name));
+        for (_, entry) in self.listed_entries() {
+            let Some(track) = library.get_track(&entry.track_id) else {
+                continue;
+            };
+            let duration = track
+                .metadata
+                .duration
+                .map_or(-1, |duration| duration as i64);
+            output.push_str(&format!(
+                "#EXTINF:{},{}\n",
+                duration,
+                single_line(&track.display_name())
+            ));
+            for line in entry.note.iter().flat_map(|note| note.lines()) {
+                output.push_str(&format!("# {}\n", line));
+            }
+            output.push_str(&format!("{}\n", track.metadata.file_path.display()));
+        }
+        output
+    }
+
     /// Mark track as played
     pub fn mark_track_played(&mut self, track_id: &str) {
         if let Some(entry) = self.entries.iter_mut().find(|e| e.track_id == track_id) {
@@ -141,70 +309,325 @@ impl Playlist {
             self.modified_at = Utc::now();
         }
     }
+
+    /// Point entries of the `replaced` tracks at `keeper`, e.g. after removing
+    /// duplicate copies. Entries that end up on the same track are merged into the
+    /// first one, adding up play counts and keeping the latest play and earliest add.
+    /// Returns whether anything changed.
+    pub fn replace_tracks(&mut self, replaced: &[String], keeper: &str) -> bool {
+        if !self
+            .entries
+            .iter()
+            .any(|entry| replaced.contains(&entry.track_id))
+        {
+            return false;
+        }
+
+        let mut entries: Vec<PlaylistEntry> = Vec::with_capacity(self.entries.len());
+        let mut keeper_index: Option<usize> = None;
+        for mut entry in self.entries.drain(..) {
+            if replaced.contains(&entry.track_id) {
+                entry.track_id = keeper.to_string();
+                // The keeper's path is filled in by the next relink
+                entry.path = None;
+            }
+            if entry.track_id != keeper {
+                entries.push(entry);
+                continue;
+            }
+            match keeper_index {
+                Some(index) => {
+                    let merged = &mut entries[index];
+                    merged.play_count += entry.play_count;
+                    merged.last_played = merged.last_played.max(entry.last_played);
+                    merged.added_at = merged.added_at.min(entry.added_at);
+                    merged.pinned |= entry.pinned;
+                    if merged.note.is_none() {
+                        merged.note = entry.note;
+                    }
+                }
+                None => {
+                    keeper_index = Some(entries.len());
+                    entries.push(entry);
+                }
+            }
+        }
+
+        self.entries = entries;
+        self.modified_at = Utc::now();
+        true
+    }
+}
+
+/// `value` with line breaks replaced, for single-line M3U directives
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+/// Case-insensitive sort key; missing values sort last
+fn sort_text(value: &Option<String>) -> (bool, String) {
+    match value {
+        Some(value) => (false, value.to_lowercase()),
+        None => (true, String::new()),
+    }
+}
+
+/// Playlist files larger than this are written as compact JSON
+pub const COMPACT_PLAYLIST_THRESHOLD: usize = 256 * 1024;
+
+/// Versions of the playlist file format
+///
+/// 1: files record their version
+const PLAYLIST_SCHEMA: Schema = Schema {
+    name: "playlist",
+    migrations: &[schema::optional_fields],
+};
+
+/// A playlist as saved, with the version of the format
+#[derive(Serialize)]
+struct StoredPlaylist<'a> {
+    version: u32,
+    #[serde(flatten)]
+    playlist: &'a Playlist,
+}
+
+/// Playlist details without its entries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlaylistSummary {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub track_count: usize,
+    pub created_at: DateTime<Utc>,
+    pub modified_at: DateTime<Utc>,
+    pub play_order: PlayOrder,
+    pub default_repeat: Option<RepeatMode>,
+    pub folder: Option<String>,
+}
+
+impl From<&Playlist> for PlaylistSummary {
+    fn from(playlist: &Playlist) -> Self {
+        Self {
+            id: playlist.id.clone(),
+            name: playlist.name.clone(),
+            description: playlist.description.clone(),
+            track_count: playlist.track_count(),
+            created_at: playlist.created_at,
+            modified_at: playlist.modified_at,
+            play_order: playlist.play_order,
+            default_repeat: playlist.default_repeat,
+            folder: playlist.folder.clone(),
+        }
+    }
+}
+
+/// Why [`PlaylistManager::edit_playlist`] made no change
+#[derive(Debug, thiserror::Error)]
+pub enum PlaylistEditError {
+    #[error("Playlist not found")]
+    NotFound,
+    /// The playlist was changed after the `modified_at` the edit was based on
+    #[error(
+        "The playlist was changed at {}; reload it and try again",
+        crate::utils::serde_rfc3339::format(modified_at)
+    )]
+    Conflict { modified_at: DateTime<Utc> },
+    #[error("Failed to save the playlist: {0}")]
+    Save(PlaylistError),
+}
+
+/// A stored playlist. Edits are serialized by `edit` and swap `current` only once
+/// they are saved, so readers never wait for an edit in progress.
+struct PlaylistSlot {
+    edit: Mutex<()>,
+    current: RwLock<Arc<Playlist>>,
+}
+
+impl PlaylistSlot {
+    fn new(playlist: Playlist) -> Arc<Self> {
+        Arc::new(Self {
+            edit: Mutex::new(()),
+            current: RwLock::new(Arc::new(playlist)),
+        })
+    }
+
+    fn get(&self) -> Arc<Playlist> {
+        self.current.read().unwrap().clone()
+    }
+
+    fn set(&self, playlist: Playlist) -> Arc<Playlist> {
+        let playlist = Arc::new(playlist);
+        *self.current.write().unwrap() = playlist.clone();
+        playlist
+    }
+}
+
+/// `modified_at` for an edit of a playlist last modified at `previous`, always later
+/// so a client holding `previous` sees the conflict even on a coarse clock
+fn next_modified_at(previous: DateTime<Utc>) -> DateTime<Utc> {
+    Utc::now().max(previous + chrono::Duration::microseconds(1))
 }
 
 /// Playlist manager
+///
+/// Each playlist is locked on its own, so editing one does not hold up reading or
+/// editing the others. Playlists are handed out behind `Arc`s without copying their
+/// entries; edits work on a copy that replaces the stored playlist once saved.
 pub struct PlaylistManager {
-    playlists: Arc<Mutex<Vec<Playlist>>>,
+    playlists: Arc<RwLock<HashMap<String, Arc<PlaylistSlot>>>>,
     current_playlist: Arc<Mutex<Option<String>>>,
-    playlist_directory: PathBuf,
+    /// Where playlists are saved; `None` keeps them in memory only
+    playlist_directory: Option<PathBuf>,
+    /// Music directories entry paths are written relative to
+    music_roots: MusicRoots,
+    /// Whether entry paths are written relative to the music directories
+    portable_paths: bool,
 }
 
 #[allow(dead_code)]
 impl PlaylistManager {
     /// Create a new playlist manager
-    pub fn new(playlist_directory: PathBuf) -> Result<Self> {
+    pub fn new(playlist_directory: PathBuf) -> Result<Self, PlaylistError> {
         std::fs::create_dir_all(&playlist_directory)?;
 
         Ok(Self {
-            playlists: Arc::new(Mutex::new(Vec::new())),
-            current_playlist: Arc::new(Mutex::new(None)),
-            playlist_directory,
+            playlist_directory: Some(playlist_directory),
+            ..Self::in_memory()
         })
     }
 
+    /// Create a playlist manager that never touches the disk: playlists are kept for
+    /// as long as it lives, saving them does nothing and there is nothing to load
+    pub fn in_memory() -> Self {
+        Self {
+            playlists: Arc::new(RwLock::new(HashMap::new())),
+            current_playlist: Arc::new(Mutex::new(None)),
+            playlist_directory: None,
+            music_roots: MusicRoots::default(),
+            portable_paths: false,
+        }
+    }
+
+    /// Resolve entry paths written relative to a music directory through `music_roots`
+    pub fn with_music_roots(mut self, music_roots: MusicRoots) -> Self {
+        self.music_roots = music_roots;
+        self
+    }
+
+    /// Write entry paths relative to the music directories, as a music directory's
+    /// logical name and the path below it, so playlist files synced between machines
+    /// mounting the music elsewhere keep working.
+    pub fn with_portable_paths(mut self, portable_paths: bool) -> Self {
+        self.portable_paths = portable_paths;
+        self
+    }
+
+    fn slot(&self, id: &str) -> Option<Arc<PlaylistSlot>> {
+        self.playlists.read().unwrap().get(id).cloned()
+    }
+
+    /// Every stored playlist, oldest first
+    fn slots(&self) -> Vec<Arc<PlaylistSlot>> {
+        let mut slots: Vec<Arc<PlaylistSlot>> =
+            self.playlists.read().unwrap().values().cloned().collect();
+        slots.sort_by_cached_key(|slot| {
+            let playlist = slot.get();
+            (playlist.created_at, playlist.id.clone())
+        });
+        slots
+    }
+
     /// Create a new playlist
     pub fn create_playlist(&self, name: String, description: Option<String>) -> String {
         let playlist = Playlist::new(name, description);
         let id = playlist.id.clone();
 
-        let mut playlists = self.playlists.lock().unwrap();
-        playlists.push(playlist);
+        let mut playlists = self.playlists.write().unwrap();
+        playlists.insert(id.clone(), PlaylistSlot::new(playlist));
 
         id
     }
 
     /// Get a playlist by ID
-    pub fn get_playlist(&self, id: &str) -> Option<Playlist> {
-        let playlists = self.playlists.lock().unwrap();
-        playlists.iter().find(|p| p.id == id).cloned()
+    pub fn get_playlist(&self, id: &str) -> Option<Arc<Playlist>> {
+        self.slot(id).map(|slot| slot.get())
     }
 
-    /// Get all playlists
-    pub fn get_playlists(&self) -> Vec<Playlist> {
-        let playlists = self.playlists.lock().unwrap();
-        playlists.clone()
+    /// Get all playlists, oldest first
+    pub fn get_playlists(&self) -> Vec<Arc<Playlist>> {
+        self.slots().iter().map(|slot| slot.get()).collect()
     }
 
-    /// Update a playlist
+    /// Get the details of every playlist without their entries
+    pub fn playlist_summaries(&self) -> Vec<PlaylistSummary> {
+        self.get_playlists()
+            .iter()
+            .map(|playlist| PlaylistSummary::from(playlist.as_ref()))
+            .collect()
+    }
+
+    /// Replace a stored playlist with `playlist`, without checking for concurrent
+    /// edits; see [`Self::edit_playlist`]
     pub fn update_playlist(&self, playlist: Playlist) -> bool {
-        let mut playlists = self.playlists.lock().unwrap();
+        let Some(slot) = self.slot(&playlist.id) else {
+            return false;
+        };
+        let _edit = slot.edit.lock().unwrap();
+        slot.set(playlist);
+        true
+    }
 
-        if let Some(index) = playlists.iter().position(|p| p.id == playlist.id) {
-            playlists[index] = playlist;
-            true
-        } else {
-            false
+    /// Edit a playlist and save it. Edits of the same playlist run one at a time and
+    /// edits of different playlists in parallel.
+    ///
+    /// With `expected_modified_at`, the `modified_at` the client last saw, the edit is
+    /// refused with [`PlaylistEditError::Conflict`] if the playlist has changed since,
+    /// so two clients cannot overwrite each other's changes. When `edit` fails the
+    /// playlist is left as it was. Returns what `edit` returned and the saved playlist.
+    pub fn edit_playlist<T, E>(
+        &self,
+        id: &str,
+        expected_modified_at: Option<DateTime<Utc>>,
+        edit: impl FnOnce(&mut Playlist) -> Result<T, E>,
+    ) -> Result<(T, Arc<Playlist>), E>
+    where
+        E: From<PlaylistEditError>,
+    {
+        let slot = self.slot(id).ok_or(PlaylistEditError::NotFound)?;
+        let _edit = slot.edit.lock().unwrap();
+
+        let mut playlist = slot.get().as_ref().clone();
+        let previous = playlist.modified_at;
+        if expected_modified_at.is_some_and(|expected| expected != previous) {
+            return Err(PlaylistEditError::Conflict {
+                modified_at: previous,
+            }
+            .into());
         }
+        let value = edit(&mut playlist)?;
+        playlist.modified_at = next_modified_at(previous);
+        self.save_playlist(&playlist)
+            .map_err(PlaylistEditError::Save)?;
+        Ok((value, slot.set(playlist)))
+    }
+
+    /// Append `track` to a playlist, see [`Self::edit_playlist`]
+    pub fn add_track(
+        &self,
+        id: &str,
+        track: &Track,
+        expected_modified_at: Option<DateTime<Utc>>,
+    ) -> Result<Arc<Playlist>, PlaylistEditError> {
+        self.edit_playlist(id, expected_modified_at, |playlist| {
+            playlist.add_track(track);
+            Ok::<_, PlaylistEditError>(())
+        })
+        .map(|((), playlist)| playlist)
     }
 
     /// Delete a playlist
     pub fn delete_playlist(&self, id: &str) -> bool {
-        let mut playlists = self.playlists.lock().unwrap();
-        let initial_len = playlists.len();
-        playlists.retain(|p| p.id != id);
-
-        let removed = initial_len != playlists.len();
+        let removed = self.playlists.write().unwrap().remove(id).is_some();
         if removed {
             let mut current = self.current_playlist.lock().unwrap();
             if current.as_deref() == Some(id) {
@@ -227,172 +650,404 @@ impl PlaylistManager {
     }
 
     /// Save playlist to file
-    pub fn save_playlist(&self, playlist: &Playlist) -> Result<()> {
-        let file_path = self
-            .playlist_directory
-            .join(format!("{}.json", playlist.id));
-        let content = serde_json::to_string_pretty(playlist)?;
+    ///
+    /// Small playlists are pretty-printed; playlists above
+    /// [`COMPACT_PLAYLIST_THRESHOLD`] are written compact.
+    pub fn save_playlist(&self, playlist: &Playlist) -> Result<(), PlaylistError> {
+        let Some(playlist_directory) = self.playlist_directory.as_ref() else {
+            return Ok(());
+        };
+        let file_path = playlist_directory.join(format!("{}.json", playlist.id));
+        let mut stored = playlist.clone();
+        for entry in &mut stored.entries {
+            if let Some(path) = entry.path.as_mut() {
+                *path = self.music_roots.stored(path, self.portable_paths);
+            }
+        }
+        let stored = StoredPlaylist {
+            version: PLAYLIST_SCHEMA.current(),
+            playlist: &stored,
+        };
+        let mut content = serde_json::to_vec(&stored)?;
+        if content.len() <= COMPACT_PLAYLIST_THRESHOLD {
+            content = serde_json::to_vec_pretty(&stored)?;
+        }
         std::fs::write(&file_path, content)?;
 
         Ok(())
     }
 
     /// Load playlist from file
-    pub fn load_playlist(&self, file_path: &PathBuf) -> Result<Playlist> {
+    pub fn load_playlist(&self, file_path: &PathBuf) -> Result<Playlist, PlaylistError> {
         let content = std::fs::read_to_string(file_path)?;
-        let mut playlist: Playlist = serde_json::from_str(&content)?;
+        let mut document: serde_json::Value = serde_json::from_str(&content)?;
+        PLAYLIST_SCHEMA.upgrade(&mut document, file_path)?;
+        let mut playlist: Playlist = serde_json::from_value(document)?;
         playlist.file_path = Some(file_path.to_path_buf());
+        for entry in &mut playlist.entries {
+            if let Some(path) = entry.path.take() {
+                entry.path = Some(self.music_roots.loaded(path));
+            }
+        }
         Ok(playlist)
     }
 
     /// Load all playlists from directory
-    pub fn load_all_playlists(&self) -> Result<()> {
-        let mut playlists = Vec::new();
+    pub fn load_all_playlists(&self) -> Result<(), PlaylistError> {
+        let Some(playlist_directory) = self.playlist_directory.as_ref() else {
+            return Ok(());
+        };
+        let mut playlists = HashMap::new();
 
-        for entry in std::fs::read_dir(&self.playlist_directory)? {
+        for entry in std::fs::read_dir(playlist_directory)? {
             let entry = entry?;
             let path = entry.path();
 
             if path.extension().and_then(|s| s.to_str()) == Some("json") {
-                if let Ok(playlist) = self.load_playlist(&path) {
-                    playlists.push(playlist);
+                match self.load_playlist(&path) {
+                    Ok(playlist) => {
+                        playlists.insert(playlist.id.clone(), PlaylistSlot::new(playlist));
+                    }
+                    Err(e) => warn!("Skipping playlist {:?}: {}", path, e),
                 }
             }
         }
 
-        let mut playlists_guard = self.playlists.lock().unwrap();
-        *playlists_guard = playlists;
+        *self.playlists.write().unwrap() = playlists;
 
         Ok(())
     }
 
-    /// Clean up playlists by removing tracks that no longer exist in the library
-    /// Returns the number of tracks removed across all playlists
-    pub fn cleanup_missing_tracks(&self, library: &Library) -> Result<usize> {
-        let mut playlists = self.playlists.lock().unwrap();
-        let mut total_removed = 0;
-        let mut playlists_to_save = Vec::new();
-
-        for playlist in playlists.iter_mut() {
-            let initial_count = playlist.entries.len();
-
-            // Filter out entries whose tracks don't exist in the library
-            playlist.entries.retain(|entry| {
-                let exists = library.track_exists(&entry.track_id);
-                if !exists {
-                    debug!(
-                        "Removing track {} from playlist '{}' - track not found in library",
-                        entry.track_id, playlist.name
-                    );
-                }
-                exists
-            });
-
-            let removed = initial_count - playlist.entries.len();
-            if removed > 0 {
-                total_removed += removed;
-                playlist.modified_at = Utc::now();
-
-                info!(
-                    "Removed {} missing track(s) from playlist '{}'",
-                    removed, playlist.name
-                );
-
-                // Clone playlist to save later (after releasing lock)
-                playlists_to_save.push(playlist.clone());
+    /// Change every playlist `change` returns a changed copy for, saving it; playlists
+    /// that fail to save keep the change in memory. Returns the changed playlists.
+    fn change_playlists(
+        &self,
+        mut change: impl FnMut(&Playlist) -> Option<Playlist>,
+    ) -> Vec<Arc<Playlist>> {
+        let mut changed = Vec::new();
+        for slot in self.slots() {
+            let _edit = slot.edit.lock().unwrap();
+            let Some(mut playlist) = change(&slot.get()) else {
+                continue;
+            };
+            playlist.modified_at = next_modified_at(slot.get().modified_at);
+            if let Err(e) = self.save_playlist(&playlist) {
+                warn!("Failed to save playlist '{}': {}", playlist.name, e);
             }
+            changed.push(slot.set(playlist));
         }
+        changed
+    }
 
-        drop(playlists);
+    /// Find entries whose tracks no longer exist in the library, in one playlist or
+    /// across all of them, without changing anything
+    pub fn find_orphaned_entries(
+        &self,
+        library: &Library,
+        playlist_id: Option<&str>,
+    ) -> Result<Vec<OrphanedEntry>, PlaylistError> {
+        match playlist_id {
+            Some(id) => self
+                .get_playlist(id)
+                .map(|playlist| orphaned_entries(&playlist, library))
+                .ok_or_else(|| PlaylistError::PlaylistNotFound(id.to_string())),
+            None => Ok(self
+                .get_playlists()
+                .iter()
+                .flat_map(|playlist| orphaned_entries(playlist, library))
+                .collect()),
+        }
+    }
 
-        // Save all updated playlists
-        for playlist in playlists_to_save {
-            if let Err(e) = self.save_playlist(&playlist) {
-                warn!("Failed to save cleaned playlist '{}': {}", playlist.name, e);
+    /// Clean up playlists by removing tracks that no longer exist in the library
+    /// Returns the removed entries across all playlists
+    pub fn cleanup_missing_tracks(
+        &self,
+        library: &Library,
+    ) -> Result<Vec<OrphanedEntry>, PlaylistError> {
+        let mut removed = Vec::new();
+
+        self.change_playlists(|playlist| {
+            let orphans = orphaned_entries(playlist, library);
+            if orphans.is_empty() {
+                return None;
             }
-        }
+            let mut cleaned = playlist.clone();
+            remove_entries(&mut cleaned, &orphans);
+            removed.extend(orphans);
+            Some(cleaned)
+        });
 
-        if total_removed > 0 {
+        if !removed.is_empty() {
             info!(
                 "Playlist cleanup complete: {} missing track(s) removed across all playlists",
-                total_removed
+                removed.len()
             );
         } else {
             debug!("Playlist cleanup complete: no missing tracks found");
         }
 
-        Ok(total_removed)
+        Ok(removed)
     }
 
-    /// Clean up a specific playlist by removing tracks that no longer exist
-    /// Returns the number of tracks removed
-    pub fn cleanup_playlist(&self, playlist_id: &str, library: &Library) -> Result<usize> {
-        let mut playlists = self.playlists.lock().unwrap();
-
-        let playlist = playlists.iter_mut().find(|p| p.id == playlist_id);
-
-        if let Some(playlist) = playlist {
-            let playlist_name = playlist.name.clone();
-            let initial_count = playlist.entries.len();
-
-            playlist.entries.retain(|entry| {
-                let exists = library.track_exists(&entry.track_id);
-                if !exists {
-                    debug!(
-                        "Removing track {} from playlist '{}' - track not found in library",
-                        entry.track_id, playlist_name
-                    );
+    /// Link playlist entries to the tracks of `library` by file path: entries whose
+    /// file belongs to a track with another id are pointed at it, and entries without a
+    /// path get the one of their track, found by its id from before the cache was
+    /// upgraded if need be. Returns the entries pointed at another track;
+    /// unless `dry_run` is set, the playlists are changed and saved.
+    pub fn relink_entries(
+        &self,
+        library: &Library,
+        dry_run: bool,
+    ) -> Result<Vec<RelinkedEntry>, PlaylistError> {
+        let mut relinked = Vec::new();
+
+        for slot in self.slots() {
+            let _edit = slot.edit.lock().unwrap();
+            let playlist = slot.get();
+            let mut changed = false;
+            let mut updated = playlist.as_ref().clone();
+            for (position, entry) in updated.entries.iter_mut().enumerate() {
+                match &entry.path {
+                    Some(EntryPath::Local(path)) => {
+                        let Some(track) = library.get_track_by_path(path) else {
+                            continue;
+                        };
+                        if track.id != entry.track_id {
+                            relinked.push(RelinkedEntry {
+                                playlist_name: playlist.name.clone(),
+                                position,
+                                previous_track_id: entry.track_id.clone(),
+                                track_id: track.id.clone(),
+                            });
+                            entry.track_id = track.id;
+                            changed = true;
+                        }
+                    }
+                    Some(EntryPath::Portable(_)) => {}
+                    None => {
+                        // Tracks of caches from older versions have new ids
+                        let track = library.get_track(&entry.track_id).or_else(|| {
+                            library
+                                .renamed_track_id(&entry.track_id)
+                                .and_then(|id| library.get_track(&id))
+                        });
+                        let Some(track) = track else {
+                            continue;
+                        };
+                        if track.id != entry.track_id {
+                            relinked.push(RelinkedEntry {
+                                playlist_name: playlist.name.clone(),
+                                position,
+                                previous_track_id: entry.track_id.clone(),
+                                track_id: track.id.clone(),
+                            });
+                            entry.track_id = track.id;
+                        }
+                        entry.path = Some(EntryPath::Local(track.metadata.file_path));
+                        changed = true;
+                    }
                 }
-                exists
-            });
+            }
+            if changed && !dry_run {
+                self.save_playlist(&updated)?;
+                slot.set(updated);
+            }
+        }
 
-            let removed = initial_count - playlist.entries.len();
-            if removed > 0 {
-                playlist.modified_at = Utc::now();
+        if !relinked.is_empty() {
+            info!(
+                "Linked {} playlist entry(ies) to tracks by file path",
+                relinked.len()
+            );
+        }
 
-                info!(
-                    "Removed {} missing track(s) from playlist '{}'",
-                    removed, playlist_name
-                );
+        Ok(relinked)
+    }
 
-                // Clone the playlist before dropping the lock
-                let playlist_clone = playlist.clone();
-                drop(playlists);
+    /// Save every playlist again, converting its entry paths to the configured form.
+    /// Returns the number of playlists written.
+    pub fn rewrite_playlists(&self) -> Result<usize, PlaylistError> {
+        let slots = self.slots();
+        for slot in &slots {
+            let _edit = slot.edit.lock().unwrap();
+            self.save_playlist(&slot.get())?;
+        }
+        Ok(slots.len())
+    }
 
-                // Save the updated playlist
-                if let Err(e) = self.save_playlist(&playlist_clone) {
-                    warn!("Failed to save cleaned playlist '{}': {}", playlist_name, e);
-                }
+    /// Point entries of the `replaced` tracks at `keeper` in every playlist, merging
+    /// entries that end up on the same track. Returns the number of playlists changed.
+    pub fn replace_tracks(&self, replaced: &[String], keeper: &str) -> usize {
+        self.change_playlists(|playlist| {
+            let mut updated = playlist.clone();
+            updated.replace_tracks(replaced, keeper).then_some(updated)
+        })
+        .len()
+    }
 
-                Ok(removed)
-            } else {
-                drop(playlists);
-                Ok(0)
+    /// Clean up a specific playlist by removing tracks that no longer exist
+    /// Returns the removed entries
+    pub fn cleanup_playlist(
+        &self,
+        playlist_id: &str,
+        library: &Library,
+    ) -> Result<Vec<OrphanedEntry>, PlaylistError> {
+        let slot = self
+            .slot(playlist_id)
+            .ok_or_else(|| PlaylistError::PlaylistNotFound(playlist_id.to_string()))?;
+        let _edit = slot.edit.lock().unwrap();
+        let playlist = slot.get();
+
+        let removed = orphaned_entries(&playlist, library);
+        if !removed.is_empty() {
+            let mut cleaned = playlist.as_ref().clone();
+            remove_entries(&mut cleaned, &removed);
+            cleaned.modified_at = next_modified_at(playlist.modified_at);
+            if let Err(e) = self.save_playlist(&cleaned) {
+                warn!("Failed to save cleaned playlist '{}': {}", cleaned.name, e);
             }
-        } else {
-            drop(playlists);
-            Err(anyhow::anyhow!("Playlist not found: {}", playlist_id))
+            slot.set(cleaned);
         }
+
+        Ok(removed)
     }
 }
 
+/// A playlist entry pointed at another track by [`PlaylistManager::relink_entries`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelinkedEntry {
+    pub playlist_name: String,
+    /// Position of the entry in the playlist
+    pub position: usize,
+    pub previous_track_id: String,
+    pub track_id: String,
+}
+
+/// A playlist entry whose track no longer exists in the library
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrphanedEntry {
+    pub playlist_id: String,
+    pub playlist_name: String,
+    pub track_id: String,
+    /// Position of the entry in the playlist before cleanup
+    pub position: usize,
+}
+
+fn orphaned_entries(playlist: &Playlist, library: &Library) -> Vec<OrphanedEntry> {
+    playlist
+        .entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| !library.track_exists(&entry.track_id))
+        .map(|(position, entry)| OrphanedEntry {
+            playlist_id: playlist.id.clone(),
+            playlist_name: playlist.name.clone(),
+            track_id: entry.track_id.clone(),
+            position,
+        })
+        .collect()
+}
+
+/// Remove the entries found by [`orphaned_entries`].
+fn remove_entries(playlist: &mut Playlist, orphans: &[OrphanedEntry]) {
+    for orphan in orphans {
+        debug!(
+            "Removing track {} from playlist '{}' - track not found in library",
+            orphan.track_id, playlist.name
+        );
+    }
+    let mut position = 0;
+    playlist.entries.retain(|_| {
+        let keep = !orphans.iter().any(|orphan| orphan.position == position);
+        position += 1;
+        keep
+    });
+    playlist.modified_at = Utc::now();
+
+    info!(
+        "Removed {} missing track(s) from playlist '{}'",
+        orphans.len(),
+        playlist.name
+    );
+}
+
 /// Playback queue
 pub struct PlaybackQueue {
     tracks: Arc<Mutex<VecDeque<String>>>,
     current_index: Arc<Mutex<Option<usize>>>,
+    /// Recently played tracks, newest first
+    history: Arc<Mutex<VecDeque<QueueHistoryEntry>>>,
+    /// Position in `history` reached by stepping back in shuffle mode
+    history_cursor: Arc<Mutex<Option<usize>>>,
     repeat_mode: Arc<Mutex<RepeatMode>>,
+    /// Repeat mode last set with [`PlaybackQueue::set_repeat_mode`], the one persisted;
+    /// a playlist's default repeat mode only applies while it is loaded
+    chosen_repeat_mode: Arc<Mutex<RepeatMode>>,
     shuffle: Arc<Mutex<bool>>,
+    /// Seed track of radio mode, which keeps the queue topped up with similar tracks
+    radio_seed: Arc<Mutex<Option<String>>>,
+    state_file: Option<PathBuf>,
+}
+
+/// Number of played tracks the queue remembers
+pub const QUEUE_HISTORY_LIMIT: usize = 200;
+
+/// A track the queue actually played
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueHistoryEntry {
+    pub track_id: String,
+    pub played_at: DateTime<Utc>,
 }
 
 /// Repeat mode for playback
 #[allow(dead_code)]
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
 pub enum RepeatMode {
+    #[default]
     None,
     One,
     All,
 }
 
+impl RepeatMode {
+    /// String form used in config files, API responses and events
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RepeatMode::None => "none",
+            RepeatMode::One => "one",
+            RepeatMode::All => "all",
+        }
+    }
+}
+
+impl std::fmt::Display for RepeatMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for RepeatMode {
+    type Err = PlaylistError;
+
+    fn from_str(value: &str) -> Result<Self, PlaylistError> {
+        match value.trim().to_lowercase().as_str() {
+            "none" | "off" => Ok(RepeatMode::None),
+            "one" | "track" => Ok(RepeatMode::One),
+            "all" | "queue" => Ok(RepeatMode::All),
+            other => Err(PlaylistError::InvalidRepeatMode(other.to_string())),
+        }
+    }
+}
+
+/// Repeat and shuffle settings persisted between runs
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QueueState {
+    repeat_mode: RepeatMode,
+    shuffle: bool,
+}
+
 #[allow(dead_code)]
 impl PlaybackQueue {
     /// Create a new playback queue
@@ -400,8 +1055,55 @@ impl PlaybackQueue {
         Self {
             tracks: Arc::new(Mutex::new(VecDeque::new())),
             current_index: Arc::new(Mutex::new(None)),
+            history: Arc::new(Mutex::new(VecDeque::new())),
+            history_cursor: Arc::new(Mutex::new(None)),
             repeat_mode: Arc::new(Mutex::new(RepeatMode::None)),
+            chosen_repeat_mode: Arc::new(Mutex::new(RepeatMode::None)),
             shuffle: Arc::new(Mutex::new(false)),
+            radio_seed: Arc::new(Mutex::new(None)),
+            state_file: None,
+        }
+    }
+
+    /// Create a queue whose repeat and shuffle settings are persisted to `state_file`
+    ///
+    /// Settings saved by a previous run take precedence; the given defaults apply when no
+    /// state has been saved yet or the file cannot be read.
+    pub fn with_state_file(
+        state_file: PathBuf,
+        default_repeat_mode: RepeatMode,
+        default_shuffle: bool,
+    ) -> Self {
+        let state = match std::fs::read_to_string(&state_file) {
+            Ok(content) => match serde_json::from_str::<QueueState>(&content) {
+                Ok(state) => state,
+                Err(e) => {
+                    warn!("Ignoring invalid queue state file {:?}: {}", state_file, e);
+                    QueueState {
+                        repeat_mode: default_repeat_mode,
+                        shuffle: default_shuffle,
+                    }
+                }
+            },
+            Err(_) => QueueState {
+                repeat_mode: default_repeat_mode,
+                shuffle: default_shuffle,
+            },
+        };
+
+        debug!(
+            "Queue settings: repeat={}, shuffle={}",
+            state.repeat_mode, state.shuffle
+        );
+
+        let queue = Self::new();
+        *queue.repeat_mode.lock().unwrap() = state.repeat_mode;
+        *queue.chosen_repeat_mode.lock().unwrap() = state.repeat_mode;
+        *queue.shuffle.lock().unwrap() = state.shuffle;
+
+        Self {
+            state_file: Some(state_file),
+            ..queue
         }
     }
 
@@ -411,17 +1113,167 @@ impl PlaybackQueue {
         tracks.extend(track_ids.iter().cloned());
     }
 
+    /// Replace the queue with a playlist's tracks in its play order
+    ///
+    /// Applies the playlist's default repeat mode when it has one, without saving it as
+    /// the queue's setting. Returns the number of queued tracks.
+    pub fn load_playlist(&self, playlist: &Playlist, library: &Library) -> usize {
+        let track_ids = playlist.tracks_in_play_order(library);
+        self.clear();
+        self.add_tracks(&track_ids);
+        if let Some(mode) = playlist.default_repeat {
+            *self.repeat_mode.lock().unwrap() = mode;
+        }
+        track_ids.len()
+    }
+
+    /// Replace the queue with the tracks of an album, playlist or artist and make the
+    /// one at `start` current, so the tracks before it can be stepped back to and the
+    /// ones after it play next. With shuffle on, the other tracks are queued after it
+    /// in random order instead.
+    ///
+    /// Returns the position of the current track.
+    pub fn load_context(&self, mut track_ids: Vec<String>, start: usize) -> usize {
+        let start = if self.is_shuffle_enabled() {
+            let current = track_ids.remove(start);
+            track_ids.shuffle(&mut rand::thread_rng());
+            track_ids.insert(0, current);
+            0
+        } else {
+            start
+        };
+
+        self.clear();
+        self.add_tracks(&track_ids);
+        *self.current_index.lock().unwrap() = Some(start);
+        start
+    }
+
+    /// Append a track to the end of the queue
+    ///
+    /// Returns the position of the appended track.
+    pub fn push_back(&self, track_id: String) -> usize {
+        let mut tracks = self.tracks.lock().unwrap();
+        tracks.push_back(track_id);
+        tracks.len() - 1
+    }
+
+    /// Insert a track right after the current one, so it plays next
+    ///
+    /// Returns the position the track was inserted at.
+    pub fn insert_next(&self, track_id: String) -> usize {
+        let mut tracks = self.tracks.lock().unwrap();
+        let current_index = self.current_index.lock().unwrap();
+
+        let position = current_index.map(|index| index + 1).unwrap_or(0);
+        let position = position.min(tracks.len());
+        tracks.insert(position, track_id);
+        position
+    }
+
     /// Clear the queue
+    ///
+    /// This also turns radio mode off, since the queue it was filling is gone.
     pub fn clear(&self) {
         let mut tracks = self.tracks.lock().unwrap();
         tracks.clear();
 
         let mut current_index = self.current_index.lock().unwrap();
         *current_index = None;
+
+        *self.radio_seed.lock().unwrap() = None;
+    }
+
+    /// Turn radio mode on, seeded with `track_id`
+    pub fn start_radio(&self, track_id: String) {
+        *self.radio_seed.lock().unwrap() = Some(track_id);
+    }
+
+    /// Turn radio mode off. Returns whether it was on.
+    pub fn stop_radio(&self) -> bool {
+        self.radio_seed.lock().unwrap().take().is_some()
+    }
+
+    /// Seed track of radio mode, if it is on
+    pub fn radio_seed(&self) -> Option<String> {
+        self.radio_seed.lock().unwrap().clone()
+    }
+
+    /// Number of queued tracks after the current one
+    pub fn upcoming_len(&self) -> usize {
+        let tracks = self.tracks.lock().unwrap();
+        let current_index = self.current_index.lock().unwrap();
+
+        match *current_index {
+            Some(index) => tracks.len().saturating_sub(index + 1),
+            None => tracks.len(),
+        }
+    }
+
+    /// Forget every played track
+    pub fn clear_history(&self) {
+        self.history.lock().unwrap().clear();
+        *self.history_cursor.lock().unwrap() = None;
+    }
+
+    /// Record that a track started playing
+    ///
+    /// Playing the track reached by stepping back through the history in shuffle mode,
+    /// or the track already at the head of the history, does not add it again, so
+    /// repeated `previous_track` calls keep walking back.
+    pub fn record_played(&self, track_id: &str) {
+        let mut history = self.history.lock().unwrap();
+        let mut cursor = self.history_cursor.lock().unwrap();
+
+        if cursor
+            .and_then(|position| history.get(position))
+            .is_some_and(|entry| entry.track_id == track_id)
+        {
+            return;
+        }
+
+        *cursor = None;
+        if history
+            .front()
+            .is_some_and(|entry| entry.track_id == track_id)
+        {
+            return;
+        }
+
+        history.push_front(QueueHistoryEntry {
+            track_id: track_id.to_string(),
+            played_at: Utc::now(),
+        });
+        history.truncate(QUEUE_HISTORY_LIMIT);
+    }
+
+    /// The most recently played tracks, newest first
+    pub fn history(&self, limit: usize) -> Vec<QueueHistoryEntry> {
+        let history = self.history.lock().unwrap();
+        history.iter().take(limit).cloned().collect()
+    }
+
+    /// Track ids in queue order
+    pub fn track_ids(&self) -> Vec<String> {
+        self.tracks.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// Position of the current track in the queue
+    pub fn current_index(&self) -> Option<usize> {
+        *self.current_index.lock().unwrap()
     }
 
     /// Get next track
+    ///
+    /// After stepping back through the history in shuffle mode this walks forward
+    /// through it again before continuing with the queue.
     pub fn next_track(&self) -> Option<String> {
+        if self.is_shuffle_enabled() {
+            if let Some(track_id) = self.step_history(false) {
+                return Some(track_id);
+            }
+        }
+
         let tracks = self.tracks.lock().unwrap();
         let mut current_index = self.current_index.lock().unwrap();
 
@@ -451,8 +1303,36 @@ impl PlaybackQueue {
         }
     }
 
+    /// The track [`next_track`](Self::next_track) would return, without moving to it
+    pub fn peek_next(&self) -> Option<String> {
+        if self.is_shuffle_enabled() {
+            let history = self.history.lock().unwrap();
+            if let Some(position) = *self.history_cursor.lock().unwrap() {
+                return history
+                    .get(position - 1)
+                    .map(|entry| entry.track_id.clone());
+            }
+        }
+
+        let tracks = self.tracks.lock().unwrap();
+        let next_index = match *self.current_index.lock().unwrap() {
+            Some(index) if index + 1 < tracks.len() => index + 1,
+            Some(_) if self.get_repeat_mode() == RepeatMode::All => 0,
+            Some(_) => return None,
+            None => 0,
+        };
+        tracks.get(next_index).cloned()
+    }
+
     /// Get previous track
+    ///
+    /// In shuffle mode queue order says nothing about what played before, so this
+    /// walks back through the play history instead.
     pub fn previous_track(&self) -> Option<String> {
+        if self.is_shuffle_enabled() {
+            return self.step_history(true);
+        }
+
         let tracks = self.tracks.lock().unwrap();
         let mut current_index = self.current_index.lock().unwrap();
 
@@ -476,6 +1356,29 @@ impl PlaybackQueue {
         }
     }
 
+    /// Move the history cursor one entry back (older) or forward (newer) and return
+    /// the track there, pointing the queue at it when it is still queued.
+    fn step_history(&self, back: bool) -> Option<String> {
+        let tracks = self.tracks.lock().unwrap();
+        let mut current_index = self.current_index.lock().unwrap();
+        let history = self.history.lock().unwrap();
+        let mut cursor = self.history_cursor.lock().unwrap();
+
+        let position = match (*cursor, back) {
+            (None, true) => 1,
+            (None, false) => return None,
+            (Some(position), true) => position + 1,
+            (Some(position), false) => position - 1,
+        };
+        let entry = history.get(position)?;
+
+        *cursor = (position > 0).then_some(position);
+        if let Some(index) = tracks.iter().position(|id| *id == entry.track_id) {
+            *current_index = Some(index);
+        }
+        Some(entry.track_id.clone())
+    }
+
     /// Get current track
     pub fn current_track(&self) -> Option<String> {
         let tracks = self.tracks.lock().unwrap();
@@ -486,8 +1389,9 @@ impl PlaybackQueue {
 
     /// Set repeat mode
     pub fn set_repeat_mode(&self, mode: RepeatMode) {
-        let mut repeat_mode = self.repeat_mode.lock().unwrap();
-        *repeat_mode = mode;
+        *self.repeat_mode.lock().unwrap() = mode;
+        *self.chosen_repeat_mode.lock().unwrap() = mode;
+        self.save_state();
     }
 
     /// Get repeat mode
@@ -497,8 +1401,20 @@ impl PlaybackQueue {
 
     /// Toggle shuffle
     pub fn toggle_shuffle(&self) {
-        let mut shuffle = self.shuffle.lock().unwrap();
-        *shuffle = !*shuffle;
+        {
+            let mut shuffle = self.shuffle.lock().unwrap();
+            *shuffle = !*shuffle;
+        }
+        self.save_state();
+    }
+
+    /// Enable or disable shuffle
+    pub fn set_shuffle(&self, enabled: bool) {
+        {
+            let mut shuffle = self.shuffle.lock().unwrap();
+            *shuffle = enabled;
+        }
+        self.save_state();
     }
 
     /// Check if shuffle is enabled
@@ -515,10 +1431,40 @@ impl PlaybackQueue {
     pub fn is_empty(&self) -> bool {
         self.tracks.lock().unwrap().is_empty()
     }
+
+    fn save_state(&self) {
+        let Some(state_file) = self.state_file.as_ref() else {
+            return;
+        };
+
+        if let Err(e) = self.write_state(state_file) {
+            warn!("Failed to save queue state to {:?}: {}", state_file, e);
+        }
+    }
+
+    fn write_state(&self, state_file: &std::path::Path) -> Result<(), PlaylistError> {
+        let state = QueueState {
+            repeat_mode: *self.chosen_repeat_mode.lock().unwrap(),
+            shuffle: self.is_shuffle_enabled(),
+        };
+
+        if let Some(parent) = state_file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        std::fs::write(state_file, serde_json::to_string_pretty(&state)?)?;
+        Ok(())
+    }
+}
+
+impl Default for PlaybackQueue {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 /// Initialize the playlist system
-pub async fn init() -> Result<()> {
+pub async fn init() -> Result<(), PlaylistError> {
     // Initialize the playlist system with default settings
     info!("Playlist system initialized successfully");
     Ok(())
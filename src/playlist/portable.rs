@@ -0,0 +1,124 @@
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// A file below a music directory, named by the directory's logical name so it can be
+/// found on machines where the directory is mounted elsewhere.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortableLocation {
+    /// Logical name of the music directory, see [`MusicRoot`]
+    pub root: String,
+    /// Path below the music directory, with `/` separators
+    pub path: String,
+}
+
+/// File a playlist entry refers to, kept next to its track id so the entry can be
+/// linked to the right track when ids differ, e.g. in a library scanned on another
+/// machine.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum EntryPath {
+    /// Path on this machine
+    Local(PathBuf),
+    /// Path relative to a music directory, written with `playlist.portable_paths`
+    /// and kept as is while no local music directory resolves it
+    Portable(PortableLocation),
+}
+
+/// A music directory and its logical name
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MusicRoot {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// The configured music directories, mapping file paths to and from
+/// [`PortableLocation`]s.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MusicRoots {
+    roots: Vec<MusicRoot>,
+}
+
+impl MusicRoots {
+    pub fn new(roots: Vec<MusicRoot>) -> Self {
+        Self { roots }
+    }
+
+    /// Location of `path` relative to the innermost music directory containing it
+    pub fn locate(&self, path: &Path) -> Option<PortableLocation> {
+        self.roots
+            .iter()
+            .filter_map(|root| Some((root, path.strip_prefix(&root.path).ok()?)))
+            .min_by_key(|(_, relative)| relative.components().count())
+            .and_then(|(root, relative)| {
+                let parts: Option<Vec<&str>> = relative
+                    .components()
+                    .map(|component| match component {
+                        Component::Normal(part) => part.to_str(),
+                        _ => None,
+                    })
+                    .collect();
+                let parts = parts.filter(|parts| !parts.is_empty())?;
+                Some(PortableLocation {
+                    root: root.name.clone(),
+                    path: parts.join("/"),
+                })
+            })
+    }
+
+    /// Path of `location` on this machine: below the music directory of the same
+    /// name, or the one where the file exists when several or none share the name.
+    /// `None` when no music directory has it.
+    pub fn resolve(&self, location: &PortableLocation) -> Option<PathBuf> {
+        let parts: Vec<&str> = location.path.split('/').collect();
+        if parts
+            .iter()
+            .any(|part| part.is_empty() || *part == "." || *part == "..")
+        {
+            return None;
+        }
+        let join = |root: &MusicRoot| {
+            parts
+                .iter()
+                .fold(root.path.clone(), |path, part| path.join(part))
+        };
+
+        let (named, others): (Vec<&MusicRoot>, Vec<&MusicRoot>) = self
+            .roots
+            .iter()
+            .partition(|root| root.name == location.root);
+        if let [root] = named.as_slice() {
+            return Some(join(root));
+        }
+        named
+            .iter()
+            .chain(others.iter())
+            .map(|root| join(root))
+            .find(|path| path.exists())
+    }
+
+    /// `path` in the form playlists are written in: portable when `portable` is set
+    /// and a music directory contains it, local otherwise.
+    pub fn stored(&self, path: &EntryPath, portable: bool) -> EntryPath {
+        match path {
+            EntryPath::Local(local) if portable => self
+                .locate(local)
+                .map_or_else(|| path.clone(), EntryPath::Portable),
+            EntryPath::Portable(location) if !portable => self
+                .resolve(location)
+                .map_or_else(|| path.clone(), EntryPath::Local),
+            _ => path.clone(),
+        }
+    }
+
+    /// `path` as read from a playlist file, resolved to a local path when possible
+    pub fn loaded(&self, path: EntryPath) -> EntryPath {
+        match path {
+            EntryPath::Portable(location) => match self.resolve(&location) {
+                Some(local) => EntryPath::Local(local),
+                None => EntryPath::Portable(location),
+            },
+            local => local,
+        }
+    }
+}
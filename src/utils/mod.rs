@@ -1,8 +1,56 @@
 #![allow(dead_code)]
 
+use std::cmp::Ordering;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+pub mod schema;
+pub mod serde_rfc3339;
+
+/// Compare strings the way people sort names: ignoring case, with runs of digits
+/// compared by value, so "Mix 2" sorts before "Mix 10".
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut left = a.chars().peekable();
+    let mut right = b.chars().peekable();
+
+    loop {
+        match (left.peek().copied(), right.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(l), Some(r)) if l.is_ascii_digit() && r.is_ascii_digit() => {
+                let l = take_digits(&mut left);
+                let r = take_digits(&mut right);
+                let (l_trimmed, r_trimmed) = (l.trim_start_matches('0'), r.trim_start_matches('0'));
+                let ordering = l_trimmed
+                    .len()
+                    .cmp(&r_trimmed.len())
+                    .then_with(|| l_trimmed.cmp(r_trimmed))
+                    .then_with(|| l.len().cmp(&r.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            (Some(l), Some(r)) => {
+                let ordering = l.to_lowercase().cmp(r.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                left.next();
+                right.next();
+            }
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+    digits
+}
+
 /// Format duration as MM:SS
 pub fn format_duration(duration: Duration) -> String {
     let total_seconds = duration.as_secs();
@@ -18,6 +66,20 @@ pub fn format_duration_seconds(seconds: u64) -> String {
     format!("{:02}:{:02}", minutes, remaining_seconds)
 }
 
+/// Format duration as H:MM:SS, or MM:SS for durations under an hour
+pub fn format_duration_long(duration: Duration) -> String {
+    let total_seconds = duration.as_secs();
+    let hours = total_seconds / 3600;
+    let minutes = (total_seconds % 3600) / 60;
+    let seconds = total_seconds % 60;
+
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{:02}:{:02}", minutes, seconds)
+    }
+}
+
 /// Format file size in human readable format
 pub fn format_file_size(bytes: u64) -> String {
     const KB: u64 = 1024;
@@ -39,16 +101,30 @@ pub fn get_file_extension(path: &Path) -> Option<String> {
         .map(|s| s.to_lowercase())
 }
 
-/// Check if a file is an audio file
+/// Default `library.supported_extensions`. Files are listed when their tags or
+/// format can be read; playing them needs a decoder for their codec.
+pub const DEFAULT_AUDIO_EXTENSIONS: &[&str] = &[
+    "mp3", "flac", "ogg", "opus", "wav", "aiff", "aif", "m4a", "aac", "wv", "ape",
+];
+
+/// Check if a file's extension is one of `extensions`, ignoring case and a leading
+/// dot in the list
+pub fn is_supported_audio_format(file_path: &Path, extensions: &[impl AsRef<str>]) -> bool {
+    let Some(extension) = file_path.extension().and_then(|ext| ext.to_str()) else {
+        return false;
+    };
+    extensions.iter().any(|supported| {
+        let supported = supported.as_ref();
+        supported
+            .strip_prefix('.')
+            .unwrap_or(supported)
+            .eq_ignore_ascii_case(extension)
+    })
+}
+
+/// Check if a file has one of the default audio extensions
 pub fn is_audio_file(path: &Path) -> bool {
-    if let Some(ext) = get_file_extension(path) {
-        matches!(
-            ext.as_str(),
-            "mp3" | "flac" | "ogg" | "wav" | "m4a" | "aac" | "opus"
-        )
-    } else {
-        false
-    }
+    is_supported_audio_format(path, DEFAULT_AUDIO_EXTENSIONS)
 }
 
 /// Get relative path from base directory
@@ -86,25 +162,59 @@ pub fn sanitize_filename(filename: &str) -> String {
 }
 
 /// Parse time string in format MM:SS or HH:MM:SS
+///
+/// Seconds may carry a fraction ("3:45.5", as found in cue sheets and LRC files),
+/// which is kept with millisecond precision. Leading zeros are optional.
 pub fn parse_time_string(time_str: &str) -> Option<Duration> {
-    let parts: Vec<&str> = time_str.split(':').collect();
-
-    match parts.len() {
-        2 => {
-            // MM:SS format
-            let minutes: u64 = parts[0].parse().ok()?;
-            let seconds: u64 = parts[1].parse().ok()?;
-            Some(Duration::from_secs(minutes * 60 + seconds))
+    let parts: Vec<&str> = time_str.trim().split(':').collect();
+
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [minutes, seconds] => (0, parse_time_component(minutes)?, *seconds),
+        [hours, minutes, seconds] => {
+            let minutes = parse_time_component(minutes)?;
+            if minutes >= 60 {
+                return None;
+            }
+            (parse_time_component(hours)?, minutes, *seconds)
         }
-        3 => {
-            // HH:MM:SS format
-            let hours: u64 = parts[0].parse().ok()?;
-            let minutes: u64 = parts[1].parse().ok()?;
-            let seconds: u64 = parts[2].parse().ok()?;
-            Some(Duration::from_secs(hours * 3600 + minutes * 60 + seconds))
+        _ => return None,
+    };
+
+    let (whole_seconds, fraction) = match seconds.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (seconds, None),
+    };
+
+    let whole_seconds = parse_time_component(whole_seconds)?;
+    if whole_seconds >= 60 {
+        return None;
+    }
+
+    let millis = match fraction {
+        Some(fraction) => {
+            if fraction.is_empty() || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            // Keep millisecond precision: "5" -> 500, "25" -> 250, "1234" -> 123
+            let digits: String = fraction.chars().chain("00".chars()).take(3).collect();
+            digits.parse::<u64>().ok()?
         }
-        _ => None,
+        None => 0,
+    };
+
+    let total_seconds = hours
+        .checked_mul(3600)?
+        .checked_add(minutes.checked_mul(60)?)?
+        .checked_add(whole_seconds)?;
+
+    Some(Duration::from_secs(total_seconds) + Duration::from_millis(millis))
+}
+
+fn parse_time_component(value: &str) -> Option<u64> {
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
     }
+    value.parse().ok()
 }
 
 /// Get human readable time ago string
@@ -152,7 +262,7 @@ pub fn capitalize_first(s: &str) -> String {
 /// Convert string to title case
 pub fn to_title_case(s: &str) -> String {
     s.split_whitespace()
-        .map(|word| capitalize_first(word))
+        .map(capitalize_first)
         .collect::<Vec<_>>()
         .join(" ")
 }
@@ -174,6 +284,17 @@ mod tests {
         assert_eq!(format_duration_seconds(3661), "61:01");
     }
 
+    #[test]
+    fn test_format_duration_long() {
+        assert_eq!(format_duration_long(Duration::ZERO), "00:00");
+        assert_eq!(format_duration_long(Duration::from_secs(65)), "01:05");
+        assert_eq!(format_duration_long(Duration::from_secs(3599)), "59:59");
+        assert_eq!(format_duration_long(Duration::from_secs(3600)), "1:00:00");
+        assert_eq!(format_duration_long(Duration::from_secs(3661)), "1:01:01");
+        assert_eq!(format_duration_long(Duration::from_secs(36000)), "10:00:00");
+        assert_eq!(format_duration_long(Duration::from_millis(59_999)), "00:59");
+    }
+
     #[test]
     fn test_format_file_size() {
         assert_eq!(format_file_size(1024), "1.0 KB");
@@ -198,6 +319,57 @@ mod tests {
         assert_eq!(parse_time_string("invalid"), None);
     }
 
+    #[test]
+    fn test_parse_time_string_boundaries() {
+        assert_eq!(parse_time_string("0:00"), Some(Duration::ZERO));
+        assert_eq!(parse_time_string("00:00:00"), Some(Duration::ZERO));
+        assert_eq!(parse_time_string("59:59"), Some(Duration::from_secs(3599)));
+        assert_eq!(
+            parse_time_string("1:00:00"),
+            Some(Duration::from_secs(3600))
+        );
+        assert_eq!(
+            parse_time_string("01:01:01"),
+            Some(Duration::from_secs(3661))
+        );
+        assert_eq!(parse_time_string("61:01"), Some(Duration::from_secs(3661)));
+        assert_eq!(parse_time_string(" 3:05 "), Some(Duration::from_secs(185)));
+    }
+
+    #[test]
+    fn test_parse_time_string_fractional_seconds() {
+        assert_eq!(
+            parse_time_string("3:45.5"),
+            Some(Duration::from_millis(225_500))
+        );
+        assert_eq!(
+            parse_time_string("03:45.50"),
+            Some(Duration::from_millis(225_500))
+        );
+        assert_eq!(
+            parse_time_string("0:00.001"),
+            Some(Duration::from_millis(1))
+        );
+        assert_eq!(
+            parse_time_string("0:01.2349"),
+            Some(Duration::from_millis(1_234))
+        );
+        assert_eq!(
+            parse_time_string("1:02:03.25"),
+            Some(Duration::from_millis(3_723_250))
+        );
+    }
+
+    #[test]
+    fn test_parse_time_string_rejects_malformed_input() {
+        for input in [
+            "", "5", ":30", "1:", "1:60", "1:60:00", "1:-5", "1:2:3:4", "3:45.", "3:45.x", "3:4 5",
+            "+1:00", "1.5:00",
+        ] {
+            assert_eq!(parse_time_string(input), None, "{:?}", input);
+        }
+    }
+
     #[test]
     fn test_truncate_string() {
         assert_eq!(truncate_string("Hello World", 8), "Hello...");
@@ -0,0 +1,93 @@
+//! Versions of the files the backend keeps: the library cache, playlists and the
+//! album override store.
+//!
+//! Each file records the version of its format in a top-level `version` field, files
+//! written before it was recorded being version 0. A [`Schema`] holds the migrations
+//! upgrading a document one version at a time; [`Schema::upgrade`] applies the ones a
+//! loaded document needs before it is read into the current structs, so files are
+//! always saved in the newest version. Files of a version newer than the build knows
+//! are refused, and left alone, rather than read partially and overwritten with what
+//! was understood of them.
+
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+
+/// Upgrade a document from the version before the migration's to its own
+pub type Migration = fn(&mut Value);
+
+/// Why a document could not be upgraded
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SchemaError {
+    /// The document was written by a newer version of Hexendrum
+    #[error(
+        "The {name} {} has format version {found}, but this version of Hexendrum reads up to version {supported}. Upgrade Hexendrum to use it; the file is left unchanged.",
+        path.display()
+    )]
+    Newer {
+        name: &'static str,
+        path: PathBuf,
+        found: u32,
+        supported: u32,
+    },
+    #[error("The {name} {} has an invalid format version: {version}", path.display())]
+    InvalidVersion {
+        name: &'static str,
+        path: PathBuf,
+        version: String,
+    },
+}
+
+/// The versions of a file format
+pub struct Schema {
+    /// What the documents are, e.g. "library cache", for error messages
+    pub name: &'static str,
+    /// Migration `i` upgrades a document of version `i` to version `i + 1`
+    pub migrations: &'static [Migration],
+}
+
+impl Schema {
+    /// The newest version, the one documents are saved in
+    pub const fn current(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+
+    /// Version `document` read from `path` was written in, 0 when it records none
+    pub fn version(&self, document: &Value, path: &Path) -> Result<u32, SchemaError> {
+        let Some(version) = document.get("version") else {
+            return Ok(0);
+        };
+        version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| SchemaError::InvalidVersion {
+                name: self.name,
+                path: path.to_path_buf(),
+                version: version.to_string(),
+            })
+    }
+
+    /// Upgrade `document` read from `path` to the current version, returning the
+    /// version it was written in. Object documents get their `version` updated.
+    pub fn upgrade(&self, document: &mut Value, path: &Path) -> Result<u32, SchemaError> {
+        let found = self.version(document, path)?;
+        if found > self.current() {
+            return Err(SchemaError::Newer {
+                name: self.name,
+                path: path.to_path_buf(),
+                found,
+                supported: self.current(),
+            });
+        }
+
+        for migration in &self.migrations[found as usize..] {
+            migration(document);
+        }
+        if let Some(object) = document.as_object_mut() {
+            object.insert("version".into(), self.current().into());
+        }
+        Ok(found)
+    }
+}
+
+/// Migration to a version that only added fields reading as empty when absent
+pub fn optional_fields(_document: &mut Value) {}
@@ -0,0 +1,95 @@
+//! Serialize `DateTime<Utc>` fields as RFC 3339 in UTC with a `Z` suffix, e.g.
+//! `2024-01-15T10:30:00Z`, keeping fractional seconds only when there are any.
+//!
+//! Use with `#[serde(with = "crate::utils::serde_rfc3339")]`, or the [`option`]
+//! module for `Option<DateTime<Utc>>`. Deserialization also accepts what older
+//! versions wrote: any UTC offset, a space instead of the `T`, timestamps without an
+//! offset (read as UTC) and Unix timestamps in seconds.
+
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, TimeZone, Utc};
+use serde::de::{self, Visitor};
+use serde::{Deserializer, Serializer};
+use std::fmt;
+
+/// Format `timestamp` the way it is serialized
+pub fn format(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+/// Parse an RFC 3339 timestamp or one of the older formats accepted when
+/// deserializing
+pub fn parse(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(timestamp) = DateTime::parse_from_rfc3339(value) {
+        return Some(timestamp.with_timezone(&Utc));
+    }
+    if let Ok(timestamp) = value.parse::<DateTime<Utc>>() {
+        return Some(timestamp);
+    }
+    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .map(|naive| naive.and_utc())
+}
+
+pub fn serialize<S: Serializer>(
+    timestamp: &DateTime<Utc>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format(timestamp))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<DateTime<Utc>, D::Error> {
+    deserializer.deserialize_any(TimestampVisitor)
+}
+
+/// The same format for `Option<DateTime<Utc>>`, with `None` as `null`
+pub mod option {
+    use super::*;
+    use serde::Deserialize;
+
+    pub fn serialize<S: Serializer>(
+        timestamp: &Option<DateTime<Utc>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match timestamp {
+            Some(timestamp) => super::serialize(timestamp, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<DateTime<Utc>>, D::Error> {
+        #[derive(Deserialize)]
+        struct Timestamp(#[serde(deserialize_with = "super::deserialize")] DateTime<Utc>);
+
+        Ok(Option::<Timestamp>::deserialize(deserializer)?.map(|Timestamp(timestamp)| timestamp))
+    }
+}
+
+struct TimestampVisitor;
+
+impl Visitor<'_> for TimestampVisitor {
+    type Value = DateTime<Utc>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("an RFC 3339 timestamp or Unix time in seconds")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        parse(value).ok_or_else(|| E::custom(format!("invalid timestamp '{}'", value)))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        Utc.timestamp_opt(value, 0)
+            .single()
+            .ok_or_else(|| E::custom(format!("Unix time {} is out of range", value)))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        let value = i64::try_from(value)
+            .map_err(|_| E::custom(format!("Unix time {} is out of range", value)))?;
+        self.visit_i64(value)
+    }
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use common::TestTrack;
+use hexendrum::config::Paths;
+use hexendrum::library::{
+    album_primary_artist, artist_credits, AlbumService, ArtistCredit, Library, Track,
+    DEFAULT_VARIOUS_ARTISTS_THRESHOLD, VARIOUS_ARTISTS,
+};
+use hexendrum::TrackMetadata;
+
+fn track(id: &str, artist: &str, album_artist: Option<&str>) -> Track {
+    TestTrack::new(id)
+        .metadata(TrackMetadata {
+            title: Some(format!("Song {}", id)),
+            artist: Some(artist.into()),
+            album: Some("Club Night".into()),
+            album_artist: album_artist.map(str::to_string),
+            ..Default::default()
+        })
+        .build()
+}
+
+fn credit(artist: &str, track_count: usize) -> ArtistCredit {
+    ArtistCredit {
+        artist: artist.into(),
+        track_count,
+    }
+}
+
+/// Tracks credited to "DJ Shadow", "DJ Shadow feat. B" and "DJ Shadow feat. A" twice
+/// each, so every artist ties and only the alphabetical tie-break decides
+fn tied_tracks() -> Vec<Track> {
+    ["DJ Shadow", "DJ Shadow feat. B", "DJ Shadow feat. A"]
+        .iter()
+        .enumerate()
+        .flat_map(|(index, artist)| {
+            [
+                track(&format!("{}a", index), artist, None),
+                track(&format!("{}b", index), artist, None),
+            ]
+        })
+        .collect()
+}
+
+#[test]
+fn credits_do_not_depend_on_track_order() {
+    let tracks = tied_tracks();
+    let expected = vec![
+        credit("DJ Shadow", 2),
+        credit("DJ Shadow feat. A", 2),
+        credit("DJ Shadow feat. B", 2),
+    ];
+
+    // Every rotation of both insertion orders
+    let mut orders: Vec<Vec<Track>> = Vec::new();
+    for shift in 0..tracks.len() {
+        let mut rotated = tracks.clone();
+        rotated.rotate_left(shift);
+        orders.push(rotated.iter().rev().cloned().collect());
+        orders.push(rotated);
+    }
+    for order in orders {
+        let credits = artist_credits(&order);
+        assert_eq!(credits, expected);
+        assert_eq!(
+            album_primary_artist(&credits, false, DEFAULT_VARIOUS_ARTISTS_THRESHOLD).as_deref(),
+            Some("DJ Shadow")
+        );
+    }
+
+    let mut tracks = tracks;
+    tracks.push(track("extra", "DJ Shadow feat. B", None));
+    tracks.push(track("untagged", "  ", None));
+    assert_eq!(
+        artist_credits(&tracks),
+        vec![
+            credit("DJ Shadow feat. B", 3),
+            credit("DJ Shadow", 2),
+            credit("DJ Shadow feat. A", 2),
+        ],
+        "the most credited artist comes first and blank artists are skipped"
+    );
+}
+
+#[test]
+fn many_artists_without_an_album_artist_are_various_artists() {
+    let credits: Vec<ArtistCredit> = ["A", "B", "C", "D", "E"]
+        .iter()
+        .map(|artist| credit(artist, 1))
+        .collect();
+
+    assert_eq!(
+        album_primary_artist(&credits, false, 4).as_deref(),
+        Some(VARIOUS_ARTISTS)
+    );
+    assert_eq!(
+        album_primary_artist(&credits[..4], false, 4).as_deref(),
+        Some("A"),
+        "the threshold itself is not exceeded"
+    );
+    assert_eq!(
+        album_primary_artist(&credits, true, 4).as_deref(),
+        Some("A"),
+        "an album artist tag marks a single-artist album"
+    );
+    assert_eq!(
+        album_primary_artist(&credits, false, 0).as_deref(),
+        Some("A"),
+        "a threshold of 0 disables Various Artists"
+    );
+    assert_eq!(album_primary_artist(&[], false, 4), None);
+}
+
+#[tokio::test]
+async fn albums_list_credits_and_honour_the_threshold() {
+    let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+    let paths = Paths::portable(workspace.path().join("data"));
+    let library = Library::with_paths(&paths);
+    for (index, guest) in ["A", "B", "C", "D", "E"].iter().enumerate() {
+        library.add_track(track(
+            &index.to_string(),
+            &format!("DJ Shadow feat. {}", guest),
+            None,
+        ));
+    }
+    library.add_track(track("solo", "DJ Shadow feat. C", None));
+
+    let albums = AlbumService::with_paths(&paths, None)
+        .search_albums(&library, None)
+        .await;
+    assert_eq!(albums.len(), 1);
+    assert_eq!(albums[0].primary_artist.as_deref(), Some(VARIOUS_ARTISTS));
+    assert_eq!(albums[0].artist_credits[0], credit("DJ Shadow feat. C", 2));
+    assert_eq!(albums[0].artist_credits.len(), 5);
+
+    let albums = AlbumService::with_paths(&paths, None)
+        .with_various_artists_threshold(5)
+        .search_albums(&library, None)
+        .await;
+    assert_eq!(
+        albums[0].primary_artist.as_deref(),
+        Some("DJ Shadow feat. C")
+    );
+}
@@ -0,0 +1,206 @@
+mod common;
+
+use common::TestTrack;
+use hexendrum::config::Paths;
+use hexendrum::library::{
+    album_identifier, AlbumDisambiguation, AlbumService, AlbumSummary, Library, ManualAlbumUpdate,
+};
+use hexendrum::TrackMetadata;
+use tempfile::TempDir;
+
+struct EditionTestEnv {
+    _workspace: TempDir,
+    paths: Paths,
+    library: Library,
+}
+
+impl EditionTestEnv {
+    fn new() -> Self {
+        let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+        let paths = Paths::portable(workspace.path().join("data"));
+        Self {
+            library: Library::with_paths(&paths),
+            _workspace: workspace,
+            paths,
+        }
+    }
+
+    fn service(&self, disambiguation: bool) -> AlbumService {
+        AlbumService::with_paths(&self.paths, None).with_album_disambiguation(disambiguation)
+    }
+
+    /// Add `count` tracks numbered from 1 with titles "Song 1", "Song 2", ...
+    fn add_release(&self, year: Option<i32>, total: Option<u32>, count: u32) {
+        for number in 1..=count {
+            self.add_track(&format!("Song {}", number), Some(number), total, year);
+        }
+    }
+
+    fn add_track(&self, title: &str, number: Option<u32>, total: Option<u32>, year: Option<i32>) {
+        let id = format!("{}-{:?}-{:?}-{:?}", title, number, total, year);
+        let track = TestTrack::new(id)
+            .metadata(TrackMetadata {
+                title: Some(title.into()),
+                artist: Some("Artist".into()),
+                album: Some("Greatest Hits".into()),
+                track_number: number,
+                track_total: total,
+                year,
+                ..Default::default()
+            })
+            .build();
+        self.library.add_track(track);
+    }
+}
+
+fn base_id() -> String {
+    album_identifier(Some("Artist"), "Greatest Hits")
+}
+
+async fn albums(service: &AlbumService, library: &Library) -> Vec<(String, Option<String>, usize)> {
+    let mut albums: Vec<_> = service
+        .search_albums(library, None)
+        .await
+        .into_iter()
+        .map(|album: AlbumSummary| (album.id, album.edition, album.track_count))
+        .collect();
+    albums.sort();
+    albums
+}
+
+#[tokio::test]
+async fn reissues_are_split_by_year() {
+    let env = EditionTestEnv::new();
+    env.add_release(Some(1998), Some(12), 12);
+    env.add_release(Some(2010), Some(12), 12);
+
+    assert_eq!(
+        albums(&env.service(true), &env.library).await,
+        vec![
+            (
+                format!("{}-1998-12", base_id()),
+                Some("1998, 12 tracks".into()),
+                12
+            ),
+            (
+                format!("{}-2010-12", base_id()),
+                Some("2010, 12 tracks".into()),
+                12
+            ),
+        ]
+    );
+    assert_eq!(
+        albums(&env.service(false), &env.library).await,
+        vec![(base_id(), None, 24)],
+        "disambiguation is off by default"
+    );
+}
+
+#[tokio::test]
+async fn deluxe_editions_are_split_by_track_total() {
+    let env = EditionTestEnv::new();
+    env.add_release(Some(2005), Some(10), 10);
+    env.add_release(Some(2005), Some(16), 16);
+    // Untagged tracks join the edition they agree with
+    env.add_track("Bonus Demo", None, Some(16), None);
+
+    let service = env.service(true);
+    assert_eq!(
+        albums(&service, &env.library).await,
+        vec![
+            (
+                format!("{}-2005-10", base_id()),
+                Some("2005, 10 tracks".into()),
+                10
+            ),
+            (
+                format!("{}-2005-16", base_id()),
+                Some("2005, 16 tracks".into()),
+                17
+            ),
+        ]
+    );
+
+    let deluxe = service.album_tracks(&env.library, &format!("{}-2005-16", base_id()));
+    assert_eq!(deluxe.len(), 17);
+    assert!(deluxe
+        .iter()
+        .all(|track| track.metadata.track_total == Some(16)));
+    assert!(service.album_tracks(&env.library, &base_id()).is_empty());
+}
+
+#[tokio::test]
+async fn tracks_without_a_year_do_not_split_an_album() {
+    let env = EditionTestEnv::new();
+    env.add_release(Some(1998), Some(12), 10);
+    env.add_track("Song 11", Some(11), Some(12), None);
+    env.add_track("Song 12", Some(12), None, None);
+
+    assert_eq!(
+        albums(&env.service(true), &env.library).await,
+        vec![(base_id(), None, 12)]
+    );
+}
+
+#[tokio::test]
+async fn compilations_tagged_with_original_years_stay_together() {
+    let env = EditionTestEnv::new();
+    for (number, year) in [(1, 1975), (2, 1977), (3, 1980), (4, 1984)] {
+        env.add_track(
+            &format!("Hit {}", number),
+            Some(number),
+            Some(4),
+            Some(year),
+        );
+    }
+
+    assert_eq!(
+        albums(&env.service(true), &env.library).await,
+        vec![(base_id(), None, 4)]
+    );
+}
+
+#[tokio::test]
+async fn manual_overrides_force_merging_or_splitting() {
+    let env = EditionTestEnv::new();
+    env.add_release(Some(1998), Some(12), 12);
+    env.add_release(Some(2010), Some(12), 12);
+    let service = env.service(true);
+
+    let set = |disambiguation| ManualAlbumUpdate {
+        title: None,
+        primary_artist: None,
+        search_album: None,
+        search_artist: None,
+        refresh_artwork: false,
+        disambiguation: Some(disambiguation),
+        musicbrainz_release_id: None,
+    };
+
+    service
+        .set_manual_override(&base_id(), set(AlbumDisambiguation::Merge))
+        .await
+        .expect("override should be stored");
+    assert_eq!(
+        albums(&service, &env.library).await,
+        vec![(base_id(), None, 24)]
+    );
+
+    let disabled = env.service(false);
+    disabled
+        .set_manual_override(&base_id(), set(AlbumDisambiguation::Split))
+        .await
+        .expect("override should be stored");
+    assert_eq!(
+        albums(&disabled, &env.library).await.len(),
+        2,
+        "splitting can be forced with disambiguation disabled"
+    );
+    assert_eq!(
+        env.service(false)
+            .get_override(&base_id())
+            .map(|record| record.disambiguation),
+        Some(AlbumDisambiguation::Split),
+        "the override should persist"
+    );
+}
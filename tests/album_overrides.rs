@@ -1,7 +1,17 @@
+mod common;
+
+use common::write_silent_wav;
 use hexendrum::library::{
-    album_identifier, AlbumExportFormat, AlbumService, Library, ManualAlbumUpdate,
+    album_artwork_url, album_identifier, artist_identifier, sidecar_path, write_track_tags,
+    AlbumExportFormat, AlbumSearch, AlbumService, AlbumSort, ArtworkDedupReport, Library,
+    ManualAlbumUpdate, TrackMetadata, TrackTagUpdate,
 };
+use hexendrum::{EventBus, EventMessage, EventPayload};
+use serial_test::serial;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use tempfile::TempDir;
+use tokio::sync::broadcast::Receiver;
 
 struct AlbumTestEnv {
     _workspace: TempDir,
@@ -44,9 +54,36 @@ impl AlbumTestEnv {
         self.music_dir.clone()
     }
 
+    /// An untagged file, given an artist and album by its sidecar
     fn create_audio_file<P: AsRef<std::path::Path>>(&self, name: P) -> std::path::PathBuf {
         let path = self.music_dir.join(name);
         std::fs::write(&path, b"fake audio data").expect("failed to write audio file");
+        std::fs::write(
+            sidecar_path(&path),
+            r#"{"artist": "Sidecar Artist", "album": "Sidecar Album"}"#,
+        )
+        .expect("failed to write sidecar");
+        path
+    }
+
+    fn create_tagged_track<P: AsRef<std::path::Path>>(
+        &self,
+        name: P,
+        artist: &str,
+        album: &str,
+    ) -> std::path::PathBuf {
+        let path = self.music_dir.join(name);
+        write_silent_wav(&path);
+        write_track_tags(
+            &path,
+            &TrackTagUpdate {
+                title: Some("Track".into()),
+                artist: Some(artist.into()),
+                album: Some(album.into()),
+                ..Default::default()
+            },
+        )
+        .expect("failed to tag audio file");
         path
     }
 }
@@ -74,6 +111,7 @@ impl Drop for AlbumTestEnv {
 }
 
 #[tokio::test]
+#[serial]
 async fn manual_override_can_be_set_and_exported() {
     let _env = AlbumTestEnv::new();
     let service = AlbumService::new(None);
@@ -84,6 +122,8 @@ async fn manual_override_can_be_set_and_exported() {
         search_album: Some("Lookup Album".into()),
         search_artist: Some("Lookup Artist".into()),
         refresh_artwork: false,
+        disambiguation: None,
+        musicbrainz_release_id: None,
     };
 
     let record = service
@@ -133,6 +173,7 @@ async fn manual_override_can_be_set_and_exported() {
 }
 
 #[tokio::test]
+#[serial]
 async fn manual_override_updates_album_search_results() {
     let env = AlbumTestEnv::new();
     let service = AlbumService::new(None);
@@ -165,6 +206,8 @@ async fn manual_override_updates_album_search_results() {
                 search_album: None,
                 search_artist: None,
                 refresh_artwork: false,
+                disambiguation: None,
+                musicbrainz_release_id: None,
             },
         )
         .await
@@ -187,3 +230,487 @@ async fn manual_override_updates_album_search_results() {
         "album summary should be marked as manually overridden"
     );
 }
+
+#[tokio::test]
+#[serial]
+async fn manual_override_updates_search_results_of_tagged_albums() {
+    let env = AlbumTestEnv::new();
+    let service = AlbumService::new(None);
+    let library = Library::new();
+
+    env.create_tagged_track("track.wav", "Original Artist", "Original Album");
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+    let album_id = album_identifier(Some("Original Artist"), "Original Album");
+
+    service
+        .set_manual_override(
+            &album_id,
+            ManualAlbumUpdate {
+                title: Some("Renamed Album".into()),
+                primary_artist: Some("Manual Artist".into()),
+                search_album: None,
+                search_artist: None,
+                refresh_artwork: false,
+                disambiguation: None,
+                musicbrainz_release_id: None,
+            },
+        )
+        .await
+        .expect("manual override should be stored");
+
+    let summary = service
+        .search_albums(&library, None)
+        .await
+        .into_iter()
+        .find(|album| album.id == album_id)
+        .expect("album summary should exist");
+    assert_eq!(summary.title, "Renamed Album");
+    assert_eq!(summary.primary_artist.as_deref(), Some("Manual Artist"));
+    assert!(summary.is_manual);
+}
+
+#[tokio::test]
+#[serial]
+async fn edit_album_rewrites_tags_and_migrates_override() {
+    let env = AlbumTestEnv::new();
+    let service = AlbumService::new(None);
+    let library = Library::new();
+
+    let first = env.create_tagged_track("01.wav", "Beatles, The", "Abbey Road");
+    let second = env.create_tagged_track("02.wav", "Beatles, The", "Abbey Road");
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+
+    let old_id = album_identifier(Some("Beatles, The"), "Abbey Road");
+    service
+        .set_manual_override(
+            &old_id,
+            ManualAlbumUpdate {
+                title: Some("Abbey Road (Remaster)".into()),
+                primary_artist: None,
+                search_album: None,
+                search_artist: None,
+                refresh_artwork: false,
+                disambiguation: None,
+                musicbrainz_release_id: None,
+            },
+        )
+        .await
+        .expect("manual override should be stored");
+
+    let report = service
+        .edit_album(
+            &library,
+            &old_id,
+            TrackTagUpdate {
+                artist: Some("The Beatles".into()),
+                genre: Some("Rock".into()),
+                year: Some(1969),
+                ..Default::default()
+            },
+        )
+        .expect("edit should succeed");
+
+    let new_id = album_identifier(Some("The Beatles"), "Abbey Road");
+    assert_eq!(report.previous_album_id, old_id);
+    assert_eq!(
+        report.album_id, new_id,
+        "album id should follow the new artist"
+    );
+    assert_eq!(report.succeeded(), 2);
+    assert_eq!(report.failed(), 0);
+
+    for path in [&first, &second] {
+        let metadata = TrackMetadata::from_file(path).expect("edited file should be readable");
+        assert_eq!(metadata.artist.as_deref(), Some("The Beatles"));
+        assert_eq!(metadata.genre.as_deref(), Some("Rock"));
+        assert_eq!(metadata.year, Some(1969));
+        assert_eq!(
+            metadata.title.as_deref(),
+            Some("Track"),
+            "title is untouched"
+        );
+    }
+
+    assert_eq!(library.get_tracks_by_album_id(&new_id).len(), 2);
+    assert!(library.get_tracks_by_album_id(&old_id).is_empty());
+
+    assert!(
+        service.get_override(&old_id).is_none(),
+        "override should no longer live under the old id"
+    );
+    let migrated = service
+        .get_override(&new_id)
+        .expect("override should move to the new id");
+    assert_eq!(migrated.title.as_deref(), Some("Abbey Road (Remaster)"));
+}
+
+#[tokio::test]
+#[serial]
+async fn edit_album_merges_the_override_into_an_existing_one() {
+    let env = AlbumTestEnv::new();
+    let service = AlbumService::new(None);
+    let library = Library::new();
+
+    env.create_tagged_track("01.wav", "Beatles, The", "Abbey Road");
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+
+    let old_id = album_identifier(Some("Beatles, The"), "Abbey Road");
+    let new_id = album_identifier(Some("The Beatles"), "Abbey Road");
+    let update = |title: &str, search_album: Option<&str>| ManualAlbumUpdate {
+        title: Some(title.into()),
+        primary_artist: None,
+        search_album: search_album.map(Into::into),
+        search_artist: None,
+        refresh_artwork: false,
+        disambiguation: None,
+        musicbrainz_release_id: None,
+    };
+    service
+        .set_manual_override(&old_id, update("Abbey Road (Remaster)", Some("Abbey Road")))
+        .await
+        .expect("manual override should be stored");
+    service
+        .set_manual_override(&new_id, update("Abbey Road (1969)", None))
+        .await
+        .expect("manual override should be stored");
+
+    let report = service
+        .edit_album(
+            &library,
+            &old_id,
+            TrackTagUpdate {
+                artist: Some("The Beatles".into()),
+                ..Default::default()
+            },
+        )
+        .expect("edit should succeed");
+    assert_eq!(report.album_id, new_id);
+    assert_eq!(report.override_conflicts, ["title"]);
+
+    assert!(service.get_override(&old_id).is_none());
+    let merged = service.get_override(&new_id).unwrap();
+    assert_eq!(merged.title.as_deref(), Some("Abbey Road (1969)"));
+    assert_eq!(merged.search_album.as_deref(), Some("Abbey Road"));
+}
+
+#[tokio::test]
+#[serial]
+async fn edit_album_reports_per_file_failures() {
+    let env = AlbumTestEnv::new();
+    let service = AlbumService::new(None);
+    let library = Library::new();
+
+    env.create_tagged_track("01.wav", "Artist", "Album");
+    let missing = env.create_tagged_track("02.wav", "Artist", "Album");
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+
+    std::fs::remove_file(&missing).expect("failed to remove track");
+
+    let album_id = album_identifier(Some("Artist"), "Album");
+    let report = service
+        .edit_album(
+            &library,
+            &album_id,
+            TrackTagUpdate {
+                genre: Some("Jazz".into()),
+                ..Default::default()
+            },
+        )
+        .expect("edit should be best-effort");
+
+    assert_eq!(report.succeeded(), 1);
+    assert_eq!(report.failed(), 1);
+    let failure = report
+        .files
+        .iter()
+        .find(|file| file.error.is_some())
+        .expect("missing file should be reported");
+    assert_eq!(failure.path, missing);
+}
+
+#[tokio::test]
+#[serial]
+async fn artist_image_is_resolved_from_album_folder_for_primary_artist() {
+    let env = AlbumTestEnv::new();
+    let album_dir = env.music_dir().join("Heroes");
+    std::fs::create_dir(&album_dir).unwrap();
+    env.create_tagged_track("Heroes/01.wav", "David Bowie", "Heroes");
+    std::fs::write(album_dir.join("artist.jpg"), [0xFF, 0xD8, 0xFF, 0xE0]).unwrap();
+
+    let library = Library::new();
+    library.scan_directories(&[env.music_dir()]).unwrap();
+    let service = AlbumService::new(None);
+
+    let path = service
+        .resolve_artist_image(&library, "David Bowie feat. Queen")
+        .await
+        .expect("featured credit should resolve to the primary artist image");
+
+    assert_eq!(
+        path.file_name().unwrap().to_string_lossy(),
+        format!("{}.jpg", artist_identifier("david bowie").unwrap())
+    );
+    assert!(path.starts_with(service.cache_directory().with_file_name("artist_art")));
+    assert_eq!(std::fs::read(&path).unwrap(), [0xFF, 0xD8, 0xFF, 0xE0]);
+}
+
+#[tokio::test]
+#[serial]
+async fn artist_image_misses_are_cached() {
+    let env = AlbumTestEnv::new();
+    let album_dir = env.music_dir().join("Album");
+    std::fs::create_dir(&album_dir).unwrap();
+    env.create_tagged_track("Album/01.wav", "Unknown Artist", "Album");
+
+    let library = Library::new();
+    library.scan_directories(&[env.music_dir()]).unwrap();
+    let service = AlbumService::new(None);
+
+    assert!(service
+        .resolve_artist_image(&library, "Unknown Artist")
+        .await
+        .is_none());
+
+    // The miss is remembered, so an image added later is not picked up until it expires.
+    std::fs::write(album_dir.join("artist.jpg"), [0xFF, 0xD8]).unwrap();
+    assert!(service
+        .resolve_artist_image(&library, "Unknown Artist")
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+#[serial]
+async fn album_listing_reads_artwork_cache_from_memory() {
+    let env = AlbumTestEnv::new();
+    let albums: Vec<String> = (1..=12).map(|index| format!("Album {}", index)).collect();
+    for (index, album) in albums.iter().enumerate() {
+        env.create_tagged_track(format!("{:02}.wav", index), "Artist", album);
+    }
+
+    let library = Library::new();
+    library.scan_directories(&[env.music_dir()]).unwrap();
+
+    let cache_dir = AlbumService::new(None).cache_directory().to_path_buf();
+    for (index, album) in albums.iter().enumerate() {
+        let album_id = album_identifier(Some("Artist"), album);
+        std::fs::write(
+            cache_dir.join(format!("{}.jpg", album_id)),
+            [0xFF, 0xD8, index as u8],
+        )
+        .unwrap();
+    }
+
+    let counter = Arc::new(AtomicUsize::new(0));
+    let service = AlbumService::new(None).with_fs_call_counter(counter.clone());
+
+    // Listing twice does not touch the cache directory at all, where checking each
+    // album would have cost one stat per album and listing.
+    for _ in 0..2 {
+        let summaries = service.search_albums(&library, None).await;
+        assert_eq!(summaries.len(), albums.len());
+        assert!(summaries.iter().all(|album| album.artwork_path.is_some()));
+    }
+    assert_eq!(counter.load(Ordering::Relaxed), 0);
+
+    // Files removed by someone else are picked up by listing the cache and its
+    // objects, then updating the index.
+    let removed = album_identifier(Some("Artist"), &albums[0]);
+    std::fs::remove_file(service.cached_artwork_path(&removed).unwrap()).unwrap();
+    service.rescan_artwork_cache();
+    assert_eq!(counter.load(Ordering::Relaxed), 3);
+    assert!(service.cached_artwork_path(&removed).is_none());
+    assert!(service
+        .cached_artwork_path(&album_identifier(Some("Artist"), &albums[1]))
+        .is_some());
+}
+
+#[tokio::test]
+#[serial]
+async fn identical_artwork_is_stored_once() {
+    let _env = AlbumTestEnv::new();
+    let service = AlbumService::new(None);
+    let cache_dir = service.cache_directory().to_path_buf();
+
+    // Covers cached by an older version, one file per album
+    let cover = [0xFF, 0xD8, 0x01, 0x02];
+    for album_id in ["box-1", "box-2", "reissue"] {
+        std::fs::write(cache_dir.join(format!("{}.jpg", album_id)), cover).unwrap();
+    }
+    std::fs::write(cache_dir.join("other.jpg"), [0xFF, 0xD8, 0x03]).unwrap();
+
+    let report = service.deduplicate_artwork();
+    assert_eq!(
+        report,
+        ArtworkDedupReport {
+            files: 4,
+            duplicates: 2,
+            bytes_saved: 8,
+        }
+    );
+    let shared = service.cached_artwork_path("box-1").unwrap();
+    assert_eq!(service.cached_artwork_path("reissue"), Some(shared.clone()));
+    assert_ne!(service.cached_artwork_path("other"), Some(shared.clone()));
+    assert_eq!(std::fs::read(&shared).unwrap(), cover);
+    assert!(!cache_dir.join("box-1.jpg").exists());
+
+    // A restarted service reads the index, with nothing left to move
+    let restarted = AlbumService::new(None);
+    assert_eq!(restarted.cached_artwork_path("box-2"), Some(shared));
+    assert_eq!(
+        restarted.deduplicate_artwork(),
+        ArtworkDedupReport::default()
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn album_search_is_sorted_and_paginated() {
+    let env = AlbumTestEnv::new();
+    let albums = [
+        ("b.wav", "Queen", "Jazz", 1978),
+        ("a1.wav", "ABBA", "Arrival", 1976),
+        ("a2.wav", "ABBA", "Arrival", 1976),
+        ("c.wav", "Yes", "Close to the Edge", 1972),
+        ("d.wav", "Blondie", "Parallel Lines", 1978),
+    ];
+    let now = std::time::SystemTime::now();
+    for (index, (file, artist, album, year)) in albums.iter().enumerate() {
+        let path = env.create_tagged_track(file, artist, album);
+        write_track_tags(
+            &path,
+            &TrackTagUpdate {
+                year: Some(*year),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        // Later files in the list were added more recently
+        let modified = now - std::time::Duration::from_secs(3600 * (10 - index as u64));
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    let library = Library::new();
+    library.scan_directories(&[env.music_dir()]).unwrap();
+    let service = AlbumService::new(None);
+
+    let titles = |sort: AlbumSort, offset: usize, limit: Option<usize>| {
+        let service = &service;
+        let library = &library;
+        async move {
+            let search = AlbumSearch {
+                query: None,
+                sort,
+                offset,
+                limit,
+                musicbrainz_release_id: None,
+            };
+            let page = service.search_albums_page(library, &search).await;
+            let titles: Vec<String> = page.albums.into_iter().map(|album| album.title).collect();
+            (page.total, titles)
+        }
+    };
+
+    assert_eq!(
+        titles(AlbumSort::Title, 0, None).await.1,
+        ["Arrival", "Close to the Edge", "Jazz", "Parallel Lines"]
+    );
+    assert_eq!(
+        titles(AlbumSort::Artist, 0, None).await.1,
+        ["Arrival", "Parallel Lines", "Jazz", "Close to the Edge"]
+    );
+    assert_eq!(
+        titles(AlbumSort::TrackCount, 0, None).await.1,
+        ["Arrival", "Close to the Edge", "Jazz", "Parallel Lines"]
+    );
+    assert_eq!(
+        titles(AlbumSort::Year, 0, None).await.1,
+        ["Close to the Edge", "Arrival", "Jazz", "Parallel Lines"]
+    );
+    assert_eq!(
+        titles(AlbumSort::RecentlyAdded, 0, None).await.1,
+        ["Parallel Lines", "Close to the Edge", "Arrival", "Jazz"]
+    );
+
+    let (total, page) = titles(AlbumSort::Title, 1, Some(2)).await;
+    assert_eq!(total, 4);
+    assert_eq!(page, ["Close to the Edge", "Jazz"]);
+
+    let (total, page) = titles(AlbumSort::Title, 10, Some(2)).await;
+    assert_eq!(total, 4);
+    assert!(page.is_empty());
+}
+
+fn artwork_events(receiver: &mut Receiver<EventMessage>) -> Vec<(String, Option<String>)> {
+    let mut events = Vec::new();
+    while let Ok(message) = receiver.try_recv() {
+        if let EventPayload::AlbumArtworkUpdated {
+            album_id,
+            artwork_url,
+        } = message.payload
+        {
+            events.push((album_id, artwork_url));
+        }
+    }
+    events
+}
+
+#[tokio::test]
+#[serial]
+async fn artwork_changes_are_announced_with_the_album_id() {
+    let env = AlbumTestEnv::new();
+    let event_bus = Arc::new(EventBus::new(None));
+    let mut events = event_bus.subscribe();
+    let service = AlbumService::new(None).with_event_bus(event_bus.clone());
+
+    let cover = env.music_dir.join("cover.jpg");
+    std::fs::write(&cover, [0xFF, 0xD8, 0x01]).unwrap();
+    let url = format!("file://{}", cover.display());
+    let stored = service.store_artwork_from_url("album", &url).await.unwrap();
+    let first_url = album_artwork_url("album", &stored);
+    assert_eq!(
+        artwork_events(&mut events),
+        [("album".to_string(), Some(first_url.clone()))]
+    );
+
+    // Storing the same image again changes nothing
+    service.store_artwork_from_url("album", &url).await.unwrap();
+    assert!(artwork_events(&mut events).is_empty());
+    assert!(service
+        .store_artwork_from_url("album", "file:///nonexistent/cover.jpg")
+        .await
+        .is_none());
+    assert!(artwork_events(&mut events).is_empty());
+
+    // A replaced image gets a new URL, so cached copies are reloaded
+    std::fs::write(&cover, [0xFF, 0xD8, 0x02]).unwrap();
+    let replaced = service.store_artwork_from_url("album", &url).await.unwrap();
+    let replaced_url = album_artwork_url("album", &replaced);
+    assert_ne!(replaced_url, first_url);
+    assert_eq!(
+        artwork_events(&mut events),
+        [("album".to_string(), Some(replaced_url))]
+    );
+
+    service.forget_artwork("album");
+    assert_eq!(artwork_events(&mut events), [("album".to_string(), None)]);
+
+    // Evicting artwork of albums no longer in the library deletes it too
+    service.store_artwork("gone", &[0xFF, 0xD8, 0x03]).unwrap();
+    artwork_events(&mut events);
+    service.evict_unused_artwork(&Library::new());
+    assert_eq!(artwork_events(&mut events), [("gone".to_string(), None)]);
+}
@@ -0,0 +1,4428 @@
+mod common;
+
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use common::{write_silent_wav, Wav};
+use hexendrum::api::{
+    create_router, AppState, GuestPolicy, JobManager, MediaCommand, MediaInfo, MediaSession,
+    MediaSessionSink, MediaStatus, PlaybackRevision, ResumePositions, RouteBudgets, ShareClaims,
+    ShareScope, ShareSigner, SleepTimer, UpNextWatcher, CONTROL_BODY_LIMIT, EDIT_BODY_LIMIT,
+    MAX_BULK_TRACKS, MAX_DIRECTORIES, MAX_PLAYLIST_NAME_CHARS,
+};
+use hexendrum::audio::{
+    transcoding_available, AudioBackend, AudioDeviceInfo, AudioPlayer, AudioState,
+    DeviceRecoveryPolicy, Precache,
+};
+use hexendrum::config::{ApiConfig, Config, Paths};
+use hexendrum::ctl::{self, CtlAddress, CtlCommand, CtlOptions, CtlTarget};
+use hexendrum::events::{EventLog, WebhookDispatcher};
+use hexendrum::library::{
+    album_identifier, update_sidecar, write_track_tags, AlbumService, DeleteMode,
+    DuplicatePreferences, Library, ReadOnlyPaths, ScanConflict, SidecarMetadata, StatsStore,
+    TrackTagUpdate, Trash, VerificationJob, WaveformCache,
+};
+use hexendrum::playlist::{PlayOrder, PlaybackQueue, PlaylistManager, RepeatMode};
+use hexendrum::{EventBus, EventMessage, EventPayload, TrackMetadata};
+use serde_json::{json, Value};
+use serial_test::serial;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::sync::broadcast::Receiver;
+use tokio::sync::Notify;
+use tower::ServiceExt;
+
+/// Tracks at least this long resume where they were left off
+const RESUME_MIN_SECONDS: u64 = 30;
+
+struct RouterTestEnv {
+    workspace: TempDir,
+    music_dir: PathBuf,
+    playlist_dir: PathBuf,
+    delete_mode: DeleteMode,
+    read_only: ReadOnlyPaths,
+    scan_conflict: ScanConflict,
+    scan_pause: Duration,
+    route_budgets: RouteBudgets,
+    guest_policy: Option<Arc<GuestPolicy>>,
+    share_allow_stream: bool,
+    old_cache: Option<String>,
+    old_config: Option<String>,
+    old_home: Option<String>,
+}
+
+impl RouterTestEnv {
+    fn new() -> Self {
+        let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+        let music_dir = workspace.path().join("music");
+        let cache_dir = workspace.path().join("cache");
+        let config_dir = workspace.path().join("config");
+        let playlist_dir = workspace.path().join("playlists");
+
+        fs::create_dir(&music_dir).expect("failed to create music dir");
+        fs::create_dir(&cache_dir).expect("failed to create cache dir");
+        fs::create_dir(&config_dir).expect("failed to create config dir");
+
+        let old_cache = std::env::var("XDG_CACHE_HOME").ok();
+        std::env::set_var("XDG_CACHE_HOME", &cache_dir);
+
+        let old_config = std::env::var("XDG_CONFIG_HOME").ok();
+        std::env::set_var("XDG_CONFIG_HOME", &config_dir);
+
+        let old_home = std::env::var("HOME").ok();
+        std::env::set_var("HOME", workspace.path());
+
+        Self {
+            workspace,
+            music_dir,
+            playlist_dir,
+            delete_mode: DeleteMode::Trash,
+            read_only: ReadOnlyPaths::default(),
+            scan_conflict: ScanConflict::default(),
+            scan_pause: Duration::ZERO,
+            route_budgets: RouteBudgets::default(),
+            guest_policy: None,
+            share_allow_stream: false,
+            old_cache,
+            old_config,
+            old_home,
+        }
+    }
+
+    fn create_tagged_track(&self, name: &str, title: &str) -> String {
+        let path = self.music_dir.join(name);
+        write_silent_wav(&path);
+        write_track_tags(
+            &path,
+            &TrackTagUpdate {
+                title: Some(title.into()),
+                artist: Some("Artist".into()),
+                ..Default::default()
+            },
+        )
+        .expect("failed to tag audio file");
+        path.to_string_lossy().to_string()
+    }
+
+    /// Create a silent file lasting `seconds`, titled after its name.
+    fn create_long_track(&self, name: &str, seconds: u32) -> String {
+        let path = self.music_dir.join(name);
+        Wav::new().silent_frames(seconds * 8000).write(&path);
+        write_track_tags(
+            &path,
+            &TrackTagUpdate {
+                title: Some(name.into()),
+                ..Default::default()
+            },
+        )
+        .expect("failed to tag audio file");
+        path.to_string_lossy().to_string()
+    }
+
+    /// Build the application state around a backend that records what it was asked to play.
+    fn state(&self) -> (AppState, Arc<Mutex<Vec<PathBuf>>>) {
+        let library = Arc::new(
+            Library::new()
+                .with_read_only(self.read_only.clone())
+                .with_scan_conflict(self.scan_conflict)
+                .with_scan_pause(self.scan_pause),
+        );
+        library
+            .scan_directories(std::slice::from_ref(&self.music_dir))
+            .expect("scan should succeed");
+
+        let event_bus = Arc::new(EventBus::new(None));
+        let plays = Arc::new(Mutex::new(Vec::new()));
+        let backend_plays = plays.clone();
+        let audio_player = AudioPlayer::with_backend(
+            move || {
+                Ok(Box::new(RecordingBackend {
+                    plays: backend_plays,
+                    queued: false,
+                }) as Box<dyn AudioBackend>)
+            },
+            DeviceRecoveryPolicy::default(),
+            Some(event_bus.clone()),
+        )
+        .expect("player should start");
+
+        let jobs = Arc::new(JobManager::new().with_event_bus(event_bus.clone()));
+        let state = AppState {
+            library,
+            playlist_manager: Arc::new(
+                PlaylistManager::new(self.playlist_dir.clone()).expect("manager should initialize"),
+            ),
+            playback_queue: Arc::new(PlaybackQueue::new()),
+            audio_player: Arc::new(audio_player),
+            album_service: Arc::new(AlbumService::new(None).with_event_bus(event_bus.clone())),
+            stats_store: Arc::new(StatsStore::new()),
+            verification_job: Arc::new(VerificationJob::new()),
+            webhooks: WebhookDispatcher::start(&event_bus, Vec::new()),
+            trash: Arc::new(Trash::with_paths(
+                self.workspace.path().join("trash_journal.json"),
+                Some(self.workspace.path().join("Trash")),
+                30,
+            )),
+            delete_mode: self.delete_mode,
+            duplicate_preferences: DuplicatePreferences::default(),
+            event_bus,
+            paths: Paths::portable(self.workspace.path().join("data")),
+            shutdown: Arc::new(Notify::new()),
+            event_log: None,
+            revision: Arc::new(PlaybackRevision::new()),
+            transcode_cache: None,
+            precache: None,
+            max_import_bytes: 64 * 1024,
+            resume_positions: Arc::new(ResumePositions::new(RESUME_MIN_SECONDS)),
+            jobs,
+            route_budgets: self.route_budgets,
+            guest_policy: self.guest_policy.clone(),
+            waveforms: Arc::new(WaveformCache::new(self.workspace.path().join("waveforms"))),
+            shares: Arc::new(
+                ShareSigner::load(
+                    self.workspace.path().join("share_secret"),
+                    self.share_allow_stream,
+                )
+                .expect("share secret should be created"),
+            ),
+            sleep_timer: Arc::new(SleepTimer::new()),
+        };
+
+        (state, plays)
+    }
+}
+
+impl Drop for RouterTestEnv {
+    fn drop(&mut self) {
+        if let Some(old_cache) = &self.old_cache {
+            std::env::set_var("XDG_CACHE_HOME", old_cache);
+        } else {
+            std::env::remove_var("XDG_CACHE_HOME");
+        }
+
+        if let Some(old_config) = &self.old_config {
+            std::env::set_var("XDG_CONFIG_HOME", old_config);
+        } else {
+            std::env::remove_var("XDG_CONFIG_HOME");
+        }
+
+        if let Some(old_home) = &self.old_home {
+            std::env::set_var("HOME", old_home);
+        } else {
+            std::env::remove_var("HOME");
+        }
+    }
+}
+
+struct RecordingBackend {
+    plays: Arc<Mutex<Vec<PathBuf>>>,
+    /// Whether a file is queued to follow gaplessly
+    queued: bool,
+}
+
+impl AudioBackend for RecordingBackend {
+    fn open(&mut self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn is_device_alive(&mut self) -> bool {
+        true
+    }
+
+    fn device_name(&self) -> Option<String> {
+        Some("recording".into())
+    }
+
+    fn device_info(&self) -> Option<AudioDeviceInfo> {
+        Some(AudioDeviceInfo {
+            device_name: Some("recording".into()),
+            sample_rate: 48000,
+            buffer_frames: Some(4096),
+            channels: 2,
+            requested_sample_rate: Some(44100),
+            requested_buffer_frames: Some(4096),
+        })
+    }
+
+    fn play(&mut self, path: &Path, _start_at: Duration, _volume: f32) -> anyhow::Result<()> {
+        self.plays.lock().unwrap().push(path.to_path_buf());
+        self.queued = false;
+        Ok(())
+    }
+
+    fn enqueue(&mut self, path: &Path) -> anyhow::Result<()> {
+        rodio::Decoder::new(std::io::BufReader::new(fs::File::open(path)?))?;
+        self.queued = true;
+        Ok(())
+    }
+
+    fn source_count(&mut self) -> usize {
+        1 + usize::from(self.queued)
+    }
+
+    fn pause(&mut self) {}
+
+    fn resume(&mut self) {}
+
+    fn stop(&mut self) {
+        self.queued = false;
+    }
+
+    fn set_volume(&mut self, _volume: f32) {}
+
+    fn preview_play(&mut self, _path: &Path, _volume: f32) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+async fn post_json(state: &AppState, uri: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::post(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("valid request");
+
+    let response = create_router(state.clone())
+        .oneshot(request)
+        .await
+        .expect("router should respond");
+
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("body should be readable");
+    let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, value)
+}
+
+async fn put_json(state: &AppState, uri: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::put(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("valid request");
+
+    let response = create_router(state.clone())
+        .oneshot(request)
+        .await
+        .expect("router should respond");
+
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("body should be readable");
+    let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, value)
+}
+
+fn playback_states(receiver: &mut Receiver<EventMessage>) -> Vec<String> {
+    let mut states = Vec::new();
+    while let Ok(message) = receiver.try_recv() {
+        if let EventPayload::PlaybackState { state, .. } = message.payload {
+            states.push(state);
+        }
+    }
+    states
+}
+
+#[tokio::test]
+#[serial]
+async fn play_interrupts_by_default_and_emits_stop_play_pair() {
+    let env = RouterTestEnv::new();
+    let first = env.create_tagged_track("first.wav", "First");
+    let second = env.create_tagged_track("second.wav", "Second");
+    let (state, plays) = env.state();
+
+    let (status, _) = post_json(&state, "/api/audio/play", json!({ "file_path": first })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut events = state.event_bus.subscribe();
+    let (status, _) = post_json(&state, "/api/audio/play", json!({ "file_path": second })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    assert_eq!(playback_states(&mut events), vec!["stopped", "playing"]);
+    assert_eq!(plays.lock().unwrap().len(), 2);
+    assert_eq!(state.audio_player.get_current_track(), Some(second));
+
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(
+        body["data"]["source_format"],
+        json!({ "sample_rate": 8000, "channels": 1, "bits_per_sample": 16 })
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn status_reports_the_position_kept_by_the_audio_thread() {
+    let env = RouterTestEnv::new();
+    let song = env.create_long_track("song.wav", 120);
+    let (state, _) = env.state();
+
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["position_seconds"], json!(0.0));
+
+    post_json(&state, "/api/audio/play", json!({ "file_path": song })).await;
+    state.audio_player.seek(Duration::from_secs(45)).unwrap();
+    post_json(&state, "/api/audio/pause", json!({})).await;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    let (_, paused) = get_json(&state, "/api/audio/status").await;
+    let position = paused["data"]["position_seconds"].as_f64().unwrap();
+    assert!((45.0..45.5).contains(&position), "{}", position);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let (_, still) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(still["data"]["position_seconds"], json!(position));
+
+    post_json(&state, "/api/audio/stop", json!({})).await;
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["position_seconds"], json!(0.0));
+}
+
+#[tokio::test]
+#[serial]
+async fn a_region_of_the_current_track_loops_until_cleared() {
+    let env = RouterTestEnv::new();
+    let song = env.create_long_track("song.wav", 120);
+    let other = env.create_long_track("other.wav", 120);
+    let (state, plays) = env.state();
+    let set_loop = |start: f64, end: f64| {
+        post_json(
+            &state,
+            "/api/audio/loop",
+            json!({ "start_seconds": start, "end_seconds": end }),
+        )
+    };
+
+    let (status, _) = set_loop(10.0, 20.0).await;
+    assert_eq!(status, StatusCode::CONFLICT, "nothing is playing");
+
+    post_json(&state, "/api/audio/play", json!({ "file_path": song })).await;
+    for (start, end) in [(20.0, 10.0), (10.0, 10.0), (10.0, 120.5), (-1.0, 10.0)] {
+        let (status, _) = set_loop(start, end).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{} to {}", start, end);
+    }
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["loop"], Value::Null);
+
+    state.audio_player.seek(Duration::from_secs(45)).unwrap();
+    let played = plays.lock().unwrap().len();
+    let (status, body) = set_loop(10.0, 45.2).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["data"],
+        json!({ "start_seconds": 10.0, "end_seconds": 45.2 })
+    );
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    assert_eq!(plays.lock().unwrap().len(), played + 1, "looped back once");
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    let position = body["data"]["position_seconds"].as_f64().unwrap();
+    assert!((10.0..10.5).contains(&position), "{}", position);
+    assert_eq!(
+        body["data"]["loop"],
+        json!({ "start_seconds": 10.0, "end_seconds": 45.2 })
+    );
+
+    let request = Request::delete("/api/audio/loop")
+        .body(Body::empty())
+        .unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["loop"], Value::Null);
+
+    set_loop(0.0, 120.0).await;
+    post_json(&state, "/api/audio/play", json!({ "file_path": other })).await;
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["loop"], Value::Null, "another track clears it");
+}
+
+#[tokio::test]
+#[serial]
+async fn sleep_timers_stop_playback_unless_cancelled() {
+    let env = RouterTestEnv::new();
+    let song = env.create_long_track("song.wav", 120);
+    let (state, _) = env.state();
+    let mut events = state.event_bus.subscribe();
+    let cancel = || {
+        let request = Request::delete("/api/audio/sleep-timer")
+            .body(Body::empty())
+            .unwrap();
+        create_router(state.clone()).oneshot(request)
+    };
+
+    let (status, _) = get_json(&state, "/api/audio/sleep-timer").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    for minutes in [0.0, -5.0, 24.0 * 60.0 + 1.0] {
+        let (status, _) = post_json(
+            &state,
+            "/api/audio/sleep-timer",
+            json!({ "minutes": minutes }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", minutes);
+    }
+
+    post_json(&state, "/api/audio/play", json!({ "file_path": song })).await;
+    let (status, body) = post_json(
+        &state,
+        "/api/audio/sleep-timer",
+        json!({ "minutes": 30, "fade": true }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["remaining_seconds"], json!(1800.0));
+    assert_eq!(body["data"]["fading"], json!(false));
+    let (_, body) = get_json(&state, "/api/audio/sleep-timer").await;
+    let remaining = body["data"]["remaining_seconds"].as_f64().unwrap();
+    assert!((1799.0..=1800.0).contains(&remaining), "{}", remaining);
+
+    // Shorter than the fade: the whole timer fades
+    let (_, body) = post_json(
+        &state,
+        "/api/audio/sleep-timer",
+        json!({ "minutes": 0.005, "fade": true }),
+    )
+    .await;
+    assert_eq!(body["data"]["fading"], json!(true), "replaces the first");
+    let response = cancel().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["state"], "Playing", "cancelled before firing");
+    let (status, _) = get_json(&state, "/api/audio/sleep-timer").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    while events.try_recv().is_ok() {}
+    post_json(
+        &state,
+        "/api/audio/sleep-timer",
+        json!({ "minutes": 0.005 }),
+    )
+    .await;
+    let mut seen = Vec::new();
+    while !seen.contains(&"sleep_timer_fired") {
+        let message = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("the timer should fire")
+            .unwrap();
+        match message.payload {
+            EventPayload::PlaybackState { state, .. } if state == "stopped" => seen.push("stopped"),
+            EventPayload::SleepTimerFired { track_path, fade } => {
+                assert_eq!(track_path, Some(song.clone()));
+                assert!(!fade);
+                seen.push("sleep_timer_fired");
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(seen, ["stopped", "sleep_timer_fired"]);
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["state"], "Stopped");
+    let (status, _) = get_json(&state, "/api/audio/sleep-timer").await;
+    assert_eq!(status, StatusCode::NOT_FOUND, "a timer that fired is gone");
+}
+
+#[tokio::test]
+#[serial]
+async fn the_playback_speed_is_set_and_reported() {
+    let env = RouterTestEnv::new();
+    let song = env.create_long_track("song.wav", 120);
+    let (state, plays) = env.state();
+    post_json(&state, "/api/audio/play", json!({ "file_path": song })).await;
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["speed"], json!(1.0));
+    let revision = body["data"]["revision"].as_u64().unwrap();
+
+    let (status, _) = post_json(&state, "/api/audio/speed", json!({ "speed": 1.5 })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["speed"], json!(1.5));
+    assert_eq!(body["data"]["state"], "Playing");
+    assert!(body["data"]["revision"].as_u64().unwrap() > revision);
+    assert_eq!(plays.lock().unwrap().len(), 1, "the track did not restart");
+
+    post_json(&state, "/api/audio/speed", json!({ "speed": 3 })).await;
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["speed"], json!(2.0), "clamped");
+    post_json(&state, "/api/audio/speed", json!({ "speed": 1 })).await;
+    assert_eq!(state.audio_player.get_speed(), 1.0);
+}
+
+#[tokio::test]
+#[serial]
+async fn files_queue_gaplessly_behind_the_current_track() {
+    let env = RouterTestEnv::new();
+    let current = env.create_long_track("current.wav", 120);
+    let next = env.create_long_track("next.wav", 120);
+    let broken = env.music_dir.join("broken.wav");
+    fs::write(&broken, b"not audio").unwrap();
+    let (state, _) = env.state();
+    let mut events = state.event_bus.subscribe();
+
+    let (status, _) = post_json(&state, "/api/audio/enqueue", json!({ "file_path": next })).await;
+    assert_eq!(status, StatusCode::CONFLICT, "nothing is playing");
+
+    post_json(&state, "/api/audio/play", json!({ "file_path": current })).await;
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/enqueue",
+        json!({ "file_path": env.music_dir.join("missing.wav") }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = post_json(&state, "/api/audio/enqueue", json!({ "file_path": broken })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let error =
+        std::iter::from_fn(|| events.try_recv().ok()).find_map(|message| match message.payload {
+            EventPayload::PlaybackError { track_path, .. } => Some(track_path),
+            _ => None,
+        });
+    assert_eq!(error, Some(broken.to_string_lossy().to_string()));
+
+    let (status, _) = post_json(&state, "/api/audio/enqueue", json!({ "file_path": next })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["next_track"], json!(next));
+    assert_eq!(body["data"]["current_track"], json!(current));
+
+    let (status, _) = post_json(&state, "/api/audio/enqueue", json!({ "file_path": next })).await;
+    assert_eq!(status, StatusCode::CONFLICT, "one file is queued at a time");
+
+    post_json(&state, "/api/audio/stop", json!({})).await;
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["next_track"], Value::Null);
+}
+
+#[tokio::test]
+#[serial]
+async fn previews_leave_main_playback_and_its_revision_alone() {
+    let env = RouterTestEnv::new();
+    let main = env.create_tagged_track("main.wav", "Main");
+    let cue = env.create_tagged_track("cue.wav", "Cue");
+    let (state, plays) = env.state();
+
+    let (status, _) = post_json(&state, "/api/audio/play", json!({ "file_path": main })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (_, before) = get_json(&state, "/api/audio/status").await;
+
+    let (status, body) = post_json(
+        &state,
+        "/api/audio/preview/play",
+        json!({ "file_path": cue }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["track_path"], json!(cue));
+    let (status, body) = post_json(
+        &state,
+        "/api/audio/preview/volume",
+        json!({ "volume": 0.25 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["volume"], json!(0.25));
+
+    let (_, after) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(after["data"]["state"], json!("Playing"));
+    assert_eq!(after["data"]["current_track"], json!(main));
+    assert_eq!(after["data"]["volume"], before["data"]["volume"]);
+    assert_eq!(after["data"]["revision"], before["data"]["revision"]);
+    assert_eq!(after["data"]["preview"]["track_path"], json!(cue));
+    assert_eq!(plays.lock().unwrap().len(), 1);
+
+    let (status, body) = post_json(&state, "/api/audio/preview/stop", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["track_path"], Value::Null);
+    assert_eq!(state.audio_player.get_state(), AudioState::Playing);
+
+    let missing = env.music_dir.join("missing.wav");
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/preview/play",
+        json!({ "file_path": missing }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn genre_tags_can_be_rewritten_to_their_canonical_names() {
+    let env = RouterTestEnv::new();
+    for (name, genre) in [
+        ("a.wav", "hip hop"),
+        ("b.wav", "Hip-Hop/Rap"),
+        ("c.wav", "Hip-Hop"),
+    ] {
+        let path = env.create_tagged_track(name, name);
+        write_track_tags(
+            Path::new(&path),
+            &TrackTagUpdate {
+                genre: Some(genre.into()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    }
+    let (state, _) = env.state();
+
+    let (status, body) = get_json(&state, "/api/library/genres").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"][0]["name"], json!("Hip-Hop"));
+    assert_eq!(body["data"][0]["track_count"], json!(3));
+    let (_, body) = get_json(&state, "/api/library/genres/raw").await;
+    assert_eq!(body["data"].as_array().unwrap().len(), 3);
+
+    let (status, body) =
+        post_json(&state, "/api/library/genres/retag?dry_run=true", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"].as_array().unwrap().len(), 2);
+    let (_, body) = get_json(&state, "/api/library/genres/raw").await;
+    assert_eq!(
+        body["data"].as_array().unwrap().len(),
+        3,
+        "dry runs write nothing"
+    );
+
+    let (status, body) = post_json(&state, "/api/library/genres/retag", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let files = body["data"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert!(
+        files.iter().all(|file| file["error"].is_null()),
+        "{:?}",
+        files
+    );
+    assert_eq!(files[0]["from"], json!("hip hop"));
+    assert_eq!(files[0]["to"], json!("Hip-Hop"));
+
+    let (_, body) = get_json(&state, "/api/library/genres/raw").await;
+    assert_eq!(
+        body["data"],
+        json!([{ "value": "Hip-Hop", "track_count": 3, "genre": "Hip-Hop" }])
+    );
+    let rescanned = TrackMetadata::from_file(&env.music_dir.join("b.wav")).unwrap();
+    assert_eq!(rescanned.genre.as_deref(), Some("Hip-Hop"));
+}
+
+#[tokio::test]
+#[serial]
+async fn tag_values_can_be_found_and_replaced_across_the_library() {
+    let env = RouterTestEnv::new();
+    env.create_tagged_track("a.wav", "Song feat Guest");
+    env.create_tagged_track("b.wav", "Other feat Guest");
+    env.create_tagged_track("c.wav", "Plain feat. Guest");
+    let (state, _) = env.state();
+
+    let (status, _) = post_json(
+        &state,
+        "/api/library/metadata/replace",
+        json!({ "field": "title", "find": "(", "replace": "", "match": "regex" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let request = json!({
+        "field": "title",
+        "find": r"\bfeat\s",
+        "replace": "feat. ",
+        "match": "regex",
+        "dry_run": true,
+    });
+    let (status, body) = post_json(&state, "/api/library/metadata/replace", request).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"].as_array().unwrap().len(), 2);
+    let unchanged = TrackMetadata::from_file(&env.music_dir.join("a.wav")).unwrap();
+    assert_eq!(
+        unchanged.title.as_deref(),
+        Some("Song feat Guest"),
+        "dry runs write nothing"
+    );
+
+    let sequence = state.library.change_sequence();
+    let mut events = state.event_bus.subscribe();
+    let request = json!({
+        "field": "title",
+        "find": r"\bfeat\s",
+        "replace": "feat. ",
+        "match": "regex",
+    });
+    let (status, body) = post_json(&state, "/api/library/metadata/replace", request).await;
+    assert_eq!(status, StatusCode::OK);
+    let files = body["data"].as_array().unwrap();
+    assert_eq!(files.len(), 2);
+    assert!(
+        files.iter().all(|file| file["error"].is_null()),
+        "{:?}",
+        files
+    );
+    assert_eq!(files[0]["from"], json!("Song feat Guest"));
+    assert_eq!(files[0]["to"], json!("Song feat. Guest"));
+    let rescanned = TrackMetadata::from_file(&env.music_dir.join("b.wav")).unwrap();
+    assert_eq!(rescanned.title.as_deref(), Some("Other feat. Guest"));
+    let (_, body) = get_json(&state, "/api/library/search?q=Other").await;
+    assert_eq!(body["data"][0]["title"], json!("Other feat. Guest"));
+
+    let mut updates = Vec::new();
+    let mut job_states = Vec::new();
+    while let Ok(message) = events.try_recv() {
+        let event = serde_json::to_value(message.payload).unwrap();
+        match event["type"].as_str() {
+            Some("library_updated") => updates.push(event),
+            Some("job_progress") if event["kind"] == json!("metadata_replace") => {
+                job_states.push(event["state"].clone())
+            }
+            _ => {}
+        }
+    }
+    assert_eq!(updates.len(), 1, "{:?}", updates);
+    assert_eq!(updates[0]["sequence"], json!(sequence + 2));
+    assert_eq!(updates[0]["updated"], json!(2));
+    assert_eq!(job_states.first(), Some(&json!("queued")));
+    assert_eq!(job_states.last(), Some(&json!("completed")));
+}
+
+#[tokio::test]
+#[serial]
+async fn precache_counters_are_reported_when_enabled() {
+    let env = RouterTestEnv::new();
+    let (mut state, _) = env.state();
+
+    let (status, _) = get_json(&state, "/api/audio/precache").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let track = env.music_dir.join("song.flac");
+    fs::write(&track, [0u8; 100]).unwrap();
+    let precache = Arc::new(Precache::new(
+        env.workspace.path().join("precache"),
+        1024,
+        4096,
+    ));
+    precache.fetch(&track).unwrap();
+    precache.resolve(&track);
+    state.precache = Some(precache);
+
+    let (status, body) = get_json(&state, "/api/audio/precache").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["data"],
+        json!({"hits": 1, "misses": 0, "files": 1, "bytes": 100, "max_bytes": 4096})
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn audio_device_reports_the_opened_stream_parameters() {
+    let env = RouterTestEnv::new();
+    let (state, _) = env.state();
+
+    let (status, body) = get_json(&state, "/api/audio/device").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["data"],
+        json!({
+            "device_name": "recording",
+            "sample_rate": 48000,
+            "buffer_frames": 4096,
+            "channels": 2,
+            "requested_sample_rate": 44100,
+            "requested_buffer_frames": 4096,
+        })
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn output_devices_are_listed_and_switched_to() {
+    let env = RouterTestEnv::new();
+    let path = env.create_tagged_track("song.wav", "Song");
+    let (state, plays) = env.state();
+
+    let (status, body) = get_json(&state, "/api/audio/devices").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["data"],
+        json!({ "devices": ["recording"], "current": "recording" })
+    );
+
+    let (status, _) = post_json(&state, "/api/audio/play", json!({ "file_path": path })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = post_json(
+        &state,
+        "/api/audio/devices/switch",
+        json!({ "name": "Recording" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["current"], "recording");
+    assert_eq!(
+        plays.lock().unwrap().len(),
+        2,
+        "the track carries on on the new device"
+    );
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["state"], "Playing");
+
+    let (status, body) = post_json(&state, "/api/audio/device", json!({ "name": null })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["current"], "recording");
+    assert_eq!(plays.lock().unwrap().len(), 3);
+    let (status, _) = get_json(&state, "/api/audio/device").await;
+    assert_eq!(
+        status,
+        StatusCode::OK,
+        "the device is still read on the same path"
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn suggest_groups_completions_by_requested_type() {
+    let env = RouterTestEnv::new();
+    env.create_tagged_track("one.wav", "Artistic License");
+    env.create_tagged_track("two.wav", "Smart Art");
+    let (state, _) = env.state();
+
+    let (status, body) = get_json(&state, "/api/library/suggest?q=art&types=title,artist").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["data"],
+        json!([
+            { "type": "title", "suggestions": ["Artistic License", "Smart Art"] },
+            { "type": "artist", "suggestions": ["Artist"] },
+        ])
+    );
+
+    let (_, body) = get_json(&state, "/api/library/suggest?q=art&limit=1").await;
+    let groups = body["data"].as_array().unwrap();
+    assert_eq!(groups.len(), 3);
+    assert!(groups
+        .iter()
+        .all(|group| group["suggestions"].as_array().unwrap().len() <= 1));
+
+    let (status, _) = get_json(&state, "/api/library/suggest?q=art&types=genre").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+#[serial]
+async fn setup_writes_a_starter_config_and_runs_the_first_scan() {
+    let env = RouterTestEnv::new();
+    let (state, _) = env.state();
+    let more_music = env.workspace.path().join("more music");
+    fs::create_dir(&more_music).unwrap();
+    write_silent_wav(&more_music.join("found.wav"));
+
+    let (status, body) = get_json(&state, "/api/setup/status").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["needs_setup"], json!(true));
+    assert_eq!(body["data"]["config_exists"], json!(false));
+    // The default ~/Music does not exist in the test home
+    assert_eq!(body["data"]["music_directories_ready"], json!(false));
+    assert_eq!(body["data"]["audio_device_available"], json!(true));
+
+    let missing = env.workspace.path().join("missing");
+    let (status, _) = post_json(
+        &state,
+        "/api/setup/initialize",
+        json!({ "music_directories": [missing] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(!state.paths.config_file().exists());
+
+    let mut events = state.event_bus.subscribe();
+    let (status, _) = post_json(
+        &state,
+        "/api/setup/initialize",
+        json!({ "music_directories": [env.music_dir, more_music], "default_volume": 0.4 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut progress = Vec::new();
+    while progress
+        .last()
+        .map(|(status, _): &(String, _)| status.as_str())
+        != Some("completed")
+    {
+        let message = tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .expect("the first scan should finish")
+            .unwrap();
+        if let EventPayload::LibraryScan {
+            status, processed, ..
+        } = message.payload
+        {
+            progress.push((status, processed));
+        }
+    }
+    assert_eq!(
+        progress,
+        vec![
+            ("started".to_string(), Some(0)),
+            ("running".to_string(), Some(1)),
+            ("running".to_string(), Some(2)),
+            ("completed".to_string(), Some(2)),
+        ]
+    );
+    assert!(state
+        .library
+        .get_track_by_path(&more_music.join("found.wav"))
+        .is_some());
+
+    let config = Config::load(&state.paths).unwrap();
+    assert_eq!(
+        config.library.music_directory_paths(),
+        vec![env.music_dir.clone(), more_music]
+    );
+    assert_eq!(config.audio.default_volume, 0.4);
+
+    let (_, body) = get_json(&state, "/api/setup/status").await;
+    assert_eq!(body["data"]["needs_setup"], json!(false));
+    assert_eq!(body["data"]["music_directories_ready"], json!(true));
+    assert_eq!(body["data"]["first_scan_completed"], json!(true));
+
+    let (status, _) = post_json(
+        &state,
+        "/api/setup/initialize",
+        json!({ "music_directories": [env.music_dir] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+#[serial]
+async fn enqueue_modes_queue_without_touching_playback() {
+    let env = RouterTestEnv::new();
+    let current = env.create_tagged_track("current.wav", "Current");
+    let last = env.create_tagged_track("last.wav", "Last");
+    let next = env.create_tagged_track("next.wav", "Next");
+    let (state, plays) = env.state();
+
+    post_json(&state, "/api/audio/play", json!({ "file_path": current })).await;
+    let mut events = state.event_bus.subscribe();
+
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/play",
+        json!({ "file_path": last, "behavior": "enqueue_end" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/play",
+        json!({ "file_path": next, "behavior": "enqueue_next" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    assert!(playback_states(&mut events).is_empty());
+    assert_eq!(plays.lock().unwrap().len(), 1);
+    assert_eq!(state.audio_player.get_state(), AudioState::Playing);
+    assert_eq!(state.audio_player.get_current_track(), Some(current));
+
+    let next_id = state
+        .library
+        .get_track_by_path(Path::new(&next))
+        .unwrap()
+        .id;
+    let last_id = state
+        .library
+        .get_track_by_path(Path::new(&last))
+        .unwrap()
+        .id;
+    assert_eq!(state.playback_queue.next_track(), Some(next_id));
+    assert_eq!(state.playback_queue.next_track(), Some(last_id));
+}
+
+#[tokio::test]
+#[serial]
+async fn enqueue_with_nothing_playing_starts_playback() {
+    let env = RouterTestEnv::new();
+    let track = env.create_tagged_track("track.wav", "Track");
+    let (state, plays) = env.state();
+
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/play",
+        json!({ "file_path": track, "behavior": "enqueue_end" }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(plays.lock().unwrap().len(), 1);
+    assert!(state.playback_queue.is_empty());
+}
+
+#[tokio::test]
+#[serial]
+async fn reject_if_playing_returns_conflict_with_current_track() {
+    let env = RouterTestEnv::new();
+    let current = env.create_tagged_track("current.wav", "Current");
+    let other = env.create_tagged_track("other.wav", "Other");
+    let (state, plays) = env.state();
+
+    post_json(&state, "/api/audio/play", json!({ "file_path": current })).await;
+
+    let (status, body) = post_json(
+        &state,
+        "/api/audio/play",
+        json!({ "file_path": other, "behavior": "reject_if_playing" }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["success"], json!(false));
+    assert_eq!(body["data"]["title"], json!("Current"));
+    assert_eq!(body["data"]["path"], json!(current));
+    assert_eq!(plays.lock().unwrap().len(), 1);
+
+    state.audio_player.stop().unwrap();
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/play",
+        json!({ "file_path": other, "behavior": "reject_if_playing" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[tokio::test]
+#[serial]
+async fn reject_if_playing_plays_over_a_paused_track() {
+    let env = RouterTestEnv::new();
+    let current = env.create_tagged_track("current.wav", "Current");
+    let other = env.create_tagged_track("other.wav", "Other");
+    let (state, plays) = env.state();
+
+    post_json(&state, "/api/audio/play", json!({ "file_path": current })).await;
+    state.audio_player.pause().unwrap();
+    assert_eq!(state.audio_player.get_state(), AudioState::Paused);
+
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/play",
+        json!({ "file_path": other, "behavior": "reject_if_playing" }),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(plays.lock().unwrap().len(), 2);
+    assert_eq!(state.audio_player.get_current_track(), Some(other));
+}
+
+async fn get_json(state: &AppState, uri: &str) -> (StatusCode, Value) {
+    let request = Request::get(uri)
+        .body(Body::empty())
+        .expect("valid request");
+
+    let response = create_router(state.clone())
+        .oneshot(request)
+        .await
+        .expect("router should respond");
+
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("body should be readable");
+    let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, value)
+}
+
+fn revisions(receiver: &mut Receiver<EventMessage>) -> Vec<(&'static str, Option<u64>)> {
+    let mut revisions = Vec::new();
+    while let Ok(message) = receiver.try_recv() {
+        match message.payload {
+            EventPayload::PlaybackState { revision, .. } => revisions.push(("state", revision)),
+            EventPayload::VolumeChanged { revision, .. } => revisions.push(("volume", revision)),
+            _ => {}
+        }
+    }
+    revisions
+}
+
+#[tokio::test]
+#[serial]
+async fn stale_revisions_are_refused_with_conflict() {
+    let env = RouterTestEnv::new();
+    let track = env.create_tagged_track("track.wav", "Track");
+    let (state, _) = env.state();
+    let mut events = state.event_bus.subscribe();
+
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["revision"], json!(0));
+
+    let (status, _) = post_json(&state, "/api/audio/play", json!({ "file_path": track })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(revisions(&mut events), vec![("state", Some(1))]);
+
+    // Two clients both saw revision 1 and move the slider at the same time
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/volume?if_revision=1",
+        json!({ "volume": 0.2 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = post_json(
+        &state,
+        "/api/audio/volume?if_revision=1",
+        json!({ "volume": 0.9 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(body["error"].as_str().unwrap().contains("revision is 2"));
+    assert_eq!(state.audio_player.get_volume(), 0.2);
+    assert_eq!(revisions(&mut events), vec![("volume", Some(2))]);
+
+    // The loser refreshes and retries; clients without a precondition always win
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["revision"], json!(2));
+    let (status, _) = post_json(&state, "/api/audio/pause?if_revision=2", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post_json(&state, "/api/audio/resume?if_revision=2", json!({})).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    let (status, _) = post_json(&state, "/api/audio/stop", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        revisions(&mut events),
+        vec![("state", Some(3)), ("state", Some(4))]
+    );
+
+    // Settings changes report the revision without advancing it
+    let (status, _) = post_json(&state, "/api/audio/repeat", json!({ "mode": "all" })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(revisions(&mut events), vec![("state", Some(4))]);
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/play?if_revision=3",
+        json!({ "file_path": track }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(state.audio_player.get_state(), AudioState::Stopped);
+}
+
+#[tokio::test]
+#[serial]
+async fn concurrent_conditional_writes_let_exactly_one_win() {
+    let env = RouterTestEnv::new();
+    let (state, _) = env.state();
+
+    let attempts = (0..8).map(|index| {
+        let state = state.clone();
+        tokio::spawn(async move {
+            post_json(
+                &state,
+                "/api/audio/volume?if_revision=0",
+                json!({ "volume": index as f32 / 10.0 }),
+            )
+            .await
+            .0
+        })
+    });
+    let mut statuses = Vec::new();
+    for attempt in attempts {
+        statuses.push(attempt.await.unwrap());
+    }
+
+    assert_eq!(statuses.iter().filter(|s| **s == StatusCode::OK).count(), 1);
+    assert_eq!(
+        statuses
+            .iter()
+            .filter(|s| **s == StatusCode::CONFLICT)
+            .count(),
+        7
+    );
+    assert_eq!(state.revision.current(), 1);
+}
+
+#[tokio::test]
+#[serial]
+async fn repeat_and_shuffle_are_reported_in_status_and_events() {
+    let env = RouterTestEnv::new();
+    let (state, _) = env.state();
+    let mut events = state.event_bus.subscribe();
+
+    let (status, _) = post_json(&state, "/api/audio/repeat", json!({ "mode": "all" })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post_json(&state, "/api/audio/shuffle", json!({ "enabled": true })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = post_json(&state, "/api/audio/repeat", json!({ "mode": "twice" })).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["repeat_mode"], json!("all"));
+    assert_eq!(body["data"]["shuffle"], json!(true));
+
+    let mut last_settings = None;
+    while let Ok(message) = events.try_recv() {
+        if let EventPayload::PlaybackState {
+            repeat_mode,
+            shuffle,
+            ..
+        } = message.payload
+        {
+            last_settings = Some((repeat_mode, shuffle));
+        }
+    }
+    assert_eq!(last_settings, Some((Some("all".into()), Some(true))));
+}
+
+#[tokio::test]
+#[serial]
+async fn queue_history_lists_played_tracks_newest_first() {
+    let env = RouterTestEnv::new();
+    let paths: Vec<String> = ["First", "Second", "Third"]
+        .iter()
+        .map(|title| env.create_tagged_track(&format!("{}.wav", title), title))
+        .collect();
+    let (state, _) = env.state();
+
+    for path in &paths {
+        post_json(&state, "/api/audio/play", json!({ "file_path": path })).await;
+    }
+
+    let (status, body) = get_json(&state, "/api/queue/history?limit=2").await;
+    assert_eq!(status, StatusCode::OK);
+    let history = body["data"].as_array().unwrap();
+    let titles: Vec<&Value> = history.iter().map(|item| &item["title"]).collect();
+    assert_eq!(titles, vec![&json!("Third"), &json!("Second")]);
+    assert!(history[0]["played_at"].is_string());
+    assert_eq!(history[0]["path"], json!(paths[2]));
+
+    let (_, body) = get_json(&state, "/api/queue").await;
+    assert_eq!(body["data"]["history"].as_array().unwrap().len(), 3);
+
+    let clear = |uri: &str| {
+        create_router(state.clone()).oneshot(Request::delete(uri).body(Body::empty()).unwrap())
+    };
+    let response = clear("/api/queue").await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(state.playback_queue.history(10).len(), 3);
+
+    clear("/api/queue?history=true").await.unwrap();
+    let (_, body) = get_json(&state, "/api/queue/history").await;
+    assert_eq!(body["data"], json!([]));
+}
+
+/// Write an MP3 whose ID3v2.3 tag holds "Intro" and "Outro" chapters at 0s and 30s.
+fn write_chaptered_mp3(path: &Path) {
+    let frame =
+        |id: &[u8], body: &[u8]| [id, &(body.len() as u32).to_be_bytes(), &[0, 0], body].concat();
+    let chap = |element: &[u8], start_ms: u32, title: &[u8]| {
+        let title = frame(b"TIT2", &[b"\x03", title].concat());
+        let times = [
+            start_ms.to_be_bytes(),
+            (start_ms + 1000).to_be_bytes(),
+            [0xFF; 4],
+            [0xFF; 4],
+        ];
+        frame(b"CHAP", &[element, b"\0", &times.concat(), &title].concat())
+    };
+    let frames = [chap(b"ch1", 0, b"Intro"), chap(b"ch2", 30_000, b"Outro")].concat();
+
+    let size = frames.len() as u32;
+    let synchsafe = [
+        (size >> 21) as u8 & 0x7F,
+        (size >> 14) as u8 & 0x7F,
+        (size >> 7) as u8 & 0x7F,
+        size as u8 & 0x7F,
+    ];
+    let tag = [b"ID3\x03\0\0".as_slice(), &synchsafe, &frames].concat();
+    fs::write(path, tag).expect("failed to write audio file");
+}
+
+#[tokio::test]
+#[serial]
+async fn chapters_are_listed_and_seekable() {
+    let env = RouterTestEnv::new();
+    let mix = env.music_dir.join("mix.mp3");
+    write_chaptered_mp3(&mix);
+    let plain = env.create_tagged_track("plain.wav", "Plain");
+    let (state, plays) = env.state();
+
+    let mix_id = state.library.get_track_by_path(&mix).unwrap().id;
+    let plain_id = state
+        .library
+        .get_track_by_path(Path::new(&plain))
+        .unwrap()
+        .id;
+
+    let (status, body) =
+        get_json(&state, &format!("/api/library/tracks/{}/chapters", mix_id)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["data"],
+        json!([
+            { "title": "Intro", "start_seconds": 0.0 },
+            { "title": "Outro", "start_seconds": 30.0 },
+        ])
+    );
+    let (_, body) = get_json(
+        &state,
+        &format!("/api/library/tracks/{}/chapters", plain_id),
+    )
+    .await;
+    assert_eq!(body["data"], json!([]));
+    let (status, _) = get_json(&state, "/api/library/tracks/missing/chapters").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Chapters live in a sidecar rather than the main cache, and survive a restart.
+    let cache = env
+        .workspace
+        .path()
+        .join("cache/hexendrum/library_cache.json");
+    assert!(!fs::read_to_string(cache).unwrap().contains("Outro"));
+    assert_eq!(
+        Library::new()
+            .get_track(&mix_id)
+            .unwrap()
+            .metadata
+            .chapters
+            .len(),
+        2
+    );
+
+    let (status, _) = post_json(&state, "/api/audio/seek-chapter", json!({ "index": 1 })).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    post_json(&state, "/api/audio/play", json!({ "file_path": mix })).await;
+    let (status, _) = post_json(&state, "/api/audio/seek-chapter", json!({ "index": 1 })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(*plays.lock().unwrap(), [mix.clone(), mix]);
+
+    let (status, body) = post_json(&state, "/api/audio/seek-chapter", json!({ "index": 2 })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"], json!("The current track has no chapter 2"));
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn waveforms_are_served_as_json_or_bytes() {
+    let env = RouterTestEnv::new();
+    let song = env.create_long_track("song.wav", 2);
+    let broken = env.create_tagged_track("broken.wav", "Broken");
+    let (state, _) = env.state();
+    fs::write(&broken, b"RIFF\0\0\0\0garbage").unwrap();
+    let id = state
+        .library
+        .get_track_by_path(Path::new(&song))
+        .unwrap()
+        .id;
+    let uri = format!("/api/library/tracks/{}/waveform", id);
+
+    let (status, body) = get_json(&state, &format!("{}?points=400", uri)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["track_id"], json!(id));
+    assert_eq!(body["data"]["points"], json!(400));
+    assert_eq!(body["data"]["duration"], json!(2));
+    // Silence, as min and max pairs
+    assert_eq!(body["data"]["data"], json!(vec![0; 800]));
+
+    let (status, headers, bytes) =
+        get_bytes(&state, &format!("{}?points=100&format=binary", uri), None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["content-type"], "application/octet-stream");
+    assert_eq!(bytes, vec![0; 200]);
+
+    // Computed once and cached
+    let (status, body) = post_json(&state, "/api/library/waveforms", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["data"],
+        json!({ "computed": 0, "cached": 1, "failed": 1 })
+    );
+
+    for points in ["0", "801"] {
+        let (status, _) = get_json(&state, &format!("{}?points={}", uri, points)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+    let broken_id = state
+        .library
+        .get_track_by_path(Path::new(&broken))
+        .unwrap()
+        .id;
+    let (status, _) = get_json(
+        &state,
+        &format!("/api/library/tracks/{}/waveform", broken_id),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    let (status, _) = get_json(&state, "/api/library/tracks/missing/waveform").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+#[serial]
+async fn verification_lists_corrupt_tracks() {
+    let env = RouterTestEnv::new();
+    env.create_tagged_track("intact.wav", "Intact");
+    let damaged = env.create_tagged_track("damaged.wav", "Damaged");
+    let (state, _) = env.state();
+    fs::write(&damaged, b"RIFF\0\0\0\0garbage").unwrap();
+
+    let (status, _) = post_json(&state, "/api/library/verify/cancel", json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let mut events = state.event_bus.subscribe();
+    let (status, _) = post_json(&state, "/api/library/verify", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let finished = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            if let Ok(message) = events.recv().await {
+                if let EventPayload::LibraryVerify { status, .. } = &message.payload {
+                    if status == "completed" {
+                        return message.payload;
+                    }
+                }
+            }
+        }
+    })
+    .await
+    .expect("verification should complete");
+    assert!(matches!(
+        finished,
+        EventPayload::LibraryVerify {
+            total: 2,
+            failed: 1,
+            ..
+        }
+    ));
+
+    let (status, body) = get_json(&state, "/api/library/tracks/corrupt").await;
+    assert_eq!(status, StatusCode::OK);
+    let entries = body["data"].as_array().expect("list of corrupt tracks");
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["path"], json!(damaged));
+    assert_eq!(entries[0]["integrity"], json!("corrupt"));
+    assert_eq!(entries[0]["track"]["title"], json!("Damaged"));
+}
+
+#[tokio::test]
+#[serial]
+async fn artist_image_supports_conditional_requests() {
+    let env = RouterTestEnv::new();
+    env.create_tagged_track("song.wav", "Song");
+    fs::write(
+        env.music_dir.join("artist.png"),
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A],
+    )
+    .unwrap();
+    let (state, _) = env.state();
+
+    let request = |etag: Option<&str>| {
+        let mut builder = Request::get("/api/library/artists/Artist%20feat.%20Guest/image");
+        if let Some(etag) = etag {
+            builder = builder.header("if-none-match", etag);
+        }
+        builder.body(Body::empty()).expect("valid request")
+    };
+
+    let response = create_router(state.clone())
+        .oneshot(request(None))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()["content-type"], "image/png");
+    let etag = response.headers()["etag"].to_str().unwrap().to_string();
+
+    let response = create_router(state.clone())
+        .oneshot(request(Some(&etag)))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let (status, _) = get_json(&state, "/api/library/artists/Nobody/image").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn deleted_tracks_can_be_restored_with_their_identifier() {
+    let env = RouterTestEnv::new();
+    let path = env.create_tagged_track("keep.wav", "Keep Me");
+    let (state, _) = env.state();
+    let track_id = state
+        .library
+        .get_track_by_path(Path::new(&path))
+        .expect("track should be scanned")
+        .id;
+    let sequence = state.library.change_sequence();
+    let mut events = state.event_bus.subscribe();
+
+    let request = Request::delete(format!("/api/library/tracks/{}", track_id))
+        .body(Body::empty())
+        .unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!Path::new(&path).exists());
+    assert!(!state.library.track_exists(&track_id));
+
+    let event = serde_json::to_value(events.try_recv().unwrap().payload).unwrap();
+    assert_eq!(event["type"], json!("library_updated"));
+    assert_eq!(event["sequence"], json!(sequence + 1));
+    assert_eq!(
+        (&event["added"], &event["removed"], &event["updated"]),
+        (&json!(0), &json!(1), &json!(0))
+    );
+    assert_eq!(event["track_ids"], json!([track_id]));
+
+    let (status, body) = post_json(
+        &state,
+        &format!("/api/library/tracks/{}/restore", track_id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["id"], json!(track_id));
+    assert_eq!(body["data"]["title"], json!("Keep Me"));
+    assert!(Path::new(&path).exists());
+    assert!(state.library.track_exists(&track_id));
+    let event = serde_json::to_value(events.try_recv().unwrap().payload).unwrap();
+    assert_eq!(event["sequence"], json!(sequence + 2));
+    assert_eq!(event["added"], json!(1));
+
+    let (status, _) = post_json(
+        &state,
+        &format!("/api/library/tracks/{}/restore", track_id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn duplicate_groups_are_reported_and_resolved_into_the_keeper() {
+    let env = RouterTestEnv::new();
+    let sparse = env.create_tagged_track("sparse.wav", "Song");
+    let complete = env.music_dir.join("complete.wav");
+    write_silent_wav(&complete);
+    write_track_tags(
+        &complete,
+        &TrackTagUpdate {
+            title: Some("Song (Remastered)".into()),
+            artist: Some("Artist".into()),
+            album: Some("Album".into()),
+            year: Some(2001),
+            ..Default::default()
+        },
+    )
+    .expect("failed to tag audio file");
+    env.create_tagged_track("other.wav", "Other Song");
+    let (state, _) = env.state();
+    let sparse = state.library.get_track_by_path(Path::new(&sparse)).unwrap();
+    let complete = state.library.get_track_by_path(&complete).unwrap();
+
+    let playlist_id = state.playlist_manager.create_playlist("Mix".into(), None);
+    let mut playlist =
+        Arc::unwrap_or_clone(state.playlist_manager.get_playlist(&playlist_id).unwrap());
+    playlist.add_track(&sparse);
+    playlist.add_track(&complete);
+    playlist.mark_track_played(&sparse.id);
+    playlist.mark_track_played(&sparse.id);
+    playlist.mark_track_played(&complete.id);
+    state.playlist_manager.update_playlist(playlist);
+
+    let stats = &state.stats_store;
+    stats.merge_tag_stats(&sparse.metadata.file_path, Some(4), None);
+    stats.merge_tag_stats(&complete.metadata.file_path, Some(2), Some(5));
+    stats.record_play(&complete.metadata.file_path);
+    stats.record_play(&sparse.metadata.file_path);
+    stats.record_play(&sparse.metadata.file_path);
+    let last_played = stats.get(&sparse.metadata.file_path).unwrap().last_played;
+
+    let (status, body) = get_json(&state, "/api/library/duplicates").await;
+    assert_eq!(status, StatusCode::OK);
+    let groups = body["data"].as_array().unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0]["track_ids"].as_array().unwrap().len(), 2);
+    let group_id = groups[0]["id"].as_str().unwrap().to_string();
+
+    let (status, body) = get_json(
+        &state,
+        &format!("/api/library/duplicates/{}/report", group_id),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["recommended_keeper"], json!(complete.id));
+    let report = body["data"]["tracks"].as_array().unwrap();
+    assert_eq!(report.len(), 2);
+    assert!(report.iter().all(|track| track["format"] == json!("wav")
+        && track["sample_rate"] == json!(8000)
+        && track["bit_depth"] == json!(16)));
+
+    let (status, _) = post_json(
+        &state,
+        &format!("/api/library/duplicates/{}/resolve", group_id),
+        json!({ "keeper_id": "not-in-the-group" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = post_json(
+        &state,
+        &format!("/api/library/duplicates/{}/resolve", group_id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["keeper_id"], json!(complete.id));
+    assert_eq!(body["data"]["removed"][0]["track_id"], json!(sparse.id));
+    assert_eq!(body["data"]["removed"][0]["restorable"], json!(true));
+    assert_eq!(body["data"]["playlists_updated"], json!(1));
+    assert!(!state.library.track_exists(&sparse.id));
+    assert!(!sparse.metadata.file_path.exists());
+
+    let playlist = state.playlist_manager.get_playlist(&playlist_id).unwrap();
+    assert_eq!(playlist.entries.len(), 1);
+    assert_eq!(playlist.entries[0].track_id, complete.id);
+    assert_eq!(playlist.entries[0].play_count, 3);
+
+    let keeper_stats = stats.get(&complete.metadata.file_path).unwrap();
+    assert_eq!(keeper_stats.play_count, 8);
+    assert_eq!(keeper_stats.rating, Some(4));
+    assert_eq!(keeper_stats.last_played, last_played);
+
+    let (_, body) = get_json(&state, "/api/library/duplicates").await;
+    assert_eq!(body["data"], json!([]));
+    let (status, _) = get_json(
+        &state,
+        &format!("/api/library/duplicates/{}/report", group_id),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn csv_imports_report_how_name_conflicts_were_handled() {
+    let env = RouterTestEnv::new();
+    env.create_tagged_track("song.wav", "Song");
+    let (state, _) = env.state();
+    let existing = state
+        .playlist_manager
+        .create_playlist("Road Trip".into(), None);
+
+    let import = |on_conflict: &str| {
+        let request = Request::post(format!(
+            "/api/playlists/import/csv?name=Road%20Trip&on_conflict={}",
+            on_conflict
+        ))
+        .header("content-type", "text/csv")
+        .body(Body::from("Title,Artist\nSong,Artist\n"))
+        .unwrap();
+        let state = state.clone();
+        async move {
+            let response = create_router(state).oneshot(request).await.unwrap();
+            let status = response.status();
+            let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            (status, serde_json::from_slice::<Value>(&bytes).unwrap())
+        }
+    };
+
+    let (status, body) = import("fail").await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["data"]["id"], json!(existing));
+    assert_eq!(body["data"]["name"], json!("Road Trip"));
+
+    let (status, body) = import("merge").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["action"], json!("merged"));
+    assert_eq!(body["data"]["playlist_id"], json!(existing));
+
+    let (status, body) = import("rename").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["action"], json!("renamed"));
+    assert_eq!(body["data"]["playlist_name"], json!("Road Trip (imported)"));
+    assert_eq!(state.playlist_manager.get_playlists().len(), 2);
+}
+
+#[tokio::test]
+#[serial]
+async fn sidecars_override_tags_and_are_reported_by_scans() {
+    let env = RouterTestEnv::new();
+    let path = env.create_tagged_track("song.wav", "Tagged Title");
+    let broken = env.create_tagged_track("broken.wav", "Broken");
+    fs::write(format!("{}.hexendrum.json", broken), r#"{"year": "soon"}"#).unwrap();
+    let (state, _) = env.state();
+    let track_id = state
+        .library
+        .get_track_by_path(Path::new(&path))
+        .expect("track should be scanned")
+        .id;
+
+    let (status, body) = get_json(&state, "/api/library/scan/report").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["tracks"], json!(2));
+    let errors = body["data"]["sidecar_errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(
+        errors[0]["path"],
+        json!(format!("{}.hexendrum.json", broken))
+    );
+
+    let request = |uri: String, body: Value| {
+        Request::put(uri)
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let response = create_router(state.clone())
+        .oneshot(request(
+            format!("/api/library/tracks/{}/sidecar", track_id),
+            json!({"title": "Sidecar Title", "genre": "Ambient"}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["data"]["id"], json!(track_id));
+    assert_eq!(body["data"]["title"], json!("Sidecar Title"));
+    assert_eq!(body["data"]["artist"], json!("Artist"));
+    assert_eq!(body["data"]["metadata_source"], json!("sidecar"));
+    assert!(Path::new(&format!("{}.hexendrum.json", path)).exists());
+
+    let track = state.library.get_track(&track_id).unwrap();
+    assert_eq!(track.metadata.genre.as_deref(), Some("Ambient"));
+
+    let response = create_router(state.clone())
+        .oneshot(request(
+            "/api/library/tracks/unknown/sidecar".into(),
+            json!({"title": "Nope"}),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn playlist_cleanup_reports_entries_and_supports_dry_run() {
+    let env = RouterTestEnv::new();
+    let kept = env.create_tagged_track("kept.wav", "Kept");
+    let deleted = env.create_tagged_track("deleted.wav", "Deleted");
+    let (state, _) = env.state();
+    let kept = state.library.get_track_by_path(Path::new(&kept)).unwrap();
+    let deleted_track = state
+        .library
+        .get_track_by_path(Path::new(&deleted))
+        .unwrap();
+
+    let playlist_id = state.playlist_manager.create_playlist("Mix".into(), None);
+    let mut playlist =
+        Arc::unwrap_or_clone(state.playlist_manager.get_playlist(&playlist_id).unwrap());
+    playlist.add_track(&kept);
+    playlist.add_track(&deleted_track);
+    state.playlist_manager.update_playlist(playlist);
+
+    let request = Request::delete(format!("/api/library/tracks/{}", deleted_track.id))
+        .body(Body::empty())
+        .unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let (status, body) = post_json(&state, "/api/playlists/cleanup?dry_run=true", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["dry_run"], json!(true));
+    assert_eq!(body["data"]["removed"], json!(1));
+    let entry = &body["data"]["entries"][0];
+    assert_eq!(entry["playlist_id"], json!(playlist_id));
+    assert_eq!(entry["playlist_name"], json!("Mix"));
+    assert_eq!(entry["track_id"], json!(deleted_track.id));
+    assert_eq!(entry["position"], json!(1));
+    assert_eq!(entry["path"], json!(deleted));
+    assert_eq!(
+        state
+            .playlist_manager
+            .get_playlist(&playlist_id)
+            .unwrap()
+            .track_count(),
+        2
+    );
+
+    let (status, body) = post_json(
+        &state,
+        &format!("/api/playlists/{}/cleanup", playlist_id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["dry_run"], json!(false));
+    assert_eq!(
+        body["data"]["entries"][0]["track_id"],
+        json!(deleted_track.id)
+    );
+    let playlist = state.playlist_manager.get_playlist(&playlist_id).unwrap();
+    assert_eq!(playlist.entries.len(), 1);
+    assert_eq!(playlist.entries[0].track_id, kept.id);
+
+    let (status, _) = post_json(&state, "/api/playlists/missing/cleanup", json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn playlist_tracks_are_paginated() {
+    let env = RouterTestEnv::new();
+    let first = env.create_tagged_track("first.wav", "First");
+    let second = env.create_tagged_track("second.wav", "Second");
+    let (state, _) = env.state();
+    let first = state.library.get_track_by_path(Path::new(&first)).unwrap();
+    let second = state.library.get_track_by_path(Path::new(&second)).unwrap();
+
+    let playlist_id = state.playlist_manager.create_playlist("Mix".into(), None);
+    let mut playlist =
+        Arc::unwrap_or_clone(state.playlist_manager.get_playlist(&playlist_id).unwrap());
+    for _ in 0..3 {
+        playlist.add_track(&first);
+        playlist.add_track(&second);
+    }
+    state.playlist_manager.update_playlist(playlist);
+
+    let (status, body) = get_json(&state, "/api/playlists").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"][0]["track_count"], json!(6));
+
+    let (status, body) = get_json(
+        &state,
+        &format!("/api/playlists/{}/tracks?offset=3&limit=2", playlist_id),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["total"], json!(6));
+    assert_eq!(body["data"]["offset"], json!(3));
+    assert_eq!(body["data"]["limit"], json!(2));
+    let entries = body["data"]["entries"].as_array().unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0]["position"], json!(3));
+    assert_eq!(entries[0]["track"]["title"], json!("Second"));
+    assert_eq!(entries[1]["track_id"], json!(first.id));
+
+    let (_, body) = get_json(
+        &state,
+        &format!("/api/playlists/{}/tracks?offset=10", playlist_id),
+    )
+    .await;
+    assert!(body["data"]["entries"].as_array().unwrap().is_empty());
+
+    let (status, _) = get_json(&state, "/api/playlists/unknown/tracks").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn playlist_entries_can_be_noted_and_pinned() {
+    let env = RouterTestEnv::new();
+    let first = env.create_tagged_track("first.wav", "First");
+    let second = env.create_tagged_track("second.wav", "Second");
+    let (state, _) = env.state();
+    let first = state.library.get_track_by_path(Path::new(&first)).unwrap();
+    let second = state.library.get_track_by_path(Path::new(&second)).unwrap();
+
+    let playlist_id = state.playlist_manager.create_playlist("Mix".into(), None);
+    let mut playlist =
+        Arc::unwrap_or_clone(state.playlist_manager.get_playlist(&playlist_id).unwrap());
+    playlist.add_track(&first);
+    playlist.add_track(&second);
+    state.playlist_manager.update_playlist(playlist);
+
+    let uri = format!("/api/playlists/{}/tracks/{}", playlist_id, second.id);
+    let request = Request::patch(&uri)
+        .header("content-type", "application/json")
+        .body(Body::from(
+            json!({ "note": "Road trip", "pinned": true }).to_string(),
+        ))
+        .unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["data"]["position"], json!(1));
+    assert_eq!(body["data"]["note"], json!("Road trip"));
+    assert_eq!(body["data"]["pinned"], json!(true));
+
+    let (status, body) = get_json(&state, &format!("/api/playlists/{}/tracks", playlist_id)).await;
+    assert_eq!(status, StatusCode::OK);
+    let entries = body["data"]["entries"].as_array().unwrap();
+    assert_eq!(entries[0]["track_id"], json!(second.id));
+    assert_eq!(entries[0]["position"], json!(1));
+    assert_eq!(entries[1]["track_id"], json!(first.id));
+    assert_eq!(entries[1]["note"], Value::Null);
+    assert_eq!(entries[1]["pinned"], json!(false));
+
+    let saved = fs::read_to_string(env.playlist_dir.join(format!("{}.json", playlist_id)))
+        .expect("playlist should be saved");
+    assert!(saved.contains("Road trip"));
+
+    let request = Request::get(format!("/api/playlists/{}/m3u", playlist_id))
+        .body(Body::empty())
+        .unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()["content-type"],
+        "audio/x-mpegurl; charset=utf-8"
+    );
+    let m3u = String::from_utf8(
+        to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec(),
+    )
+    .unwrap();
+    assert!(m3u.contains("Artist - Second\n# Road trip\n"));
+
+    let request = Request::patch(format!(
+        "/api/playlists/{}/tracks/{}",
+        playlist_id, "missing"
+    ))
+    .header("content-type", "application/json")
+    .body(Body::from(json!({ "pinned": true }).to_string()))
+    .unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn playing_from_an_album_queues_the_tracks_around_the_chosen_one() {
+    let env = RouterTestEnv::new();
+    // File names run against the track numbers, so only the numbers give the order
+    let paths: Vec<String> = (1..=5)
+        .map(|number| {
+            let title = format!("Track {}", number);
+            let path = env.create_tagged_track(&format!("{}.wav", 6 - number), &title);
+            update_sidecar(
+                Path::new(&path),
+                SidecarMetadata {
+                    album: Some("Album".into()),
+                    track_number: Some(number),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            path
+        })
+        .collect();
+    let elsewhere = env.create_tagged_track("elsewhere.wav", "Elsewhere");
+    let (state, plays) = env.state();
+    let track_id = |path: &str| state.library.get_track_by_path(Path::new(path)).unwrap().id;
+    let album = json!({"type": "album", "id": album_identifier(Some("Artist"), "Album")});
+    let titles = |tracks: &Value| -> Vec<String> {
+        tracks
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|track| track["title"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let (status, body) = post_json(
+        &state,
+        "/api/audio/play-context",
+        json!({"context": album, "track_id": track_id(&paths[2])}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["current"]["title"], json!("Track 3"));
+    assert_eq!(titles(&body["data"]["previous"]), ["Track 1", "Track 2"]);
+    assert_eq!(titles(&body["data"]["next"]), ["Track 4", "Track 5"]);
+    assert_eq!(
+        (&body["data"]["position"], &body["data"]["length"]),
+        (&json!(2), &json!(5))
+    );
+    assert_eq!(
+        plays.lock().unwrap().last(),
+        Some(&PathBuf::from(&paths[2]))
+    );
+    assert_eq!(state.playback_queue.peek_next(), Some(track_id(&paths[3])));
+
+    let (status, body) = post_json(
+        &state,
+        "/api/audio/play-context",
+        json!({"context": album, "track_id": track_id(&elsewhere)}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().contains("is not in album"));
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/play-context",
+        json!({"context": {"type": "playlist", "id": "missing"}, "track_id": track_id(&elsewhere)}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Artists are listed album by album, tracks without an album last
+    let (status, body) = post_json(
+        &state,
+        "/api/audio/play-context",
+        json!({"context": {"type": "artist", "id": "Artist"}, "track_id": track_id(&elsewhere)}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        (&body["data"]["position"], &body["data"]["length"]),
+        (&json!(5), &json!(6))
+    );
+    assert_eq!(
+        titles(&body["data"]["previous"]),
+        ["Track 3", "Track 4", "Track 5"]
+    );
+    assert_eq!(body["data"]["next"], json!([]));
+
+    // With shuffle on the rest of the album follows the chosen track in random order
+    state.playback_queue.set_shuffle(true);
+    let (status, body) = post_json(
+        &state,
+        "/api/audio/play-context",
+        json!({"context": album, "track_id": track_id(&paths[2])}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["current"]["title"], json!("Track 3"));
+    assert_eq!(
+        (&body["data"]["position"], &body["data"]["length"]),
+        (&json!(0), &json!(5))
+    );
+    assert_eq!(body["data"]["previous"], json!([]));
+    let mut queued = state.playback_queue.track_ids();
+    queued.sort();
+    let mut album_tracks: Vec<String> = paths.iter().map(|path| track_id(path)).collect();
+    album_tracks.sort();
+    assert_eq!(queued, album_tracks);
+}
+
+#[tokio::test]
+#[serial]
+async fn playing_a_playlist_queues_it_in_its_play_order() {
+    let env = RouterTestEnv::new();
+    let paths: Vec<String> = ["Charlie", "Alpha", "Bravo"]
+        .iter()
+        .map(|title| env.create_tagged_track(&format!("{}.wav", title), title))
+        .collect();
+    let (state, plays) = env.state();
+
+    let playlist_id = state.playlist_manager.create_playlist("Chill".into(), None);
+    let mut playlist =
+        Arc::unwrap_or_clone(state.playlist_manager.get_playlist(&playlist_id).unwrap());
+    for path in &paths {
+        playlist.add_track(&state.library.get_track_by_path(Path::new(path)).unwrap());
+    }
+    state.playlist_manager.update_playlist(playlist);
+
+    let request = |body: Value| {
+        Request::patch(format!("/api/playlists/{}", playlist_id))
+            .header("content-type", "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    };
+    let queued_titles = |body: &Value| -> Vec<String> {
+        body["data"]["tracks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|track| track["title"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let play_uri = format!("/api/playlists/{}/play", playlist_id);
+
+    let (status, body) = post_json(&state, &play_uri, json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(queued_titles(&body), ["Charlie", "Alpha", "Bravo"]);
+    assert_eq!(body["data"]["current_index"], json!(0));
+    assert_eq!(body["data"]["repeat_mode"], json!("none"));
+    assert_eq!(
+        plays.lock().unwrap().last(),
+        Some(&PathBuf::from(&paths[0]))
+    );
+
+    let response = create_router(state.clone())
+        .oneshot(request(
+            json!({ "play_order": "title", "default_repeat": "all" }),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert_eq!(body["data"]["play_order"], json!("title"));
+    assert_eq!(body["data"]["default_repeat"], json!("all"));
+    assert_eq!(body["data"]["name"], json!("Chill"));
+
+    let (status, body) = post_json(&state, &play_uri, json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(queued_titles(&body), ["Alpha", "Bravo", "Charlie"]);
+    assert_eq!(body["data"]["repeat_mode"], json!("all"));
+    assert_eq!(
+        plays.lock().unwrap().last(),
+        Some(&PathBuf::from(&paths[1]))
+    );
+
+    // The stored order is untouched and persisted with the new settings
+    let (_, body) = get_json(&state, &format!("/api/playlists/{}/tracks", playlist_id)).await;
+    let stored: Vec<&str> = body["data"]["entries"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| entry["track"]["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(stored, ["Charlie", "Alpha", "Bravo"]);
+    let saved = fs::read_to_string(env.playlist_dir.join(format!("{}.json", playlist_id))).unwrap();
+    assert!(saved.contains("\"play_order\": \"title\""));
+
+    let response = create_router(state.clone())
+        .oneshot(request(json!({ "default_repeat": null, "name": " " })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let response = create_router(state.clone())
+        .oneshot(request(json!({ "default_repeat": null })))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let playlist = state.playlist_manager.get_playlist(&playlist_id).unwrap();
+    assert_eq!(playlist.default_repeat, None);
+    assert_eq!(playlist.play_order, PlayOrder::Title);
+
+    let (status, _) = post_json(&state, "/api/playlists/missing/play", json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn radio_mode_queues_similar_tracks_until_the_queue_is_replaced() {
+    let env = RouterTestEnv::new();
+    let tag = |name: &str, genre: &str, year: i32| {
+        let path = env.create_tagged_track(name, name);
+        write_track_tags(
+            Path::new(&path),
+            &TrackTagUpdate {
+                genre: Some(genre.into()),
+                year: Some(year),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        path
+    };
+    let seed = tag("seed.wav", "Rock", 1975);
+    let similar = tag("similar.wav", "Rock", 1978);
+    let played = tag("played.wav", "Rock", 1976);
+    tag("unrelated.wav", "Jazz", 2020);
+    let (state, _) = env.state();
+    let track_id = |path: &str| state.library.get_track_by_path(Path::new(path)).unwrap().id;
+
+    let (status, _) = post_json(&state, "/api/audio/play", json!({ "file_path": played })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut events = state.event_bus.subscribe();
+    let (status, body) = post_json(
+        &state,
+        "/api/audio/radio",
+        json!({ "track_id": track_id(&seed) }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let queued: Vec<&Value> = body["data"]["tracks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|track| &track["id"])
+        .collect();
+    assert_eq!(queued, vec![&json!(track_id(&similar))]);
+
+    let mut radio_events = Vec::new();
+    while let Ok(message) = events.try_recv() {
+        if let EventPayload::RadioMode {
+            enabled,
+            seed_track_id,
+        } = message.payload
+        {
+            radio_events.push((enabled, seed_track_id));
+        }
+    }
+    assert_eq!(radio_events, vec![(true, Some(track_id(&seed)))]);
+
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["radio_seed"], json!(track_id(&seed)));
+
+    let request = Request::delete("/api/queue").body(Body::empty()).unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["radio_seed"], Value::Null);
+
+    let (status, _) = post_json(&state, "/api/audio/radio", json!({ "track_id": "missing" })).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+/// `resume_position` of the track at `path`, as listed by the library
+async fn resume_position(state: &AppState, path: &str) -> Value {
+    let (_, body) = get_json(state, "/api/library/tracks").await;
+    body["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|track| track["path"] == json!(path))
+        .expect("track should be listed")["resume_position"]
+        .clone()
+}
+
+#[tokio::test]
+#[serial]
+async fn long_tracks_resume_where_they_were_left_off() {
+    let env = RouterTestEnv::new();
+    let book = env.create_long_track("book.wav", 40);
+    let song = env.create_tagged_track("song.wav", "Song");
+    let (state, _) = env.state();
+    let position = || state.audio_player.get_position().as_secs();
+
+    let (status, _) = post_json(&state, "/api/audio/play", json!({ "file_path": book })).await;
+    assert_eq!(status, StatusCode::OK);
+    state.audio_player.seek(Duration::from_secs(25)).unwrap();
+    let (status, _) = post_json(&state, "/api/audio/pause", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(resume_position(&state, &book).await, json!(25));
+
+    // Positions are persisted, and only for long tracks
+    let (status, _) = post_json(&state, "/api/audio/stop", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    let reloaded = StatsStore::new();
+    assert_eq!(reloaded.resume_position(Path::new(&book)), Some(25));
+
+    let (status, _) = post_json(&state, "/api/audio/play", json!({ "file_path": book })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(position(), 25);
+
+    // Replacing the track saves its position too
+    state.audio_player.seek(Duration::from_secs(12)).unwrap();
+    let (status, _) = post_json(&state, "/api/audio/play", json!({ "file_path": song })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(resume_position(&state, &book).await, json!(12));
+    assert_eq!(resume_position(&state, &song).await, Value::Null);
+
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/play",
+        json!({ "file_path": book, "from_start": true }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(position(), 0);
+
+    // The watcher keeps the position current, and finishing the track forgets it
+    state.audio_player.seek(Duration::from_secs(20)).unwrap();
+    state.resume_positions.tick(&state);
+    assert_eq!(resume_position(&state, &book).await, json!(20));
+    state.audio_player.seek(Duration::from_secs(35)).unwrap();
+    state.resume_positions.tick(&state);
+    assert_eq!(resume_position(&state, &book).await, Value::Null);
+    assert_eq!(StatsStore::new().resume_position(Path::new(&book)), None);
+}
+
+fn up_next_events(receiver: &mut Receiver<EventMessage>) -> Vec<(Option<String>, u32)> {
+    let mut announced = Vec::new();
+    while let Ok(message) = receiver.try_recv() {
+        if let EventPayload::UpNext {
+            track,
+            seconds_until,
+        } = message.payload
+        {
+            announced.push((track.title, seconds_until));
+        }
+    }
+    announced
+}
+
+#[tokio::test]
+#[serial]
+async fn next_track_is_announced_once_before_the_current_one_ends() {
+    let env = RouterTestEnv::new();
+    let first = env.create_tagged_track("first.wav", "First");
+    env.create_tagged_track("second.wav", "Second");
+    let (state, _) = env.state();
+    let track_id = |title: &str| {
+        state
+            .library
+            .get_tracks()
+            .into_iter()
+            .find(|track| track.metadata.title.as_deref() == Some(title))
+            .unwrap()
+            .id
+    };
+    let watcher = UpNextWatcher::new(10);
+    let mut events = state.event_bus.subscribe();
+
+    let (status, _) = post_json(&state, "/api/audio/play", json!({ "file_path": first })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Nothing queued after the current track
+    watcher.tick(&state);
+    assert!(up_next_events(&mut events).is_empty());
+
+    state
+        .playback_queue
+        .add_tracks(&[track_id("First"), track_id("Second")]);
+    state.playback_queue.next_track();
+
+    state.playback_queue.set_repeat_mode(RepeatMode::One);
+    watcher.tick(&state);
+    assert!(up_next_events(&mut events).is_empty());
+
+    // The silent test tracks are shorter than the lead time, so the announcement is due
+    state.playback_queue.set_repeat_mode(RepeatMode::None);
+    watcher.tick(&state);
+    watcher.tick(&state);
+    assert_eq!(
+        up_next_events(&mut events),
+        vec![(Some("Second".to_string()), 0)]
+    );
+
+    // A lead time of zero turns the announcements off
+    let disabled = UpNextWatcher::new(0);
+    disabled.tick(&state);
+    assert!(up_next_events(&mut events).is_empty());
+}
+
+/// Ids of the tracks titled `titles`, in that order
+fn track_ids(state: &AppState, titles: &[&str]) -> Vec<String> {
+    let tracks = state.library.get_tracks();
+    titles
+        .iter()
+        .map(|title| {
+            tracks
+                .iter()
+                .find(|track| track.metadata.title.as_deref() == Some(title))
+                .unwrap()
+                .id
+                .clone()
+        })
+        .collect()
+}
+
+#[tokio::test]
+#[serial]
+async fn selected_tracks_are_queued_in_one_call() {
+    let env = RouterTestEnv::new();
+    for title in ["One", "Two", "Three", "Four"] {
+        env.create_tagged_track(&format!("{}.wav", title), title);
+    }
+    let (state, _) = env.state();
+    let ids = track_ids(&state, &["One", "Two", "Three", "Four"]);
+    let mut events = state.event_bus.subscribe();
+
+    let (status, body) = post_json(
+        &state,
+        "/api/library/tracks/bulk",
+        json!({ "track_ids": [ids[0], ids[1]], "action": "enqueue" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["action"], json!("enqueue"));
+    assert_eq!(body["data"]["succeeded"], json!(2));
+    assert_eq!(body["data"]["results"][1]["track_id"], json!(ids[1]));
+    state.playback_queue.next_track();
+
+    // Queued after the current track, keeping the order of the selection
+    let (status, _) = post_json(
+        &state,
+        "/api/library/tracks/bulk",
+        json!({
+            "track_ids": [ids[2], ids[3]],
+            "action": "enqueue",
+            "params": { "next": true },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        state.playback_queue.track_ids(),
+        vec![
+            ids[0].clone(),
+            ids[2].clone(),
+            ids[3].clone(),
+            ids[1].clone()
+        ]
+    );
+    let mut queue_events = 0;
+    while let Ok(message) = events.try_recv() {
+        if matches!(message.payload, EventPayload::QueueUpdated { .. }) {
+            queue_events += 1;
+        }
+    }
+    assert_eq!(queue_events, 2, "one event per call");
+
+    // Nothing happens when one of the tracks is unknown
+    let (status, body) = post_json(
+        &state,
+        "/api/library/tracks/bulk",
+        json!({ "track_ids": [ids[0], "missing"], "action": "enqueue" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert!(body["error"].as_str().unwrap().contains("missing"));
+    assert_eq!(state.playback_queue.len(), 4);
+
+    for track_ids in [json!([]), json!(vec![ids[0].clone(); MAX_BULK_TRACKS + 1])] {
+        let (status, _) = post_json(
+            &state,
+            "/api/library/tracks/bulk",
+            json!({ "track_ids": track_ids, "action": "enqueue" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn selected_tracks_are_added_to_a_playlist_in_one_save() {
+    let env = RouterTestEnv::new();
+    for title in ["One", "Two"] {
+        env.create_tagged_track(&format!("{}.wav", title), title);
+    }
+    let (state, _) = env.state();
+    let ids = track_ids(&state, &["One", "Two"]);
+    let playlist_id = state.playlist_manager.create_playlist("Mix".into(), None);
+
+    let (status, body) = post_json(
+        &state,
+        "/api/library/tracks/bulk",
+        json!({
+            "track_ids": [ids[1], ids[0]],
+            "action": "add_to_playlist",
+            "params": { "playlist_id": playlist_id },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["succeeded"], json!(2));
+    assert_eq!(body["data"]["failed"], json!(0));
+
+    let playlist = state.playlist_manager.get_playlist(&playlist_id).unwrap();
+    let entries: Vec<&str> = playlist
+        .entries
+        .iter()
+        .map(|entry| entry.track_id.as_str())
+        .collect();
+    assert_eq!(entries, vec![ids[1].as_str(), ids[0].as_str()]);
+    let saved = state
+        .playlist_manager
+        .load_playlist(&env.playlist_dir.join(format!("{}.json", playlist_id)))
+        .expect("the playlist should be saved");
+    assert_eq!(saved.entries.len(), 2);
+
+    let (status, _) = post_json(
+        &state,
+        "/api/library/tracks/bulk",
+        json!({
+            "track_ids": [ids[0]],
+            "action": "add_to_playlist",
+            "params": { "playlist_id": "missing" },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn tracks_are_rated_and_marked_as_favorites() {
+    let env = RouterTestEnv::new();
+    for title in ["One", "Two"] {
+        env.create_tagged_track(&format!("{}.wav", title), title);
+    }
+    let (state, _) = env.state();
+    let ids = track_ids(&state, &["One", "Two"]);
+    let path = |id: &str| state.library.get_track(id).unwrap().metadata.file_path;
+
+    let (status, body) = put_json(
+        &state,
+        &format!("/api/library/tracks/{}/rating", ids[0]),
+        json!({ "rating": 4 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["rating"], json!(4));
+    assert_eq!(body["data"]["favorite"], json!(false));
+
+    let (status, body) = put_json(
+        &state,
+        &format!("/api/library/tracks/{}/favorite", ids[0]),
+        json!({ "favorite": true }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["favorite"], json!(true));
+    assert_eq!(body["data"]["rating"], json!(4));
+
+    for rating in [0, 6] {
+        let (status, _) = put_json(
+            &state,
+            &format!("/api/library/tracks/{}/rating", ids[0]),
+            json!({ "rating": rating }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+    let (status, _) = put_json(
+        &state,
+        "/api/library/tracks/missing/rating",
+        json!({ "rating": 3 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // In bulk, through the same stats
+    let (status, body) = post_json(
+        &state,
+        "/api/library/tracks/bulk",
+        json!({ "track_ids": ids, "action": "set_rating", "params": { "rating": 2 } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["succeeded"], json!(2));
+    let (status, _) = post_json(
+        &state,
+        "/api/library/tracks/bulk",
+        json!({ "track_ids": [ids[1]], "action": "favorite" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post_json(
+        &state,
+        "/api/library/tracks/bulk",
+        json!({ "track_ids": [ids[0]], "action": "favorite", "params": { "favorite": false } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let saved = StatsStore::new();
+    for (id, favorite) in [(&ids[0], false), (&ids[1], true)] {
+        let stats = saved.get(&path(id)).unwrap();
+        assert_eq!(stats.rating, Some(2));
+        assert_eq!(stats.favorite, favorite);
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn deletion_is_refused_when_forbidden() {
+    let mut env = RouterTestEnv::new();
+    env.delete_mode = DeleteMode::Forbid;
+    let path = env.create_tagged_track("precious.wav", "Precious");
+    let (state, _) = env.state();
+    let track_id = state.library.get_tracks()[0].id.clone();
+
+    let request = Request::delete(format!("/api/library/tracks/{}", track_id))
+        .body(Body::empty())
+        .unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let (status, _) = post_json(
+        &state,
+        "/api/library/tracks/bulk",
+        json!({ "track_ids": [track_id], "action": "remove" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(Path::new(&path).exists());
+    assert!(state.library.track_exists(&track_id));
+}
+
+#[tokio::test]
+#[serial]
+async fn read_only_directories_refuse_writes_but_still_scan() {
+    let mut env = RouterTestEnv::new();
+    env.read_only = ReadOnlyPaths {
+        everything: false,
+        roots: vec![env.music_dir.clone()],
+    };
+    let path = env.create_tagged_track("shared.wav", "Shared");
+    write_track_tags(
+        Path::new(&path),
+        &TrackTagUpdate {
+            album: Some("Shared Album".into()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let modified = fs::metadata(&path).unwrap().modified().unwrap();
+    let (state, _) = env.state();
+    let track_id = state
+        .library
+        .get_track_by_path(Path::new(&path))
+        .expect("read-only directories are still scanned")
+        .id;
+
+    let request = Request::delete(format!("/api/library/tracks/{}", track_id))
+        .body(Body::empty())
+        .unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let request = Request::put(format!("/api/library/tracks/{}/sidecar", track_id))
+        .header("content-type", "application/json")
+        .body(Body::from(json!({"title": "Renamed"}).to_string()))
+        .unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let body: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert!(body["error"].as_str().unwrap().contains("read-only"));
+
+    let (status, body) = post_json(
+        &state,
+        &format!(
+            "/api/library/albums/{}/edit",
+            album_identifier(Some("Artist"), "Shared Album")
+        ),
+        json!({"genre": "Jazz"}),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body["error"].as_str().unwrap().contains("read-only"));
+
+    assert!(state.library.track_exists(&track_id));
+    assert!(!Path::new(&format!("{}.hexendrum.json", path)).exists());
+    assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+}
+
+#[tokio::test]
+#[serial]
+async fn track_changes_are_rejected_while_a_scan_runs() {
+    let mut env = RouterTestEnv::new();
+    env.scan_conflict = ScanConflict::Reject;
+    env.scan_pause = Duration::from_millis(100);
+    env.create_tagged_track("first.wav", "First");
+    let path = env.create_tagged_track("second.wav", "Second");
+    let (state, _) = env.state();
+    let track_id = state
+        .library
+        .get_track_by_path(Path::new(&path))
+        .expect("track should be scanned")
+        .id;
+
+    let scan = {
+        let library = state.library.clone();
+        let directory = env.music_dir.clone();
+        // Read every file, so the scan takes as long as the scan pause makes it
+        std::thread::spawn(move || library.scan_directories_with(&[directory], true))
+    };
+    while !state.library.is_scanning() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+
+    let request = Request::delete(format!("/api/library/tracks/{}", track_id))
+        .body(Body::empty())
+        .unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(response.headers()["retry-after"], "5");
+    let body: Value =
+        serde_json::from_slice(&to_bytes(response.into_body(), usize::MAX).await.unwrap()).unwrap();
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains("scan is in progress"));
+    assert!(Path::new(&path).exists());
+
+    // Dry runs change nothing, so they are answered during the scan
+    let (status, _) = send(
+        &state,
+        "POST",
+        "/api/playlists/cleanup?dry_run=true",
+        "application/json",
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    scan.join().unwrap().expect("scan should succeed");
+    let track_id = state
+        .library
+        .get_track_by_path(Path::new(&path))
+        .unwrap()
+        .id;
+    let request = Request::delete(format!("/api/library/tracks/{}", track_id))
+        .body(Body::empty())
+        .unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!Path::new(&path).exists());
+}
+
+#[tokio::test]
+#[serial]
+async fn maintenance_runs_selected_tasks_while_playing() {
+    let env = RouterTestEnv::new();
+    let playing = env.create_tagged_track("playing.wav", "Playing");
+    let deleted = env.create_tagged_track("deleted.wav", "Deleted");
+    let (state, _) = env.state();
+    let mut events = state.event_bus.subscribe();
+
+    let (status, _) = post_json(&state, "/api/audio/play", json!({ "file_path": playing })).await;
+    assert_eq!(status, StatusCode::OK);
+    fs::remove_file(&deleted).unwrap();
+    env.create_tagged_track("added.wav", "Added");
+
+    let (status, body) = post_json(
+        &state,
+        "/api/maintenance",
+        json!({
+            "tasks": ["playlist_cleanup", "rescan"],
+            "directories": [env.music_dir],
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let tasks = body["data"]["tasks"].as_array().unwrap();
+    assert_eq!(tasks.len(), 2);
+    assert_eq!(tasks[0]["task"], json!("rescan"));
+    assert_eq!(tasks[0]["success"], json!(true));
+    assert_eq!(tasks[0]["message"], json!("1 added, 0 updated, 1 removed"));
+    assert_eq!(tasks[1]["task"], json!("playlist_cleanup"));
+    assert!(tasks[1]["duration_ms"].is_u64());
+
+    let mut progress = Vec::new();
+    while let Ok(message) = events.try_recv() {
+        if let EventPayload::Maintenance { status, task, .. } = message.payload {
+            progress.push((status, task));
+        }
+    }
+    assert_eq!(
+        progress,
+        vec![
+            ("started".to_string(), None),
+            ("running".to_string(), Some("rescan".to_string())),
+            ("running".to_string(), Some("playlist_cleanup".to_string())),
+            ("completed".to_string(), None),
+        ]
+    );
+    assert_eq!(state.audio_player.get_state(), AudioState::Playing);
+    assert_eq!(state.library.track_count(), 2);
+
+    let (status, _) = post_json(&state, "/api/maintenance", json!({ "tasks": ["defrag"] })).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+fn collect_refs(value: &Value, refs: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, nested) in map {
+                match (key.as_str(), nested) {
+                    ("$ref", Value::String(reference)) => refs.push(reference.clone()),
+                    _ => collect_refs(nested, refs),
+                }
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_refs(item, refs)),
+        _ => {}
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn openapi_document_has_no_dangling_refs() {
+    let env = RouterTestEnv::new();
+    let (state, _) = env.state();
+
+    let (status, doc) = get_json(&state, "/api-doc/openapi.json").await;
+    assert_eq!(status, StatusCode::OK);
+
+    let schemas = doc["components"]["schemas"]
+        .as_object()
+        .expect("document should have schemas");
+    let mut refs = Vec::new();
+    collect_refs(&doc, &mut refs);
+    assert!(!refs.is_empty());
+    for reference in &refs {
+        let name = reference
+            .strip_prefix("#/components/schemas/")
+            .unwrap_or_else(|| panic!("unexpected reference {}", reference));
+        assert!(
+            schemas.contains_key(name),
+            "dangling reference {}",
+            reference
+        );
+    }
+
+    // Every route is documented, with typed path parameters and a response body for
+    // JSON endpoints.
+    let paths = doc["paths"]
+        .as_object()
+        .expect("document should have paths");
+    for path in [
+        "/api/library/tracks/{id}",
+        "/api/library/albums/{id}/manual",
+        "/api/playlists/{id}/cleanup",
+        "/api/audio/repeat",
+        "/api/events/ws",
+    ] {
+        assert!(paths.contains_key(path), "{} is not documented", path);
+    }
+    for (path, operations) in paths {
+        for (method, operation) in operations.as_object().unwrap() {
+            let parameters = operation["parameters"]
+                .as_array()
+                .cloned()
+                .unwrap_or_default();
+            for segment in path.split('/').filter(|s| s.starts_with('{')) {
+                let name = segment.trim_matches(['{', '}']);
+                let parameter = parameters
+                    .iter()
+                    .find(|p| p["name"] == name && p["in"] == "path")
+                    .unwrap_or_else(|| panic!("{} {} lacks parameter {}", method, path, name));
+                assert!(
+                    matches!(
+                        parameter["schema"]["type"].as_str(),
+                        Some("string" | "integer")
+                    ),
+                    "{} {} has an untyped parameter {}",
+                    method,
+                    path,
+                    name
+                );
+                assert!(parameter.get("example").is_some());
+            }
+            assert!(
+                operation["responses"]
+                    .as_object()
+                    .is_some_and(|responses| !responses.is_empty()),
+                "{} {} has no responses",
+                method,
+                path
+            );
+        }
+    }
+
+    // Enumerations are modeled as such rather than free-form strings.
+    assert_eq!(schemas["RepeatMode"]["enum"], json!(["none", "one", "all"]));
+    assert_eq!(
+        schemas["AlbumExportFormat"]["enum"],
+        json!(["json", "yaml"])
+    );
+    assert_eq!(
+        schemas["RepeatModeRequest"]["properties"]["mode"]["$ref"],
+        "#/components/schemas/RepeatMode"
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn errors_are_returned_as_api_responses() {
+    let env = RouterTestEnv::new();
+    let (state, _) = env.state();
+
+    let (status, body) = post_json(&state, "/api/audio/repeat", json!({"mode": "sideways"})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["success"], false);
+    assert!(body["data"].is_null());
+    assert!(body["error"].as_str().unwrap().contains("sideways"));
+}
+
+async fn send(
+    state: &AppState,
+    method: &str,
+    uri: &str,
+    content_type: &str,
+    body: String,
+) -> (StatusCode, Value) {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("content-type", content_type)
+        .body(Body::from(body))
+        .expect("valid request");
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
+
+#[tokio::test]
+#[serial]
+async fn oversized_bodies_and_invalid_fields_are_refused() {
+    let env = RouterTestEnv::new();
+    env.create_tagged_track("song.wav", "Song");
+    let (state, _) = env.state();
+    let playlist = state.playlist_manager.create_playlist("Mix".into(), None);
+    let padding = "x".repeat(CONTROL_BODY_LIMIT);
+
+    for uri in ["/api/audio/volume", "/api/library/scan", "/api/audio/play"] {
+        let body = json!({"volume": 0.5, "directories": [], "padding": padding}).to_string();
+        let (status, body) = send(&state, "POST", uri, "application/json", body).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE, "{}", uri);
+        assert_eq!(body["success"], false, "{}", uri);
+        assert!(body["error"].is_string(), "{}", uri);
+    }
+
+    // Edits allow more than control endpoints, but not without bound
+    let rename = |name: String| json!({"name": name}).to_string();
+    let uri = format!("/api/playlists/{}", playlist);
+    let (status, _) = send(
+        &state,
+        "PATCH",
+        &uri,
+        "application/json",
+        rename(padding.clone()),
+    )
+    .await;
+    assert_eq!(
+        status,
+        StatusCode::BAD_REQUEST,
+        "names are capped, not the body"
+    );
+    let huge = rename("x".repeat(EDIT_BODY_LIMIT));
+    let (status, body) = send(&state, "PATCH", &uri, "application/json", huge).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["success"], false);
+    let name = "x".repeat(MAX_PLAYLIST_NAME_CHARS);
+    let (status, body) = send(
+        &state,
+        "PATCH",
+        &uri,
+        "application/json",
+        rename(name.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["name"], json!(name));
+
+    // Imports follow `max_import_bytes`
+    let mut csv = String::from("Title,Artist\n");
+    while csv.len() <= state.max_import_bytes {
+        csv.push_str("Song,Artist\n");
+    }
+    let (status, body) = send(&state, "POST", "/api/playlists/import/csv", "text/csv", csv).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["success"], false);
+    let uri = format!(
+        "/api/playlists/import/csv?name={}",
+        "x".repeat(MAX_PLAYLIST_NAME_CHARS + 1)
+    );
+    let (status, _) = send(&state, "POST", &uri, "text/csv", "Title\nSong\n".into()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let directories = vec![env.music_dir.clone(); MAX_DIRECTORIES + 1];
+    let (status, body) = post_json(
+        &state,
+        "/api/library/scan",
+        json!({ "directories": directories }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().contains("directories"));
+
+    // Out of range for f32, so it arrives as infinity
+    let (status, _) = post_json(&state, "/api/audio/volume", json!({"volume": 1e39})).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(state.audio_player.get_volume(), 0.7);
+    let (status, _) = post_json(&state, "/api/audio/volume", json!({"volume": 1.5})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(state.audio_player.get_volume(), 1.0);
+}
+
+#[tokio::test]
+#[serial]
+async fn playlists_can_be_filed_into_folders() {
+    let env = RouterTestEnv::new();
+    let (state, _) = env.state();
+    let intervals = state
+        .playlist_manager
+        .create_playlist("Intervals".into(), None);
+    state.playlist_manager.create_playlist("Loose".into(), None);
+
+    let uri = format!("/api/playlists/{}", intervals);
+    let folder = |folder: Value| json!({ "folder": folder }).to_string();
+    let (status, body) = send(
+        &state,
+        "PATCH",
+        &uri,
+        "application/json",
+        folder(json!(" Workout / Running/")),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["folder"], json!("Workout/Running"));
+
+    let (status, body) = get_json(&state, "/api/playlists/tree").await;
+    assert_eq!(status, StatusCode::OK);
+    let root = &body["data"];
+    assert_eq!(root["playlists"][0]["name"], json!("Loose"));
+    assert_eq!(root["folders"][0]["path"], json!("Workout"));
+    let running = &root["folders"][0]["folders"][0];
+    assert_eq!(running["path"], json!("Workout/Running"));
+    assert_eq!(running["playlists"][0]["id"], json!(intervals));
+
+    let (status, body) = post_json(
+        &state,
+        "/api/playlists/folders/rename",
+        json!({ "from": "Workout", "to": "Sport" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"], json!(1));
+    let (_, body) = get_json(&state, "/api/playlists").await;
+    let moved = body["data"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|playlist| playlist["id"] == json!(intervals))
+        .unwrap();
+    assert_eq!(moved["folder"], json!("Sport/Running"));
+
+    let (status, _) = post_json(
+        &state,
+        "/api/playlists/folders/rename",
+        json!({ "from": "Workout", "to": "Sport" }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(
+        &state,
+        "PATCH",
+        &uri,
+        "application/json",
+        folder(json!("x".repeat(MAX_PLAYLIST_NAME_CHARS * 3))),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, body) = send(
+        &state,
+        "PATCH",
+        &uri,
+        "application/json",
+        folder(Value::Null),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["folder"], Value::Null);
+    let (_, body) = get_json(&state, "/api/playlists/tree").await;
+    assert_eq!(body["data"]["folders"], json!([]));
+    assert_eq!(body["data"]["playlists"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+#[serial]
+async fn playlist_edits_based_on_a_stale_modified_at_conflict() {
+    let env = RouterTestEnv::new();
+    let path = env.create_tagged_track("song.wav", "Song");
+    let (state, _) = env.state();
+    let track_id = state
+        .library
+        .get_track_by_path(Path::new(&path))
+        .unwrap()
+        .id;
+    let playlist = state.playlist_manager.create_playlist("Mix".into(), None);
+    let uri = format!("/api/playlists/{}", playlist);
+
+    let (_, body) = get_json(&state, "/api/playlists").await;
+    let seen = body["data"][0]["modified_at"].clone();
+    let rename = |name: &str, modified_at: &Value| {
+        json!({ "name": name, "modified_at": modified_at }).to_string()
+    };
+
+    let (status, body) = send(
+        &state,
+        "PATCH",
+        &uri,
+        "application/json",
+        rename("Ours", &seen),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let ours = body["data"]["modified_at"].clone();
+    assert_ne!(ours, seen);
+
+    let (status, body) = send(
+        &state,
+        "PATCH",
+        &uri,
+        "application/json",
+        rename("Theirs", &seen),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .contains(ours.as_str().unwrap()));
+    let (status, body) = post_json(
+        &state,
+        "/api/library/tracks/bulk",
+        json!({
+            "track_ids": [track_id],
+            "action": "add_to_playlist",
+            "params": { "playlist_id": playlist, "modified_at": seen },
+        }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT, "{}", body);
+    let stored = state.playlist_manager.get_playlist(&playlist).unwrap();
+    assert_eq!(stored.name, "Ours");
+    assert!(stored.entries.is_empty());
+
+    // Without modified_at the last write wins, as before
+    let (status, _) = send(
+        &state,
+        "PATCH",
+        &uri,
+        "application/json",
+        json!({"name": "Theirs"}).to_string(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = send(
+        &state,
+        "PATCH",
+        &uri,
+        "application/json",
+        rename("Again", &ours),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+}
+
+type EventClient =
+    tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// Serve the router on an ephemeral port for tests that need a real connection.
+async fn serve(state: AppState) -> std::net::SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, create_router(state)).await });
+    address
+}
+
+async fn connect_events(address: std::net::SocketAddr, query: &str) -> EventClient {
+    let url = format!("ws://{}/api/events/ws{}", address, query);
+    let (socket, _) = tokio_tungstenite::connect_async(url)
+        .await
+        .expect("websocket should connect");
+    socket
+}
+
+/// Collect the types of the events received until the connection stays quiet.
+async fn received_types(socket: &mut EventClient) -> Vec<String> {
+    use futures_util::StreamExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let mut types = Vec::new();
+    while let Ok(Some(Ok(message))) =
+        tokio::time::timeout(Duration::from_millis(300), socket.next()).await
+    {
+        if let Message::Text(text) = message {
+            let event: Value = serde_json::from_str(&text).unwrap();
+            types.push(event["type"].as_str().unwrap().to_string());
+        }
+    }
+    types
+}
+
+#[tokio::test]
+#[serial]
+async fn event_stream_only_delivers_subscribed_types() {
+    let env = RouterTestEnv::new();
+    let (state, _) = env.state();
+    let address = serve(state.clone()).await;
+
+    let mut playback = connect_events(address, "?types=playback_state").await;
+    let mut library = connect_events(address, "?types=library_scan,library_updated").await;
+
+    // The initial snapshot is filtered too.
+    assert_eq!(received_types(&mut playback).await, ["playback_state"]);
+    assert_eq!(received_types(&mut library).await, ["library_updated"]);
+
+    state.event_bus.emit(EventPayload::volume_changed(0.5));
+    state
+        .event_bus
+        .emit(EventPayload::library_scan("started", None, None));
+    state.event_bus.emit(EventPayload::playback_state(
+        "playing", None, None, None, None,
+    ));
+    state.event_bus.emit(EventPayload::library_updated(3));
+
+    assert_eq!(received_types(&mut playback).await, ["playback_state"]);
+    assert_eq!(
+        received_types(&mut library).await,
+        ["library_scan", "library_updated"]
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn event_stream_rejects_unknown_types() {
+    use futures_util::StreamExt;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let env = RouterTestEnv::new();
+    let (state, _) = env.state();
+    let address = serve(state).await;
+
+    let mut socket = connect_events(address, "?types=playback_state,bogus").await;
+    match socket.next().await {
+        Some(Ok(Message::Close(Some(frame)))) => {
+            assert_eq!(frame.code, CloseCode::Policy);
+            assert!(frame.reason.contains("bogus"), "{}", frame.reason);
+        }
+        other => panic!("expected a close frame, got {:?}", other),
+    }
+}
+
+fn ctl_options(args: &[&str]) -> anyhow::Result<CtlOptions> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    CtlOptions::parse(&args, CtlTarget::default())
+}
+
+#[tokio::test]
+#[serial]
+async fn ctl_commands_control_a_running_backend() {
+    let env = RouterTestEnv::new();
+    env.create_tagged_track("song.wav", "Song");
+    let (state, plays) = env.state();
+    let address = serve(state.clone()).await;
+    let port = address.port().to_string();
+
+    let options = ctl_options(&["volume", "0.5", "--port", &port]).unwrap();
+    assert_eq!(options.command, CtlCommand::Volume(0.5));
+    assert_eq!(ctl::execute(&options).await.unwrap(), "Volume set to 0.5");
+
+    // Tracks can be played by library identifier.
+    let track = state.library.get_tracks()[0].clone();
+    let options = ctl_options(&["play", &track.id, "--port", &port]).unwrap();
+    ctl::execute(&options).await.unwrap();
+    assert_eq!(*plays.lock().unwrap(), [track.metadata.file_path]);
+
+    let options = ctl_options(&["status", "--json", "--port", &port]).unwrap();
+    let status: Value = serde_json::from_str(&ctl::execute(&options).await.unwrap()).unwrap();
+    assert_eq!(status["volume"], 0.5);
+    assert_eq!(status["state"], "Playing");
+
+    let options = ctl_options(&["status", "--port", &port]).unwrap();
+    let output = ctl::execute(&options).await.unwrap();
+    assert!(output.starts_with("Playing: "), "{}", output);
+    assert!(output.contains("volume 50%"), "{}", output);
+
+    assert!(ctl_options(&["volume", "loud"]).is_err());
+    assert!(ctl_options(&["rewind"]).is_err());
+}
+
+#[tokio::test]
+#[serial]
+async fn ctl_sends_the_configured_owner_token() {
+    let mut env = RouterTestEnv::new();
+    env.guest_policy = Some(Arc::new(
+        GuestPolicy::new("party", 2, 0.5).with_owner_token("owner"),
+    ));
+    let (state, _) = env.state();
+    let address = serve(state).await;
+
+    let mut config = ApiConfig {
+        port: address.port(),
+        ..ApiConfig::default()
+    };
+    let options =
+        CtlOptions::parse(&["stop".to_string()], CtlTarget::from_config(&config)).unwrap();
+    let error = ctl::execute(&options).await.unwrap_err();
+    assert!(error.to_string().contains("HTTP 401"), "{}", error);
+
+    config.auth_token = Some("owner".into());
+    let options =
+        CtlOptions::parse(&["stop".to_string()], CtlTarget::from_config(&config)).unwrap();
+    assert_eq!(options.target.auth_token.as_deref(), Some("owner"));
+    ctl::execute(&options).await.unwrap();
+
+    // Guests may not stop playback.
+    config.auth_token = Some("party".into());
+    let options =
+        CtlOptions::parse(&["stop".to_string()], CtlTarget::from_config(&config)).unwrap();
+    let error = ctl::execute(&options).await.unwrap_err();
+    assert!(error.to_string().contains("HTTP 403"), "{}", error);
+}
+
+#[tokio::test]
+#[serial]
+async fn event_log_is_readable_through_the_api() {
+    let env = RouterTestEnv::new();
+    let (mut state, _) = env.state();
+
+    let (status, _) = get_json(&state, "/api/events/log").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    state.event_log = Some(EventLog::start_with_limit(
+        &state.event_bus,
+        env.workspace.path().join("events.jsonl"),
+        1024 * 1024,
+        2,
+    ));
+    let (status, _) = post_json(&state, "/api/audio/volume", json!({ "volume": 0.3 })).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    let entries = loop {
+        let (status, body) = get_json(&state, "/api/events/log?since=1h&limit=10").await;
+        assert_eq!(status, StatusCode::OK);
+        let entries = body["data"]["entries"].as_array().unwrap().clone();
+        if !entries.is_empty() {
+            break entries;
+        }
+        assert!(
+            std::time::Instant::now() < deadline,
+            "event was never logged"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    };
+    assert_eq!(entries[0]["type"], "volume_changed");
+
+    let (status, body) = get_json(&state, "/api/events/log?since=last-tuesday").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().contains("invalid since"));
+}
+
+#[tokio::test]
+async fn ctl_reports_when_backend_is_not_running() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port().to_string();
+    drop(listener);
+
+    let options = ctl_options(&["pause", "--port", &port]).unwrap();
+    let error = ctl::execute(&options).await.unwrap_err();
+    assert!(
+        error.to_string().contains("backend not running"),
+        "{}",
+        error
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn shutdown_requests_notify_the_backend() {
+    let env = RouterTestEnv::new();
+    let (state, _) = env.state();
+    let address = serve(state.clone()).await;
+
+    let notified = state.shutdown.notified();
+    let target = CtlTarget {
+        address: CtlAddress::Tcp(address.port()),
+        auth_token: None,
+    };
+    ctl::request_shutdown(&target)
+        .await
+        .expect("shutdown request should succeed");
+    tokio::time::timeout(Duration::from_secs(5), notified)
+        .await
+        .expect("backend should be told to shut down");
+}
+
+#[tokio::test]
+async fn binding_a_taken_port_fails_with_a_readable_error() {
+    let taken = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = taken.local_addr().unwrap().port();
+
+    let error = hexendrum::api::bind_server(port).await.unwrap_err();
+    assert!(error.to_string().contains("already in use"), "{}", error);
+}
+
+#[cfg(unix)]
+#[tokio::test]
+#[serial]
+async fn api_is_served_on_a_unix_socket() {
+    use futures_util::StreamExt;
+    use hexendrum::api::serve_unix_socket;
+    use std::os::unix::fs::PermissionsExt;
+
+    let env = RouterTestEnv::new();
+    let (state, _) = env.state();
+
+    // A socket left behind by a crashed instance is replaced.
+    let socket = env.workspace.path().join("run").join("hexendrum.sock");
+    fs::create_dir(socket.parent().unwrap()).unwrap();
+    drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+
+    tokio::spawn(serve_unix_socket(state.clone(), socket.clone()));
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    while std::os::unix::net::UnixStream::connect(&socket).is_err() {
+        assert!(std::time::Instant::now() < deadline, "socket never came up");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let mode = fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let options = ctl_options(&["status", "--json", "--socket", socket.to_str().unwrap()]).unwrap();
+    let status: Value = serde_json::from_str(&ctl::execute(&options).await.unwrap()).unwrap();
+    assert_eq!(status["state"], "Stopped");
+
+    // WebSocket upgrades work over the socket as well.
+    let stream = tokio::net::UnixStream::connect(&socket).await.unwrap();
+    let (mut events, _) = tokio_tungstenite::client_async("ws://localhost/api/events/ws", stream)
+        .await
+        .expect("websocket should upgrade over the unix socket");
+    let first = events.next().await.unwrap().unwrap();
+    let first: Value = serde_json::from_str(first.to_text().unwrap()).unwrap();
+    assert_eq!(first["type"], "playback_state");
+
+    // Other files are never removed.
+    let regular = env.workspace.path().join("not-a-socket");
+    fs::write(&regular, "keep me").unwrap();
+    assert!(serve_unix_socket(state, regular.clone()).await.is_err());
+    assert_eq!(fs::read_to_string(&regular).unwrap(), "keep me");
+}
+
+#[tokio::test]
+#[serial]
+async fn library_endpoints_answer_while_the_cache_loads() {
+    let env = RouterTestEnv::new();
+    env.create_tagged_track("one.wav", "One");
+    env.create_tagged_track("two.wav", "Two");
+    let (mut state, _) = env.state();
+    let library = Arc::new(Library::deferred(&Paths::standard(), false));
+    state.library = library.clone();
+
+    let (status, health) = get_json(&state, "/api/health").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(health["loading"], json!(true));
+
+    let (status, tracks) = get_json(&state, "/api/library/tracks").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tracks["data"], json!([]));
+    assert_eq!(tracks["loading"], json!(true));
+
+    let strict = Request::get("/api/library/stats")
+        .header("prefer", "return=minimal, handling=strict")
+        .body(Body::empty())
+        .expect("valid request");
+    let response = create_router(state.clone())
+        .oneshot(strict)
+        .await
+        .expect("router should respond");
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    assert_eq!(library.load_in_background().await.unwrap(), 2);
+    let (status, tracks) = get_json(&state, "/api/library/tracks").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(tracks["data"].as_array().map(Vec::len), Some(2));
+    assert!(tracks.get("loading").is_none());
+    let (_, health) = get_json(&state, "/api/health").await;
+    assert!(health.get("loading").is_none());
+}
+
+async fn get_bytes(
+    state: &AppState,
+    uri: &str,
+    range: Option<&str>,
+) -> (StatusCode, axum::http::HeaderMap, Vec<u8>) {
+    let mut request = Request::get(uri);
+    if let Some(range) = range {
+        request = request.header("range", range);
+    }
+    let response = create_router(state.clone())
+        .oneshot(request.body(Body::empty()).expect("valid request"))
+        .await
+        .expect("router should respond");
+
+    let status = response.status();
+    let headers = response.headers().clone();
+    let bytes = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("body should be readable");
+    (status, headers, bytes.to_vec())
+}
+
+#[tokio::test]
+#[serial]
+async fn tracks_stream_with_byte_ranges_and_validate_transcoding() {
+    let env = RouterTestEnv::new();
+    let path = env.create_tagged_track("song.wav", "Song");
+    let (state, _) = env.state();
+    let track_id = state.library.get_tracks()[0].id.clone();
+    let uri = format!("/api/library/tracks/{}/stream", track_id);
+    let content = fs::read(&path).unwrap();
+
+    let (status, headers, body) = get_bytes(&state, &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(headers["content-type"], "audio/wav");
+    assert_eq!(headers["accept-ranges"], "bytes");
+    assert_eq!(body, content);
+
+    let (status, headers, body) = get_bytes(&state, &uri, Some("bytes=4-11")).await;
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        headers["content-range"],
+        format!("bytes 4-11/{}", content.len()).as_str()
+    );
+    assert_eq!(body, &content[4..12]);
+
+    let (_, _, body) = get_bytes(&state, &uri, Some("bytes=-4")).await;
+    assert_eq!(body, &content[content.len() - 4..]);
+
+    let range = format!("bytes={}-", content.len());
+    let (status, headers, _) = get_bytes(&state, &uri, Some(&range)).await;
+    assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(
+        headers["content-range"],
+        format!("bytes */{}", content.len()).as_str()
+    );
+
+    for query in [
+        "transcode=mp3",
+        "transcode=opus&bitrate=1000",
+        "transcode=opus&start=-5",
+        "bitrate=128",
+        "start=30",
+    ] {
+        let (status, body) = get_json(&state, &format!("{}?{}", uri, query)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", query);
+        assert_eq!(body["success"], json!(false));
+    }
+
+    let (status, _) = get_json(&state, "/api/library/tracks/unknown/stream").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Without ffmpeg (or the transcode feature) the file is sent as stored
+    if !transcoding_available() {
+        let (status, headers, body) = get_bytes(
+            &state,
+            &format!("{}?transcode=opus&bitrate=96&start=30", uri),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["x-transcode"], "unavailable");
+        assert_eq!(headers["accept-ranges"], "bytes");
+        assert_eq!(body, content);
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn slow_requests_time_out_while_other_routes_stay_responsive() {
+    let mut env = RouterTestEnv::new();
+    env.scan_pause = Duration::from_millis(400);
+    env.route_budgets.long = Duration::from_millis(100);
+    let (state, _) = env.state();
+    // Read by the rescan, which then sleeps
+    env.create_tagged_track("new.wav", "New");
+
+    let maintenance = post_json(
+        &state,
+        "/api/maintenance",
+        json!({ "tasks": ["rescan"], "directories": [env.music_dir.clone()] }),
+    );
+    let health = async {
+        let started = std::time::Instant::now();
+        let (status, _) = get_json(&state, "/api/health").await;
+        (status, started.elapsed())
+    };
+    let ((status, body), (health_status, health_time)) = tokio::join!(maintenance, health);
+
+    assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(body["success"], false);
+    assert_eq!(body["error"], "The request did not finish within 100ms");
+    assert_eq!(health_status, StatusCode::OK);
+    assert!(
+        health_time < Duration::from_millis(100),
+        "{:?}",
+        health_time
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn scans_run_as_background_jobs() {
+    let mut env = RouterTestEnv::new();
+    env.create_tagged_track("one.wav", "One");
+    env.scan_pause = Duration::from_millis(200);
+    let (state, _) = env.state();
+    env.create_tagged_track("two.wav", "Two");
+
+    let request = json!({ "directories": [env.music_dir.clone()] });
+    let (status, body) = post_json(&state, "/api/library/scan", request.clone()).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(body["data"]["job"], 1);
+    assert_eq!(body["data"]["running"], true);
+
+    let (status, _) = post_json(&state, "/api/library/scan", request).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let mut status = Value::Null;
+    for _ in 0..100 {
+        status = get_json(&state, "/api/library/scan/status").await.1["data"].clone();
+        if status["running"] == false {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(
+        status,
+        json!({"job": 1, "running": false, "tracks": 2, "error": null})
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn running_scans_are_cancelled_keeping_the_library() {
+    let mut env = RouterTestEnv::new();
+    env.create_tagged_track("one.wav", "One");
+    env.scan_pause = Duration::from_millis(20);
+    let (state, _) = env.state();
+    for index in 0..100 {
+        env.create_tagged_track(&format!("new-{:03}.wav", index), "New");
+    }
+    let mut events = state.event_bus.subscribe();
+
+    let (status, _) = post_json(&state, "/api/library/scan/cancel", json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let request = json!({ "directories": [env.music_dir.clone()] });
+    let (status, _) = post_json(&state, "/api/library/scan", request).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    while !state.library.is_scanning() {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let (status, _) = post_json(&state, "/api/library/scan/cancel", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let mut status = Value::Null;
+    for _ in 0..100 {
+        status = get_json(&state, "/api/library/scan/status").await.1["data"].clone();
+        if status["running"] == false {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    assert_eq!(status["running"], false);
+    assert!(!state.library.is_scanning());
+    let (_, job) = get_json(&state, "/api/jobs/1").await;
+    assert_eq!(job["data"]["state"], "cancelled");
+    assert_eq!(state.library.track_count(), 1);
+
+    let mut statuses = Vec::new();
+    while let Ok(message) = events.try_recv() {
+        if let EventPayload::LibraryScan { status, .. } = message.payload {
+            statuses.push(status);
+        }
+    }
+    assert_eq!(statuses.first().map(String::as_str), Some("started"));
+    assert_eq!(statuses.last().map(String::as_str), Some("cancelled"));
+}
+
+#[tokio::test]
+#[serial]
+async fn scans_cancelled_right_after_enqueueing_stop() {
+    let mut env = RouterTestEnv::new();
+    env.create_tagged_track("one.wav", "One");
+    env.scan_pause = Duration::from_millis(20);
+    let (state, _) = env.state();
+    for index in 0..50 {
+        env.create_tagged_track(&format!("new-{:03}.wav", index), "New");
+    }
+
+    let mut events = state.event_bus.subscribe();
+
+    // Cancelled both before the job runs and as the scan is handed to its thread,
+    // which is before the library lets it be cancelled
+    let request = json!({ "directories": [env.music_dir.clone()] });
+    for job in 1..=6 {
+        let (status, _) = post_json(&state, "/api/library/scan", request.clone()).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        if job % 2 == 0 {
+            loop {
+                let message = events.recv().await.unwrap();
+                if matches!(message.payload, EventPayload::LibraryScan { ref status, .. } if status == "started")
+                {
+                    break;
+                }
+            }
+        }
+        let (status, _) = post_json(&state, "/api/library/scan/cancel", json!({})).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let mut job_state = Value::Null;
+        for _ in 0..100 {
+            job_state =
+                get_json(&state, &format!("/api/jobs/{}", job)).await.1["data"]["state"].clone();
+            if job_state != "running" && job_state != "queued" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        assert_eq!(job_state, "cancelled");
+        assert!(!state.library.is_scanning());
+        assert_eq!(state.library.track_count(), 1);
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn jobs_are_listed_and_cancelled_only_when_they_can_be() {
+    let env = RouterTestEnv::new();
+    env.create_tagged_track("one.wav", "One");
+    let (state, _) = env.state();
+    let mut events = state.event_bus.subscribe();
+
+    let (status, body) = post_json(&state, "/api/library/waveforms", json!({})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["computed"], 1);
+    let request = json!({ "directories": [env.music_dir.clone()] });
+    let (status, _) = post_json(&state, "/api/library/scan", request).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    for _ in 0..100 {
+        if !get_json(&state, "/api/jobs/2").await.1["data"]["state"]
+            .as_str()
+            .is_some_and(|state| state == "running" || state == "queued")
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+
+    let (status, body) = get_json(&state, "/api/jobs").await;
+    assert_eq!(status, StatusCode::OK);
+    let jobs = body["data"].as_array().unwrap();
+    assert_eq!(
+        jobs.iter()
+            .map(|job| (job["id"].clone(), job["kind"].clone(), job["state"].clone()))
+            .collect::<Vec<_>>(),
+        vec![
+            (json!(2), json!("scan"), json!("completed")),
+            (json!(1), json!("waveforms"), json!("completed")),
+        ],
+        "newest first"
+    );
+    assert_eq!(jobs[0]["result"], json!({ "tracks": 1 }));
+    assert_eq!(jobs[0]["params"]["directories"][0], json!(env.music_dir));
+    assert_eq!(jobs[0]["progress"]["current"], 1);
+    assert_eq!(jobs[0]["progress"]["total"], 1);
+    assert_eq!(jobs[1]["progress"]["current"], 1);
+    assert_eq!(jobs[1]["progress"]["total"], 1);
+    let (_, body) = get_json(&state, "/api/jobs?kind=waveforms&state=completed").await;
+    assert_eq!(body["data"].as_array().unwrap().len(), 1);
+    let (status, _) = get_json(&state, "/api/jobs?state=sleeping").await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = post_json(&state, "/api/jobs/1/cancel", json!({})).await;
+    assert_eq!(
+        status,
+        StatusCode::CONFLICT,
+        "finished jobs stay as they are"
+    );
+    let (status, _) = get_json(&state, "/api/jobs/99").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = post_json(&state, "/api/jobs/99/cancel", json!({})).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let mut states = Vec::new();
+    while let Ok(message) = events.try_recv() {
+        if let EventPayload::JobProgress {
+            job_id: 2, state, ..
+        } = message.payload
+        {
+            states.push(state);
+        }
+    }
+    // Scans report their progress once the files are counted and after the last one
+    assert_eq!(
+        states,
+        vec!["queued", "running", "running", "running", "completed"]
+    );
+}
+
+#[tokio::test]
+#[serial]
+async fn uploaded_artwork_is_announced_and_served_with_its_new_etag() {
+    let env = RouterTestEnv::new();
+    let path = env.create_tagged_track("song.wav", "Song");
+    update_sidecar(
+        Path::new(&path),
+        SidecarMetadata {
+            album: Some("Album".into()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    let (state, _) = env.state();
+    let album_id = album_identifier(Some("Artist"), "Album");
+    let uri = format!("/api/library/albums/{}/artwork", album_id);
+    let mut events = state.event_bus.subscribe();
+
+    let upload = |bytes: Vec<u8>, uri: String| {
+        let state = state.clone();
+        async move {
+            let request = Request::put(uri)
+                .header("content-type", "image/jpeg")
+                .body(Body::from(bytes))
+                .expect("valid request");
+            let response = create_router(state).oneshot(request).await.unwrap();
+            let status = response.status();
+            let etag = response
+                .headers()
+                .get("etag")
+                .map(|value| value.to_str().unwrap().to_string());
+            let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+            (status, etag, body)
+        }
+    };
+    let artwork_etag = || async {
+        let request = Request::get(uri.as_str()).body(Body::empty()).unwrap();
+        let response = create_router(state.clone()).oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        response.headers()["etag"].to_str().unwrap().to_string()
+    };
+
+    let (status, etag, body) = upload(vec![0xFF, 0xD8, 0x01], uri.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(etag.as_deref(), Some(artwork_etag().await.as_str()));
+    let artwork_url = body["data"]["artwork_url"].as_str().unwrap().to_string();
+    assert!(artwork_url.starts_with(&format!("{}?v=", uri)));
+    let announced: Vec<_> = std::iter::from_fn(|| events.try_recv().ok())
+        .filter_map(|message| match message.payload {
+            EventPayload::AlbumArtworkUpdated {
+                album_id,
+                artwork_url,
+            } => Some((album_id, artwork_url)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(announced, [(album_id.clone(), Some(artwork_url.clone()))]);
+
+    // Replacing it changes the ETag served right away
+    let (status, replaced, body) = upload(vec![0xFF, 0xD8, 0x02], uri.clone()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_ne!(replaced, etag);
+    assert_eq!(replaced.as_deref(), Some(artwork_etag().await.as_str()));
+    assert_ne!(body["data"]["artwork_url"], artwork_url.as_str());
+
+    let (status, _, _) = upload(b"not an image".to_vec(), uri.clone()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _, _) = upload(
+        vec![0xFF, 0xD8, 0x03],
+        "/api/library/albums/unknown/artwork".into(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[serial]
+async fn guests_are_limited_to_browsing_queueing_skipping_and_bounded_volume() {
+    let mut env = RouterTestEnv::new();
+    env.guest_policy = Some(Arc::new(
+        GuestPolicy::new("party", 2, 0.5).with_owner_token("owner"),
+    ));
+    let current = env.create_tagged_track("current.wav", "Current");
+    env.create_tagged_track("next.wav", "Next");
+    env.create_tagged_track("later.wav", "Later");
+    let (state, plays) = env.state();
+    let ids = track_ids(&state, &["Next", "Later"]);
+
+    let call =
+        |token: Option<&'static str>, method: &'static str, uri: &'static str, body: Value| {
+            let state = state.clone();
+            async move {
+                let mut request = Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("content-type", "application/json");
+                if let Some(token) = token {
+                    request = request.header("authorization", format!("Bearer {}", token));
+                }
+                let request = request
+                    .body(Body::from(body.to_string()))
+                    .expect("valid request");
+                let response = create_router(state).oneshot(request).await.unwrap();
+                let status = response.status();
+                let retry_after = response
+                    .headers()
+                    .get("retry-after")
+                    .map(|value| value.to_str().unwrap().to_string());
+                let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                let body: Value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+                (status, body, retry_after)
+            }
+        };
+    let guest = |method, uri, body| call(Some("party"), method, uri, body);
+    let (status, _, _) = call(
+        Some("owner"),
+        "POST",
+        "/api/audio/play",
+        json!({ "file_path": current }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let mut events = state.event_bus.subscribe();
+
+    let (status, body, _) = guest("GET", "/api/library/tracks", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"].as_array().unwrap().len(), 3);
+
+    // Changes outside the guest profile are refused
+    let (status, body, _) = guest("DELETE", "/api/queue", Value::Null).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "Guests may not use DELETE /api/queue");
+    let (status, _, _) = guest("POST", "/api/library/scan", json!({})).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _, _) = guest("GET", "/api/health/doctor", Value::Null).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Enqueueing is rate-limited
+    let (status, body, _) = guest("POST", "/api/queue", json!({ "track_id": ids[0] })).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    let (status, _, _) = guest("POST", "/api/queue", json!({ "track_id": ids[1] })).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body, retry_after) =
+        guest("POST", "/api/queue", json!({ "track_id": ids[1] })).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["error"], "Guests may queue 2 tracks per minute");
+    assert!(retry_after.unwrap().parse::<u64>().unwrap() > 0);
+    assert_eq!(state.playback_queue.len(), 2);
+
+    // Up to the volume bound
+    let (status, body, _) = guest("POST", "/api/audio/volume", json!({ "volume": 0.9 })).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "Guests may set the volume up to 0.5");
+    let (status, _, _) = guest("POST", "/api/audio/volume", json!({ "volume": 0.4 })).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!((state.audio_player.get_volume() - 0.4).abs() < 1e-6);
+
+    let (status, body, _) = guest("POST", "/api/audio/next", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["title"], "Next");
+    assert_eq!(plays.lock().unwrap().len(), 2);
+
+    // Events they cause are attributed to guests
+    let sources: Vec<_> = std::iter::from_fn(|| events.try_recv().ok())
+        .map(|message| message.source)
+        .collect();
+    assert!(!sources.is_empty());
+    assert!(sources
+        .iter()
+        .all(|source| source.as_deref() == Some("guest")));
+
+    // Requests with the owner token are not restricted
+    let (status, _, _) = call(
+        Some("owner"),
+        "POST",
+        "/api/audio/volume",
+        json!({ "volume": 1.0 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(events.try_recv().unwrap().source, None);
+
+    // Requests with neither token are refused
+    let (status, _, _) = call(Some("stranger"), "GET", "/api/library/tracks", Value::Null).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let request = Request::delete("/api/queue").body(Body::empty()).unwrap();
+    let response = create_router(state.clone()).oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(response.headers()["www-authenticate"], "Bearer");
+    assert_eq!(state.playback_queue.len(), 2);
+    let (status, _, _) = call(Some("owner"), "DELETE", "/api/queue", Value::Null).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(state.playback_queue.len(), 0);
+}
+
+#[tokio::test]
+#[serial]
+async fn share_links_show_only_their_scope_until_they_expire() {
+    let mut env = RouterTestEnv::new();
+    env.share_allow_stream = true;
+    for (name, title) in [("a.wav", "A"), ("b.wav", "B")] {
+        let path = env.music_dir.join(name);
+        write_silent_wav(&path);
+        write_track_tags(
+            &path,
+            &TrackTagUpdate {
+                title: Some(title.into()),
+                artist: Some("Artist".into()),
+                album: Some("Album".into()),
+                ..Default::default()
+            },
+        )
+        .expect("failed to tag audio file");
+    }
+    let other = env.create_tagged_track("other.wav", "Other");
+    let (state, _) = env.state();
+    let ids = track_ids(&state, &["A", "B", "Other"]);
+    let share = |scope: Value| {
+        let state = state.clone();
+        async move {
+            let (status, body) = post_json(&state, "/api/share", json!({ "scope": scope })).await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            body["data"]["token"].as_str().unwrap().to_string()
+        }
+    };
+
+    let track = share(json!({ "kind": "track", "id": ids[0] })).await;
+    let (status, body) = get_json(&state, &format!("/api/share/{}", track)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body["data"]["scope"],
+        json!({ "kind": "track", "id": ids[0] })
+    );
+    let shared = &body["data"]["tracks"];
+    assert_eq!(shared.as_array().unwrap().len(), 1);
+    assert_eq!(shared[0]["title"], "A");
+    assert_eq!(shared[0]["album"], "Album");
+    assert!(shared[0].get("path").is_none());
+    let stream_url = shared[0]["stream_url"].as_str().unwrap().to_string();
+    let (status, _, bytes) = get_bytes(&state, &stream_url, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(bytes, fs::read(env.music_dir.join("a.wav")).unwrap());
+
+    // Other tracks cannot be reached through the link
+    let (status, _, _) = get_bytes(
+        &state,
+        &format!("/api/share/{}/tracks/{}/stream", track, ids[2]),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let album =
+        share(json!({ "kind": "album", "id": album_identifier(Some("Artist"), "Album") })).await;
+    let (_, body) = get_json(&state, &format!("/api/share/{}", album)).await;
+    let titles: Vec<_> = body["data"]["tracks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|track| track["title"].clone())
+        .collect();
+    assert_eq!(titles, [json!("A"), json!("B")]);
+
+    let now_playing = share(json!({ "kind": "now_playing" })).await;
+    let (_, body) = get_json(&state, &format!("/api/share/{}", now_playing)).await;
+    assert_eq!(body["data"]["tracks"], json!([]));
+    post_json(&state, "/api/audio/play", json!({ "file_path": other })).await;
+    let (_, body) = get_json(&state, &format!("/api/share/{}", now_playing)).await;
+    assert_eq!(body["data"]["tracks"][0]["title"], "Other");
+
+    let (status, _) = post_json(
+        &state,
+        "/api/share",
+        json!({ "scope": { "kind": "track", "id": "missing" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = post_json(
+        &state,
+        "/api/share",
+        json!({ "scope": { "kind": "now_playing" }, "expires_in_hours": 0 }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // Altered and expired links are refused
+    let altered = track.replacen("eyJ", "eyK", 1);
+    let (status, _) = get_json(&state, &format!("/api/share/{}", altered)).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let expired = state.shares.sign(&ShareClaims {
+        scope: ShareScope::Track { id: ids[0].clone() },
+        expires_at: chrono::Utc::now() - chrono::Duration::minutes(1),
+    });
+    let (status, body) = get_json(&state, &format!("/api/share/{}", expired)).await;
+    assert_eq!(status, StatusCode::GONE);
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("This share link expired"));
+    let (status, _, _) = get_bytes(
+        &state,
+        &format!("/api/share/{}/tracks/{}/stream", expired, ids[0]),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::GONE);
+
+    // With a guest token set, links open without any token, and guests may open
+    // links but not create or revoke them
+    let guarded = AppState {
+        guest_policy: Some(Arc::new(
+            GuestPolicy::new("party", 2, 0.5).with_owner_token("owner"),
+        )),
+        ..state.clone()
+    };
+    let (status, _) = get_json(&guarded, &format!("/api/share/{}", track)).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = post_json(
+        &guarded,
+        "/api/share",
+        json!({ "scope": { "kind": "now_playing" } }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let guest = |method: &'static str, uri: String| {
+        let state = guarded.clone();
+        async move {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("authorization", "Bearer party")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    json!({ "scope": { "kind": "now_playing" } }).to_string(),
+                ))
+                .expect("valid request");
+            create_router(state)
+                .oneshot(request)
+                .await
+                .unwrap()
+                .status()
+        }
+    };
+    assert_eq!(
+        guest("GET", format!("/api/share/{}", track)).await,
+        StatusCode::OK
+    );
+    assert_eq!(
+        guest("POST", "/api/share".to_string()).await,
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        guest("DELETE", "/api/share".to_string()).await,
+        StatusCode::FORBIDDEN
+    );
+
+    // Revoking replaces the secret, so no earlier link works
+    let (status, _) = send(
+        &state,
+        "DELETE",
+        "/api/share",
+        "application/json",
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    for token in [&track, &album, &now_playing] {
+        let (status, _) = get_json(&state, &format!("/api/share/{}", token)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
+
+#[tokio::test]
+#[serial]
+async fn shared_tracks_are_not_streamed_unless_allowed() {
+    let env = RouterTestEnv::new();
+    env.create_tagged_track("song.wav", "Song");
+    let (state, _) = env.state();
+    let id = track_ids(&state, &["Song"]).remove(0);
+
+    let (_, body) = post_json(
+        &state,
+        "/api/share",
+        json!({ "scope": { "kind": "track", "id": id }, "expires_in_hours": 1 }),
+    )
+    .await;
+    let token = body["data"]["token"].as_str().unwrap();
+    assert_eq!(body["data"]["url"], format!("/api/share/{}", token));
+
+    let (_, body) = get_json(&state, &format!("/api/share/{}", token)).await;
+    assert_eq!(body["data"]["tracks"][0]["stream_url"], Value::Null);
+    let (status, _, _) = get_bytes(
+        &state,
+        &format!("/api/share/{}/tracks/{}/stream", token, id),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+#[serial]
+async fn playlist_stats_break_the_tracks_down_by_artist_genre_and_decade() {
+    let env = RouterTestEnv::new();
+    let tag = |name: &str, seconds: u32, artist: &str, genre: &str, year: Option<i32>| {
+        let path = env.music_dir.join(name);
+        Wav::new().silent_frames(seconds * 8000).write(&path);
+        write_track_tags(
+            &path,
+            &TrackTagUpdate {
+                title: Some(name.into()),
+                artist: Some(artist.into()),
+                genre: Some(genre.into()),
+                year,
+                ..Default::default()
+            },
+        )
+        .expect("failed to tag audio file");
+        path
+    };
+    let paths = [
+        tag("one.wav", 60, "Zed", "Ambient", Some(1994)),
+        tag("two.wav", 120, "Zed", "Ambient", Some(1999)),
+        tag("three.wav", 30, "Adam", "Techno", Some(2003)),
+        tag("four.wav", 90, "Bea", "Techno", None),
+    ];
+    let (state, _) = env.state();
+
+    let playlist_id = state
+        .playlist_manager
+        .create_playlist("Mixtape".into(), None);
+    let mut playlist =
+        Arc::unwrap_or_clone(state.playlist_manager.get_playlist(&playlist_id).unwrap());
+    for path in &paths {
+        playlist.add_track(&state.library.get_track_by_path(path).unwrap());
+    }
+    playlist.entries[3].track_id = "gone".into();
+    let first_added = "2024-01-15T10:30:00Z".parse().unwrap();
+    let last_added = "2024-03-02T21:05:00Z".parse().unwrap();
+    playlist.entries[0].added_at = last_added;
+    playlist.entries[2].added_at = first_added;
+    playlist.entries[1].added_at = first_added;
+    playlist.entries[3].added_at = first_added;
+    state.playlist_manager.update_playlist(playlist);
+
+    let (status, body) = get_json(&state, &format!("/api/playlists/{}/stats", playlist_id)).await;
+    assert_eq!(status, StatusCode::OK);
+    let stats = &body["data"];
+    assert_eq!(stats["track_count"], 3);
+    assert_eq!(stats["missing_tracks"], 1);
+    assert_eq!(stats["total_duration"], 210);
+    assert_eq!(stats["average_duration"], 70);
+    assert_eq!(
+        stats["artists"],
+        json!([
+            { "name": "Zed", "track_count": 2 },
+            { "name": "Adam", "track_count": 1 },
+        ])
+    );
+    assert_eq!(
+        stats["genres"],
+        json!([
+            { "name": "Ambient", "track_count": 2 },
+            { "name": "Techno", "track_count": 1 },
+        ])
+    );
+    assert_eq!(
+        stats["decades"],
+        json!([
+            { "decade": 1990, "track_count": 2 },
+            { "decade": 2000, "track_count": 1 },
+        ])
+    );
+    assert_eq!(stats["first_added"], "2024-01-15T10:30:00Z");
+    assert_eq!(stats["last_added"], "2024-03-02T21:05:00Z");
+
+    let (status, _) = get_json(&state, "/api/playlists/unknown/stats").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+/// Media session stand-in keeping the title and playback status last shown
+#[derive(Clone, Default)]
+struct RecordingMediaSession {
+    shown: Arc<Mutex<(Option<String>, Option<MediaStatus>)>>,
+    metadata_updates: Arc<Mutex<usize>>,
+}
+
+impl MediaSessionSink for RecordingMediaSession {
+    fn set_metadata(&mut self, info: Option<&MediaInfo>) {
+        self.shown.lock().unwrap().0 = info.map(|info| info.title.clone());
+        *self.metadata_updates.lock().unwrap() += 1;
+    }
+
+    fn set_status(&mut self, status: MediaStatus) {
+        self.shown.lock().unwrap().1 = Some(status);
+    }
+}
+
+impl RecordingMediaSession {
+    /// Wait until the session shows `title` with playback `status`
+    async fn shows(&self, title: Option<&str>, status: &str) {
+        let current = || {
+            let shown = self.shown.lock().unwrap();
+            let status = match shown.1 {
+                Some(MediaStatus::Playing { .. }) => "playing",
+                Some(MediaStatus::Paused { .. }) => "paused",
+                Some(MediaStatus::Stopped) => "stopped",
+                None => "unset",
+            };
+            (shown.0.clone(), status)
+        };
+        for _ in 0..100 {
+            if current() == (title.map(String::from), status) {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("the media session shows {:?}", current());
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn the_media_session_follows_playback_and_controls_it() {
+    let env = RouterTestEnv::new();
+    let first = env.create_long_track("first.wav", 60);
+    let second = env.create_long_track("second.wav", 60);
+    let (state, plays) = env.state();
+    let ids = [&first, &second]
+        .map(|path| state.library.get_track_by_path(Path::new(path)).unwrap().id)
+        .to_vec();
+    state.playback_queue.load_context(ids, 0);
+
+    let session = RecordingMediaSession::default();
+    let (commands, received) = tokio::sync::mpsc::unbounded_channel();
+    let follower = MediaSession::follow(state.clone(), session.clone(), received);
+    session.shows(None, "stopped").await;
+
+    commands.send(MediaCommand::Toggle).unwrap();
+    session.shows(Some("first.wav"), "playing").await;
+    let revision = state.revision.current();
+
+    // Changes made through the API show too
+    post_json(&state, "/api/audio/pause", Value::Null).await;
+    session.shows(Some("first.wav"), "paused").await;
+    commands.send(MediaCommand::Play).unwrap();
+    session.shows(Some("first.wav"), "playing").await;
+    assert!(state.revision.current() > revision);
+
+    commands.send(MediaCommand::Next).unwrap();
+    session.shows(Some("second.wav"), "playing").await;
+    commands.send(MediaCommand::Previous).unwrap();
+    session.shows(Some("first.wav"), "playing").await;
+    commands
+        .send(MediaCommand::SetPosition(Duration::from_secs(20)))
+        .unwrap();
+    for _ in 0..100 {
+        if plays.lock().unwrap().len() == 4 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(state.audio_player.get_position() >= Duration::from_secs(20));
+    assert_eq!(
+        plays.lock().unwrap().clone(),
+        [&first, &second, &first, &first].map(PathBuf::from),
+        "seeking restarts the file at the position"
+    );
+
+    commands.send(MediaCommand::Stop).unwrap();
+    session.shows(None, "stopped").await;
+    assert_eq!(
+        *session.metadata_updates.lock().unwrap(),
+        4,
+        "the track is sent only when it changes"
+    );
+
+    drop(commands);
+    follower.await.expect("the session stops with its commands");
+}
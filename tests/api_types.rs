@@ -1,7 +1,11 @@
+use chrono::Utc;
 use hexendrum::api::{
     ApiResponsePlaylists, ApiResponseStats, ApiResponseString, ApiResponseTracks, ApiResponseUsize,
     AudioStatusResponse, LibraryStats, PlaylistResponse, TrackResponse,
 };
+use hexendrum::audio::PreviewStatus;
+use hexendrum::library::MetadataSource;
+use hexendrum::playlist::PlayOrder;
 
 #[test]
 fn api_response_structs_support_field_access() {
@@ -15,6 +19,15 @@ fn api_response_structs_support_field_access() {
         duration: Some(123),
         file_size: 42,
         path: "/tmp/song.mp3".into(),
+        last_modified: Utc::now(),
+        metadata_source: MetadataSource::File,
+        guessed: Default::default(),
+        musicbrainz: Default::default(),
+        replaygain: Default::default(),
+        technical: None,
+        resume_position: None,
+        rating: None,
+        favorite: false,
     };
 
     let playlist = PlaylistResponse {
@@ -24,6 +37,9 @@ fn api_response_structs_support_field_access() {
         track_count: 1,
         created_at: "2024-01-01T00:00:00Z".into(),
         modified_at: "2024-01-01T00:00:00Z".into(),
+        play_order: PlayOrder::Shuffle,
+        default_repeat: None,
+        folder: None,
     };
 
     let stats = LibraryStats {
@@ -37,6 +53,7 @@ fn api_response_structs_support_field_access() {
         success: true,
         data: Some(vec![track]),
         error: None,
+        loading: false,
     };
     assert!(response_tracks.success);
     assert_eq!(response_tracks.data.unwrap().len(), 1);
@@ -45,6 +62,7 @@ fn api_response_structs_support_field_access() {
         success: true,
         data: Some("ok".into()),
         error: None,
+        loading: false,
     };
     assert_eq!(response_string.data.as_deref(), Some("ok"));
 
@@ -52,6 +70,7 @@ fn api_response_structs_support_field_access() {
         success: true,
         data: Some(stats),
         error: None,
+        loading: false,
     };
     assert_eq!(response_stats.data.unwrap().total_tracks, 1);
 
@@ -59,6 +78,7 @@ fn api_response_structs_support_field_access() {
         success: true,
         data: Some(vec![playlist]),
         error: None,
+        loading: false,
     };
     assert_eq!(response_playlists.data.unwrap().len(), 1);
 
@@ -66,6 +86,7 @@ fn api_response_structs_support_field_access() {
         success: true,
         data: Some(10),
         error: None,
+        loading: false,
     };
     assert_eq!(response_usize.data, Some(10));
 }
@@ -75,7 +96,21 @@ fn audio_status_response_fields_are_accessible() {
     let status = AudioStatusResponse {
         state: "Stopped".into(),
         current_track: None,
+        next_track: None,
+        position_seconds: 0.0,
         volume: 0.5,
+        speed: 1.0,
+        repeat_mode: "none".into(),
+        shuffle: false,
+        radio_seed: None,
+        source_format: None,
+        playback_loop: None,
+        preview: PreviewStatus {
+            track_path: None,
+            position: 0.0,
+            volume: 0.5,
+        },
+        revision: 3,
     };
 
     assert_eq!(status.state, "Stopped");
@@ -0,0 +1,1125 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use hexendrum::audio::{
+    match_output_device, AudioBackend, AudioDeviceInfo, AudioPlayer, AudioState,
+    DeviceRecoveryPolicy, GainResolver, NullBackend, Precache, ReplayGain, ReplayGainMode,
+    StreamRequest, VolumeCurve, DEFAULT_PREVIEW_VOLUME, MAX_SPEED, MIN_SPEED,
+};
+use hexendrum::{EventBus, EventPayload};
+
+/// File on a preview sink, with the multipliers it was given
+type MockPreview = (PathBuf, Vec<f32>);
+
+/// Simulated output device whose presence can be toggled from the test.
+#[derive(Clone, Default)]
+struct MockDevice {
+    connected: Arc<AtomicBool>,
+    plays: Arc<Mutex<Vec<(PathBuf, Duration)>>>,
+    /// Output multipliers passed to `play` and `set_volume`
+    volumes: Arc<Mutex<Vec<f32>>>,
+    /// Stream parameters reported once opened
+    info: Arc<Mutex<Option<AudioDeviceInfo>>>,
+    preview: Arc<Mutex<Option<MockPreview>>>,
+    /// Set by the test to make the preview reach its end
+    preview_done: Arc<AtomicBool>,
+    /// Set by the test to make the current track reach its end
+    track_done: Arc<AtomicBool>,
+    /// Files queued to follow gaplessly
+    enqueued: Arc<Mutex<Vec<PathBuf>>>,
+    /// Set by the test when the device reports no active route
+    unrouted: Arc<AtomicBool>,
+    /// Output chosen with `select_device`, one of [`MOCK_OUTPUTS`]
+    selected: Arc<Mutex<Option<String>>>,
+    /// Set by the test when the "USB DAC" output is unplugged
+    usb_unplugged: Arc<AtomicBool>,
+    /// Whether the main sink is paused
+    paused: Arc<AtomicBool>,
+    /// Whether the main sink was stopped since the last `play`
+    stopped: Arc<AtomicBool>,
+    /// Speed factors passed to `set_speed`
+    speeds: Arc<Mutex<Vec<f32>>>,
+}
+
+const MOCK_OUTPUTS: [&str; 2] = ["mock", "USB DAC"];
+
+impl MockDevice {
+    fn connected() -> Self {
+        let device = Self::default();
+        device.connected.store(true, Ordering::SeqCst);
+        device
+    }
+
+    fn set_connected(&self, connected: bool) {
+        self.connected.store(connected, Ordering::SeqCst);
+    }
+
+    /// Whether the selected output is there
+    fn present(&self) -> bool {
+        let usb_selected = self.selected.lock().unwrap().as_deref() == Some(MOCK_OUTPUTS[1]);
+        self.connected.load(Ordering::SeqCst)
+            && !(usb_selected && self.usb_unplugged.load(Ordering::SeqCst))
+    }
+
+    fn plays(&self) -> Vec<(PathBuf, Duration)> {
+        self.plays.lock().unwrap().clone()
+    }
+
+    fn last_volume(&self) -> Option<f32> {
+        self.volumes.lock().unwrap().last().copied()
+    }
+
+    fn preview(&self) -> Option<MockPreview> {
+        self.preview.lock().unwrap().clone()
+    }
+}
+
+struct MockBackend {
+    device: MockDevice,
+    open: bool,
+    /// Sources left to play, the current one included
+    sources: usize,
+    /// Whether the last source played to its end
+    ended: bool,
+}
+
+impl MockBackend {
+    /// End the current source when the test says so
+    fn drain(&mut self) {
+        if self.sources > 0 && self.device.track_done.swap(false, Ordering::SeqCst) {
+            self.sources -= 1;
+            self.ended = self.sources == 0;
+        }
+    }
+}
+
+impl AudioBackend for MockBackend {
+    fn open(&mut self) -> Result<()> {
+        self.open = self.device.present();
+        if self.open {
+            Ok(())
+        } else {
+            Err(anyhow!("mock device missing"))
+        }
+    }
+
+    fn is_device_alive(&mut self) -> bool {
+        self.open && self.device.present()
+    }
+
+    fn has_active_route(&mut self) -> bool {
+        !self.device.unrouted.load(Ordering::SeqCst)
+    }
+
+    fn device_name(&self) -> Option<String> {
+        let selected = self.device.selected.lock().unwrap().clone();
+        Some(selected.unwrap_or_else(|| MOCK_OUTPUTS[0].into()))
+    }
+
+    fn output_devices(&self) -> Vec<String> {
+        MOCK_OUTPUTS.map(String::from).to_vec()
+    }
+
+    fn select_device(&mut self, name: Option<String>) {
+        let outputs = self.output_devices();
+        *self.device.selected.lock().unwrap() = name
+            .and_then(|name| match_output_device(&outputs, &name))
+            .map(|index| outputs[index].clone());
+    }
+
+    fn selected_device(&self) -> Option<String> {
+        self.device.selected.lock().unwrap().clone()
+    }
+
+    fn device_info(&self) -> Option<AudioDeviceInfo> {
+        self.device
+            .info
+            .lock()
+            .unwrap()
+            .clone()
+            .filter(|_| self.open)
+    }
+
+    fn play(&mut self, path: &Path, start_at: Duration, volume: f32) -> Result<()> {
+        if !self.is_device_alive() {
+            return Err(anyhow!("mock device missing"));
+        }
+        self.device.volumes.lock().unwrap().push(volume);
+        self.device.track_done.store(false, Ordering::SeqCst);
+        self.device.paused.store(false, Ordering::SeqCst);
+        self.device.stopped.store(false, Ordering::SeqCst);
+        self.sources = 1;
+        self.ended = false;
+        self.device
+            .plays
+            .lock()
+            .unwrap()
+            .push((path.to_path_buf(), start_at));
+        Ok(())
+    }
+
+    fn pause(&mut self) {
+        self.device.paused.store(true, Ordering::SeqCst);
+    }
+
+    fn resume(&mut self) {
+        self.device.paused.store(false, Ordering::SeqCst);
+    }
+
+    fn stop(&mut self) {
+        self.device.stopped.store(true, Ordering::SeqCst);
+        self.sources = 0;
+        self.ended = false;
+    }
+
+    fn finished(&mut self) -> bool {
+        self.drain();
+        self.ended
+    }
+
+    fn enqueue(&mut self, path: &Path) -> Result<()> {
+        if self.sources == 0 {
+            return Err(anyhow!("nothing is playing"));
+        }
+        if path.extension().is_some_and(|ext| ext == "txt") {
+            return Err(anyhow!("cannot decode {:?}", path));
+        }
+        self.device
+            .enqueued
+            .lock()
+            .unwrap()
+            .push(path.to_path_buf());
+        self.sources += 1;
+        Ok(())
+    }
+
+    fn source_count(&mut self) -> usize {
+        self.drain();
+        self.sources
+    }
+
+    fn set_volume(&mut self, volume: f32) {
+        self.device.volumes.lock().unwrap().push(volume);
+    }
+
+    fn set_speed(&mut self, speed: f32) {
+        self.device.speeds.lock().unwrap().push(speed);
+    }
+
+    fn preview_play(&mut self, path: &Path, volume: f32) -> Result<()> {
+        if !self.is_device_alive() {
+            return Err(anyhow!("mock device missing"));
+        }
+        self.device.preview_done.store(false, Ordering::SeqCst);
+        *self.device.preview.lock().unwrap() = Some((path.to_path_buf(), vec![volume]));
+        Ok(())
+    }
+
+    fn preview_stop(&mut self) {
+        *self.device.preview.lock().unwrap() = None;
+    }
+
+    fn set_preview_volume(&mut self, volume: f32) {
+        if let Some((_, volumes)) = self.device.preview.lock().unwrap().as_mut() {
+            volumes.push(volume);
+        }
+    }
+
+    fn preview_finished(&mut self) -> bool {
+        self.device.preview_done.load(Ordering::SeqCst)
+    }
+}
+
+fn mock_player(device: &MockDevice, policy: DeviceRecoveryPolicy) -> (AudioPlayer, Arc<EventBus>) {
+    mock_player_on(device, policy, Arc::new(EventBus::new(None)))
+}
+
+fn mock_player_on(
+    device: &MockDevice,
+    policy: DeviceRecoveryPolicy,
+    event_bus: Arc<EventBus>,
+) -> (AudioPlayer, Arc<EventBus>) {
+    let backend_device = device.clone();
+    let player = AudioPlayer::with_backend(
+        move || {
+            Ok(Box::new(MockBackend {
+                device: backend_device,
+                open: false,
+                sources: 0,
+                ended: false,
+            }) as Box<dyn AudioBackend>)
+        },
+        policy,
+        Some(event_bus.clone()),
+    )
+    .expect("player starts with a connected device");
+    (player, event_bus)
+}
+
+fn fast_policy(max_attempts: u32) -> DeviceRecoveryPolicy {
+    DeviceRecoveryPolicy {
+        check_interval: Duration::from_millis(10),
+        retry_delay: Duration::from_millis(10),
+        max_attempts,
+    }
+}
+
+fn wait_for_state(player: &AudioPlayer, expected: AudioState) {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while player.get_state() != expected {
+        assert!(
+            Instant::now() < deadline,
+            "timed out waiting for {:?}, player is {:?}",
+            expected,
+            player.get_state()
+        );
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+fn device_statuses(
+    receiver: &mut tokio::sync::broadcast::Receiver<hexendrum::EventMessage>,
+    expected: usize,
+) -> Vec<String> {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let mut statuses = Vec::new();
+    while statuses.len() < expected && Instant::now() < deadline {
+        match receiver.try_recv() {
+            Ok(message) => {
+                if let EventPayload::AudioDevice { status, .. } = message.payload {
+                    statuses.push(status);
+                }
+            }
+            Err(_) => std::thread::sleep(Duration::from_millis(5)),
+        }
+    }
+    statuses
+}
+
+#[test]
+fn lost_device_is_reacquired_and_playback_resumes_at_saved_position() {
+    let device = MockDevice::connected();
+    let (player, event_bus) = mock_player(&device, fast_policy(50));
+    let mut events = event_bus.subscribe();
+
+    player.play(Path::new("/music/song.flac")).unwrap();
+    std::thread::sleep(Duration::from_millis(60));
+
+    device.set_connected(false);
+    wait_for_state(&player, AudioState::DeviceLost);
+    assert_eq!(
+        player.get_current_track().as_deref(),
+        Some("/music/song.flac")
+    );
+
+    device.set_connected(true);
+    wait_for_state(&player, AudioState::Playing);
+
+    let plays = device.plays();
+    assert_eq!(plays.len(), 2);
+    assert_eq!(plays[1].0, PathBuf::from("/music/song.flac"));
+    assert!(plays[1].1 >= Duration::from_millis(50));
+
+    assert_eq!(device_statuses(&mut events, 2), vec!["lost", "recovered"]);
+}
+
+#[test]
+fn paused_track_stays_paused_after_recovery() {
+    let device = MockDevice::connected();
+    let (player, _event_bus) = mock_player(&device, fast_policy(50));
+
+    player.play(Path::new("/music/song.flac")).unwrap();
+    player.pause().unwrap();
+
+    device.set_connected(false);
+    wait_for_state(&player, AudioState::DeviceLost);
+    device.set_connected(true);
+    wait_for_state(&player, AudioState::Paused);
+}
+
+#[test]
+fn precached_tracks_play_from_their_copy() {
+    let temp = tempfile::TempDir::new().unwrap();
+    let track = temp.path().join("song.flac");
+    std::fs::write(&track, [0u8; 64]).unwrap();
+    let precache = Arc::new(Precache::new(temp.path().join("precache"), 1024, 1 << 20));
+    precache.fetch(&track).unwrap();
+
+    let device = MockDevice::connected();
+    let (player, _event_bus) = mock_player(&device, DeviceRecoveryPolicy::default());
+    player.set_precache(Some(precache.clone())).unwrap();
+    player.play(&track).unwrap();
+
+    assert_eq!(device.plays()[0].0, precache.cached(&track).unwrap());
+    assert_eq!(
+        player.get_current_track(),
+        Some(track.to_string_lossy().to_string())
+    );
+    assert_eq!(precache.stats().hits, 1);
+}
+
+fn finished_tracks(
+    receiver: &mut tokio::sync::broadcast::Receiver<hexendrum::EventMessage>,
+    wait: Duration,
+) -> Vec<(String, Option<String>)> {
+    let deadline = Instant::now() + wait;
+    let mut tracks = Vec::new();
+    while Instant::now() < deadline {
+        match receiver.try_recv() {
+            Ok(message) => {
+                if let EventPayload::PlaybackFinished {
+                    track_path,
+                    track_id,
+                } = message.payload
+                {
+                    tracks.push((track_path, track_id));
+                    break;
+                }
+            }
+            Err(_) => std::thread::sleep(Duration::from_millis(5)),
+        }
+    }
+    tracks
+}
+
+#[test]
+fn tracks_playing_to_their_end_are_announced_once_stopped() {
+    let device = MockDevice::connected();
+    let (player, event_bus) = mock_player(&device, fast_policy(50));
+    let mut events = event_bus.subscribe();
+    player
+        .set_track_resolver(Some(Arc::new(|path: &Path| {
+            (path == Path::new("/music/known.flac")).then(|| "track-1".to_string())
+        })))
+        .unwrap();
+
+    // Stopping by hand is not finishing
+    player.play(Path::new("/music/known.flac")).unwrap();
+    player.stop().unwrap();
+    device.track_done.store(true, Ordering::SeqCst);
+    assert!(finished_tracks(&mut events, Duration::from_millis(100)).is_empty());
+
+    player.play(Path::new("/music/known.flac")).unwrap();
+    device.track_done.store(true, Ordering::SeqCst);
+    assert_eq!(
+        finished_tracks(&mut events, Duration::from_secs(2)),
+        vec![("/music/known.flac".to_string(), Some("track-1".to_string()))]
+    );
+    assert_eq!(player.get_state(), AudioState::Stopped);
+    assert_eq!(player.get_current_track(), None);
+    assert_eq!(player.get_position(), Duration::ZERO);
+
+    // A paused track is not played to its end
+    player.play(Path::new("/music/other.flac")).unwrap();
+    player.pause().unwrap();
+    device.track_done.store(true, Ordering::SeqCst);
+    assert!(finished_tracks(&mut events, Duration::from_millis(100)).is_empty());
+    assert_eq!(player.get_state(), AudioState::Paused);
+    player.resume().unwrap();
+    assert_eq!(
+        finished_tracks(&mut events, Duration::from_secs(2)),
+        vec![("/music/other.flac".to_string(), None)]
+    );
+}
+
+#[test]
+fn queued_tracks_follow_without_a_gap() {
+    let device = MockDevice::connected();
+    let (player, event_bus) = mock_player(&device, fast_policy(50));
+    let mut events = event_bus.subscribe();
+
+    assert!(player.enqueue_next(Path::new("/music/b.flac")).is_err());
+    player.play(Path::new("/music/a.flac")).unwrap();
+    player.enqueue_next(Path::new("/music/b.flac")).unwrap();
+    assert!(player.enqueue_next(Path::new("/music/c.flac")).is_err());
+    assert_eq!(player.get_next_track().as_deref(), Some("/music/b.flac"));
+
+    device.track_done.store(true, Ordering::SeqCst);
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while player.get_current_track().as_deref() != Some("/music/b.flac") {
+        assert!(Instant::now() < deadline, "the queued track never started");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(player.get_state(), AudioState::Playing);
+    assert_eq!(player.get_next_track(), None);
+    assert_eq!(device.plays().len(), 1, "the output is not restarted");
+    assert_eq!(device.enqueued.lock().unwrap().len(), 1);
+
+    device.track_done.store(true, Ordering::SeqCst);
+    assert_eq!(
+        finished_tracks(&mut events, Duration::from_secs(2)),
+        vec![("/music/b.flac".to_string(), None)],
+        "only the last track is announced as finished"
+    );
+}
+
+#[test]
+fn undecodable_queued_tracks_are_reported_and_playback_stops_at_the_end() {
+    let device = MockDevice::connected();
+    let (player, event_bus) = mock_player(&device, fast_policy(50));
+    let mut events = event_bus.subscribe();
+
+    player.play(Path::new("/music/a.flac")).unwrap();
+    assert!(player.enqueue_next(Path::new("/music/notes.txt")).is_err());
+    assert_eq!(player.get_next_track(), None);
+    let error =
+        std::iter::from_fn(|| events.try_recv().ok()).find_map(|message| match message.payload {
+            EventPayload::PlaybackError { track_path, .. } => Some(track_path),
+            _ => None,
+        });
+    assert_eq!(error.as_deref(), Some("/music/notes.txt"));
+
+    // Queued tracks are dropped on stop, and queued again after seeking
+    player.enqueue_next(Path::new("/music/b.flac")).unwrap();
+    player.seek(Duration::from_secs(10)).unwrap();
+    assert_eq!(player.get_next_track().as_deref(), Some("/music/b.flac"));
+    assert_eq!(device.enqueued.lock().unwrap().len(), 2);
+    player.stop().unwrap();
+    assert_eq!(player.get_next_track(), None);
+
+    player.play(Path::new("/music/a.flac")).unwrap();
+    device.track_done.store(true, Ordering::SeqCst);
+    assert_eq!(
+        finished_tracks(&mut events, Duration::from_secs(2)),
+        vec![("/music/a.flac".to_string(), None)]
+    );
+    assert_eq!(player.get_state(), AudioState::Stopped);
+}
+
+#[test]
+fn playback_stops_when_device_never_returns() {
+    let device = MockDevice::connected();
+    let policy = DeviceRecoveryPolicy {
+        retry_delay: Duration::from_millis(40),
+        ..fast_policy(3)
+    };
+    let (player, event_bus) = mock_player(&device, policy);
+    let mut events = event_bus.subscribe();
+
+    player.play(Path::new("/music/song.flac")).unwrap();
+    device.set_connected(false);
+
+    wait_for_state(&player, AudioState::DeviceLost);
+    wait_for_state(&player, AudioState::Stopped);
+    assert!(player.get_current_track().is_none());
+    assert_eq!(device_statuses(&mut events, 2), vec!["lost", "unavailable"]);
+
+    device.set_connected(true);
+    player.play(Path::new("/music/other.flac")).unwrap();
+    assert_eq!(player.get_state(), AudioState::Playing);
+}
+
+#[test]
+fn playback_lost_for_too_long_returns_paused() {
+    let device = MockDevice::connected();
+    let (player, event_bus) = mock_player(&device, fast_policy(1000));
+    player
+        .set_auto_pause(Some(Duration::from_millis(50)))
+        .unwrap();
+    let mut events = event_bus.subscribe();
+
+    player.play(Path::new("/music/song.flac")).unwrap();
+    device.set_connected(false);
+    assert_eq!(device_statuses(&mut events, 2), vec!["lost", "auto_paused"]);
+
+    device.set_connected(true);
+    wait_for_state(&player, AudioState::Paused);
+    assert_eq!(
+        player.get_current_track().as_deref(),
+        Some("/music/song.flac")
+    );
+}
+
+#[test]
+fn playback_without_an_active_route_is_paused() {
+    let device = MockDevice::connected();
+    let (player, event_bus) = mock_player(&device, fast_policy(5));
+    let mut events = event_bus.subscribe();
+
+    // Disabled by default
+    player.play(Path::new("/music/song.flac")).unwrap();
+    device.unrouted.store(true, Ordering::SeqCst);
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(player.get_state(), AudioState::Playing);
+
+    player
+        .set_auto_pause(Some(Duration::from_millis(50)))
+        .unwrap();
+    wait_for_state(&player, AudioState::Paused);
+    assert_eq!(device_statuses(&mut events, 1), vec!["auto_paused"]);
+
+    // Resuming is up to the listener, and restarts the watchdog
+    device.unrouted.store(false, Ordering::SeqCst);
+    player.resume().unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    assert_eq!(player.get_state(), AudioState::Playing);
+}
+
+#[test]
+fn seeking_restarts_the_track_at_the_position() {
+    let device = MockDevice::connected();
+    let (player, _event_bus) = mock_player(&device, DeviceRecoveryPolicy::default());
+
+    assert!(player.seek(Duration::from_secs(5)).is_err());
+
+    player.play(Path::new("/music/mix.flac")).unwrap();
+    player.pause().unwrap();
+    player.seek(Duration::from_secs(90)).unwrap();
+
+    let plays = device.plays();
+    assert_eq!(plays.len(), 2);
+    assert_eq!(
+        plays[1],
+        (PathBuf::from("/music/mix.flac"), Duration::from_secs(90))
+    );
+    assert_eq!(player.get_state(), AudioState::Paused);
+}
+
+#[test]
+fn tracks_can_start_partway_through() {
+    let device = MockDevice::connected();
+    let (player, _event_bus) = mock_player(&device, DeviceRecoveryPolicy::default());
+
+    player
+        .play_from(Path::new("/music/audiobook.m4b"), Duration::from_secs(1520))
+        .unwrap();
+    assert_eq!(
+        device.plays(),
+        vec![(
+            PathBuf::from("/music/audiobook.m4b"),
+            Duration::from_secs(1520)
+        )]
+    );
+    assert!(player.get_position() >= Duration::from_secs(1520));
+    assert!(player.get_position() < Duration::from_secs(1521));
+
+    player.play(Path::new("/music/song.flac")).unwrap();
+    assert!(player.get_position() < Duration::from_secs(1));
+}
+
+#[test]
+fn volume_curves_keep_the_boundaries_and_are_monotonic() {
+    let curves = [
+        VolumeCurve::Linear,
+        VolumeCurve::Logarithmic,
+        VolumeCurve::CustomExponent(2.0),
+        VolumeCurve::CustomExponent(0.5),
+    ];
+    for curve in curves {
+        assert_eq!(curve.apply(0.0), 0.0, "{}", curve);
+        assert_eq!(curve.apply(1.0), 1.0, "{}", curve);
+        assert_eq!(curve.apply(-0.5), 0.0, "{}", curve);
+        assert_eq!(curve.apply(1.5), 1.0, "{}", curve);
+        assert_eq!(curve.apply(f32::NAN), 0.0, "{}", curve);
+
+        let mut previous = 0.0;
+        for step in 1..=100 {
+            let level = curve.apply(step as f32 / 100.0);
+            assert!(level > previous, "{} is not increasing at {}", curve, step);
+            previous = level;
+        }
+    }
+
+    assert_eq!(VolumeCurve::Linear.apply(0.25), 0.25);
+    assert_eq!(VolumeCurve::CustomExponent(2.0).apply(0.5), 0.25);
+    // -30 dB at half volume
+    assert!((VolumeCurve::Logarithmic.apply(0.5) - 0.031_622_78).abs() < 1e-6);
+}
+
+#[test]
+fn volume_curves_parse_from_config_strings() {
+    assert_eq!(
+        "linear".parse::<VolumeCurve>().unwrap(),
+        VolumeCurve::Linear
+    );
+    assert_eq!(
+        " Logarithmic ".parse::<VolumeCurve>().unwrap(),
+        VolumeCurve::Logarithmic
+    );
+    let custom: VolumeCurve = "custom_exponent(2.5)".parse().unwrap();
+    assert_eq!(custom, VolumeCurve::CustomExponent(2.5));
+    assert_eq!(custom.to_string().parse::<VolumeCurve>().unwrap(), custom);
+
+    for invalid in [
+        "loud",
+        "custom_exponent(0)",
+        "custom_exponent(-1)",
+        "custom_exponent(x)",
+    ] {
+        assert!(invalid.parse::<VolumeCurve>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn volume_curve_only_changes_the_output_level() {
+    let device = MockDevice::connected();
+    let (player, _) = mock_player(&device, fast_policy(1));
+
+    player.set_volume(0.5).unwrap();
+    assert_eq!(device.last_volume(), Some(0.5));
+
+    player
+        .set_volume_curve(VolumeCurve::CustomExponent(2.0))
+        .unwrap();
+    assert_eq!(device.last_volume(), Some(0.25));
+    assert_eq!(player.get_volume(), 0.5);
+
+    player.play(Path::new("/music/song.flac")).unwrap();
+    assert_eq!(device.last_volume(), Some(0.25));
+
+    player.set_volume(1.0).unwrap();
+    assert_eq!(device.last_volume(), Some(1.0));
+    assert_eq!(player.get_volume(), 1.0);
+}
+
+#[test]
+fn replaygain_multiplies_with_the_volume() {
+    let device = MockDevice::connected();
+    let (player, _) = mock_player(&device, fast_policy(50));
+    let resolver: GainResolver = Arc::new(|path: &Path| {
+        if path.ends_with("loud.flac") {
+            ReplayGain {
+                track_gain_db: Some(-6.0206),
+                album_gain_db: Some(-12.0412),
+                ..Default::default()
+            }
+        } else {
+            ReplayGain::default()
+        }
+    });
+    let last_volume = || device.last_volume().unwrap();
+    let close = |actual: f32, expected: f32| (actual - expected).abs() < 0.001;
+
+    player.set_volume(0.5).unwrap();
+    player
+        .set_replaygain(ReplayGainMode::Track, Some(resolver.clone()))
+        .unwrap();
+    player.play(Path::new("/music/loud.flac")).unwrap();
+    assert!(close(last_volume(), 0.25), "got {}", last_volume());
+
+    player.set_volume(1.0).unwrap();
+    assert!(close(last_volume(), 0.5), "got {}", last_volume());
+    assert_eq!(player.get_volume(), 1.0, "the volume itself is kept");
+
+    player
+        .set_replaygain(ReplayGainMode::Album, Some(resolver.clone()))
+        .unwrap();
+    assert!(
+        close(last_volume(), 0.25),
+        "the current track follows the mode"
+    );
+
+    player
+        .enqueue_next(Path::new("/music/untagged.flac"))
+        .unwrap();
+    device.track_done.store(true, Ordering::SeqCst);
+    let deadline = Instant::now() + Duration::from_secs(2);
+    while player.get_current_track().as_deref() != Some("/music/untagged.flac") {
+        assert!(Instant::now() < deadline, "the queued track never started");
+        std::thread::sleep(Duration::from_millis(5));
+    }
+    assert_eq!(last_volume(), 1.0, "untagged tracks play at unity gain");
+
+    player
+        .set_replaygain(ReplayGainMode::Off, Some(resolver))
+        .unwrap();
+    player.play(Path::new("/music/loud.flac")).unwrap();
+    assert_eq!(last_volume(), 1.0);
+}
+
+#[test]
+fn pause_resume_and_stop_fade_on_the_audio_thread() {
+    let device = MockDevice::connected();
+    let (player, _) = mock_player(&device, fast_policy(50));
+    let wait_for = |flag: &AtomicBool, what: &str| {
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !flag.load(Ordering::SeqCst) {
+            assert!(Instant::now() < deadline, "{} never happened", what);
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    };
+
+    player.set_volume(0.5).unwrap();
+    player.play(Path::new("/music/song.flac")).unwrap();
+    player.pause().unwrap();
+    assert!(device.paused.load(Ordering::SeqCst), "no fade by default");
+    player.resume().unwrap();
+    assert_eq!(device.last_volume(), Some(0.5));
+
+    player.set_pause_fade(Duration::from_millis(200)).unwrap();
+    let started = Instant::now();
+    player.pause().unwrap();
+    assert!(started.elapsed() < Duration::from_millis(100));
+    assert_eq!(player.get_state(), AudioState::Paused, "paused at once");
+    assert!(
+        !device.paused.load(Ordering::SeqCst),
+        "the sink fades first"
+    );
+    wait_for(&device.paused, "pausing the sink");
+    let fading: Vec<f32> = device.volumes.lock().unwrap().clone();
+    assert!(fading
+        .windows(2)
+        .rev()
+        .take(3)
+        .all(|pair| pair[1] <= pair[0]));
+    assert_eq!(device.last_volume(), Some(0.0));
+
+    player.resume().unwrap();
+    assert_eq!(player.get_state(), AudioState::Playing);
+    std::thread::sleep(Duration::from_millis(50));
+    player.pause().unwrap();
+    std::thread::sleep(Duration::from_millis(20));
+    player.resume().unwrap();
+    std::thread::sleep(Duration::from_millis(300));
+    assert!(
+        !device.paused.load(Ordering::SeqCst),
+        "the cancelled pause never reached the sink"
+    );
+    assert_eq!(device.last_volume(), Some(0.5), "back at full volume");
+
+    player.stop().unwrap();
+    assert_eq!(player.get_state(), AudioState::Stopped, "stopped at once");
+    assert_eq!(player.get_current_track(), None);
+    assert!(
+        !device.stopped.load(Ordering::SeqCst),
+        "the sink fades first"
+    );
+    wait_for(&device.stopped, "stopping the sink");
+
+    player.play(Path::new("/music/song.flac")).unwrap();
+    assert_eq!(device.last_volume(), Some(0.5), "new tracks start unfaded");
+}
+
+#[test]
+fn volume_fades_leave_the_volume_setting_alone() {
+    let device = MockDevice::connected();
+    let (player, _) = mock_player(&device, fast_policy(50));
+    player.set_volume(0.5).unwrap();
+    player.play(Path::new("/music/song.flac")).unwrap();
+
+    player.fade_volume(0.0, Duration::from_millis(100)).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    let faded: Vec<f32> = device.volumes.lock().unwrap().clone();
+    assert!(faded
+        .windows(2)
+        .rev()
+        .take(3)
+        .all(|pair| pair[1] <= pair[0]));
+    assert_eq!(device.last_volume(), Some(0.0));
+    assert_eq!(player.get_volume(), 0.5);
+    assert_eq!(player.get_state(), AudioState::Playing);
+
+    player.fade_volume(1.0, Duration::ZERO).unwrap();
+    assert_eq!(device.last_volume(), Some(0.5));
+    player.fade_volume(0.0, Duration::ZERO).unwrap();
+    player.play(Path::new("/music/other.flac")).unwrap();
+    assert_eq!(device.last_volume(), Some(0.5), "new tracks start unfaded");
+    assert!(player.fade_volume(f32::NAN, Duration::ZERO).is_err());
+}
+
+#[test]
+fn speed_changes_keep_the_position_in_track_time() {
+    let device = MockDevice::connected();
+    let (player, _) = mock_player(&device, fast_policy(50));
+    assert_eq!(player.get_speed(), 1.0);
+    player.play(Path::new("/music/song.flac")).unwrap();
+    player.seek(Duration::from_secs(10)).unwrap();
+
+    player.set_speed(2.0).unwrap();
+    std::thread::sleep(Duration::from_millis(200));
+    player.pause().unwrap();
+    let position = player.get_position();
+    assert!(
+        position >= Duration::from_millis(10_400) && position < Duration::from_millis(10_600),
+        "{:?}",
+        position
+    );
+    std::thread::sleep(Duration::from_millis(50));
+    assert_eq!(player.get_position(), position, "paused");
+    player.resume().unwrap();
+    assert_eq!(player.get_speed(), 2.0, "kept across pause and resume");
+
+    player.play(Path::new("/music/other.flac")).unwrap();
+    assert_eq!(player.get_speed(), 2.0, "kept for the next track");
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(player.get_position() >= Duration::from_millis(200));
+
+    player.set_speed(5.0).unwrap();
+    assert_eq!(player.get_speed(), MAX_SPEED);
+    player.set_speed(0.1).unwrap();
+    assert_eq!(player.get_speed(), MIN_SPEED);
+    assert!(player.set_speed(f32::NAN).is_err());
+    player.set_speed(1.0).unwrap();
+    assert_eq!(player.get_speed(), 1.0);
+    assert_eq!(
+        *device.speeds.lock().unwrap(),
+        vec![2.0, MAX_SPEED, MIN_SPEED, 1.0]
+    );
+}
+
+#[test]
+fn nan_volumes_are_refused() {
+    let device = MockDevice::connected();
+    let (player, _) = mock_player(&device, fast_policy(1));
+    player.set_volume(0.5).unwrap();
+
+    // `f32::clamp` passes NaN through, which used to reach the sink
+    assert!(player.set_volume(f32::NAN).is_err());
+    assert_eq!(player.get_volume(), 0.5);
+    assert_eq!(device.last_volume(), Some(0.5));
+
+    player.set_volume(f32::INFINITY).unwrap();
+    assert_eq!(player.get_volume(), 1.0);
+}
+
+fn preview_states(
+    receiver: &mut tokio::sync::broadcast::Receiver<hexendrum::EventMessage>,
+    expected: usize,
+) -> Vec<(String, Option<String>)> {
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let mut states = Vec::new();
+    while states.len() < expected && Instant::now() < deadline {
+        match receiver.try_recv() {
+            Ok(message) => {
+                if let EventPayload::AudioPreview {
+                    state, track_path, ..
+                } = message.payload
+                {
+                    states.push((state, track_path));
+                }
+            }
+            Err(_) => std::thread::sleep(Duration::from_millis(5)),
+        }
+    }
+    states
+}
+
+#[test]
+fn previews_mix_without_touching_main_playback() {
+    let device = MockDevice::connected();
+    let (player, bus) = mock_player(&device, fast_policy(1));
+    let mut events = bus.subscribe();
+
+    player.play(Path::new("/music/main.flac")).unwrap();
+    player.preview_play(Path::new("/music/cue.flac")).unwrap();
+    assert_eq!(player.get_state(), AudioState::Playing);
+    assert_eq!(
+        player.get_current_track().as_deref(),
+        Some("/music/main.flac")
+    );
+    assert_eq!(device.plays().len(), 1, "main playback is not restarted");
+    let status = player.preview_status();
+    assert_eq!(status.track_path.as_deref(), Some("/music/cue.flac"));
+    assert_eq!(status.volume, DEFAULT_PREVIEW_VOLUME);
+    assert_eq!(
+        device.preview(),
+        Some((
+            PathBuf::from("/music/cue.flac"),
+            vec![DEFAULT_PREVIEW_VOLUME]
+        ))
+    );
+
+    player
+        .set_volume_curve(VolumeCurve::CustomExponent(2.0))
+        .unwrap();
+    player.set_preview_volume(0.2).unwrap();
+    assert_eq!(player.preview_status().volume, 0.2);
+    let (_, volumes) = device.preview().unwrap();
+    assert!(
+        (volumes.last().unwrap() - 0.04).abs() < 1e-6,
+        "{:?}",
+        volumes
+    );
+    assert_eq!(player.get_volume(), 0.7);
+    assert!(player.set_preview_volume(f32::NAN).is_err());
+
+    // Stopping main playback leaves the preview playing, and the other way round
+    player.stop().unwrap();
+    assert_eq!(
+        player.preview_status().track_path.as_deref(),
+        Some("/music/cue.flac")
+    );
+    player.play(Path::new("/music/main.flac")).unwrap();
+    player.preview_stop().unwrap();
+    assert_eq!(player.preview_status().track_path, None);
+    assert_eq!(player.preview_status().position, 0.0);
+    assert_eq!(device.preview(), None);
+    assert_eq!(player.get_state(), AudioState::Playing);
+
+    assert_eq!(
+        preview_states(&mut events, 2),
+        vec![
+            ("playing".to_string(), Some("/music/cue.flac".to_string())),
+            ("stopped".to_string(), Some("/music/cue.flac".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn previews_end_on_their_own_and_with_the_device() {
+    let device = MockDevice::connected();
+    let (player, bus) = mock_player(&device, fast_policy(50));
+    let mut events = bus.subscribe();
+
+    player.preview_play(Path::new("/music/cue.flac")).unwrap();
+    device.preview_done.store(true, Ordering::SeqCst);
+    let states = preview_states(&mut events, 2);
+    assert_eq!(states[1].0, "ended");
+    assert_eq!(player.preview_status().track_path, None);
+    assert_eq!(player.get_state(), AudioState::Stopped);
+
+    player.play(Path::new("/music/main.flac")).unwrap();
+    player.preview_play(Path::new("/music/cue.flac")).unwrap();
+    device.set_connected(false);
+    wait_for_state(&player, AudioState::DeviceLost);
+    assert_eq!(player.preview_status().track_path, None);
+    assert!(player.preview_play(Path::new("/music/cue.flac")).is_err());
+
+    device.set_connected(true);
+    wait_for_state(&player, AudioState::Playing);
+    assert_eq!(player.preview_status().track_path, None);
+    player.preview_play(Path::new("/music/cue.flac")).unwrap();
+}
+
+#[test]
+fn output_devices_are_matched_by_name_ignoring_case() {
+    let names = vec![
+        "Speakers (USB Audio)".to_string(),
+        "HDMI Output".to_string(),
+        "usb".to_string(),
+    ];
+    assert_eq!(match_output_device(&names, "USB"), Some(2), "exact first");
+    assert_eq!(match_output_device(&names, "hdmi"), Some(1));
+    assert_eq!(match_output_device(&names, "speakers (usb"), Some(0));
+    assert_eq!(match_output_device(&names, "bluetooth"), None);
+    assert_eq!(match_output_device(&names, " "), None);
+}
+
+#[test]
+fn switching_devices_carries_on_with_the_current_track() {
+    let device = MockDevice::connected();
+    let (player, event_bus) = mock_player(&device, fast_policy(50));
+    let mut events = event_bus.subscribe();
+    assert_eq!(player.list_output_devices(), vec!["mock", "USB DAC"]);
+
+    player.play(Path::new("/music/song.flac")).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+    player.switch_device(Some("usb".into())).unwrap();
+
+    let plays = device.plays();
+    assert_eq!(plays.len(), 2, "the track restarts on the new device");
+    assert_eq!(plays[1].0, PathBuf::from("/music/song.flac"));
+    assert!(plays[1].1 >= Duration::from_millis(100));
+    assert_eq!(player.get_state(), AudioState::Playing);
+    assert_eq!(device_statuses(&mut events, 1), vec!["switched"]);
+    assert_eq!(device.selected.lock().unwrap().as_deref(), Some("USB DAC"));
+
+    player.pause().unwrap();
+    player.switch_device(None).unwrap();
+    assert_eq!(device.plays().len(), 3);
+    assert_eq!(
+        player.get_state(),
+        AudioState::Paused,
+        "paused tracks stay paused"
+    );
+    assert_eq!(*device.selected.lock().unwrap(), None);
+}
+
+#[test]
+fn playback_falls_back_to_the_default_device_when_the_chosen_one_disappears() {
+    let device = MockDevice::connected();
+    let (player, event_bus) = mock_player(&device, fast_policy(50));
+    let mut events = event_bus.subscribe();
+    player.switch_device(Some("usb".into())).unwrap();
+    player.play(Path::new("/music/song.flac")).unwrap();
+    std::thread::sleep(Duration::from_millis(100));
+
+    device.usb_unplugged.store(true, Ordering::SeqCst);
+    wait_for_state(&player, AudioState::DeviceLost);
+    wait_for_state(&player, AudioState::Playing);
+    assert_eq!(
+        device_statuses(&mut events, 4),
+        vec!["switched", "lost", "fallback", "recovered"]
+    );
+    assert_eq!(*device.selected.lock().unwrap(), None);
+    let plays = device.plays();
+    assert_eq!(plays.last().unwrap().0, PathBuf::from("/music/song.flac"));
+    assert!(
+        plays.last().unwrap().1 >= Duration::from_millis(100),
+        "the track carries on where it was"
+    );
+}
+
+#[test]
+fn null_backend_reports_the_requested_parameters() {
+    let request = StreamRequest {
+        sample_rate: Some(48000),
+        buffer_frames: Some(1024),
+    };
+    let event_bus = Arc::new(EventBus::new(None));
+    let mut events = event_bus.subscribe();
+    let player = AudioPlayer::with_backend(
+        move || Ok(Box::new(NullBackend::new(request)) as Box<dyn AudioBackend>),
+        DeviceRecoveryPolicy::default(),
+        Some(event_bus.clone()),
+    )
+    .expect("the null backend always opens");
+
+    let info = player
+        .device_info()
+        .expect("the null backend reports its parameters");
+    assert_eq!(
+        info,
+        AudioDeviceInfo {
+            device_name: Some("null".into()),
+            sample_rate: 48000,
+            buffer_frames: Some(1024),
+            channels: 2,
+            requested_sample_rate: Some(48000),
+            requested_buffer_frames: Some(1024),
+        }
+    );
+    assert!(info.differences().is_empty());
+    assert!(device_statuses(&mut events, 1).is_empty());
+}
+
+#[test]
+fn differing_device_parameters_are_reported_once() {
+    let device = MockDevice::connected();
+    let info = AudioDeviceInfo {
+        device_name: Some("mock".into()),
+        sample_rate: 48000,
+        buffer_frames: None,
+        channels: 2,
+        requested_sample_rate: Some(44100),
+        requested_buffer_frames: Some(4096),
+    };
+    *device.info.lock().unwrap() = Some(info.clone());
+    assert_eq!(
+        info.differences(),
+        vec![
+            "sample rate 48000 Hz instead of 44100 Hz",
+            "default buffer size instead of 4096 frames",
+        ]
+    );
+
+    let event_bus = Arc::new(EventBus::new(None));
+    let mut events = event_bus.subscribe();
+    let (player, _) = mock_player_on(&device, fast_policy(50), event_bus);
+    assert_eq!(player.device_info(), Some(info));
+
+    player.play(Path::new("/music/song.flac")).unwrap();
+    device.set_connected(false);
+    wait_for_state(&player, AudioState::DeviceLost);
+    device.set_connected(true);
+    wait_for_state(&player, AudioState::Playing);
+
+    assert_eq!(
+        device_statuses(&mut events, 4),
+        vec!["mismatch", "lost", "recovered"],
+        "reopening the device does not repeat the report"
+    );
+}
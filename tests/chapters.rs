@@ -0,0 +1,200 @@
+use hexendrum::library::{
+    parse_id3v2_chapters, parse_mp4_chapters, parse_vorbis_chapters, read_container_chapters,
+    Chapter,
+};
+use std::fs;
+use tempfile::TempDir;
+
+fn chapter(title: &str, start_seconds: f64) -> Chapter {
+    Chapter {
+        title: title.into(),
+        start_seconds,
+    }
+}
+
+fn synchsafe(size: usize) -> [u8; 4] {
+    let size = size as u32;
+    [
+        (size >> 21) as u8 & 0x7F,
+        (size >> 14) as u8 & 0x7F,
+        (size >> 7) as u8 & 0x7F,
+        size as u8 & 0x7F,
+    ]
+}
+
+fn id3_frame(version: u8, id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut frame = id.to_vec();
+    if version == 4 {
+        frame.extend_from_slice(&synchsafe(body.len()));
+    } else {
+        frame.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    }
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend_from_slice(body);
+    frame
+}
+
+fn chap_frame(version: u8, element: &str, start_ms: u32, title: Option<&[u8]>) -> Vec<u8> {
+    let mut body = element.as_bytes().to_vec();
+    body.push(0);
+    body.extend_from_slice(&start_ms.to_be_bytes());
+    body.extend_from_slice(&(start_ms + 1000).to_be_bytes());
+    body.extend_from_slice(&[0xFF; 8]);
+    if let Some(title) = title {
+        body.extend(id3_frame(version, b"TIT2", title));
+    }
+    id3_frame(version, b"CHAP", &body)
+}
+
+fn id3_tag(version: u8, frames: &[Vec<u8>]) -> Vec<u8> {
+    let body: Vec<u8> = frames.concat();
+    let mut tag = b"ID3".to_vec();
+    tag.extend_from_slice(&[version, 0, 0]);
+    // Trailing padding, as written by most taggers
+    tag.extend_from_slice(&synchsafe(body.len() + 16));
+    tag.extend(body);
+    tag.resize(tag.len() + 16, 0);
+    tag
+}
+
+fn mp4_box(name: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+    data.extend_from_slice(name);
+    data.extend_from_slice(payload);
+    data
+}
+
+fn chpl_box(chapters: &[(u64, &str)]) -> Vec<u8> {
+    let mut payload = vec![1, 0, 0, 0, 0, 0, 0, 0, chapters.len() as u8];
+    for (start, title) in chapters {
+        payload.extend_from_slice(&start.to_be_bytes());
+        payload.push(title.len() as u8);
+        payload.extend_from_slice(title.as_bytes());
+    }
+    mp4_box(b"chpl", &payload)
+}
+
+#[test]
+fn vorbis_comment_chapters_are_ordered_and_named() {
+    let fields = [
+        ("CHAPTER002", "00:31:04.500"),
+        ("CHAPTER002NAME", "Side B"),
+        ("chapter001", "00:00:00.000"),
+        ("CHAPTER001NAME", "Side A"),
+        ("CHAPTER003", "01:02:03"),
+        ("CHAPTER004", "not a time"),
+        ("CHAPTERS", "ignored"),
+        ("TITLE", "Mix"),
+    ];
+
+    assert_eq!(
+        parse_vorbis_chapters(fields),
+        vec![
+            chapter("Side A", 0.0),
+            chapter("Side B", 1864.5),
+            chapter("Chapter 3", 3723.0),
+        ]
+    );
+    assert!(parse_vorbis_chapters([("TITLE", "Mix")]).is_empty());
+}
+
+#[test]
+fn id3v23_chap_frames_are_parsed() {
+    let mut utf16_title = vec![1, 0xFF, 0xFE];
+    for unit in "Kapitel 2 – Zwei".encode_utf16() {
+        utf16_title.extend_from_slice(&unit.to_le_bytes());
+    }
+    let tag = id3_tag(
+        3,
+        &[
+            id3_frame(3, b"TIT2", b"\x03Audiobook"),
+            chap_frame(3, "ch2", 754_250, Some(&utf16_title)),
+            chap_frame(3, "ch1", 0, Some(b"\x00Prologue")),
+            chap_frame(3, "ch3", 1_500_000, None),
+        ],
+    );
+
+    assert_eq!(
+        parse_id3v2_chapters(&tag),
+        vec![
+            chapter("Prologue", 0.0),
+            chapter("Kapitel 2 – Zwei", 754.25),
+            chapter("Chapter 3", 1500.0),
+        ]
+    );
+}
+
+#[test]
+fn id3v24_uses_synchsafe_frame_sizes() {
+    // A title long enough that a plain size and a synchsafe size differ
+    let title = format!("\x03{}", "x".repeat(200));
+    let tag = id3_tag(4, &[chap_frame(4, "ch1", 60_000, Some(title.as_bytes()))]);
+
+    assert_eq!(
+        parse_id3v2_chapters(&tag),
+        vec![chapter(&"x".repeat(200), 60.0)]
+    );
+}
+
+#[test]
+fn mp4_nero_chapters_are_parsed() {
+    let udta = mp4_box(
+        b"udta",
+        &chpl_box(&[
+            (0, "Intro"),
+            (1_234_500_000, "Peak Time"),
+            (600_000_000, ""),
+        ]),
+    );
+    let moov = [mp4_box(b"mvhd", &[0; 100]), udta].concat();
+
+    assert_eq!(
+        parse_mp4_chapters(&moov),
+        vec![
+            chapter("Intro", 0.0),
+            chapter("Chapter 2", 60.0),
+            chapter("Peak Time", 123.45),
+        ]
+    );
+}
+
+#[test]
+fn container_chapters_are_read_from_files() {
+    let workspace = TempDir::new().unwrap();
+
+    let mp3 = workspace.path().join("book.mp3");
+    let mut bytes = id3_tag(3, &[chap_frame(3, "ch1", 2_000, Some(b"\x03One"))]);
+    bytes.extend_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+    fs::write(&mp3, bytes).unwrap();
+    assert_eq!(read_container_chapters(&mp3), vec![chapter("One", 2.0)]);
+
+    // `moov` after the media data, as written by most encoders
+    let m4a = workspace.path().join("mix.m4a");
+    let moov = mp4_box(b"moov", &mp4_box(b"udta", &chpl_box(&[(0, "Start")])));
+    let bytes = [
+        mp4_box(b"ftyp", b"M4A \0\0\0\0"),
+        mp4_box(b"mdat", &[0; 4096]),
+        moov,
+    ]
+    .concat();
+    fs::write(&m4a, bytes).unwrap();
+    assert_eq!(read_container_chapters(&m4a), vec![chapter("Start", 0.0)]);
+
+    // A 64-bit box size that would overflow the offset ends the walk
+    let crafted = workspace.path().join("crafted.m4a");
+    let mut huge = 1u32.to_be_bytes().to_vec();
+    huge.extend_from_slice(b"free");
+    huge.extend_from_slice(&(u64::MAX - 8).to_be_bytes());
+    let moov = mp4_box(b"moov", &mp4_box(b"udta", &chpl_box(&[(0, "Start")])));
+    fs::write(
+        &crafted,
+        [mp4_box(b"ftyp", b"M4A \0\0\0\0"), huge, moov].concat(),
+    )
+    .unwrap();
+    assert!(read_container_chapters(&crafted).is_empty());
+
+    let plain = workspace.path().join("plain.mp3");
+    fs::write(&plain, id3_tag(3, &[id3_frame(3, b"TIT2", b"\x03Song")])).unwrap();
+    assert!(read_container_chapters(&plain).is_empty());
+    assert!(read_container_chapters(&workspace.path().join("missing.mp3")).is_empty());
+}
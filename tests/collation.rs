@@ -0,0 +1,122 @@
+use hexendrum::library::{Collator, Section};
+
+const ARTISTS: &[&str] = &[
+    "Zola Jesus",
+    "Édith Piaf",
+    "坂本龍一",
+    "Ólafur Arnalds",
+    "ABBA",
+    "Åsa Jinder",
+    "Öresund",
+    "2Pac",
+    "edith",
+    "Ängel",
+    "Émilie Simon",
+    "Oasis",
+];
+
+fn sorted(locale: Option<&str>, names: &[&str]) -> Vec<String> {
+    let mut names: Vec<String> = names.iter().map(|name| name.to_string()).collect();
+    Collator::for_locale(locale).sort_by_name(&mut names, String::as_str);
+    names
+}
+
+fn section(label: &str, offset: usize, count: usize) -> Section {
+    Section {
+        label: label.into(),
+        offset,
+        count,
+    }
+}
+
+#[test]
+fn accented_names_sort_with_their_base_letter_in_the_root_order() {
+    let expected = vec![
+        "2Pac",
+        "ABBA",
+        "Ängel",
+        "Åsa Jinder",
+        "edith",
+        "Édith Piaf",
+        "Émilie Simon",
+        "Oasis",
+        "Ólafur Arnalds",
+        "Öresund",
+        "Zola Jesus",
+        "坂本龍一",
+    ];
+    assert_eq!(sorted(None, ARTISTS), expected);
+    assert_eq!(sorted(Some("en-US"), ARTISTS), expected);
+    // Unknown locales fall back to the root order
+    assert_eq!(sorted(Some("xx-YY"), ARTISTS), expected);
+}
+
+#[test]
+fn swedish_sorts_its_extra_letters_after_z() {
+    let expected = vec![
+        "2Pac",
+        "ABBA",
+        "edith",
+        "Édith Piaf",
+        "Émilie Simon",
+        "Oasis",
+        "Ólafur Arnalds",
+        "Zola Jesus",
+        "Åsa Jinder",
+        "Ängel",
+        "Öresund",
+        "坂本龍一",
+    ];
+    assert_eq!(sorted(Some("sv-SE"), ARTISTS), expected);
+    assert_eq!(sorted(Some("sv_SE"), ARTISTS), expected);
+
+    // Danish and Norwegian order the same letters differently
+    assert_eq!(
+        sorted(Some("da"), &["Øresund", "Åsa", "Æble", "Zink"]),
+        vec!["Zink", "Æble", "Øresund", "Åsa"]
+    );
+}
+
+#[test]
+fn spanish_sorts_n_with_tilde_between_n_and_o() {
+    let names = ["Oro", "Ñandú", "Nube", "Nación"];
+    assert_eq!(sorted(None, &names), vec!["Nación", "Ñandú", "Nube", "Oro"]);
+    assert_eq!(
+        sorted(Some("es"), &names),
+        vec!["Nación", "Nube", "Ñandú", "Oro"]
+    );
+}
+
+#[test]
+fn punctuation_separates_words_and_case_only_breaks_ties() {
+    assert_eq!(
+        sorted(None, &["The-Dream", "Thebes", "the dream", "The Dream"]),
+        vec!["The Dream", "The-Dream", "the dream", "Thebes"]
+    );
+}
+
+#[test]
+fn sorted_listings_are_split_into_letter_sections() {
+    let root = Collator::default();
+    let names = sorted(None, ARTISTS);
+    assert_eq!(
+        root.sections(names.iter().map(String::as_str)),
+        vec![
+            section("#", 0, 1),
+            section("A", 1, 3),
+            section("E", 4, 3),
+            section("O", 7, 3),
+            section("Z", 10, 1),
+            section("#", 11, 1),
+        ]
+    );
+
+    let swedish = Collator::for_locale(Some("sv"));
+    let names = sorted(Some("sv"), ARTISTS);
+    let labels: Vec<String> = swedish
+        .sections(names.iter().map(String::as_str))
+        .into_iter()
+        .map(|section| section.label)
+        .collect();
+    assert_eq!(labels, ["#", "A", "E", "O", "Z", "Å", "Ä", "Ö", "#"]);
+}
@@ -0,0 +1,151 @@
+//! Helpers shared by the integration tests. Each test binary uses only some of them.
+#![allow(dead_code)]
+
+use chrono::{DateTime, Utc};
+use hexendrum::library::Track;
+use hexendrum::TrackMetadata;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A PCM WAV file, by default a tenth of a second of 16-bit mono silence at 8 kHz,
+/// which tag readers and decoders accept
+pub struct Wav {
+    sample_rate: u32,
+    channels: u16,
+    bits: u16,
+    data: Vec<u8>,
+    declared_len: Option<u32>,
+}
+
+impl Default for Wav {
+    fn default() -> Self {
+        Self {
+            sample_rate: 8000,
+            channels: 1,
+            bits: 16,
+            data: vec![0; 1600],
+            declared_len: None,
+        }
+    }
+}
+
+impl Wav {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Set the channels and bits per sample; call before [`Wav::silent_frames`]
+    pub fn format(mut self, channels: u16, bits: u16) -> Self {
+        self.channels = channels;
+        self.bits = bits;
+        self
+    }
+
+    fn block_align(&self) -> u16 {
+        self.channels * self.bits / 8
+    }
+
+    /// Hold `frames` frames of silence
+    pub fn silent_frames(mut self, frames: u32) -> Self {
+        self.data = vec![0; frames as usize * usize::from(self.block_align())];
+        self
+    }
+
+    /// Hold `samples`, 16-bit and interleaved
+    pub fn samples(mut self, samples: &[i16]) -> Self {
+        self.data = samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect();
+        self
+    }
+
+    /// Claim `len` bytes of audio in the header, whatever the file holds
+    pub fn declared_len(mut self, len: u32) -> Self {
+        self.declared_len = Some(len);
+        self
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        let data_len = self.declared_len.unwrap_or(self.data.len() as u32);
+        let block_align = self.block_align();
+        let mut bytes = Vec::with_capacity(44 + self.data.len());
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&(36 + data_len).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&16u32.to_le_bytes());
+        bytes.extend_from_slice(&1u16.to_le_bytes());
+        bytes.extend_from_slice(&self.channels.to_le_bytes());
+        bytes.extend_from_slice(&self.sample_rate.to_le_bytes());
+        bytes.extend_from_slice(&(self.sample_rate * u32::from(block_align)).to_le_bytes());
+        bytes.extend_from_slice(&block_align.to_le_bytes());
+        bytes.extend_from_slice(&self.bits.to_le_bytes());
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&data_len.to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+
+    pub fn write(&self, path: &Path) {
+        fs::write(path, self.bytes()).expect("failed to write audio file");
+    }
+}
+
+/// Write a short, silent 16-bit mono WAV file that tag readers and decoders accept.
+pub fn write_silent_wav(path: &Path) {
+    Wav::new().write(path);
+}
+
+/// A library track that only lives in memory, by default untagged, at
+/// `/music/<id>.flac` and modified now
+pub struct TestTrack {
+    id: String,
+    file_path: PathBuf,
+    last_modified: DateTime<Utc>,
+    metadata: TrackMetadata,
+}
+
+impl TestTrack {
+    pub fn new(id: impl Into<String>) -> Self {
+        let id = id.into();
+        Self {
+            file_path: PathBuf::from(format!("/music/{}.flac", id)),
+            id,
+            last_modified: Utc::now(),
+            metadata: TrackMetadata::default(),
+        }
+    }
+
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.file_path = path.into();
+        self
+    }
+
+    pub fn modified(mut self, at: DateTime<Utc>) -> Self {
+        self.last_modified = at;
+        self
+    }
+
+    /// Take the tags and details of `metadata`, keeping the path and modification
+    /// time set on the builder
+    pub fn metadata(mut self, metadata: TrackMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    pub fn build(self) -> Track {
+        Track {
+            id: self.id,
+            metadata: TrackMetadata {
+                file_path: self.file_path,
+                last_modified: self.last_modified,
+                ..self.metadata
+            },
+        }
+    }
+}
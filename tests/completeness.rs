@@ -0,0 +1,127 @@
+mod common;
+
+use common::{write_silent_wav, TestTrack};
+use hexendrum::library::{album_identifier, find_incomplete_albums, Track};
+use hexendrum::TrackMetadata;
+use lofty::config::WriteOptions;
+use lofty::file::{AudioFile, TaggedFileExt};
+use lofty::prelude::Accessor;
+use lofty::probe::Probe;
+use lofty::tag::Tag;
+use tempfile::TempDir;
+
+fn track(album: &str, number: Option<u32>, total: Option<u32>) -> Track {
+    let id = format!("{}-{:?}-{:?}", album, number, total);
+    TestTrack::new(&id)
+        .metadata(TrackMetadata {
+            title: Some(id),
+            artist: Some("Artist".into()),
+            album: Some(album.into()),
+            track_number: number,
+            track_total: total,
+            ..Default::default()
+        })
+        .build()
+}
+
+fn missing(tracks: &[Track]) -> Vec<(String, Vec<u32>)> {
+    find_incomplete_albums(tracks)
+        .into_iter()
+        .map(|album| (album.title, album.missing_track_numbers))
+        .collect()
+}
+
+#[test]
+fn gaps_in_track_numbers_are_listed() {
+    let tracks = vec![
+        track("Gaps", Some(1), None),
+        track("Gaps", Some(2), None),
+        track("Gaps", Some(5), None),
+        track("Gaps", Some(7), None),
+    ];
+    assert_eq!(missing(&tracks), vec![("Gaps".to_string(), vec![3, 4, 6])]);
+
+    let album = &find_incomplete_albums(&tracks)[0];
+    assert_eq!(album.album_id, album_identifier(Some("Artist"), "Gaps"));
+    assert_eq!(album.primary_artist.as_deref(), Some("Artist"));
+    assert_eq!((album.track_count, album.track_total), (4, None));
+}
+
+#[test]
+fn missing_tracks_at_the_end_come_from_the_track_total() {
+    let tracks = vec![
+        track("Short", Some(1), Some(4)),
+        track("Short", Some(2), Some(4)),
+        // The highest total of the album wins
+        track("Short", Some(3), Some(6)),
+    ];
+    let albums = find_incomplete_albums(&tracks);
+    assert_eq!(albums.len(), 1);
+    assert_eq!(albums[0].missing_track_numbers, vec![4, 5, 6]);
+    assert_eq!(albums[0].track_total, Some(6));
+}
+
+#[test]
+fn complete_and_unnumbered_albums_are_not_flagged() {
+    let tracks = vec![
+        track("Complete", Some(2), Some(3)),
+        track("Complete", Some(1), Some(3)),
+        track("Complete", Some(3), Some(3)),
+        // No total, nothing missing below the highest number
+        track("Untotalled", Some(1), None),
+        track("Untotalled", Some(2), None),
+        // No numbering data at all
+        track("Loose", None, None),
+        track("Loose", None, None),
+        // A total without track numbers cannot say which tracks are missing
+        track("Totals only", None, Some(10)),
+    ];
+    assert!(missing(&tracks).is_empty());
+}
+
+#[test]
+fn duplicates_and_bogus_numbers_do_not_hide_gaps() {
+    let tracks = vec![
+        track("Doubles", Some(1), Some(3)),
+        track("Doubles", Some(1), Some(3)),
+        track("Doubles", Some(3), Some(3)),
+        track("Doubles", Some(0), None),
+        track("Doubles", Some(4000), None),
+    ];
+    assert_eq!(missing(&tracks), vec![("Doubles".to_string(), vec![2])]);
+}
+
+#[test]
+fn albums_are_sorted_by_title() {
+    let tracks = vec![
+        track("b side", Some(2), None),
+        track("A Side", Some(3), None),
+    ];
+    assert_eq!(
+        missing(&tracks),
+        vec![
+            ("A Side".to_string(), vec![1, 2]),
+            ("b side".to_string(), vec![1])
+        ]
+    );
+}
+
+#[test]
+fn track_totals_are_read_from_tags() {
+    let workspace = TempDir::new().unwrap();
+    let path = workspace.path().join("track.wav");
+    write_silent_wav(&path);
+
+    let mut tagged_file = Probe::open(&path).unwrap().read().unwrap();
+    let mut tag = Tag::new(tagged_file.primary_tag_type());
+    tag.set_track(3);
+    tag.set_track_total(12);
+    tagged_file.insert_tag(tag);
+    tagged_file
+        .save_to_path(&path, WriteOptions::default())
+        .unwrap();
+
+    let metadata = TrackMetadata::from_file(&path).unwrap();
+    assert_eq!(metadata.track_number, Some(3));
+    assert_eq!(metadata.track_total, Some(12));
+}
@@ -1,63 +1,229 @@
-use hexendrum::config::Config;
+use hexendrum::audio::VolumeCurve;
+use hexendrum::config::{Config, ConfigError, MusicDirectory, Paths, DATA_DIR_ENV, DATA_DIR_FLAG};
+use hexendrum::library::ReadOnlyPaths;
+use hexendrum::playlist::RepeatMode;
 use serial_test::serial;
 use std::fs;
 use tempfile::TempDir;
 
-fn setup_env() -> (TempDir, Option<String>, Option<String>, Option<String>) {
+fn portable_paths() -> (TempDir, Paths) {
     let workspace = tempfile::tempdir().expect("failed to create temp workspace");
-    let cache_dir = workspace.path().join("cache");
-    let config_dir = workspace.path().join("config");
+    let paths = Paths::portable(workspace.path());
+    fs::create_dir_all(&paths.config_dir).expect("failed to create config dir");
+    (workspace, paths)
+}
 
-    fs::create_dir(&cache_dir).expect("failed to create cache dir");
-    fs::create_dir(&config_dir).expect("failed to create config dir");
+#[test]
+fn config_save_and_load_roundtrip() {
+    let (_workspace, paths) = portable_paths();
 
-    let old_cache = std::env::var("XDG_CACHE_HOME").ok();
-    let old_config = std::env::var("XDG_CONFIG_HOME").ok();
-    let old_home = std::env::var("HOME").ok();
+    let mut config = Config::default();
+    config.audio.default_volume = 0.42;
+    config.library.auto_scan = false;
+    config.playlist.auto_save = false;
 
-    std::env::set_var("XDG_CACHE_HOME", &cache_dir);
-    std::env::set_var("XDG_CONFIG_HOME", &config_dir);
-    std::env::set_var("HOME", workspace.path());
+    config.save(&paths).expect("saving config should succeed");
 
-    (workspace, old_cache, old_config, old_home)
+    let loaded = Config::load(&paths).expect("loading config should succeed");
+    assert_eq!(loaded.audio.default_volume, 0.42);
+    assert!(!loaded.library.auto_scan);
+    assert!(!loaded.playlist.auto_save);
 }
 
-fn restore_env(old_cache: Option<String>, old_config: Option<String>, old_home: Option<String>) {
-    if let Some(old) = old_cache {
-        std::env::set_var("XDG_CACHE_HOME", old);
-    } else {
-        std::env::remove_var("XDG_CACHE_HOME");
-    }
+#[test]
+fn repeat_and_shuffle_defaults_load_from_config_file() {
+    let (_workspace, paths) = portable_paths();
 
-    if let Some(old) = old_config {
-        std::env::set_var("XDG_CONFIG_HOME", old);
-    } else {
-        std::env::remove_var("XDG_CONFIG_HOME");
-    }
+    fs::write(
+        paths.config_file(),
+        "[playlist]\ndefault_repeat_mode = \"All\"\ndefault_shuffle = true\n",
+    )
+    .expect("failed to write config");
 
-    if let Some(old) = old_home {
-        std::env::set_var("HOME", old);
-    } else {
-        std::env::remove_var("HOME");
+    let loaded = Config::load(&paths).expect("loading config should succeed");
+    assert_eq!(loaded.playlist.default_repeat_mode, RepeatMode::All);
+    assert!(loaded.playlist.default_shuffle);
+}
+
+#[test]
+fn invalid_repeat_mode_falls_back_without_discarding_config() {
+    let (_workspace, paths) = portable_paths();
+
+    fs::write(
+        paths.config_file(),
+        "[playlist]\ndefault_repeat_mode = \"forever\"\nauto_save = false\n",
+    )
+    .expect("failed to write config");
+
+    let loaded = Config::load(&paths).expect("loading config should succeed");
+    assert_eq!(loaded.playlist.default_repeat_mode, RepeatMode::None);
+    assert!(!loaded.playlist.auto_save);
+}
+
+#[test]
+fn malformed_config_files_are_reported_with_their_path() {
+    let (_workspace, paths) = portable_paths();
+    fs::write(paths.config_file(), "[audio]\ndefault_volume = \"loud\"\n")
+        .expect("failed to write config");
+
+    match Config::load(&paths) {
+        Err(ConfigError::Invalid { path, .. }) => assert_eq!(path, paths.config_file()),
+        other => panic!("expected an invalid config, got {:?}", other.map(|_| ())),
     }
 }
 
 #[test]
-#[serial]
-fn config_save_and_load_roundtrip() {
-    let (_workspace, old_cache, old_config, old_home) = setup_env();
+fn music_directories_can_be_marked_read_only() {
+    let (_workspace, paths) = portable_paths();
+
+    fs::write(
+        paths.config_file(),
+        "[library]\nmusic_directories = [\"/music/local\", { path = \"/mnt/share\", read_only = true }]\n",
+    )
+    .expect("failed to write config");
+
+    let loaded = Config::load(&paths).expect("loading config should succeed");
+    assert_eq!(
+        loaded.library.music_directories,
+        vec![
+            MusicDirectory {
+                path: "/music/local".into(),
+                read_only: false,
+                name: None,
+            },
+            MusicDirectory {
+                path: "/mnt/share".into(),
+                read_only: true,
+                name: None,
+            },
+        ]
+    );
+    assert_eq!(
+        loaded.library.read_only_paths(),
+        ReadOnlyPaths {
+            everything: false,
+            roots: vec!["/mnt/share".into()],
+        }
+    );
+
+    loaded.save(&paths).expect("saving config should succeed");
+    let reloaded = Config::load(&paths).expect("loading config should succeed");
+    assert_eq!(
+        reloaded.library.music_directories,
+        loaded.library.music_directories
+    );
+}
+
+#[test]
+fn volume_curve_loads_from_config_file() {
+    let (_workspace, paths) = portable_paths();
 
     let mut config = Config::default();
-    config.audio.default_volume = 0.42;
-    config.library.auto_scan = false;
-    config.playlist.auto_save = false;
+    config.audio.volume_curve = VolumeCurve::CustomExponent(2.5);
+    config.save(&paths).expect("saving config should succeed");
+    let loaded = Config::load(&paths).expect("loading config should succeed");
+    assert_eq!(loaded.audio.volume_curve, VolumeCurve::CustomExponent(2.5));
 
-    config.save().expect("saving config should succeed");
+    fs::write(
+        paths.config_file(),
+        "[audio]\nvolume_curve = \"shouty\"\ndefault_volume = 0.3\n",
+    )
+    .expect("failed to write config");
+    let loaded = Config::load(&paths).expect("loading config should succeed");
+    assert_eq!(loaded.audio.volume_curve, VolumeCurve::Linear);
+    assert_eq!(loaded.audio.default_volume, 0.3);
+}
 
-    let loaded = Config::load().expect("loading config should succeed");
-    assert_eq!(loaded.audio.default_volume, 0.42);
-    assert!(!loaded.library.auto_scan);
-    assert!(!loaded.playlist.auto_save);
+#[test]
+fn webhooks_load_from_config_file_with_defaults() {
+    let (_workspace, paths) = portable_paths();
+
+    fs::write(
+        paths.config_file(),
+        "[[webhooks]]\nurl = \"http://localhost:8123/hook\"\nevents = [\"playback_state\"]\nsecret = \"s3cret\"\n\n[[webhooks]]\nurl = \"http://localhost:9000/all\"\ntimeout_secs = 1\n",
+    )
+    .expect("failed to write config");
+
+    let loaded = Config::load(&paths).expect("loading config should succeed");
+    assert_eq!(loaded.webhooks.len(), 2);
+    assert_eq!(loaded.webhooks[0].events, vec!["playback_state"]);
+    assert_eq!(loaded.webhooks[0].secret.as_deref(), Some("s3cret"));
+    assert_eq!(loaded.webhooks[0].timeout_secs, 5);
+    assert!(loaded.webhooks[1].events.is_empty());
+    assert_eq!(loaded.webhooks[1].timeout_secs, 1);
+    assert_eq!(loaded.webhooks[1].max_retries, 2);
+}
+
+#[test]
+#[serial]
+fn data_dir_comes_from_flag_then_environment() {
+    let mut args: Vec<String> = [
+        "hexendrum",
+        "--data-dir",
+        "stick/hexendrum",
+        "ctl",
+        "status",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect();
+    let flag = Paths::take_data_dir_arg(&mut args).expect("flag should parse");
+    assert_eq!(args, ["hexendrum", "ctl", "status"]);
+
+    let paths = Paths::resolve(flag).expect("paths should resolve");
+    let base = std::env::current_dir().unwrap().join("stick/hexendrum");
+    assert_eq!(paths.config_file(), base.join("config.toml"));
+    assert_eq!(
+        paths.library_cache_file(),
+        base.join("cache/library_cache.json")
+    );
+    assert_eq!(paths.home_trash, None);
+
+    let mut args = vec!["hexendrum".to_string(), "--data-dir".to_string()];
+    assert!(matches!(
+        Paths::take_data_dir_arg(&mut args),
+        Err(ConfigError::MissingFlagValue(DATA_DIR_FLAG))
+    ));
+
+    let old = std::env::var(DATA_DIR_ENV).ok();
+    std::env::set_var(DATA_DIR_ENV, "/media/usb/hexendrum");
+    let from_env = Paths::resolve(None).expect("paths should resolve");
+    let from_flag = Paths::resolve(Some("/elsewhere".into())).expect("paths should resolve");
+    std::env::remove_var(DATA_DIR_ENV);
+    let standard = Paths::resolve(None).expect("paths should resolve");
+    if let Some(old) = old {
+        std::env::set_var(DATA_DIR_ENV, old);
+    }
+
+    assert_eq!(from_env, Paths::portable("/media/usb/hexendrum"));
+    assert_eq!(from_flag, Paths::portable("/elsewhere"));
+    assert_eq!(standard, Paths::standard());
+}
+
+#[test]
+fn now_playing_templates_are_checked_when_loading() {
+    let (_workspace, paths) = portable_paths();
+
+    fs::write(
+        paths.config_file(),
+        "[integrations]\nnow_playing_file = \"/tmp/now-playing.txt\"\nnow_playing_template = \"{title} ({album})\"\n",
+    )
+    .expect("failed to write config");
+    let loaded = Config::load(&paths).expect("loading config should succeed");
+    assert_eq!(
+        loaded.integrations.now_playing_template.to_string(),
+        "{title} ({album})"
+    );
 
-    restore_env(old_cache, old_config, old_home);
+    fs::write(
+        paths.config_file(),
+        "[integrations]\nnow_playing_template = \"{artist} - {track}\"\n",
+    )
+    .expect("failed to write config");
+    let error = Config::load(&paths).unwrap_err();
+    assert!(
+        format!("{:#}", error).contains("Unknown placeholder {track}"),
+        "{:#}",
+        error
+    );
 }
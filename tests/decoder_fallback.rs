@@ -0,0 +1,199 @@
+mod common;
+
+use common::Wav;
+use hexendrum::audio::{open_decoder, SymphoniaSource};
+use rodio::{Decoder, Source};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const RATE: u32 = 8000;
+const BLOCK_ALIGN: u16 = 256;
+/// Frames of a mono IMA ADPCM block: the header's sample, then two per data byte
+const FRAMES_PER_BLOCK: usize = (BLOCK_ALIGN as usize - 4) * 2 + 1;
+
+/// Write a mono IMA ADPCM WAV of `damaged` blocks whose headers carry an invalid
+/// step index, followed by `intact` blocks swinging up and down. hound reads only
+/// PCM, and rodio's symphonia path gives up after 3 undecodable packets in a row,
+/// of two blocks each.
+fn write_damaged_adpcm(path: &Path, damaged: usize, intact: usize) {
+    let blocks = damaged + intact;
+    let data_len = (blocks * BLOCK_ALIGN as usize) as u32;
+
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"RIFF");
+    bytes.extend_from_slice(&(4 + 28 + 8 + data_len).to_le_bytes());
+    bytes.extend_from_slice(b"WAVEfmt ");
+    bytes.extend_from_slice(&20u32.to_le_bytes());
+    bytes.extend_from_slice(&0x11u16.to_le_bytes());
+    bytes.extend_from_slice(&1u16.to_le_bytes());
+    bytes.extend_from_slice(&RATE.to_le_bytes());
+    bytes.extend_from_slice(
+        &(RATE * u32::from(BLOCK_ALIGN) / FRAMES_PER_BLOCK as u32).to_le_bytes(),
+    );
+    bytes.extend_from_slice(&BLOCK_ALIGN.to_le_bytes());
+    bytes.extend_from_slice(&4u16.to_le_bytes());
+    bytes.extend_from_slice(&2u16.to_le_bytes());
+    bytes.extend_from_slice(&(FRAMES_PER_BLOCK as u16).to_le_bytes());
+    bytes.extend_from_slice(b"data");
+    bytes.extend_from_slice(&data_len.to_le_bytes());
+    for block in 0..blocks {
+        let step_index = if block < damaged { 200 } else { 40 };
+        bytes.extend_from_slice(&0i16.to_le_bytes());
+        bytes.extend_from_slice(&[step_index, 0]);
+        for byte in 0..BLOCK_ALIGN as usize - 4 {
+            // Rising steeply for a while, then falling
+            let nibble = if byte % 16 < 8 { 0x7 } else { 0xf };
+            bytes.push(nibble | nibble << 4);
+        }
+    }
+
+    fs::write(path, bytes).expect("failed to write audio file");
+}
+
+/// Write a mono 16-bit PCM WAV of `frames` frames of `sample`
+fn write_pcm(path: &Path, frames: u32, sample: i16) {
+    Wav::new()
+        .sample_rate(RATE)
+        .samples(&vec![sample; frames as usize])
+        .write(path);
+}
+
+/// Write a mono 16-bit AIFF of `frames` frames of `sample`
+fn write_aiff(path: &Path, frames: u32, sample: i16) {
+    let data_len = frames * 2;
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(b"FORM");
+    bytes.extend_from_slice(&(4 + 26 + 16 + data_len).to_be_bytes());
+    bytes.extend_from_slice(b"AIFFCOMM");
+    bytes.extend_from_slice(&18u32.to_be_bytes());
+    bytes.extend_from_slice(&1i16.to_be_bytes());
+    bytes.extend_from_slice(&frames.to_be_bytes());
+    bytes.extend_from_slice(&16i16.to_be_bytes());
+    // 8000 as an 80-bit extended float
+    bytes.extend_from_slice(&[0x40, 0x0b, 0xfa, 0, 0, 0, 0, 0, 0, 0]);
+    bytes.extend_from_slice(b"SSND");
+    bytes.extend_from_slice(&(8 + data_len).to_be_bytes());
+    bytes.extend_from_slice(&[0; 8]);
+    for _ in 0..frames {
+        bytes.extend_from_slice(&sample.to_be_bytes());
+    }
+    fs::write(path, bytes).expect("failed to write audio file");
+}
+
+#[test]
+fn files_rodio_rejects_are_decoded_with_symphonia() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join("damaged.wav");
+    write_damaged_adpcm(&path, 10, 20);
+
+    assert!(
+        Decoder::new(BufReader::new(File::open(&path).unwrap())).is_err(),
+        "rodio refuses the fixture, or it proves nothing"
+    );
+
+    let source = open_decoder(&path).expect("symphonia decodes what rodio refused");
+    assert_eq!(source.channels(), 1);
+    assert_eq!(source.sample_rate(), RATE);
+    let samples: Vec<f32> = source.collect();
+    assert_eq!(
+        samples.len(),
+        20 * FRAMES_PER_BLOCK,
+        "every intact block plays"
+    );
+    assert!(samples.iter().all(|sample| sample.abs() <= 1.0));
+    assert!(samples.iter().any(|sample| sample.abs() > 0.1));
+
+    let direct = SymphoniaSource::open(&path).unwrap();
+    assert_eq!(
+        direct.total_duration(),
+        Some(Duration::from_secs_f64(
+            (30 * FRAMES_PER_BLOCK) as f64 / f64::from(RATE)
+        ))
+    );
+}
+
+#[test]
+fn files_rodio_decodes_still_go_through_rodio() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join("plain.wav");
+    write_pcm(&path, 800, i16::MAX / 2);
+
+    let samples: Vec<f32> = open_decoder(&path).unwrap().collect();
+    assert_eq!(samples.len(), 800);
+    assert!(samples.iter().all(|sample| (sample - 0.5).abs() < 1e-3));
+}
+
+#[test]
+fn aiff_files_play() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join("take.aiff");
+    write_aiff(&path, 800, i16::MIN / 2);
+
+    let source = open_decoder(&path).unwrap();
+    assert_eq!(source.sample_rate(), RATE);
+    let samples: Vec<f32> = source.collect();
+    assert_eq!(samples.len(), 800);
+    assert!(samples.iter().all(|sample| (sample + 0.5).abs() < 1e-3));
+}
+
+/// Half a second of a 440 Hz sine at half scale on both channels, encoded at 48 kHz
+/// with the usual 312 frames of pre-skip, and tagged "Opus Sine" by "Test Tones"
+const OPUS_FIXTURE: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/tests/fixtures/audio/sine.opus"
+);
+
+#[test]
+fn opus_files_play() {
+    let path = Path::new(OPUS_FIXTURE);
+    assert!(
+        SymphoniaSource::open(path).is_err(),
+        "symphonia decodes Opus itself, or this proves nothing"
+    );
+
+    let source = open_decoder(path).expect("Opus files decode");
+    assert_eq!(source.channels(), 2);
+    assert_eq!(source.sample_rate(), 48_000);
+    assert_eq!(source.total_duration(), Some(Duration::from_millis(500)));
+    let samples: Vec<f32> = source.collect();
+    assert_eq!(
+        samples.len(),
+        24_000 * 2,
+        "the pre-skip and the padding past the end are dropped"
+    );
+
+    let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+    let rms = (left.iter().map(|sample| sample * sample).sum::<f32>() / left.len() as f32).sqrt();
+    assert!((rms - 0.5 / 2f32.sqrt()).abs() < 0.05, "{}", rms);
+    let rising = left
+        .windows(2)
+        .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+        .count();
+    assert!((215..=225).contains(&rising), "{} cycles", rising);
+    let right = samples.iter().skip(1).step_by(2);
+    assert!(left.iter().zip(right).all(|(l, r)| (l - r).abs() < 0.05));
+}
+
+#[test]
+fn files_neither_decoder_reads_report_both_errors() {
+    let temp = TempDir::new().unwrap();
+    let path = temp.path().join("noise.wav");
+    fs::write(&path, b"not audio at all, just some bytes").unwrap();
+
+    let error = open_decoder(&path).err().expect("nothing decodes this");
+    let message = error.to_string();
+    assert!(
+        message.starts_with("Failed to decode audio file"),
+        "{}",
+        message
+    );
+    assert!(message.contains("symphonia:"), "{}", message);
+
+    // Too many damaged blocks in a row for the fallback as well
+    let damaged = temp.path().join("ruined.wav");
+    write_damaged_adpcm(&damaged, 80, 2);
+    assert!(open_decoder(&damaged).is_err());
+}
@@ -0,0 +1,149 @@
+use axum::body::{to_bytes, Body};
+use axum::http::{Request, StatusCode};
+use hexendrum::api::{create_router, AppState};
+use hexendrum::config::{Config, Paths};
+use hexendrum::demo::{self, DEMO_SEED};
+use hexendrum::playlist::PlaylistManager;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::time::Duration;
+use tempfile::TempDir;
+use tower::ServiceExt;
+
+fn demo_state(workspace: &TempDir) -> (AppState, Paths) {
+    let paths = Paths::portable(workspace.path().join("data"));
+    let state = demo::state(&paths, &Config::default(), DEMO_SEED).expect("demo state builds");
+    (state, paths)
+}
+
+async fn request_json(state: &AppState, request: Request<Body>) -> (StatusCode, Value) {
+    let response = create_router(state.clone())
+        .oneshot(request)
+        .await
+        .expect("router should respond");
+
+    let status = response.status();
+    let bytes = to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("body should be readable");
+    let value = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+    (status, value)
+}
+
+async fn get_json(state: &AppState, uri: &str) -> (StatusCode, Value) {
+    request_json(state, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+async fn post_json(state: &AppState, uri: &str, body: Value) -> (StatusCode, Value) {
+    let request = Request::post(uri)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    request_json(state, request).await
+}
+
+#[test]
+fn generated_libraries_depend_only_on_the_seed() {
+    let tracks = demo::generate_tracks(DEMO_SEED);
+    assert!(
+        (200..1000).contains(&tracks.len()),
+        "a few hundred tracks, got {}",
+        tracks.len()
+    );
+    let ids: HashSet<_> = tracks.iter().map(|track| &track.id).collect();
+    let paths: HashSet<_> = tracks
+        .iter()
+        .map(|track| &track.metadata.file_path)
+        .collect();
+    assert_eq!(ids.len(), tracks.len());
+    assert_eq!(paths.len(), tracks.len());
+
+    let again = demo::generate_tracks(DEMO_SEED);
+    assert_eq!(
+        again.iter().map(|track| &track.id).collect::<Vec<_>>(),
+        tracks.iter().map(|track| &track.id).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        again
+            .iter()
+            .map(|track| &track.metadata.title)
+            .collect::<Vec<_>>(),
+        tracks
+            .iter()
+            .map(|track| &track.metadata.title)
+            .collect::<Vec<_>>()
+    );
+    assert_ne!(demo::generate_tracks(DEMO_SEED + 1)[0].id, tracks[0].id);
+}
+
+#[tokio::test]
+async fn the_demo_library_is_served_and_survives_scans() {
+    let workspace = TempDir::new().unwrap();
+    let (state, paths) = demo_state(&workspace);
+    let generated = demo::generate_tracks(DEMO_SEED).len();
+
+    let (status, body) = get_json(&state, "/api/library/tracks").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"].as_array().unwrap().len(), generated);
+    let (status, body) = get_json(&state, "/api/library/albums/search").await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["data"]["total"].as_u64().unwrap() >= 32);
+
+    let (status, _) = post_json(
+        &state,
+        "/api/library/scan",
+        json!({ "directories": ["/nowhere"] }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    let mut scan = Value::Null;
+    for _ in 0..100 {
+        scan = get_json(&state, "/api/library/scan/status").await.1;
+        if scan["data"]["running"] == false {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(scan["data"]["tracks"], generated);
+    assert_eq!(state.library.track_count(), generated);
+    assert!(
+        !paths.library_cache_file().exists(),
+        "the demo library is never cached"
+    );
+}
+
+#[tokio::test]
+async fn tracks_play_without_files_and_their_position_advances() {
+    let workspace = TempDir::new().unwrap();
+    let (state, _) = demo_state(&workspace);
+    let track = &demo::generate_tracks(DEMO_SEED)[0];
+    assert!(!track.metadata.file_path.exists());
+
+    let (status, _) = post_json(
+        &state,
+        "/api/audio/play",
+        json!({ "file_path": track.metadata.file_path }),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let (_, body) = get_json(&state, "/api/audio/status").await;
+    assert_eq!(body["data"]["state"], "Playing");
+    assert!(body["data"]["position_seconds"].as_f64().unwrap() > 0.0);
+}
+
+#[test]
+fn in_memory_playlists_are_never_written() {
+    let manager = PlaylistManager::in_memory();
+    let id = manager.create_playlist("Demo".into(), None);
+    let playlist = manager.get_playlist(&id).unwrap();
+    manager.save_playlist(&playlist).unwrap();
+    manager.load_all_playlists().unwrap();
+
+    assert_eq!(
+        manager.get_playlists().len(),
+        1,
+        "loading keeps what is in memory"
+    );
+}
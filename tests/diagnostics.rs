@@ -0,0 +1,171 @@
+use hexendrum::config::Paths;
+use hexendrum::diagnostics::{
+    check_config, check_lastfm_at, check_music_directory, check_port, check_writable_directory,
+    run_all, CheckResult, CheckStatus, DoctorReport,
+};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use tempfile::TempDir;
+
+fn extensions() -> Vec<String> {
+    vec!["mp3".to_string(), "flac".to_string()]
+}
+
+/// Answer a single HTTP request with `body` and return the address to send it to.
+fn serve_once(body: &'static str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = [0u8; 4096];
+        let _ = stream.read(&mut request);
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            body.len(),
+            body
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    });
+    format!("http://{}/2.0/", address)
+}
+
+#[test]
+fn config_parse_errors_fail_and_typos_warn() {
+    let workspace = TempDir::new().unwrap();
+    let paths = Paths::portable(workspace.path());
+
+    let (check, _) = check_config(&paths);
+    assert_eq!(check.status, CheckStatus::Pass, "{:?}", check);
+
+    fs::write(paths.config_file(), "[audio]\ndefault_volume = \"loud\"\n").unwrap();
+    let (check, config) = check_config(&paths);
+    assert_eq!(check.status, CheckStatus::Fail);
+    assert!(check.hint.is_some());
+    assert_eq!(config.audio.default_volume, 0.7);
+
+    fs::write(paths.config_file(), "[audio]\ndefault_volume = 3.0\n").unwrap();
+    let (check, _) = check_config(&paths);
+    assert_eq!(check.status, CheckStatus::Fail);
+    assert!(check.message.contains("audio.default_volume"));
+
+    fs::write(
+        paths.config_file(),
+        "[libary]\nauto_scan = false\n\n[api]\nport = 4000\nunix_socket = \"/tmp/h.sock\"\n\n[[webhooks]]\nurl = \"http://localhost/hook\"\nsecrett = \"x\"\n",
+    )
+    .unwrap();
+    let (check, config) = check_config(&paths);
+    assert_eq!(check.status, CheckStatus::Warn);
+    assert!(check.message.contains("libary"), "{}", check.message);
+    assert!(check.message.contains("webhooks[0].secrett"));
+    assert!(!check.message.contains("unix_socket"));
+    assert_eq!(config.api.port, 4000);
+}
+
+#[test]
+fn music_directories_are_checked_and_counted() {
+    let workspace = TempDir::new().unwrap();
+    let music = workspace.path().join("music");
+    fs::create_dir_all(music.join("album")).unwrap();
+
+    let check = check_music_directory(&workspace.path().join("missing"), &extensions());
+    assert_eq!(check.status, CheckStatus::Fail);
+
+    let check = check_music_directory(&music, &extensions());
+    assert_eq!(check.status, CheckStatus::Warn);
+
+    fs::write(music.join("album/one.mp3"), b"").unwrap();
+    fs::write(music.join("album/two.FLAC"), b"").unwrap();
+    fs::write(music.join("cover.jpg"), b"").unwrap();
+    let check = check_music_directory(&music, &extensions());
+    assert_eq!(check.status, CheckStatus::Pass);
+    assert_eq!(check.message, "2 audio file(s)");
+}
+
+#[test]
+fn writable_directories_are_created_and_probed() {
+    let workspace = TempDir::new().unwrap();
+    let cache = workspace.path().join("cache/nested");
+    let check = check_writable_directory("cache directory", &cache);
+    assert_eq!(check.status, CheckStatus::Pass);
+    assert!(cache.is_dir());
+    assert_eq!(fs::read_dir(&cache).unwrap().count(), 0);
+
+    let file = workspace.path().join("file");
+    fs::write(&file, b"").unwrap();
+    let check = check_writable_directory("cache directory", &file);
+    assert_eq!(check.status, CheckStatus::Fail);
+}
+
+#[test]
+fn lastfm_key_must_be_present_and_accepted() {
+    let check = check_lastfm_at("http://127.0.0.1:9/", "  ");
+    assert_eq!(check.status, CheckStatus::Warn);
+
+    let endpoint = serve_once(r#"{"error":10,"message":"Invalid API key"}"#);
+    let check = check_lastfm_at(&endpoint, "bad-key");
+    assert_eq!(check.status, CheckStatus::Fail, "{:?}", check);
+    assert!(check.message.contains("Invalid API key"));
+
+    let endpoint = serve_once(r#"{"artist":{"name":"Cher"}}"#);
+    let check = check_lastfm_at(&endpoint, "good-key");
+    assert_eq!(check.status, CheckStatus::Pass, "{:?}", check);
+}
+
+#[test]
+fn ports_in_use_fail() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    assert_eq!(check_port(port).status, CheckStatus::Fail);
+
+    drop(listener);
+    assert_eq!(check_port(port).status, CheckStatus::Pass);
+}
+
+#[test]
+fn report_status_is_the_worst_check() {
+    let report = DoctorReport::new(vec![
+        CheckResult::pass("a", "fine"),
+        CheckResult::warn("b", "meh", "do something"),
+    ]);
+    assert_eq!(report.status, CheckStatus::Warn);
+    assert!(!report.has_failures());
+
+    let report = DoctorReport::new(vec![
+        CheckResult::pass("a", "fine"),
+        CheckResult::fail("longer name", "broken", "fix it"),
+    ]);
+    assert!(report.has_failures());
+    let rendered = report.render();
+    assert!(
+        rendered.contains("FAIL  longer name  broken"),
+        "{}",
+        rendered
+    );
+    assert!(rendered.contains("-> fix it"));
+    assert!(rendered.ends_with("1 passed, 0 warning(s), 1 failed"));
+}
+
+#[test]
+fn backend_reports_skip_device_and_port_checks() {
+    let workspace = TempDir::new().unwrap();
+    let paths = Paths::portable(workspace.path());
+    let music = workspace.path().join("music");
+    fs::create_dir(&music).unwrap();
+    fs::write(
+        paths.config_file(),
+        format!(
+            "[library]\nmusic_directories = [{:?}]\n",
+            music.to_string_lossy()
+        ),
+    )
+    .unwrap();
+
+    let report = run_all(&paths, true);
+    let names: Vec<&str> = report.checks.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names[0], "configuration");
+    assert!(names.iter().any(|name| name.starts_with("music directory")));
+    assert!(!names.contains(&"audio device"));
+    assert!(!names.iter().any(|name| name.starts_with("API port")));
+    assert!(paths.playlist_dir().is_dir());
+}
@@ -0,0 +1,144 @@
+mod common;
+
+use common::TestTrack;
+use hexendrum::library::{
+    find_duplicate_groups, recommend_keeper, tag_completeness, DuplicateCandidate,
+    DuplicatePreferences, Track,
+};
+use hexendrum::TrackMetadata;
+
+fn track(id: &str, title: &str, artist: &str, duration: Option<u64>) -> Track {
+    TestTrack::new(id)
+        .metadata(TrackMetadata {
+            title: Some(title.into()),
+            artist: Some(artist.into()),
+            duration,
+            ..Default::default()
+        })
+        .build()
+}
+
+fn candidate(id: &str, format: &str, bitrate: Option<u32>, tags: f32) -> DuplicateCandidate {
+    DuplicateCandidate {
+        track_id: id.into(),
+        path: format!("/music/{}.{}", id, format),
+        format: format.into(),
+        duration: Some(240),
+        file_size: 0,
+        bitrate_kbps: bitrate,
+        sample_rate: None,
+        bit_depth: None,
+        tag_completeness: tags,
+        recommended: false,
+    }
+}
+
+fn keeper(candidates: &[DuplicateCandidate], preferences: &DuplicatePreferences) -> String {
+    let index = recommend_keeper(candidates, preferences).expect("a keeper should be chosen");
+    candidates[index].track_id.clone()
+}
+
+#[test]
+fn preferred_formats_win_over_bitrate_and_tags() {
+    let preferences = DuplicatePreferences::default();
+    let candidates = [
+        candidate("mp3", "mp3", Some(320), 1.0),
+        candidate("flac", "FLAC", Some(900), 0.25),
+        candidate("unknown", "wma", Some(1411), 1.0),
+    ];
+    assert_eq!(keeper(&candidates, &preferences), "flac");
+
+    let mp3_first = DuplicatePreferences {
+        formats: vec!["mp3".into(), "flac".into()],
+        ..Default::default()
+    };
+    assert_eq!(keeper(&candidates, &mp3_first), "mp3");
+}
+
+#[test]
+fn higher_bitrates_win_unless_within_the_tolerance() {
+    let preferences = DuplicatePreferences::default();
+    let candidates = [
+        candidate("v0", "mp3", Some(245), 1.0),
+        candidate("cbr320", "mp3", Some(320), 0.5),
+    ];
+    assert_eq!(keeper(&candidates, &preferences), "cbr320");
+
+    // Embedded artwork makes the second copy slightly larger; the tags decide
+    let candidates = [
+        candidate("tagged", "mp3", Some(320), 1.0),
+        candidate("artwork", "mp3", Some(330), 0.5),
+    ];
+    assert_eq!(keeper(&candidates, &preferences), "tagged");
+
+    let ignore_bitrate = DuplicatePreferences {
+        prefer_higher_bitrate: false,
+        ..Default::default()
+    };
+    let candidates = [
+        candidate("v0", "mp3", Some(245), 1.0),
+        candidate("cbr320", "mp3", Some(320), 0.5),
+    ];
+    assert_eq!(keeper(&candidates, &ignore_bitrate), "v0");
+}
+
+#[test]
+fn ties_keep_the_first_copy() {
+    let preferences = DuplicatePreferences {
+        formats: Vec::new(),
+        prefer_higher_bitrate: false,
+        prefer_complete_tags: false,
+    };
+    let candidates = [
+        candidate("first", "mp3", Some(128), 0.25),
+        candidate("second", "flac", Some(900), 1.0),
+    ];
+    assert_eq!(keeper(&candidates, &preferences), "first");
+    assert_eq!(recommend_keeper(&[], &preferences), None);
+}
+
+#[test]
+fn tag_completeness_counts_descriptive_tags() {
+    let mut track = track("a", "Song", "Artist", None);
+    assert!((tag_completeness(&track.metadata) - 2.0 / 7.0).abs() < f32::EPSILON);
+
+    track.metadata.album = Some("Album".into());
+    track.metadata.album_artist = Some("Artist".into());
+    track.metadata.track_number = Some(1);
+    track.metadata.year = Some(1999);
+    track.metadata.genre = Some("Rock".into());
+    assert_eq!(tag_completeness(&track.metadata), 1.0);
+
+    track.metadata.genre = Some("  ".into());
+    assert!(tag_completeness(&track.metadata) < 1.0);
+}
+
+#[test]
+fn duplicates_share_a_title_artist_and_duration() {
+    let tracks = [
+        track("flac", "Paranoid Android", "Radiohead", Some(383)),
+        track(
+            "mp3",
+            "Paranoid Android (Remastered)",
+            "radiohead",
+            Some(385),
+        ),
+        track("untimed", "Paranoid Android", "Radiohead", None),
+        track("live", "Paranoid Android", "Radiohead", Some(420)),
+        track("cover", "Paranoid Android", "Brad Mehldau", Some(384)),
+        track("other", "Karma Police", "Radiohead", Some(261)),
+    ];
+
+    let groups = find_duplicate_groups(&tracks);
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].title, "Paranoid Android");
+    assert_eq!(groups[0].artist.as_deref(), Some("Radiohead"));
+    assert_eq!(groups[0].track_ids, vec!["flac", "mp3", "untimed"]);
+
+    let reordered: Vec<Track> = tracks.iter().rev().cloned().collect();
+    assert_eq!(
+        find_duplicate_groups(&reordered)[0].id,
+        groups[0].id,
+        "group identifiers should not depend on library order"
+    );
+}
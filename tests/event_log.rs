@@ -0,0 +1,161 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use hexendrum::events::{parse_since, EventLog};
+use hexendrum::{EventBus, EventPayload};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+struct EventLogTestEnv {
+    _workspace: TempDir,
+    path: PathBuf,
+    event_bus: EventBus,
+}
+
+impl EventLogTestEnv {
+    fn new() -> Self {
+        let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+        Self {
+            path: workspace.path().join("logs").join("events.jsonl"),
+            _workspace: workspace,
+            event_bus: EventBus::new(None),
+        }
+    }
+
+    fn start(&self, max_bytes: u64, max_files: usize) -> Arc<EventLog> {
+        EventLog::start_with_limit(&self.event_bus, self.path.clone(), max_bytes, max_files)
+    }
+
+    fn rotated(&self, index: usize) -> PathBuf {
+        PathBuf::from(format!("{}.{}", self.path.display(), index))
+    }
+}
+
+fn volume(entry: &Value) -> f64 {
+    entry["volume"]
+        .as_f64()
+        .expect("entry should be a volume event")
+}
+
+/// Wait until the entry for `last_volume` has been written.
+async fn wait_for(log: &EventLog, last_volume: f64) -> Vec<Value> {
+    let deadline = std::time::Instant::now() + std::time::Duration::from_secs(5);
+    loop {
+        let entries = log.read_entries(None, usize::MAX).unwrap();
+        if entries.last().map(volume) == Some(last_volume) {
+            return entries;
+        }
+        assert!(
+            std::time::Instant::now() < deadline,
+            "event was never logged"
+        );
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+}
+
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map(|meta| meta.len()).unwrap_or(0)
+}
+
+#[tokio::test]
+async fn events_are_appended_as_json_lines() {
+    let env = EventLogTestEnv::new();
+    let log = env.start(1024 * 1024, 3);
+
+    env.event_bus.emit(EventPayload::volume_changed(0.25));
+    env.event_bus.emit(EventPayload::library_updated(12));
+    env.event_bus.emit(EventPayload::volume_changed(0.5));
+    wait_for(&log, 0.5).await;
+
+    let content = fs::read_to_string(&env.path).unwrap();
+    let lines: Vec<Value> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[1]["type"], "library_updated");
+    assert_eq!(lines[1]["total_tracks"], 12);
+    assert!(
+        lines[1].get("sequence").is_none(),
+        "unknown deltas are left out"
+    );
+    assert!(lines[0]["timestamp"].is_string());
+    assert_eq!(log.dropped(), 0);
+}
+
+#[tokio::test]
+async fn log_files_rotate_by_size_and_keep_the_configured_number() {
+    let env = EventLogTestEnv::new();
+    let max_bytes = 300;
+    let log = env.start(max_bytes, 3);
+
+    for index in 1..=40 {
+        env.event_bus
+            .emit(EventPayload::volume_changed(index as f32 / 100.0));
+    }
+    let entries = wait_for(&log, 0.4).await;
+
+    assert!(env.rotated(1).exists());
+    assert!(env.rotated(2).exists());
+    assert!(!env.rotated(3).exists());
+    for path in [env.path.clone(), env.rotated(1), env.rotated(2)] {
+        assert!(file_size(&path) <= max_bytes, "{:?} is too large", path);
+    }
+
+    // Older entries went with the removed files; the kept ones stay in order.
+    assert!(entries.len() < 40);
+    let volumes: Vec<f64> = entries.iter().map(volume).collect();
+    assert!(volumes.windows(2).all(|pair| pair[0] < pair[1]));
+
+    let recent = log.read_entries(None, 2).unwrap();
+    assert_eq!(recent.iter().map(volume).collect::<Vec<_>>(), [0.39, 0.4]);
+}
+
+#[tokio::test]
+async fn entries_can_be_filtered_by_time() {
+    let env = EventLogTestEnv::new();
+    let log = env.start(1024 * 1024, 2);
+
+    env.event_bus.emit(EventPayload::volume_changed(0.1));
+    wait_for(&log, 0.1).await;
+    let after_first: DateTime<Utc> = Utc::now();
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    env.event_bus.emit(EventPayload::volume_changed(0.2));
+    wait_for(&log, 0.2).await;
+
+    let entries = log.read_entries(Some(after_first), 10).unwrap();
+    assert_eq!(entries.iter().map(volume).collect::<Vec<_>>(), [0.2]);
+}
+
+#[test]
+fn since_accepts_timestamps_and_ages() {
+    let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+
+    assert_eq!(
+        parse_since("2024-05-31T22:30:00+02:00", now).unwrap(),
+        Utc.with_ymd_and_hms(2024, 5, 31, 20, 30, 0).unwrap()
+    );
+    assert_eq!(
+        parse_since("1717243200", now).unwrap(),
+        Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+    );
+    assert_eq!(
+        parse_since("30s", now).unwrap(),
+        now - Duration::seconds(30)
+    );
+    assert_eq!(
+        parse_since(" 15m ", now).unwrap(),
+        now - Duration::minutes(15)
+    );
+    assert_eq!(parse_since("2h", now).unwrap(), now - Duration::hours(2));
+    assert_eq!(parse_since("7d", now).unwrap(), now - Duration::days(7));
+
+    for invalid in ["", "m", "abc", "5x", "1.5h", "-h", "-5m", "yesterday"] {
+        assert!(
+            parse_since(invalid, now).is_err(),
+            "{:?} was accepted",
+            invalid
+        );
+    }
+}
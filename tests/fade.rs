@@ -0,0 +1,103 @@
+use hexendrum::audio::{Fade, FadeOut};
+use rodio::buffer::SamplesBuffer;
+use std::time::Duration;
+
+/// A stereo source at 1000 Hz holding `amplitude` for `frames` frames
+fn constant(frames: usize, amplitude: f32) -> SamplesBuffer<f32> {
+    SamplesBuffer::new(2, 1000, vec![amplitude; frames * 2])
+}
+
+fn assert_close(actual: &[f32], expected: &[f32]) {
+    assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+    for (index, (actual, expected)) in actual.iter().zip(expected).enumerate() {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "sample {}: {} rather than {}",
+            index,
+            actual,
+            expected
+        );
+    }
+}
+
+#[test]
+fn playback_ramps_in_over_whole_frames() {
+    // 10 ms at 1000 Hz: a ramp of 10 frames
+    let fade = Fade::new(constant(50, 0.5), Duration::from_millis(10), FadeOut::new());
+    let samples: Vec<f32> = fade.collect();
+    assert_eq!(samples.len(), 100, "nothing is dropped");
+
+    let ramp: Vec<f32> = (0..10)
+        .flat_map(|frame| [0.5 * frame as f32 / 10.0; 2])
+        .collect();
+    assert_close(&samples[..20], &ramp);
+    assert_eq!(samples[0], 0.0, "the first frame is silent");
+    assert!(samples[20..].iter().all(|sample| *sample == 0.5));
+
+    let continuing: Vec<f32> =
+        Fade::continuing(constant(5, 0.5), Duration::from_millis(10), FadeOut::new()).collect();
+    assert!(continuing.iter().all(|sample| *sample == 0.5));
+    let unramped: Vec<f32> = Fade::new(constant(5, 0.5), Duration::ZERO, FadeOut::new()).collect();
+    assert!(unramped.iter().all(|sample| *sample == 0.5));
+}
+
+#[test]
+fn stopping_ramps_out_from_the_next_frame_then_ends() {
+    let fade_out = FadeOut::new();
+    let mut fade = Fade::new(
+        constant(1000, 1.0),
+        Duration::from_millis(4),
+        fade_out.clone(),
+    );
+    let mut samples: Vec<f32> = fade.by_ref().take(21).collect();
+    // Triggered within frame 10, which keeps its gain
+    fade_out.trigger();
+    samples.extend(fade.by_ref());
+    assert!(fade.next().is_none());
+
+    assert_close(&samples[..8], &[0.0, 0.0, 0.25, 0.25, 0.5, 0.5, 0.75, 0.75]);
+    assert!(samples[8..22].iter().all(|sample| *sample == 1.0));
+    assert_close(
+        &samples[22..],
+        &[0.75, 0.75, 0.5, 0.5, 0.25, 0.25, 0.0, 0.0],
+    );
+}
+
+#[test]
+fn stopping_during_the_ramp_in_never_raises_the_level() {
+    let fade_out = FadeOut::new();
+    let mut fade = Fade::new(
+        constant(1000, 1.0),
+        Duration::from_millis(4),
+        fade_out.clone(),
+    );
+    let mut samples: Vec<f32> = fade.by_ref().take(6).collect();
+    fade_out.trigger();
+    samples.extend(fade);
+
+    // Frame 2 played at 0.5; the ramp-out goes down from there
+    assert_close(
+        &samples,
+        &[
+            0.0, 0.0, 0.25, 0.25, 0.5, 0.5, 0.375, 0.375, 0.25, 0.25, 0.125, 0.125, 0.0, 0.0,
+        ],
+    );
+}
+
+#[test]
+fn sources_not_started_when_stopped_never_play() {
+    let fade_out = FadeOut::new();
+    let queued = Fade::continuing(
+        constant(10, 1.0),
+        Duration::from_millis(4),
+        fade_out.clone(),
+    );
+    fade_out.trigger();
+    assert_eq!(queued.count(), 0);
+
+    let unramped = FadeOut::new();
+    let mut fade = Fade::new(constant(10, 1.0), Duration::ZERO, unramped.clone());
+    assert_eq!(fade.next(), Some(1.0));
+    unramped.trigger();
+    assert_eq!(fade.by_ref().count(), 1, "the frame under way is finished");
+}
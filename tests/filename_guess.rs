@@ -0,0 +1,76 @@
+use hexendrum::library::{guess_from_path, FilenameGuess};
+use std::path::Path;
+
+fn guess(title: Option<&str>, artist: Option<&str>) -> FilenameGuess {
+    FilenameGuess {
+        title: title.map(Into::into),
+        artist: artist.map(Into::into),
+    }
+}
+
+#[test]
+fn titles_and_artists_are_guessed_from_common_file_names() {
+    let cases = [
+        (
+            "/music/01 - Daft Punk - One More Time.mp3",
+            guess(Some("One More Time"), Some("Daft Punk")),
+        ),
+        (
+            "/music/Daft Punk - Digital Love.mp3",
+            guess(Some("Digital Love"), Some("Daft Punk")),
+        ),
+        (
+            "/music/Radiohead/OK Computer/03 Subterranean Homesick Alien.flac",
+            guess(Some("Subterranean Homesick Alien"), Some("Radiohead")),
+        ),
+        (
+            "/music/Radiohead/OK Computer/1-03 Subterranean Homesick Alien.flac",
+            guess(Some("Subterranean Homesick Alien"), Some("Radiohead")),
+        ),
+        (
+            "/music/Radiohead/OK Computer/3. Subterranean Homesick Alien.flac",
+            guess(Some("Subterranean Homesick Alien"), Some("Radiohead")),
+        ),
+        (
+            "/music/Radiohead/OK Computer/07) Airbag.flac",
+            guess(Some("Airbag"), Some("Radiohead")),
+        ),
+        (
+            "/downloads/05_-_The_Knife_-_Heartbeats.mp3",
+            guess(Some("Heartbeats"), Some("The Knife")),
+        ),
+        (
+            "/downloads/Portishead – Roads.ogg",
+            guess(Some("Roads"), Some("Portishead")),
+        ),
+        (
+            "/downloads/Massive Attack - Teardrop [Official Video] [u7K72X4eo_s].m4a",
+            guess(Some("Teardrop"), Some("Massive Attack")),
+        ),
+        (
+            "/downloads/Boards of Canada - 04 - Roygbiv.mp3",
+            guess(Some("Roygbiv"), Some("Boards of Canada")),
+        ),
+        (
+            "/music/Nena - 99 Luftballons - Live.mp3",
+            guess(Some("99 Luftballons - Live"), Some("Nena")),
+        ),
+        (
+            "/music/99 Luftballons.mp3",
+            guess(Some("99 Luftballons"), None),
+        ),
+        ("/music/Interlude.wav", guess(Some("Interlude"), None)),
+        // Not an artist/album layout
+        ("/home/alex/Music/04 Intro.mp3", guess(Some("Intro"), None)),
+        (
+            "/music/Various Artists/Now 42/12 - Song.mp3",
+            guess(Some("Song"), None),
+        ),
+        ("/music/Album/07.mp3", guess(None, None)),
+        ("/music/[2019].mp3", guess(Some("[2019]"), None)),
+    ];
+
+    for (path, expected) in cases {
+        assert_eq!(guess_from_path(Path::new(path)), expected, "{}", path);
+    }
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use common::TestTrack;
+use hexendrum::library::{genre_key, GenreIndex, GenreNormalizer, Track};
+use hexendrum::TrackMetadata;
+
+fn track(id: usize, genre: &str) -> Track {
+    TestTrack::new(id.to_string())
+        .metadata(TrackMetadata {
+            title: Some(format!("Song {}", id)),
+            artist: Some("Artist".into()),
+            genre: Some(genre.into()),
+            ..Default::default()
+        })
+        .build()
+}
+
+fn tracks(genres: &[&str]) -> Vec<Track> {
+    genres
+        .iter()
+        .enumerate()
+        .map(|(id, genre)| track(id, genre))
+        .collect()
+}
+
+#[test]
+fn keys_ignore_case_punctuation_and_spacing() {
+    for spelling in ["Hip-Hop", "hip hop", "HipHop", "HIP_HOP", " hip-hop "] {
+        assert_eq!(genre_key(spelling), "hiphop", "{}", spelling);
+    }
+    assert_eq!(genre_key("Drum & Bass"), genre_key("drum and bass"));
+    assert_eq!(genre_key("Électronique"), "electronique");
+    assert_eq!(genre_key("--"), "");
+}
+
+#[test]
+fn common_alias_clusters_share_a_canonical_name() {
+    let normalizer = GenreNormalizer::new();
+    let clusters: [(&str, &[&str]); 6] = [
+        (
+            "Hip-Hop",
+            &[
+                "Hip-Hop",
+                "hip hop",
+                "HipHop",
+                "Hip-Hop/Rap",
+                "Hip Hop & Rap",
+            ],
+        ),
+        (
+            "R&B",
+            &["R&B", "RnB", "r'n'b", "R and B", "Rhythm and Blues"],
+        ),
+        (
+            "Drum & Bass",
+            &["Drum & Bass", "drum and bass", "Drum'n'Bass", "DnB"],
+        ),
+        (
+            "Rock & Roll",
+            &["Rock and Roll", "rock 'n' roll", "Rock&Roll"],
+        ),
+        ("Electronic", &["electronic", "Electronica"]),
+        ("Soundtrack", &["OST", "Original Soundtrack", "soundtracks"]),
+    ];
+    for (canonical, spellings) in clusters {
+        for spelling in spellings {
+            assert_eq!(normalizer.alias(spelling), Some(canonical), "{}", spelling);
+        }
+    }
+    assert_eq!(
+        normalizer.alias("Rap"),
+        None,
+        "rap stays a genre of its own"
+    );
+    assert_eq!(normalizer.alias("Jazz"), None);
+}
+
+#[test]
+fn the_index_merges_spellings_and_keeps_raw_values() {
+    let library = tracks(&[
+        "Hip-Hop",
+        "hip hop",
+        "hip hop",
+        "HipHop",
+        "Hip-Hop/Rap",
+        "jazz",
+        "Jazz",
+        "jazz",
+        " ",
+    ]);
+    let index = GenreIndex::build(&library, &GenreNormalizer::new());
+
+    let genres = index.genres();
+    assert_eq!(genres.len(), 2);
+    assert_eq!(genres[0].name, "Hip-Hop");
+    assert_eq!(genres[0].track_count, 5);
+    assert_eq!(
+        genres[0].spellings,
+        vec!["hip hop", "Hip-Hop", "Hip-Hop/Rap", "HipHop"]
+    );
+    assert_eq!(
+        genres[1].name, "jazz",
+        "unaliased genres take the common spelling"
+    );
+    assert_eq!(genres[1].track_count, 3);
+
+    let raw = index.raw_genres();
+    let values: Vec<(&str, usize, &str)> = raw
+        .iter()
+        .map(|genre| {
+            (
+                genre.value.as_str(),
+                genre.track_count,
+                genre.genre.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        values,
+        vec![
+            ("Hip-Hop", 1, "Hip-Hop"),
+            ("Hip-Hop/Rap", 1, "Hip-Hop"),
+            ("HipHop", 1, "Hip-Hop"),
+            ("Jazz", 1, "jazz"),
+            ("hip hop", 2, "Hip-Hop"),
+            ("jazz", 2, "jazz"),
+        ]
+    );
+    assert_eq!(index.canonical("HipHop"), Some("Hip-Hop"));
+    assert_eq!(index.canonical("Polka"), None);
+    assert_eq!(
+        library[0].metadata.genre.as_deref(),
+        Some("Hip-Hop"),
+        "tracks keep their raw tag"
+    );
+}
+
+#[test]
+fn configured_aliases_extend_and_override_the_built_in_ones() {
+    let normalizer = GenreNormalizer::new().with_aliases([
+        ("Rap".to_string(), "Hip-Hop".to_string()),
+        ("electronica".to_string(), "IDM".to_string()),
+        ("Jazz".to_string(), "Jazz".to_string()),
+    ]);
+    assert_eq!(normalizer.alias("rap"), Some("Hip-Hop"));
+    assert_eq!(normalizer.alias("Electronica"), Some("IDM"));
+    assert_eq!(normalizer.alias("Electronic"), Some("Electronic"));
+
+    let index = GenreIndex::build(&tracks(&["jazz", "jazz", "RAP", "Hip Hop"]), &normalizer);
+    let names: Vec<(&str, usize)> = index
+        .genres()
+        .iter()
+        .map(|genre| (genre.name.as_str(), genre.track_count))
+        .collect();
+    assert_eq!(names, vec![("Hip-Hop", 2), ("Jazz", 2)]);
+}
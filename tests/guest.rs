@@ -0,0 +1,80 @@
+use axum::http::Method;
+use hexendrum::api::{guest_rule, GuestPolicy, GuestRule, RateLimiter};
+use hexendrum::config::ApiConfig;
+use std::time::{Duration, Instant};
+
+#[test]
+fn guests_may_browse_queue_skip_and_set_the_volume_only() {
+    assert_eq!(
+        guest_rule(&Method::GET, "/api/library/tracks"),
+        Some(GuestRule::Allow)
+    );
+    assert_eq!(
+        guest_rule(&Method::HEAD, "/api/library/albums/:id/artwork"),
+        Some(GuestRule::Allow)
+    );
+    assert_eq!(
+        guest_rule(&Method::POST, "/api/queue"),
+        Some(GuestRule::Enqueue)
+    );
+    assert_eq!(
+        guest_rule(&Method::POST, "/api/audio/next"),
+        Some(GuestRule::Allow)
+    );
+    assert_eq!(
+        guest_rule(&Method::POST, "/api/audio/volume"),
+        Some(GuestRule::Volume)
+    );
+
+    for (method, path) in [
+        (Method::DELETE, "/api/queue"),
+        (Method::POST, "/api/library/scan"),
+        (Method::DELETE, "/api/library/tracks/:id"),
+        (Method::POST, "/api/playlists"),
+        (Method::DELETE, "/api/playlists/:id"),
+        (Method::PUT, "/api/library/albums/:id/artwork"),
+        (Method::POST, "/api/maintenance"),
+        (Method::GET, "/api/health/doctor"),
+        (Method::GET, "/api/webhooks"),
+        (Method::POST, "/api/share"),
+        (Method::DELETE, "/api/share"),
+    ] {
+        assert_eq!(guest_rule(&method, path), None, "{} {}", method, path);
+    }
+}
+
+#[test]
+fn rate_limiter_allows_the_limit_per_window() {
+    let limiter = RateLimiter::new(2, Duration::from_secs(60));
+    let start = Instant::now();
+
+    assert_eq!(limiter.acquire_at(start), Ok(()));
+    assert_eq!(limiter.acquire_at(start + Duration::from_secs(10)), Ok(()));
+    assert_eq!(
+        limiter.acquire_at(start + Duration::from_secs(20)),
+        Err(Duration::from_secs(40))
+    );
+    // Refused attempts do not count
+    assert_eq!(limiter.acquire_at(start + Duration::from_secs(60)), Ok(()));
+    assert_eq!(
+        limiter.acquire_at(start + Duration::from_secs(61)),
+        Err(Duration::from_secs(9))
+    );
+    assert_eq!(limiter.acquire_at(start + Duration::from_secs(70)), Ok(()));
+}
+
+#[test]
+fn a_guest_token_needs_a_different_owner_token() {
+    let mut config = ApiConfig::default();
+    assert!(GuestPolicy::from_config(&config).unwrap().is_none());
+
+    config.guest_token = Some("party".into());
+    config.auth_token = Some("  ".into());
+    let error = GuestPolicy::from_config(&config).err().unwrap();
+    assert!(error.to_string().contains("api.auth_token"), "{}", error);
+    config.auth_token = Some("party".into());
+    assert!(GuestPolicy::from_config(&config).is_err());
+
+    config.auth_token = Some("owner".into());
+    assert!(GuestPolicy::from_config(&config).unwrap().is_some());
+}
@@ -0,0 +1,85 @@
+use hexendrum::config::{ApiConfig, Paths};
+use hexendrum::instance::{is_process_alive, InstanceInfo, InstanceLock, LockOutcome};
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+struct InstanceTestEnv {
+    _workspace: TempDir,
+    paths: Paths,
+}
+
+impl InstanceTestEnv {
+    fn new() -> Self {
+        let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+        Self {
+            paths: Paths::portable(workspace.path().join("data")),
+            _workspace: workspace,
+        }
+    }
+
+    fn acquire(&self, info: &InstanceInfo) -> LockOutcome {
+        InstanceLock::acquire(&self.paths.instance_lock_file(), info)
+            .expect("lock file should be usable")
+    }
+}
+
+fn api_config(port: u16) -> ApiConfig {
+    ApiConfig {
+        port,
+        ..ApiConfig::default()
+    }
+}
+
+#[test]
+fn second_instance_sees_the_running_one() {
+    let env = InstanceTestEnv::new();
+    let info = InstanceInfo::current(&api_config(4040));
+
+    let lock = match env.acquire(&info) {
+        LockOutcome::Acquired(lock) => lock,
+        LockOutcome::Held(other) => panic!("lock unexpectedly held by {:?}", other),
+    };
+    assert_eq!(lock.path(), env.paths.instance_lock_file());
+
+    let second = InstanceInfo::current(&api_config(5050));
+    match env.acquire(&second) {
+        LockOutcome::Held(Some(other)) => {
+            assert_eq!(other, info);
+            assert_eq!(other.address(), "http://127.0.0.1:4040");
+        }
+        other => panic!("expected the lock to be held, got {:?}", other),
+    }
+
+    // Releasing the lock lets the next instance in.
+    drop(lock);
+    assert!(matches!(env.acquire(&second), LockOutcome::Acquired(_)));
+}
+
+#[test]
+fn addresses_follow_the_api_configuration() {
+    let socket = PathBuf::from("/run/hexendrum.sock");
+    let unix_only = ApiConfig {
+        listen_tcp: false,
+        unix_socket: Some(socket.clone()),
+        ..ApiConfig::default()
+    };
+    let info = InstanceInfo::current(&unix_only);
+    assert_eq!(info.port, None);
+    assert_eq!(info.address(), "unix:/run/hexendrum.sock");
+
+    let both = ApiConfig {
+        unix_socket: Some(socket),
+        ..api_config(3031)
+    };
+    assert_eq!(
+        InstanceInfo::current(&both).address(),
+        "http://127.0.0.1:3031"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn process_liveness_is_detected() {
+    assert!(is_process_alive(std::process::id()));
+    assert!(!is_process_alive(u32::MAX));
+}
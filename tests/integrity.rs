@@ -0,0 +1,116 @@
+mod common;
+
+use common::{TestTrack, Wav};
+use hexendrum::library::{
+    verify_file, IntegrityStatus, StatsStore, Track, VerificationJob, VerifyProgress,
+};
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+
+/// Write a mono 16-bit WAV whose header declares `declared_len` bytes of audio data
+/// but which only contains `written_len` bytes.
+fn write_wav(path: &Path, declared_len: u32, written_len: u32) {
+    Wav::new()
+        .silent_frames(written_len / 2)
+        .declared_len(declared_len)
+        .write(path);
+}
+
+fn track(path: &Path) -> Track {
+    TestTrack::new(path.file_stem().unwrap().to_string_lossy())
+        .path(path)
+        .build()
+}
+
+async fn run_job(tracks: Vec<Track>, stats: Arc<StatsStore>) -> Vec<VerifyProgress> {
+    let job = Arc::new(VerificationJob::new());
+    let reports = Arc::new(Mutex::new(Vec::new()));
+    let sink = reports.clone();
+
+    assert!(job.start(tracks, stats, move |progress| {
+        sink.lock().unwrap().push(progress.clone());
+    }));
+
+    let deadline = std::time::Instant::now() + Duration::from_secs(10);
+    while job.is_running() {
+        assert!(
+            std::time::Instant::now() < deadline,
+            "verification did not finish"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    let reports = reports.lock().unwrap().clone();
+    reports
+}
+
+#[test]
+fn complete_wav_verifies_ok() {
+    let workspace = TempDir::new().unwrap();
+    let path = workspace.path().join("complete.wav");
+    write_wav(&path, 16000, 16000);
+
+    let check = verify_file(&path);
+    assert_eq!(check.status, IntegrityStatus::Ok, "{:?}", check.error);
+}
+
+#[test]
+fn truncated_and_invalid_files_are_corrupt() {
+    let workspace = TempDir::new().unwrap();
+
+    let truncated = workspace.path().join("truncated.wav");
+    write_wav(&truncated, 160000, 16000);
+    let check = verify_file(&truncated);
+    assert_eq!(check.status, IntegrityStatus::Corrupt);
+    assert!(check.error.unwrap().contains("truncated"));
+
+    let garbage = workspace.path().join("garbage.mp3");
+    fs::write(&garbage, b"definitely not audio").unwrap();
+    assert_eq!(verify_file(&garbage).status, IntegrityStatus::Corrupt);
+}
+
+#[test]
+fn missing_file_is_unreadable() {
+    let workspace = TempDir::new().unwrap();
+    let check = verify_file(&workspace.path().join("missing.flac"));
+    assert_eq!(check.status, IntegrityStatus::Unreadable);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn job_records_failures_and_skips_unchanged_files() {
+    let workspace = TempDir::new().unwrap();
+    let good = workspace.path().join("good.wav");
+    let bad = workspace.path().join("bad.wav");
+    write_wav(&good, 16000, 16000);
+    write_wav(&bad, 160000, 16000);
+
+    let stats_path = workspace.path().join("stats.json");
+    let stats = Arc::new(StatsStore::with_path(stats_path.clone()));
+    let tracks = vec![track(&good), track(&bad)];
+
+    let reports = run_job(tracks.clone(), stats.clone()).await;
+    assert_eq!(reports.first().unwrap().status, "started");
+    let last = reports.last().unwrap();
+    assert_eq!(last.status, "completed");
+    assert_eq!((last.processed, last.skipped, last.failed), (2, 0, 1));
+
+    let failures = stats.integrity_failures();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].0, bad);
+    assert_eq!(failures[0].1.status, IntegrityStatus::Corrupt);
+
+    // Results survive a restart, and verified-ok files are skipped on the next run
+    // while failed ones are checked again.
+    let reloaded = Arc::new(StatsStore::with_path(stats_path));
+    assert_eq!(
+        reloaded.integrity(&good).map(|record| record.status),
+        Some(IntegrityStatus::Ok)
+    );
+
+    let reports = run_job(tracks, reloaded).await;
+    let last = reports.last().unwrap();
+    assert_eq!((last.processed, last.skipped, last.failed), (2, 1, 1));
+}
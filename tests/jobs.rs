@@ -0,0 +1,157 @@
+use hexendrum::api::{Job, JobError, JobKind, JobManager, JobState};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::sync::oneshot;
+
+/// Wait until job `id` has finished
+async fn finished(jobs: &JobManager, id: u64) -> Job {
+    for _ in 0..100 {
+        let job = jobs.get(id).expect("the job is kept");
+        if job.state.is_finished() {
+            return job;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("job {} did not finish", id);
+}
+
+#[tokio::test]
+async fn one_job_of_a_kind_runs_at_a_time() {
+    let jobs = Arc::new(JobManager::new());
+    let (release, released) = oneshot::channel::<()>();
+    let scan = jobs
+        .start(
+            JobKind::Scan,
+            json!({ "directories": ["/music"] }),
+            |_| async move {
+                released.await?;
+                Ok(json!({ "tracks": 3 }))
+            },
+        )
+        .unwrap();
+    assert_eq!(scan.id, 1);
+    assert!(scan.cancellable);
+
+    let busy = jobs.start(JobKind::Scan, Value::Null, |_| async { Ok(()) });
+    assert!(matches!(busy, Err(JobError::Busy(JobKind::Scan))));
+    let other = jobs
+        .run(JobKind::Maintenance, Value::Null, |_| async { Ok(7) })
+        .await
+        .expect("other kinds are not held up");
+    assert_eq!(other.unwrap(), 7);
+
+    release.send(()).unwrap();
+    let scan = finished(&jobs, scan.id).await;
+    assert_eq!(scan.state, JobState::Completed);
+    assert_eq!(scan.result, Some(json!({ "tracks": 3 })));
+    assert_eq!(scan.params["directories"][0], "/music");
+    assert!(scan.started_at.is_some() && scan.finished_at.is_some());
+    assert!(jobs
+        .start(JobKind::Scan, Value::Null, |_| async { Ok(()) })
+        .is_ok());
+}
+
+#[tokio::test]
+async fn cancelled_jobs_stop_and_failed_ones_keep_their_error() {
+    let jobs = Arc::new(JobManager::new());
+    let verify = jobs
+        .start(JobKind::Verify, Value::Null, |handle| async move {
+            handle.progress(1, Some(10), Some("first".into()));
+            handle.cancelled().await;
+            Ok(json!({ "processed": 1 }))
+        })
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert_eq!(jobs.get(verify.id).unwrap().progress.current, 1);
+
+    let cancelling = jobs.cancel(verify.id).unwrap();
+    assert!(cancelling.cancel_requested);
+    let verify = finished(&jobs, verify.id).await;
+    assert_eq!(verify.state, JobState::Cancelled);
+    assert_eq!(verify.result, Some(json!({ "processed": 1 })));
+    assert!(matches!(jobs.cancel(verify.id), Err(JobError::Finished(_))));
+    assert!(matches!(jobs.cancel(99), Err(JobError::NotFound(99))));
+
+    let failed = jobs
+        .run(JobKind::Maintenance, Value::Null, |_| async {
+            Err::<(), _>(anyhow::anyhow!("disk full"))
+        })
+        .await
+        .unwrap();
+    assert!(failed.is_err());
+    let maintenance = jobs.latest(JobKind::Maintenance).unwrap();
+    assert_eq!(maintenance.state, JobState::Failed);
+    assert_eq!(maintenance.error.as_deref(), Some("disk full"));
+}
+
+#[tokio::test]
+async fn maintenance_cannot_be_cancelled() {
+    let jobs = Arc::new(JobManager::new());
+    let (release, released) = oneshot::channel::<()>();
+    let maintenance = jobs
+        .start(JobKind::Maintenance, Value::Null, |_| async move {
+            released.await?;
+            Ok(())
+        })
+        .unwrap();
+    assert!(matches!(
+        jobs.cancel(maintenance.id),
+        Err(JobError::NotCancellable(JobKind::Maintenance))
+    ));
+    release.send(()).unwrap();
+    assert_eq!(
+        finished(&jobs, maintenance.id).await.state,
+        JobState::Completed
+    );
+}
+
+#[tokio::test]
+async fn finished_jobs_outside_the_history_window_are_forgotten() {
+    let jobs = Arc::new(JobManager::new().with_history(Duration::from_millis(100)));
+    for _ in 0..2 {
+        jobs.run(JobKind::Waveforms, Value::Null, |_| async { Ok(()) })
+            .await
+            .unwrap()
+            .unwrap();
+    }
+    jobs.run(JobKind::Maintenance, Value::Null, |_| async { Ok(()) })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        jobs.list().iter().map(|job| job.id).collect::<Vec<_>>(),
+        vec![3, 2, 1]
+    );
+
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(
+        jobs.list().iter().map(|job| job.id).collect::<Vec<_>>(),
+        vec![3, 2],
+        "the latest job of each kind is kept"
+    );
+}
+
+#[tokio::test]
+async fn the_history_survives_restarts_when_saved() {
+    let workspace = TempDir::new().unwrap();
+    let history_file = workspace.path().join("cache").join("jobs.json");
+    let jobs = Arc::new(JobManager::new().with_history_file(history_file.clone()));
+    jobs.run(JobKind::Scan, json!({ "directories": [] }), |_| async {
+        Ok(json!({ "tracks": 0 }))
+    })
+    .await
+    .unwrap()
+    .unwrap();
+    drop(jobs);
+
+    let jobs = Arc::new(JobManager::new().with_history_file(history_file));
+    let scan = jobs.latest(JobKind::Scan).expect("the scan was saved");
+    assert_eq!(scan.state, JobState::Completed);
+    assert_eq!(scan.result, Some(json!({ "tracks": 0 })));
+    let next = jobs
+        .start(JobKind::Verify, Value::Null, |_| async { Ok(()) })
+        .unwrap();
+    assert_eq!(next.id, 2, "ids continue after the saved ones");
+}
@@ -1,45 +1,38 @@
-use hexendrum::library::Library;
-use serial_test::serial;
+mod common;
+
+use chrono::{DateTime, Utc};
+use common::Wav;
+use hexendrum::audio::{is_supported_audio_format, AudioProbe, TechnicalInfo};
+use hexendrum::config::{LibraryConfig, Paths};
+use hexendrum::library::{
+    content_fingerprint, sidecar_path, track_identifier, AudioProber, Collator, Library,
+    LibraryError, ReadOnlyPaths, ScanConflict, ScanInProgressError, SidecarMetadata, Track,
+    TrackTagUpdate, FINGERPRINT_CHUNK,
+};
+use serde_json::json;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
 use tempfile::TempDir;
 
 struct LibraryTestEnv {
     _workspace: TempDir,
     music_dir: PathBuf,
-    cache_dir: PathBuf,
-    old_cache: Option<String>,
-    old_config: Option<String>,
-    old_home: Option<String>,
+    paths: Paths,
 }
 
 impl LibraryTestEnv {
     fn new() -> Self {
         let workspace = tempfile::tempdir().expect("failed to create temp workspace");
         let music_dir = workspace.path().join("music");
-        let cache_dir = workspace.path().join("cache");
-        let config_dir = workspace.path().join("config");
-
         fs::create_dir(&music_dir).expect("failed to create music dir");
-        fs::create_dir(&cache_dir).expect("failed to create cache dir");
-        fs::create_dir(&config_dir).expect("failed to create config dir");
-
-        let old_cache = std::env::var("XDG_CACHE_HOME").ok();
-        std::env::set_var("XDG_CACHE_HOME", &cache_dir);
-
-        let old_config = std::env::var("XDG_CONFIG_HOME").ok();
-        std::env::set_var("XDG_CONFIG_HOME", &config_dir);
-
-        let old_home = std::env::var("HOME").ok();
-        std::env::set_var("HOME", workspace.path());
 
         Self {
+            paths: Paths::portable(workspace.path().join("data")),
             _workspace: workspace,
             music_dir,
-            cache_dir,
-            old_cache,
-            old_config,
-            old_home,
         }
     }
 
@@ -47,8 +40,12 @@ impl LibraryTestEnv {
         self.music_dir.clone()
     }
 
-    fn cache_file(&self) -> PathBuf {
-        self.cache_dir.join("hexendrum").join("library_cache.json")
+    fn library(&self) -> Library {
+        Library::with_paths(&self.paths)
+    }
+
+    fn fingerprinted_library(&self) -> Library {
+        Library::with_content_fingerprints(&self.paths, true)
     }
 
     fn create_audio_file<P: AsRef<Path>>(&self, name: P) -> PathBuf {
@@ -58,35 +55,12 @@ impl LibraryTestEnv {
     }
 }
 
-impl Drop for LibraryTestEnv {
-    fn drop(&mut self) {
-        if let Some(old_cache) = &self.old_cache {
-            std::env::set_var("XDG_CACHE_HOME", old_cache);
-        } else {
-            std::env::remove_var("XDG_CACHE_HOME");
-        }
-
-        if let Some(old_config) = &self.old_config {
-            std::env::set_var("XDG_CONFIG_HOME", old_config);
-        } else {
-            std::env::remove_var("XDG_CONFIG_HOME");
-        }
-
-        if let Some(old_home) = &self.old_home {
-            std::env::set_var("HOME", old_home);
-        } else {
-            std::env::remove_var("HOME");
-        }
-    }
-}
-
 #[test]
-#[serial]
 fn scan_directories_discovers_audio_files_and_populates_cache() {
     let env = LibraryTestEnv::new();
     let track_path = env.create_audio_file("sample.mp3");
 
-    let library = Library::new();
+    let library = env.library();
     library
         .scan_directories(&[env.music_dir()])
         .expect("scan should succeed");
@@ -113,7 +87,7 @@ fn scan_directories_discovers_audio_files_and_populates_cache() {
         "scan should not report in-progress after completion"
     );
 
-    let cache_file = env.cache_file();
+    let cache_file = env.paths.library_cache_file();
     assert!(
         cache_file.exists(),
         "library cache should be written after scan"
@@ -129,13 +103,12 @@ fn scan_directories_discovers_audio_files_and_populates_cache() {
 }
 
 #[test]
-#[serial]
 fn remove_track_updates_library_state() {
     let env = LibraryTestEnv::new();
     let keep_path = env.create_audio_file("keep.mp3");
     let remove_path = env.create_audio_file("remove.mp3");
 
-    let library = Library::new();
+    let library = env.library();
     library
         .scan_directories(&[env.music_dir()])
         .expect("scan should succeed");
@@ -175,3 +148,1085 @@ fn remove_track_updates_library_state() {
         "remaining track should still be present"
     );
 }
+
+/// Move the modification time of every file in `dir` an hour ahead, as a backup tool
+/// restoring the files would.
+fn touch_all(dir: &Path) {
+    let later = SystemTime::now() + Duration::from_secs(3600);
+    for entry in fs::read_dir(dir).unwrap() {
+        let file = fs::File::options()
+            .write(true)
+            .open(entry.unwrap().path())
+            .unwrap();
+        file.set_modified(later).unwrap();
+    }
+}
+
+#[test]
+fn touched_files_keep_their_cache_entries_when_fingerprinted() {
+    let env = LibraryTestEnv::new();
+    for index in 0..5u8 {
+        let content = vec![index; 3 * FINGERPRINT_CHUNK as usize];
+        fs::write(env.music_dir.join(format!("{}.mp3", index)), content).unwrap();
+    }
+
+    let library = env.fingerprinted_library();
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+    let mut ids = library.get_track_ids();
+    ids.sort();
+    assert_eq!(ids.len(), 5);
+
+    touch_all(&env.music_dir);
+
+    // Without fingerprints every touched file looks modified
+    assert_eq!(env.library().track_count(), 0);
+
+    let reloaded = env.fingerprinted_library();
+    let mut reloaded_ids = reloaded.get_track_ids();
+    reloaded_ids.sort();
+    assert_eq!(reloaded_ids, ids);
+
+    // The new modification times were written back to the cache
+    assert_eq!(env.library().track_count(), 5);
+
+    // A file whose end changed is probed again even though its size did not
+    let changed = env.music_dir.join("3.mp3");
+    let mut content = fs::read(&changed).unwrap();
+    *content.last_mut().unwrap() = 0xff;
+    fs::write(&changed, content).unwrap();
+    touch_all(&env.music_dir);
+
+    let reloaded = env.fingerprinted_library();
+    assert_eq!(reloaded.track_count(), 4);
+    assert!(reloaded.get_track_by_path(&changed).is_none());
+}
+
+#[test]
+fn fingerprints_cover_size_and_both_ends_of_a_file() {
+    let env = LibraryTestEnv::new();
+    let chunk = FINGERPRINT_CHUNK as usize;
+    let original = env.music_dir.join("original.flac");
+    let content: Vec<u8> = (0..3 * chunk).map(|index| (index % 251) as u8).collect();
+    fs::write(&original, &content).unwrap();
+    let fingerprint = content_fingerprint(&original).unwrap();
+
+    let copy = env.music_dir.join("copy.flac");
+    fs::copy(&original, &copy).unwrap();
+    assert_eq!(content_fingerprint(&copy).unwrap(), fingerprint);
+
+    let variant = |name: &str, change: &dyn Fn(&mut Vec<u8>)| {
+        let mut changed = content.clone();
+        change(&mut changed);
+        let path = env.music_dir.join(name);
+        fs::write(&path, changed).unwrap();
+        content_fingerprint(&path).unwrap()
+    };
+    assert_ne!(variant("head.flac", &|bytes| bytes[10] ^= 1), fingerprint);
+    assert_ne!(
+        variant("tail.flac", &|bytes| *bytes.last_mut().unwrap() ^= 1),
+        fingerprint
+    );
+    assert_ne!(variant("longer.flac", &|bytes| bytes.push(0)), fingerprint);
+    // The middle is not read
+    assert_eq!(
+        variant("middle.flac", &|bytes| bytes[chunk + chunk / 2] ^= 1),
+        fingerprint
+    );
+
+    let small = env.create_audio_file("small.mp3");
+    assert_eq!(
+        content_fingerprint(&small).unwrap(),
+        content_fingerprint(&small).unwrap()
+    );
+    assert!(content_fingerprint(&env.music_dir.join("missing.mp3")).is_err());
+}
+
+#[test]
+fn refresh_updates_the_library_in_place() {
+    let env = LibraryTestEnv::new();
+    let kept = env.create_audio_file("kept.mp3");
+    let modified = env.create_audio_file("modified.mp3");
+    let deleted = env.create_audio_file("deleted.mp3");
+
+    let library = env.library();
+    library.scan_directories(&[env.music_dir()]).unwrap();
+    let kept_id = library.get_track_by_path(&kept).unwrap().id;
+    let modified_id = library.get_track_by_path(&modified).unwrap().id;
+
+    fs::remove_file(&deleted).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&modified)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(3600))
+        .unwrap();
+    let added = env.create_audio_file("added.mp3");
+
+    let report = library.refresh(&[env.music_dir()]).unwrap();
+    assert_eq!((report.added, report.updated, report.removed), (1, 1, 1));
+
+    assert_eq!(library.track_count(), 3);
+    assert_eq!(library.get_track_by_path(&kept).unwrap().id, kept_id);
+    assert_eq!(
+        library.get_track_by_path(&modified).unwrap().id,
+        modified_id
+    );
+    assert!(library.get_track_by_path(&added).is_some());
+    assert!(library.get_track_by_path(&deleted).is_none());
+
+    assert!(library.refresh(&[env.music_dir()]).unwrap().is_empty());
+    assert_eq!(env.library().track_count(), 3);
+}
+
+#[test]
+fn scans_merge_into_the_library_keeping_unchanged_tracks() {
+    let env = LibraryTestEnv::new();
+    let first_dir = env.music_dir().join("a");
+    let second_dir = env.music_dir().join("b");
+    fs::create_dir(&first_dir).unwrap();
+    fs::create_dir(&second_dir).unwrap();
+    let kept = env.create_audio_file("a/kept.mp3");
+    let modified = env.create_audio_file("a/modified.mp3");
+    let deleted = env.create_audio_file("a/deleted.mp3");
+    let elsewhere = env.create_audio_file("b/elsewhere.mp3");
+
+    let library = env.library();
+    library
+        .scan_directories(std::slice::from_ref(&first_dir))
+        .unwrap();
+    let report = library
+        .scan_directories(std::slice::from_ref(&second_dir))
+        .unwrap();
+    assert_eq!(report.tracks, 4, "scanning b keeps what was found in a");
+    let kept_id = library.get_track_by_path(&kept).unwrap().id;
+    let modified_id = library.get_track_by_path(&modified).unwrap().id;
+    let elsewhere_id = library.get_track_by_path(&elsewhere).unwrap().id;
+
+    fs::remove_file(&deleted).unwrap();
+    fs::remove_file(&elsewhere).unwrap();
+    fs::write(sidecar_path(&modified), r#"{"title": "Modified"}"#).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&modified)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(3600))
+        .unwrap();
+    let added = env.create_audio_file("a/added.mp3");
+    let sequence = library.change_sequence();
+
+    let report = library
+        .scan_directories(&[first_dir.clone(), env.music_dir().join("missing")])
+        .unwrap();
+    assert_eq!(report.tracks, 4);
+    assert_eq!(library.get_track_by_path(&kept).unwrap().id, kept_id);
+    let track = library.get_track_by_path(&modified).unwrap();
+    assert_eq!(track.id, modified_id);
+    assert_eq!(track.metadata.title.as_deref(), Some("Modified"));
+    assert!(library.get_track_by_path(&added).is_some());
+    assert!(library.get_track_by_path(&deleted).is_none());
+    assert_eq!(
+        library.get_track_by_path(&elsewhere).unwrap().id,
+        elsewhere_id,
+        "only tracks under the scanned directories are dropped"
+    );
+    assert_eq!(library.change_sequence(), sequence + 1);
+
+    library
+        .scan_directories(std::slice::from_ref(&first_dir))
+        .unwrap();
+    assert_eq!(
+        library.change_sequence(),
+        sequence + 1,
+        "a scan finding nothing new changes nothing"
+    );
+    assert_eq!(env.library().get_track_by_path(&kept).unwrap().id, kept_id);
+
+    let report = library.scan_directories_with(&[first_dir], true).unwrap();
+    assert_eq!(report.tracks, 3, "a full rescan replaces the library");
+    assert_eq!(
+        library.get_track_by_path(&kept).unwrap().id,
+        kept_id,
+        "ids derive from paths, so even full rescans keep them"
+    );
+    assert!(library.get_track_by_path(&elsewhere).is_none());
+}
+
+#[test]
+fn untagged_files_get_titles_and_artists_guessed_from_their_paths() {
+    let env = LibraryTestEnv::new();
+    let album_dir = env.music_dir.join("Radiohead").join("OK Computer");
+    fs::create_dir_all(&album_dir).unwrap();
+    let airbag = env.create_audio_file(album_dir.join("01 Airbag.mp3"));
+    let roads = env.create_audio_file("02 - Portishead - Roads.mp3");
+    let interlude = env.create_audio_file("Interlude.mp3");
+
+    let library = env.library();
+    library.scan_directories(&[env.music_dir()]).unwrap();
+
+    let airbag = library.get_track_by_path(&airbag).unwrap();
+    assert_eq!(airbag.metadata.title.as_deref(), Some("Airbag"));
+    assert_eq!(airbag.metadata.artist.as_deref(), Some("Radiohead"));
+    assert!(airbag.metadata.guessed.title && airbag.metadata.guessed.artist);
+    assert_eq!(airbag.display_name(), "Radiohead - Airbag");
+    let interlude = library.get_track_by_path(&interlude).unwrap();
+    assert_eq!(interlude.metadata.title.as_deref(), Some("Interlude"));
+    assert!(!interlude.metadata.guessed.artist);
+
+    assert_eq!(library.search_tracks("portishead").len(), 1);
+    assert_eq!(library.get_tracks_by_artist("Radiohead").len(), 1);
+    // Guessed artists are only listed on request
+    assert!(library.get_artists().is_empty());
+    let listing = env.library().with_guessed_artists(true);
+    listing.scan_directories(&[env.music_dir()]).unwrap();
+    assert_eq!(listing.get_artists(), vec!["Portishead", "Radiohead"]);
+
+    // A real tag replaces the guess
+    let roads = library.get_track_by_path(&roads).unwrap();
+    let update = SidecarMetadata {
+        artist: Some("Portishead".into()),
+        ..Default::default()
+    };
+    let roads = library.update_track_sidecar(&roads.id, update).unwrap();
+    assert!(roads.metadata.guessed.title && !roads.metadata.guessed.artist);
+    assert_eq!(library.get_artists(), vec!["Portishead"]);
+}
+
+#[test]
+fn artists_are_listed_in_the_order_of_the_sort_locale() {
+    let env = LibraryTestEnv::new();
+    for artist in ["Zola Jesus", "Édith Piaf", "Åsa Jinder", "ABBA"] {
+        let album_dir = env.music_dir.join(artist).join("Album");
+        fs::create_dir_all(&album_dir).unwrap();
+        env.create_audio_file(album_dir.join("01 Song.mp3"));
+    }
+
+    let root = env.library().with_guessed_artists(true);
+    root.scan_directories(&[env.music_dir()]).unwrap();
+    assert_eq!(
+        root.get_artists(),
+        vec!["ABBA", "Åsa Jinder", "Édith Piaf", "Zola Jesus"]
+    );
+
+    let swedish = env
+        .library()
+        .with_guessed_artists(true)
+        .with_collator(Collator::for_locale(Some("sv-SE")));
+    swedish.scan_directories(&[env.music_dir()]).unwrap();
+    assert_eq!(
+        swedish.get_artists(),
+        vec!["ABBA", "Édith Piaf", "Zola Jesus", "Åsa Jinder"]
+    );
+}
+
+/// Write a silent 16-bit mono WAV file of 0.1 seconds at `sample_rate`
+fn write_wav(path: &Path, sample_rate: u32) {
+    Wav::new()
+        .sample_rate(sample_rate)
+        .silent_frames(sample_rate / 10)
+        .write(path);
+}
+
+/// Write a FLAC file stating `seconds` of audio: the stream header and the header of
+/// its first frame, which is all a probe reads
+fn write_flac(path: &Path, sample_rate: u32, channels: u8, bits: u8, seconds: u64) {
+    let samples = sample_rate as u64 * seconds;
+    let format = (sample_rate as u64) << 44
+        | ((channels - 1) as u64) << 41
+        | ((bits - 1) as u64) << 36
+        | samples;
+    let stream_info = [
+        &4096u16.to_be_bytes()[..],
+        &4096u16.to_be_bytes(),
+        &[0; 6],
+        &format.to_be_bytes(),
+        &[0; 16],
+    ]
+    .concat();
+    let header = [0x80, 0, 0, stream_info.len() as u8];
+
+    // Fixed blocks of 4096 samples, with the sample rate and size of the stream header
+    let mut frame = vec![0xFF, 0xF8, 0xC0, (channels - 1) << 4, 0];
+    let crc8 = frame.iter().fold(0u8, |crc, byte| {
+        (0..8).fold(crc ^ byte, |crc, _| {
+            if crc & 0x80 != 0 {
+                (crc << 1) ^ 0x07
+            } else {
+                crc << 1
+            }
+        })
+    });
+    frame.push(crc8);
+
+    let bytes = [b"fLaC".as_slice(), &header, &stream_info, &frame].concat();
+    fs::write(path, bytes).unwrap();
+}
+
+#[test]
+fn technical_details_are_read_while_scanning_and_searchable() {
+    let env = LibraryTestEnv::new();
+    let flac = env.music_dir.join("Hi-Res Song.flac");
+    write_flac(&flac, 96_000, 2, 24, 10);
+    let wav = env.music_dir.join("Phone Song.wav");
+    write_wav(&wav, 8_000);
+
+    let library = env.library();
+    library.scan_directories(&[env.music_dir()]).unwrap();
+
+    let flac = library.get_track_by_path(&flac).unwrap();
+    assert_eq!(flac.metadata.duration, Some(10));
+    let technical = flac.metadata.technical.unwrap();
+    assert_eq!(technical.codec.as_deref(), Some("flac"));
+    assert_eq!(technical.sample_rate, Some(96_000));
+    assert_eq!(technical.channels, Some(2));
+    assert_eq!(technical.bits_per_sample, Some(24));
+
+    let wav = library.get_track_by_path(&wav).unwrap();
+    let technical = wav.metadata.technical.unwrap();
+    assert_eq!(technical.codec.as_deref(), Some("pcm_s16le"));
+    assert_eq!(technical.sample_rate, Some(8_000));
+    assert_eq!(technical.channels, Some(1));
+    assert_eq!(technical.bits_per_sample, Some(16));
+    // 1644 bytes over 0.1 seconds
+    assert_eq!(technical.bitrate_kbps, Some(132));
+
+    let titles = |query: &str| {
+        let mut titles: Vec<String> = library
+            .search_tracks(query)
+            .into_iter()
+            .filter_map(|track| track.metadata.title)
+            .collect();
+        titles.sort();
+        titles
+    };
+    assert_eq!(titles("format:flac"), vec!["Hi-Res Song"]);
+    assert_eq!(titles("format:FLAC song"), vec!["Hi-Res Song"]);
+    assert_eq!(titles("format:wav"), vec!["Phone Song"]);
+    assert_eq!(titles("format:pcm"), vec!["Phone Song"]);
+    assert_eq!(titles("samplerate:>48000"), vec!["Hi-Res Song"]);
+    assert_eq!(titles("samplerate:<=44.1k"), vec!["Phone Song"]);
+    assert_eq!(titles("samplerate:96khz"), vec!["Hi-Res Song"]);
+    assert_eq!(
+        titles("samplerate:>=8000 song"),
+        vec!["Hi-Res Song", "Phone Song"]
+    );
+    assert!(titles("format:flac phone").is_empty());
+    // Unknown or malformed filters are searched as text
+    assert!(titles("samplerate:fast").is_empty());
+    assert_eq!(titles("song"), vec!["Hi-Res Song", "Phone Song"]);
+}
+
+#[test]
+fn caches_without_technical_details_have_them_read_on_load() {
+    let env = LibraryTestEnv::new();
+    let flac = env.music_dir.join("song.flac");
+    write_flac(&flac, 44_100, 2, 16, 60);
+    let library = env.library();
+    library.scan_directories(&[env.music_dir()]).unwrap();
+    let id = library.get_track_by_path(&flac).unwrap().id;
+
+    // A cache written before technical details were recorded
+    let cache_file = env.paths.library_cache_file();
+    let mut cache: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+    assert_eq!(cache["version"], 3);
+    cache.as_object_mut().unwrap().remove("version");
+    cache["tracks"][0]["track"]["metadata"]
+        .as_object_mut()
+        .unwrap()
+        .remove("technical");
+    fs::write(&cache_file, cache.to_string()).unwrap();
+
+    let reloaded = env.library();
+    let track = reloaded.get_track(&id).expect("cached track keeps its id");
+    assert_eq!(track.metadata.technical.unwrap().sample_rate, Some(44_100));
+    let cache: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+    assert_eq!(cache["version"], 3);
+    assert_eq!(
+        cache["tracks"][0]["track"]["metadata"]["technical"]["codec"],
+        "flac"
+    );
+}
+
+#[test]
+fn track_ids_derive_from_paths_and_random_ones_are_replaced_on_load() {
+    let env = LibraryTestEnv::new();
+    let song = env.create_audio_file("song.mp3");
+    let other = env.create_audio_file("other.mp3");
+    let library = env.library();
+    library.scan_directories(&[env.music_dir()]).unwrap();
+    let id = library.get_track_by_path(&song).unwrap().id;
+    assert_eq!(id, track_identifier(&song));
+    assert_eq!(id.len(), 64);
+    assert_ne!(id, library.get_track_by_path(&other).unwrap().id);
+    // Spelled differently, the same file has the same id
+    assert_eq!(
+        track_identifier(&env.music_dir().join("..").join("music").join("song.mp3")),
+        id
+    );
+
+    // A fresh library scanning the same files from scratch agrees
+    fs::remove_file(env.paths.library_cache_file()).unwrap();
+    let rebuilt = env.library();
+    rebuilt
+        .scan_directories_with(&[env.music_dir()], true)
+        .unwrap();
+    assert_eq!(rebuilt.get_track_by_path(&song).unwrap().id, id);
+
+    // A cache written when ids were random
+    let cache_file = env.paths.library_cache_file();
+    let mut cache: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+    let old_id = "550e8400-e29b-41d4-a716-446655440000";
+    for entry in cache["tracks"].as_array_mut().unwrap() {
+        if entry["track"]["metadata"]["file_path"] == json!(song) {
+            entry["track"]["id"] = json!(old_id);
+        }
+    }
+    fs::write(&cache_file, cache.to_string()).unwrap();
+
+    let upgraded = env.library();
+    assert_eq!(upgraded.track_count(), 2);
+    assert!(upgraded.get_track(old_id).is_none());
+    assert_eq!(upgraded.get_track_by_path(&song).unwrap().id, id);
+    assert_eq!(upgraded.renamed_track_id(old_id), Some(id.clone()));
+    let cache: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+    assert_eq!(
+        cache["tracks"][0]["track"]["id"].as_str().unwrap().len(),
+        64
+    );
+    assert!(cache["tracks"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|entry| entry["track"]["id"] != old_id));
+    assert_eq!(
+        cache["previous_ids"][old_id],
+        json!(id),
+        "the old id is kept"
+    );
+
+    // Entries read again from changed or missing files are renamed as well, and the
+    // old ids still resolve after a restart
+    let gone_id = "6ba7b810-9dad-11d1-80b4-00c04fd430c8";
+    let mut cache = cache;
+    for entry in cache["tracks"].as_array_mut().unwrap() {
+        if entry["track"]["metadata"]["file_path"] == json!(other) {
+            entry["track"]["id"] = json!(gone_id);
+        }
+    }
+    fs::write(&cache_file, cache.to_string()).unwrap();
+    let other_id = track_identifier(&other);
+    fs::remove_file(&other).unwrap();
+    let restarted = env.library();
+    assert_eq!(restarted.track_count(), 1);
+    assert_eq!(restarted.renamed_track_id(gone_id), Some(other_id));
+    assert_eq!(restarted.renamed_track_id(old_id), Some(id));
+
+    // Old ids a run has loaded back are not saved again
+    restarted.save_to_cache().unwrap();
+    let cache: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+    assert!(cache["previous_ids"].get(old_id).is_none());
+    assert!(cache["previous_ids"].get(gone_id).is_some());
+    let later = env.library();
+    assert_eq!(later.renamed_track_id(old_id), None);
+}
+
+#[test]
+fn library_changes_are_numbered_and_merged_into_deltas() {
+    let env = LibraryTestEnv::new();
+    let modified = env.create_audio_file("modified.mp3");
+    let deleted = env.create_audio_file("deleted.mp3");
+
+    let library = env.library();
+    assert_eq!(library.change_sequence(), 0);
+    library.scan_directories(&[env.music_dir()]).unwrap();
+    let after_scan = library.change_sequence();
+    let (sequence, delta) = library.changes_since(0);
+    let delta = delta.unwrap();
+    assert_eq!(sequence, after_scan);
+    assert_eq!((delta.added, delta.removed, delta.updated), (2, 0, 0));
+
+    let modified_id = library.get_track_by_path(&modified).unwrap().id;
+    let deleted_id = library.get_track_by_path(&deleted).unwrap().id;
+    fs::remove_file(&deleted).unwrap();
+    fs::File::options()
+        .write(true)
+        .open(&modified)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(3600))
+        .unwrap();
+    library.refresh(&[env.music_dir()]).unwrap();
+    assert!(library.remove_track(&modified_id));
+
+    let (sequence, delta) = library.changes_since(after_scan);
+    let delta = delta.unwrap();
+    assert_eq!(sequence, after_scan + 2);
+    assert_eq!((delta.added, delta.removed, delta.updated), (0, 2, 1));
+    // The modified track is listed once though it was updated, then removed
+    assert_eq!(delta.track_ids, Some(vec![deleted_id, modified_id]));
+
+    let (_, delta) = library.changes_since(sequence);
+    assert!(delta.unwrap().is_empty());
+}
+
+#[test]
+fn read_only_libraries_scan_but_refuse_tag_and_sidecar_writes() {
+    let env = LibraryTestEnv::new();
+    let track_path = env.create_audio_file("shared.mp3");
+
+    let library = env.library().with_read_only(ReadOnlyPaths {
+        everything: true,
+        roots: Vec::new(),
+    });
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+    assert_eq!(library.track_count(), 1);
+    assert!(env.paths.library_cache_file().exists());
+
+    let track_id = library.get_track_by_path(&track_path).unwrap().id;
+    let error = library
+        .update_track_sidecar(
+            &track_id,
+            SidecarMetadata {
+                title: Some("Renamed".into()),
+                ..Default::default()
+            },
+        )
+        .expect_err("sidecar writes should be refused");
+    assert!(matches!(error, LibraryError::ReadOnly(_)));
+    assert!(!sidecar_path(&track_path).exists());
+
+    let (_, result) = library
+        .update_tracks_tags(
+            std::slice::from_ref(&track_id),
+            &TrackTagUpdate {
+                genre: Some("Jazz".into()),
+                ..Default::default()
+            },
+        )
+        .remove(0);
+    let error = result.expect_err("tag writes should be refused");
+    assert!(matches!(error, LibraryError::ReadOnly(_)));
+    assert_eq!(fs::read(&track_path).unwrap(), b"fake audio data");
+}
+
+/// Write a cache of `count` tracks, all copies of one file so every entry stays valid
+fn write_large_cache(env: &LibraryTestEnv, count: usize) {
+    let path = env.create_audio_file("song.mp3");
+    let library = env.library();
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+    let track = library.get_track_by_path(&path).unwrap();
+    let file_mtime: DateTime<Utc> = fs::metadata(&path).unwrap().modified().unwrap().into();
+
+    let tracks: Vec<_> = (0..count)
+        .map(|index| {
+            let mut copy = track.clone();
+            copy.id = format!("track-{}", index);
+            copy.metadata.title = Some(format!("Song {}", index));
+            json!({ "track": copy, "file_mtime": file_mtime, "sidecar_mtime": null })
+        })
+        .collect();
+    let cache = json!({ "version": 2, "tracks": tracks, "cached_at": Utc::now() });
+    fs::write(env.paths.library_cache_file(), cache.to_string()).unwrap();
+}
+
+#[tokio::test]
+async fn deferred_libraries_load_a_large_cache_in_the_background() {
+    let env = LibraryTestEnv::new();
+    write_large_cache(&env, 30_000);
+    assert_eq!(env.library().track_count(), 30_000);
+
+    let started = Instant::now();
+    let library = Arc::new(Library::deferred(&env.paths, false));
+    let elapsed = started.elapsed();
+    assert!(
+        elapsed < Duration::from_millis(50),
+        "construction took {:?}",
+        elapsed
+    );
+    assert!(!library.is_ready());
+    assert_eq!(library.track_count(), 0);
+
+    let loaded = library.load_in_background().await.unwrap();
+    assert_eq!(loaded, 30_000);
+    assert!(library.is_ready());
+    library.ready().await;
+    assert_eq!(library.track_count(), 30_000);
+}
+
+#[tokio::test]
+async fn scans_requested_while_the_cache_loads_wait_for_it() {
+    let env = LibraryTestEnv::new();
+    write_large_cache(&env, 100);
+    env.create_audio_file("other.mp3");
+
+    let library = Arc::new(Library::deferred(&env.paths, false));
+    let scan = {
+        let library = library.clone();
+        let directory = env.music_dir();
+        std::thread::spawn(move || library.scan_directories(&[directory]))
+    };
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(!scan.is_finished(), "the scan should wait for the cache");
+    assert!(!library.is_scanning());
+
+    library.load_in_background().await.unwrap();
+    let report = scan.join().unwrap().expect("scan should succeed");
+
+    // The scan ran after the load, so its track joined the cached ones rather than
+    // being replaced by them
+    assert_eq!(report.tracks, 101);
+    assert_eq!(library.track_count(), 101);
+}
+
+/// Start a scan of `directories` on another thread and wait until it is running
+fn start_scan(
+    library: &Arc<Library>,
+    directories: Vec<PathBuf>,
+) -> std::thread::JoinHandle<Result<hexendrum::library::ScanReport, LibraryError>> {
+    let scan = {
+        let library = library.clone();
+        // Read every file, so the scan takes as long as the scan pause makes it
+        std::thread::spawn(move || library.scan_directories_with(&directories, true))
+    };
+    while !library.is_scanning() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    scan
+}
+
+#[test]
+fn library_errors_tell_their_causes_apart() {
+    let env = LibraryTestEnv::new();
+    env.create_audio_file("song.mp3");
+
+    let cache = env.paths.library_cache_file();
+    fs::create_dir_all(cache.parent().unwrap()).unwrap();
+    fs::write(&cache, "{ not json").unwrap();
+    match env.library().load_from_cache() {
+        Err(LibraryError::CacheCorrupt { path, .. }) => assert_eq!(path, cache),
+        other => panic!("expected a corrupt cache, got {:?}", other),
+    }
+
+    let missing = env.music_dir().join("missing.flac");
+    match Track::new(missing.clone()) {
+        Err(LibraryError::Unreadable { path, .. }) => assert_eq!(path, missing),
+        other => panic!("expected an unreadable file, got {:?}", other),
+    }
+
+    let library = Arc::new(env.library().with_scan_pause(Duration::from_millis(50)));
+    let (_, result) = library
+        .update_tracks_tags(&["missing".to_string()], &TrackTagUpdate::default())
+        .remove(0);
+    let error = result.expect_err("unknown tracks cannot be tagged");
+    assert!(matches!(error, LibraryError::TrackNotFound(id) if id == "missing"));
+
+    let scan = start_scan(&library, vec![env.music_dir()]);
+    let error = library
+        .refresh(&[env.music_dir()])
+        .expect_err("a refresh cannot run during a scan");
+    assert!(matches!(error, LibraryError::ScanInProgress));
+    scan.join().unwrap().unwrap();
+}
+
+#[test]
+fn changes_made_during_a_scan_are_kept_when_it_completes() {
+    let env = LibraryTestEnv::new();
+    // The first directory is scanned before the second, so the edited file has been
+    // read by the time it is edited
+    let first_dir = env.music_dir().join("a");
+    let second_dir = env.music_dir().join("b");
+    fs::create_dir(&first_dir).unwrap();
+    fs::create_dir(&second_dir).unwrap();
+    let removed = env.create_audio_file("a/removed.mp3");
+    let edited = env.create_audio_file("a/edited.mp3");
+    for index in 0..10 {
+        env.create_audio_file(format!("b/other-{}.mp3", index));
+    }
+    let directories = vec![first_dir, second_dir];
+
+    let library = Arc::new(env.library().with_scan_pause(Duration::from_millis(50)));
+    library.scan_directories(&directories).unwrap();
+    let removed_id = library.get_track_by_path(&removed).unwrap().id;
+    let edited_id = library.get_track_by_path(&edited).unwrap().id;
+    let sequence = library.change_sequence();
+
+    let scan = start_scan(&library, directories);
+    std::thread::sleep(Duration::from_millis(200));
+    assert!(library.remove_track(&removed_id));
+    library
+        .update_track_sidecar(
+            &edited_id,
+            SidecarMetadata {
+                title: Some("Edited during the scan".into()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    assert!(!scan.is_finished(), "the scan should still be running");
+    let report = scan.join().unwrap().unwrap();
+
+    assert_eq!(report.tracks, 11);
+    assert_eq!(library.track_count(), 11);
+    assert!(library.get_track_by_path(&removed).is_none());
+    let track = library.get_track_by_path(&edited).unwrap();
+    assert_eq!(
+        track.metadata.title.as_deref(),
+        Some("Edited during the scan")
+    );
+    // Removal, edit and scan
+    assert_eq!(library.change_sequence(), sequence + 3);
+
+    // The cache written by the scan has the changes too
+    let reloaded = env.library();
+    assert!(reloaded.get_track_by_path(&removed).is_none());
+    let track = reloaded.get_track_by_path(&edited).unwrap();
+    assert_eq!(
+        track.metadata.title.as_deref(),
+        Some("Edited during the scan")
+    );
+}
+
+#[test]
+fn tracks_removed_during_a_refresh_stay_removed() {
+    let env = LibraryTestEnv::new();
+    let modified = env.create_audio_file("modified.mp3");
+    let library = Arc::new(env.library().with_scan_pause(Duration::from_millis(50)));
+    library.scan_directories(&[env.music_dir()]).unwrap();
+    let modified_id = library.get_track_by_path(&modified).unwrap().id;
+
+    fs::File::options()
+        .write(true)
+        .open(&modified)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(3600))
+        .unwrap();
+    let added: Vec<PathBuf> = (0..5)
+        .map(|index| env.create_audio_file(format!("added-{}.mp3", index)))
+        .collect();
+
+    let refresh = {
+        let library = library.clone();
+        let directory = env.music_dir();
+        std::thread::spawn(move || library.refresh(&[directory]))
+    };
+    while !library.is_scanning() {
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    // Removed after the refresh re-read it, which must not bring it back
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(library.remove_track(&modified_id));
+    let report = refresh.join().unwrap().unwrap();
+
+    assert_eq!((report.added, report.updated, report.removed), (5, 0, 0));
+    assert!(library.get_track_by_path(&modified).is_none());
+    assert!(added
+        .iter()
+        .all(|path| library.get_track_by_path(path).is_some()));
+}
+
+#[tokio::test]
+async fn changes_wait_for_a_running_scan_or_are_rejected() {
+    let env = LibraryTestEnv::new();
+    env.create_audio_file("song.mp3");
+    env.create_audio_file("other.mp3");
+
+    let library = Arc::new(env.library().with_scan_pause(Duration::from_millis(100)));
+    let scan = start_scan(&library, vec![env.music_dir()]);
+    library.guard_mutation().await.unwrap();
+    assert!(!library.is_scanning());
+    scan.join().unwrap().unwrap();
+
+    let library = Arc::new(
+        env.library()
+            .with_scan_conflict(ScanConflict::Reject)
+            .with_scan_pause(Duration::from_millis(100)),
+    );
+    let scan = start_scan(&library, vec![env.music_dir()]);
+    let error: ScanInProgressError = library.guard_mutation().await.unwrap_err();
+    assert_eq!(error.retry_after, Duration::from_secs(5));
+    scan.join().unwrap().unwrap();
+    library.guard_mutation().await.unwrap();
+}
+
+#[test]
+fn scans_pick_up_only_files_with_the_configured_extensions() {
+    let env = LibraryTestEnv::new();
+    let opus = env.create_audio_file("voice.opus");
+    env.create_audio_file("song.mp3");
+    env.create_audio_file("notes.txt");
+
+    let library = env.library();
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+    assert_eq!(library.track_count(), 2, "opus is picked up by default");
+    assert!(library.get_track_by_path(&opus).is_some());
+
+    let other = LibraryTestEnv::new();
+    other.create_audio_file("voice.opus");
+    let mp3 = other.create_audio_file("song.MP3");
+    let library = other
+        .library()
+        .with_extensions(vec![".mp3".to_string(), "flac".to_string()]);
+    library
+        .scan_directories(&[other.music_dir()])
+        .expect("scan should succeed");
+    assert_eq!(library.track_count(), 1, "opus is left out once not listed");
+    assert!(library.get_track_by_path(&mp3).is_some());
+
+    let opus = other.create_audio_file("later.opus");
+    let library = library.with_extensions(vec!["opus".to_string()]);
+    let report = library
+        .refresh(&[other.music_dir()])
+        .expect("refresh should succeed");
+    assert_eq!(report.added, 2, "refreshes follow the list too");
+    assert!(library.get_track_by_path(&opus).is_some());
+
+    let defaults = LibraryConfig::default().supported_extensions;
+    for extension in ["opus", "wv", "aiff", "ape"] {
+        assert!(
+            defaults.iter().any(|default| default == extension),
+            "{}",
+            extension
+        );
+    }
+    assert!(is_supported_audio_format(Path::new("a.WV"), &defaults));
+    assert!(!is_supported_audio_format(Path::new("a.txt"), &defaults));
+    assert!(!is_supported_audio_format(Path::new("wv"), &defaults));
+}
+
+#[test]
+fn opus_files_are_scanned_with_their_tags_and_length() {
+    let env = LibraryTestEnv::new();
+    let path = env.music_dir().join("sine.opus");
+    fs::copy(
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/audio/sine.opus"),
+        &path,
+    )
+    .unwrap();
+
+    let library = env.library();
+    library.scan_directories(&[env.music_dir()]).unwrap();
+    let metadata = library.get_track_by_path(&path).unwrap().metadata;
+    assert_eq!(metadata.title.as_deref(), Some("Opus Sine"));
+    assert_eq!(metadata.artist.as_deref(), Some("Test Tones"));
+    assert!(metadata.duration.is_some());
+    let technical = metadata.technical.unwrap();
+    assert_eq!(technical.sample_rate, Some(48_000));
+    assert_eq!(technical.channels, Some(2));
+}
+
+/// Prober that counts the files it reads, reporting each as three minutes long
+struct CountingProber {
+    probes: Arc<AtomicUsize>,
+}
+
+impl AudioProber for CountingProber {
+    fn probe(&self, _file_path: &Path) -> anyhow::Result<AudioProbe> {
+        self.probes.fetch_add(1, Ordering::SeqCst);
+        Ok(AudioProbe {
+            duration: Duration::from_secs(180),
+            technical: TechnicalInfo::default(),
+        })
+    }
+}
+
+#[test]
+fn rescans_of_unchanged_files_do_not_probe_them_again() {
+    let env = LibraryTestEnv::new();
+    for index in 0..20 {
+        env.create_audio_file(format!("track{:02}.mp3", index));
+    }
+    let probes = Arc::new(AtomicUsize::new(0));
+    let library = || {
+        env.library().with_prober(CountingProber {
+            probes: probes.clone(),
+        })
+    };
+
+    let first = library();
+    first
+        .scan_directories_with(&[env.music_dir()], true)
+        .unwrap();
+    assert_eq!(probes.load(Ordering::SeqCst), 20);
+    first
+        .scan_directories_with(&[env.music_dir()], true)
+        .unwrap();
+    assert_eq!(
+        probes.load(Ordering::SeqCst),
+        20,
+        "second scan probed files"
+    );
+    assert!(first
+        .get_tracks()
+        .iter()
+        .all(|track| track.metadata.duration == Some(180)));
+
+    // Full rescans read the tags again but not the durations, and the cache outlives
+    // the library
+    let second = library();
+    second
+        .scan_directories_with(&[env.music_dir()], true)
+        .unwrap();
+    assert_eq!(probes.load(Ordering::SeqCst), 20, "the cache was not saved");
+    assert_eq!(second.track_count(), 20);
+
+    // Only a file whose size changed is probed again, by scans and refreshes alike
+    let changed = env.music_dir().join("track03.mp3");
+    fs::write(&changed, b"longer fake audio data").unwrap();
+    second
+        .scan_directories_with(&[env.music_dir()], true)
+        .unwrap();
+    assert_eq!(probes.load(Ordering::SeqCst), 21);
+    let added = env.create_audio_file("added.mp3");
+    second.refresh(&[env.music_dir()]).unwrap();
+    assert_eq!(probes.load(Ordering::SeqCst), 22);
+    assert!(second.get_track_by_path(&added).is_some());
+
+    second.clear_cache().unwrap();
+    library()
+        .scan_directories_with(&[env.music_dir()], true)
+        .unwrap();
+    assert_eq!(probes.load(Ordering::SeqCst), 43, "cleared with the cache");
+}
+
+#[test]
+fn parallel_scans_keep_each_file_with_its_own_metadata() {
+    let env = LibraryTestEnv::new();
+    let mut expected = Vec::new();
+    for index in 0..300 {
+        let dir = env.music_dir().join(format!("disc{}", index % 10));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{:03}.mp3", index));
+        fs::write(&path, b"fake audio data").unwrap();
+        let sidecar = if index % 50 == 7 {
+            "{ not json".to_string()
+        } else {
+            json!({ "title": format!("Title {}", index), "track_number": index }).to_string()
+        };
+        fs::write(sidecar_path(&path), sidecar).unwrap();
+        expected.push((path, index));
+    }
+
+    let library = env.library();
+    let report = library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+    assert!(!library.is_scanning());
+    assert_eq!(report.tracks, 300);
+    assert_eq!(library.track_count(), 300);
+
+    let mut ids = std::collections::HashSet::new();
+    for (path, index) in &expected {
+        let track = library
+            .get_track_by_path(path)
+            .expect("every file is a track");
+        assert_eq!(&track.metadata.file_path, path);
+        assert!(ids.insert(track.id.clone()), "ids are unique");
+        if index % 50 == 7 {
+            assert_eq!(track.metadata.track_number, None);
+        } else {
+            assert_eq!(track.metadata.title, Some(format!("Title {}", index)));
+            assert_eq!(track.metadata.track_number, Some(*index));
+        }
+    }
+    let mut broken: Vec<PathBuf> = report
+        .sidecar_errors
+        .iter()
+        .map(|error| error.path.clone())
+        .collect();
+    broken.sort();
+    let mut expected_broken: Vec<PathBuf> = expected
+        .iter()
+        .filter(|(_, index)| index % 50 == 7)
+        .map(|(path, _)| sidecar_path(path))
+        .collect();
+    expected_broken.sort();
+    assert_eq!(broken, expected_broken);
+
+    // The cache holds the merged result
+    let reloaded = env.library();
+    assert_eq!(reloaded.track_count(), 300);
+    assert_eq!(
+        reloaded
+            .get_track_by_path(&expected[123].0)
+            .unwrap()
+            .metadata
+            .title
+            .as_deref(),
+        Some("Title 123")
+    );
+}
+
+#[test]
+fn scans_report_progress_every_interval_and_after_the_last_file() {
+    let env = LibraryTestEnv::new();
+    for index in 0..25 {
+        env.create_audio_file(format!("{:02}.mp3", index));
+    }
+    fs::write(env.music_dir().join("cover.jpg"), b"not audio").unwrap();
+
+    let library = env.library().with_scan_progress_interval(10);
+    let reported = std::sync::Mutex::new(Vec::new());
+    let report = library
+        .scan_directories_reporting(&[env.music_dir()], false, &|progress| {
+            reported.lock().unwrap().push(progress);
+        })
+        .expect("scan should succeed");
+    assert_eq!(report.files, 25);
+    let reported: Vec<(usize, usize)> = reported
+        .into_inner()
+        .unwrap()
+        .iter()
+        .map(|progress| (progress.processed, progress.total))
+        .collect();
+    assert_eq!(reported, vec![(0, 25), (10, 25), (20, 25), (25, 25)]);
+
+    // Unchanged files count as they are skipped
+    env.create_audio_file("25.mp3");
+    let reported = std::sync::Mutex::new(Vec::new());
+    library
+        .scan_directories_reporting(&[env.music_dir()], false, &|progress| {
+            reported.lock().unwrap().push(progress.processed);
+        })
+        .unwrap();
+    assert_eq!(reported.into_inner().unwrap(), vec![0, 10, 20, 26]);
+}
+
+#[test]
+fn cancelled_scans_leave_the_library_as_it_was() {
+    let env = LibraryTestEnv::new();
+    let kept = env.create_audio_file("kept.mp3");
+    let library = Arc::new(env.library().with_scan_pause(Duration::from_millis(10)));
+    library.scan_directories(&[env.music_dir()]).unwrap();
+    let sequence = library.change_sequence();
+    assert!(!library.cancel_scan(), "no scan is running");
+
+    for index in 0..200 {
+        env.create_audio_file(format!("new-{:03}.mp3", index));
+    }
+    let scan = start_scan(&library, vec![env.music_dir()]);
+    std::thread::sleep(Duration::from_millis(100));
+    assert!(library.cancel_scan());
+    let error = scan.join().unwrap().expect_err("the scan was cancelled");
+    assert!(matches!(error, LibraryError::ScanCancelled));
+    assert!(!library.is_scanning());
+
+    assert_eq!(library.track_count(), 1);
+    assert!(library.get_track_by_path(&kept).is_some());
+    assert_eq!(library.change_sequence(), sequence);
+    assert_eq!(env.library().track_count(), 1, "the cache is left alone");
+
+    // The next scan is not cancelled by the last one's request
+    let report = library.scan_directories(&[env.music_dir()]).unwrap();
+    assert_eq!(report.tracks, 201);
+}
@@ -0,0 +1,324 @@
+use chrono::Utc;
+use hexendrum::config::Paths;
+use hexendrum::library::{
+    album_identifier, sidecar_path, update_sidecar, AlbumService, IntegrityRecord, IntegrityStatus,
+    Library, SidecarMetadata, StatsStore, Trash,
+};
+use hexendrum::maintenance::{
+    Maintenance, MaintenanceOptions, MaintenanceReport, MaintenanceRequest, MaintenanceTask,
+    TaskReport,
+};
+use hexendrum::playlist::PlaylistManager;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::TempDir;
+
+struct MaintenanceTestEnv {
+    workspace: TempDir,
+    music_dir: PathBuf,
+    paths: Paths,
+    library: Library,
+    playlist_manager: PlaylistManager,
+    album_service: AlbumService,
+    stats_store: StatsStore,
+    trash: Trash,
+}
+
+impl MaintenanceTestEnv {
+    fn new() -> Self {
+        let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+        let music_dir = workspace.path().join("music");
+        fs::create_dir(&music_dir).expect("failed to create music dir");
+        let paths = Paths::portable(workspace.path().join("data"));
+
+        Self {
+            library: Library::with_paths(&paths),
+            playlist_manager: PlaylistManager::new(paths.playlist_dir()).unwrap(),
+            album_service: AlbumService::with_paths(&paths, None),
+            stats_store: StatsStore::with_path(paths.stats_file()),
+            trash: Trash::with_paths(
+                workspace.path().join("trash_journal.json"),
+                Some(workspace.path().join("Trash")),
+                30,
+            ),
+            workspace,
+            music_dir,
+            paths,
+        }
+    }
+
+    fn create_audio_file(&self, name: &str) -> PathBuf {
+        let path = self.music_dir.join(name);
+        fs::write(&path, b"fake audio data").expect("failed to write audio file");
+        path
+    }
+
+    /// Drop `image` into the artwork cache as an older version would have stored it
+    /// and let the service move it into its store.
+    fn cache_artwork(&self, album_id: &str, image: &[u8]) -> PathBuf {
+        let path = self.paths.album_art_dir().join(format!("{}.jpg", album_id));
+        fs::write(&path, image).unwrap();
+        self.album_service.rescan_artwork_cache();
+        self.album_service.cached_artwork_path(album_id).unwrap()
+    }
+
+    fn run(&self, request: &MaintenanceRequest) -> MaintenanceReport {
+        let maintenance = Maintenance {
+            library: &self.library,
+            playlist_manager: &self.playlist_manager,
+            album_service: &self.album_service,
+            stats_store: &self.stats_store,
+            trash: &self.trash,
+            music_directories: vec![self.music_dir.clone()],
+        };
+        maintenance.run(request, |_, _| {})
+    }
+}
+
+fn integrity_ok() -> IntegrityRecord {
+    IntegrityRecord {
+        status: IntegrityStatus::Ok,
+        error: None,
+        checked_at: Utc::now(),
+        file_modified: Utc::now(),
+    }
+}
+
+fn task(report: &MaintenanceReport, task: MaintenanceTask) -> &TaskReport {
+    report
+        .tasks
+        .iter()
+        .find(|report| report.task == task)
+        .expect("task should have run")
+}
+
+#[test]
+fn maintenance_cleans_up_after_deleted_files() {
+    let env = MaintenanceTestEnv::new();
+    let kept = env.create_audio_file("kept.mp3");
+    let deleted = env.create_audio_file("deleted.mp3");
+    let trashed = env.create_audio_file("trashed.mp3");
+    for path in [&kept, &deleted, &trashed] {
+        update_sidecar(
+            path,
+            SidecarMetadata {
+                artist: Some("Artist".into()),
+                album: Some(path.file_stem().unwrap().to_string_lossy().to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        env.stats_store.record_integrity(path, integrity_ok());
+    }
+    env.library
+        .scan_directories(std::slice::from_ref(&env.music_dir))
+        .unwrap();
+
+    let playlist_id = env.playlist_manager.create_playlist("Mix".into(), None);
+    let mut playlist =
+        std::sync::Arc::unwrap_or_clone(env.playlist_manager.get_playlist(&playlist_id).unwrap());
+    for path in [&kept, &deleted] {
+        playlist.add_track(&env.library.get_track_by_path(path).unwrap());
+    }
+    env.playlist_manager.update_playlist(playlist);
+
+    let kept_art = env.cache_artwork(&album_identifier(Some("Artist"), "kept"), b"kept");
+    let deleted_art = env.cache_artwork(&album_identifier(Some("Artist"), "deleted"), b"deleted");
+
+    fs::remove_file(&deleted).unwrap();
+    let trashed_id = env.library.get_track_by_path(&trashed).unwrap().id;
+    env.trash.move_to_trash(&trashed_id, &trashed).unwrap();
+
+    let dry_run = env.run(&MaintenanceRequest {
+        dry_run: true,
+        ..Default::default()
+    });
+    assert!(dry_run.dry_run);
+    assert!(!dry_run.has_failures());
+    let order: Vec<MaintenanceTask> = dry_run.tasks.iter().map(|task| task.task).collect();
+    assert_eq!(order, MaintenanceTask::ALL.to_vec());
+    assert_eq!(task(&dry_run, MaintenanceTask::Rescan).affected, 2);
+    assert_eq!(task(&dry_run, MaintenanceTask::PlaylistCleanup).affected, 1);
+    assert_eq!(
+        task(&dry_run, MaintenanceTask::OrphanedSidecars).removed,
+        vec![sidecar_path(&deleted).display().to_string()]
+    );
+    assert_eq!(task(&dry_run, MaintenanceTask::CompactStats).affected, 2);
+    assert!(sidecar_path(&deleted).exists());
+    assert!(deleted_art.exists());
+    assert_eq!(
+        env.playlist_manager
+            .get_playlist(&playlist_id)
+            .unwrap()
+            .track_count(),
+        2
+    );
+
+    let report = env.run(&MaintenanceRequest::default());
+    assert!(!report.has_failures());
+    assert_eq!(task(&report, MaintenanceTask::PlaylistCleanup).affected, 1);
+    assert_eq!(
+        task(&report, MaintenanceTask::ArtworkCache).removed,
+        vec![deleted_art.display().to_string()]
+    );
+    assert_eq!(task(&report, MaintenanceTask::CompactStats).affected, 2);
+
+    assert_eq!(env.library.track_count(), 1);
+    assert_eq!(
+        env.playlist_manager
+            .get_playlist(&playlist_id)
+            .unwrap()
+            .track_count(),
+        1
+    );
+    assert!(kept_art.exists());
+    assert!(!deleted_art.exists());
+    assert!(sidecar_path(&kept).exists());
+    assert!(!sidecar_path(&deleted).exists());
+    // The trashed file can still be restored with its sidecar
+    assert!(sidecar_path(&trashed).exists());
+    assert!(env.stats_store.get(&kept).is_some());
+    assert!(env.stats_store.get(&deleted).is_none());
+    assert_eq!(
+        StatsStore::with_path(env.paths.stats_file()).stale_records(),
+        Vec::<PathBuf>::new()
+    );
+}
+
+#[test]
+fn shared_artwork_is_kept_while_an_album_uses_it() {
+    let env = MaintenanceTestEnv::new();
+    for album in ["Box 1", "Box 2", "Single"] {
+        let path = env.create_audio_file(&format!("{}.mp3", album));
+        update_sidecar(
+            &path,
+            SidecarMetadata {
+                artist: Some("Artist".into()),
+                album: Some(album.into()),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+    }
+    env.library
+        .scan_directories(std::slice::from_ref(&env.music_dir))
+        .unwrap();
+
+    let box_cover = env.cache_artwork(&album_identifier(Some("Artist"), "Box 1"), b"box");
+    let shared = env.cache_artwork(&album_identifier(Some("Artist"), "Box 2"), b"box");
+    let single_cover = env.cache_artwork(&album_identifier(Some("Artist"), "Single"), b"single");
+    assert_eq!(box_cover, shared);
+
+    let evict = || {
+        let report = env.run(&MaintenanceRequest {
+            tasks: vec![MaintenanceTask::ArtworkCache],
+            ..Default::default()
+        });
+        task(&report, MaintenanceTask::ArtworkCache).removed.clone()
+    };
+
+    // One album of the box set leaves, the other still shows the cover
+    fs::remove_file(env.music_dir.join("Box 1.mp3")).unwrap();
+    fs::remove_file(env.music_dir.join("Single.mp3")).unwrap();
+    env.library
+        .scan_directories(std::slice::from_ref(&env.music_dir))
+        .unwrap();
+    assert_eq!(evict(), vec![single_cover.display().to_string()]);
+    assert!(box_cover.exists());
+    assert!(env
+        .album_service
+        .cached_artwork_path(&album_identifier(Some("Artist"), "Box 1"))
+        .is_none());
+
+    fs::remove_file(env.music_dir.join("Box 2.mp3")).unwrap();
+    env.library
+        .scan_directories(std::slice::from_ref(&env.music_dir))
+        .unwrap();
+    assert_eq!(evict(), vec![box_cover.display().to_string()]);
+    assert!(!box_cover.exists());
+}
+
+#[test]
+fn only_selected_tasks_run_once_in_order() {
+    let env = MaintenanceTestEnv::new();
+    env.create_audio_file("new.mp3");
+
+    let report = env.run(&MaintenanceRequest {
+        tasks: vec![
+            MaintenanceTask::CompactStats,
+            MaintenanceTask::Rescan,
+            MaintenanceTask::CompactStats,
+        ],
+        ..Default::default()
+    });
+
+    let order: Vec<MaintenanceTask> = report.tasks.iter().map(|task| task.task).collect();
+    assert_eq!(
+        order,
+        vec![MaintenanceTask::Rescan, MaintenanceTask::CompactStats]
+    );
+    assert_eq!(report.tasks[0].message, "1 added, 0 updated, 0 removed");
+    assert_eq!(env.library.track_count(), 1);
+}
+
+#[test]
+fn failing_tasks_are_reported_without_stopping_the_run() {
+    let env = MaintenanceTestEnv::new();
+    // The store cannot be saved below a regular file
+    let blocker = env.workspace.path().join("blocker");
+    fs::write(&blocker, b"").unwrap();
+    let blocked = StatsStore::with_path(blocker.join("stats.json"));
+    let maintenance = Maintenance {
+        library: &env.library,
+        playlist_manager: &env.playlist_manager,
+        album_service: &env.album_service,
+        stats_store: &blocked,
+        trash: &env.trash,
+        music_directories: vec![env.music_dir.clone()],
+    };
+    let mut progress = Vec::new();
+    let report = maintenance.run(
+        &MaintenanceRequest {
+            tasks: vec![MaintenanceTask::CompactStats, MaintenanceTask::SaveCache],
+            ..Default::default()
+        },
+        |task, completed| progress.push((task, completed)),
+    );
+
+    assert_eq!(
+        progress,
+        vec![
+            (MaintenanceTask::SaveCache, 0),
+            (MaintenanceTask::CompactStats, 1)
+        ]
+    );
+    assert!(report.has_failures());
+    assert!(report.tasks[0].success);
+    assert!(!report.tasks[1].success);
+    assert!(report.render().contains("FAIL  compact_stats"));
+}
+
+#[test]
+fn cli_options_select_tasks() {
+    let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    let options =
+        MaintenanceOptions::parse(&args(&["--tasks", "rescan,Playlist_Cleanup", "--dry-run"]))
+            .unwrap();
+    assert_eq!(
+        options.request.tasks,
+        vec![MaintenanceTask::Rescan, MaintenanceTask::PlaylistCleanup]
+    );
+    assert!(options.request.dry_run);
+    assert!(!options.json);
+
+    let options = MaintenanceOptions::parse(&args(&["--json"])).unwrap();
+    assert_eq!(
+        options.request.selected_tasks(),
+        MaintenanceTask::ALL.to_vec()
+    );
+
+    assert!(MaintenanceOptions::parse(&args(&["--tasks", "defrag"])).is_err());
+    assert!(MaintenanceOptions::parse(&args(&["--tasks"])).is_err());
+    assert!(MaintenanceOptions::parse(&args(&["now"])).is_err());
+}
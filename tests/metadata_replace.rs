@@ -0,0 +1,122 @@
+use hexendrum::library::{MetadataReplace, ReplaceError, ReplaceMatch, MAX_FIND_LENGTH};
+
+#[test]
+fn values_are_found_and_replaced_by_match_mode() {
+    use ReplaceMatch::{Exact, Regex, Substring};
+    let cases: &[(ReplaceMatch, &str, &str, &str, Option<&str>)] = &[
+        // Exact matches the whole value, as it is
+        (
+            Exact,
+            "Unknown Artist ",
+            "Unknown Artist",
+            "Unknown Artist ",
+            Some("Unknown Artist"),
+        ),
+        (
+            Exact,
+            "Unknown Artist ",
+            "Unknown Artist",
+            "Unknown Artist",
+            None,
+        ),
+        (Exact, "Unknown Artist", "Various", "unknown artist", None),
+        (Exact, "Unknown Artist", "Various", "Unknown Artists", None),
+        (Exact, "Unknown", "", "Unknown", Some("")),
+        // Substring replaces every occurrence
+        (Substring, "feat", "feat.", "A feat B", Some("A feat. B")),
+        (
+            Substring,
+            "feat",
+            "feat.",
+            "A feat B feat C",
+            Some("A feat. B feat. C"),
+        ),
+        (Substring, "feat", "feat.", "A Feat B", None),
+        (Substring, "feat", "feat", "A feat B", None),
+        (Substring, " ", "", "Unknown Artist ", Some("UnknownArtist")),
+        (Substring, "Live", " ", "Live", Some("")),
+        // Regex replaces every match, with groups
+        (Regex, r"\bfeat\s", "feat. ", "A feat B", Some("A feat. B")),
+        (Regex, r"\bfeat\s", "feat. ", "A feat. B", None),
+        (
+            Regex,
+            r"\s+$",
+            "",
+            "Unknown Artist  ",
+            Some("Unknown Artist"),
+        ),
+        (
+            Regex,
+            r"^(.+), The$",
+            "The $1",
+            "Beatles, The",
+            Some("The Beatles"),
+        ),
+        (
+            Regex,
+            r"(?i)^unknown artist$",
+            "Various",
+            "UNKNOWN ARTIST",
+            Some("Various"),
+        ),
+        (Regex, r"(?P<n>\d+)", "#${n}", "Track 7", Some("Track #7")),
+        (Regex, "x*", "-", "ab", Some("-a-b-")),
+        (Regex, "z", "y", "abc", None),
+    ];
+
+    for (mode, find, replace, value, expected) in cases {
+        let replacer = MetadataReplace::new(find, replace, *mode).unwrap();
+        assert_eq!(
+            replacer.apply(value).as_deref(),
+            *expected,
+            "{:?} {:?} -> {:?} on {:?}",
+            mode,
+            find,
+            replace,
+            value
+        );
+    }
+}
+
+#[test]
+fn unusable_patterns_are_refused() {
+    for mode in [
+        ReplaceMatch::Exact,
+        ReplaceMatch::Substring,
+        ReplaceMatch::Regex,
+    ] {
+        assert!(matches!(
+            MetadataReplace::new("", "x", mode),
+            Err(ReplaceError::EmptyFind)
+        ));
+        let long = "a".repeat(MAX_FIND_LENGTH + 1);
+        assert!(matches!(
+            MetadataReplace::new(&long, "x", mode),
+            Err(ReplaceError::FindTooLong)
+        ));
+        assert!(MetadataReplace::new(&"a".repeat(MAX_FIND_LENGTH), "x", mode).is_ok());
+    }
+
+    for pattern in ["(", "[a-", r"(a)\1", r"a(?=b)"] {
+        assert!(
+            matches!(
+                MetadataReplace::new(pattern, "x", ReplaceMatch::Regex),
+                Err(ReplaceError::InvalidRegex(_))
+            ),
+            "{:?} is accepted",
+            pattern
+        );
+    }
+    // Compiles to more than the size limit, however short
+    assert!(matches!(
+        MetadataReplace::new(r"\w{1000}\w{1000}", "x", ReplaceMatch::Regex),
+        Err(ReplaceError::InvalidRegex(_))
+    ));
+    // Classic catastrophic backtracking patterns match in linear time
+    let replacer = MetadataReplace::new("(a+)+$", "", ReplaceMatch::Regex).unwrap();
+    let value = format!("{}b", "a".repeat(10_000));
+    assert_eq!(replacer.apply(&value), None);
+    // Special characters are plain text outside regex mode
+    let replacer = MetadataReplace::new("(a+)+$", "x", ReplaceMatch::Substring).unwrap();
+    assert_eq!(replacer.apply("b(a+)+$").as_deref(), Some("bx"));
+}
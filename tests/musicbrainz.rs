@@ -0,0 +1,280 @@
+mod common;
+
+use common::{write_silent_wav, TestTrack};
+use hexendrum::config::Paths;
+use hexendrum::library::{
+    album_identifier, normalize_mbid, AlbumService, Library, ManualAlbumUpdate, MusicBrainzIds,
+    Track,
+};
+use hexendrum::TrackMetadata;
+use lofty::config::WriteOptions;
+use lofty::id3::v2::{Frame, Id3v2Tag, UniqueFileIdentifierFrame};
+use lofty::prelude::{Accessor, TagExt};
+use std::fs;
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::Path;
+use std::sync::mpsc;
+use tempfile::TempDir;
+
+const RECORDING: &str = "b1a9c0e9-d987-4042-ae91-78d6a3267d69";
+const RELEASE: &str = "1dc4c347-a1db-32aa-b14f-bc9cc507b843";
+const REISSUE: &str = "3f8a5e5b-c24b-4068-9f1c-afad8829e06b";
+const ARTIST: &str = "0383dadf-2a4e-4d10-a46a-e9e041da8eb3";
+
+/// Write a WAV file tagged like MusicBrainz Picard would, with upper-case ids
+fn write_tagged_wav(path: &Path) {
+    write_silent_wav(path);
+    let mut tag = Id3v2Tag::new();
+    tag.set_title("Song".into());
+    tag.set_album("Album".into());
+    tag.set_artist("Artist".into());
+    tag.insert(Frame::UniqueFileIdentifier(UniqueFileIdentifierFrame::new(
+        "http://musicbrainz.org".into(),
+        RECORDING.to_uppercase().into_bytes(),
+    )));
+    tag.insert_user_text("MusicBrainz Album Id".into(), RELEASE.to_uppercase());
+    tag.insert_user_text("MusicBrainz Artist Id".into(), format!("{}; other", ARTIST));
+    tag.save_to_path(path, WriteOptions::default())
+        .expect("failed to tag audio file");
+}
+
+fn track(id: &str, album: &str, artist: &str, release_id: Option<&str>) -> Track {
+    TestTrack::new(id)
+        .metadata(TrackMetadata {
+            title: Some(id.into()),
+            artist: Some(artist.into()),
+            album: Some(album.into()),
+            musicbrainz: MusicBrainzIds {
+                release_id: release_id.map(str::to_string),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .build()
+}
+
+#[test]
+fn mbids_are_normalized_and_others_refused() {
+    assert_eq!(
+        normalize_mbid(&RELEASE.to_uppercase()).as_deref(),
+        Some(RELEASE)
+    );
+    assert_eq!(
+        normalize_mbid(&format!(" {} / {}", RELEASE, REISSUE)).as_deref(),
+        Some(RELEASE)
+    );
+    assert_eq!(normalize_mbid("not an id"), None);
+    assert_eq!(normalize_mbid(""), None);
+}
+
+#[test]
+fn tagged_ids_are_read_and_tracks_found_by_recording() {
+    let workspace = TempDir::new().unwrap();
+    let music_dir = workspace.path().join("music");
+    fs::create_dir(&music_dir).unwrap();
+    let path = music_dir.join("song.wav");
+    write_tagged_wav(&path);
+    write_silent_wav(&music_dir.join("untagged.wav"));
+
+    let library = Library::with_paths(&Paths::portable(workspace.path().join("data")));
+    library.scan_directories(&[music_dir]).unwrap();
+    let track = library.get_track_by_path(&path).unwrap();
+    assert_eq!(
+        track.metadata.musicbrainz,
+        MusicBrainzIds {
+            track_id: Some(RECORDING.into()),
+            release_id: Some(RELEASE.into()),
+            artist_id: Some(ARTIST.into()),
+        }
+    );
+
+    let found = library.get_tracks_by_musicbrainz_id(&RECORDING.to_uppercase());
+    assert_eq!(
+        found.iter().map(|track| &track.id).collect::<Vec<_>>(),
+        vec![&track.id]
+    );
+    assert!(library.get_tracks_by_musicbrainz_id(REISSUE).is_empty());
+    assert!(library.get_tracks_by_musicbrainz_id("nonsense").is_empty());
+    assert_ne!(
+        track.album_id(),
+        Some(album_identifier(Some("Artist"), "Album")),
+        "the release identifies the album"
+    );
+}
+
+#[test]
+fn caches_without_mbids_have_them_read_on_load() {
+    let workspace = TempDir::new().unwrap();
+    let music_dir = workspace.path().join("music");
+    fs::create_dir(&music_dir).unwrap();
+    let path = music_dir.join("song.wav");
+    write_tagged_wav(&path);
+    let paths = Paths::portable(workspace.path().join("data"));
+    Library::with_paths(&paths)
+        .scan_directories(&[music_dir])
+        .unwrap();
+
+    // A cache written before MusicBrainz identifiers were read
+    let cache_file = paths.library_cache_file();
+    let mut cache: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+    cache["version"] = 1.into();
+    cache["tracks"][0]["track"]["metadata"]
+        .as_object_mut()
+        .unwrap()
+        .remove("musicbrainz");
+    fs::write(&cache_file, cache.to_string()).unwrap();
+
+    let reloaded = Library::with_paths(&paths);
+    let track = reloaded.get_track_by_path(&path).unwrap();
+    assert_eq!(
+        track.metadata.musicbrainz.track_id.as_deref(),
+        Some(RECORDING)
+    );
+    let cache: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+    assert_eq!(cache["version"], 3);
+}
+
+#[tokio::test]
+async fn releases_group_albums_ahead_of_their_titles() {
+    let workspace = TempDir::new().unwrap();
+    let paths = Paths::portable(workspace.path().join("data"));
+    let library = Library::with_paths(&paths);
+    // One release whose tracks spell the album and artist differently
+    library.add_track(track("a1", "Abbey Road", "The Beatles", Some(RELEASE)));
+    library.add_track(track("a2", "Abbey Rd.", "Beatles", Some(RELEASE)));
+    // A reissue sharing title and artist, told apart by its release
+    library.add_track(track("b1", "Abbey Road", "The Beatles", Some(REISSUE)));
+    // Untagged tracks still group by title and artist
+    library.add_track(track("c1", "Abbey Road", "The Beatles", None));
+    library.add_track(track("c2", "Abbey Road", "The Beatles", None));
+
+    let service = AlbumService::with_paths(&paths, None);
+    let mut albums: Vec<_> = service
+        .search_albums(&library, None)
+        .await
+        .into_iter()
+        .map(|album| (album.musicbrainz_release_id, album.track_count))
+        .collect();
+    albums.sort();
+    assert_eq!(
+        albums,
+        vec![
+            (None, 2),
+            (Some(RELEASE.to_string()), 2),
+            (Some(REISSUE.to_string()), 1),
+        ]
+    );
+
+    let album = service
+        .album_by_musicbrainz_id(&library, &RELEASE.to_uppercase())
+        .await
+        .expect("the release is found");
+    assert_eq!(album.track_count, 2);
+    assert_eq!(
+        library.get_tracks_by_album_id(&album.id).len(),
+        2,
+        "tracks and albums agree on the identifier"
+    );
+    assert!(service
+        .album_by_musicbrainz_id(&library, "00000000-0000-0000-0000-000000000000")
+        .await
+        .is_none());
+}
+
+/// Answer `bodies.len()` HTTP requests in turn, sending each request line to the
+/// returned receiver, and return the address to send them to.
+fn serve_lastfm(bodies: Vec<String>) -> (String, mpsc::Receiver<String>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for body in bodies {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 4096];
+            let read = stream.read(&mut request).unwrap_or(0);
+            let request = String::from_utf8_lossy(&request[..read]);
+            let _ = sender.send(request.lines().next().unwrap_or_default().to_string());
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    (format!("http://{}/2.0/", address), receiver)
+}
+
+#[tokio::test]
+async fn lastfm_lookups_prefer_tagged_releases_and_keep_matched_ones() {
+    let workspace = TempDir::new().unwrap();
+    let paths = Paths::portable(workspace.path().join("data"));
+    let library = Library::with_paths(&paths);
+    library.add_track(track("a1", "Abbey Road", "The Beatles", Some(RELEASE)));
+    library.add_track(track("c1", "Let It Be", "The Beatles", None));
+
+    // Albums are listed without a key, which would fetch their artwork
+    let listing = AlbumService::with_paths(&paths, None);
+    let tagged = listing
+        .album_by_musicbrainz_id(&library, RELEASE)
+        .await
+        .unwrap();
+    let untagged = listing
+        .search_albums(&library, Some("Let It Be"))
+        .await
+        .remove(0);
+    assert_eq!(untagged.musicbrainz_release_id, None);
+
+    let matched = format!(
+        r#"{{"album":{{"name":"Let It Be","mbid":"{}"}}}}"#,
+        REISSUE.to_uppercase()
+    );
+    let (endpoint, requests) = serve_lastfm(vec![
+        r#"{"album":{"name":"Abbey Road","mbid":""}}"#.to_string(),
+        matched,
+    ]);
+    let service =
+        AlbumService::with_paths(&paths, Some("key".into())).with_lastfm_endpoint(&endpoint);
+    let update = |album: &str, musicbrainz_release_id: Option<&str>| ManualAlbumUpdate {
+        title: None,
+        primary_artist: None,
+        search_album: Some(album.into()),
+        search_artist: Some("The Beatles".into()),
+        refresh_artwork: false,
+        disambiguation: None,
+        musicbrainz_release_id: musicbrainz_release_id.map(str::to_string),
+    };
+
+    // Tagged with a release, the album is looked up by it alone
+    let record = service
+        .set_manual_override(&tagged.id, update("Abbey Road", Some(RELEASE)))
+        .await
+        .unwrap();
+    let request = requests.recv().unwrap();
+    assert!(
+        request.contains(&format!("mbid={}", RELEASE)),
+        "{}",
+        request
+    );
+    assert!(!request.contains("artist="), "{}", request);
+    assert_eq!(record.musicbrainz_release_id, None, "the tag already says");
+
+    // Untagged, it is looked up by name and keeps the release Last.fm matched
+    let record = service
+        .set_manual_override(&untagged.id, update("Let It Be", None))
+        .await
+        .unwrap();
+    let request = requests.recv().unwrap();
+    assert!(request.contains("artist=The+Beatles"), "{}", request);
+    assert!(!request.contains("mbid="), "{}", request);
+    assert_eq!(record.musicbrainz_release_id.as_deref(), Some(REISSUE));
+
+    let album = AlbumService::with_paths(&paths, None)
+        .album_by_musicbrainz_id(&library, REISSUE)
+        .await
+        .expect("the matched release finds the album");
+    assert_eq!(album.id, untagged.id);
+}
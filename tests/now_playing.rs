@@ -0,0 +1,142 @@
+mod common;
+
+use common::TestTrack;
+use hexendrum::config::IntegrationsConfig;
+use hexendrum::events::{NowPlaying, NowPlayingTemplate, NowPlayingWriter};
+use hexendrum::library::{Library, Track};
+use hexendrum::{EventBus, EventPayload, TrackMetadata};
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn track(id: &str, title: Option<&str>, artist: Option<&str>) -> Track {
+    TestTrack::new(id)
+        .metadata(TrackMetadata {
+            title: title.map(str::to_string),
+            artist: artist.map(str::to_string),
+            album: Some("Geogaddi".into()),
+            duration: Some(245),
+            ..Default::default()
+        })
+        .build()
+}
+
+fn playback(state: &str, track: Option<&Track>) -> EventPayload {
+    EventPayload::playback_state(
+        state,
+        track.map(|track| track.metadata.file_path.to_string_lossy().to_string()),
+        track.map(|track| track.id.clone()),
+        Some(0.5),
+        track.and_then(|track| track.metadata.duration),
+    )
+}
+
+/// Wait until `path` holds `expected`
+async fn wait_for(path: &Path, expected: &str) {
+    let deadline = std::time::Instant::now() + Duration::from_secs(5);
+    loop {
+        let content = fs::read_to_string(path).unwrap_or_default();
+        if content == expected {
+            return;
+        }
+        assert!(
+            std::time::Instant::now() < deadline,
+            "now-playing file holds {:?}, expected {:?}",
+            content,
+            expected
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[test]
+fn templates_render_placeholders_and_refuse_unknown_ones() {
+    let template = NowPlayingTemplate::parse("{artist} — {title} [{position}/{duration}] {{live}}")
+        .expect("template should parse");
+    assert!(template.uses_position());
+    let track = NowPlaying {
+        title: "Dawn Chorus".into(),
+        artist: Some("Boards of Canada".into()),
+        album: None,
+        duration: Some(245),
+    };
+    assert_eq!(
+        template.render(&track, Duration::from_secs(65)),
+        "Boards of Canada — Dawn Chorus [01:05/04:05] {live}"
+    );
+    assert!(!NowPlayingTemplate::default().uses_position());
+
+    let error = NowPlayingTemplate::parse("{artist} — {song}").unwrap_err();
+    assert!(
+        error.to_string().contains("Unknown placeholder {song}"),
+        "{}",
+        error
+    );
+    assert!(NowPlayingTemplate::parse("{title").is_err());
+    assert!(NowPlayingTemplate::parse("title}").is_err());
+}
+
+#[tokio::test]
+async fn now_playing_file_follows_playback_and_is_cleared_on_stop() {
+    let workspace = tempfile::tempdir().unwrap();
+    let path = workspace.path().join("now-playing.txt");
+    fs::write(&path, "stale").unwrap();
+    let library = Arc::new(Library::new());
+    let dawn = track("dawn", Some("Dawn Chorus"), Some("Boards of Canada"));
+    let untitled = track("untitled-01", None, None);
+    library.add_track(dawn.clone());
+    library.add_track(untitled.clone());
+    let event_bus = EventBus::new(None);
+    let config = IntegrationsConfig {
+        now_playing_file: Some(path.clone()),
+        ..Default::default()
+    };
+
+    NowPlayingWriter::start(&event_bus, &config, library, || Duration::ZERO)
+        .expect("writer should start");
+    wait_for(&path, "").await;
+
+    event_bus.emit(playback("playing", Some(&dawn)));
+    wait_for(&path, "Boards of Canada — Dawn Chorus").await;
+
+    // Pausing keeps the track shown
+    event_bus.emit(playback("paused", Some(&dawn)));
+    event_bus.emit(playback("playing", Some(&untitled)));
+    wait_for(&path, " — untitled-01").await;
+
+    event_bus.emit(playback("stopped", None));
+    wait_for(&path, "").await;
+    assert!(!workspace.path().join("now-playing.txt.tmp").exists());
+}
+
+#[tokio::test]
+async fn position_templates_are_refreshed_while_playing() {
+    let workspace = tempfile::tempdir().unwrap();
+    let path = workspace.path().join("now-playing.txt");
+    let library = Arc::new(Library::new());
+    let dawn = track("dawn", Some("Dawn Chorus"), Some("Boards of Canada"));
+    library.add_track(dawn.clone());
+    let event_bus = EventBus::new(None);
+    let config = IntegrationsConfig {
+        now_playing_file: Some(path.clone()),
+        now_playing_template: NowPlayingTemplate::parse("{title} {position}/{duration}").unwrap(),
+        now_playing_refresh_secs: 1,
+        ..Default::default()
+    };
+    let position = Arc::new(AtomicU64::new(3));
+    let read_position = position.clone();
+
+    NowPlayingWriter::start(&event_bus, &config, library, move || {
+        Duration::from_secs(read_position.load(Ordering::SeqCst))
+    })
+    .expect("writer should start");
+
+    event_bus.emit(playback("playing", Some(&dawn)));
+    wait_for(&path, "Dawn Chorus 00:03/04:05").await;
+
+    // Picked up without another event
+    position.store(64, Ordering::SeqCst);
+    wait_for(&path, "Dawn Chorus 01:04/04:05").await;
+}
@@ -1,8 +1,21 @@
-use hexendrum::library::Library;
-use hexendrum::playlist::{PlaybackQueue, PlaylistManager, RepeatMode};
+mod common;
+
+use chrono::{Duration as ChronoDuration, Utc};
+use common::write_silent_wav;
+use hexendrum::config::Paths;
+use hexendrum::library::{write_track_tags, Library, Track, TrackTagUpdate};
+use hexendrum::playlist::{
+    normalize_folder, EntryPath, ImportAction, ImportConflictPolicy, MusicRoot, MusicRoots,
+    PlayOrder, PlaybackQueue, Playlist, PlaylistEditError, PlaylistEntry, PlaylistError,
+    PlaylistFolder, PlaylistManager, PortableLocation, RepeatMode, COMPACT_PLAYLIST_THRESHOLD,
+};
+use hexendrum::utils::natural_cmp;
 use serial_test::serial;
+use std::cmp::Ordering;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tempfile::TempDir;
 
 struct PlaylistTestEnv {
@@ -59,6 +72,21 @@ impl PlaylistTestEnv {
         fs::write(&path, b"fake audio data").expect("failed to write audio file");
         path
     }
+
+    fn create_tagged_track<P: AsRef<Path>>(&self, name: P, artist: &str, title: &str) -> PathBuf {
+        let path = self.music_dir.join(name);
+        write_silent_wav(&path);
+        write_track_tags(
+            &path,
+            &TrackTagUpdate {
+                title: Some(title.into()),
+                artist: Some(artist.into()),
+                ..Default::default()
+            },
+        )
+        .expect("failed to tag audio file");
+        path
+    }
 }
 
 impl Drop for PlaylistTestEnv {
@@ -106,6 +134,7 @@ fn playlist_manager_workflow_and_cleanup() {
     let playlist_id = manager.create_playlist("My Playlist".into(), Some("Great tracks".into()));
     let mut playlist = manager
         .get_playlist(&playlist_id)
+        .map(Arc::unwrap_or_clone)
         .expect("playlist should exist");
 
     playlist.add_track(&track_a);
@@ -151,20 +180,280 @@ fn playlist_manager_workflow_and_cleanup() {
 
     // Remove a track from the library so cleanup routines have work to do
     assert!(library.remove_track(&track_b.id));
+    let orphans = manager
+        .find_orphaned_entries(&library, None)
+        .expect("dry run should succeed");
+    assert_eq!(orphans.len(), 1);
+    assert_eq!(orphans[0].track_id, track_b.id);
+    assert_eq!(orphans[0].playlist_name, "My Playlist");
+    assert_eq!(
+        manager.get_playlist(&playlist_id).unwrap().track_count(),
+        2,
+        "finding orphaned entries must not remove them"
+    );
+
     let removed_from_single = manager
         .cleanup_playlist(&playlist_id, &library)
         .expect("cleanup should succeed");
-    assert_eq!(removed_from_single, 1);
+    assert_eq!(removed_from_single, orphans);
 
     let removed_total = manager
         .cleanup_missing_tracks(&library)
         .expect("global cleanup should succeed");
-    assert_eq!(removed_total, 0);
+    assert!(removed_total.is_empty());
+
+    let missing = manager
+        .cleanup_playlist("missing", &library)
+        .expect_err("unknown playlists cannot be cleaned up");
+    assert!(matches!(missing, PlaylistError::PlaylistNotFound(id) if id == "missing"));
 
     assert!(manager.delete_playlist(&playlist_id));
     assert!(manager.get_playlist(&playlist_id).is_none());
 }
 
+const EXPORTIFY_CSV: &str = "\
+Track URI,Track Name,Artist Name(s),Album Name,Duration (ms)
+spotify:track:1,Bohemian Rhapsody - Remastered 2011,Queen,A Night at the Opera,200
+spotify:track:2,Under Presure,Bowie,Hot Space,100
+spotify:track:3,One More Time,Daft Punk,Discovery,320357
+";
+
+#[test]
+#[serial]
+fn csv_import_dry_run_reports_matches_without_creating_playlist() {
+    let env = PlaylistTestEnv::new();
+    env.create_tagged_track("bohemian.wav", "Queen", "Bohemian Rhapsody");
+    env.create_tagged_track("pressure.wav", "Queen & David Bowie", "Under Pressure");
+
+    let library = Library::new();
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+
+    let manager = PlaylistManager::new(env.playlist_dir()).expect("manager should initialize");
+    let report = manager
+        .import_csv(
+            &library,
+            "Imported",
+            EXPORTIFY_CSV,
+            true,
+            ImportConflictPolicy::Rename,
+        )
+        .expect("import should succeed");
+
+    assert!(report.dry_run);
+    assert!(report.playlist_id.is_none());
+    assert_eq!(report.total_rows, 3);
+    assert_eq!(report.matched.len(), 2);
+    assert_eq!(report.matched[0].row.line, 2);
+    assert_eq!(report.matched[0].track.confidence, 1.0);
+    assert_eq!(report.low_confidence().count(), 1);
+    assert_eq!(report.unmatched.len(), 1);
+    assert_eq!(report.unmatched[0].query.title, "One More Time");
+    assert_eq!(report.unmatched[0].query.duration, Some(320));
+    assert!(manager.get_playlists().is_empty());
+}
+
+#[test]
+#[serial]
+fn csv_import_creates_playlist_in_csv_order() {
+    let env = PlaylistTestEnv::new();
+    env.create_tagged_track("bohemian.wav", "Queen", "Bohemian Rhapsody");
+    env.create_tagged_track("pressure.wav", "Queen & David Bowie", "Under Pressure");
+
+    let library = Library::new();
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+
+    let manager = PlaylistManager::new(env.playlist_dir()).expect("manager should initialize");
+    let report = manager
+        .import_csv(
+            &library,
+            "Imported",
+            EXPORTIFY_CSV,
+            false,
+            ImportConflictPolicy::Rename,
+        )
+        .expect("import should succeed");
+
+    let playlist_id = report.playlist_id.expect("playlist should be created");
+    let playlist = manager
+        .get_playlist(&playlist_id)
+        .expect("playlist should be registered");
+    let track_ids: Vec<String> = playlist
+        .entries
+        .iter()
+        .map(|entry| entry.track_id.clone())
+        .collect();
+    let expected: Vec<String> = report
+        .matched
+        .iter()
+        .map(|entry| entry.track.track_id.clone())
+        .collect();
+
+    assert_eq!(playlist.name, "Imported");
+    assert_eq!(track_ids, expected);
+    assert!(env
+        .playlist_dir()
+        .join(format!("{}.json", playlist_id))
+        .exists());
+}
+
+#[test]
+#[serial]
+fn csv_import_rejects_files_without_title_column() {
+    let env = PlaylistTestEnv::new();
+    let library = Library::new();
+    let manager = PlaylistManager::new(env.playlist_dir()).expect("manager should initialize");
+
+    let error = manager
+        .import_csv(
+            &library,
+            "Broken",
+            "Artist,Album\nQueen,Jazz\n",
+            true,
+            ImportConflictPolicy::Rename,
+        )
+        .expect_err("the CSV has no title column");
+    assert!(matches!(error, PlaylistError::MissingTitleColumn));
+}
+
+/// A playlist named `name` with entries for `track_ids`
+fn imported_playlist(name: &str, track_ids: &[&str]) -> Playlist {
+    let mut playlist = Playlist::new(name.into(), Some("Imported".into()));
+    playlist.entries = track_ids
+        .iter()
+        .map(|track_id| PlaylistEntry {
+            track_id: track_id.to_string(),
+            added_at: Utc::now(),
+            play_count: 0,
+            last_played: None,
+            note: None,
+            pinned: false,
+            path: None,
+        })
+        .collect();
+    playlist
+}
+
+fn entry_ids(manager: &PlaylistManager, playlist_id: &str) -> Vec<String> {
+    manager
+        .get_playlist(playlist_id)
+        .expect("playlist should exist")
+        .entries
+        .iter()
+        .map(|entry| entry.track_id.clone())
+        .collect()
+}
+
+/// A manager holding a playlist "Road Trip" with tracks a and b
+fn manager_with_road_trip() -> (TempDir, PlaylistManager, Playlist) {
+    let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+    let manager = PlaylistManager::new(workspace.path().join("playlists"))
+        .expect("manager should initialize");
+    let mut existing = imported_playlist("Road Trip", &["a", "b"]);
+    existing.created_at = Utc::now() - ChronoDuration::days(30);
+    let outcome = manager
+        .import_playlist(existing.clone(), ImportConflictPolicy::Fail)
+        .expect("the first import should not conflict");
+    assert_eq!(outcome.action, ImportAction::Created);
+    (workspace, manager, existing)
+}
+
+#[test]
+fn import_conflicts_can_be_renamed() {
+    let (_workspace, manager, existing) = manager_with_road_trip();
+
+    let outcome = manager
+        .import_playlist(
+            imported_playlist("road trip", &["c"]),
+            ImportConflictPolicy::Rename,
+        )
+        .unwrap();
+    assert_eq!(outcome.action, ImportAction::Renamed);
+    assert_eq!(outcome.playlist_name, "road trip (imported)");
+    assert_ne!(outcome.playlist_id, existing.id);
+
+    // Same id as the existing playlist and the renamed name taken too
+    let mut clash = imported_playlist("Road Trip", &["d"]);
+    clash.id = existing.id.clone();
+    let outcome = manager
+        .import_playlist(clash, ImportConflictPolicy::Rename)
+        .unwrap();
+    assert_eq!(outcome.playlist_name, "Road Trip (imported 2)");
+    assert_ne!(outcome.playlist_id, existing.id);
+    assert_eq!(entry_ids(&manager, &existing.id), vec!["a", "b"]);
+    assert_eq!(manager.get_playlists().len(), 3);
+}
+
+#[test]
+fn import_conflicts_can_be_merged() {
+    let (_workspace, manager, existing) = manager_with_road_trip();
+
+    let outcome = manager
+        .import_playlist(
+            imported_playlist("Road Trip", &["b", "c", "c", "d"]),
+            ImportConflictPolicy::Merge,
+        )
+        .unwrap();
+    assert_eq!(outcome.action, ImportAction::Merged);
+    assert_eq!(outcome.playlist_id, existing.id);
+    assert_eq!(outcome.entries_added, 2);
+    assert_eq!(entry_ids(&manager, &existing.id), vec!["a", "b", "c", "d"]);
+    assert_eq!(manager.get_playlists().len(), 1);
+}
+
+#[test]
+fn import_conflicts_can_replace_the_existing_playlist() {
+    let (workspace, manager, existing) = manager_with_road_trip();
+
+    let mut replacement = imported_playlist("Road Trip", &["c"]);
+    replacement.play_order = PlayOrder::Shuffle;
+    let outcome = manager
+        .import_playlist(replacement, ImportConflictPolicy::Replace)
+        .unwrap();
+    assert_eq!(outcome.action, ImportAction::Replaced);
+    assert_eq!(outcome.playlist_id, existing.id);
+
+    let playlist = manager.get_playlist(&existing.id).unwrap();
+    assert_eq!(entry_ids(&manager, &existing.id), vec!["c"]);
+    assert_eq!(playlist.created_at, existing.created_at);
+    assert_eq!(playlist.play_order, PlayOrder::Shuffle);
+    assert_eq!(manager.get_playlists().len(), 1);
+
+    let saved = manager
+        .load_playlist(
+            &workspace
+                .path()
+                .join("playlists")
+                .join(format!("{}.json", existing.id)),
+        )
+        .expect("the replaced playlist should be saved");
+    assert_eq!(saved.entries.len(), 1);
+}
+
+#[test]
+fn import_conflicts_can_fail() {
+    let (_workspace, manager, existing) = manager_with_road_trip();
+
+    let error = manager
+        .import_playlist(
+            imported_playlist("Road Trip", &["c"]),
+            ImportConflictPolicy::Fail,
+        )
+        .expect_err("the import should be refused");
+    let PlaylistError::Conflict(conflict) = error else {
+        panic!(
+            "the error should name the conflicting playlist, got {:?}",
+            error
+        );
+    };
+    assert_eq!(conflict.existing.id, existing.id);
+    assert_eq!(conflict.existing.track_count, 2);
+    assert_eq!(manager.get_playlists().len(), 1);
+}
+
 #[test]
 fn playback_queue_operations_cover_all_branches() {
     let queue = PlaybackQueue::new();
@@ -191,3 +480,842 @@ fn playback_queue_operations_cover_all_branches() {
     assert!(queue.is_empty());
     assert!(queue.next_track().is_none());
 }
+
+#[test]
+fn shuffle_previous_walks_the_play_history() {
+    let queue = PlaybackQueue::new();
+    queue.add_tracks(&["a".to_string(), "b".to_string(), "c".to_string()]);
+    queue.set_shuffle(true);
+
+    for track in ["c", "a", "b"] {
+        queue.record_played(track);
+    }
+    let history: Vec<String> = queue.history(2).into_iter().map(|e| e.track_id).collect();
+    assert_eq!(history, vec!["b", "a"]);
+
+    assert_eq!(queue.previous_track(), Some("a".into()));
+    assert_eq!(queue.current_track(), Some("a".into()));
+    // Playing what we stepped back to must not break the walk.
+    queue.record_played("a");
+    assert_eq!(queue.previous_track(), Some("c".into()));
+    assert_eq!(queue.previous_track(), None);
+
+    assert_eq!(queue.next_track(), Some("a".into()));
+    assert_eq!(queue.next_track(), Some("b".into()));
+
+    queue.clear();
+    assert_eq!(queue.history(10).len(), 3);
+    queue.clear_history();
+    assert!(queue.history(10).is_empty());
+    assert_eq!(queue.previous_track(), None);
+}
+
+#[test]
+fn shuffle_back_play_back_does_not_repeat_the_same_track() {
+    let queue = PlaybackQueue::new();
+    queue.add_tracks(&["a".to_string(), "b".to_string(), "c".to_string()]);
+    queue.set_shuffle(true);
+
+    for track in ["c", "a", "b"] {
+        queue.record_played(track);
+    }
+
+    assert_eq!(queue.previous_track(), Some("a".into()));
+    assert_eq!(queue.next_track(), Some("b".into()));
+    // Back at the head of the history, so playing it again must not push a duplicate.
+    queue.record_played("b");
+    assert_eq!(queue.history(10).len(), 3);
+    assert_eq!(queue.previous_track(), Some("a".into()));
+    assert_eq!(queue.previous_track(), Some("c".into()));
+}
+
+#[test]
+#[serial]
+fn queue_settings_persist_to_state_file() {
+    let env = PlaylistTestEnv::new();
+    let state_file = env.playlist_dir().join("queue_state.json");
+
+    let queue = PlaybackQueue::with_state_file(state_file.clone(), RepeatMode::One, true);
+    assert_eq!(queue.get_repeat_mode(), RepeatMode::One);
+    assert!(queue.is_shuffle_enabled());
+    assert!(
+        !state_file.exists(),
+        "defaults alone should not be persisted"
+    );
+
+    queue.set_repeat_mode(RepeatMode::All);
+    queue.set_shuffle(false);
+    drop(queue);
+
+    let restored = PlaybackQueue::with_state_file(state_file.clone(), RepeatMode::None, true);
+    assert_eq!(restored.get_repeat_mode(), RepeatMode::All);
+    assert!(!restored.is_shuffle_enabled());
+
+    fs::write(&state_file, "not json").expect("failed to corrupt state file");
+    let fallback = PlaybackQueue::with_state_file(state_file, RepeatMode::One, false);
+    assert_eq!(fallback.get_repeat_mode(), RepeatMode::One);
+}
+
+#[test]
+fn repeat_mode_parses_and_serializes_as_snake_case() {
+    assert_eq!("all".parse::<RepeatMode>().unwrap(), RepeatMode::All);
+    assert_eq!(" One ".parse::<RepeatMode>().unwrap(), RepeatMode::One);
+    assert!(matches!(
+        "sometimes".parse::<RepeatMode>(),
+        Err(PlaylistError::InvalidRepeatMode(mode)) if mode == "sometimes"
+    ));
+
+    assert_eq!(
+        serde_json::to_string(&RepeatMode::None).unwrap(),
+        "\"none\""
+    );
+    assert_eq!(
+        serde_json::from_str::<RepeatMode>("\"all\"").unwrap(),
+        RepeatMode::All
+    );
+    assert_eq!(RepeatMode::One.to_string(), "one");
+}
+
+#[test]
+fn large_playlists_are_listed_cheaply_and_saved_compact() {
+    let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+    let playlist_dir = workspace.path().join("playlists");
+    let manager = PlaylistManager::new(playlist_dir.clone()).expect("manager should initialize");
+
+    let playlist_id = manager.create_playlist("Everything".into(), None);
+    let mut playlist = manager
+        .get_playlist(&playlist_id)
+        .map(Arc::unwrap_or_clone)
+        .unwrap();
+    let added_at = Utc::now();
+    playlist.entries = (0..20_000)
+        .map(|index| PlaylistEntry {
+            track_id: format!("{:08}-0000-4000-8000-000000000000", index),
+            added_at,
+            play_count: 0,
+            last_played: None,
+            note: None,
+            pinned: false,
+            path: None,
+        })
+        .collect();
+    manager.update_playlist(playlist.clone());
+
+    let started = Instant::now();
+    for _ in 0..1000 {
+        let summaries = manager.playlist_summaries();
+        assert_eq!(summaries[0].track_count, 20_000);
+        let shared = manager.get_playlist(&playlist_id).unwrap();
+        assert_eq!(shared.track_count(), 20_000);
+    }
+    assert!(
+        started.elapsed() < Duration::from_secs(1),
+        "listing took {:?}",
+        started.elapsed()
+    );
+    assert!(Arc::ptr_eq(
+        &manager.get_playlist(&playlist_id).unwrap(),
+        &manager.get_playlists()[0]
+    ));
+
+    manager
+        .save_playlist(&playlist)
+        .expect("playlist should save");
+    let path = playlist_dir.join(format!("{}.json", playlist_id));
+    let size = fs::metadata(&path).unwrap().len() as usize;
+    let pretty_size = serde_json::to_vec_pretty(&playlist).unwrap().len();
+    assert!(size > COMPACT_PLAYLIST_THRESHOLD);
+    assert_eq!(
+        size,
+        r#"{"version":1,"#.len() + serde_json::to_vec(&playlist).unwrap().len() - 1,
+        "compact, with the format version"
+    );
+    assert!(size < pretty_size, "{} vs {}", size, pretty_size);
+    assert!(size < 20_000 * 160);
+
+    let loaded = manager.load_playlist(&path).expect("playlist should load");
+    assert_eq!(loaded.track_count(), 20_000);
+
+    // Small playlists stay readable
+    let small = Playlist::new("Small".into(), None);
+    manager.save_playlist(&small).unwrap();
+    let content = fs::read_to_string(playlist_dir.join(format!("{}.json", small.id))).unwrap();
+    assert!(content.contains("\n  \"name\": \"Small\""));
+}
+
+#[test]
+#[serial]
+fn play_order_controls_the_queue_but_not_the_stored_entries() {
+    let env = PlaylistTestEnv::new();
+    env.create_tagged_track("1.wav", "Zed", "Bravo");
+    env.create_tagged_track("2.wav", "Abba", "Delta");
+    env.create_tagged_track("3.wav", "Moby", "Alpha");
+    env.create_tagged_track("4.wav", "Abba", "Charlie");
+
+    let library = Library::new();
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+    let id_of = |title: &str| {
+        library
+            .get_tracks()
+            .into_iter()
+            .find(|track| track.metadata.title.as_deref() == Some(title))
+            .unwrap()
+            .id
+    };
+
+    let mut playlist = Playlist::new("Chill".into(), None);
+    let added_at = Utc::now();
+    playlist.entries = ["Bravo", "Delta", "Alpha", "Charlie"]
+        .iter()
+        .enumerate()
+        .map(|(index, title)| PlaylistEntry {
+            track_id: id_of(title),
+            added_at: added_at + ChronoDuration::minutes(index as i64),
+            play_count: 0,
+            last_played: None,
+            note: None,
+            pinned: false,
+            path: None,
+        })
+        .collect();
+    let stored: Vec<String> = playlist
+        .entries
+        .iter()
+        .map(|entry| entry.track_id.clone())
+        .collect();
+
+    let queue = PlaybackQueue::new();
+    let queued_titles = |playlist: &Playlist| {
+        assert_eq!(queue.load_playlist(playlist, &library), 4);
+        queue
+            .track_ids()
+            .iter()
+            .map(|id| library.get_track(id).unwrap().metadata.title.unwrap())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        queued_titles(&playlist),
+        ["Bravo", "Delta", "Alpha", "Charlie"]
+    );
+    playlist.play_order = PlayOrder::Title;
+    assert_eq!(
+        queued_titles(&playlist),
+        ["Alpha", "Bravo", "Charlie", "Delta"]
+    );
+    playlist.play_order = PlayOrder::Artist;
+    assert_eq!(
+        queued_titles(&playlist),
+        ["Charlie", "Delta", "Alpha", "Bravo"]
+    );
+    playlist.play_order = PlayOrder::AddedDesc;
+    assert_eq!(
+        queued_titles(&playlist),
+        ["Charlie", "Alpha", "Delta", "Bravo"]
+    );
+    playlist.play_order = PlayOrder::Shuffle;
+    let mut shuffled = queued_titles(&playlist);
+    shuffled.sort();
+    assert_eq!(shuffled, ["Alpha", "Bravo", "Charlie", "Delta"]);
+
+    let entries: Vec<String> = playlist
+        .entries
+        .iter()
+        .map(|entry| entry.track_id.clone())
+        .collect();
+    assert_eq!(entries, stored);
+
+    // The queue keeps its repeat mode unless the playlist has a default
+    queue.set_repeat_mode(RepeatMode::One);
+    queue.load_playlist(&playlist, &library);
+    assert_eq!(queue.get_repeat_mode(), RepeatMode::One);
+    playlist.default_repeat = Some(RepeatMode::All);
+    queue.load_playlist(&playlist, &library);
+    assert_eq!(queue.get_repeat_mode(), RepeatMode::All);
+}
+
+#[test]
+#[serial]
+fn a_playlist_repeat_mode_is_not_saved_as_the_queue_setting() {
+    let env = PlaylistTestEnv::new();
+    env.create_tagged_track("1.wav", "Abba", "Alpha");
+    let library = Library::new();
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+    let state_file = env.playlist_dir().join("queue_state.json");
+
+    let queue = PlaybackQueue::with_state_file(state_file.clone(), RepeatMode::None, false);
+    queue.set_repeat_mode(RepeatMode::One);
+    let saved = fs::read_to_string(&state_file).unwrap();
+
+    let mut playlist = Playlist::new("Loop".into(), None);
+    playlist.add_track(&library.get_tracks()[0]);
+    playlist.default_repeat = Some(RepeatMode::All);
+    queue.load_playlist(&playlist, &library);
+    assert_eq!(queue.get_repeat_mode(), RepeatMode::All);
+    assert_eq!(fs::read_to_string(&state_file).unwrap(), saved);
+
+    // Saving another setting keeps the chosen repeat mode
+    queue.set_shuffle(true);
+    let restored = PlaybackQueue::with_state_file(state_file, RepeatMode::None, false);
+    assert_eq!(restored.get_repeat_mode(), RepeatMode::One);
+    assert!(restored.is_shuffle_enabled());
+}
+
+#[test]
+fn playlists_saved_before_play_orders_load_with_defaults() {
+    let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+    let manager = PlaylistManager::new(workspace.path().to_path_buf()).unwrap();
+    let path = workspace.path().join("old.json");
+    fs::write(
+        &path,
+        r#"{
+            "id": "old",
+            "name": "Album queue",
+            "description": null,
+            "created_at": "2024-01-15T10:30:00Z",
+            "modified_at": "2024-01-15T10:30:00Z",
+            "entries": [],
+            "file_path": null
+        }"#,
+    )
+    .unwrap();
+
+    let playlist = manager
+        .load_playlist(&path)
+        .expect("old playlist should load");
+    assert_eq!(playlist.play_order, PlayOrder::Stored);
+    assert_eq!(playlist.default_repeat, None);
+    assert_eq!(playlist.folder, None);
+
+    let mut playlist = playlist;
+    playlist.play_order = PlayOrder::AddedDesc;
+    playlist.default_repeat = Some(RepeatMode::One);
+    let json = serde_json::to_value(&playlist).unwrap();
+    assert_eq!(json["play_order"], "added_desc");
+    assert_eq!(json["default_repeat"], "one");
+}
+
+#[test]
+#[serial]
+fn pinned_entries_come_first_and_notes_reach_m3u_exports() {
+    let env = PlaylistTestEnv::new();
+    env.create_tagged_track("1.wav", "Abba", "First");
+    env.create_tagged_track("2.wav", "Abba", "Second");
+    env.create_tagged_track("3.wav", "Moby", "Third");
+    env.create_tagged_track("4.wav", "Moby", "Fourth");
+
+    let library = Library::new();
+    library
+        .scan_directories(&[env.music_dir()])
+        .expect("scan should succeed");
+    let track = |title: &str| {
+        library
+            .get_tracks()
+            .into_iter()
+            .find(|track| track.metadata.title.as_deref() == Some(title))
+            .unwrap()
+    };
+
+    let mut playlist = Playlist::new("Mixtape".into(), None);
+    for title in ["First", "Second", "Third", "Fourth"] {
+        playlist.add_track(&track(title));
+    }
+
+    assert_eq!(
+        playlist.update_entry(&track("Fourth").id, None, Some(true)),
+        Some(3)
+    );
+    assert_eq!(
+        playlist.update_entry(
+            &track("Second").id,
+            Some(Some("  This reminds me\nof the trip ".into())),
+            Some(true)
+        ),
+        Some(1)
+    );
+    assert_eq!(playlist.update_entry("missing", None, Some(true)), None);
+
+    let listed: Vec<usize> = playlist
+        .listed_entries()
+        .into_iter()
+        .map(|(position, _)| position)
+        .collect();
+    assert_eq!(listed, vec![1, 3, 0, 2]);
+    let titles: Vec<String> = playlist
+        .tracks_in_play_order(&library)
+        .iter()
+        .map(|id| library.get_track(id).unwrap().metadata.title.unwrap())
+        .collect();
+    assert_eq!(titles, vec!["Second", "Fourth", "First", "Third"]);
+
+    let m3u = playlist.to_m3u(&library);
+    let lines: Vec<&str> = m3u.lines().collect();
+    assert_eq!(lines[0], "#EXTM3U");
+    assert_eq!(lines[1], "#PLAYLIST:Mixtape");
+    assert!(lines[2].starts_with("#EXTINF:"));
+    assert!(lines[2].ends_with(",Abba - Second"));
+    assert_eq!(lines[3], "# This reminds me");
+    assert_eq!(lines[4], "# of the trip");
+    assert!(lines[5].ends_with("2.wav"));
+    assert!(lines[6].ends_with(",Moby - Fourth"));
+    assert_eq!(lines.len(), 2 + 4 * 2 + 2);
+
+    // A blank note removes it
+    playlist.update_entry(&track("Second").id, Some(Some(" ".into())), None);
+    assert_eq!(playlist.entries[1].note, None);
+    assert!(playlist.entries[1].pinned);
+}
+
+#[test]
+fn playlist_entries_saved_before_notes_load_unchanged() {
+    let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+    let manager = PlaylistManager::new(workspace.path().to_path_buf()).unwrap();
+    let path = workspace.path().join("old.json");
+    let entry = r#"{
+        "track_id": "f47ac10b-58cc-4372-a567-0e02b2c3d479",
+        "added_at": "2024-01-15T10:30:00Z",
+        "play_count": 2,
+        "last_played": null
+    }"#;
+    fs::write(
+        &path,
+        format!(
+            r#"{{
+                "id": "old",
+                "name": "Mixtape",
+                "description": null,
+                "created_at": "2024-01-15T10:30:00Z",
+                "modified_at": "2024-01-15T10:30:00Z",
+                "entries": [{}],
+                "file_path": null
+            }}"#,
+            entry
+        ),
+    )
+    .unwrap();
+
+    let playlist = manager
+        .load_playlist(&path)
+        .expect("old playlist should load");
+    assert_eq!(playlist.entries[0].note, None);
+    assert!(!playlist.entries[0].pinned);
+    assert_eq!(
+        serde_json::to_value(&playlist.entries[0]).unwrap(),
+        serde_json::from_str::<serde_json::Value>(entry).unwrap()
+    );
+}
+
+/// A library scanned from `music_dir` holding "Artist/Album/01 Song.wav"
+fn library_with_song(music_dir: &Path) -> (Library, PathBuf) {
+    let album_dir = music_dir.join("Artist").join("Album");
+    fs::create_dir_all(&album_dir).unwrap();
+    let song = album_dir.join("01 Song.wav");
+    write_silent_wav(&song);
+    // Each machine has a cache of its own
+    let library = Library::with_paths(&Paths::portable(music_dir.with_file_name("data")));
+    library
+        .scan_directories(&[music_dir.to_path_buf()])
+        .unwrap();
+    (library, song)
+}
+
+fn music_roots(path: &Path) -> MusicRoots {
+    MusicRoots::new(vec![MusicRoot {
+        name: "Music".into(),
+        path: path.to_path_buf(),
+    }])
+}
+
+#[test]
+#[serial]
+fn portable_playlists_follow_their_tracks_to_another_machine() {
+    let _env = PlaylistTestEnv::new();
+    let workspace = tempfile::tempdir().unwrap();
+    let (music_a, playlists_a) = (
+        workspace.path().join("a/Music"),
+        workspace.path().join("a/lists"),
+    );
+    let (music_b, playlists_b) = (
+        workspace.path().join("b/Music"),
+        workspace.path().join("b/lists"),
+    );
+    fs::create_dir_all(&playlists_a).unwrap();
+    fs::create_dir_all(&playlists_b).unwrap();
+
+    // Machine A writes the entry relative to its "Music" directory
+    let (library_a, _) = library_with_song(&music_a);
+    let manager_a = PlaylistManager::new(playlists_a.clone())
+        .unwrap()
+        .with_music_roots(music_roots(&music_a))
+        .with_portable_paths(true);
+    let mut playlist = Playlist::new("Synced".into(), None);
+    playlist.add_track(&library_a.get_tracks()[0]);
+    manager_a.save_playlist(&playlist).unwrap();
+    let file_name = format!("{}.json", playlist.id);
+    let saved: serde_json::Value =
+        serde_json::from_slice(&fs::read(playlists_a.join(&file_name)).unwrap()).unwrap();
+    assert_eq!(
+        saved["entries"][0]["path"],
+        serde_json::json!({ "root": "Music", "path": "Artist/Album/01 Song.wav" })
+    );
+
+    // Machine B mounts the music elsewhere and scanned it into other track ids
+    let (library_b, song_b) = library_with_song(&music_b);
+    let track_b = library_b.get_tracks()[0].clone();
+    assert_ne!(track_b.id, playlist.entries[0].track_id);
+    fs::copy(playlists_a.join(&file_name), playlists_b.join(&file_name)).unwrap();
+    let manager_b = PlaylistManager::new(playlists_b.clone())
+        .unwrap()
+        .with_music_roots(music_roots(&music_b));
+    manager_b.load_all_playlists().unwrap();
+    let loaded = manager_b.get_playlist(&playlist.id).unwrap();
+    assert_eq!(
+        loaded.entries[0].path,
+        Some(EntryPath::Local(song_b.clone()))
+    );
+
+    let relinked = manager_b.relink_entries(&library_b, true).unwrap();
+    assert_eq!(relinked.len(), 1);
+    assert_eq!(relinked[0].track_id, track_b.id);
+    assert_eq!(
+        entry_ids(&manager_b, &playlist.id),
+        vec![playlist.entries[0].track_id.clone()]
+    );
+
+    manager_b.relink_entries(&library_b, false).unwrap();
+    assert_eq!(
+        entry_ids(&manager_b, &playlist.id),
+        vec![track_b.id.clone()]
+    );
+    assert!(manager_b
+        .relink_entries(&library_b, false)
+        .unwrap()
+        .is_empty());
+
+    // Without portable paths, machine B writes its own absolute path back
+    let saved: serde_json::Value =
+        serde_json::from_slice(&fs::read(playlists_b.join(&file_name)).unwrap()).unwrap();
+    assert_eq!(saved["entries"][0]["path"], serde_json::json!(song_b));
+}
+
+#[test]
+#[serial]
+fn entries_of_tracks_with_random_ids_follow_them_when_the_cache_is_upgraded() {
+    let env = PlaylistTestEnv::new();
+    let music_dir = env.music_dir();
+    let (library, song) = library_with_song(&music_dir);
+    let id = library.get_track_by_path(&song).unwrap().id;
+
+    // Written when track ids were random, with entries that have no path
+    let paths = Paths::portable(music_dir.with_file_name("data"));
+    let cache_file = paths.library_cache_file();
+    let old_id = "550e8400-e29b-41d4-a716-446655440000";
+    let mut cache: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&cache_file).unwrap()).unwrap();
+    cache["tracks"][0]["track"]["id"] = serde_json::json!(old_id);
+    fs::write(&cache_file, cache.to_string()).unwrap();
+    let manager = PlaylistManager::new(env.playlist_dir()).unwrap();
+    let playlist = imported_playlist("Old", &[old_id, "gone"]);
+    manager.save_playlist(&playlist).unwrap();
+    manager.load_all_playlists().unwrap();
+
+    let upgraded = Library::with_paths(&paths);
+    let relinked = manager.relink_entries(&upgraded, false).unwrap();
+    assert_eq!(relinked.len(), 1);
+    assert_eq!(relinked[0].previous_track_id, old_id);
+    assert_eq!(entry_ids(&manager, &playlist.id), vec![id.as_str(), "gone"]);
+    let entry = &manager.get_playlist(&playlist.id).unwrap().entries[0];
+    assert_eq!(entry.path, Some(EntryPath::Local(song)));
+    assert_eq!(
+        manager
+            .find_orphaned_entries(&upgraded, None)
+            .unwrap()
+            .len(),
+        1,
+        "only the entry of a track that is gone is left to clean up"
+    );
+
+    // A playlist still using the old id is relinked by a later start as well
+    let later = imported_playlist("Later", &[old_id]);
+    manager.save_playlist(&later).unwrap();
+    manager.load_all_playlists().unwrap();
+    let restarted = Library::with_paths(&paths);
+    let relinked = manager.relink_entries(&restarted, false).unwrap();
+    assert_eq!(relinked.len(), 1);
+    assert_eq!(entry_ids(&manager, &later.id), vec![id.as_str()]);
+}
+
+#[test]
+fn portable_entries_resolve_below_the_innermost_music_directory() {
+    let roots = MusicRoots::new(vec![
+        MusicRoot {
+            name: "Music".into(),
+            path: PathBuf::from("/srv/music"),
+        },
+        MusicRoot {
+            name: "Live".into(),
+            path: PathBuf::from("/srv/music/live"),
+        },
+    ]);
+
+    let location = roots
+        .locate(Path::new("/srv/music/live/2019/set.flac"))
+        .unwrap();
+    assert_eq!(
+        location,
+        PortableLocation {
+            root: "Live".into(),
+            path: "2019/set.flac".into(),
+        }
+    );
+    assert_eq!(
+        roots.resolve(&location),
+        Some(PathBuf::from("/srv/music/live/2019/set.flac"))
+    );
+    assert_eq!(roots.locate(Path::new("/home/me/song.mp3")), None);
+
+    // Entries leaving their music directory are never resolved
+    let escaping = PortableLocation {
+        root: "Music".into(),
+        path: "../secret.mp3".into(),
+    };
+    assert_eq!(roots.resolve(&escaping), None);
+    assert_eq!(
+        roots.loaded(EntryPath::Portable(escaping.clone())),
+        EntryPath::Portable(escaping)
+    );
+}
+
+#[test]
+fn natural_order_compares_numbers_by_value() {
+    assert_eq!(natural_cmp("Mix 2", "Mix 10"), Ordering::Less);
+    assert_eq!(natural_cmp("mix 10", "Mix 9"), Ordering::Greater);
+    assert_eq!(natural_cmp("Disc 2", "Disc 02"), Ordering::Less);
+    assert_eq!(natural_cmp("Mix", "mix"), Ordering::Less);
+    assert_eq!(natural_cmp("abc", "ABD"), Ordering::Less);
+    assert_eq!(natural_cmp("Running", "Running 2"), Ordering::Less);
+
+    let mut names = vec!["Track 10", "track 1", "Track 9", "Intro", "Track 1a"];
+    names.sort_by(|a, b| natural_cmp(a, b));
+    assert_eq!(
+        names,
+        vec!["Intro", "track 1", "Track 1a", "Track 9", "Track 10"]
+    );
+}
+
+fn folder_names(folder: &PlaylistFolder) -> Vec<&str> {
+    folder
+        .folders
+        .iter()
+        .map(|child| child.name.as_str())
+        .collect()
+}
+
+fn playlist_names(folder: &PlaylistFolder) -> Vec<&str> {
+    folder
+        .playlists
+        .iter()
+        .map(|playlist| playlist.name.as_str())
+        .collect()
+}
+
+fn create_in_folder(manager: &PlaylistManager, name: &str, folder: Option<&str>) -> String {
+    let id = manager.create_playlist(name.to_string(), None);
+    let mut playlist = Arc::unwrap_or_clone(manager.get_playlist(&id).unwrap());
+    playlist.folder = folder.map(str::to_string);
+    manager.save_playlist(&playlist).unwrap();
+    manager.update_playlist(playlist);
+    id
+}
+
+#[test]
+fn playlists_are_nested_under_their_folders_in_natural_order() {
+    assert_eq!(
+        normalize_folder(" Workout//Running/ "),
+        Some("Workout/Running".to_string())
+    );
+    assert_eq!(normalize_folder(" / "), None);
+
+    let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+    let manager = PlaylistManager::new(workspace.path().to_path_buf()).unwrap();
+    create_in_folder(&manager, "Loose 10", None);
+    create_in_folder(&manager, "Loose 9", None);
+    create_in_folder(&manager, "Intervals", Some("Workout/Running"));
+    create_in_folder(&manager, "Warmup", Some("Workout"));
+    create_in_folder(&manager, "Long run", Some("Workout/Running"));
+    create_in_folder(&manager, "Sunday", Some("Chill"));
+    create_in_folder(&manager, "Week 10", Some("Archive 10"));
+    create_in_folder(&manager, "Week 2", Some("Archive 2"));
+
+    let tree = manager.playlist_tree();
+    assert_eq!(tree.path, "");
+    assert_eq!(playlist_names(&tree), vec!["Loose 9", "Loose 10"]);
+    assert_eq!(
+        folder_names(&tree),
+        vec!["Archive 2", "Archive 10", "Chill", "Workout"]
+    );
+
+    let workout = &tree.folders[3];
+    assert_eq!(workout.path, "Workout");
+    assert_eq!(playlist_names(workout), vec!["Warmup"]);
+    let running = &workout.folders[0];
+    assert_eq!(running.name, "Running");
+    assert_eq!(running.path, "Workout/Running");
+    assert_eq!(playlist_names(running), vec!["Intervals", "Long run"]);
+    assert!(running.folders.is_empty());
+}
+
+#[test]
+fn renaming_a_folder_moves_every_playlist_below_it() {
+    let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+    let manager = PlaylistManager::new(workspace.path().to_path_buf()).unwrap();
+    let warmup = create_in_folder(&manager, "Warmup", Some("Workout"));
+    let intervals = create_in_folder(&manager, "Intervals", Some("Workout/Running"));
+    let lookalike = create_in_folder(&manager, "Other", Some("Workouts"));
+    let loose = create_in_folder(&manager, "Loose", None);
+
+    assert_eq!(
+        manager.rename_folder("Workout", "Sport/Training").unwrap(),
+        2
+    );
+    let folder_of = |id: &str| manager.get_playlist(id).unwrap().folder.clone();
+    assert_eq!(folder_of(&warmup).as_deref(), Some("Sport/Training"));
+    assert_eq!(
+        folder_of(&intervals).as_deref(),
+        Some("Sport/Training/Running")
+    );
+    assert_eq!(folder_of(&lookalike).as_deref(), Some("Workouts"));
+    assert_eq!(folder_of(&loose), None);
+
+    let reloaded = PlaylistManager::new(workspace.path().to_path_buf()).unwrap();
+    reloaded.load_all_playlists().unwrap();
+    assert_eq!(
+        reloaded.get_playlist(&intervals).unwrap().folder.as_deref(),
+        Some("Sport/Training/Running")
+    );
+
+    assert_eq!(manager.rename_folder("Sport/Training", "").unwrap(), 2);
+    assert_eq!(folder_of(&warmup), None);
+    assert_eq!(folder_of(&intervals).as_deref(), Some("Running"));
+    assert_eq!(manager.rename_folder("Missing", "Elsewhere").unwrap(), 0);
+    assert!(matches!(
+        manager.rename_folder(" / ", "Elsewhere"),
+        Err(PlaylistError::RootFolder)
+    ));
+}
+
+/// A manager with two empty playlists and a track to add to them
+fn manager_with_two_playlists() -> (TempDir, Arc<PlaylistManager>, Track, String, String) {
+    let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+    let song = workspace.path().join("song.wav");
+    write_silent_wav(&song);
+    let track = Track::new(song).expect("track should load");
+    let manager = Arc::new(
+        PlaylistManager::new(workspace.path().join("playlists"))
+            .expect("manager should initialize"),
+    );
+    let first = manager.create_playlist("First".into(), None);
+    let second = manager.create_playlist("Second".into(), None);
+    (workspace, manager, track, first, second)
+}
+
+#[test]
+fn editing_one_playlist_does_not_hold_up_the_others() {
+    let (_workspace, manager, track, first, second) = manager_with_two_playlists();
+    let (started, wait_started) = std::sync::mpsc::channel();
+
+    let slow_edit = {
+        let manager = manager.clone();
+        let first = first.clone();
+        std::thread::spawn(move || {
+            manager.edit_playlist(&first, None, |playlist| {
+                started.send(()).unwrap();
+                std::thread::sleep(Duration::from_millis(600));
+                playlist.name = "Renamed".into();
+                Ok::<_, PlaylistEditError>(())
+            })
+        })
+    };
+    wait_started.recv().unwrap();
+
+    let start = Instant::now();
+    manager.add_track(&second, &track, None).unwrap();
+    assert_eq!(manager.get_playlist(&first).unwrap().name, "First");
+    assert_eq!(manager.playlist_summaries().len(), 2);
+    assert!(
+        start.elapsed() < Duration::from_millis(300),
+        "waited {:?} for the edit of another playlist",
+        start.elapsed()
+    );
+
+    slow_edit.join().unwrap().unwrap();
+    assert_eq!(manager.get_playlist(&first).unwrap().name, "Renamed");
+    assert_eq!(manager.get_playlist(&second).unwrap().track_count(), 1);
+}
+
+#[test]
+fn parallel_adds_to_the_same_playlist_are_all_kept() {
+    let (workspace, manager, track, first, second) = manager_with_two_playlists();
+
+    let threads: Vec<_> = (0..8)
+        .map(|thread| {
+            let manager = manager.clone();
+            let track = track.clone();
+            let id = if thread % 4 == 0 {
+                second.clone()
+            } else {
+                first.clone()
+            };
+            std::thread::spawn(move || {
+                for _ in 0..10 {
+                    manager.add_track(&id, &track, None).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(manager.get_playlist(&first).unwrap().track_count(), 60);
+    assert_eq!(manager.get_playlist(&second).unwrap().track_count(), 20);
+    let reloaded = PlaylistManager::new(workspace.path().join("playlists")).unwrap();
+    reloaded.load_all_playlists().unwrap();
+    assert_eq!(reloaded.get_playlist(&first).unwrap().track_count(), 60);
+}
+
+#[test]
+fn edits_based_on_a_stale_modified_at_are_refused() {
+    let (_workspace, manager, track, first, _) = manager_with_two_playlists();
+    let seen = manager.get_playlist(&first).unwrap().modified_at;
+
+    let updated = manager.add_track(&first, &track, Some(seen)).unwrap();
+    assert!(updated.modified_at > seen);
+
+    match manager.add_track(&first, &track, Some(seen)) {
+        Err(PlaylistEditError::Conflict { modified_at }) => {
+            assert_eq!(modified_at, updated.modified_at)
+        }
+        other => panic!("expected a conflict, got {:?}", other.map(|_| ())),
+    }
+    assert_eq!(manager.get_playlist(&first).unwrap().track_count(), 1);
+
+    // A failed edit leaves the playlist as it was
+    let failed = manager.edit_playlist(&first, Some(updated.modified_at), |playlist| {
+        playlist.clear();
+        Err::<(), _>(PlaylistEditError::NotFound)
+    });
+    assert!(failed.is_err());
+    let current = manager.get_playlist(&first).unwrap();
+    assert_eq!(current.track_count(), 1);
+    assert_eq!(current.modified_at, updated.modified_at);
+
+    assert!(matches!(
+        manager.add_track("missing", &track, None),
+        Err(PlaylistEditError::NotFound)
+    ));
+}
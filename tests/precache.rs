@@ -0,0 +1,99 @@
+use hexendrum::audio::{Precache, PrecacheFetch};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+/// A directory standing in for slow storage, and a cache limited to `max_bytes`
+/// that copies files up to 1 KiB
+fn setup(max_bytes: u64) -> (TempDir, PathBuf, Precache) {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("nas");
+    fs::create_dir_all(&source).unwrap();
+    let precache = Precache::new(temp.path().join("precache"), 1024, max_bytes);
+    (temp, source, precache)
+}
+
+fn write(path: &Path, len: usize) {
+    fs::write(path, vec![7u8; len]).unwrap();
+}
+
+#[test]
+fn fetched_tracks_play_from_their_copy() {
+    let (_temp, source, precache) = setup(1 << 20);
+    let track = source.join("song.flac");
+    write(&track, 600);
+
+    assert_eq!(precache.resolve(&track), track);
+    assert_eq!(precache.fetch(&track).unwrap(), PrecacheFetch::Copied);
+    assert_eq!(precache.fetch(&track).unwrap(), PrecacheFetch::Cached);
+
+    let copy = precache.resolve(&track);
+    assert_ne!(copy, track);
+    assert_eq!(copy.extension().unwrap(), "flac");
+    assert_eq!(fs::read(&copy).unwrap(), fs::read(&track).unwrap());
+
+    let stats = precache.stats();
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+    assert_eq!((stats.files, stats.bytes), (1, 600));
+}
+
+#[test]
+fn changed_sources_never_play_from_a_stale_copy() {
+    let (_temp, source, precache) = setup(1 << 20);
+    let track = source.join("song.mp3");
+    write(&track, 600);
+    precache.fetch(&track).unwrap();
+    let copy = precache.resolve(&track);
+
+    write(&track, 700);
+    assert_eq!(precache.resolve(&track), track);
+    assert!(!copy.exists());
+    assert_eq!(precache.stats().files, 0);
+
+    assert_eq!(precache.fetch(&track).unwrap(), PrecacheFetch::Copied);
+    assert_eq!(fs::read(precache.resolve(&track)).unwrap().len(), 700);
+}
+
+#[test]
+fn large_tracks_are_read_ahead_without_copying() {
+    let (_temp, source, precache) = setup(1 << 20);
+    let track = source.join("live.wav");
+    write(&track, 4096);
+
+    assert_eq!(precache.fetch(&track).unwrap(), PrecacheFetch::ReadAhead);
+    assert_eq!(precache.resolve(&track), track);
+    assert_eq!(precache.stats().files, 0);
+    assert!(precache.fetch(&source.join("missing.wav")).is_err());
+}
+
+#[test]
+fn least_recently_played_copies_are_evicted() {
+    let (_temp, source, precache) = setup(1000);
+    let tracks: Vec<PathBuf> = (0..3)
+        .map(|index| source.join(format!("{}.ogg", index)))
+        .collect();
+    for (age, track) in tracks.iter().enumerate() {
+        write(track, 400);
+        precache.fetch(track).unwrap();
+        // Modification times order the copies by when they were last used
+        let copy = precache.cached(track).unwrap();
+        let used = SystemTime::now() - Duration::from_secs(60 * (3 - age as u64));
+        fs::File::options()
+            .append(true)
+            .open(&copy)
+            .unwrap()
+            .set_modified(used)
+            .unwrap();
+        if age == 1 {
+            // Playing the first track again keeps it over the second
+            precache.resolve(&tracks[0]);
+        }
+    }
+
+    assert!(precache.cached(&tracks[0]).is_some());
+    assert!(precache.cached(&tracks[1]).is_none());
+    assert!(precache.cached(&tracks[2]).is_some());
+    let stats = precache.stats();
+    assert_eq!((stats.files, stats.bytes, stats.max_bytes), (2, 800, 1000));
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use common::TestTrack;
+use hexendrum::library::{similar_tracks, similarity, Track};
+use hexendrum::TrackMetadata;
+use std::collections::HashSet;
+
+fn track(id: &str, genre: Option<&str>, year: Option<i32>, duration: Option<u64>) -> Track {
+    TestTrack::new(id)
+        .metadata(TrackMetadata {
+            title: Some(id.into()),
+            year,
+            genre: genre.map(str::to_string),
+            duration,
+            ..Default::default()
+        })
+        .build()
+}
+
+fn ids(tracks: Vec<&Track>) -> Vec<&str> {
+    tracks.into_iter().map(|track| track.id.as_str()).collect()
+}
+
+#[test]
+fn identical_tracks_score_highest() {
+    let seed = track("seed", Some("Rock"), Some(1975), Some(300));
+    let twin = track("twin", Some(" rock "), Some(1979), Some(300));
+    assert!((similarity(&seed.metadata, &twin.metadata) - 1.0).abs() < 1e-6);
+
+    let stranger = track("stranger", Some("Jazz"), Some(1950), None);
+    assert_eq!(similarity(&seed.metadata, &stranger.metadata), 0.0);
+
+    let untagged = track("untagged", None, None, None);
+    assert_eq!(similarity(&seed.metadata, &untagged.metadata), 0.0);
+}
+
+#[test]
+fn genre_outweighs_decade_which_outweighs_duration() {
+    let seed = track("seed", Some("Rock"), Some(1985), Some(240));
+    let same_genre = track("genre", Some("Rock"), Some(2015), Some(100));
+    let same_decade = track("decade", Some("Pop"), Some(1981), Some(240));
+    let adjacent_decade = track("adjacent", Some("Pop"), Some(1990), Some(240));
+    let same_duration = track("duration", Some("Pop"), Some(2015), Some(240));
+
+    let score = |candidate: &Track| similarity(&seed.metadata, &candidate.metadata);
+    assert!(score(&same_genre) > score(&same_decade));
+    assert!(score(&same_decade) > score(&adjacent_decade));
+    assert!(score(&adjacent_decade) > score(&same_duration));
+    assert!(score(&same_duration) > 0.0);
+}
+
+#[test]
+fn closer_durations_score_higher() {
+    let seed = track("seed", Some("Rock"), None, Some(200));
+    let close = track("close", Some("Rock"), None, Some(210));
+    let far = track("far", Some("Rock"), None, Some(600));
+    assert!(
+        similarity(&seed.metadata, &close.metadata) > similarity(&seed.metadata, &far.metadata)
+    );
+}
+
+#[test]
+fn picks_skip_the_seed_exclusions_and_unrelated_tracks() {
+    let seed = track("seed", Some("Rock"), Some(1975), Some(300));
+    let candidates = vec![
+        seed.clone(),
+        track("b-rock", Some("Rock"), Some(1990), None),
+        track("a-rock", Some("Rock"), Some(1990), None),
+        track("best", Some("Rock"), Some(1976), Some(290)),
+        track("played", Some("Rock"), Some(1975), Some(300)),
+        // Only the duration matches
+        track("lookalike", Some("Jazz"), Some(2020), Some(300)),
+        track("neighbour", Some("Jazz"), Some(1982), None),
+    ];
+    let exclude: HashSet<String> = ["played".to_string()].into_iter().collect();
+
+    assert_eq!(
+        ids(similar_tracks(&seed, &candidates, &exclude, 10)),
+        vec!["best", "a-rock", "b-rock", "neighbour"]
+    );
+    assert_eq!(
+        ids(similar_tracks(&seed, &candidates, &exclude, 2)),
+        vec!["best", "a-rock"]
+    );
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::write_silent_wav;
+use hexendrum::audio::{ReplayGain, ReplayGainMode};
+use hexendrum::config::Paths;
+use hexendrum::library::Library;
+use lofty::config::WriteOptions;
+use lofty::id3::v2::Id3v2Tag;
+use lofty::prelude::{Accessor, TagExt};
+use std::fs;
+use tempfile::TempDir;
+
+fn close(actual: f32, expected: f32) -> bool {
+    (actual - expected).abs() < 0.001
+}
+
+#[test]
+fn gains_are_read_from_the_tags_when_scanning() {
+    let workspace = TempDir::new().unwrap();
+    let music_dir = workspace.path().join("music");
+    fs::create_dir(&music_dir).unwrap();
+    let tagged = music_dir.join("tagged.wav");
+    write_silent_wav(&tagged);
+    let mut tag = Id3v2Tag::new();
+    tag.set_title("Loud".into());
+    tag.insert_user_text("REPLAYGAIN_TRACK_GAIN".into(), "-6.54 dB".into());
+    tag.insert_user_text("REPLAYGAIN_TRACK_PEAK".into(), "0.988312".into());
+    tag.insert_user_text("REPLAYGAIN_ALBUM_GAIN".into(), "+1.20 dB".into());
+    tag.insert_user_text("REPLAYGAIN_ALBUM_PEAK".into(), "not a number".into());
+    tag.save_to_path(&tagged, WriteOptions::default())
+        .expect("failed to tag audio file");
+    let untagged = music_dir.join("untagged.wav");
+    write_silent_wav(&untagged);
+
+    let library = Library::with_paths(&Paths::portable(workspace.path().join("data")));
+    library.scan_directories(&[music_dir]).unwrap();
+    let gain = library
+        .get_track_by_path(&tagged)
+        .unwrap()
+        .metadata
+        .replaygain;
+    assert_eq!(
+        gain,
+        ReplayGain {
+            track_gain_db: Some(-6.54),
+            track_peak: Some(0.988312),
+            album_gain_db: Some(1.2),
+            album_peak: None,
+        }
+    );
+    assert_eq!(ReplayGain::read(&tagged), gain);
+    assert!(library
+        .get_track_by_path(&untagged)
+        .unwrap()
+        .metadata
+        .replaygain
+        .is_empty());
+}
+
+#[test]
+fn multipliers_follow_the_mode_and_stay_below_clipping() {
+    let gain = ReplayGain {
+        track_gain_db: Some(-6.0206),
+        track_peak: Some(0.9),
+        album_gain_db: Some(6.0206),
+        album_peak: Some(0.8),
+    };
+    assert_eq!(gain.multiplier(ReplayGainMode::Off), 1.0);
+    assert!(close(gain.multiplier(ReplayGainMode::Track), 0.5));
+    assert!(
+        close(gain.multiplier(ReplayGainMode::Album), 1.25),
+        "doubling would clip the album's peak"
+    );
+
+    let track_only = ReplayGain {
+        track_gain_db: Some(-6.0206),
+        ..Default::default()
+    };
+    assert!(close(track_only.multiplier(ReplayGainMode::Album), 0.5));
+    assert_eq!(ReplayGain::default().multiplier(ReplayGainMode::Track), 1.0);
+}
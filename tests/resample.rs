@@ -0,0 +1,103 @@
+mod common;
+
+use common::Wav;
+use hexendrum::audio::{probe_source_format, BitDepthLimiter, LinearResampler, SourceFormat};
+use rodio::buffer::SamplesBuffer;
+use rodio::source::SineWave;
+use rodio::Source;
+use std::path::Path;
+use std::time::Duration;
+
+/// Count the frequency of a mono signal from its upward zero crossings.
+fn measured_frequency(samples: &[f32], sample_rate: u32) -> f32 {
+    let crossings = samples
+        .windows(2)
+        .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+        .count();
+    crossings as f32 * sample_rate as f32 / samples.len() as f32
+}
+
+#[test]
+fn resampling_preserves_the_frequency_of_a_sine_wave() {
+    for to_rate in [44_100, 22_050, 96_000] {
+        let sine = SineWave::new(440.0).take_duration(Duration::from_secs(1));
+        assert_eq!(sine.sample_rate(), 48_000);
+
+        let resampler = LinearResampler::new(sine, to_rate);
+        assert_eq!(resampler.sample_rate(), to_rate);
+        let samples: Vec<f32> = resampler.collect();
+
+        let expected_len = to_rate as usize;
+        assert!(
+            samples.len().abs_diff(expected_len) <= 2,
+            "{} samples at {} Hz",
+            samples.len(),
+            to_rate
+        );
+        let frequency = measured_frequency(&samples, to_rate);
+        assert!(
+            (frequency - 440.0).abs() <= 2.0,
+            "{} Hz at {}",
+            frequency,
+            to_rate
+        );
+        assert!(samples.iter().all(|sample| sample.abs() <= 1.0));
+    }
+}
+
+#[test]
+fn resampling_keeps_channels_apart() {
+    let frames = 100;
+    let samples: Vec<f32> = (0..frames).flat_map(|_| [0.5, -0.25]).collect();
+    let resampler = LinearResampler::new(SamplesBuffer::new(2, 1000, samples), 2000);
+    assert_eq!(resampler.channels(), 2);
+
+    let output: Vec<f32> = resampler.collect();
+    assert_eq!(output.len() % 2, 0);
+    assert!(output.len().abs_diff(frames * 4) <= 4, "{}", output.len());
+    for frame in output.chunks(2) {
+        assert!((frame[0] - 0.5).abs() < 1e-6, "{:?}", frame);
+        assert!((frame[1] + 0.25).abs() < 1e-6, "{:?}", frame);
+    }
+}
+
+#[test]
+fn an_empty_source_stays_empty() {
+    let resampler = LinearResampler::new(SamplesBuffer::<f32>::new(1, 48_000, Vec::new()), 44_100);
+    assert_eq!(resampler.count(), 0);
+}
+
+#[test]
+fn bit_depth_is_reduced_to_the_fallback() {
+    let samples = vec![0.0, 0.1, -0.33, 0.9, -1.0];
+    let limited: Vec<f32> = BitDepthLimiter::new(SamplesBuffer::new(1, 8000, samples), 4).collect();
+
+    // Four bits leave steps of 1/8
+    assert_eq!(limited, vec![0.0, 0.125, -0.375, 0.875, -1.0]);
+}
+
+#[test]
+fn source_format_is_read_from_codec_parameters() {
+    let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+    let path = workspace.path().join("tone.wav");
+    write_wav(&path, 88_200, 2, 24);
+
+    assert_eq!(
+        probe_source_format(&path).unwrap(),
+        SourceFormat {
+            sample_rate: Some(88_200),
+            channels: Some(2),
+            bits_per_sample: Some(24),
+        }
+    );
+    assert!(probe_source_format(&workspace.path().join("missing.wav")).is_err());
+}
+
+/// Write a short, silent PCM WAV file with the given format.
+fn write_wav(path: &Path, sample_rate: u32, channels: u16, bits: u16) {
+    Wav::new()
+        .sample_rate(sample_rate)
+        .format(channels, bits)
+        .silent_frames(100)
+        .write(path);
+}
@@ -0,0 +1,315 @@
+mod common;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use common::write_silent_wav;
+use hexendrum::config::Paths;
+use hexendrum::library::{
+    track_identifier, AlbumDisambiguation, AlbumService, Library, ManualAlbumUpdate,
+};
+use hexendrum::playlist::{EntryPath, PlayOrder, PlaylistManager, RepeatMode};
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+const TRACK_ID: &str = "3f0c3b9e-8d5a-4a52-9c34-1f2c9d1e7a01";
+const PLAYLIST_ID: &str = "8c1d4f62-2b7e-4e0a-9f3d-5a6b7c8d9e01";
+const ALBUM_ID: &str = "album-northern-static";
+
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/schema")
+        .join(name);
+    fs::read_to_string(&path).unwrap_or_else(|e| panic!("cannot read {:?}: {}", path, e))
+}
+
+fn read_json(path: &Path) -> Value {
+    serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+}
+
+/// A workspace with the track the cache fixtures describe, and the fixture `name`
+/// installed as its library cache
+fn cache_workspace(name: &str) -> (TempDir, Paths, PathBuf) {
+    let workspace = TempDir::new().unwrap();
+    let music_dir = workspace.path().join("music");
+    fs::create_dir(&music_dir).unwrap();
+    let track_path = music_dir.join("harbor.wav");
+    write_silent_wav(&track_path);
+    let mtime: DateTime<Utc> = fs::metadata(&track_path)
+        .unwrap()
+        .modified()
+        .unwrap()
+        .into();
+
+    let paths = Paths::portable(workspace.path().join("data"));
+    fs::create_dir_all(&paths.cache_dir).unwrap();
+    let cache = fixture(name)
+        .replace("$MUSIC_DIR", &music_dir.to_string_lossy())
+        .replace(
+            "$FILE_MTIME",
+            &mtime.to_rfc3339_opts(SecondsFormat::Nanos, true),
+        );
+    fs::write(paths.library_cache_file(), cache).unwrap();
+    (workspace, paths, track_path)
+}
+
+#[test]
+fn every_library_cache_version_loads_and_is_saved_as_the_newest() {
+    for (version, name) in [
+        (0, "library_cache_v0.json"),
+        (1, "library_cache_v1.json"),
+        (2, "library_cache_v2.json"),
+        (3, "library_cache_v3.json"),
+    ] {
+        let (_workspace, paths, track_path) = cache_workspace(name);
+        let library = Library::with_paths(&paths);
+        let track = library
+            .get_track_by_path(&track_path)
+            .unwrap_or_else(|| panic!("the track of the version {} cache is kept", version));
+
+        // Ids used to be random, and are replaced by ones derived from paths
+        let expected_id = track_identifier(&track_path);
+        assert_eq!(track.id, expected_id);
+        assert_eq!(
+            library.renamed_track_id(TRACK_ID).as_deref(),
+            Some(expected_id.as_str())
+        );
+
+        let metadata = &track.metadata;
+        assert_eq!(metadata.file_path, track_path);
+        assert_eq!(metadata.title.as_deref(), Some("Harbor Lights"));
+        assert_eq!(metadata.artist.as_deref(), Some("The Quiet Engines"));
+        assert_eq!(metadata.album.as_deref(), Some("Northern Static"));
+        assert_eq!(metadata.track_number, Some(3));
+        assert_eq!(metadata.year, Some(2009));
+        assert_eq!(metadata.genre.as_deref(), Some("Post-Rock"));
+        assert_eq!(metadata.duration, Some(412));
+        assert_eq!(metadata.file_size, 1644);
+
+        let technical = metadata.technical.as_ref().expect("technical details");
+        let musicbrainz = &metadata.musicbrainz;
+        if version == 0 {
+            // Read from the file, which is a plain WAV without tags
+            assert_eq!(technical.sample_rate, Some(8000));
+            assert_eq!(metadata.track_total, None);
+        } else {
+            assert_eq!(technical.codec.as_deref(), Some("flac"));
+            assert_eq!(technical.sample_rate, Some(96000));
+            assert_eq!(metadata.album_artist.as_deref(), Some("The Quiet Engines"));
+            assert_eq!(metadata.track_total, Some(9));
+        }
+        if version >= 2 {
+            assert_eq!(
+                musicbrainz.track_id.as_deref(),
+                Some("b1a9c0e9-d987-4042-ae91-78d6a3267d69")
+            );
+            assert_eq!(
+                musicbrainz.release_id.as_deref(),
+                Some("1dc4c347-a1db-32aa-b14f-bc9cc507b843")
+            );
+        } else {
+            assert!(musicbrainz.is_empty(), "read from the untagged file");
+        }
+        if version >= 3 {
+            assert_eq!(metadata.replaygain.track_gain_db, Some(-6.54));
+            assert_eq!(metadata.replaygain.track_peak, Some(0.988312));
+            assert_eq!(metadata.replaygain.album_gain_db, Some(-7.2));
+        } else {
+            assert!(
+                metadata.replaygain.is_empty(),
+                "read from the untagged file"
+            );
+        }
+
+        library.save_to_cache().unwrap();
+        let saved = read_json(&paths.library_cache_file());
+        assert_eq!(saved["version"], 3);
+        assert_eq!(saved["tracks"][0]["track"]["id"], expected_id.as_str());
+        assert_eq!(saved["previous_ids"][TRACK_ID], expected_id.as_str());
+    }
+}
+
+#[test]
+fn every_playlist_version_loads_and_is_saved_as_the_newest() {
+    for (version, name) in [(0, "playlist_v0.json"), (1, "playlist_v1.json")] {
+        let workspace = TempDir::new().unwrap();
+        let playlist_dir = workspace.path().join("playlists");
+        fs::create_dir(&playlist_dir).unwrap();
+        let file = playlist_dir.join(format!("{}.json", PLAYLIST_ID));
+        fs::write(&file, fixture(name)).unwrap();
+
+        let manager = PlaylistManager::new(playlist_dir).unwrap();
+        manager.load_all_playlists().unwrap();
+        let playlist = manager
+            .get_playlist(PLAYLIST_ID)
+            .unwrap_or_else(|| panic!("the version {} playlist is loaded", version));
+        assert_eq!(playlist.name, "Late Drive");
+        assert_eq!(
+            playlist.description.as_deref(),
+            Some("Slow songs for the way home")
+        );
+        assert_eq!(
+            playlist.created_at,
+            "2023-04-02T21:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert_eq!(playlist.entries.len(), 2);
+        let first = &playlist.entries[0];
+        assert_eq!(first.track_id, TRACK_ID);
+        assert_eq!(first.play_count, 4);
+        assert!(first.last_played.is_some());
+        assert!(playlist.entries[1].last_played.is_none());
+
+        if version == 0 {
+            assert_eq!(
+                playlist.modified_at,
+                "2023-04-03T07:30:00Z".parse::<DateTime<Utc>>().unwrap()
+            );
+            assert_eq!(playlist.play_order, PlayOrder::Stored);
+            assert_eq!(playlist.default_repeat, None);
+            assert_eq!(playlist.folder, None);
+            assert!(playlist.entries.iter().all(|entry| entry.path.is_none()));
+        } else {
+            assert_eq!(playlist.play_order, PlayOrder::Shuffle);
+            assert_eq!(playlist.default_repeat, Some(RepeatMode::All));
+            assert_eq!(playlist.folder.as_deref(), Some("Driving/Night"));
+            assert_eq!(
+                first.note.as_deref(),
+                Some("Fade in from the previous track")
+            );
+            assert!(first.pinned);
+            assert_eq!(
+                first.path,
+                Some(EntryPath::Local(
+                    "/music/The Quiet Engines/Northern Static/03 Harbor Lights.flac".into()
+                ))
+            );
+            assert!(matches!(
+                &playlist.entries[1].path,
+                Some(EntryPath::Portable(location)) if location.root == "Shared"
+            ));
+        }
+
+        manager.save_playlist(&playlist).unwrap();
+        let saved = read_json(&file);
+        assert_eq!(saved["version"], 1);
+        assert_eq!(saved["name"], "Late Drive");
+        assert_eq!(saved["entries"][0]["play_count"], 4);
+    }
+}
+
+#[tokio::test]
+async fn every_album_override_store_version_loads_and_is_saved_as_the_newest() {
+    for (version, name) in [
+        (0, "album_overrides_v0.json"),
+        (1, "album_overrides_v1.json"),
+    ] {
+        let workspace = TempDir::new().unwrap();
+        let paths = Paths::portable(workspace.path().join("data"));
+        let file = paths.album_overrides_file();
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, fixture(name)).unwrap();
+
+        let service = AlbumService::with_paths(&paths, None);
+        let record = service
+            .get_override(ALBUM_ID)
+            .unwrap_or_else(|| panic!("the version {} override is loaded", version));
+        assert_eq!(
+            record.title.as_deref(),
+            Some("Northern Static (Remastered)")
+        );
+        assert_eq!(record.primary_artist.as_deref(), Some("The Quiet Engines"));
+        assert_eq!(record.search_album.as_deref(), Some("Northern Static"));
+        assert_eq!(
+            record.artwork_path.as_deref(),
+            Some("/covers/northern-static.jpg")
+        );
+        let metadata = record.metadata.expect("album metadata");
+        assert_eq!(metadata.tags, vec!["post-rock", "instrumental"]);
+        assert_eq!(metadata.source.as_deref(), Some("lastfm"));
+        let expected = if version == 0 {
+            AlbumDisambiguation::Auto
+        } else {
+            AlbumDisambiguation::Split
+        };
+        assert_eq!(record.disambiguation, expected);
+
+        service
+            .set_manual_override(
+                "another-album",
+                ManualAlbumUpdate {
+                    title: None,
+                    primary_artist: None,
+                    search_album: None,
+                    search_artist: None,
+                    refresh_artwork: false,
+                    disambiguation: Some(AlbumDisambiguation::Merge),
+                    musicbrainz_release_id: None,
+                },
+            )
+            .await
+            .unwrap();
+        let saved = read_json(&file);
+        assert_eq!(saved["version"], 1);
+        assert_eq!(saved["records"][0]["album_id"], ALBUM_ID);
+        assert_eq!(
+            saved["records"][0]["artwork_path"],
+            "/covers/northern-static.jpg"
+        );
+    }
+}
+
+#[tokio::test]
+async fn files_from_newer_versions_are_refused_and_left_alone() {
+    let (_workspace, paths, _) = cache_workspace("library_cache_v3.json");
+    let cache_file = paths.library_cache_file();
+    let mut cache = read_json(&cache_file);
+    cache["version"] = 99.into();
+    let future_cache = cache.to_string();
+    fs::write(&cache_file, &future_cache).unwrap();
+
+    let library = Library::with_paths(&paths);
+    assert_eq!(library.track_count(), 0);
+    let error = library.load_from_cache().unwrap_err().to_string();
+    assert!(error.contains("format version 99"), "{}", error);
+    assert!(error.contains("reads up to version 3"), "{}", error);
+    assert!(library.save_to_cache().is_err());
+    assert_eq!(fs::read_to_string(&cache_file).unwrap(), future_cache);
+
+    let playlist_dir = paths.playlist_dir();
+    fs::create_dir_all(&playlist_dir).unwrap();
+    let mut playlist: Value = serde_json::from_str(&fixture("playlist_v1.json")).unwrap();
+    playlist["version"] = 7.into();
+    fs::write(
+        playlist_dir.join(format!("{}.json", PLAYLIST_ID)),
+        playlist.to_string(),
+    )
+    .unwrap();
+    let manager = PlaylistManager::new(playlist_dir.clone()).unwrap();
+    manager.load_all_playlists().unwrap();
+    assert!(manager.get_playlist(PLAYLIST_ID).is_none());
+    let error = manager
+        .load_playlist(&playlist_dir.join(format!("{}.json", PLAYLIST_ID)))
+        .unwrap_err()
+        .to_string();
+    assert!(error.contains("playlist"), "{}", error);
+    assert!(error.contains("format version 7"), "{}", error);
+
+    let overrides_file = paths.album_overrides_file();
+    let future_overrides = r#"{"version": 2, "albums": {}}"#;
+    fs::write(&overrides_file, future_overrides).unwrap();
+    let service = AlbumService::with_paths(&paths, None);
+    let update = ManualAlbumUpdate {
+        title: Some("Renamed".into()),
+        primary_artist: None,
+        search_album: None,
+        search_artist: None,
+        refresh_artwork: false,
+        disambiguation: None,
+        musicbrainz_release_id: None,
+    };
+    assert!(service.set_manual_override(ALBUM_ID, update).await.is_err());
+    assert_eq!(
+        fs::read_to_string(&overrides_file).unwrap(),
+        future_overrides
+    );
+}
@@ -0,0 +1,84 @@
+use chrono::{DateTime, Duration, Utc};
+use hexendrum::api::{ShareClaims, ShareError, ShareScope, ShareSigner};
+use std::fs;
+use tempfile::TempDir;
+
+fn claims(expires_at: DateTime<Utc>) -> ShareClaims {
+    ShareClaims {
+        scope: ShareScope::Album {
+            id: "1f3870be274f6c49b3e31a0c6728957f".into(),
+        },
+        expires_at: DateTime::from_timestamp(expires_at.timestamp(), 0).unwrap(),
+    }
+}
+
+#[test]
+fn links_carry_their_scope_until_they_expire() {
+    let workspace = TempDir::new().unwrap();
+    let signer = ShareSigner::load(workspace.path().join("share_secret"), false).unwrap();
+    let now = Utc::now();
+    let shared = claims(now + Duration::hours(1));
+    let token = signer.sign(&shared);
+
+    assert_eq!(signer.verify(&token, now), Ok(shared.clone()));
+    assert_eq!(
+        signer.verify(&token, now + Duration::hours(2)),
+        Err(ShareError::Expired {
+            expires_at: shared.expires_at
+        })
+    );
+}
+
+#[test]
+fn altered_links_are_refused() {
+    let workspace = TempDir::new().unwrap();
+    let signer = ShareSigner::load(workspace.path().join("share_secret"), false).unwrap();
+    let now = Utc::now();
+    let token = signer.sign(&claims(now + Duration::hours(1)));
+    let (payload, mac) = token.split_once('.').unwrap();
+
+    // Another scope with the original signature
+    let other = signer.sign(&ShareClaims {
+        scope: ShareScope::NowPlaying,
+        ..claims(now + Duration::hours(1))
+    });
+    let other_payload = other.split_once('.').unwrap().0;
+    for altered in [
+        format!("{}.{}", other_payload, mac),
+        format!("{}.{}", payload, &mac[1..]),
+        payload.to_string(),
+        String::new(),
+        "not a token".to_string(),
+    ] {
+        assert_eq!(
+            signer.verify(&altered, now),
+            Err(ShareError::Invalid),
+            "{}",
+            altered
+        );
+    }
+}
+
+#[test]
+fn the_secret_persists_until_it_is_rotated() {
+    let workspace = TempDir::new().unwrap();
+    let path = workspace.path().join("config/share_secret");
+    let signer = ShareSigner::load(path.clone(), false).unwrap();
+    let now = Utc::now();
+    let token = signer.sign(&claims(now + Duration::hours(1)));
+
+    let reloaded = ShareSigner::load(path.clone(), true).unwrap();
+    assert!(reloaded.allow_stream());
+    assert!(reloaded.verify(&token, now).is_ok());
+
+    signer.rotate().unwrap();
+    assert_eq!(signer.verify(&token, now), Err(ShareError::Invalid));
+    let rotated = ShareSigner::load(path.clone(), false).unwrap();
+    assert_eq!(rotated.verify(&token, now), Err(ShareError::Invalid));
+    assert!(rotated
+        .verify(&signer.sign(&claims(now + Duration::hours(1))), now)
+        .is_ok());
+
+    fs::write(&path, "not hex").unwrap();
+    assert!(ShareSigner::load(path, false).is_err());
+}
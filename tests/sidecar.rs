@@ -0,0 +1,155 @@
+use hexendrum::config::Paths;
+use hexendrum::library::{
+    read_sidecar, sidecar_path, update_sidecar, Library, MetadataSource, SidecarMetadata,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+struct SidecarTestEnv {
+    _workspace: TempDir,
+    music_dir: PathBuf,
+    paths: Paths,
+}
+
+impl SidecarTestEnv {
+    fn new() -> Self {
+        let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+        let music_dir = workspace.path().join("music");
+        fs::create_dir(&music_dir).expect("failed to create music dir");
+
+        Self {
+            paths: Paths::portable(workspace.path().join("data")),
+            _workspace: workspace,
+            music_dir,
+        }
+    }
+
+    fn library(&self) -> Library {
+        Library::with_paths(&self.paths)
+    }
+
+    fn scan(&self, library: &Library) -> hexendrum::library::ScanReport {
+        library
+            .scan_directories(std::slice::from_ref(&self.music_dir))
+            .expect("scan should succeed")
+    }
+
+    fn create_audio_file(&self, name: &str) -> PathBuf {
+        let path = self.music_dir.join(name);
+        fs::write(&path, b"fake audio data").expect("failed to write audio file");
+        path
+    }
+
+    fn write_sidecar(&self, audio_path: &Path, content: &str) {
+        fs::write(sidecar_path(audio_path), content).expect("failed to write sidecar");
+    }
+}
+
+#[test]
+fn sidecar_path_appends_suffix_to_the_file_name() {
+    assert_eq!(
+        sidecar_path(Path::new("/music/song.flac")),
+        PathBuf::from("/music/song.flac.hexendrum.json")
+    );
+}
+
+#[test]
+fn sidecar_fields_take_precedence_over_tags() {
+    let env = SidecarTestEnv::new();
+    let tagged = env.create_audio_file("tagged.mp3");
+    let plain = env.create_audio_file("plain.mp3");
+    env.write_sidecar(
+        &tagged,
+        r#"{"title": "Sidecar Title", "artist": "Sidecar Artist", "year": 1999, "track_number": 4}"#,
+    );
+
+    let library = env.library();
+    let report = env.scan(&library);
+    assert_eq!(report.tracks, 2);
+    assert!(report.sidecar_errors.is_empty());
+
+    let track = library.get_track_by_path(&tagged).unwrap();
+    assert_eq!(track.metadata.title.as_deref(), Some("Sidecar Title"));
+    assert_eq!(track.metadata.artist.as_deref(), Some("Sidecar Artist"));
+    assert_eq!(track.metadata.year, Some(1999));
+    assert_eq!(track.metadata.track_number, Some(4));
+    assert_eq!(track.metadata.album, None);
+    assert_eq!(track.metadata.metadata_source, MetadataSource::Sidecar);
+
+    let track = library.get_track_by_path(&plain).unwrap();
+    assert_eq!(track.metadata.metadata_source, MetadataSource::File);
+}
+
+#[test]
+fn invalid_sidecars_are_reported_without_failing_the_scan() {
+    let env = SidecarTestEnv::new();
+    let typo = env.create_audio_file("typo.mp3");
+    let broken = env.create_audio_file("broken.mp3");
+    env.write_sidecar(&typo, r#"{"titel": "Oops"}"#);
+    env.write_sidecar(&broken, "{ not json");
+
+    let library = env.library();
+    let report = env.scan(&library);
+    assert_eq!(report.tracks, 2);
+    assert_eq!(library.last_scan_report(), Some(report.clone()));
+
+    let mut paths: Vec<PathBuf> = report
+        .sidecar_errors
+        .iter()
+        .map(|error| error.path.clone())
+        .collect();
+    paths.sort();
+    assert_eq!(paths, vec![sidecar_path(&broken), sidecar_path(&typo)]);
+    assert!(report
+        .sidecar_errors
+        .iter()
+        .any(|error| error.error.contains("titel")));
+
+    let track = library.get_track_by_path(&typo).unwrap();
+    assert_eq!(track.metadata.metadata_source, MetadataSource::File);
+}
+
+#[test]
+fn updates_merge_into_an_existing_sidecar() {
+    let env = SidecarTestEnv::new();
+    let path = env.create_audio_file("song.mp3");
+    env.write_sidecar(&path, r#"{"title": "Title", "genre": "Jazz"}"#);
+
+    let written = update_sidecar(
+        &path,
+        SidecarMetadata {
+            genre: Some("Blues".into()),
+            year: Some(1960),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert_eq!(written.title.as_deref(), Some("Title"));
+    assert_eq!(written.genre.as_deref(), Some("Blues"));
+    assert_eq!(read_sidecar(&path).unwrap(), Some(written));
+
+    assert!(update_sidecar(&env.music_dir.join("missing.mp3"), Default::default()).is_err());
+}
+
+#[test]
+fn cached_tracks_are_invalidated_when_their_sidecar_changes() {
+    let env = SidecarTestEnv::new();
+    let path = env.create_audio_file("song.mp3");
+
+    let library = env.library();
+    env.scan(&library);
+    assert_eq!(library.track_count(), 1);
+
+    // Reloading an unchanged library keeps the cached track
+    assert_eq!(env.library().track_count(), 1);
+
+    env.write_sidecar(&path, r#"{"title": "New Title"}"#);
+    assert_eq!(env.library().track_count(), 0);
+
+    let library = env.library();
+    env.scan(&library);
+    let track = library.get_track_by_path(&path).unwrap();
+    assert_eq!(track.metadata.title.as_deref(), Some("New Title"));
+    assert_eq!(env.library().track_count(), 1);
+}
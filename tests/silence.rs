@@ -0,0 +1,136 @@
+use hexendrum::audio::{rms, SilenceDetector, SilenceSkip, SilenceSkipped, SilenceSkipper};
+use rodio::buffer::SamplesBuffer;
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const RATE: u32 = 1000;
+
+fn settings() -> SilenceSkip {
+    SilenceSkip {
+        threshold_db: -60.0,
+        min_silence: Duration::from_secs(5),
+    }
+}
+
+/// `seconds` of a 50 Hz sine wave at `level_db` dBFS RMS
+fn tone(seconds: f32, level_db: f32) -> Vec<f32> {
+    let amplitude = 10f32.powf(level_db / 20.0) * 2f32.sqrt();
+    (0..(seconds * RATE as f32) as usize)
+        .map(|index| amplitude * (2.0 * PI * 50.0 * index as f32 / RATE as f32).sin())
+        .collect()
+}
+
+fn silence(seconds: f32) -> Vec<f32> {
+    vec![0.0; (seconds * RATE as f32) as usize]
+}
+
+/// Run `samples` through a skipper, returning the samples played and the skips
+/// reported.
+fn skip(samples: Vec<f32>, start_at: Duration) -> (Vec<f32>, Vec<SilenceSkipped>) {
+    let skips = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&skips);
+    let skipper = SilenceSkipper::new(
+        SamplesBuffer::new(1, RATE, samples),
+        settings(),
+        start_at,
+        Some(Arc::new(move |skipped| {
+            recorded.lock().unwrap().push(skipped)
+        })),
+    );
+    let played: Vec<f32> = skipper.collect();
+    let skips = skips.lock().unwrap().clone();
+    (played, skips)
+}
+
+fn seconds(samples: &[f32]) -> f32 {
+    samples.len() as f32 / RATE as f32
+}
+
+#[test]
+fn rms_measures_the_level_of_a_window() {
+    assert_eq!(rms(&[]), 0.0);
+    assert_eq!(rms(&[0.5, -0.5, 0.5, -0.5]), 0.5);
+    let level = 20.0 * rms(&tone(1.0, -20.0)).log10();
+    assert!((level + 20.0).abs() < 0.1, "{} dB", level);
+}
+
+#[test]
+fn the_detector_skips_only_after_the_minimum_duration() {
+    let mut detector = SilenceDetector::new(settings());
+    let window = Duration::from_secs(1);
+    let quiet = tone(1.0, -70.0);
+    let loud = tone(1.0, -20.0);
+
+    for second in 0..5 {
+        assert!(!detector.skip(&quiet, window), "second {}", second);
+    }
+    assert!(detector.skip(&quiet, window));
+    assert!(detector.skip(&quiet, window));
+    assert!(!detector.skip(&loud, window));
+    assert!(
+        !detector.skip(&quiet, window),
+        "a loud window restarts the count"
+    );
+}
+
+#[test]
+fn long_gaps_are_skipped_after_the_minimum_duration() {
+    let mut samples = tone(3.0, -20.0);
+    samples.extend(silence(25.0));
+    samples.extend(tone(2.0, -20.0));
+
+    let (played, skips) = skip(samples, Duration::ZERO);
+    assert!(
+        (seconds(&played) - 10.0).abs() <= 0.05,
+        "{} seconds played",
+        seconds(&played)
+    );
+    assert_eq!(skips.len(), 1);
+    assert!(
+        (skips[0].from.as_secs_f32() - 8.0).abs() <= 0.05,
+        "{:?}",
+        skips
+    );
+    assert!(
+        (skips[0].to.as_secs_f32() - 28.0).abs() <= 0.05,
+        "{:?}",
+        skips
+    );
+}
+
+#[test]
+fn quiet_passages_shorter_than_the_minimum_play_in_full() {
+    let mut samples = tone(2.0, -20.0);
+    // A pianissimo passage, then a general pause just under the minimum
+    samples.extend(tone(4.9, -65.0));
+    samples.extend(tone(1.0, -45.0));
+    samples.extend(silence(4.9));
+    samples.extend(tone(2.0, -20.0));
+    let total = samples.len();
+
+    let (played, skips) = skip(samples.clone(), Duration::ZERO);
+    assert!(skips.is_empty(), "{:?}", skips);
+    assert_eq!(played.len(), total);
+    assert_eq!(played, samples);
+}
+
+#[test]
+fn trailing_silence_is_skipped_to_the_end() {
+    let mut samples = tone(2.0, -20.0);
+    samples.extend(silence(30.0));
+
+    let (played, skips) = skip(samples, Duration::from_secs(60));
+    assert!((seconds(&played) - 7.0).abs() <= 0.05);
+    assert_eq!(skips.len(), 1);
+    assert!(
+        (skips[0].from.as_secs_f32() - 67.0).abs() <= 0.05,
+        "positions count from the start offset: {:?}",
+        skips
+    );
+    assert!(
+        (skips[0].to.as_secs_f32() - 92.0).abs() <= 0.05,
+        "{:?}",
+        skips
+    );
+}
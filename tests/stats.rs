@@ -0,0 +1,234 @@
+use hexendrum::library::StatsStore;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use tempfile::TempDir;
+
+fn log_path(stats_path: &Path) -> PathBuf {
+    stats_path.with_extension("jsonl")
+}
+
+#[test]
+fn changes_are_appended_to_the_log_and_replayed_on_load() {
+    let workspace = TempDir::new().unwrap();
+    let stats_path = workspace.path().join("stats.json");
+    let song = workspace.path().join("song.flac");
+    let book = workspace.path().join("book.m4b");
+
+    let stats = StatsStore::with_path(stats_path.clone());
+    assert_eq!(stats.record_play(&song), 1);
+    assert_eq!(stats.record_play(&song), 2);
+    assert!(stats.record_resume_position(&book, Some(754)));
+    stats.save().unwrap();
+
+    // Nothing is rewritten until the log is folded into the snapshot
+    assert!(!stats_path.exists());
+    let log = fs::read_to_string(log_path(&stats_path)).unwrap();
+    assert_eq!(log.lines().count(), 4);
+    assert_eq!(log.lines().next(), Some(r#"{"stats_log_version":1}"#));
+
+    let reloaded = StatsStore::with_path(stats_path.clone());
+    assert_eq!(reloaded.play_count(&song), 2);
+    assert!(reloaded.get(&song).unwrap().last_played.is_some());
+    assert_eq!(reloaded.resume_position(&book), Some(754));
+
+    // Appending after a reload keeps the single header
+    reloaded.record_resume_position(&book, None);
+    reloaded.save().unwrap();
+    let log = fs::read_to_string(log_path(&stats_path)).unwrap();
+    assert_eq!(log.matches("stats_log_version").count(), 1);
+    assert_eq!(StatsStore::with_path(stats_path).get(&book), None);
+}
+
+#[test]
+fn duplicate_stats_are_merged_into_the_keeper_and_replayed_on_load() {
+    let workspace = TempDir::new().unwrap();
+    let stats_path = workspace.path().join("stats.json");
+    let keeper = workspace.path().join("song.flac");
+    let copy = workspace.path().join("song.mp3");
+
+    let stats = StatsStore::with_path(stats_path.clone());
+    stats.merge_tag_stats(&keeper, Some(3), None);
+    stats.merge_tag_stats(&copy, Some(5), None);
+    stats.record_play(&keeper);
+    stats.record_play(&copy);
+    stats.record_play(&copy);
+    let last_played = stats.get(&copy).unwrap().last_played;
+
+    stats.merge_duplicate(&copy, &keeper);
+    stats.save().unwrap();
+
+    let reloaded = StatsStore::with_path(stats_path);
+    let merged = reloaded.get(&keeper).unwrap();
+    assert_eq!(merged.play_count, 3);
+    assert_eq!(merged.rating, Some(5));
+    assert_eq!(merged.last_played, last_played);
+}
+
+#[test]
+fn ratings_and_favorites_are_set_and_replayed_on_load() {
+    let workspace = TempDir::new().unwrap();
+    let stats_path = workspace.path().join("stats.json");
+    let song = workspace.path().join("song.flac");
+    let copy = workspace.path().join("song.mp3");
+
+    let stats = StatsStore::with_path(stats_path.clone());
+    assert!(stats.set_rating(&song, Some(4)));
+    assert!(!stats.set_rating(&song, Some(4)));
+    assert!(stats.set_favorite(&copy, true));
+    assert!(!stats.set_favorite(&copy, true));
+    // A rating set by hand is not replaced by the one in the tags
+    assert_eq!(stats.merge_tag_stats(&song, Some(2), None), (false, false));
+    stats.save().unwrap();
+
+    let reloaded = StatsStore::with_path(stats_path.clone());
+    assert_eq!(reloaded.rating(&song), Some(4));
+    assert!(!reloaded.get(&song).unwrap().favorite);
+    assert!(reloaded.get(&copy).unwrap().favorite);
+
+    reloaded.merge_duplicate(&copy, &song);
+    assert!(reloaded.set_favorite(&copy, false));
+    assert!(reloaded.set_rating(&song, None));
+    reloaded.save().unwrap();
+    let reloaded = StatsStore::with_path(stats_path);
+    assert_eq!(reloaded.get(&copy), None, "nothing left to keep");
+    let merged = reloaded.get(&song).unwrap();
+    assert!(merged.favorite);
+    assert_eq!(merged.rating, None);
+}
+
+#[test]
+fn a_partly_written_last_line_is_dropped() {
+    let workspace = TempDir::new().unwrap();
+    let stats_path = workspace.path().join("stats.json");
+    let song = workspace.path().join("song.flac");
+
+    let stats = StatsStore::with_path(stats_path.clone());
+    stats.record_play(&song);
+    stats.save().unwrap();
+    let log = log_path(&stats_path);
+    let complete = fs::read_to_string(&log).unwrap();
+    // A crash in the middle of appending the second play
+    fs::write(&log, format!("{}{{\"event\":\"played\",\"pa", complete)).unwrap();
+
+    let reloaded = StatsStore::with_path(stats_path.clone());
+    assert_eq!(reloaded.play_count(&song), 1);
+    assert_eq!(fs::read_to_string(&log).unwrap(), complete);
+
+    reloaded.record_play(&song);
+    reloaded.save().unwrap();
+    assert_eq!(StatsStore::with_path(stats_path).play_count(&song), 2);
+}
+
+#[test]
+fn compaction_folds_the_log_into_the_snapshot() {
+    let workspace = TempDir::new().unwrap();
+    let stats_path = workspace.path().join("stats.json");
+    let kept = workspace.path().join("kept.flac");
+    let deleted = workspace.path().join("deleted.flac");
+    fs::write(&kept, b"audio").unwrap();
+
+    let stats = StatsStore::with_path(stats_path.clone());
+    stats.record_play(&kept);
+    stats.record_play(&deleted);
+    stats.save().unwrap();
+
+    assert_eq!(stats.compact().unwrap(), vec![deleted.clone()]);
+    assert!(!log_path(&stats_path).exists());
+    let snapshot: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&stats_path).unwrap()).unwrap();
+    assert_eq!(snapshot["version"], 1);
+
+    let reloaded = StatsStore::with_path(stats_path);
+    assert_eq!(reloaded.play_count(&kept), 1);
+    assert_eq!(reloaded.get(&deleted), None);
+}
+
+#[test]
+fn saving_folds_a_log_past_its_limit_into_the_snapshot() {
+    let workspace = TempDir::new().unwrap();
+    let stats_path = workspace.path().join("stats.json");
+    let song = workspace.path().join("song.flac");
+
+    let stats = StatsStore::with_path(stats_path.clone()).with_log_limit(1024);
+    stats.record_play(&song);
+    stats.save().unwrap();
+    assert!(!stats_path.exists());
+
+    for _ in 0..20 {
+        stats.record_play(&song);
+        stats.save().unwrap();
+    }
+    assert!(stats_path.exists());
+    assert!(fs::metadata(log_path(&stats_path)).map_or(0, |meta| meta.len()) <= 1024);
+    assert_eq!(StatsStore::with_path(stats_path).play_count(&song), 21);
+}
+
+#[test]
+fn stats_saved_before_the_log_existed_still_load() {
+    let workspace = TempDir::new().unwrap();
+    let stats_path = workspace.path().join("stats.json");
+    let book = workspace.path().join("book.m4b");
+    let legacy = format!(
+        "{{{:?}: {{\"resume_position\": 120}}}}",
+        book.to_string_lossy()
+    );
+    fs::write(&stats_path, legacy).unwrap();
+
+    let stats = StatsStore::with_path(stats_path.clone());
+    assert_eq!(stats.resume_position(&book), Some(120));
+    stats.compact().unwrap();
+    let snapshot: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(&stats_path).unwrap()).unwrap();
+    assert_eq!(snapshot["version"], 1);
+}
+
+#[test]
+fn a_log_in_an_unknown_format_is_moved_aside() {
+    let workspace = TempDir::new().unwrap();
+    let stats_path = workspace.path().join("stats.json");
+    let log = log_path(&stats_path);
+    fs::write(
+        &log,
+        "{\"stats_log_version\":99}\n{\"event\":\"teleported\"}\n",
+    )
+    .unwrap();
+
+    let stats = StatsStore::with_path(stats_path.clone());
+    assert!(!log.exists());
+    assert!(stats_path.with_extension("jsonl.unsupported").exists());
+
+    let song = workspace.path().join("song.flac");
+    stats.record_play(&song);
+    stats.save().unwrap();
+    assert_eq!(StatsStore::with_path(stats_path).play_count(&song), 1);
+}
+
+#[test]
+fn a_large_log_loads_quickly() {
+    let workspace = TempDir::new().unwrap();
+    let stats_path = workspace.path().join("stats.json");
+    let stats = StatsStore::with_path(stats_path.clone()).with_log_limit(u64::MAX);
+    for index in 0..100_000 {
+        let path = workspace.path().join(format!("{}.flac", index % 5_000));
+        stats.record_play(&path);
+    }
+    stats.save().unwrap();
+    assert_eq!(
+        fs::read_to_string(log_path(&stats_path))
+            .unwrap()
+            .lines()
+            .count(),
+        100_001
+    );
+
+    let started = Instant::now();
+    let reloaded = StatsStore::with_path(stats_path);
+    let elapsed = started.elapsed();
+    assert_eq!(reloaded.play_count(&workspace.path().join("42.flac")), 20);
+    assert!(
+        elapsed.as_secs() < 5,
+        "loading 100k events took {:?}",
+        elapsed
+    );
+}
@@ -0,0 +1,189 @@
+mod common;
+
+use common::TestTrack;
+use hexendrum::config::Paths;
+use hexendrum::library::{
+    fold_words, Library, SuggestionGroup, SuggestionIndex, SuggestionType, Track,
+};
+use hexendrum::TrackMetadata;
+use std::time::{Duration, Instant};
+
+fn track(id: usize, title: &str, artist: &str, album: &str) -> Track {
+    TestTrack::new(id.to_string())
+        .metadata(TrackMetadata {
+            title: Some(title.into()),
+            artist: Some(artist.into()),
+            album: Some(album.into()),
+            ..Default::default()
+        })
+        .build()
+}
+
+fn suggestions(groups: &[SuggestionGroup], kind: SuggestionType) -> Vec<&str> {
+    groups
+        .iter()
+        .find(|group| group.kind == kind)
+        .map(|group| group.suggestions.iter().map(String::as_str).collect())
+        .unwrap_or_default()
+}
+
+#[test]
+fn prefix_matches_rank_before_substring_matches() {
+    let tracks = vec![
+        track(1, "Paranoid Android", "Radiohead", "OK Computer"),
+        track(2, "Karma Police", "Radiohead", "OK Computer"),
+        track(
+            3,
+            "Video Killed the Radio Star",
+            "The Buggles",
+            "The Age of Plastic",
+        ),
+        track(4, "Radio Ga Ga", "Queen", "The Works"),
+        track(5, "Ready to Go", "Republica", "Republica"),
+        track(6, "Hit Radio", "Bizarre Radiohead Tribute", "Covers"),
+    ];
+    let index = SuggestionIndex::build(&tracks);
+
+    let groups = index.suggest("radio", &SuggestionType::ALL, 8);
+    assert_eq!(
+        groups.iter().map(|group| group.kind).collect::<Vec<_>>(),
+        SuggestionType::ALL.to_vec()
+    );
+    // Radiohead is on more tracks than the tribute band, and starts with the query
+    assert_eq!(
+        suggestions(&groups, SuggestionType::Artist),
+        vec!["Radiohead", "Bizarre Radiohead Tribute"]
+    );
+    assert_eq!(
+        suggestions(&groups, SuggestionType::Title),
+        vec!["Radio Ga Ga", "Hit Radio", "Video Killed the Radio Star"]
+    );
+    assert!(suggestions(&groups, SuggestionType::Album).is_empty());
+
+    // Substring matches inside words come last
+    let groups = index.suggest("ndro", &[SuggestionType::Title], 8);
+    assert_eq!(
+        suggestions(&groups, SuggestionType::Title),
+        vec!["Paranoid Android"]
+    );
+
+    let groups = index.suggest("ok comp", &[SuggestionType::Album], 8);
+    assert_eq!(
+        suggestions(&groups, SuggestionType::Album),
+        vec!["OK Computer"],
+        "suggestions are distinct"
+    );
+
+    let groups = index.suggest("r", &[SuggestionType::Artist], 1);
+    assert_eq!(
+        suggestions(&groups, SuggestionType::Artist),
+        vec!["Radiohead"]
+    );
+    assert!(index
+        .suggest("  ", &SuggestionType::ALL, 8)
+        .iter()
+        .all(|group| group.suggestions.is_empty()));
+}
+
+#[test]
+fn diacritics_and_case_are_ignored() {
+    assert_eq!(fold_words("Björk / Sigur Rós"), "bjork sigur ros");
+    assert_eq!(fold_words("  Straße—Œuvre "), "strasse oeuvre");
+
+    let tracks = vec![
+        track(1, "Jóga", "Björk", "Homogenic"),
+        track(2, "Hoppípolla", "Sigur Rós", "Takk..."),
+        track(3, "Hoppipolla", "Sigur Ros", "Takk"),
+        track(4, "Hoppípolla", "Sigur Rós", "Takk..."),
+    ];
+    let index = SuggestionIndex::build(&tracks);
+
+    let groups = index.suggest("BJORK", &[SuggestionType::Artist], 8);
+    assert_eq!(suggestions(&groups, SuggestionType::Artist), vec!["Björk"]);
+
+    // Spellings folding to the same string are one suggestion, in the most common spelling
+    let groups = index.suggest("hoppi", &[SuggestionType::Title], 8);
+    assert_eq!(
+        suggestions(&groups, SuggestionType::Title),
+        vec!["Hoppípolla"]
+    );
+    let groups = index.suggest("sigur rós", &[SuggestionType::Artist], 8);
+    assert_eq!(
+        suggestions(&groups, SuggestionType::Artist),
+        vec!["Sigur Rós"]
+    );
+}
+
+#[test]
+fn library_suggestions_follow_track_changes() {
+    let workspace = tempfile::tempdir().expect("failed to create temp workspace");
+    let library = Library::with_paths(&Paths::portable(workspace.path().join("data")));
+    library.add_track(track(1, "Teardrop", "Massive Attack", "Mezzanine"));
+
+    let artists = |query| {
+        library
+            .suggest(query, &[SuggestionType::Artist], 8)
+            .remove(0)
+            .suggestions
+    };
+    assert_eq!(artists("mas"), vec!["Massive Attack"]);
+
+    library.add_track(track(2, "Glory Box", "Portishead", "Dummy"));
+    assert_eq!(artists("port"), vec!["Portishead"]);
+
+    assert!(library.remove_track("1"));
+    assert!(artists("mas").is_empty());
+}
+
+#[test]
+fn suggestions_stay_fast_on_large_libraries() {
+    let words = [
+        "love", "night", "dream", "fire", "heart", "river", "light", "shadow", "summer", "storm",
+        "golden", "echo", "silver", "wild", "ocean", "electric",
+    ];
+    let tracks: Vec<Track> = (0..50_000)
+        .map(|n| {
+            let word = |shift: usize| words[(n >> shift) % words.len()];
+            track(
+                n,
+                &format!("{} {} {}", word(0), word(4), n),
+                &format!("{} {} band {}", word(2), word(6), n % 5_000),
+                &format!("{} of {} {}", word(1), word(5), n / 10),
+            )
+        })
+        .collect();
+    let index = SuggestionIndex::build(&tracks);
+
+    let queries = [
+        "l",
+        "lo",
+        "lov",
+        "love",
+        "love n",
+        "love ni",
+        "ech",
+        "silver wi",
+        "band 42",
+        "of gol",
+        "xyz",
+        "ream",
+        "4999",
+    ];
+    let started = Instant::now();
+    for query in queries {
+        let groups = index.suggest(query, &SuggestionType::ALL, 8);
+        assert!(groups.iter().all(|group| group.suggestions.len() <= 8));
+    }
+    let per_query = started.elapsed() / queries.len() as u32;
+    // Generous enough for unoptimized test builds; release builds take a few milliseconds
+    assert!(
+        per_query < Duration::from_millis(100),
+        "suggestions took {:?} per query",
+        per_query
+    );
+
+    let groups = index.suggest("band 42", &[SuggestionType::Artist], 8);
+    assert!(suggestions(&groups, SuggestionType::Artist)
+        .iter()
+        .all(|artist| artist.contains("band 42")));
+}
@@ -0,0 +1,127 @@
+mod common;
+
+use common::{write_silent_wav, TestTrack};
+use hexendrum::library::{
+    import_tag_stats, read_tag_stats, RatingScale, StatsStore, TagStats, Track,
+};
+use lofty::config::WriteOptions;
+use lofty::id3::v2::{Frame, Id3v2Tag, PopularimeterFrame};
+use lofty::iff::wav::RiffInfoList;
+use lofty::prelude::TagExt;
+use std::path::{Path, PathBuf};
+
+fn track(path: &Path) -> Track {
+    TestTrack::new(path.to_string_lossy()).path(path).build()
+}
+
+#[test]
+fn popm_ratings_map_the_windows_media_player_and_musicbee_values() {
+    let rating = |byte: u8| RatingScale::Popm.to_rating(f64::from(byte));
+
+    assert_eq!(rating(0), None);
+    // Windows Media Player, also written by foobar2000 and MusicBee
+    for (byte, stars) in [(1, 1), (64, 2), (128, 3), (196, 4), (255, 5)] {
+        assert_eq!(rating(byte), Some(stars), "POPM {}", byte);
+    }
+    // MusicBee half stars round up
+    for (byte, stars) in [(13, 1), (54, 2), (118, 3), (186, 4), (242, 5)] {
+        assert_eq!(rating(byte), Some(stars), "POPM {}", byte);
+    }
+}
+
+#[test]
+fn star_percent_and_fmps_ratings_map_to_whole_stars() {
+    // foobar2000 RATING
+    assert_eq!(RatingScale::Stars.to_rating(0.0), None);
+    assert_eq!(RatingScale::Stars.to_rating(3.0), Some(3));
+    assert_eq!(RatingScale::Stars.to_rating(3.5), Some(4));
+    assert_eq!(RatingScale::Stars.to_rating(7.0), Some(5));
+    // MusicBee and MediaMonkey RATING
+    assert_eq!(RatingScale::Percent.to_rating(20.0), Some(1));
+    assert_eq!(RatingScale::Percent.to_rating(60.0), Some(3));
+    assert_eq!(RatingScale::Percent.to_rating(70.0), Some(4));
+    assert_eq!(RatingScale::Percent.to_rating(100.0), Some(5));
+    // FMPS_Rating
+    assert_eq!(RatingScale::Fraction.to_rating(0.0), None);
+    assert_eq!(RatingScale::Fraction.to_rating(0.2), Some(1));
+    assert_eq!(RatingScale::Fraction.to_rating(0.6), Some(3));
+    assert_eq!(RatingScale::Fraction.to_rating(0.9), Some(5));
+    assert_eq!(RatingScale::Fraction.to_rating(f64::NAN), None);
+
+    assert_eq!(RatingScale::guess(4.0), RatingScale::Stars);
+    assert_eq!(RatingScale::guess(80.0), RatingScale::Percent);
+    assert_eq!(RatingScale::guess(196.0), RatingScale::Popm);
+}
+
+#[test]
+fn tag_stats_are_imported_without_lowering_local_play_counts() {
+    let workspace = tempfile::tempdir().unwrap();
+    let popm = workspace.path().join("popm.wav");
+    let fmps = workspace.path().join("fmps.wav");
+    let riff = workspace.path().join("riff.wav");
+    let plain = workspace.path().join("plain.wav");
+    for path in [&popm, &fmps, &riff, &plain] {
+        write_silent_wav(path);
+    }
+
+    let mut tag = Id3v2Tag::new();
+    tag.insert(Frame::Popularimeter(PopularimeterFrame::new(
+        "Windows Media Player 9 Series".into(),
+        196,
+        12,
+    )));
+    tag.save_to_path(&popm, WriteOptions::default()).unwrap();
+    let mut tag = Id3v2Tag::new();
+    tag.insert_user_text("FMPS_Rating".into(), "0.6".into());
+    tag.insert_user_text("FMPS_Playcount".into(), "30.000000".into());
+    tag.save_to_path(&fmps, WriteOptions::default()).unwrap();
+    let mut info = RiffInfoList::default();
+    info.insert("IRTD".into(), "100".into());
+    info.save_to_path(&riff, WriteOptions::default()).unwrap();
+
+    assert_eq!(
+        read_tag_stats(&popm).unwrap(),
+        TagStats {
+            rating: Some(4),
+            play_count: Some(12)
+        }
+    );
+    assert_eq!(
+        read_tag_stats(&fmps).unwrap(),
+        TagStats {
+            rating: Some(3),
+            play_count: Some(30)
+        }
+    );
+    assert_eq!(read_tag_stats(&riff).unwrap().rating, Some(5));
+    assert_eq!(read_tag_stats(&plain).unwrap(), TagStats::default());
+
+    let stats = StatsStore::with_path(workspace.path().join("stats.json"));
+    // Played here more often than the tags say
+    for _ in 0..40 {
+        stats.record_play(&fmps);
+    }
+    let missing = PathBuf::from("/nonexistent/track.wav");
+    let tracks: Vec<Track> = [&popm, &fmps, &riff, &plain, &missing]
+        .into_iter()
+        .map(|path| track(path))
+        .collect();
+
+    let report = import_tag_stats(&tracks, &stats);
+    assert_eq!(report.scanned, 4);
+    assert_eq!(report.failed, 1);
+    assert_eq!(report.ratings, 3);
+    assert_eq!(report.play_counts, 1);
+    assert_eq!(stats.rating(&popm), Some(4));
+    assert_eq!(stats.play_count(&popm), 12);
+    assert_eq!(stats.rating(&fmps), Some(3));
+    assert_eq!(stats.play_count(&fmps), 40);
+    assert_eq!(stats.rating(&plain), None);
+
+    // Importing again changes nothing, and the imported values persist
+    assert_eq!(import_tag_stats(&tracks, &stats).ratings, 0);
+    stats.save().unwrap();
+    let reloaded = StatsStore::with_path(workspace.path().join("stats.json"));
+    assert_eq!(reloaded.rating(&riff), Some(5));
+    assert_eq!(reloaded.play_count(&popm), 12);
+}
@@ -0,0 +1,193 @@
+mod common;
+
+use chrono::{DateTime, TimeZone, Utc};
+use common::TestTrack;
+use hexendrum::api::{QueueHistoryItem, TrackResponse};
+use hexendrum::instance::InstanceInfo;
+use hexendrum::library::{
+    AlbumDisambiguation, AlbumOverrideRecord, IntegrityRecord, IntegrityStatus, Track, TrashEntry,
+};
+use hexendrum::playlist::Playlist;
+use hexendrum::utils::serde_rfc3339;
+use hexendrum::{EventMessage, EventPayload, TrackMetadata};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::PathBuf;
+
+fn timestamp() -> DateTime<Utc> {
+    Utc.timestamp_opt(1_705_314_600, 123_456_789).unwrap()
+}
+
+/// Serialize `value`, check `field` is written as RFC 3339 in UTC, and read it back
+fn round_trip<T: Serialize + DeserializeOwned>(value: &T, field: &str) -> T {
+    let json = serde_json::to_value(value).expect("value should serialize");
+    assert_eq!(
+        json.pointer(field),
+        Some(&json!("2024-01-15T10:30:00.123456789Z")),
+        "{} in {}",
+        field,
+        json
+    );
+    serde_json::from_value(json).expect("value should deserialize")
+}
+
+fn track() -> Track {
+    TestTrack::new("track")
+        .path("/music/song.flac")
+        .modified(timestamp())
+        .metadata(TrackMetadata {
+            title: Some("Song".into()),
+            duration: Some(180),
+            file_size: 1024,
+            ..Default::default()
+        })
+        .build()
+}
+
+#[test]
+fn timestamps_are_formatted_in_utc_with_optional_fractions() {
+    assert_eq!(
+        serde_rfc3339::format(&Utc.timestamp_opt(1_705_314_600, 0).unwrap()),
+        "2024-01-15T10:30:00Z"
+    );
+    assert_eq!(
+        serde_rfc3339::format(&Utc.timestamp_opt(1_705_314_600, 250_000_000).unwrap()),
+        "2024-01-15T10:30:00.250Z"
+    );
+    assert_eq!(
+        serde_rfc3339::format(&timestamp()),
+        "2024-01-15T10:30:00.123456789Z"
+    );
+}
+
+#[test]
+fn older_timestamp_formats_are_still_read() {
+    let expected = Utc.timestamp_opt(1_705_314_600, 0).unwrap();
+    for value in [
+        "2024-01-15T10:30:00Z",
+        "2024-01-15T10:30:00+00:00",
+        "2024-01-15T11:30:00+01:00",
+        "2024-01-15 10:30:00Z",
+        "2024-01-15T10:30:00",
+        "2024-01-15 10:30:00",
+    ] {
+        assert_eq!(serde_rfc3339::parse(value), Some(expected), "{}", value);
+    }
+    assert_eq!(serde_rfc3339::parse("yesterday"), None);
+
+    let entry: TrashEntry = serde_json::from_value(json!({
+        "track_id": "track",
+        "original_path": "/music/song.flac",
+        "trash_path": "/trash/song.flac",
+        "info_path": null,
+        "deleted_at": 1_705_314_600,
+    }))
+    .expect("Unix timestamps should be read");
+    assert_eq!(entry.deleted_at, expected);
+
+    assert!(serde_json::from_value::<TrashEntry>(json!({
+        "track_id": "track",
+        "original_path": "/music/song.flac",
+        "trash_path": "/trash/song.flac",
+        "info_path": null,
+        "deleted_at": "soon",
+    }))
+    .is_err());
+}
+
+#[test]
+fn events_are_timestamped_in_rfc3339() {
+    let mut message = EventMessage::new(EventPayload::library_updated(3));
+    message.timestamp = timestamp();
+    let json = serde_json::to_value(&message).unwrap();
+    assert_eq!(json["timestamp"], json!("2024-01-15T10:30:00.123456789Z"));
+}
+
+#[test]
+fn tracks_round_trip() {
+    let track = round_trip(&track(), "/metadata/last_modified");
+    assert_eq!(track.metadata.last_modified, timestamp());
+
+    let response = round_trip(&TrackResponse::from(&track), "/last_modified");
+    assert_eq!(response.last_modified, timestamp());
+
+    let item = round_trip(
+        &QueueHistoryItem {
+            track: response,
+            played_at: timestamp(),
+        },
+        "/played_at",
+    );
+    assert_eq!(item.played_at, timestamp());
+    assert_eq!(item.track.last_modified, timestamp());
+}
+
+#[test]
+fn playlists_round_trip() {
+    let mut playlist = Playlist::new("Mix".into(), None);
+    playlist.add_track(&track());
+    playlist.add_track(&track());
+    playlist.created_at = timestamp();
+    playlist.modified_at = timestamp();
+    playlist.entries[0].added_at = timestamp();
+    playlist.entries[0].last_played = Some(timestamp());
+
+    let json = serde_json::to_value(&playlist).unwrap();
+    assert_eq!(json["entries"][1]["last_played"], Value::Null);
+    let restored = round_trip(&playlist, "/created_at");
+    round_trip(&playlist, "/modified_at");
+    round_trip(&playlist, "/entries/0/added_at");
+    round_trip(&playlist, "/entries/0/last_played");
+    assert_eq!(restored.created_at, timestamp());
+    assert_eq!(restored.modified_at, timestamp());
+    assert_eq!(restored.entries[0].added_at, timestamp());
+    assert_eq!(restored.entries[0].last_played, Some(timestamp()));
+    assert_eq!(restored.entries[1].last_played, None);
+}
+
+#[test]
+fn stores_round_trip() {
+    let record = IntegrityRecord {
+        status: IntegrityStatus::Ok,
+        error: None,
+        checked_at: timestamp(),
+        file_modified: timestamp(),
+    };
+    assert_eq!(round_trip(&record, "/checked_at"), record);
+    assert_eq!(round_trip(&record, "/file_modified"), record);
+
+    let entry = TrashEntry {
+        track_id: "track".into(),
+        original_path: PathBuf::from("/music/song.flac"),
+        trash_path: PathBuf::from("/trash/song.flac"),
+        info_path: None,
+        deleted_at: timestamp(),
+    };
+    assert_eq!(round_trip(&entry, "/deleted_at"), entry);
+
+    let instance = InstanceInfo {
+        pid: 42,
+        port: Some(3030),
+        unix_socket: None,
+        started_at: timestamp(),
+    };
+    assert_eq!(round_trip(&instance, "/started_at"), instance);
+
+    let album_override = AlbumOverrideRecord {
+        album_id: "album".into(),
+        title: Some("Album".into()),
+        primary_artist: None,
+        search_album: None,
+        search_artist: None,
+        metadata: None,
+        artwork_path: None,
+        disambiguation: AlbumDisambiguation::Auto,
+        musicbrainz_release_id: None,
+        updated_at: timestamp(),
+    };
+    assert_eq!(
+        round_trip(&album_override, "/updated_at").updated_at,
+        timestamp()
+    );
+}
@@ -0,0 +1,106 @@
+mod common;
+
+use common::TestTrack;
+use hexendrum::library::{match_track, Track, TrackQuery};
+use hexendrum::TrackMetadata;
+
+fn track(id: &str, artist: &str, title: &str, duration: Option<u64>) -> Track {
+    TestTrack::new(id)
+        .metadata(TrackMetadata {
+            title: Some(title.into()),
+            artist: Some(artist.into()),
+            duration,
+            ..Default::default()
+        })
+        .build()
+}
+
+fn query(artist: &str, title: &str, duration: Option<u64>) -> TrackQuery {
+    TrackQuery {
+        title: title.into(),
+        artist: Some(artist.into()),
+        album: None,
+        duration,
+    }
+}
+
+fn library() -> Vec<Track> {
+    vec![
+        track("bohemian", "Queen", "Bohemian Rhapsody", Some(355)),
+        track(
+            "pressure",
+            "Queen & David Bowie",
+            "Under Pressure",
+            Some(248),
+        ),
+        track("heroes", "David Bowie", "Heroes", Some(371)),
+        track("heroes-live", "David Bowie", "Heroes", Some(420)),
+    ]
+}
+
+#[test]
+fn exact_artist_and_title_within_duration_tolerance_is_full_confidence() {
+    let tracks = library();
+    let found = match_track(&query("QUEEN", "bohemian rhapsody", Some(357)), &tracks)
+        .expect("track should match");
+
+    assert_eq!(found.track_id, "bohemian");
+    assert_eq!(found.confidence, 1.0);
+    assert!(!found.is_low_confidence());
+}
+
+#[test]
+fn version_suffixes_and_featured_artists_are_ignored() {
+    let tracks = library();
+
+    let remaster = match_track(
+        &query("Queen", "Bohemian Rhapsody - Remastered 2011", Some(355)),
+        &tracks,
+    )
+    .expect("remaster should match");
+    assert_eq!(remaster.track_id, "bohemian");
+    assert_eq!(remaster.confidence, 1.0);
+
+    let collaboration = match_track(&query("David Bowie,Queen", "Under Pressure", None), &tracks)
+        .expect("collaboration should match");
+    assert_eq!(collaboration.track_id, "pressure");
+}
+
+#[test]
+fn duration_disambiguates_between_recordings() {
+    let tracks = library();
+
+    let studio = match_track(&query("David Bowie", "Heroes", Some(370)), &tracks).unwrap();
+    assert_eq!(studio.track_id, "heroes");
+
+    let live = match_track(&query("David Bowie", "Heroes", Some(421)), &tracks).unwrap();
+    assert_eq!(live.track_id, "heroes-live");
+}
+
+#[test]
+fn fuzzy_fallback_reports_lower_confidence() {
+    let tracks = library();
+    let found = match_track(&query("Queen", "Bohemian Rapsody", Some(355)), &tracks)
+        .expect("typo should still match");
+
+    assert_eq!(found.track_id, "bohemian");
+    assert!(found.confidence < 1.0);
+    assert!(found.confidence > 0.6);
+}
+
+#[test]
+fn mismatched_duration_is_flagged_for_review() {
+    let tracks = library();
+    let found = match_track(&query("Queen", "Bohemian Rhapsody", Some(600)), &tracks)
+        .expect("title and artist still match");
+
+    assert_eq!(found.track_id, "bohemian");
+    assert!(found.is_low_confidence());
+}
+
+#[test]
+fn unrelated_tracks_are_not_matched() {
+    let tracks = library();
+    assert!(match_track(&query("Daft Punk", "One More Time", Some(320)), &tracks).is_none());
+    assert!(match_track(&query("Queen", "   ", None), &tracks).is_none());
+}
@@ -0,0 +1,110 @@
+use hexendrum::audio::{
+    Transcode, TranscodeCache, TranscodeFormat, DEFAULT_BITRATE_KBPS, MAX_BITRATE_KBPS,
+};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+fn opus(bitrate_kbps: u32, start: f64) -> Transcode {
+    Transcode {
+        format: TranscodeFormat::Opus,
+        bitrate_kbps,
+        start,
+    }
+}
+
+#[test]
+fn stream_parameters_are_validated() {
+    assert_eq!(Transcode::from_params(None, None, None), Ok(None));
+    assert_eq!(Transcode::from_params(Some(" "), None, None), Ok(None));
+    assert_eq!(
+        Transcode::from_params(Some("OPUS"), None, None),
+        Ok(Some(opus(DEFAULT_BITRATE_KBPS, 0.0)))
+    );
+    assert_eq!(
+        Transcode::from_params(Some("opus"), Some(64), Some(90.5)),
+        Ok(Some(opus(64, 90.5)))
+    );
+
+    for (format, bitrate, start) in [
+        (Some("aac"), None, None),
+        (Some("opus"), Some(8), None),
+        (Some("opus"), Some(MAX_BITRATE_KBPS + 1), None),
+        (Some("opus"), None, Some(-1.0)),
+        (Some("opus"), None, Some(f64::NAN)),
+        (None, Some(128), None),
+        (None, None, Some(30.0)),
+    ] {
+        assert!(
+            Transcode::from_params(format, bitrate, start).is_err(),
+            "{:?} {:?} {:?} should be refused",
+            format,
+            bitrate,
+            start
+        );
+    }
+}
+
+#[test]
+fn cache_keys_depend_on_file_and_bitrate() {
+    let path = Path::new("/music/song.flac");
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_705_314_600);
+    let key = TranscodeCache::key(path, modified, &opus(128, 0.0)).unwrap();
+    assert!(key.ends_with(".opus"));
+
+    assert_eq!(
+        TranscodeCache::key(path, modified, &opus(128, 0.0)),
+        Some(key.clone())
+    );
+    assert_ne!(
+        TranscodeCache::key(path, modified, &opus(96, 0.0)),
+        Some(key.clone())
+    );
+    assert_ne!(
+        TranscodeCache::key(path, modified + Duration::from_secs(1), &opus(128, 0.0)),
+        Some(key.clone())
+    );
+    // Streams starting past the beginning are not cached
+    assert_eq!(TranscodeCache::key(path, modified, &opus(128, 30.0)), None);
+}
+
+#[test]
+fn least_recently_streamed_transcodes_are_evicted() {
+    let workspace = tempfile::tempdir().unwrap();
+    let dir = workspace.path().join("transcoded");
+    let cache = TranscodeCache::new(dir.clone(), 250);
+    assert_eq!(cache.evict().unwrap(), 0);
+    fs::create_dir(&dir).unwrap();
+
+    let now = SystemTime::now();
+    for (index, key) in ["a.opus", "b.opus"].iter().enumerate() {
+        let partial = cache.partial_path(key);
+        fs::write(&partial, vec![0; 100]).unwrap();
+        cache.store(&partial, key).unwrap();
+        fs::File::options()
+            .append(true)
+            .open(dir.join(key))
+            .unwrap()
+            .set_modified(now - Duration::from_secs(60 - index as u64))
+            .unwrap();
+    }
+    assert_eq!(cache.size(), 200);
+
+    // Streaming "a" again makes "b" the least recently used
+    assert_eq!(cache.get("a.opus"), Some(dir.join("a.opus")));
+    assert_eq!(cache.get("missing.opus"), None);
+
+    // Partial transcodes being written are neither counted nor evicted
+    let writing = cache.partial_path("d.opus");
+    fs::write(&writing, vec![0; 500]).unwrap();
+
+    let partial = cache.partial_path("c.opus");
+    fs::write(&partial, vec![0; 100]).unwrap();
+    cache.store(&partial, "c.opus").unwrap();
+
+    assert!(!dir.join("b.opus").exists());
+    assert!(dir.join("a.opus").exists());
+    assert!(dir.join("c.opus").exists());
+    assert!(writing.exists());
+    assert_eq!(cache.size(), 200);
+}
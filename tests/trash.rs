@@ -0,0 +1,101 @@
+use hexendrum::library::{Trash, FALLBACK_TRASH_DIR};
+use std::fs;
+use tempfile::TempDir;
+
+fn music_file(workspace: &TempDir, name: &str) -> std::path::PathBuf {
+    let dir = workspace.path().join("music").join("Some Artist");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, b"audio").unwrap();
+    path
+}
+
+#[test]
+fn home_trash_follows_freedesktop_layout_and_restores() {
+    let workspace = TempDir::new().unwrap();
+    let home_trash = workspace.path().join("Trash");
+    let trash = Trash::with_paths(
+        workspace.path().join("journal.json"),
+        Some(home_trash.clone()),
+        30,
+    );
+    let path = music_file(&workspace, "01 Track #1.flac");
+
+    let entry = trash.move_to_trash("track-1", &path).unwrap();
+    assert!(!path.exists());
+    assert_eq!(entry.trash_path, home_trash.join("files/01 Track #1.flac"));
+    assert!(entry.trash_path.exists());
+
+    let info = fs::read_to_string(entry.info_path.as_ref().unwrap()).unwrap();
+    assert!(info.starts_with("[Trash Info]\n"));
+    assert!(info.contains("Some%20Artist/01%20Track%20%231.flac\n"));
+    assert!(info.contains("DeletionDate="));
+
+    // A second file with the same name gets a distinct trash name.
+    let again = music_file(&workspace, "01 Track #1.flac");
+    let second = trash.move_to_trash("track-2", &again).unwrap();
+    assert_eq!(
+        second.trash_path,
+        home_trash.join("files/01 Track #1.2.flac")
+    );
+
+    // The journal survives a restart.
+    let reopened = Trash::with_paths(workspace.path().join("journal.json"), Some(home_trash), 30);
+    assert!(reopened.is_restorable("track-1"));
+    let restored = reopened.restore("track-1").unwrap();
+    assert_eq!(restored.original_path, path);
+    assert_eq!(fs::read(&path).unwrap(), b"audio");
+    assert!(!entry.info_path.unwrap().exists());
+    assert!(!reopened.is_restorable("track-1"));
+}
+
+#[test]
+fn fallback_trash_is_used_without_a_home_trash() {
+    let workspace = TempDir::new().unwrap();
+    let trash = Trash::with_paths(workspace.path().join("journal.json"), None, 30);
+    let path = music_file(&workspace, "song.mp3");
+
+    let entry = trash.move_to_trash("song", &path).unwrap();
+    let fallback_dir = path.parent().unwrap().join(FALLBACK_TRASH_DIR);
+    assert_eq!(entry.trash_path, fallback_dir.join("song.mp3"));
+    assert!(entry.info_path.is_none());
+
+    // Restoring refuses to overwrite a file that reappeared in the meantime.
+    fs::write(&path, b"new").unwrap();
+    assert!(trash.restore("song").is_err());
+    fs::remove_file(&path).unwrap();
+
+    trash.restore("song").unwrap();
+    assert!(path.exists());
+    assert!(!fallback_dir.exists());
+}
+
+#[test]
+fn expired_entries_are_purged_and_no_longer_restorable() {
+    let workspace = TempDir::new().unwrap();
+    let trash = Trash::with_paths(workspace.path().join("journal.json"), None, 0);
+    let path = music_file(&workspace, "old.ogg");
+    let entry = trash.move_to_trash("old", &path).unwrap();
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    assert!(!trash.is_restorable("old"));
+    assert_eq!(trash.purge_expired().unwrap(), 1);
+    assert!(!entry.trash_path.exists());
+    assert!(!entry.trash_path.parent().unwrap().exists());
+    assert_eq!(trash.purge_expired().unwrap(), 0);
+}
+
+#[test]
+fn expired_entries_leave_the_home_trash_alone() {
+    let workspace = TempDir::new().unwrap();
+    let journal = workspace.path().join("journal.json");
+    let trash = Trash::with_paths(journal.clone(), Some(workspace.path().join("Trash")), 0);
+    let path = music_file(&workspace, "old.ogg");
+    let entry = trash.move_to_trash("old", &path).unwrap();
+
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    assert_eq!(trash.purge_expired().unwrap(), 1);
+    assert!(entry.trash_path.exists());
+    assert!(entry.info_path.unwrap().exists());
+    assert_eq!(fs::read_to_string(journal).unwrap().trim(), "[]");
+}
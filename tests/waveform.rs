@@ -0,0 +1,144 @@
+mod common;
+
+use common::{TestTrack, Wav};
+use hexendrum::library::{compute_waveform, Peak, Track, Waveform, WaveformCache};
+use std::f32::consts::PI;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use tempfile::TempDir;
+
+const RATE: u32 = 8000;
+
+/// Write a mono 16-bit WAV of a 200 Hz sine lasting `seconds`, whose amplitude
+/// rises linearly from silence to full scale.
+fn write_ramped_sine(path: &Path, seconds: u32) {
+    let frames = RATE * seconds;
+    let samples: Vec<i16> = (0..frames)
+        .map(|frame| {
+            let envelope = frame as f32 / frames as f32;
+            let phase = 2.0 * PI * 200.0 * frame as f32 / RATE as f32;
+            (envelope * phase.sin() * f32::from(i16::MAX)) as i16
+        })
+        .collect();
+    Wav::new().sample_rate(RATE).samples(&samples).write(path);
+}
+
+fn track(path: &Path) -> Track {
+    TestTrack::new(path.file_stem().unwrap().to_string_lossy())
+        .path(path)
+        .build()
+}
+
+fn peak(min: i8, max: i8) -> Peak {
+    Peak { min, max }
+}
+
+#[test]
+fn peaks_follow_the_envelope_of_a_sine() {
+    let workspace = TempDir::new().unwrap();
+    let path = workspace.path().join("ramp.wav");
+    write_ramped_sine(&path, 4);
+
+    let waveform = compute_waveform(&path, 100).unwrap();
+    assert_eq!(waveform.peaks.len(), 100);
+    for (index, peak) in waveform.peaks.iter().enumerate() {
+        // The envelope at the end of each bucket, where its loudest cycle is
+        let expected = 127.0 * (index + 1) as f32 / 100.0;
+        assert!(
+            (f32::from(peak.max) - expected).abs() <= 3.0,
+            "bucket {}: max {} for an envelope of {}",
+            index,
+            peak.max,
+            expected
+        );
+        assert!(
+            (i16::from(peak.min) + i16::from(peak.max)).abs() <= 2,
+            "bucket {}: {:?} is not symmetric",
+            index,
+            peak
+        );
+    }
+}
+
+#[test]
+fn downsampling_merges_neighbouring_peaks() {
+    let waveform = Waveform {
+        peaks: vec![
+            peak(-1, 2),
+            peak(-5, 1),
+            peak(-2, 7),
+            peak(0, 3),
+            peak(-9, 4),
+        ],
+    };
+    assert_eq!(waveform.downsample(2), vec![peak(-5, 2), peak(-9, 7)]);
+    assert_eq!(waveform.downsample(1), vec![peak(-9, 7)]);
+    // Waveforms are never upsampled
+    assert_eq!(waveform.downsample(10), waveform.peaks);
+}
+
+#[test]
+fn undecodable_files_have_no_waveform() {
+    let workspace = TempDir::new().unwrap();
+    let path = workspace.path().join("broken.wav");
+    fs::write(&path, b"not audio").unwrap();
+    assert!(compute_waveform(&path, 100).is_err());
+}
+
+#[test]
+fn cached_waveforms_are_reused_until_the_file_changes() {
+    let workspace = TempDir::new().unwrap();
+    let path = workspace.path().join("ramp.wav");
+    write_ramped_sine(&path, 2);
+    let cache = WaveformCache::new(workspace.path().join("waveforms"));
+
+    assert!(cache.cached(&path).is_none());
+    let (computed, fresh) = cache.get_or_compute(&path).unwrap();
+    assert!(fresh);
+    let (cached, fresh) = cache.get_or_compute(&path).unwrap();
+    assert!(!fresh);
+    assert_eq!(cached, computed);
+    // The cache survives a restart
+    let reopened = WaveformCache::new(workspace.path().join("waveforms"));
+    assert_eq!(reopened.cached(&path), Some(computed.clone()));
+
+    // A new modification time invalidates it
+    let file = fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    assert!(cache.cached(&path).is_none());
+    assert!(cache.get_or_compute(&path).unwrap().1);
+
+    // So does a new size, even with the old modification time
+    let modified = fs::metadata(&path).unwrap().modified().unwrap();
+    write_ramped_sine(&path, 1);
+    fs::File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    assert!(cache.cached(&path).is_none());
+}
+
+#[test]
+fn precomputing_skips_cached_waveforms_and_counts_failures() {
+    let workspace = TempDir::new().unwrap();
+    let cache = WaveformCache::new(workspace.path().join("waveforms"));
+    let mut tracks = Vec::new();
+    for name in ["a.wav", "b.wav", "c.wav"] {
+        let path = workspace.path().join(name);
+        write_ramped_sine(&path, 1);
+        tracks.push(track(&path));
+    }
+    let broken = workspace.path().join("broken.wav");
+    fs::write(&broken, b"not audio").unwrap();
+    tracks.push(track(&broken));
+    cache.get_or_compute(&tracks[0].metadata.file_path).unwrap();
+
+    let report = cache.precompute(&tracks);
+    assert_eq!((report.computed, report.cached, report.failed), (2, 1, 1));
+    let report = cache.precompute(&tracks);
+    assert_eq!((report.computed, report.cached, report.failed), (0, 3, 1));
+}
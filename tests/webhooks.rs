@@ -0,0 +1,161 @@
+use hexendrum::config::WebhookConfig;
+use hexendrum::events::{WebhookDispatcher, WebhookStatus};
+use hexendrum::{EventBus, EventPayload};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+
+/// Request captured by the test receiver.
+struct Received {
+    headers: String,
+    body: Value,
+}
+
+/// Minimal HTTP receiver that answers every request with `status`.
+async fn spawn_receiver(status: u16) -> (String, mpsc::UnboundedReceiver<Received>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let mut buffer = Vec::new();
+            let mut chunk = [0u8; 4096];
+            let (headers, body) = loop {
+                let read = stream.read(&mut chunk).await.unwrap_or(0);
+                if read == 0 {
+                    break (String::new(), Vec::new());
+                }
+                buffer.extend_from_slice(&chunk[..read]);
+
+                let text = String::from_utf8_lossy(&buffer).to_string();
+                if let Some(end) = text.find("\r\n\r\n") {
+                    let headers = text[..end].to_lowercase();
+                    let length = headers
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .and_then(|value| value.trim().parse::<usize>().ok())
+                        .unwrap_or(0);
+                    if buffer.len() >= end + 4 + length {
+                        break (headers, buffer[end + 4..end + 4 + length].to_vec());
+                    }
+                }
+            };
+
+            if let Ok(body) = serde_json::from_slice(&body) {
+                let _ = sender.send(Received { headers, body });
+            }
+
+            let response = format!("HTTP/1.1 {} Test\r\nContent-Length: 0\r\n\r\n", status);
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+
+    (url, receiver)
+}
+
+fn hook(url: &str, events: &[&str]) -> WebhookConfig {
+    WebhookConfig {
+        url: url.to_string(),
+        events: events.iter().map(|event| event.to_string()).collect(),
+        secret: None,
+        timeout_secs: 2,
+        max_retries: 0,
+    }
+}
+
+async fn wait_for_status<F>(dispatcher: &WebhookDispatcher, done: F) -> Vec<WebhookStatus>
+where
+    F: Fn(&[WebhookStatus]) -> bool,
+{
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+    loop {
+        let status = dispatcher.status();
+        if done(&status) {
+            return status;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "webhook status never settled: {:?}",
+            status
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+#[tokio::test]
+async fn matching_events_are_posted_with_secret_header() {
+    let (url, mut requests) = spawn_receiver(200).await;
+    let bus = Arc::new(EventBus::new(None));
+    let mut config = hook(&url, &["playback_state"]);
+    config.secret = Some("s3cret".into());
+    let dispatcher = WebhookDispatcher::start(&bus, vec![config]);
+
+    bus.emit(EventPayload::volume_changed(0.5));
+    bus.emit(EventPayload::playback_state(
+        "playing",
+        Some("/music/song.flac".into()),
+        Some("song".into()),
+        Some(0.5),
+        Some(180),
+    ));
+
+    let received = tokio::time::timeout(Duration::from_secs(10), requests.recv())
+        .await
+        .expect("webhook should be delivered")
+        .unwrap();
+    assert_eq!(received.body["type"], "playback_state");
+    assert_eq!(received.body["state"], "playing");
+    assert!(received.body["timestamp"].is_string());
+    assert!(received.headers.contains("x-hexendrum-token: s3cret"));
+    assert!(received.headers.contains("content-type: application/json"));
+
+    let status = wait_for_status(&dispatcher, |status| status[0].delivered == 1).await;
+    assert_eq!((status[0].failed, status[0].dropped), (0, 0));
+    assert!(requests.try_recv().is_err(), "filtered event was delivered");
+}
+
+#[tokio::test]
+async fn failing_receivers_are_retried_and_counted() {
+    let (url, mut requests) = spawn_receiver(500).await;
+    let bus = Arc::new(EventBus::new(None));
+    let mut config = hook(&url, &[]);
+    config.max_retries = 1;
+    let dispatcher = WebhookDispatcher::start(&bus, vec![config]);
+
+    bus.emit(EventPayload::volume_changed(0.3));
+
+    let status = wait_for_status(&dispatcher, |status| status[0].failed == 1).await;
+    assert_eq!(status[0].delivered, 0);
+
+    let mut attempts = 0;
+    while requests.try_recv().is_ok() {
+        attempts += 1;
+    }
+    assert_eq!(attempts, 2);
+}
+
+#[tokio::test]
+async fn reload_keeps_unchanged_hooks_and_drops_removed_ones() {
+    let (url, _requests) = spawn_receiver(200).await;
+    let bus = Arc::new(EventBus::new(None));
+    let kept = hook(&url, &[]);
+    let dispatcher = WebhookDispatcher::start(&bus, vec![kept.clone()]);
+
+    bus.emit(EventPayload::volume_changed(0.1));
+    wait_for_status(&dispatcher, |status| status[0].delivered == 1).await;
+
+    dispatcher.reload(vec![kept, hook("", &[]), hook(&url, &["library_updated"])]);
+
+    let status = dispatcher.status();
+    assert_eq!(status.len(), 2, "hooks without a URL are ignored");
+    assert_eq!(status[0].delivered, 1, "unchanged hook keeps its counters");
+    assert_eq!(status[1].events, vec!["library_updated"]);
+    assert_eq!(status[1].delivered, 0);
+
+    dispatcher.reload(Vec::new());
+    assert!(dispatcher.status().is_empty());
+}
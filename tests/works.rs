@@ -0,0 +1,172 @@
+mod common;
+
+use common::TestTrack;
+use hexendrum::library::{group_works, parse_work_title, ParsedWorkTitle, Track};
+use hexendrum::TrackMetadata;
+
+fn parsed(work: &str, movement: Option<&str>, number: Option<u32>) -> Option<ParsedWorkTitle> {
+    Some(ParsedWorkTitle {
+        work: work.into(),
+        movement: movement.map(Into::into),
+        movement_number: number,
+    })
+}
+
+fn track(id: &str, composer: Option<&str>, title: &str, album: &str) -> Track {
+    TestTrack::new(id)
+        .metadata(TrackMetadata {
+            title: Some(title.into()),
+            artist: Some(format!("{} Orchestra", album)),
+            album: Some(album.into()),
+            composer: composer.map(Into::into),
+            ..Default::default()
+        })
+        .build()
+}
+
+#[test]
+fn colon_separated_titles_with_roman_numerals() {
+    assert_eq!(
+        parse_work_title("Symphony No. 5 in C minor, Op. 67: II. Andante con moto"),
+        parsed(
+            "Symphony No. 5 in C minor, Op. 67",
+            Some("Andante con moto"),
+            Some(2)
+        )
+    );
+    assert_eq!(
+        parse_work_title("Symphony No. 9 in D minor, Op. 125: IV. Presto - Allegro assai"),
+        parsed(
+            "Symphony No. 9 in D minor, Op. 125",
+            Some("Presto - Allegro assai"),
+            Some(4)
+        )
+    );
+    assert_eq!(
+        parse_work_title("Requiem in D minor, K. 626: 3. Sequentia: Dies irae"),
+        parsed(
+            "Requiem in D minor, K. 626",
+            Some("Sequentia: Dies irae"),
+            Some(3)
+        )
+    );
+}
+
+#[test]
+fn colon_titles_without_movement_numbers_keep_the_movement_name() {
+    assert_eq!(
+        parse_work_title("Goldberg Variations, BWV 988: Aria"),
+        parsed("Goldberg Variations, BWV 988", Some("Aria"), None)
+    );
+    assert_eq!(
+        parse_work_title("Cello Suite No. 1 in G major, BWV 1007: Prélude"),
+        parsed(
+            "Cello Suite No. 1 in G major, BWV 1007",
+            Some("Prélude"),
+            None
+        )
+    );
+    // Movement names that merely start with numeral letters are not numbers.
+    assert_eq!(
+        parse_work_title("Carmen: Intermezzo"),
+        parsed("Carmen", Some("Intermezzo"), None)
+    );
+}
+
+#[test]
+fn dash_separated_titles_require_a_movement_number() {
+    assert_eq!(
+        parse_work_title("Piano Sonata No. 14 in C-sharp minor - I. Adagio sostenuto"),
+        parsed(
+            "Piano Sonata No. 14 in C-sharp minor",
+            Some("Adagio sostenuto"),
+            Some(1)
+        )
+    );
+    assert_eq!(
+        parse_work_title("String Quartet No. 14 - VII"),
+        parsed("String Quartet No. 14", None, Some(7))
+    );
+    assert_eq!(parse_work_title("Clair de lune - Live"), None);
+    assert_eq!(parse_work_title("Symphony No. 40 - IIII. Finale"), None);
+    assert_eq!(parse_work_title("Boléro"), None);
+}
+
+#[test]
+fn works_group_movements_across_albums_and_order_them() {
+    let tracks = vec![
+        track(
+            "b3",
+            Some("Ludwig van Beethoven"),
+            "Symphony No. 5 in C minor, Op. 67: III. Allegro",
+            "Karajan 1963",
+        ),
+        track(
+            "b1",
+            Some("Ludwig van Beethoven"),
+            "Beethoven: Symphony No. 5 in C minor, Op. 67: I. Allegro con brio",
+            "Kleiber 1975",
+        ),
+        track(
+            "b2",
+            Some("Ludwig van Beethoven"),
+            "Symphony No. 5 in C minor, Op. 67: II. Andante con moto",
+            "Karajan 1963",
+        ),
+        track(
+            "m1",
+            Some("W. A. Mozart"),
+            "Eine kleine Nachtmusik",
+            "Serenades",
+        ),
+        track("pop", None, "Song: Remix", "Pop Album"),
+    ];
+
+    let works = group_works(&tracks, None);
+    assert_eq!(works.len(), 2, "tracks without a composer are skipped");
+
+    let symphony = &works[0];
+    assert_eq!(symphony.composer, "Ludwig van Beethoven");
+    assert_eq!(symphony.title, "Symphony No. 5 in C minor, Op. 67");
+    let order: Vec<&str> = symphony
+        .movements
+        .iter()
+        .map(|movement| movement.track_id.as_str())
+        .collect();
+    assert_eq!(order, ["b1", "b2", "b3"]);
+    assert_eq!(
+        symphony.movements[0].movement.as_deref(),
+        Some("Allegro con brio")
+    );
+
+    assert_eq!(works[1].title, "Eine kleine Nachtmusik");
+    assert!(works[1].movements[0].movement.is_none());
+
+    let mozart = group_works(&tracks, Some("w. a. mozart"));
+    assert_eq!(mozart.len(), 1);
+    assert_eq!(mozart[0].composer, "W. A. Mozart");
+}
+
+#[test]
+fn work_and_movement_tags_take_precedence_over_titles() {
+    let mut tagged = track(
+        "t1",
+        Some("Johann Sebastian Bach"),
+        "Präludium",
+        "Well-Tempered Clavier",
+    );
+    tagged.metadata.work = Some("Das wohltemperierte Klavier I, BWV 846".into());
+    tagged.metadata.movement_number = Some(1);
+
+    let mut second = tagged.clone();
+    second.id = "t2".into();
+    second.metadata.title = Some("Fuge".into());
+    second.metadata.movement = Some("Fuga a 4".into());
+    second.metadata.movement_number = Some(2);
+
+    let works = group_works(&[second, tagged], None);
+    assert_eq!(works.len(), 1);
+    assert_eq!(works[0].title, "Das wohltemperierte Klavier I, BWV 846");
+    assert_eq!(works[0].movements[0].track_id, "t1");
+    assert_eq!(works[0].movements[1].movement.as_deref(), Some("Fuga a 4"));
+}